@@ -0,0 +1,266 @@
+// An Anchor program wrapping the verifier (see `contract/src/lib.rs`), for dapps built
+// on Anchor: typed instructions with an IDL and `#[derive(Accounts)]` validation in
+// place of hand-packed `[t, i, j, payload]` data and account metas.
+//
+// Every instruction packs its arguments into the verifier's instruction data and runs
+// `contract::process_instruction` under this program's id, so the scratch accounts,
+// VK accounts and the other program-derived addresses of a verification belong to this
+// program, and the verifier checks what it always checks. The account structs add what
+// Anchor can check up front: signers, writability and the addresses of the scratch
+// accounts an instruction names. A step whose accounts vary, such as a final
+// exponentiation stage, takes them as remaining accounts in the order
+// `contract::schedule` lists them; `step` passes any other verifier instruction as is.
+//
+// Build with `anchor build`, which writes the IDL to `target/idl/anchor_verifier.json`;
+// `idl/anchor_verifier.json` is a copy of it for integrators without the Anchor CLI.
+use anchor_lang::prelude::*;
+use contract::instruction::VerifierInstruction::{
+    CloseSession, CreateScratch as CreateScratchInstruction, DeltaMillerLoopSteps,
+    GammaMillerLoopSteps, VerifyComplete as VerifyCompleteInstruction,
+};
+use contract::instruction::{Payload, StepIndex};
+use contract::scratch::{CLOSE_SESSION, CREATE_SCRATCH, RESET_SESSION};
+use contract::VK_ACCOUNT_FLAG;
+
+declare_id!("ApivHevBaap7yVG7ufnZRASPB1UHJHhtSpuMHP5yc6G8");
+
+// Final exponentiation stages, from `PrepareFinal` (t = 2) to `HardPartY16` (t = 16)
+const FINAL_STAGES: std::ops::RangeInclusive<u8> = 2..=16;
+
+#[program]
+pub mod anchor_verifier {
+    use super::*;
+
+    /// Create the `role` scratch account of session `session_id` (see
+    /// `contract/src/scratch.rs`) at the payer's expense.
+    pub fn create_scratch(ctx: Context<CreateScratch>, session_id: u64, role: u8) -> Result<()> {
+        let data = CreateScratchInstruction(StepIndex::new(role, CREATE_SCRATCH)).pack();
+        let accounts = vec![
+            ctx.accounts.payer.to_account_info(),
+            ctx.accounts.scratch.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+        ];
+        run(ctx.program_id, accounts, data, Some(session_id))
+    }
+
+    /// Run `steps` steps of the gamma or delta Miller loop from (i, j) on, with `input`
+    /// the prepared input or proof.c behind its encoding byte; a gamma loop without one
+    /// reads the folded inputs account.
+    pub fn miller_loop(
+        ctx: Context<MillerLoop>,
+        session_id: u64,
+        table: LoopTable,
+        i: u8,
+        j: u8,
+        steps: u8,
+        input: Vec<u8>,
+    ) -> Result<()> {
+        let index = StepIndex::new(i, j);
+        let mut data = match table {
+            LoopTable::Gamma => GammaMillerLoopSteps(index, steps, Payload(input)),
+            LoopTable::Delta => DeltaMillerLoopSteps(index, steps, Payload(input)),
+        }
+        .pack();
+        let accounts = &ctx.accounts;
+        let mut infos = vec![];
+        if let Some(vk) = &accounts.vk {
+            data[0] |= VK_ACCOUNT_FLAG as u8;
+            infos.push(vk.to_account_info());
+        }
+        infos.push(accounts.payer.to_account_info());
+        infos.push(accounts.accumulator.to_account_info());
+        infos.extend(
+            accounts
+                .inputs
+                .iter()
+                .map(|inputs| inputs.to_account_info()),
+        );
+        run(ctx.program_id, infos, data, Some(session_id))
+    }
+
+    /// Run the final exponentiation stage `stage` (t = 2..=16) with index (i, j) and its
+    /// `input`, on the scratch accounts given as remaining accounts, followed by any the
+    /// final step's flags call for.
+    pub fn final_exponentiation<'info>(
+        ctx: Context<'_, '_, '_, 'info, FinalExponentiation<'info>>,
+        session_id: u64,
+        stage: u8,
+        i: u8,
+        j: u8,
+        input: Vec<u8>,
+    ) -> Result<()> {
+        if !FINAL_STAGES.contains(&stage) {
+            return Err(ProgramError::InvalidInstructionData.into());
+        }
+        let mut data = [&[stage, i, j][..], &input].concat();
+        let mut infos = vec![];
+        if let Some(vk) = &ctx.accounts.vk {
+            data[0] |= VK_ACCOUNT_FLAG as u8;
+            infos.push(vk.to_account_info());
+        }
+        infos.push(ctx.accounts.payer.to_account_info());
+        infos.extend(ctx.remaining_accounts.iter().cloned());
+        run(ctx.program_id, infos, data, Some(session_id))
+    }
+
+    /// The whole verification in one instruction (see `contract/src/verify_complete.rs`),
+    /// `flags` in `i`; a receipt takes its accounts as remaining accounts.
+    pub fn verify_complete<'info>(
+        ctx: Context<'_, '_, '_, 'info, VerifyComplete<'info>>,
+        flags: u8,
+        input: Vec<u8>,
+    ) -> Result<()> {
+        let mut data = VerifyCompleteInstruction(StepIndex::new(flags, 0), Payload(input)).pack();
+        let mut infos = vec![];
+        if let Some(vk) = &ctx.accounts.vk {
+            data[0] |= VK_ACCOUNT_FLAG as u8;
+            infos.push(vk.to_account_info());
+        }
+        infos.extend(ctx.remaining_accounts.iter().cloned());
+        run(ctx.program_id, infos, data, None)
+    }
+
+    /// Close the scratch accounts of session `session_id` with `roles`, given in that
+    /// order as remaining accounts, moving their rent to `refund` (the payer if none); or
+    /// with `reset`, write their initial data again.
+    pub fn close_session<'info>(
+        ctx: Context<'_, '_, '_, 'info, Session<'info>>,
+        session_id: u64,
+        roles: Vec<u8>,
+        reset: bool,
+    ) -> Result<()> {
+        if roles.len() != ctx.remaining_accounts.len() {
+            return Err(ErrorCode::AccountNotEnoughKeys.into());
+        }
+        let operation = if reset { RESET_SESSION } else { CLOSE_SESSION };
+        let data = CloseSession(StepIndex::new(operation, 0), Payload(roles)).pack();
+        let payer = ctx.accounts.payer.to_account_info();
+        let mut infos = vec![payer.clone()];
+        if !reset {
+            let refund = ctx.accounts.refund.as_ref();
+            infos.push(refund.map_or(payer, |refund| refund.to_account_info()));
+        }
+        infos.extend(ctx.remaining_accounts.iter().cloned());
+        run(ctx.program_id, infos, data, Some(session_id))
+    }
+
+    /// Any other verifier instruction, `data` as `contract::instruction` packs it
+    /// (session id included) and its accounts as remaining accounts.
+    pub fn step<'info>(ctx: Context<'_, '_, '_, 'info, Step>, data: Vec<u8>) -> Result<()> {
+        run(ctx.program_id, ctx.remaining_accounts.to_vec(), data, None)
+    }
+}
+
+fn run(
+    program_id: &Pubkey,
+    accounts: Vec<AccountInfo>,
+    mut data: Vec<u8>,
+    session_id: Option<u64>,
+) -> Result<()> {
+    data.extend(session_id.iter().flat_map(|id| id.to_le_bytes()));
+    contract::process_instruction(program_id, &accounts, &data)?;
+    Ok(())
+}
+
+/// The Miller loop a step advances, whose scratch role it is.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopTable {
+    Gamma,
+    Delta,
+}
+
+#[derive(Accounts)]
+#[instruction(session_id: u64, role: u8)]
+pub struct CreateScratch<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: created and initialized by the verifier
+    #[account(
+        mut,
+        seeds = [payer.key().as_ref(), &session_id.to_le_bytes(), &[role]],
+        bump
+    )]
+    pub scratch: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(session_id: u64, table: LoopTable)]
+pub struct MillerLoop<'info> {
+    /// CHECK: a VK account of this program, checked by the verifier
+    pub vk: Option<UncheckedAccount<'info>>,
+    pub payer: Signer<'info>,
+    /// CHECK: the header is checked by the verifier
+    #[account(
+        mut,
+        seeds = [payer.key().as_ref(), &session_id.to_le_bytes(), &[table as u8]],
+        bump
+    )]
+    pub accumulator: UncheckedAccount<'info>,
+    /// CHECK: the folded inputs account, checked by the verifier
+    pub inputs: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct FinalExponentiation<'info> {
+    /// CHECK: a VK account of this program, checked by the verifier
+    pub vk: Option<UncheckedAccount<'info>>,
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyComplete<'info> {
+    /// CHECK: a VK account of this program, checked by the verifier
+    pub vk: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct Session<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: only receives lamports
+    #[account(mut)]
+    pub refund: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct Step {}
+
+#[cfg(test)]
+mod test {
+    use anchor_lang::InstructionData;
+    use ark_bn254::Fr;
+    use circuit::PointEncoding;
+    use contract::VerifierError;
+
+    use super::*;
+
+    // The wrapper verifies as the verifier does, and fails with its errors.
+    #[test]
+    fn test_verify_complete() {
+        let (_, proof, public_inputs) = circuit::demo_proof().unwrap();
+        let (mut lamports, mut data) = (0, vec![]);
+        // the program's own id stands for the absent VK account
+        let accounts = [AccountInfo::new(
+            &ID,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &ID,
+            true,
+            0,
+        )];
+        let verify = |public_inputs: &[Fr]| {
+            let input =
+                circuit::complete_submission(&proof, public_inputs, PointEncoding::Compressed);
+            let data = instruction::VerifyComplete { flags: 0, input }.data();
+            entry(&ID, &accounts, &data)
+        };
+        verify(&public_inputs).unwrap();
+        assert_eq!(
+            verify(&[Fr::from(1u64)]),
+            Err(VerifierError::InvalidProof.into())
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use ark_bn254::{Bn254, Fr, G1Projective};
+use ark_ff::to_bytes;
+use ark_groth16::{prepare_inputs, prepare_verifying_key, PreparedVerifyingKey, VerifyingKey};
+use ark_relations::r1cs::Result as R1CSResult;
+
+/// The folded public input `IC_0 + sum(x_i * IC_i)` together with the inputs that
+/// produced it, so it can be recomputed independently.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FoldedInput {
+    pub public_inputs: Vec<Fr>,
+    pub prepared_input: G1Projective,
+}
+
+impl FoldedInput {
+    pub fn new(pvk: &PreparedVerifyingKey<Bn254>, public_inputs: &[Fr]) -> R1CSResult<Self> {
+        Ok(FoldedInput {
+            public_inputs: public_inputs.to_vec(),
+            prepared_input: prepare_inputs(pvk, public_inputs)?,
+        })
+    }
+
+    /// The folded point exactly as submitted to the gamma Miller loop.
+    pub fn prepared_input_bytes(&self) -> Vec<u8> {
+        to_bytes!(self.prepared_input).unwrap()
+    }
+
+    /// The public inputs as 32-byte little-endian field elements.
+    pub fn public_input_bytes(&self) -> Vec<u8> {
+        to_bytes!(self.public_inputs).unwrap()
+    }
+
+    /// Recompute the folding against `vk` and compare with the recorded point.
+    pub fn check(&self, vk: &VerifyingKey<Bn254>) -> bool {
+        prepare_inputs(&prepare_verifying_key(vk), &self.public_inputs).ok()
+            == Some(self.prepared_input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_std::rand::Rng;
+
+    use super::*;
+    use crate::vk::tests::demo_params;
+
+    #[test]
+    fn folded_input_recomputes() {
+        let (params, _, mut rng) = demo_params();
+        let pvk = prepare_verifying_key(&params.vk);
+        let mut folded = FoldedInput::new(&pvk, &[rng.gen()]).unwrap();
+        assert!(folded.check(&params.vk));
+
+        folded.public_inputs[0] = rng.gen();
+        assert!(!folded.check(&params.vk));
+    }
+}
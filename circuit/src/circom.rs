@@ -0,0 +1,380 @@
+use std::io::{Error, ErrorKind, Result};
+
+use ark_bn254::{Bn254, Fr, FrParameters};
+use ark_ff::{BigInteger256, FpParameters, FromBytes, PrimeField};
+use ark_groth16::{create_random_proof, ProvingKey};
+use ark_relations::lc;
+use ark_relations::r1cs::{
+    ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef, LinearCombination,
+    Result as R1CSResult, SynthesisError, Variable,
+};
+use ark_std::rand::{CryptoRng, RngCore};
+
+use crate::ProofWithInputs;
+
+// circom's binary files (iden3 `binfileutils`) are a 4-byte magic, a u32 version and a
+// u32 section count, then the sections, each a u32 type, a u64 length and its data, in
+// any order. Integers are little-endian.
+//
+//   .r1cs (version 1):
+//     1 header: field element size n8 (32), the prime, then the wires, public outputs,
+//       public inputs and private inputs (u32 each), labels (u64) and constraints (u32)
+//     2 constraints: A, B and C of each, a linear combination being a u32 term count
+//       and as many terms, a u32 wire and an n8-byte coefficient
+//     3 wire to label map, not read
+//   .wtns (version 2):
+//     1 header: n8, the prime, then the values (u32)
+//     2 values, one per wire
+//
+// Field elements are canonical, little-endian (see `FieldForm`). Wire 0 is the constant
+// one, then come the public outputs and the public inputs, which are the proof's
+// public inputs in that order, then the private wires.
+const R1CS_VERSION: u32 = 1;
+const WTNS_VERSION: u32 = 2;
+const SECTION_HEADER: u32 = 1;
+const SECTION_CONSTRAINTS: u32 = 2;
+const SECTION_WITNESS: u32 = 2;
+const FR_LEN: usize = 32;
+
+/// A linear combination of a circom constraint, as `(wire, coefficient)` terms.
+pub type CircomLc = Vec<(usize, Fr)>;
+
+/// The constraint system of a circom circuit over BN254, read from its `.r1cs` file
+/// with [`read_r1cs`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct R1cs {
+    /// Wires, the constant one included.
+    pub num_wires: usize,
+    /// Public outputs and public inputs, the proof's public inputs.
+    pub num_public_inputs: usize,
+    /// `A · B = C` of every constraint.
+    pub constraints: Vec<(CircomLc, CircomLc, CircomLc)>,
+}
+
+/// Read a circom `.r1cs` file. The circuit must be compiled for BN254 (circom's default
+/// prime), and every constraint may only name the file's wires.
+pub fn read_r1cs(bytes: &[u8]) -> Result<R1cs> {
+    let file = BinFile::new(bytes, b"r1cs", R1CS_VERSION)?;
+    let mut header = file.section(SECTION_HEADER)?;
+    header.prime(FrParameters::MODULUS)?;
+    let num_wires = header.u32()? as usize;
+    let num_public_inputs = header.u32()? as usize + header.u32()? as usize;
+    let _private_inputs = header.u32()?;
+    let _labels = header.u64()?;
+    let num_constraints = header.u32()?;
+    if num_wires <= num_public_inputs {
+        return Err(invalid("fewer wires than public inputs"));
+    }
+
+    let mut reader = file.section(SECTION_CONSTRAINTS)?;
+    let mut lc = || -> Result<CircomLc> {
+        let terms = reader.u32()?;
+        (0..terms)
+            .map(|_| {
+                let wire = reader.u32()? as usize;
+                if wire >= num_wires {
+                    return Err(invalid("constraint names an unknown wire"));
+                }
+                Ok((wire, reader.fr()?))
+            })
+            .collect()
+    };
+    let constraints = (0..num_constraints)
+        .map(|_| Ok((lc()?, lc()?, lc()?)))
+        .collect::<Result<_>>()?;
+    if !reader.src.is_empty() {
+        return Err(invalid("trailing bytes after the constraints"));
+    }
+    Ok(R1cs {
+        num_wires,
+        num_public_inputs,
+        constraints,
+    })
+}
+
+/// Read a circom witness, the `.wtns` file of `snarkjs wtns calculate` or of the
+/// circuit's witness generator: the value of every wire of the circuit, in order.
+pub fn read_wtns(bytes: &[u8]) -> Result<Vec<Fr>> {
+    let file = BinFile::new(bytes, b"wtns", WTNS_VERSION)?;
+    let mut header = file.section(SECTION_HEADER)?;
+    header.prime(FrParameters::MODULUS)?;
+    let count = header.u32()?;
+    let mut reader = file.section(SECTION_WITNESS)?;
+    let witness = (0..count)
+        .map(|_| reader.fr())
+        .collect::<Result<Vec<_>>>()?;
+    if !reader.src.is_empty() {
+        return Err(invalid("trailing bytes after the witness"));
+    }
+    match witness.first() {
+        Some(one) if *one == Fr::from(1u64) => Ok(witness),
+        _ => Err(invalid("the witness does not start with the constant one")),
+    }
+}
+
+/// A circom circuit as an arkworks circuit: without a witness for the setup (e.g.
+/// [`setup_keys`](crate::setup_keys)), with one, every wire's value, for proving (see
+/// [`prove_circom`] and [`prove_and_prepare`](crate::prove_and_prepare)).
+#[derive(Clone, Copy)]
+pub struct CircomCircuit<'a> {
+    pub r1cs: &'a R1cs,
+    pub witness: Option<&'a [Fr]>,
+}
+
+impl<'a> CircomCircuit<'a> {
+    /// The public inputs the witness proves, the public outputs first.
+    pub fn public_inputs(&self) -> Option<Vec<Fr>> {
+        let public = self.witness?.get(1..=self.r1cs.num_public_inputs)?;
+        Some(public.to_vec())
+    }
+
+    fn value(&self, wire: usize) -> R1CSResult<Fr> {
+        self.witness
+            .and_then(|witness| witness.get(wire).copied())
+            .ok_or(SynthesisError::AssignmentMissing)
+    }
+}
+
+impl<'a> ConstraintSynthesizer<Fr> for CircomCircuit<'a> {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> R1CSResult<()> {
+        let public = self.r1cs.num_public_inputs;
+        for wire in 1..=public {
+            cs.new_input_variable(|| self.value(wire))?;
+        }
+        for wire in public + 1..self.r1cs.num_wires {
+            cs.new_witness_variable(|| self.value(wire))?;
+        }
+        let variable = |wire: usize| match wire {
+            0 => Variable::One,
+            wire if wire <= public => Variable::Instance(wire),
+            wire => Variable::Witness(wire - public - 1),
+        };
+        let lc = |terms: &CircomLc| {
+            let mut lc: LinearCombination<Fr> = lc!();
+            for (wire, coeff) in terms.iter() {
+                lc += (*coeff, variable(*wire));
+            }
+            lc
+        };
+        for (a, b, c) in self.r1cs.constraints.iter() {
+            cs.enforce_constraint(lc(a), lc(b), lc(c))?;
+        }
+        Ok(())
+    }
+}
+
+/// Prove the circom circuit `r1cs` for `witness` (see [`read_wtns`]) under `pk`, from
+/// a setup of the same circuit, returning the proof and its public inputs. A witness
+/// that does not satisfy every constraint fails with `Unsatisfiable` rather than
+/// yielding a proof the program rejects.
+pub fn prove_circom<R: RngCore + CryptoRng>(
+    r1cs: &R1cs,
+    witness: &[Fr],
+    pk: &ProvingKey<Bn254>,
+    rng: &mut R,
+) -> R1CSResult<ProofWithInputs> {
+    if pk.vk.gamma_abc_g1.len() != r1cs.num_public_inputs + 1 {
+        return Err(SynthesisError::MalformedVerifyingKey);
+    }
+    let circuit = CircomCircuit {
+        r1cs,
+        witness: Some(witness),
+    };
+    let cs = ConstraintSystem::<Fr>::new_ref();
+    circuit.generate_constraints(cs.clone())?;
+    if !cs.is_satisfied()? {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    let public_inputs = circuit.public_inputs().unwrap();
+    Ok((create_random_proof(circuit, pk, rng)?, public_inputs))
+}
+
+// The sections of a circom binary file by type; a type present twice keeps the first.
+pub(crate) struct BinFile<'a> {
+    sections: Vec<(u32, &'a [u8])>,
+}
+
+impl<'a> BinFile<'a> {
+    pub(crate) fn new(bytes: &'a [u8], magic: &[u8; 4], version: u32) -> Result<Self> {
+        let mut reader = BinReader { src: bytes };
+        if reader.take(4)? != magic {
+            return Err(invalid("not a circom file of the expected kind"));
+        }
+        if reader.u32()? != version {
+            return Err(invalid("unsupported file version"));
+        }
+        let count = reader.u32()?;
+        let mut sections = vec![];
+        for _ in 0..count {
+            let kind = reader.u32()?;
+            let len = reader.u64()? as usize;
+            sections.push((kind, reader.take(len)?));
+        }
+        Ok(BinFile { sections })
+    }
+
+    pub(crate) fn section(&self, kind: u32) -> Result<BinReader<'a>> {
+        match self.sections.iter().find(|(k, _)| *k == kind) {
+            Some((_, src)) => Ok(BinReader { src }),
+            None => Err(invalid("missing section")),
+        }
+    }
+}
+
+pub(crate) struct BinReader<'a> {
+    pub(crate) src: &'a [u8],
+}
+
+impl<'a> BinReader<'a> {
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.src.len() < len {
+            return Err(invalid("unexpected end of data"));
+        }
+        let (bytes, rest) = self.src.split_at(len);
+        self.src = rest;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64> {
+        Ok(self.u32()? as u64 | (self.u32()? as u64) << 32)
+    }
+
+    // A canonical scalar field element.
+    pub(crate) fn fr(&mut self) -> Result<Fr> {
+        let repr = BigInteger256::read(self.take(FR_LEN)?)?;
+        Fr::from_repr(repr).ok_or_else(|| invalid("field element is not reduced"))
+    }
+
+    // The field element size and the prime of a header, which must be `modulus`, of
+    // one of BN254's fields.
+    pub(crate) fn prime(&mut self, modulus: BigInteger256) -> Result<()> {
+        if self.u32()? as usize != FR_LEN {
+            return Err(invalid("unsupported field element size"));
+        }
+        let prime = BigInteger256::read(self.take(FR_LEN)?)?;
+        if prime != modulus {
+            return Err(invalid("not a circuit over BN254"));
+        }
+        Ok(())
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use ark_ff::{to_bytes, BigInteger};
+    use ark_groth16::{generate_random_parameters, prepare_verifying_key, verify_proof};
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    pub(crate) fn bin_file(magic: &[u8; 4], version: u32, sections: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut out = magic.to_vec();
+        out.extend(version.to_le_bytes());
+        out.extend((sections.len() as u32).to_le_bytes());
+        for (kind, data) in sections {
+            out.extend(kind.to_le_bytes());
+            out.extend((data.len() as u64).to_le_bytes());
+            out.extend(data);
+        }
+        out
+    }
+
+    fn field_header() -> Vec<u8> {
+        let mut out = (FR_LEN as u32).to_le_bytes().to_vec();
+        out.extend(FrParameters::MODULUS.to_bytes_le());
+        out
+    }
+
+    fn write_lc(out: &mut Vec<u8>, terms: &[(u32, u64)]) {
+        out.extend((terms.len() as u32).to_le_bytes());
+        for (wire, coeff) in terms {
+            out.extend(wire.to_le_bytes());
+            out.extend(to_bytes!(Fr::from(*coeff)).unwrap());
+        }
+    }
+
+    // out = x * y + 2, out public, x a public input, y private: wires one, out, x, y
+    // and t = x * y.
+    pub(crate) fn r1cs_file() -> Vec<u8> {
+        let mut header = field_header();
+        for count in [5u32, 1, 1, 1].iter() {
+            header.extend(count.to_le_bytes());
+        }
+        header.extend(5u64.to_le_bytes());
+        header.extend(2u32.to_le_bytes());
+        let mut constraints = vec![];
+        write_lc(&mut constraints, &[(2, 1)]);
+        write_lc(&mut constraints, &[(3, 1)]);
+        write_lc(&mut constraints, &[(4, 1)]);
+        write_lc(&mut constraints, &[(0, 1)]);
+        write_lc(&mut constraints, &[(4, 1), (0, 2)]);
+        write_lc(&mut constraints, &[(1, 1)]);
+        let sections = [(SECTION_HEADER, header), (SECTION_CONSTRAINTS, constraints)];
+        bin_file(b"r1cs", R1CS_VERSION, &sections)
+    }
+
+    pub(crate) fn wtns_file(values: &[u64]) -> Vec<u8> {
+        let mut header = field_header();
+        header.extend((values.len() as u32).to_le_bytes());
+        let witness = values
+            .iter()
+            .flat_map(|value| to_bytes!(Fr::from(*value)).unwrap())
+            .collect();
+        // sections may come in any order
+        let sections = [(SECTION_WITNESS, witness), (SECTION_HEADER, header)];
+        bin_file(b"wtns", WTNS_VERSION, &sections)
+    }
+
+    #[test]
+    fn prove_circom_circuit() {
+        let rng = &mut StdRng::seed_from_u64(31);
+        let r1cs = read_r1cs(&r1cs_file()).unwrap();
+        assert_eq!((r1cs.num_wires, r1cs.num_public_inputs), (5, 2));
+        assert_eq!(
+            r1cs.constraints[1].1,
+            vec![(4, Fr::from(1u64)), (0, Fr::from(2u64))]
+        );
+        let blank = CircomCircuit {
+            r1cs: &r1cs,
+            witness: None,
+        };
+        let pk = generate_random_parameters::<Bn254, _, _>(blank, rng).unwrap();
+        let pvk = prepare_verifying_key(&pk.vk);
+
+        let witness = read_wtns(&wtns_file(&[1, 17, 3, 5, 15])).unwrap();
+        let (proof, public_inputs) = prove_circom(&r1cs, &witness, &pk, rng).unwrap();
+        assert_eq!(public_inputs, vec![Fr::from(17u64), Fr::from(3u64)]);
+        assert!(verify_proof(&pvk, &proof, &public_inputs).unwrap());
+
+        let wrong = read_wtns(&wtns_file(&[1, 18, 3, 5, 15])).unwrap();
+        assert!(matches!(
+            prove_circom(&r1cs, &wrong, &pk, rng),
+            Err(SynthesisError::Unsatisfiable)
+        ));
+        assert!(matches!(
+            prove_circom(&r1cs, &witness[..4], &pk, rng),
+            Err(SynthesisError::AssignmentMissing)
+        ));
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let r1cs = r1cs_file();
+        assert!(read_r1cs(&r1cs[..r1cs.len() - 1]).is_err());
+        assert!(read_wtns(&r1cs).is_err());
+        let mut other_prime = r1cs.clone();
+        // the prime's lowest byte, after the file and section headers and n8
+        other_prime[12 + 12 + 4] ^= 1;
+        assert!(read_r1cs(&other_prime).is_err());
+        assert!(read_wtns(&wtns_file(&[2, 17, 3, 5, 15])).is_err());
+    }
+}
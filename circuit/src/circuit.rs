@@ -1,32 +1,125 @@
 use ark_ff::Field;
 use ark_relations::{
     lc, ns,
-    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError, Variable},
+    r1cs::{
+        ConstraintSynthesizer, ConstraintSystemRef, LinearCombination, SynthesisError, Variable,
+    },
 };
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
 
 pub const MIMC_ROUNDS: usize = 322;
+// The round function's power map, x^3 as in the bellman example. MiMC is only a
+// permutation when gcd(exponent, |F| - 1) = 1, which callers choosing another exponent
+// have to check for their field.
+pub const MIMC_EXPONENT: u64 = 3;
 
-pub fn mimc<F: Field>(mut xl: F, mut xr: F, constants: &[F]) -> F {
+// The one owner of witness-derived values, which it overwrites when dropped with the
+// `zeroize` feature enabled. Field elements are `Copy`, so only the values worked on in
+// place here are wiped; copies read out of it are not.
+pub(crate) struct Secret<T: Zeroize>(pub T);
+
+#[cfg(not(feature = "zeroize"))]
+pub(crate) trait Zeroize {}
+
+#[cfg(not(feature = "zeroize"))]
+impl<T> Zeroize for T {}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "zeroize")]
+        self.0.zeroize();
+    }
+}
+
+pub fn mimc<F: Field>(xl: F, xr: F, constants: &[F], exponent: u64) -> F {
+    mimc_public_inputs(xl, xr, constants, exponent, 1)[0]
+}
+
+// The rounds after which the circuit exposes xL as a public input when it has
+// `num_public_inputs` of them: evenly spaced, the last one giving the image.
+pub fn public_rounds(num_public_inputs: usize) -> Vec<usize> {
+    assert!((1..=MIMC_ROUNDS).contains(&num_public_inputs));
+    (1..=num_public_inputs)
+        .map(|k| k * MIMC_ROUNDS / num_public_inputs - 1)
+        .collect()
+}
+
+// xL after each of `public_rounds(num_public_inputs)`, the circuit's public inputs in
+// order. The last one is the image. The rounds run on a `Secret` state; the caller's
+// `xl` and `xr` are its own to wipe.
+pub fn mimc_public_inputs<F: Field>(
+    xl: F,
+    xr: F,
+    constants: &[F],
+    exponent: u64,
+    num_public_inputs: usize,
+) -> Vec<F> {
     assert_eq!(constants.len(), MIMC_ROUNDS);
+    let public = public_rounds(num_public_inputs);
+
+    // [xL, xR, (xL + Ci)^e]
+    let mut state = Secret([xl, xr, F::zero()]);
+    let [xl, xr, tmp] = &mut state.0;
+    let mut public_inputs = Vec::with_capacity(num_public_inputs);
+    for (i, constant) in constants.iter().enumerate() {
+        *tmp = *xl;
+        tmp.add_assign(constant);
+        *tmp = tmp.pow([exponent]);
+        tmp.add_assign(&*xr);
+        *xr = *xl;
+        *xl = *tmp;
+        if public.contains(&i) {
+            public_inputs.push(*xl);
+        }
+    }
+
+    public_inputs
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Square,
+    MulBase,
+}
 
-    for i in 0..MIMC_ROUNDS {
-        let mut tmp1 = xl;
-        tmp1.add_assign(&constants[i]);
-        let mut tmp2 = tmp1;
-        tmp2.square_in_place();
-        tmp2.mul_assign(&tmp1);
-        tmp2.add_assign(&xr);
-        xr = xl;
-        xl = tmp2;
+// The other factor of a chain step applied to `acc`, with its value.
+fn operand<F: Field>(
+    op: Op,
+    acc: &LinearCombination<F>,
+    acc_value: Option<F>,
+    base: &LinearCombination<F>,
+    base_value: Option<F>,
+) -> (LinearCombination<F>, Option<F>) {
+    match op {
+        Op::Square => (acc.clone(), acc_value),
+        Op::MulBase => (base.clone(), base_value),
     }
+}
 
-    xl
+// Left-to-right square-and-multiply chain computing base^exponent from base.
+fn power_chain(exponent: u64) -> Vec<Op> {
+    assert!(exponent >= 2);
+    let bits = 64 - exponent.leading_zeros();
+    let mut ops = vec![];
+    for bit in (0..bits - 1).rev() {
+        ops.push(Op::Square);
+        if exponent >> bit & 1 == 1 {
+            ops.push(Op::MulBase);
+        }
+    }
+    ops
 }
 
+// The witness values are copied into the constraint system arkworks builds from the
+// circuit, which nothing wipes.
 pub struct Circuit<'a, F: Field> {
     pub xl: Option<F>,
     pub xr: Option<F>,
     pub constants: &'a [F],
+    pub exponent: u64,
+    // xL is public after each of `public_rounds(num_public_inputs)`
+    pub num_public_inputs: usize,
 }
 
 impl<'a, F: Field> ConstraintSynthesizer<F> for Circuit<'a, F> {
@@ -43,49 +136,56 @@ impl<'a, F: Field> ConstraintSynthesizer<F> for Circuit<'a, F> {
         let mut xr =
             cs.new_witness_variable(|| xr_value.ok_or(SynthesisError::AssignmentMissing))?;
 
+        let ops = power_chain(self.exponent);
+        let public = public_rounds(self.num_public_inputs);
         for i in 0..MIMC_ROUNDS {
-            // xL, xR := xR + (xL + Ci)^3, xL
+            // xL, xR := xR + (xL + Ci)^e, xL
             let ns = ns!(cs, "round");
             let cs = ns.cs();
 
-            // tmp = (xL + Ci)^2
-            let tmp_value = xl_value.map(|mut e| {
+            // base = xL + Ci
+            let base = lc!() + xl + (self.constants[i], Variable::One);
+            let base_value = xl_value.map(|mut e| {
                 e.add_assign(&self.constants[i]);
-                e.square_in_place();
                 e
             });
-            let tmp =
-                cs.new_witness_variable(|| tmp_value.ok_or(SynthesisError::AssignmentMissing))?;
-
-            cs.enforce_constraint(
-                lc!() + xl + (self.constants[i], Variable::One),
-                lc!() + xl + (self.constants[i], Variable::One),
-                lc!() + tmp,
-            )?;
-
-            // new_xL = xR + (xL + Ci)^3
-            // new_xL = xR + tmp * (xL + Ci)
-            // new_xL - xR = tmp * (xL + Ci)
-            let new_xl_value = xl_value.map(|mut e| {
-                e.add_assign(&self.constants[i]);
-                e.mul_assign(&tmp_value.unwrap());
+
+            // One constraint per step of the chain, the intermediate powers are witnesses.
+            // For e = 3: tmp = (xL + Ci)^2, then new_xL - xR = tmp * (xL + Ci).
+            let (last, chain) = ops.split_last().unwrap();
+            let mut acc = base.clone();
+            let mut acc_value = base_value;
+            for op in chain.iter() {
+                let (rhs, rhs_value) = operand(*op, &acc, acc_value, &base, base_value);
+                let tmp_value = acc_value.map(|mut e| {
+                    e.mul_assign(&rhs_value.unwrap());
+                    e
+                });
+                let tmp =
+                    cs.new_witness_variable(|| tmp_value.ok_or(SynthesisError::AssignmentMissing))?;
+                cs.enforce_constraint(acc, rhs, lc!() + tmp)?;
+                acc = lc!() + tmp;
+                acc_value = tmp_value;
+            }
+
+            // new_xL = xR + acc * rhs
+            // new_xL - xR = acc * rhs
+            let (rhs, rhs_value) = operand(*last, &acc, acc_value, &base, base_value);
+            let new_xl_value = acc_value.map(|mut e| {
+                e.mul_assign(&rhs_value.unwrap());
                 e.add_assign(&xr_value.unwrap());
                 e
             });
 
-            let new_xl = if i == (MIMC_ROUNDS - 1) {
-                // This is the last round, xL is our image and so
+            let new_xl = if public.contains(&i) {
+                // xL is public after this round, the last one giving our image, and so
                 // we allocate a public input.
                 cs.new_input_variable(|| new_xl_value.ok_or(SynthesisError::AssignmentMissing))?
             } else {
                 cs.new_witness_variable(|| new_xl_value.ok_or(SynthesisError::AssignmentMissing))?
             };
 
-            cs.enforce_constraint(
-                lc!() + tmp,
-                lc!() + xl + (self.constants[i], Variable::One),
-                lc!() + new_xl - xr,
-            )?;
+            cs.enforce_constraint(acc, rhs, lc!() + new_xl - xr)?;
 
             // xR = xL
             xr = xl;
@@ -99,3 +199,63 @@ impl<'a, F: Field> ConstraintSynthesizer<F> for Circuit<'a, F> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::Fr;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn native_matches_constraints() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let constants = (0..MIMC_ROUNDS).map(|_| rng.gen()).collect::<Vec<Fr>>();
+        for &exponent in [2u64, MIMC_EXPONENT, 5, 7].iter() {
+            let (xl, xr) = (rng.gen(), rng.gen());
+            let cs = ConstraintSystem::<Fr>::new_ref();
+            Circuit {
+                xl: Some(xl),
+                xr: Some(xr),
+                constants: &constants,
+                exponent,
+                num_public_inputs: 1,
+            }
+            .generate_constraints(cs.clone())
+            .unwrap();
+
+            assert!(cs.is_satisfied().unwrap());
+            let image = cs.borrow().unwrap().instance_assignment[1];
+            assert_eq!(image, mimc(xl, xr, &constants, exponent));
+            let per_round = power_chain(exponent).len();
+            assert_eq!(cs.num_constraints(), MIMC_ROUNDS * per_round);
+        }
+    }
+
+    #[test]
+    fn several_public_inputs() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let constants = (0..MIMC_ROUNDS).map(|_| rng.gen()).collect::<Vec<Fr>>();
+        let (xl, xr) = (rng.gen(), rng.gen());
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        Circuit {
+            xl: Some(xl),
+            xr: Some(xr),
+            constants: &constants,
+            exponent: MIMC_EXPONENT,
+            num_public_inputs: 4,
+        }
+        .generate_constraints(cs.clone())
+        .unwrap();
+
+        assert!(cs.is_satisfied().unwrap());
+        let public_inputs = mimc_public_inputs(xl, xr, &constants, MIMC_EXPONENT, 4);
+        assert_eq!(
+            cs.borrow().unwrap().instance_assignment[1..],
+            public_inputs[..]
+        );
+        assert_eq!(public_inputs[3], mimc(xl, xr, &constants, MIMC_EXPONENT));
+        assert_eq!(public_rounds(4), [79, 160, 240, 321]);
+    }
+}
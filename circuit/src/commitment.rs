@@ -0,0 +1,30 @@
+use ark_bn254::Fr;
+use ark_ff::{to_bytes, PrimeField};
+use sha2::{Digest, Sha256};
+
+/// Fold `inputs` into a single public input by hash chaining.
+///
+/// The chain starts from 32 zero bytes and absorbs the inputs in order:
+///
+/// ```text
+/// acc_0 = [0u8; 32]
+/// acc_k = sha256(acc_{k-1} || le_bytes(inputs[k-1]))   for k = 1..=n
+/// public_input = Fr::from_le_bytes_mod_order(acc_n)
+/// ```
+///
+/// where `le_bytes` is the 32-byte little-endian canonical encoding of the field element.
+/// The contract computes the same value in `contract::commitment::hash_chain`.
+pub fn hash_chain(inputs: &[Fr]) -> Fr {
+    Fr::from_le_bytes_mod_order(&hash_chain_accumulator(inputs))
+}
+
+pub fn hash_chain_accumulator(inputs: &[Fr]) -> [u8; 32] {
+    let mut acc = [0u8; 32];
+    for input in inputs {
+        let mut hasher = Sha256::new();
+        hasher.update(acc);
+        hasher.update(to_bytes!(input).unwrap());
+        acc.copy_from_slice(&hasher.finalize());
+    }
+    acc
+}
@@ -0,0 +1,257 @@
+use std::io::{Error, ErrorKind, Result};
+
+use ark_bn254::{Bn254, Fq, Fq2, FqParameters, G1Affine, G1Projective, G2Affine};
+use ark_ec::short_weierstrass_jacobian::GroupAffine;
+use ark_ec::{ProjectiveCurve, SWModelParameters};
+use ark_ff::{to_bytes, BigInteger256, FpParameters, FromBytes, PrimeField, ToBytes, Zero};
+use ark_groth16::{Proof, VerifyingKey};
+use ark_serialize::CanonicalSerialize;
+
+// How a serializer writes base field elements, 32 little-endian bytes either way. A
+// value read in the wrong form is still a field element, just not the intended one.
+// arkworks `ToBytes` and `CanonicalSerialize`, circom's `.r1cs` and `.wtns` and gnark
+// (byte-reversed) write them canonical; `Fp256` limbs, as in `contract/src/pvk.rs`, and
+// snarkjs `.zkey` sections in Montgomery form.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldForm {
+    // The integer value itself
+    Canonical,
+    // The value times `R = 2^256 mod q`
+    Montgomery,
+}
+
+// How the program reads an instruction's curve points, the byte ahead of them, see
+// `contract/src/point.rs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointEncoding {
+    // arkworks `ToBytes`: 65 bytes per G1 point, 96 projective, 129 per G2 point
+    Uncompressed = 0,
+    // arkworks `CanonicalSerialize`: 32 bytes per G1 point, 64 per G2 point
+    Compressed = 1,
+}
+
+pub const FQ_LEN: usize = 32;
+pub const G1_LEN: usize = 2 * FQ_LEN;
+pub const G2_LEN: usize = 4 * FQ_LEN;
+pub const PROOF_LEN: usize = 2 * G1_LEN + G2_LEN;
+
+// Read a base field element in `form`.
+pub fn read_fq(bytes: &[u8], form: FieldForm) -> Result<Fq> {
+    let repr = BigInteger256::read(check_len(bytes, FQ_LEN)?)?;
+    if repr >= FqParameters::MODULUS {
+        return Err(invalid("field element is not reduced"));
+    }
+    Ok(match form {
+        FieldForm::Canonical => Fq::from_repr(repr).unwrap(),
+        FieldForm::Montgomery => Fq::new(repr),
+    })
+}
+
+// Read an affine G1 point as `x || y`; all zeros is the point at infinity.
+pub fn read_g1(bytes: &[u8], form: FieldForm) -> Result<G1Affine> {
+    let bytes = check_len(bytes, G1_LEN)?;
+    let x = read_fq(&bytes[0..32], form)?;
+    let y = read_fq(&bytes[32..64], form)?;
+    if x.is_zero() && y.is_zero() {
+        return Ok(G1Affine::zero());
+    }
+    checked(G1Affine::new(x, y, false))
+}
+
+// Read an affine G2 point as `x.c0 || x.c1 || y.c0 || y.c1`, likewise.
+pub fn read_g2(bytes: &[u8], form: FieldForm) -> Result<G2Affine> {
+    let bytes = check_len(bytes, G2_LEN)?;
+    let x = Fq2::new(
+        read_fq(&bytes[0..32], form)?,
+        read_fq(&bytes[32..64], form)?,
+    );
+    let y = Fq2::new(
+        read_fq(&bytes[64..96], form)?,
+        read_fq(&bytes[96..128], form)?,
+    );
+    if x.is_zero() && y.is_zero() {
+        return Ok(G2Affine::zero());
+    }
+    checked(G2Affine::new(x, y, false))
+}
+
+// Read a proof as `a || b || c`.
+pub fn read_proof(bytes: &[u8], form: FieldForm) -> Result<Proof<Bn254>> {
+    let bytes = check_len(bytes, PROOF_LEN)?;
+    Ok(Proof {
+        a: read_g1(&bytes[0..G1_LEN], form)?,
+        b: read_g2(&bytes[G1_LEN..G1_LEN + G2_LEN], form)?,
+        c: read_g1(&bytes[G1_LEN + G2_LEN..], form)?,
+    })
+}
+
+// Read a verifying key as `alpha || beta || gamma || delta`, then its IC points.
+pub fn read_verifying_key(bytes: &[u8], form: FieldForm) -> Result<VerifyingKey<Bn254>> {
+    let ic_offset = G1_LEN + 3 * G2_LEN;
+    if bytes.len() < ic_offset + G1_LEN {
+        return Err(invalid("unexpected verifying key length"));
+    }
+    let ic = bytes[ic_offset..].chunks_exact(G1_LEN);
+    if !ic.remainder().is_empty() {
+        return Err(invalid("unexpected verifying key length"));
+    }
+    Ok(VerifyingKey {
+        alpha_g1: read_g1(&bytes[0..G1_LEN], form)?,
+        beta_g2: read_g2(&bytes[G1_LEN..G1_LEN + G2_LEN], form)?,
+        gamma_g2: read_g2(&bytes[G1_LEN + G2_LEN..G1_LEN + 2 * G2_LEN], form)?,
+        delta_g2: read_g2(&bytes[G1_LEN + 2 * G2_LEN..ic_offset], form)?,
+        gamma_abc_g1: ic
+            .map(|point| read_g1(point, form))
+            .collect::<Result<_>>()?,
+    })
+}
+
+// An affine G1 point as the `alt_bn128` syscalls read it: `x || y`, big-endian.
+pub fn write_alt_bn128_g1(p: &G1Affine) -> Vec<u8> {
+    if p.infinity {
+        return vec![0; G1_LEN];
+    }
+    [p.x, p.y].iter().flat_map(fq_be).collect()
+}
+
+// An affine G2 point likewise: `x.c1 || x.c0 || y.c1 || y.c0` (EIP-197).
+pub fn write_alt_bn128_g2(p: &G2Affine) -> Vec<u8> {
+    if p.infinity {
+        return vec![0; G2_LEN];
+    }
+    [p.x.c1, p.x.c0, p.y.c1, p.y.c0]
+        .iter()
+        .flat_map(fq_be)
+        .collect()
+}
+
+// Write an affine G1 point in `encoding`.
+pub fn write_g1(p: &G1Affine, encoding: PointEncoding) -> Vec<u8> {
+    write_point(p, encoding)
+}
+
+// Write an affine G2 point in `encoding`.
+pub fn write_g2(p: &G2Affine, encoding: PointEncoding) -> Vec<u8> {
+    write_point(p, encoding)
+}
+
+// Write a projective G1 point in `encoding`; compressed, it is sent affine.
+pub fn write_g1_projective(p: &G1Projective, encoding: PointEncoding) -> Vec<u8> {
+    match encoding {
+        PointEncoding::Uncompressed => to_bytes!(p).unwrap(),
+        PointEncoding::Compressed => write_g1(&p.into_affine(), encoding),
+    }
+}
+
+fn write_point<T: ToBytes + CanonicalSerialize>(p: &T, encoding: PointEncoding) -> Vec<u8> {
+    match encoding {
+        PointEncoding::Uncompressed => to_bytes!(p).unwrap(),
+        PointEncoding::Compressed => {
+            let mut bytes = vec![];
+            p.serialize(&mut bytes).unwrap();
+            bytes
+        }
+    }
+}
+
+fn fq_be(x: &Fq) -> Vec<u8> {
+    let mut bytes = to_bytes!(x).unwrap();
+    bytes.reverse();
+    bytes
+}
+
+fn check_len(bytes: &[u8], len: usize) -> Result<&[u8]> {
+    if bytes.len() != len {
+        return Err(invalid("unexpected length"));
+    }
+    Ok(bytes)
+}
+
+// Reject points off the curve or outside the prime-order subgroup.
+fn checked<P: SWModelParameters>(point: GroupAffine<P>) -> Result<GroupAffine<P>> {
+    if !in_subgroup(&point) {
+        return Err(invalid("point is not in the prime-order subgroup"));
+    }
+    Ok(point)
+}
+
+// Whether `point` is on the curve and in the prime-order subgroup.
+pub(crate) fn in_subgroup<P: SWModelParameters>(point: &GroupAffine<P>) -> bool {
+    point.infinity || (point.is_on_curve() && point.is_in_correct_subgroup_assuming_on_curve())
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_ff::Fp256;
+    use ark_groth16::{prepare_verifying_key, verify_proof};
+
+    use super::*;
+    use crate::demo_proof;
+
+    fn write_fq(out: &mut Vec<u8>, x: &Fq, form: FieldForm) {
+        match form {
+            FieldForm::Canonical => out.extend(to_bytes!(x).unwrap()),
+            FieldForm::Montgomery => {
+                let Fp256(limbs, _) = x;
+                out.extend(to_bytes!(limbs).unwrap())
+            }
+        }
+    }
+
+    fn write_g1(out: &mut Vec<u8>, p: &G1Affine, form: FieldForm) {
+        write_fq(out, &p.x, form);
+        write_fq(out, &p.y, form);
+    }
+
+    fn write_g2(out: &mut Vec<u8>, p: &G2Affine, form: FieldForm) {
+        for x in [p.x.c0, p.x.c1, p.y.c0, p.y.c1].iter() {
+            write_fq(out, x, form);
+        }
+    }
+
+    fn encode(
+        vk: &VerifyingKey<Bn254>,
+        proof: &Proof<Bn254>,
+        form: FieldForm,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let mut vk_bytes = vec![];
+        write_g1(&mut vk_bytes, &vk.alpha_g1, form);
+        write_g2(&mut vk_bytes, &vk.beta_g2, form);
+        write_g2(&mut vk_bytes, &vk.gamma_g2, form);
+        write_g2(&mut vk_bytes, &vk.delta_g2, form);
+        for p in vk.gamma_abc_g1.iter() {
+            write_g1(&mut vk_bytes, p, form);
+        }
+
+        let mut proof_bytes = vec![];
+        write_g1(&mut proof_bytes, &proof.a, form);
+        write_g2(&mut proof_bytes, &proof.b, form);
+        write_g1(&mut proof_bytes, &proof.c, form);
+        (vk_bytes, proof_bytes)
+    }
+
+    #[test]
+    fn both_forms_verify() {
+        let (pvk, proof, public_inputs) = demo_proof().unwrap();
+        for form in [FieldForm::Canonical, FieldForm::Montgomery].iter() {
+            let (vk_bytes, proof_bytes) = encode(&pvk.vk, &proof, *form);
+            let vk = read_verifying_key(&vk_bytes, *form).unwrap();
+            let decoded = read_proof(&proof_bytes, *form).unwrap();
+            assert_eq!(vk, pvk.vk);
+            assert_eq!(decoded, proof);
+            assert!(verify_proof(&prepare_verifying_key(&vk), &decoded, &public_inputs).unwrap());
+        }
+    }
+
+    #[test]
+    fn wrong_form_is_rejected() {
+        let (pvk, proof, _) = demo_proof().unwrap();
+        let (_, proof_bytes) = encode(&pvk.vk, &proof, FieldForm::Montgomery);
+        // decodes to field elements, but not to a point on the curve
+        assert!(read_proof(&proof_bytes, FieldForm::Canonical).is_err());
+    }
+}
@@ -0,0 +1,293 @@
+use std::io::{Error, ErrorKind, Result};
+
+use ark_bn254::{Bn254, Fq, Fq2, G1Affine, G2Affine};
+use ark_ff::Zero;
+use ark_groth16::{Proof, VerifyingKey};
+
+use crate::encoding::{in_subgroup, read_fq, read_g1, read_g2, FieldForm, FQ_LEN};
+
+// gnark (gnark-crypto) BN254 points are big-endian with flags in the two top bits of the
+// first byte, which a 254-bit modulus leaves free:
+//
+//   00 uncompressed (`WriteRawTo`): G1 x || y (64 bytes), G2 x.A1 || x.A0 || y.A1 || y.A0
+//      (128 bytes)
+//   01 point at infinity, all other bits zero
+//   10 / 11 compressed (`WriteTo`): x only, G1 32 bytes and G2 x.A1 || x.A0 (64 bytes),
+//      y being the lexicographically smallest / largest root
+//
+// A proof or key is written in one form throughout, told apart by its first point.
+const FLAG_MASK: u8 = 0b11 << 6;
+const FLAG_UNCOMPRESSED: u8 = 0b00 << 6;
+const FLAG_INFINITY: u8 = 0b01 << 6;
+const FLAG_SMALLEST: u8 = 0b10 << 6;
+const FLAG_LARGEST: u8 = 0b11 << 6;
+
+/// Read a gnark Groth16 proof over BN254, `Ar || Bs || Krs`, as written by `WriteTo`
+/// (compressed, 128 bytes) or `WriteRawTo` (256 bytes).
+///
+/// gnark 0.9 and later append the proof's commitments, a big-endian u32 count and a
+/// proof-of-knowledge point; the count must be zero, the program does not check
+/// commitments.
+pub fn read_gnark_proof(bytes: &[u8]) -> Result<Proof<Bn254>> {
+    let mut reader = GnarkReader::new(bytes)?;
+    let proof = Proof {
+        a: reader.g1()?,
+        b: reader.g2()?,
+        c: reader.g1()?,
+    };
+    if !reader.src.is_empty() {
+        if reader.u32()? != 0 {
+            return Err(invalid("proofs with commitments are not supported"));
+        }
+        let _commitment_pok = reader.g1()?;
+    }
+    if !reader.src.is_empty() {
+        return Err(invalid("unexpected proof length"));
+    }
+    Ok(proof)
+}
+
+/// Read a gnark Groth16 verifying key over BN254 as written by `WriteTo` or
+/// `WriteRawTo`: `G1.Alpha || G1.Beta || G2.Beta || G2.Gamma || G1.Delta || G2.Delta`
+/// followed by `G1.K`, the IC points behind a big-endian u32 count.
+///
+/// The G1 copies of beta and delta are only used by gnark's own prover. Data following
+/// `G1.K`, such as the commitment keys of newer gnark versions, is not read; circuits
+/// using commitments are not supported.
+pub fn read_gnark_verifying_key(bytes: &[u8]) -> Result<VerifyingKey<Bn254>> {
+    let mut reader = GnarkReader::new(bytes)?;
+    let alpha_g1 = reader.g1()?;
+    let _beta_g1 = reader.g1()?;
+    let beta_g2 = reader.g2()?;
+    let gamma_g2 = reader.g2()?;
+    let _delta_g1 = reader.g1()?;
+    let delta_g2 = reader.g2()?;
+    let count = reader.u32()?;
+    if count == 0 {
+        return Err(invalid("verifying key has no IC points"));
+    }
+    let gamma_abc_g1 = (0..count).map(|_| reader.g1()).collect::<Result<_>>()?;
+    Ok(VerifyingKey {
+        alpha_g1,
+        beta_g2,
+        gamma_g2,
+        delta_g2,
+        gamma_abc_g1,
+    })
+}
+
+struct GnarkReader<'a> {
+    src: &'a [u8],
+    compressed: bool,
+}
+
+impl<'a> GnarkReader<'a> {
+    fn new(src: &'a [u8]) -> Result<Self> {
+        let compressed = match src.first().map(|b| b & FLAG_MASK) {
+            Some(FLAG_UNCOMPRESSED) => false,
+            Some(FLAG_SMALLEST) | Some(FLAG_LARGEST) => true,
+            _ => return Err(invalid("cannot tell the point encoding")),
+        };
+        Ok(GnarkReader { src, compressed })
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.src.len() < len {
+            return Err(invalid("unexpected end of data"));
+        }
+        let (bytes, rest) = self.src.split_at(len);
+        self.src = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // The point's flag and its big-endian coordinates with the flag cleared, or `None`
+    // for the point at infinity.
+    fn point(&mut self, elements: usize) -> Result<Option<(u8, Vec<u8>)>> {
+        let len = if self.compressed {
+            elements
+        } else {
+            2 * elements
+        } * FQ_LEN;
+        let mut bytes = self.take(len)?.to_vec();
+        let flag = bytes[0] & FLAG_MASK;
+        bytes[0] &= !FLAG_MASK;
+        if flag == FLAG_INFINITY {
+            if bytes.iter().any(|b| *b != 0) {
+                return Err(invalid("point at infinity with coordinates"));
+            }
+            return Ok(None);
+        }
+        if (flag == FLAG_UNCOMPRESSED) == self.compressed {
+            return Err(invalid("mixed point encodings"));
+        }
+        Ok(Some((flag, bytes)))
+    }
+
+    fn g1(&mut self) -> Result<G1Affine> {
+        let (flag, bytes) = match self.point(1)? {
+            Some(point) => point,
+            None => return Ok(G1Affine::zero()),
+        };
+        if !self.compressed {
+            return read_g1(&little_endian(&bytes), FieldForm::Canonical);
+        }
+        G1Affine::get_point_from_x(fq(&bytes)?, flag == FLAG_LARGEST)
+            .ok_or_else(|| invalid("point is not on the curve"))
+    }
+
+    fn g2(&mut self) -> Result<G2Affine> {
+        let (flag, bytes) = match self.point(2)? {
+            Some(point) => point,
+            None => return Ok(G2Affine::zero()),
+        };
+        if !self.compressed {
+            // A1 || A0 pairs to arkworks' c0 || c1
+            let mut swapped = bytes[FQ_LEN..2 * FQ_LEN].to_vec();
+            swapped.extend(&bytes[..FQ_LEN]);
+            swapped.extend(&bytes[3 * FQ_LEN..]);
+            swapped.extend(&bytes[2 * FQ_LEN..3 * FQ_LEN]);
+            return read_g2(&little_endian(&swapped), FieldForm::Canonical);
+        }
+        let x = Fq2::new(fq(&bytes[FQ_LEN..])?, fq(&bytes[..FQ_LEN])?);
+        match G2Affine::get_point_from_x(x, flag == FLAG_LARGEST) {
+            Some(point) if in_subgroup(&point) => Ok(point),
+            _ => Err(invalid("point is not in the prime-order subgroup")),
+        }
+    }
+}
+
+fn fq(big_endian: &[u8]) -> Result<Fq> {
+    read_fq(&little_endian(big_endian), FieldForm::Canonical)
+}
+
+// Reverse each 32-byte element.
+fn little_endian(big_endian: &[u8]) -> Vec<u8> {
+    big_endian
+        .chunks(FQ_LEN)
+        .flat_map(|element| element.iter().rev().copied())
+        .collect()
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_ec::AffineCurve;
+    use ark_ff::{to_bytes, One};
+    use ark_groth16::verify_proof;
+
+    use super::*;
+    use crate::demo_proof;
+
+    fn write_fq(out: &mut Vec<u8>, x: &Fq) {
+        let mut bytes = to_bytes!(x).unwrap();
+        bytes.reverse();
+        out.extend(bytes);
+    }
+
+    // `coords` as written, x alone when compressed, with the point's flag set
+    fn write_point(
+        out: &mut Vec<u8>,
+        coords: &[Fq],
+        infinity: bool,
+        largest: bool,
+        compressed: bool,
+    ) {
+        let at = out.len();
+        let coords = if compressed {
+            &coords[..coords.len() / 2]
+        } else {
+            coords
+        };
+        for x in coords.iter() {
+            if infinity {
+                out.extend(vec![0; FQ_LEN]);
+            } else {
+                write_fq(out, x);
+            }
+        }
+        out[at] |= match (infinity, compressed, largest) {
+            (true, _, _) => FLAG_INFINITY,
+            (false, false, _) => FLAG_UNCOMPRESSED,
+            (false, true, false) => FLAG_SMALLEST,
+            (false, true, true) => FLAG_LARGEST,
+        };
+    }
+
+    fn write_g1(out: &mut Vec<u8>, p: &G1Affine, compressed: bool) {
+        write_point(out, &[p.x, p.y], p.is_zero(), p.y > -p.y, compressed);
+    }
+
+    fn write_g2(out: &mut Vec<u8>, p: &G2Affine, compressed: bool) {
+        let coords = [p.x.c1, p.x.c0, p.y.c1, p.y.c0];
+        write_point(out, &coords, p.is_zero(), p.y > -p.y, compressed);
+    }
+
+    #[test]
+    fn both_forms_verify() {
+        let (pvk, proof, public_inputs) = demo_proof().unwrap();
+        let vk = &pvk.vk;
+        for &compressed in [true, false].iter() {
+            let mut proof_bytes = vec![];
+            write_g1(&mut proof_bytes, &proof.a, compressed);
+            write_g2(&mut proof_bytes, &proof.b, compressed);
+            write_g1(&mut proof_bytes, &proof.c, compressed);
+            assert_eq!(proof_bytes.len(), if compressed { 128 } else { 256 });
+
+            let mut vk_bytes = vec![];
+            write_g1(&mut vk_bytes, &vk.alpha_g1, compressed);
+            write_g1(&mut vk_bytes, &G1Affine::zero(), compressed);
+            write_g2(&mut vk_bytes, &vk.beta_g2, compressed);
+            write_g2(&mut vk_bytes, &vk.gamma_g2, compressed);
+            write_g1(&mut vk_bytes, &G1Affine::zero(), compressed);
+            write_g2(&mut vk_bytes, &vk.delta_g2, compressed);
+            vk_bytes.extend((vk.gamma_abc_g1.len() as u32).to_be_bytes().iter());
+            for p in vk.gamma_abc_g1.iter() {
+                write_g1(&mut vk_bytes, p, compressed);
+            }
+
+            let decoded = read_gnark_proof(&proof_bytes).unwrap();
+            assert_eq!(decoded, proof);
+            assert_eq!(&read_gnark_verifying_key(&vk_bytes).unwrap(), vk);
+            assert!(verify_proof(&pvk, &decoded, &public_inputs).unwrap());
+
+            // gnark 0.9 and later: no commitments
+            let mut with_commitments = proof_bytes.clone();
+            with_commitments.extend([0; 4].iter());
+            write_g1(&mut with_commitments, &G1Affine::zero(), compressed);
+            assert_eq!(read_gnark_proof(&with_commitments).unwrap(), proof);
+            with_commitments[proof_bytes.len() + 3] = 1;
+            assert!(read_gnark_proof(&with_commitments).is_err());
+            assert!(read_gnark_proof(&proof_bytes[..proof_bytes.len() - 1]).is_err());
+        }
+    }
+
+    #[test]
+    fn compressed_points() {
+        // x = 1 with y = 2, the smaller root
+        let mut bytes = vec![0; FQ_LEN];
+        bytes[0] = FLAG_SMALLEST;
+        bytes[FQ_LEN - 1] = 1;
+        let mut reader = GnarkReader::new(&bytes).unwrap();
+        assert_eq!(reader.g1().unwrap(), G1Affine::prime_subgroup_generator());
+        bytes[0] = FLAG_LARGEST;
+        let mut reader = GnarkReader::new(&bytes).unwrap();
+        assert_eq!(reader.g1().unwrap(), -G1Affine::prime_subgroup_generator());
+
+        // a G2 point on the curve but outside the prime-order subgroup
+        let point = (1u64..)
+            .find_map(|i| G2Affine::get_point_from_x(Fq2::new(Fq::from(i), Fq::one()), false))
+            .unwrap();
+        assert!(!in_subgroup(&point));
+        let mut bytes = vec![];
+        write_g2(&mut bytes, &point, true);
+        assert!(GnarkReader::new(&bytes).unwrap().g2().is_err());
+    }
+}
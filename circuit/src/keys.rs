@@ -0,0 +1,102 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Result};
+use std::path::Path;
+
+use ark_bn254::{Bn254, Fr};
+use ark_groth16::{generate_random_parameters, ProvingKey, VerifyingKey};
+use ark_relations::r1cs::ConstraintSynthesizer;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::rand::{CryptoRng, RngCore};
+
+/// Run the Groth16 setup of `circuit` once, with the witness left unassigned, and write
+/// its keys with [`save_keys`], so that later proofs reuse them instead of running the
+/// setup again. Returns the proving key.
+pub fn setup_keys<C, R>(
+    circuit: C,
+    pk_path: impl AsRef<Path>,
+    vk_path: impl AsRef<Path>,
+    rng: &mut R,
+) -> Result<ProvingKey<Bn254>>
+where
+    C: ConstraintSynthesizer<Fr>,
+    R: RngCore + CryptoRng,
+{
+    let pk = generate_random_parameters::<Bn254, _, _>(circuit, rng)
+        .map_err(|err| Error::new(ErrorKind::InvalidInput, err.to_string()))?;
+    save_keys(&pk, pk_path, vk_path)?;
+    Ok(pk)
+}
+
+/// Write `pk` to `pk_path` with arkworks' compressed `CanonicalSerialize`, as the
+/// client's `PROVING_KEY` takes it, and its verifying key to `vk_path` uncompressed,
+/// as `upload-vk`, `validate-vk` and `gen-pvk` take it.
+pub fn save_keys(
+    pk: &ProvingKey<Bn254>,
+    pk_path: impl AsRef<Path>,
+    vk_path: impl AsRef<Path>,
+) -> Result<()> {
+    pk.serialize(BufWriter::new(File::create(pk_path)?))
+        .map_err(io_error)?;
+    pk.vk
+        .serialize_uncompressed(BufWriter::new(File::create(vk_path)?))
+        .map_err(io_error)
+}
+
+/// Read a proving key written by [`save_keys`], checking its points.
+pub fn load_proving_key(path: impl AsRef<Path>) -> Result<ProvingKey<Bn254>> {
+    ProvingKey::deserialize(BufReader::new(File::open(path)?)).map_err(io_error)
+}
+
+/// Read a verifying key written by [`save_keys`], checking its points.
+pub fn load_verifying_key(path: impl AsRef<Path>) -> Result<VerifyingKey<Bn254>> {
+    VerifyingKey::deserialize_uncompressed(BufReader::new(File::open(path)?)).map_err(io_error)
+}
+
+fn io_error(err: SerializationError) -> Error {
+    match err {
+        SerializationError::IoError(err) => err,
+        err => Error::new(ErrorKind::InvalidData, err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use ark_groth16::{prepare_verifying_key, verify_proof};
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{demo_constants, prove, Circuit, MIMC_EXPONENT};
+
+    #[test]
+    fn keys_round_trip_through_files() {
+        let rng = &mut StdRng::seed_from_u64(21);
+        let constants = demo_constants();
+        let dir = env::temp_dir().join(format!("circuit-keys-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (pk_path, vk_path) = (dir.join("pk.bin"), dir.join("vk.bin"));
+        let blank = Circuit::<Fr> {
+            xl: None,
+            xr: None,
+            constants: &constants,
+            exponent: MIMC_EXPONENT,
+            num_public_inputs: 1,
+        };
+        let pk = setup_keys(blank, &pk_path, &vk_path, rng).unwrap();
+
+        let loaded = load_proving_key(&pk_path).unwrap();
+        assert_eq!(loaded, pk);
+        let vk = load_verifying_key(&vk_path).unwrap();
+        assert_eq!(vk, pk.vk);
+        let (proof, public_inputs) = prove(&loaded, &constants, rng).unwrap();
+        assert!(verify_proof(&prepare_verifying_key(&vk), &proof, &public_inputs).unwrap());
+
+        fs::write(&vk_path, b"not a key").unwrap();
+        let err = load_verifying_key(&vk_path).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert!(load_proving_key(dir.join("missing.bin")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,17 +1,49 @@
+use std::collections::HashMap;
+
 use ark_bn254::{Bn254, Fq12Parameters, Fq2Parameters, Fr, G1Affine};
 use ark_ec::bn::{BnParameters, G1Prepared, G2Prepared, TwistType};
 use ark_ff::{to_bytes, Field, Fp12, Fp12ParamsWrapper, Fp2, QuadExtField};
 use ark_groth16::{
-    create_random_proof, generate_random_parameters, prepare_inputs, prepare_verifying_key,
+    create_random_proof, generate_random_parameters, prepare_verifying_key, verify_proof,
+    PreparedVerifyingKey, Proof, ProvingKey,
 };
-use ark_relations::r1cs::Result as R1CSResult;
+use ark_relations::r1cs::{ConstraintSynthesizer, Result as R1CSResult, SynthesisError};
+use ark_serialize::CanonicalSerialize;
 use ark_std::rand;
-use ark_std::rand::Rng;
-use num_traits::One;
+use ark_std::rand::{CryptoRng, Rng, RngCore};
+use sha2::{Digest, Sha256};
 
-use crate::circuit::{mimc, Circuit, MIMC_ROUNDS};
+pub use crate::audit::FoldedInput;
+pub use crate::circom::{prove_circom, read_r1cs, read_wtns, CircomCircuit, CircomLc, R1cs};
+use crate::circuit::Secret;
+pub use crate::circuit::{
+    mimc, mimc_public_inputs, public_rounds, Circuit, MIMC_EXPONENT, MIMC_ROUNDS,
+};
+pub use crate::commitment::{hash_chain, hash_chain_accumulator};
+pub use crate::encoding::{
+    read_fq, read_g1, read_g2, read_proof, read_verifying_key, write_alt_bn128_g1,
+    write_alt_bn128_g2, write_g1, write_g1_projective, write_g2, FieldForm, PointEncoding,
+};
+pub use crate::gnark::{read_gnark_proof, read_gnark_verifying_key};
+pub use crate::keys::{load_proving_key, load_verifying_key, save_keys, setup_keys};
+pub use crate::pvk_source::pvk_source;
+pub use crate::vk::{
+    check_proof, normalize_gamma_abc, on_chain_vk_id, prepare_verifying_key_with_layout,
+    to_on_chain_vk, to_stateless_vk, validate_vk, vk_fingerprint, vk_hash, GammaAbcLayout,
+    StatelessVk, VkReport,
+};
+pub use crate::zkey::{read_zkey, read_zkey_verifying_key};
 
+mod audit;
+mod circom;
 mod circuit;
+mod commitment;
+mod encoding;
+mod gnark;
+mod keys;
+mod pvk_source;
+mod vk;
+mod zkey;
 
 pub(crate) type EllCoeff<F> = (F, F, F);
 
@@ -34,67 +66,277 @@ pub fn ell(f: &mut Fp12<Fq12Parameters>, coeffs: &EllCoeff<Fp2<Fq2Parameters>>,
     }
 }
 
-pub fn initialize() -> R1CSResult<(Vec<u8>, Vec<u8>, Vec<u8>)> {
-    let rng = &mut {
-        use rand::SeedableRng;
-        // arbitrary seed
-        let seed = [
-            1, 0, 0, 0, 23, 0, 0, 0, 200, 1, 0, 0, 210, 30, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0,
-        ];
-        rand::rngs::StdRng::from_seed(seed)
-    };
+// Set up the MiMC demo circuit and prove a random preimage.
+pub fn demo_proof() -> R1CSResult<(PreparedVerifyingKey<Bn254>, Proof<Bn254>, Vec<Fr>)> {
+    let (pvk, mut proofs) = demo_batch(1)?;
+    let (proof, public_inputs) = proofs.remove(0);
+    Ok((pvk, proof, public_inputs))
+}
+
+// A proof with its public inputs.
+pub type ProofWithInputs = (Proof<Bn254>, Vec<Fr>);
+
+// The demo setup's proving key for the circuit with `num_public_inputs` public inputs,
+// the same on every call. With one input it is the key of `demo_proof`, which the
+// program is built with.
+pub fn demo_key(num_public_inputs: usize) -> R1CSResult<ProvingKey<Bn254>> {
+    let rng = &mut demo_rng();
+    let constants = (0..MIMC_ROUNDS).map(|_| rng.gen()).collect::<Vec<_>>();
+    setup_with_inputs(&constants, num_public_inputs, rng)
+}
 
+// `count` proofs under the demo key, for the program's batch verification (t = 25).
+// The first one is `demo_proof`'s.
+pub fn demo_batch(count: usize) -> R1CSResult<(PreparedVerifyingKey<Bn254>, Vec<ProofWithInputs>)> {
+    let rng = &mut demo_rng();
     let constants = (0..MIMC_ROUNDS).map(|_| rng.gen()).collect::<Vec<_>>();
 
     println!("Creating parameters...");
+    let params = setup(&constants, rng)?;
+    let pvk = prepare_verifying_key(&params.vk);
 
-    // Create parameters for our circuit
-    let params = {
-        let c = Circuit::<Fr> {
-            xl: None,
-            xr: None,
-            constants: &constants,
-        };
+    println!("Creating proofs...");
+    let proofs = (0..count)
+        .map(|_| prove(&params, &constants, rng))
+        .collect::<R1CSResult<Vec<_>>>()?;
+    Ok((pvk, proofs))
+}
+
+fn demo_rng() -> rand::rngs::StdRng {
+    use rand::SeedableRng;
+    // arbitrary seed
+    let seed = [
+        1, 0, 0, 0, 23, 0, 0, 0, 200, 1, 0, 0, 210, 30, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0,
+    ];
+    rand::rngs::StdRng::from_seed(seed)
+}
+
+// The MiMC round constants of the demo circuit, which the program's verifying key is
+// for. A proving key for these constants can be generated with `setup` and any RNG.
+pub fn demo_constants() -> Vec<Fr> {
+    let rng = &mut demo_rng();
+    (0..MIMC_ROUNDS).map(|_| rng.gen()).collect()
+}
+
+// Generate Groth16 parameters for the MiMC circuit with round `constants`. Any
+// cryptographic RNG works, e.g. a hardware RNG or one replaying a ceremony transcript.
+pub fn setup<R: RngCore + CryptoRng>(
+    constants: &[Fr],
+    rng: &mut R,
+) -> R1CSResult<ProvingKey<Bn254>> {
+    setup_with_inputs(constants, 1, rng)
+}
 
-        generate_random_parameters::<Bn254, _, _>(c, rng).unwrap()
+// `setup` for the circuit with `num_public_inputs` public inputs (see
+// `public_rounds`), between 1 and `MIMC_ROUNDS`.
+pub fn setup_with_inputs<R: RngCore + CryptoRng>(
+    constants: &[Fr],
+    num_public_inputs: usize,
+    rng: &mut R,
+) -> R1CSResult<ProvingKey<Bn254>> {
+    let c = Circuit::<Fr> {
+        xl: None,
+        xr: None,
+        constants,
+        exponent: MIMC_EXPONENT,
+        num_public_inputs,
     };
+    generate_random_parameters::<Bn254, _, _>(c, rng)
+}
 
-    let pvk = prepare_verifying_key(&params.vk);
-    println!("Creating proofs...");
-    // Generate a random preimage and compute the image
-    let l = rng.gen();
-    let r = rng.gen();
-    let public_inputs = mimc(l, r, &constants);
+// Prove knowledge of a random MiMC preimage under `params`, returning the proof and
+// its public inputs, as many as the key has. The preimage is held in a `Secret`; `rng`
+// is the caller's to wipe.
+pub fn prove<R: RngCore + CryptoRng>(
+    params: &ProvingKey<Bn254>,
+    constants: &[Fr],
+    rng: &mut R,
+) -> R1CSResult<(Proof<Bn254>, Vec<Fr>)> {
+    // Generate a random preimage
+    let preimage = Secret([rng.gen(), rng.gen()]);
+    let [l, r] = preimage.0;
+    prove_preimage(params, constants, l, r, rng)
+}
 
-    // proof_vec.truncate(0);
+// Prove knowledge of the MiMC preimage `(xl, xr)` under `params`, returning the proof
+// and its public inputs, as many as the key has. The preimage is copied into the
+// circuit, see `Circuit`; `xl` and `xr` are the caller's to wipe.
+pub fn prove_preimage<R: RngCore + CryptoRng>(
+    params: &ProvingKey<Bn254>,
+    constants: &[Fr],
+    xl: Fr,
+    xr: Fr,
+    rng: &mut R,
+) -> R1CSResult<(Proof<Bn254>, Vec<Fr>)> {
+    // Compute the image
+    let num_public_inputs = params.vk.gamma_abc_g1.len() - 1;
+    let public_inputs = mimc_public_inputs(xl, xr, constants, MIMC_EXPONENT, num_public_inputs);
 
     // Create an instance of our circuit (with the
     // witness)
     let c = Circuit {
-        xl: Some(l),
-        xr: Some(r),
-        constants: &constants,
+        xl: Some(xl),
+        xr: Some(xr),
+        constants,
+        exponent: MIMC_EXPONENT,
+        num_public_inputs,
     };
 
     // Create a groth16 proof with our parameters.
-    let proof = create_random_proof(c, &params, rng).unwrap();
-    let prepared_input = prepare_inputs(&pvk, &[public_inputs])?;
-    let mut qap = Fp12::<Fq12Parameters>::one();
-    let r = offline_miller_loop(
-        &G1Prepared::<ark_bn254::Parameters>::from(proof.a.clone()),
-        &G2Prepared::<ark_bn254::Parameters>::from(proof.b.clone()),
-        Fp12::<Fq12Parameters>::one(),
-    );
-    qap *= r;
-    Ok((
-        to_bytes!(proof.c).unwrap(),
-        to_bytes!(prepared_input).unwrap(),
-        to_bytes!(qap).unwrap(),
-    ))
-}
-
-fn offline_miller_loop(
+    let proof = create_random_proof(c, params, rng)?;
+    Ok((proof, public_inputs))
+}
+
+// `prove_preimage` for the witness values by name, `xl` and `xr`; other names are
+// ignored, and a missing one fails with `AssignmentMissing`. Returns the proof and its
+// public inputs serialized with arkworks' compressed `CanonicalSerialize`, as the
+// client's `verify --proof --public` reads them.
+pub fn prove_witness<R: RngCore + CryptoRng>(
+    params: &ProvingKey<Bn254>,
+    constants: &[Fr],
+    witness: &HashMap<String, Fr>,
+    rng: &mut R,
+) -> R1CSResult<(Vec<u8>, Vec<u8>)> {
+    let value = |name: &str| witness.get(name).copied();
+    let (xl, xr) = match (value("xl"), value("xr")) {
+        (Some(xl), Some(xr)) => (xl, xr),
+        _ => return Err(SynthesisError::AssignmentMissing),
+    };
+    let (proof, public_inputs) = prove_preimage(params, constants, xl, xr, rng)?;
+    let mut proof_bytes = vec![];
+    proof
+        .serialize(&mut proof_bytes)
+        .expect("serializing to a vector cannot fail");
+    let mut public_bytes = vec![];
+    public_inputs
+        .serialize(&mut public_bytes)
+        .expect("serializing to a vector cannot fail");
+    Ok((proof_bytes, public_bytes))
+}
+
+// The demo's public inputs and their folded G1 point, for external auditing.
+pub fn folded_input() -> R1CSResult<FoldedInput> {
+    let (pvk, _, public_inputs) = demo_proof()?;
+    FoldedInput::new(&pvk, &public_inputs)
+}
+
+pub fn initialize() -> R1CSResult<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    let (pvk, proof, public_inputs) = demo_proof()?;
+    submission(&pvk, &proof, &public_inputs, PointEncoding::Uncompressed)
+}
+
+// Prove with a pre-generated proving key for the demo circuit (see `demo_constants`),
+// however it was produced, and return what `initialize` returns. The program only
+// accepts the proof if its verifying key is `params.vk`.
+pub fn initialize_with_key<R: RngCore + CryptoRng>(
+    params: &ProvingKey<Bn254>,
+    rng: &mut R,
+) -> R1CSResult<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    let (proof, public_inputs) = prove(params, &demo_constants(), rng)?;
+    let pvk = prepare_verifying_key(&params.vk);
+    submission(&pvk, &proof, &public_inputs, PointEncoding::Uncompressed)
+}
+
+// Prove `circuit`, any relation over the BN254 scalar field, under `pk`, and return what
+// `initialize` returns for the proof of `public_inputs`, the circuit's public inputs in
+// the order it allocates them. The program only accepts the proof if its verifying key
+// is `pvk.vk`, baked in or uploaded to a VK account.
+pub fn prove_and_prepare<C: ConstraintSynthesizer<Fr>, R: RngCore + CryptoRng>(
+    circuit: C,
+    pk: &ProvingKey<Bn254>,
+    pvk: &PreparedVerifyingKey<Bn254>,
+    public_inputs: &[Fr],
+    rng: &mut R,
+) -> R1CSResult<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    let proof = create_random_proof(circuit, pk, rng)?;
+    submission(pvk, &proof, public_inputs, PointEncoding::Uncompressed)
+}
+
+// (proof.c, prepared input, A || B) as sent to the program for `proof` of
+// `public_inputs` under `pvk`, each behind the byte selecting `encoding`: the delta and
+// gamma loops' payloads and, see `proof_ab`, the on-chain A·B loop's
+pub fn submission(
+    pvk: &PreparedVerifyingKey<Bn254>,
+    proof: &Proof<Bn254>,
+    public_inputs: &[Fr],
+    encoding: PointEncoding,
+) -> R1CSResult<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    let folded = FoldedInput::new(pvk, public_inputs)?;
+    let mut proof_c = vec![encoding as u8];
+    proof_c.extend(write_g1(&proof.c, encoding));
+    let mut prepared_input = vec![encoding as u8];
+    prepared_input.extend(write_g1_projective(&folded.prepared_input, encoding));
+    Ok((proof_c, prepared_input, proof_ab(proof, encoding)))
+}
+
+// encoding || proof.a || proof.b as sent with every step of the program's on-chain A·B
+// Miller loop
+pub fn proof_ab(proof: &Proof<Bn254>, encoding: PointEncoding) -> Vec<u8> {
+    let mut data = vec![encoding as u8];
+    data.extend(write_g1(&proof.a, encoding));
+    data.extend(write_g2(&proof.b, encoding));
+    data
+}
+
+// proof.a || proof.b || proof.c in the `alt_bn128` syscall encoding, then the public
+// inputs as 32-byte little-endian values, as sent with the program's single-instruction
+// verification (t = 23), which folds the inputs itself
+pub fn alt_bn128_submission(proof: &Proof<Bn254>, public_inputs: &[Fr]) -> Vec<u8> {
+    let mut data = write_alt_bn128_g1(&proof.a);
+    data.extend(write_alt_bn128_g2(&proof.b));
+    data.extend(write_alt_bn128_g1(&proof.c));
+    data.extend(to_bytes!(public_inputs).unwrap());
+    data
+}
+
+// encoding || proof.a || proof.b || proof.c || public inputs as sent with the program's
+// single-instruction verification from raw inputs (t = 24)
+pub fn complete_submission(
+    proof: &Proof<Bn254>,
+    public_inputs: &[Fr],
+    encoding: PointEncoding,
+) -> Vec<u8> {
+    let mut data = vec![encoding as u8];
+    data.extend(proof_entry(proof, public_inputs, encoding));
+    data
+}
+
+// encoding and the proofs of a batch, each as in `complete_submission`, as sent with the
+// program's batch verification (t = 25)
+pub fn batch_submission(proofs: &[ProofWithInputs], encoding: PointEncoding) -> Vec<u8> {
+    let mut data = vec![encoding as u8];
+    for (proof, public_inputs) in proofs.iter() {
+        data.extend(proof_entry(proof, public_inputs, encoding));
+    }
+    data
+}
+
+fn proof_entry(proof: &Proof<Bn254>, public_inputs: &[Fr], encoding: PointEncoding) -> Vec<u8> {
+    let mut data = write_g1(&proof.a, encoding);
+    data.extend(write_g2(&proof.b, encoding));
+    data.extend(write_g1(&proof.c, encoding));
+    for x in public_inputs.iter() {
+        data.extend(to_bytes!(x).unwrap());
+    }
+    data
+}
+
+// Verify the demo proof entirely off-chain, for the contract's attestation mode.
+// Returns the verification result and sha256(proof || public inputs), the statement
+// hash recorded on-chain.
+pub fn attest() -> R1CSResult<(bool, [u8; 32])> {
+    let (pvk, proof, public_inputs) = demo_proof()?;
+    let valid = verify_proof(&pvk, &proof, &public_inputs)?;
+    let mut hasher = Sha256::new();
+    hasher.update(to_bytes!(proof.a, proof.b, proof.c).unwrap());
+    hasher.update(to_bytes!(public_inputs).unwrap());
+    let mut statement_hash = [0u8; 32];
+    statement_hash.copy_from_slice(&hasher.finalize());
+    Ok((valid, statement_hash))
+}
+
+pub fn offline_miller_loop(
     p: &G1Prepared<ark_bn254::Parameters>,
     q: &G2Prepared<ark_bn254::Parameters>,
     mut f: QuadExtField<Fp12ParamsWrapper<Fq12Parameters>>,
@@ -129,10 +371,116 @@ fn offline_miller_loop(
 
 #[cfg(test)]
 mod tests {
-    use crate::initialize;
+    use ark_relations::lc;
+    use ark_relations::r1cs::ConstraintSystemRef;
+    use ark_serialize::CanonicalDeserialize;
+    use rand_chacha::rand_core::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+
+    // Knowledge of the factors of a public product.
+    struct Factors {
+        x: Option<Fr>,
+        y: Option<Fr>,
+    }
+
+    impl ConstraintSynthesizer<Fr> for Factors {
+        fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> R1CSResult<()> {
+            let product = match (self.x, self.y) {
+                (Some(x), Some(y)) => Some(x * y),
+                _ => None,
+            };
+            let z = cs.new_input_variable(|| product.ok_or(SynthesisError::AssignmentMissing))?;
+            let x = cs.new_witness_variable(|| self.x.ok_or(SynthesisError::AssignmentMissing))?;
+            let y = cs.new_witness_variable(|| self.y.ok_or(SynthesisError::AssignmentMissing))?;
+            cs.enforce_constraint(lc!() + x, lc!() + y, lc!() + z)
+        }
+    }
 
     #[test]
     fn it_works() {
         println!("{:?}", initialize());
     }
+
+    #[test]
+    fn setup_with_other_rng() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(11);
+        let constants = demo_constants();
+        let params = setup(&constants, rng).unwrap();
+        let (proof, public_inputs) = prove(&params, &constants, rng).unwrap();
+        let pvk = prepare_verifying_key(&params.vk);
+        assert!(verify_proof(&pvk, &proof, &public_inputs).unwrap());
+
+        let (demo_pvk, _, _) = demo_proof().unwrap();
+        assert_ne!(params.vk, demo_pvk.vk);
+        let (proof_c, prepared_input, proof_ab) = initialize_with_key(&params, rng).unwrap();
+        assert_eq!(
+            (proof_c.len(), prepared_input.len(), proof_ab.len()),
+            (66, 97, 195)
+        );
+    }
+
+    #[test]
+    fn prove_and_prepare_any_circuit() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(13);
+        let blank = Factors { x: None, y: None };
+        let pk = generate_random_parameters::<Bn254, _, _>(blank, rng).unwrap();
+        let pvk = prepare_verifying_key(&pk.vk);
+        let (x, y): (Fr, Fr) = (rng.gen(), rng.gen());
+        let factors = || Factors {
+            x: Some(x),
+            y: Some(y),
+        };
+
+        let proof = create_random_proof(factors(), &pk, &mut rng.clone()).unwrap();
+        assert!(verify_proof(&pvk, &proof, &[x * y]).unwrap());
+        let prepared = prove_and_prepare(factors(), &pk, &pvk, &[x * y], rng).unwrap();
+        let expected = submission(&pvk, &proof, &[x * y], PointEncoding::Uncompressed).unwrap();
+        assert_eq!(prepared, expected);
+        assert!(prove_and_prepare(factors(), &pk, &pvk, &[], rng).is_err());
+    }
+
+    #[test]
+    fn prove_explicit_witness() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(14);
+        let constants = demo_constants();
+        let params = setup_with_inputs(&constants, 2, rng).unwrap();
+        let pvk = prepare_verifying_key(&params.vk);
+        let (xl, xr) = (Fr::from(3u64), Fr::from(5u64));
+        let mut witness = HashMap::new();
+        witness.insert("xl".to_string(), xl);
+        witness.insert("xr".to_string(), xr);
+
+        let (proof, public) = prove_witness(&params, &constants, &witness, rng).unwrap();
+        let proof = Proof::<Bn254>::deserialize(&proof[..]).unwrap();
+        let public_inputs = Vec::<Fr>::deserialize(&public[..]).unwrap();
+        assert_eq!(
+            public_inputs,
+            mimc_public_inputs(xl, xr, &constants, MIMC_EXPONENT, 2)
+        );
+        assert!(verify_proof(&pvk, &proof, &public_inputs).unwrap());
+
+        witness.remove("xr");
+        assert!(matches!(
+            prove_witness(&params, &constants, &witness, rng),
+            Err(SynthesisError::AssignmentMissing)
+        ));
+    }
+
+    #[test]
+    fn prove_several_public_inputs() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(12);
+        let constants = demo_constants();
+        let params = setup_with_inputs(&constants, 3, rng).unwrap();
+        let (proof, public_inputs) = prove(&params, &constants, rng).unwrap();
+        assert_eq!(public_inputs.len(), 3);
+        let pvk = prepare_verifying_key(&params.vk);
+        assert!(verify_proof(&pvk, &proof, &public_inputs).unwrap());
+        assert!(verify_proof(&pvk, &proof, &public_inputs[..1]).is_err());
+
+        let (demo_pvk, _, _) = demo_proof().unwrap();
+        assert_eq!(demo_key(1).unwrap().vk, demo_pvk.vk);
+        assert_eq!(demo_key(2).unwrap().vk.gamma_abc_g1.len(), 3);
+    }
 }
@@ -0,0 +1,187 @@
+use std::fmt::Write;
+
+use ark_bn254::{Bn254, Fq, Fq12, Fq2};
+use ark_groth16::{prepare_verifying_key, VerifyingKey};
+
+use crate::encoding::{write_alt_bn128_g1, write_alt_bn128_g2};
+use crate::vk::on_chain_vk_id;
+
+const HEADER: &str = "\
+// Generated by `client gen-pvk` from the verifying key of the demo circuit; regenerate it
+// for another circuit rather than editing the constants by hand.
+use ark_bn254::{Fq12Parameters, Fq2Parameters, G1Affine};
+use ark_ff::{
+    BigInteger256, CubicExtField, Fp12ParamsWrapper, Fp256, Fp2ParamsWrapper, QuadExtField,
+};
+use arrayref::array_ref;
+";
+
+const COEFF_GETTERS: &str = "\
+fn get_gamma_data<const LEN: usize>(offset: usize) -> [u64; LEN] {
+    let mut arr: [u64; LEN] = [0; LEN];
+    arr.copy_from_slice(&GAMMA[offset..offset + LEN]);
+    arr
+}
+
+pub fn get_gamma_qef(
+    ell_offset: usize,
+    quad_index: usize,
+) -> QuadExtField<Fp2ParamsWrapper<Fq2Parameters>> {
+    let quad_offset = ell_offset * 3;
+    let fp_offset = (quad_offset + quad_index) * 2;
+    QuadExtField::<Fp2ParamsWrapper<Fq2Parameters>>::new(
+        Fp256::new(BigInteger256::new(get_gamma_data(fp_offset * 4))),
+        Fp256::new(BigInteger256::new(get_gamma_data((fp_offset + 1) * 4))),
+    )
+}
+
+fn get_delta_data<const LEN: usize>(offset: usize) -> [u64; LEN] {
+    let mut arr: [u64; LEN] = [0; LEN];
+    arr.copy_from_slice(&DELTA[offset..offset + LEN]);
+    arr
+}
+
+pub fn get_delta_qef(
+    ell_offset: usize,
+    quad_index: usize,
+) -> QuadExtField<Fp2ParamsWrapper<Fq2Parameters>> {
+    let quad_offset = ell_offset * 3;
+    let fp_offset = (quad_offset + quad_index) * 2;
+    QuadExtField::<Fp2ParamsWrapper<Fq2Parameters>>::new(
+        Fp256::new(BigInteger256::new(get_delta_data(fp_offset * 4))),
+        Fp256::new(BigInteger256::new(get_delta_data((fp_offset + 1) * 4))),
+    )
+}
+";
+
+const GET_IC: &str = "\
+pub fn get_ic(i: usize) -> G1Affine {
+    let offset = i * 8;
+    G1Affine::new(
+        Fp256::new(BigInteger256::new(*array_ref![IC, offset, 4])),
+        Fp256::new(BigInteger256::new(*array_ref![IC, offset + 4, 4])),
+        false,
+    )
+}
+";
+
+/// Render `contract/src/pvk.rs` for `vk`: the prepared `-gamma_g2` and `-delta_g2` ell
+/// coefficient tables, `alpha_g1_beta_g2` and the IC points as Montgomery limbs, with
+/// the getters the program reads them through, the key's points in the encoding of the
+/// `alt_bn128` syscalls and its id. The output is rustfmt-clean, so the
+/// file regenerated for the demo circuit is the one in the repository up to its tests.
+pub fn pvk_source(vk: &VerifyingKey<Bn254>) -> Result<String, String> {
+    let pvk = prepare_verifying_key(vk);
+    let coeff_limbs = |coeffs: &[(Fq2, Fq2, Fq2)]| {
+        coeffs
+            .iter()
+            .flat_map(|(c0, c1, c2)| vec![c0, c1, c2])
+            .flat_map(|c| vec![c.c0, c.c1])
+            .flat_map(limbs)
+            .collect::<Vec<_>>()
+    };
+    let gamma = coeff_limbs(&pvk.gamma_g2_neg_pc.ell_coeffs);
+    let delta = coeff_limbs(&pvk.delta_g2_neg_pc.ell_coeffs);
+    let ic = vk
+        .gamma_abc_g1
+        .iter()
+        .flat_map(|p| vec![p.x, p.y])
+        .flat_map(limbs)
+        .collect::<Vec<_>>();
+
+    let mut source = String::from(HEADER);
+    source.push('\n');
+    table(&mut source, "GAMMA", &gamma);
+    source.push('\n');
+    table(&mut source, "DELTA", &delta);
+    source.push('\n');
+    source.push_str(COEFF_GETTERS);
+    source.push('\n');
+    alpha_g1_beta_g2(&mut source, &pvk.alpha_g1_beta_g2);
+    source.push('\n');
+    source.push_str(
+        "// alpha_g1, beta_g2, gamma_g2 and delta_g2 as the alt_bn128 syscalls read them, see\n\
+         // `alt_bn128.rs`\n",
+    );
+    let points = [
+        ("ALPHA_G1", write_alt_bn128_g1(&vk.alpha_g1)),
+        ("BETA_G2", write_alt_bn128_g2(&vk.beta_g2)),
+        ("GAMMA_G2", write_alt_bn128_g2(&vk.gamma_g2)),
+        ("DELTA_G2", write_alt_bn128_g2(&vk.delta_g2)),
+    ];
+    for (name, point) in points.iter() {
+        // only the `alt-bn128` build reads them
+        source.push_str("#[cfg_attr(not(feature = \"alt-bn128\"), allow(dead_code))]\n");
+        bytes(&mut source, name, point);
+    }
+    source.push('\n');
+    source.push_str(
+        "// sha256 of the key in the on-chain VK layout, the id results, nullifiers and\n\
+         // receipts name it by, see `utils::vk_id`\n",
+    );
+    bytes(&mut source, "VK_ID", &on_chain_vk_id(vk)?);
+    source.push('\n');
+    source.push_str(
+        "// gamma_abc_g1 (IC) points, constant term first: x then y of each, four limbs per\n\
+         // coordinate\n",
+    );
+    writeln!(
+        source,
+        "pub const NUM_PUBLIC_INPUTS: usize = {};",
+        vk.gamma_abc_g1.len().saturating_sub(1)
+    )
+    .unwrap();
+    source.push('\n');
+    table(&mut source, "IC", &ic);
+    source.push('\n');
+    source.push_str(GET_IC);
+    Ok(source)
+}
+
+fn limbs(x: Fq) -> Vec<u64> {
+    (x.0).0.to_vec()
+}
+
+fn table(source: &mut String, name: &str, limbs: &[u64]) {
+    writeln!(source, "static {}: [u64; {}] = [", name, limbs.len()).unwrap();
+    for limb in limbs {
+        writeln!(source, "    {},", limb).unwrap();
+    }
+    source.push_str("];\n");
+}
+
+// A byte table the way rustfmt lays it out, sixteen to a line.
+fn bytes(source: &mut String, name: &str, bytes: &[u8]) {
+    writeln!(source, "pub const {}: [u8; {}] = [", name, bytes.len()).unwrap();
+    for line in bytes.chunks(16) {
+        let line = line
+            .iter()
+            .map(|b| format!("0x{:02x},", b))
+            .collect::<Vec<_>>();
+        writeln!(source, "    {}", line.join(" ")).unwrap();
+    }
+    source.push_str("];\n");
+}
+
+fn alpha_g1_beta_g2(source: &mut String, f: &Fq12) {
+    source.push_str(
+        "pub fn get_alpha_g1_beta_g2() -> QuadExtField<Fp12ParamsWrapper<Fq12Parameters>> {\n    \
+         QuadExtField::<Fp12ParamsWrapper<Fq12Parameters>>::new(\n",
+    );
+    for c in [f.c0, f.c1].iter() {
+        source.push_str("        CubicExtField::new(\n");
+        for c in [c.c0, c.c1, c.c2].iter() {
+            source.push_str("            QuadExtField::new(\n");
+            for x in [c.c0, c.c1].iter() {
+                source.push_str("                Fp256::new(BigInteger256::new([\n");
+                for limb in limbs(*x) {
+                    writeln!(source, "                    {},", limb).unwrap();
+                }
+                source.push_str("                ])),\n");
+            }
+            source.push_str("            ),\n");
+        }
+        source.push_str("        ),\n");
+    }
+    source.push_str("    )\n}\n");
+}
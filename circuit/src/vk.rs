@@ -0,0 +1,299 @@
+use ark_bn254::{Bn254, Fq12, Fr};
+use ark_ec::PairingEngine;
+use ark_ff::{to_bytes, One};
+use ark_groth16::{prepare_verifying_key, verify_proof, PreparedVerifyingKey, Proof, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+use crate::encoding::in_subgroup;
+
+// One prepared G2 ell coefficient, three Fp2 elements.
+const ELL_COEFF_LEN: usize = 192;
+
+// Where a key's IC points hold the constant term `IC_0`. arkworks, snarkjs and gnark
+// write it first; some tools export `[IC_1, ..., IC_n, IC_0]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GammaAbcLayout {
+    // `[IC_0, IC_1, ..., IC_n]`, as arkworks reads them
+    ConstantFirst,
+    // `[IC_1, ..., IC_n, IC_0]`
+    ConstantLast,
+}
+
+// Reorder the IC points of `vk`, in `layout`, into arkworks' order.
+pub fn normalize_gamma_abc(vk: &mut VerifyingKey<Bn254>, layout: GammaAbcLayout) {
+    match layout {
+        GammaAbcLayout::ConstantFirst => {}
+        GammaAbcLayout::ConstantLast => {
+            if !vk.gamma_abc_g1.is_empty() {
+                vk.gamma_abc_g1.rotate_right(1);
+            }
+        }
+    }
+}
+
+/// A verifying key in the pieces the contract reads from instruction data when
+/// verifying without a stored key: the prepared `-gamma_g2` and `-delta_g2` ell
+/// coefficient tables (192 bytes per coefficient) and `alpha_g1_beta_g2`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatelessVk {
+    pub gamma_coeffs: Vec<u8>,
+    pub delta_coeffs: Vec<u8>,
+    pub alpha_g1_beta_g2: Vec<u8>,
+}
+
+pub fn to_stateless_vk(vk: &VerifyingKey<Bn254>) -> StatelessVk {
+    let pvk = prepare_verifying_key(vk);
+    let mut gamma_coeffs = vec![];
+    for (c0, c1, c2) in pvk.gamma_g2_neg_pc.ell_coeffs.iter() {
+        gamma_coeffs.extend(to_bytes!(c0, c1, c2).unwrap());
+    }
+    let mut delta_coeffs = vec![];
+    for (c0, c1, c2) in pvk.delta_g2_neg_pc.ell_coeffs.iter() {
+        delta_coeffs.extend(to_bytes!(c0, c1, c2).unwrap());
+    }
+    StatelessVk {
+        gamma_coeffs,
+        delta_coeffs,
+        alpha_g1_beta_g2: to_bytes!(pvk.alpha_g1_beta_g2).unwrap(),
+    }
+}
+
+/// Serialize `vk` into the on-chain verifying key layout read by
+/// `contract::parse_on_chain_vk` (see `contract/src/vk.rs` for the field offsets):
+/// `num_public_inputs`, the number of ell coefficients per G2 table, `alpha_g1_beta_g2`,
+/// the prepared `-gamma_g2` and `-delta_g2` coefficient tables and the IC points. A key
+/// without IC points has no public input count and is refused.
+pub fn to_on_chain_vk(vk: &VerifyingKey<Bn254>) -> Result<Vec<u8>, String> {
+    if vk.gamma_abc_g1.is_empty() {
+        return Err("gamma_abc_g1 is empty".to_string());
+    }
+    let stateless = to_stateless_vk(vk);
+    let num_public_inputs = vk.gamma_abc_g1.len() as u32 - 1;
+    let num_coeffs = (stateless.gamma_coeffs.len() / ELL_COEFF_LEN) as u32;
+
+    let mut data = vec![];
+    data.extend_from_slice(&num_public_inputs.to_le_bytes());
+    data.extend_from_slice(&num_coeffs.to_le_bytes());
+    data.extend(stateless.alpha_g1_beta_g2);
+    data.extend(stateless.gamma_coeffs);
+    data.extend(stateless.delta_coeffs);
+    data.extend(to_bytes!(vk.gamma_abc_g1).unwrap());
+    Ok(data)
+}
+
+/// The id the contract names `vk` by in results, nullifiers and receipts: sha256 of
+/// [`to_on_chain_vk`], the hash a locked VK upload is finalized with.
+pub fn on_chain_vk_id(vk: &VerifyingKey<Bn254>) -> Result<[u8; 32], String> {
+    let mut id = [0u8; 32];
+    id.copy_from_slice(&Sha256::digest(&to_on_chain_vk(vk)?));
+    Ok(id)
+}
+
+/// sha256 of the arkworks encoding of `vk`, identifying a circuit together with its
+/// setup.
+pub fn vk_hash(vk: &VerifyingKey<Bn254>) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Sha256::digest(&to_bytes!(vk).unwrap()));
+    hash
+}
+
+/// The first 8 bytes of [`vk_hash`] in hex, short enough for logs.
+pub fn vk_fingerprint(vk: &VerifyingKey<Bn254>) -> String {
+    vk_hash(vk)[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// What [`validate_vk`] reports about a well-formed verifying key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VkReport {
+    pub num_public_inputs: usize,
+    pub vk_hash: [u8; 32],
+    pub alpha_g1_beta_g2: Fq12,
+}
+
+/// Check `vk` before deriving program constants from it: every point must be on the
+/// curve and in the prime-order subgroup, there must be an IC point for the constant
+/// term, and `e(alpha_g1, beta_g2)` must be non-trivial and match the prepared key.
+/// On failure, names the offending part of the key.
+pub fn validate_vk(vk: &VerifyingKey<Bn254>) -> Result<VkReport, String> {
+    if !in_subgroup(&vk.alpha_g1) {
+        return Err("alpha_g1 is not in the G1 subgroup".to_string());
+    }
+    for (name, point) in [
+        ("beta_g2", &vk.beta_g2),
+        ("gamma_g2", &vk.gamma_g2),
+        ("delta_g2", &vk.delta_g2),
+    ]
+    .iter()
+    {
+        if !in_subgroup(*point) {
+            return Err(format!("{} is not in the G2 subgroup", name));
+        }
+    }
+    for (i, point) in vk.gamma_abc_g1.iter().enumerate() {
+        if !in_subgroup(point) {
+            return Err(format!("gamma_abc_g1[{}] is not in the G1 subgroup", i));
+        }
+    }
+    if vk.gamma_abc_g1.is_empty() {
+        return Err("gamma_abc_g1 is empty".to_string());
+    }
+
+    let alpha_g1_beta_g2 = Bn254::pairing(vk.alpha_g1, vk.beta_g2);
+    if alpha_g1_beta_g2.is_one() {
+        return Err("alpha_g1_beta_g2 is trivial".to_string());
+    }
+    if prepare_verifying_key(vk).alpha_g1_beta_g2 != alpha_g1_beta_g2 {
+        return Err("alpha_g1_beta_g2 does not match the prepared key".to_string());
+    }
+    Ok(VkReport {
+        num_public_inputs: vk.gamma_abc_g1.len() - 1,
+        vk_hash: vk_hash(vk),
+        alpha_g1_beta_g2,
+    })
+}
+
+/// Check `proof` of `public_inputs` against `vk` off-chain, before paying for its
+/// on-chain verification: the proof's points must be on the curve and in the
+/// prime-order subgroup, there must be a public input for every IC point but the
+/// constant term, and the pairing check must hold. On failure, says which part fails.
+pub fn check_proof(
+    vk: &VerifyingKey<Bn254>,
+    proof: &Proof<Bn254>,
+    public_inputs: &[Fr],
+) -> Result<(), String> {
+    for (name, point) in [("a", &proof.a), ("c", &proof.c)].iter() {
+        if !in_subgroup(*point) {
+            return Err(format!("proof.{} is not in the G1 subgroup", name));
+        }
+    }
+    if !in_subgroup(&proof.b) {
+        return Err("proof.b is not in the G2 subgroup".to_string());
+    }
+    if public_inputs.len() + 1 != vk.gamma_abc_g1.len() {
+        return Err(format!(
+            "{} public inputs given, the verifying key takes {}",
+            public_inputs.len(),
+            vk.gamma_abc_g1.len().saturating_sub(1)
+        ));
+    }
+    match verify_proof(&prepare_verifying_key(vk), proof, public_inputs) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err("the pairing check fails".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+// Prepare a verifying key whose IC points are in `layout`.
+pub fn prepare_verifying_key_with_layout(
+    mut vk: VerifyingKey<Bn254>,
+    layout: GammaAbcLayout,
+) -> PreparedVerifyingKey<Bn254> {
+    normalize_gamma_abc(&mut vk, layout);
+    prepare_verifying_key(&vk)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use ark_groth16::{generate_random_parameters, prepare_inputs, ProvingKey};
+    use ark_std::rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::circuit::{Circuit, MIMC_EXPONENT, MIMC_ROUNDS};
+
+    pub(crate) fn demo_params() -> (ProvingKey<Bn254>, Vec<Fr>, StdRng) {
+        let mut rng = StdRng::seed_from_u64(7);
+        let constants = (0..MIMC_ROUNDS).map(|_| rng.gen()).collect::<Vec<_>>();
+        let c = Circuit::<Fr> {
+            xl: None,
+            xr: None,
+            constants: &constants,
+            exponent: MIMC_EXPONENT,
+            num_public_inputs: 1,
+        };
+        let params = generate_random_parameters::<Bn254, _, _>(c, &mut rng).unwrap();
+        (params, constants, rng)
+    }
+
+    fn folded_with_layout(layout: GammaAbcLayout) {
+        let (params, _, mut rng) = demo_params();
+        let input: Fr = rng.gen();
+        let expected = prepare_inputs(&prepare_verifying_key(&params.vk), &[input]).unwrap();
+
+        let mut exported = params.vk.clone();
+        if layout == GammaAbcLayout::ConstantLast {
+            exported.gamma_abc_g1.rotate_left(1);
+        }
+        let pvk = prepare_verifying_key_with_layout(exported, layout);
+        assert_eq!(pvk.vk.gamma_abc_g1, params.vk.gamma_abc_g1);
+        assert_eq!(prepare_inputs(&pvk, &[input]).unwrap(), expected);
+    }
+
+    #[test]
+    fn fingerprint_identifies_setup() {
+        let (params, _, _) = demo_params();
+        let fingerprint = vk_fingerprint(&params.vk);
+        assert_eq!(fingerprint.len(), 16);
+        assert_eq!(fingerprint, vk_fingerprint(&params.vk.clone()));
+
+        let mut other = params.vk.clone();
+        other.gamma_abc_g1.rotate_left(1);
+        assert_ne!(vk_hash(&other), vk_hash(&params.vk));
+    }
+
+    #[test]
+    fn validate_demo_vk() {
+        let (params, _, _) = demo_params();
+        let report = validate_vk(&params.vk).unwrap();
+        assert_eq!(report.num_public_inputs, 1);
+        assert_eq!(report.vk_hash, vk_hash(&params.vk));
+
+        let mut off_curve = params.vk.clone();
+        off_curve.gamma_abc_g1[1].y += ark_bn254::Fq::one();
+        assert_eq!(
+            validate_vk(&off_curve),
+            Err("gamma_abc_g1[1] is not in the G1 subgroup".to_string())
+        );
+
+        // a key without IC points has no public input count to store
+        let mut no_ic = params.vk.clone();
+        no_ic.gamma_abc_g1.clear();
+        let empty = Err("gamma_abc_g1 is empty".to_string());
+        assert_eq!(to_on_chain_vk(&no_ic), empty);
+        assert_eq!(on_chain_vk_id(&no_ic), empty.map(|_: Vec<u8>| [0; 32]));
+    }
+
+    #[test]
+    fn check_demo_proof() {
+        let (pvk, proof, public_inputs) = crate::demo_proof().unwrap();
+        assert_eq!(check_proof(&pvk.vk, &proof, &public_inputs), Ok(()));
+
+        let other_input = [public_inputs[0] + Fr::one()];
+        assert_eq!(
+            check_proof(&pvk.vk, &proof, &other_input),
+            Err("the pairing check fails".to_string())
+        );
+        assert_eq!(
+            check_proof(&pvk.vk, &proof, &[]),
+            Err("0 public inputs given, the verifying key takes 1".to_string())
+        );
+        let mut off_curve = proof.clone();
+        off_curve.c.y += ark_bn254::Fq::one();
+        assert_eq!(
+            check_proof(&pvk.vk, &off_curve, &public_inputs),
+            Err("proof.c is not in the G1 subgroup".to_string())
+        );
+    }
+
+    #[test]
+    fn constant_first_layout() {
+        folded_with_layout(GammaAbcLayout::ConstantFirst);
+    }
+
+    #[test]
+    fn constant_last_layout() {
+        folded_with_layout(GammaAbcLayout::ConstantLast);
+    }
+}
@@ -0,0 +1,370 @@
+use std::io::{Error, ErrorKind, Result};
+
+use ark_bn254::{Bn254, FqParameters, Fr, FrParameters, G1Affine, G1Projective, G2Affine};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{FftField, Field, FpParameters, One, PrimeField};
+use ark_groth16::{ProvingKey, VerifyingKey};
+
+use crate::circom::{BinFile, BinReader};
+use crate::encoding::{read_g1, read_g2, FieldForm, G1_LEN, G2_LEN};
+
+// A snarkjs Groth16 proving key, the `.zkey` of `snarkjs groth16 setup` and of every
+// phase-2 contribution after it, is a circom binary file (see `circom.rs`), magic
+// "zkey", version 1:
+//
+//   1 protocol: u32, 1 for Groth16
+//   2 header: n8q (32) and q, n8r (32) and r, then the wires, the public inputs and
+//     the domain size (u32 each), then alpha (G1), beta (G1, G2), gamma (G2) and
+//     delta (G1, G2)
+//   3 IC: one G1 point per public input and the constant one
+//   4 A and B coefficients of the constraints, not read: arkworks synthesizes them
+//     from the circuit
+//   5 A, 6 B (G1), 7 B (G2): one point per wire
+//   8 C: one G1 point per private wire, `(beta A_i + alpha B_i + C_i) / delta`
+//   9 H: one G1 point per element of the domain
+//   10 contributions, not read
+//
+// Points are affine, `x || y`, in `FieldForm::Montgomery`, all zeros being the point
+// at infinity. Every section but H is laid out as arkworks lays out its own keys,
+// wires in the order of the circuit's variables (see `CircomCircuit`).
+//
+// H is not. snarkjs evaluates the quotient polynomial on the odd powers of a root of
+// unity of twice the domain size n, `w`, and H_i is the Lagrange basis polynomial of
+// `w^(2i+1)` at tau, over delta. arkworks takes the quotient's coefficients, and its
+// h_query holds `tau^k Z(tau) / delta` for k < n - 1, with `Z(X) = X^n - 1`. That
+// polynomial has degree below 2n and vanishes on the even powers of `w`, where it is
+// -2 on the odd ones, so
+//
+//   h_query[k] = -2 w^k sum_i w^(2ik) H_i,
+//
+// a discrete Fourier transform of the H points over the domain, which `read_zkey`
+// computes once for the key. snarkjs takes `w` to be a power of 5^t (for r - 1 = 2^28
+// t), as arkworks does.
+const ZKEY_VERSION: u32 = 1;
+const SECTION_PROTOCOL: u32 = 1;
+const SECTION_HEADER: u32 = 2;
+const SECTION_IC: u32 = 3;
+const SECTION_A: u32 = 5;
+const SECTION_B_G1: u32 = 6;
+const SECTION_B_G2: u32 = 7;
+const SECTION_C: u32 = 8;
+const SECTION_H: u32 = 9;
+const PROTOCOL_GROTH16: u32 = 1;
+
+/// Read a snarkjs Groth16 proving key (`.zkey`) over BN254 as an arkworks proving key,
+/// its verifying key included, so that a circom circuit is proven with arkworks (see
+/// [`prove_circom`](crate::prove_circom)) under the keys of a snarkjs ceremony, without
+/// a setup of its own.
+///
+/// Every point is checked to be on the curve and in the prime-order subgroup. The H
+/// points are converted to arkworks' basis, which takes `n log n` scalar
+/// multiplications for a domain of `n`; save the key with
+/// [`save_keys`](crate::save_keys) rather than converting it again for every proof.
+pub fn read_zkey(bytes: &[u8]) -> Result<ProvingKey<Bn254>> {
+    let (file, header) = read_header(bytes)?;
+    let vk = header.verifying_key(&file)?;
+    let private_wires = header.wires - header.public_inputs - 1;
+    Ok(ProvingKey {
+        vk,
+        beta_g1: header.beta_g1,
+        delta_g1: header.delta_g1,
+        a_query: g1_points(&file, SECTION_A, header.wires)?,
+        b_g1_query: g1_points(&file, SECTION_B_G1, header.wires)?,
+        b_g2_query: g2_points(&file, SECTION_B_G2, header.wires)?,
+        h_query: h_query(g1_points(&file, SECTION_H, header.domain_size)?)?,
+        l_query: g1_points(&file, SECTION_C, private_wires)?,
+    })
+}
+
+/// Read only the verifying key of a snarkjs Groth16 proving key (`.zkey`), as
+/// `snarkjs zkey export verificationkey` does.
+pub fn read_zkey_verifying_key(bytes: &[u8]) -> Result<VerifyingKey<Bn254>> {
+    let (file, header) = read_header(bytes)?;
+    header.verifying_key(&file)
+}
+
+struct Header {
+    wires: usize,
+    public_inputs: usize,
+    domain_size: usize,
+    alpha_g1: G1Affine,
+    beta_g1: G1Affine,
+    beta_g2: G2Affine,
+    gamma_g2: G2Affine,
+    delta_g1: G1Affine,
+    delta_g2: G2Affine,
+}
+
+impl Header {
+    fn verifying_key(&self, file: &BinFile) -> Result<VerifyingKey<Bn254>> {
+        Ok(VerifyingKey {
+            alpha_g1: self.alpha_g1,
+            beta_g2: self.beta_g2,
+            gamma_g2: self.gamma_g2,
+            delta_g2: self.delta_g2,
+            gamma_abc_g1: g1_points(file, SECTION_IC, self.public_inputs + 1)?,
+        })
+    }
+}
+
+fn read_header(bytes: &[u8]) -> Result<(BinFile<'_>, Header)> {
+    let file = BinFile::new(bytes, b"zkey", ZKEY_VERSION)?;
+    if file.section(SECTION_PROTOCOL)?.u32()? != PROTOCOL_GROTH16 {
+        return Err(invalid("not a Groth16 key"));
+    }
+    let mut reader = file.section(SECTION_HEADER)?;
+    reader.prime(FqParameters::MODULUS)?;
+    reader.prime(FrParameters::MODULUS)?;
+    let wires = reader.u32()? as usize;
+    let public_inputs = reader.u32()? as usize;
+    let domain_size = reader.u32()? as usize;
+    if wires <= public_inputs || !domain_size.is_power_of_two() {
+        return Err(invalid("malformed header"));
+    }
+    let header = Header {
+        wires,
+        public_inputs,
+        domain_size,
+        alpha_g1: g1(&mut reader)?,
+        beta_g1: g1(&mut reader)?,
+        beta_g2: g2(&mut reader)?,
+        gamma_g2: g2(&mut reader)?,
+        delta_g1: g1(&mut reader)?,
+        delta_g2: g2(&mut reader)?,
+    };
+    Ok((file, header))
+}
+
+fn g1(reader: &mut BinReader) -> Result<G1Affine> {
+    read_g1(reader.take(G1_LEN)?, FieldForm::Montgomery)
+}
+
+fn g2(reader: &mut BinReader) -> Result<G2Affine> {
+    read_g2(reader.take(G2_LEN)?, FieldForm::Montgomery)
+}
+
+fn g1_points(file: &BinFile, section: u32, count: usize) -> Result<Vec<G1Affine>> {
+    let mut reader = file.section(section)?;
+    let points = (0..count).map(|_| g1(&mut reader)).collect::<Result<_>>()?;
+    check_end(&reader)?;
+    Ok(points)
+}
+
+fn g2_points(file: &BinFile, section: u32, count: usize) -> Result<Vec<G2Affine>> {
+    let mut reader = file.section(section)?;
+    let points = (0..count).map(|_| g2(&mut reader)).collect::<Result<_>>()?;
+    check_end(&reader)?;
+    Ok(points)
+}
+
+fn check_end(reader: &BinReader) -> Result<()> {
+    if !reader.src.is_empty() {
+        return Err(invalid("unexpected section length"));
+    }
+    Ok(())
+}
+
+// arkworks' h_query from snarkjs' H points, see above.
+fn h_query(h: Vec<G1Affine>) -> Result<Vec<G1Affine>> {
+    let n = h.len();
+    let w = Fr::get_root_of_unity(2 * n).ok_or_else(|| invalid("domain too large"))?;
+    let mut points = h.iter().map(G1Affine::into_projective).collect::<Vec<_>>();
+    fft(&mut points, w.square());
+    let mut scale = -Fr::from(2u64);
+    for point in points.iter_mut() {
+        *point = point.mul(scale.into_repr());
+        scale *= w;
+    }
+    points.truncate(n - 1);
+    Ok(G1Projective::batch_normalization_into_affine(&points))
+}
+
+// `points[k] = sum_i omega^(ik) points[i]` in place, for `omega` of order
+// `points.len()`, a power of two: radix-2, over bit-reversed points.
+fn fft(points: &mut [G1Projective], omega: Fr) {
+    let n = points.len();
+    if n < 2 {
+        return;
+    }
+    let bits = n.trailing_zeros();
+    for k in 0..n {
+        let reversed = k.reverse_bits() >> (usize::BITS - bits) as usize;
+        if k < reversed {
+            points.swap(k, reversed);
+        }
+    }
+    let mut m = 1;
+    while m < n {
+        let step = omega.pow([(n / (2 * m)) as u64]);
+        for start in (0..n).step_by(2 * m) {
+            let mut twiddle = Fr::one();
+            for j in start..start + m {
+                let odd = points[j + m].mul(twiddle.into_repr());
+                points[j + m] = points[j] - odd;
+                points[j] += odd;
+                twiddle *= step;
+            }
+        }
+        m *= 2;
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::{Fq, G2Projective};
+    use ark_ff::{to_bytes, ToBytes, UniformRand, Zero};
+    use ark_groth16::{prepare_verifying_key, verify_proof};
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::circom::tests::{bin_file, r1cs_file, wtns_file};
+    use crate::{prove_circom, read_r1cs, read_wtns, R1cs};
+
+    fn write_fq(out: &mut Vec<u8>, x: &Fq) {
+        // the Montgomery form is the element's internal representation
+        out.extend(to_bytes!(x.0).unwrap());
+    }
+
+    // all zeros for the point at infinity
+    fn write_g1(out: &mut Vec<u8>, p: G1Projective) {
+        let p = p.into_affine();
+        if p.is_zero() {
+            out.extend([0; 64].iter());
+            return;
+        }
+        write_fq(out, &p.x);
+        write_fq(out, &p.y);
+    }
+
+    fn write_g2(out: &mut Vec<u8>, p: G2Projective) {
+        let p = p.into_affine();
+        if p.is_zero() {
+            out.extend([0; 128].iter());
+            return;
+        }
+        for c in [p.x.c0, p.x.c1, p.y.c0, p.y.c1].iter() {
+            write_fq(out, c);
+        }
+    }
+
+    fn field_header<P: FpParameters>(out: &mut Vec<u8>) {
+        out.extend(32u32.to_le_bytes());
+        P::MODULUS.write(&mut *out).unwrap();
+    }
+
+    // Lagrange basis polynomial `i` of the domain of `n` elements, at `tau`.
+    fn lagrange(n: usize, i: usize, tau: Fr) -> Fr {
+        let w = Fr::get_root_of_unity(n).unwrap().pow([i as u64]);
+        let z = tau.pow([n as u64]) - Fr::one();
+        w * z / (Fr::from(n as u64) * (tau - w))
+    }
+
+    // The `.zkey` snarkjs would write for `r1cs` from a setup with these trapdoors.
+    fn zkey_file(r1cs: &R1cs, tau: Fr, alpha: Fr, beta: Fr, gamma: Fr, delta: Fr) -> Vec<u8> {
+        let public = r1cs.num_public_inputs;
+        let rows = r1cs.constraints.len() + public + 1;
+        let n = rows.next_power_of_two();
+        // A, B and C of every wire at tau, with the input rows after the constraints
+        let (mut a, mut b, mut c) = (vec![Fr::zero(); r1cs.num_wires], vec![], vec![]);
+        b.resize(r1cs.num_wires, Fr::zero());
+        c.resize(r1cs.num_wires, Fr::zero());
+        for (row, (lc_a, lc_b, lc_c)) in r1cs.constraints.iter().enumerate() {
+            let l = lagrange(n, row, tau);
+            for (lc, at) in [(lc_a, &mut a), (lc_b, &mut b), (lc_c, &mut c)].iter_mut() {
+                for (wire, coeff) in lc.iter() {
+                    at[*wire] += *coeff * l;
+                }
+            }
+        }
+        for (wire, at) in a.iter_mut().enumerate().take(public + 1) {
+            *at += lagrange(n, r1cs.constraints.len() + wire, tau);
+        }
+        let g1 = G1Projective::prime_subgroup_generator();
+        let g2 = G2Projective::prime_subgroup_generator();
+        let k = |i: usize| beta * a[i] + alpha * b[i] + c[i];
+
+        let mut header = vec![];
+        field_header::<FqParameters>(&mut header);
+        field_header::<FrParameters>(&mut header);
+        for count in [r1cs.num_wires, public, n].iter() {
+            header.extend((*count as u32).to_le_bytes());
+        }
+        write_g1(&mut header, g1.mul(alpha.into_repr()));
+        write_g1(&mut header, g1.mul(beta.into_repr()));
+        write_g2(&mut header, g2.mul(beta.into_repr()));
+        write_g2(&mut header, g2.mul(gamma.into_repr()));
+        write_g1(&mut header, g1.mul(delta.into_repr()));
+        write_g2(&mut header, g2.mul(delta.into_repr()));
+        let g1_section = |scalars: Vec<Fr>| {
+            let mut out = vec![];
+            for s in scalars {
+                write_g1(&mut out, g1.mul(s.into_repr()));
+            }
+            out
+        };
+        let mut b_g2 = vec![];
+        for s in b.iter() {
+            write_g2(&mut b_g2, g2.mul(s.into_repr()));
+        }
+        let h = (0..n)
+            .map(|i| lagrange(2 * n, 2 * i + 1, tau) / delta)
+            .collect();
+        let sections = [
+            (SECTION_PROTOCOL, PROTOCOL_GROTH16.to_le_bytes().to_vec()),
+            (SECTION_HEADER, header),
+            (
+                SECTION_IC,
+                g1_section((0..=public).map(|i| k(i) / gamma).collect()),
+            ),
+            (4, vec![]),
+            (SECTION_A, g1_section(a.clone())),
+            (SECTION_B_G1, g1_section(b.clone())),
+            (SECTION_B_G2, b_g2),
+            (
+                SECTION_C,
+                g1_section((public + 1..r1cs.num_wires).map(|i| k(i) / delta).collect()),
+            ),
+            (SECTION_H, g1_section(h)),
+        ];
+        bin_file(b"zkey", ZKEY_VERSION, &sections)
+    }
+
+    #[test]
+    fn prove_under_snarkjs_key() {
+        let rng = &mut StdRng::seed_from_u64(41);
+        let r1cs = read_r1cs(&r1cs_file()).unwrap();
+        let t = (0..5).map(|_| Fr::rand(rng)).collect::<Vec<_>>();
+        let zkey = zkey_file(&r1cs, t[0], t[1], t[2], t[3], t[4]);
+
+        let pk = read_zkey(&zkey).unwrap();
+        assert_eq!(read_zkey_verifying_key(&zkey).unwrap(), pk.vk);
+        assert_eq!(pk.h_query.len(), 7);
+        let witness = read_wtns(&wtns_file(&[1, 17, 3, 5, 15])).unwrap();
+        let (proof, public_inputs) = prove_circom(&r1cs, &witness, &pk, rng).unwrap();
+        let pvk = prepare_verifying_key(&pk.vk);
+        assert!(verify_proof(&pvk, &proof, &public_inputs).unwrap());
+
+        assert!(read_zkey(&zkey[..zkey.len() - 1]).is_err());
+        assert!(read_zkey(&r1cs_file()).is_err());
+    }
+
+    #[test]
+    fn fft_matches_direct_sum() {
+        let rng = &mut StdRng::seed_from_u64(42);
+        let points = (0..8).map(|_| G1Projective::rand(rng)).collect::<Vec<_>>();
+        let omega = Fr::get_root_of_unity(8).unwrap();
+        let mut transformed = points.clone();
+        fft(&mut transformed, omega);
+        for (k, point) in transformed.iter().enumerate() {
+            let mut sum = G1Projective::zero();
+            for (i, p) in points.iter().enumerate() {
+                sum += p.mul(omega.pow([(i * k) as u64]).into_repr());
+            }
+            assert_eq!(*point, sum);
+        }
+    }
+}
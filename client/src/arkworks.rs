@@ -0,0 +1,119 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+use ark_bn254::{Bn254, Fr};
+use ark_groth16::{Proof, VerifyingKey};
+use ark_serialize::CanonicalDeserialize;
+use circuit::{normalize_gamma_abc, GammaAbcLayout};
+
+// Proofs, verifying keys and public inputs as provers built on ark-groth16 write them
+// with `CanonicalSerialize`, read into the types the rest of the client builds
+// instructions from. Either form is read: compressed (`serialize`) or uncompressed
+// (`serialize_uncompressed`), whichever spans the whole file. Points are checked to be
+// on the curve and in the prime-order subgroup.
+
+pub fn read_proof(bytes: &[u8]) -> Result<Proof<Bn254>> {
+    read(bytes)
+}
+
+pub fn read_verifying_key(bytes: &[u8]) -> Result<VerifyingKey<Bn254>> {
+    read(bytes)
+}
+
+// The public inputs as a serialized `Vec<Fr>`: the count as a little-endian u64, then
+// 32 bytes per input.
+pub fn read_public_inputs(bytes: &[u8]) -> Result<Vec<Fr>> {
+    read(bytes)
+}
+
+// Read a verifying key, a proof and its public inputs, each from its own file, the key's
+// IC points in `layout`, reordered into arkworks'.
+pub fn read_files(
+    vk_path: &str,
+    proof_path: &str,
+    public_path: &str,
+    layout: GammaAbcLayout,
+) -> Result<(VerifyingKey<Bn254>, Proof<Bn254>, Vec<Fr>)> {
+    let mut vk = read_verifying_key(&fs::read(vk_path)?)?;
+    normalize_gamma_abc(&mut vk, layout);
+    let proof = read_proof(&fs::read(proof_path)?)?;
+    let public_inputs = read_public_inputs(&fs::read(public_path)?)?;
+    if public_inputs.len() + 1 != vk.gamma_abc_g1.len() {
+        return Err(invalid(
+            "public input count does not match the verifying key",
+        ));
+    }
+    Ok((vk, proof, public_inputs))
+}
+
+fn read<T: CanonicalDeserialize>(bytes: &[u8]) -> Result<T> {
+    let mut reader = bytes;
+    if let Ok(value) = T::deserialize(&mut reader) {
+        if reader.is_empty() {
+            return Ok(value);
+        }
+    }
+    let mut reader = bytes;
+    match T::deserialize_uncompressed(&mut reader) {
+        Ok(_) if !reader.is_empty() => Err(invalid("trailing bytes")),
+        Ok(value) => Ok(value),
+        Err(err) => Err(Error::new(ErrorKind::InvalidData, err)),
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use ark_groth16::{prepare_verifying_key, verify_proof};
+    use ark_serialize::CanonicalSerialize;
+
+    use super::*;
+
+    fn to_bytes<T: CanonicalSerialize>(value: &T) -> Vec<u8> {
+        let mut bytes = vec![];
+        value.serialize(&mut bytes).unwrap();
+        bytes
+    }
+
+    // A key exported with its constant term last verifies once read in that layout.
+    #[test]
+    fn test_read_files_in_layout() {
+        let (pvk, proof, public_inputs) = circuit::demo_proof().unwrap();
+        let dir = env::temp_dir().join(format!("arkworks-files-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let (vk_path, proof_path, public_path) =
+            (path("vk.bin"), path("proof.bin"), path("public.bin"));
+
+        let mut exported = pvk.vk.clone();
+        exported.gamma_abc_g1.rotate_left(1);
+        fs::write(&vk_path, to_bytes(&exported)).unwrap();
+        fs::write(&proof_path, to_bytes(&proof)).unwrap();
+        fs::write(&public_path, to_bytes(&public_inputs)).unwrap();
+
+        let read = |layout| read_files(&vk_path, &proof_path, &public_path, layout).unwrap();
+        let (vk, read_proof, read_inputs) = read(GammaAbcLayout::ConstantLast);
+        assert_eq!(vk, pvk.vk);
+        assert_eq!((&read_proof, &read_inputs), (&proof, &public_inputs));
+        assert!(verify_proof(&prepare_verifying_key(&vk), &proof, &public_inputs).unwrap());
+
+        let (vk, _, _) = read(GammaAbcLayout::ConstantFirst);
+        assert_eq!(vk, exported);
+        assert!(!verify_proof(&prepare_verifying_key(&vk), &proof, &public_inputs).unwrap());
+
+        fs::write(&public_path, to_bytes(&vec![public_inputs[0]; 2])).unwrap();
+        let err = read_files(
+            &vk_path,
+            &proof_path,
+            &public_path,
+            GammaAbcLayout::ConstantLast,
+        );
+        assert_eq!(err.unwrap_err().kind(), ErrorKind::InvalidData);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
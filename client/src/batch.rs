@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+
+use ark_groth16::prepare_verifying_key;
+use circuit::{submission, ProofWithInputs};
+use log::info;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::client::Client;
+use crate::error::{ClientError, ClientResult};
+use crate::steps;
+
+// A proof queued for batch verification. `vk` is the VK account holding the proof's
+// verifying key (see `Client::upload_vk`), `None` meaning the VK baked into the
+// program.
+pub struct BatchProof {
+    pub vk: Option<Pubkey>,
+    pub proof_c: Vec<u8>,
+    pub prepared_input: Vec<u8>,
+    pub proof_ab: Vec<u8>,
+}
+
+// `count` proofs of the demo circuit under its seeded setup, queued against the VK baked
+// into the program.
+pub fn demo_proofs(count: usize) -> ClientResult<Vec<BatchProof>> {
+    (0..count)
+        .map(|_| {
+            let (proof_c, prepared_input, proof_ab) = circuit::initialize()
+                .map_err(|err| ClientError::Input(format!("cannot prove: {}", err)))?;
+            Ok(BatchProof {
+                vk: None,
+                proof_c,
+                prepared_input,
+                proof_ab,
+            })
+        })
+        .collect()
+}
+
+// Group proof indices by verifying key, preserving submission order within a group.
+pub fn group_by_vk(proofs: &[BatchProof]) -> BTreeMap<Option<Pubkey>, Vec<usize>> {
+    let mut groups: BTreeMap<Option<Pubkey>, Vec<usize>> = BTreeMap::new();
+    for (index, proof) in proofs.iter().enumerate() {
+        groups.entry(proof.vk).or_default().push(index);
+    }
+    groups
+}
+
+impl Client {
+    // Verify proofs against several verifying keys in one batch. The proofs run in
+    // rounds of up to `max_sessions` (see `set_max_sessions`), all of them in one round
+    // by default. Within a round every proof gets its own session accounts (session id
+    // the client's plus the proof's slot in the round), so accumulators never mix, and
+    // all proofs' streams are sent side by side. The next round resets and reuses the
+    // same accounts, so a batch holds the rent of no more sessions than run at once.
+    //
+    // Grouping by VK is also the boundary for random-linear-combination aggregation:
+    // proofs are only ever combined with proofs under the same VK, since the combined
+    // check pairs against that VK's gamma/delta/alpha_g1_beta_g2. A group is therefore
+    // the unit that aggregation would collapse into a single session; different
+    // groups always stay separate sessions.
+    pub fn verify_batch(&self, proofs: &[BatchProof]) -> ClientResult<()> {
+        let groups = group_by_vk(proofs);
+        for (vk, indices) in groups.iter() {
+            let vk_name = match vk {
+                Some(vk) => vk.to_string(),
+                None => format!("<program> ({})", self.vk_fingerprint()?),
+            };
+            info!(
+                "verifying {} proof(s) against vk {}",
+                indices.len(),
+                vk_name
+            );
+        }
+        let order = groups.values().flatten().cloned().collect::<Vec<_>>();
+        let sessions = self.max_sessions().unwrap_or(order.len()).max(1);
+        let rounds = order.chunks(sessions).len();
+        self.start_session()?;
+        for (round, indices) in order.chunks(sessions).enumerate() {
+            if rounds > 1 {
+                info!(
+                    "round {} of {}: {} proof(s)",
+                    round + 1,
+                    rounds,
+                    indices.len()
+                );
+            }
+            self.set_phase("accounts");
+            let mut streams = vec![];
+            for (slot, &index) in indices.iter().enumerate() {
+                let proof = &proofs[index];
+                let keys = self.create_session_accounts(self.session_id() + slot as u64)?;
+
+                let gamma = steps::gamma_miller_loop(keys.gamma, &proof.prepared_input);
+                let delta = steps::delta_miller_loop(keys.delta, &proof.proof_c);
+                let final_steps = steps::final_exponentiation(&keys);
+                let mut proof_streams = self.miller_loops(gamma, delta);
+                proof_streams.push(steps::ab_miller_loop(keys.ab, &proof.proof_ab));
+                proof_streams.push(self.pack_naf_digits(final_steps));
+                for stream in proof_streams.iter_mut() {
+                    steps::in_session(stream, keys.session_id);
+                    if let Some(vk) = proof.vk {
+                        stream
+                            .iter_mut()
+                            .for_each(|step| steps::use_vk_account(step, vk));
+                    }
+                }
+                streams.extend(proof_streams);
+            }
+            self.set_phase("batch");
+            self.send_streams(&streams)?;
+        }
+        self.set_phase("");
+        Ok(())
+    }
+
+    // Verify `proofs`, from any prover, as one batch (see `verify_batch`) against the
+    // verifying key in use, read from the VK account or carried inline as for
+    // `groth16_verify_proof`.
+    pub fn verify_proofs(&self, proofs: &[ProofWithInputs]) -> ClientResult<()> {
+        let pvk = prepare_verifying_key(&self.verifying_key()?);
+        let proofs = proofs
+            .iter()
+            .map(|(proof, public_inputs)| {
+                let (proof_c, prepared_input, proof_ab) =
+                    submission(&pvk, proof, public_inputs, self.point_encoding())
+                        .map_err(ClientError::input)?;
+                Ok(BatchProof {
+                    vk: None,
+                    proof_c,
+                    prepared_input,
+                    proof_ab,
+                })
+            })
+            .collect::<ClientResult<Vec<_>>>()?;
+        self.verify_batch(&proofs)
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+
+use groth16_sol_client::{Client, ClientResult};
+
+pub struct BenchReport {
+    pub runs: usize,
+    pub latencies: Vec<Duration>,
+    pub total_fees: u64,
+}
+
+impl BenchReport {
+    pub fn median(&self) -> Duration {
+        percentile(&self.latencies, 50)
+    }
+
+    pub fn p95(&self) -> Duration {
+        percentile(&self.latencies, 95)
+    }
+
+    pub fn print(&self) {
+        println!("| runs | median (s) | p95 (s) | total fees (SOL) | fees/run (lamports) |");
+        println!("|------|------------|---------|------------------|---------------------|");
+        println!(
+            "| {} | {:.2} | {:.2} | {:.6} | {} |",
+            self.runs,
+            self.median().as_secs_f64(),
+            self.p95().as_secs_f64(),
+            self.total_fees as f64 / LAMPORTS_PER_SOL as f64,
+            self.total_fees / self.runs.max(1) as u64,
+        );
+    }
+}
+
+fn percentile(samples: &[Duration], p: usize) -> Duration {
+    if samples.is_empty() {
+        return Duration::default();
+    }
+    let mut sorted = samples.to_vec();
+    sorted.sort();
+    // nearest-rank percentile
+    let rank = (p as f64 / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.max(1) - 1]
+}
+
+// Run `runs` full verifications against the configured cluster. Fees are measured as
+// the payer's balance delta, so the first run also includes rent for any account it
+// had to create, unless the client closes the scratch accounts after every
+// verification (`enable_cleanup`) and so every run creates them again.
+pub fn bench(client: &Client, runs: usize) -> ClientResult<BenchReport> {
+    let mut latencies = Vec::with_capacity(runs);
+    let mut total_fees = 0;
+    for run in 0..runs {
+        let before = client.get_payer_balance()?;
+        let start = Instant::now();
+        client.groth16_verify()?;
+        let elapsed = start.elapsed();
+        let after = client.get_payer_balance()?;
+        total_fees += before.saturating_sub(after);
+        println!("run {}: {:.2}s", run + 1, elapsed.as_secs_f64());
+        latencies.push(elapsed);
+    }
+    Ok(BenchReport {
+        runs,
+        latencies,
+        total_fees,
+    })
+}
@@ -0,0 +1,455 @@
+use std::str::FromStr;
+
+use circuit::GammaAbcLayout;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+// The command line: options for the cluster, payer and program, then a subcommand,
+// `verify` when none is given. The verification options stay environment variables
+// (see the README).
+pub fn app() -> App<'static, 'static> {
+    let vk_file = || Arg::with_name("vk").value_name("VK_FILE");
+    App::new("client")
+        .about("Verify Groth16 proofs with the on-chain verifier")
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .short("C")
+                .value_name("FILE")
+                .env("SOLANA_CONFIG")
+                .global(true)
+                .help("Solana CLI config file [default: the CLI's]"),
+        )
+        .arg(
+            Arg::with_name("url")
+                .long("url")
+                .short("u")
+                .value_name("URL")
+                .global(true)
+                .help("JSON RPC URL of the cluster [default: the Solana CLI config's]"),
+        )
+        .arg(
+            Arg::with_name("cluster")
+                .long("cluster")
+                .value_name("CLUSTER")
+                .possible_values(&["localnet", "devnet", "testnet", "mainnet-beta"])
+                .conflicts_with("url")
+                .global(true)
+                .help("Cluster to connect to, at its public RPC URL"),
+        )
+        .arg(
+            Arg::with_name("ws")
+                .long("ws")
+                .value_name("URL")
+                .env("WEBSOCKET_URL")
+                .global(true)
+                .help("Websocket URL to subscribe to signatures at [default: the RPC URL's]"),
+        )
+        .arg(
+            Arg::with_name("no_websocket")
+                .long("no-websocket")
+                .conflicts_with("ws")
+                .global(true)
+                .help("Poll transaction statuses instead of subscribing to signatures"),
+        )
+        .arg(
+            Arg::with_name("tpu")
+                .long("tpu")
+                .env("TPU")
+                .global(true)
+                .help("Send transactions to the leaders' TPUs over QUIC instead of over RPC"),
+        )
+        .arg(
+            Arg::with_name("commitment")
+                .long("commitment")
+                .value_name("COMMITMENT")
+                .possible_values(&["processed", "confirmed", "finalized"])
+                .global(true)
+                .help("Commitment to confirm transactions at [default: the cluster's]"),
+        )
+        .arg(
+            Arg::with_name("keypair")
+                .long("keypair")
+                .short("k")
+                .value_name("KEYPAIR")
+                .env("PAYER_KEYPAIR")
+                .global(true)
+                .help("Fee payer keypair file or usb://ledger [default: the Solana CLI config's]"),
+        )
+        .arg(
+            Arg::with_name("signer_command")
+                .long("signer-command")
+                .value_name("COMMAND")
+                .env("SIGNER_COMMAND")
+                .conflicts_with("keypair")
+                .global(true)
+                .help("Command signing for the fee payer through an external service"),
+        )
+        .arg(
+            Arg::with_name("program_keypair")
+                .long("program-keypair")
+                .value_name("KEYPAIR")
+                .env("PROGRAM_KEYPAIR")
+                .global(true)
+                .help("Program keypair [default: target/deploy/contract-keypair.json]"),
+        )
+        .arg(
+            Arg::with_name("program_id")
+                .long("program-id")
+                .value_name("PUBKEY")
+                .env("PROGRAM_ID")
+                .global(true)
+                .help("Verifier program [default: the program keypair's]"),
+        )
+        .arg(
+            Arg::with_name("session")
+                .long("session")
+                .value_name("ID")
+                .env("SESSION_ID")
+                .default_value("0")
+                .global(true)
+                .help("Session id the scratch accounts derive from"),
+        )
+        .arg(
+            Arg::with_name("compute_unit_price")
+                .long("compute-unit-price")
+                .value_name("MICRO_LAMPORTS")
+                .env("COMPUTE_UNIT_PRICE")
+                .global(true)
+                .help("Priority fee: price of a compute unit, on every transaction"),
+        )
+        .arg(
+            Arg::with_name("nonce_account")
+                .long("nonce-account")
+                .value_name("PUBKEY")
+                .env("NONCE_ACCOUNTS")
+                .multiple(true)
+                .number_of_values(1)
+                .use_delimiter(true)
+                .global(true)
+                .help("Durable nonce account to sign over, one per transaction in flight"),
+        )
+        .arg(
+            Arg::with_name("keep_accounts")
+                .long("keep-accounts")
+                .global(true)
+                .help("Keep the scratch accounts after a verification, for the next one"),
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .global(true)
+                .help("Submit proofs from other provers that fail the off-chain check"),
+        )
+        .arg(
+            Arg::with_name("ic-layout")
+                .long("ic-layout")
+                .value_name("LAYOUT")
+                .possible_values(&["constant-first", "constant-last"])
+                .default_value("constant-first")
+                .global(true)
+                .help("Position of the constant term among the verifying key's IC points"),
+        )
+        .args(&verify_args())
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Verify a demo proof, or the given proof files, on chain")
+                .args(&verify_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("resume")
+                .about("Resume an interrupted verification from its session manifest")
+                .arg(
+                    Arg::with_name("manifest")
+                        .value_name("MANIFEST")
+                        .help("Session manifest [default: session-<SESSION>.json]"),
+                )
+                .arg(report_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("deploy")
+                .about("Deploy or upgrade the verifier program")
+                .arg(
+                    Arg::with_name("program")
+                        .long("program")
+                        .value_name("SO_FILE")
+                        .env("PROGRAM_SO")
+                        .help("Program to deploy [default: target/deploy/contract.so]"),
+                )
+                .arg(
+                    Arg::with_name("buffer")
+                        .long("buffer")
+                        .value_name("KEYPAIR_FILE")
+                        .env("PROGRAM_BUFFER")
+                        .help("Buffer keypair, kept to resume [default: buffer-<PROGRAM_ID>.json]"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("create-session")
+                .about("Create the session's scratch accounts, or reset those it has"),
+        )
+        .subcommand(
+            SubCommand::with_name("create-nonce-accounts")
+                .about("Create durable nonce accounts authorized to the payer")
+                .arg(Arg::with_name("count").value_name("COUNT").default_value("1")),
+        )
+        .subcommand(
+            SubCommand::with_name("status")
+                .about("List the session's scratch accounts and how far its verification is"),
+        )
+        .subcommand(
+            SubCommand::with_name("close")
+                .alias("close-session")
+                .about("Close the session's scratch accounts")
+                .arg(
+                    Arg::with_name("refund")
+                        .value_name("REFUND")
+                        .help("Address receiving the rent [default: the payer]"),
+                )
+                .arg(
+                    Arg::with_name("reset")
+                        .long("reset")
+                        .conflicts_with("refund")
+                        .help("Reset the accounts to their initial data instead"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("estimate")
+                .about("Estimate the fees and rent of a verification, sending nothing")
+                .arg(vk_file().long("vk").help("Verifying key [default: the one in use]"))
+                .arg(
+                    Arg::with_name("inputs")
+                        .long("inputs")
+                        .value_name("COUNT")
+                        .conflicts_with("vk")
+                        .help("Public input count of the verifying key instead"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("dry-run")
+                .about("Write the instructions of a verification to a file, sending nothing")
+                .args(&verify_args()[..4])
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .value_name("FILE")
+                        .help("File to write [default: instructions-<SESSION>.json]"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Benchmark full verifications")
+                .arg(Arg::with_name("runs").value_name("RUNS").default_value("5")),
+        )
+        .subcommand(
+            SubCommand::with_name("batch")
+                .about("Verify demo proofs as one batch")
+                .arg(Arg::with_name("count").value_name("COUNT").default_value("2"))
+                .arg(
+                    Arg::with_name("max_sessions")
+                        .long("max-sessions")
+                        .value_name("N")
+                        .env("MAX_SESSIONS")
+                        .help("Sessions verifying proofs at once [default: one per proof]"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("complete")
+                .about("Verify the demo proof in a single instruction"),
+        )
+        .subcommand(
+            SubCommand::with_name("combined")
+                .about("Verify demo proofs in a single instruction, combined")
+                .arg(Arg::with_name("count").value_name("COUNT").default_value("2")),
+        )
+        .subcommand(
+            SubCommand::with_name("snarkjs")
+                .about("Verify a circom + snarkjs proof in a single instruction")
+                .arg(vk_file().value_name("VERIFICATION_KEY_JSON").required(true))
+                .arg(Arg::with_name("proof").value_name("PROOF_JSON").required(true))
+                .arg(Arg::with_name("public").value_name("PUBLIC_JSON").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("gnark")
+                .about("Verify a gnark proof in a single instruction")
+                .arg(vk_file().required(true))
+                .arg(Arg::with_name("proof").value_name("PROOF_FILE").required(true))
+                .arg(Arg::with_name("inputs").value_name("PUBLIC_INPUT").multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("audit")
+                .about("Print the public inputs and the folded input point"),
+        )
+        .subcommand(
+            SubCommand::with_name("attest")
+                .about("Verify off-chain and record an attestation on chain"),
+        )
+        .subcommand(
+            SubCommand::with_name("callback")
+                .about("Verify, then call a program in the final transaction")
+                .arg(Arg::with_name("program").value_name("PROGRAM_ID").required(true))
+                .arg(Arg::with_name("accounts").value_name("ACCOUNT").multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("upload-vk")
+                .about("Upload a verifying key into a new VK account")
+                .arg(vk_file()),
+        )
+        .subcommand(
+            SubCommand::with_name("init-registry")
+                .about("Create the VK registry, administered by the payer"),
+        )
+        .subcommand(
+            SubCommand::with_name("register-vk")
+                .about("Upload a verifying key and register it under an id")
+                .arg(Arg::with_name("id").value_name("ID").required(true))
+                .arg(vk_file()),
+        )
+        .subcommand(
+            SubCommand::with_name("update-vk")
+                .about("Upload a verifying key and point a registered id at it")
+                .arg(Arg::with_name("id").value_name("ID").required(true))
+                .arg(vk_file()),
+        )
+        .subcommand(
+            SubCommand::with_name("freeze-vk")
+                .about("Make a registered id immutable")
+                .arg(Arg::with_name("id").value_name("ID").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("set-registry-admin")
+                .about("Hand the VK registry over to another administrator")
+                .arg(Arg::with_name("admin").value_name("PUBKEY").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("calibrate")
+                .about("Measure the compute units of every step, for STEP_COSTS")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .default_value("step-costs.txt"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("pairing").about("Check a demo pairing product on chain"),
+        )
+        .subcommand(
+            SubCommand::with_name("nullifier")
+                .about("Tell whether a nullifier has been spent")
+                .arg(Arg::with_name("nullifier").value_name("HEX").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("result")
+                .about("Print a record of the result log")
+                .arg(Arg::with_name("index").value_name("INDEX").default_value("0")),
+        )
+        .subcommand(
+            SubCommand::with_name("validate-vk")
+                .about("Check a verifying key file")
+                .arg(Arg::with_name("file").value_name("FILE").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("setup")
+                .about("Generate and save keys for the demo circuit")
+                .arg(Arg::with_name("pk").value_name("PK_FILE").required(true))
+                .arg(vk_file().required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("gen-pvk")
+                .about("Write the program's hardcoded verifying key for another circuit")
+                .arg(vk_file().required(true))
+                .arg(Arg::with_name("output").value_name("PVK_RS")),
+        )
+}
+
+// The proof files `verify` takes, all or none.
+fn verify_args() -> Vec<Arg<'static, 'static>> {
+    let file = |name, help| {
+        Arg::with_name(name)
+            .long(name)
+            .value_name("FILE")
+            .requires_all(&["proof", "public", "vk"])
+            .help(help)
+    };
+    vec![
+        file("proof", "Proof: snarkjs proof.json, or a gnark or arkworks proof file"),
+        file(
+            "public",
+            "Public inputs: snarkjs public.json, decimal values separated by whitespace \
+             (gnark) or an arkworks Vec<Fr>",
+        ),
+        file("vk", "Verifying key: snarkjs verification_key.json, or a gnark or arkworks file"),
+        Arg::with_name("format")
+            .long("format")
+            .value_name("FORMAT")
+            .possible_values(&["snarkjs", "gnark", "arkworks"])
+            .requires("proof")
+            .help("Format of the proof files [default: snarkjs for a .json proof, else arkworks]"),
+        Arg::with_name("manifest")
+            .long("manifest")
+            .value_name("FILE")
+            .help("Session manifest to record progress to [default: session-<SESSION>.json]"),
+        report_arg(),
+    ]
+}
+
+// Where to write the JSON report of a verification, `-` for stdout.
+fn report_arg() -> Arg<'static, 'static> {
+    Arg::with_name("report")
+        .long("report")
+        .value_name("FILE")
+        .help("Write a JSON report of the verification to FILE, or - for stdout")
+}
+
+// The value of argument `name` parsed, exiting with clap's error when it does not parse.
+pub fn value<T: FromStr>(args: &ArgMatches, name: &str) -> Option<T> {
+    args.value_of(name)
+        .map(|_| value_t!(args, name, T).unwrap_or_else(|err| err.exit()))
+}
+
+// How a verifying key file orders its IC points, see `circuit::GammaAbcLayout`.
+pub fn ic_layout(args: &ArgMatches) -> GammaAbcLayout {
+    match args.value_of("ic-layout") {
+        Some("constant-last") => GammaAbcLayout::ConstantLast,
+        _ => GammaAbcLayout::ConstantFirst,
+    }
+}
+
+// The session manifest argument, or the session's default one.
+pub fn manifest_path(args: &ArgMatches) -> String {
+    match args.value_of("manifest") {
+        Some(path) => path.to_string(),
+        None => format!("session-{}.json", value::<u64>(args, "session").unwrap()),
+    }
+}
+
+// Prints what the library reports as it goes: progress to stdout, warnings to stderr.
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Info && metadata.target().starts_with("groth16_sol_client")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Info => println!("{}", record.args()),
+            Level::Warn => eprintln!("warning: {}", record.args()),
+            _ => eprintln!("error: {}", record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+// Have `Logger` print the messages of the library.
+pub fn init_logger() {
+    static LOGGER: Logger = Logger;
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+}
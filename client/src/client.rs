@@ -1,323 +1,3844 @@
-use std::path::Path;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use ark_ec::bn::BnParameters;
+use clap::ArgMatches;
+use solana_clap_utils::keypair::signer_from_path;
 use solana_cli_config::{Config, CONFIG_FILE};
-use solana_client::client_error::Result as ClientResult;
+use futures::future::join_all;
+use futures::stream::{self, StreamExt};
+use log::{info, warn};
+use solana_client::client_error::{
+    ClientError as RpcClientError, ClientErrorKind, Result as RpcResult,
+};
+use solana_client::nonblocking;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::nonblocking::tpu_client::TpuClient;
+use solana_client::nonce_utils;
 use solana_client::rpc_client::RpcClient;
-use solana_client::rpc_response::RpcVersionInfo;
+use solana_client::rpc_config::{
+    RpcSendTransactionConfig, RpcSignatureSubscribeConfig, RpcTransactionConfig,
+};
+use solana_client::rpc_custom_error::JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY;
+use solana_client::rpc_request::RpcError;
+use solana_client::rpc_response::{RpcSignatureResult, RpcVersionInfo};
+use solana_client::tpu_client::TpuClientConfig;
+use solana_quic_client::{QuicConfig, QuicConnectionManager, QuicPool};
+use solana_transaction_status::UiTransactionEncoding;
+use solana_sdk::account::Account;
+use solana_sdk::account_utils::StateMut;
+use solana_sdk::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
 use solana_sdk::commitment_config::CommitmentConfig;
-use solana_sdk::instruction::AccountMeta;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::hash::{hash, Hash};
+use solana_sdk::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_sdk::message::Message;
 use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use solana_sdk::program_error::ProgramError;
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::signature::{read_keypair_file, Keypair};
+use solana_sdk::signature::{read_keypair_file, write_keypair_file, Keypair, Signature};
 use solana_sdk::signer::Signer;
-use solana_sdk::transaction::Transaction;
+use solana_sdk::nonce::State as NonceState;
+use solana_sdk::system_instruction;
+use solana_sdk::system_program;
+use solana_sdk::transaction::{Transaction, TransactionError};
 
-use circuit::initialize;
+use ark_bn254::{Bn254, Fq12, Fr, G1Affine, G2Affine};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{to_bytes, One, UniformRand};
+use contract::instruction::VerifierInstruction::{
+    self, CloseSession, CreateScratch, FoldPublicInputs, RecordAttestation, VerifyAltBn128,
+    VerifyBatch, VerifyComplete, VkRegistry, WriteVk,
+};
+use contract::attestation::attestation_address;
+use contract::g2_prepare::{DELTA_TABLE, GAMMA_TABLE};
+use contract::header::{check_account_header, AccountKind, ACCOUNT_HEADER_LEN};
+use contract::pairing::PAIR_POINT;
+use contract::nullifier::{nullifier_address, NULLIFIER_INPUT};
+use contract::receipt::{receipt_address, statement_hash, Receipt, RECEIPT_FLAG};
+use contract::result_log::{
+    max_capacity as max_log_capacity, record_offset, result_log_address, CREATE_RESULT_LOG,
+    LOG_HEADER_LEN,
+};
+use contract::registry::{
+    program_data_address, registry_address, vk_entry_address, VkEntry, ENTRY_IMMUTABLE,
+    REGISTRY_FREEZE, REGISTRY_INIT, REGISTRY_REGISTER, REGISTRY_SET_ADMIN, REGISTRY_UPDATE,
+    VK_REGISTRY_FLAGS,
+};
+use contract::plan::StepCosts;
+use contract::schedule::prepare_g2_steps;
+use contract::scratch::{
+    check_header, scratch_address, Role, CLOSE_SESSION, RESET_SESSION, ROLE_COUNT,
+    SCRATCH_HEADER_LEN,
+};
+use contract::vk::{FINALIZE_VK, INIT_VK_ACCOUNT, VK_ACCOUNT_HEADER_LEN, WRITE_VK_CHUNK};
+use contract::{
+    parse_on_chain_vk, read_accumulator, read_vk_account, VerifierError, VK_ACCOUNT_FLAG,
+};
+use contract::instruction::{Payload, StepIndex};
+use ark_groth16::{
+    prepare_verifying_key, PreparedVerifyingKey, Proof, ProvingKey, VerifyingKey,
+};
+use crate::cluster::{Cluster, MAINNET_BETA_GENESIS_HASH};
+use crate::error::{ClientError, ClientResult};
+use crate::manifest::{transaction_hash, Manifest};
+use crate::report::{proof_hash, Report, ReportLog};
+use circuit::{
+    alt_bn128_submission, attest, batch_submission, complete_submission, demo_batch,
+    demo_constants, demo_proof, folded_input, on_chain_vk_id, prove, submission,
+    to_on_chain_vk, to_stateless_vk, vk_fingerprint, write_g1, write_g2, FoldedInput,
+    PointEncoding, ProofWithInputs, StatelessVk,
+};
+use rand::rngs::OsRng;
+use tokio::runtime::{self, Runtime};
 
-const CONTRACT_SO_PATH: &str =
-    "/mnt/e/Programs/zklink/groth16-sol-verifier/target/deploy/contract.so";
-const CONTRACT_KEYPAIR_PATH: &str =
-    "/mnt/e/Programs/zklink/groth16-sol-verifier/target/deploy/contract-keypair.json";
-const SIZE: usize = 384;
+use crate::steps::{self, Callback, SessionKeys, Step};
+
+// Where `cargo build-bpf` writes the program and its keypair: the deploy directory of
+// the target directory the client was built into, the workspace's unless
+// CARGO_TARGET_DIR named another
+const CONTRACT_SO: &str = "contract.so";
+const CONTRACT_KEYPAIR: &str = "contract-keypair.json";
+// Fold instruction (t = 18) source taking the raw public inputs from instruction data
+const SOURCE_INSTRUCTION: u8 = 3;
+// Times a transaction is resent after a retryable failure, and the wait before the
+// first resend, doubled for every later one
+const RETRIES: u32 = 3;
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+// Bytes of verifying key written per upload transaction
+const VK_CHUNK_LEN: usize = 900;
+// Compute units requested per transaction, and the default planning target
+pub const COMPUTE_UNITS: u32 = 1_000_000;
+// Transactions sent at once while they are independent of each other
+const MAX_IN_FLIGHT: usize = 16;
+// Wait between polls of the statuses of transactions confirmed together, and with
+// signature subscriptions, for notifications before polling anyway
+const STATUS_POLL_INTERVAL: Duration = Duration::from_millis(400);
+const NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(2);
+// Compute units a transaction without a request gets per instruction
+const DEFAULT_INSTRUCTION_UNITS: u64 = 200_000;
+// Headroom of a compute unit request sized by simulation: a share of the measured
+// units, and a fixed amount for the compute budget and nonce instructions
+const PREFLIGHT_MARGIN_PERCENT: u64 = 20;
+const PREFLIGHT_OVERHEAD_UNITS: u64 = 1_000;
 
 pub struct Client {
     config: Config,
+    // The cluster the RPC URL belongs to, if known, and the commitment transactions are
+    // confirmed at
+    cluster: Option<Cluster>,
+    commitment: CommitmentConfig,
     connection: RpcClient,
-    payer: Keypair,
+    // Connection to the same cluster that transactions are sent over, several in
+    // flight at once, driven by `runtime`
+    sender: nonblocking::rpc_client::RpcClient,
+    // Signature subscriptions telling when those transactions land, if connected
+    pubsub: Option<PubsubClient>,
+    // Sends those transactions straight to the upcoming leaders over QUIC, if connected
+    tpu: Option<TpuClient<QuicPool, QuicConnectionManager, QuicConfig>>,
+    runtime: Runtime,
+    payer: Box<dyn Signer>,
+    // Funds the rent of the log, result, session and VK accounts; the fee payer when
+    // unset. Scratch accounts are always funded by the fee payer they derive from.
+    rent_payer: Option<Keypair>,
+    // Session id the scratch accounts derive from, next to the payer
+    session_id: u64,
+    // Sessions a batch verifies proofs in at once, all of its proofs when unset
+    max_sessions: Option<usize>,
     program_id: Pubkey,
+    // The program build and keypair `deploy` deploys
+    program_path: String,
+    program_keypair_path: String,
+    // Where `deploy` saves the keypair of its buffer account, see `buffer_keypair_path`
+    buffer_keypair_path: Option<String>,
+    compute_budget: Cell<bool>,
+    // Priority fee: price of a compute unit in micro-lamports, attached to every
+    // transaction
+    compute_unit_price: Option<u64>,
+    // Durable nonce accounts, authorized to the payer, whose nonces the transactions
+    // use instead of a recent blockhash; those not in use by a transaction in flight
+    nonce_accounts: Vec<Pubkey>,
+    free_nonce_accounts: RefCell<Vec<Pubkey>>,
+    // Resends after a retryable failure, and the wait before the first one
+    retries: u32,
+    retry_backoff: Duration,
+    // Whether steps record their compute units in the session's telemetry account
+    // (contract `telemetry` feature)
+    telemetry: bool,
+    // Append-only log the final step records its result to
+    result_log: Option<Pubkey>,
+    // Whether a session account guards against concurrent writers, and the current
+    // session's (nonce, next step index)
+    session_guard: bool,
+    session: Cell<(u64, u32)>,
+    // Pre-generated proving key for the demo circuit; the seeded demo setup when unset
+    proving_key: Option<ProvingKey<Bn254>>,
+    // Verifying key of proofs from another prover; the proving key's when unset
+    verifying_key: Option<VerifyingKey<Bn254>>,
+    // Fingerprint of the verifying key in use, computed on first use
+    vk_fingerprint: RefCell<Option<String>>,
+    // VK account the Miller-loop and final steps read the verifying key from
+    vk_account: Option<Pubkey>,
+    // Id and registry entry of the VK account, when steps name it by its registered id
+    registered_vk: Option<(u64, Pubkey)>,
+    // Send the raw public inputs and fold them on-chain against the VK account
+    onchain_inputs: bool,
+    // Send the verifying key in the instruction data instead of using the baked one
+    inline_vk: bool,
+    stateless_vk: RefCell<Option<StatelessVk>>,
+    // Whether the final step writes the outcome of the latest verification to the
+    // session's result account
+    result_account: bool,
+    // Try the single-instruction verification over the alt_bn128 syscall first
+    alt_bn128: bool,
+    // Send curve points compressed, see `contract/src/point.rs`
+    compressed_points: bool,
+    // Spend the nullifier of the public inputs, see `contract/src/nullifier.rs`
+    nullifiers: bool,
+    // Create a receipt for every statement verified in a single instruction
+    receipts: bool,
+    // Gamma and delta Miller-loop steps run per instruction
+    miller_loop_steps: u8,
+    // NAF digits per exponentiation-by-x step of the final exponentiation
+    naf_digits: u8,
+    // Advance the gamma and delta loops together
+    fused_miller_loops: bool,
+    // Have the program compute the gamma and delta tables of uploaded keys
+    onchain_g2: bool,
+    // Measured compute units per step and the units a transaction of steps is planned
+    // for, see `contract/src/plan.rs`; one step per transaction when unset
+    step_costs: Option<(StepCosts, u32)>,
+    // Step costs recorded by simulating every step sent, while calibrating
+    calibration: RefCell<Option<StepCosts>>,
+    // Size every compute unit request from `preflight_costs`, the units of one step of
+    // each instruction tag, simulated the first time the tag is sent
+    preflight: bool,
+    preflight_costs: RefCell<StepCosts>,
+    // Manifest of the chunked verification in flight, kept at `manifest_path` for
+    // `resume`, see `manifest.rs`
+    manifest_path: Option<String>,
+    manifest: RefCell<Option<Manifest>>,
+    // Close the session's scratch accounts once a chunked verification succeeds
+    cleanup: bool,
+    // Called for every transaction of `phase` that lands, see `set_progress`
+    progress: Option<ProgressCallback>,
+    phase: Cell<&'static str>,
+    // The proof verified and the transactions landed since `enable_report`
+    report: RefCell<Option<ReportLog>>,
+}
+
+type ProgressCallback = Box<dyn Fn(&ProgressEvent)>;
+
+// A transaction sent by `send_concurrently`, a step of a verification say, landed.
+pub struct ProgressEvent {
+    // What the transactions are for: "accounts", "inputs", "miller loops", "final
+    // exponentiation", "batch" for the steps of a batch, or "" outside a verification
+    pub phase: &'static str,
+    // Transactions of the phase landed so far, this one included, and in all
+    pub step: usize,
+    pub steps: usize,
+    pub signature: Signature,
+    // Compute units the transaction consumed, if the node tells
+    pub units: Option<u64>,
+}
+
+// What a chunked verification costs, as `estimate` counts it, fees and rent in
+// lamports. The rent comes back once the accounts are closed.
+pub struct Estimate {
+    pub transactions: usize,
+    pub signatures: usize,
+    pub signature_fees: u64,
+    // Accounts created, and their rent
+    pub accounts: usize,
+    pub rent: u64,
+    // Compute units requested, and their price at the compute unit price set
+    pub compute_units: u64,
+    pub priority_fees: u64,
+}
+
+impl Estimate {
+    // All of it in lamports, the rent included.
+    pub fn total(&self) -> u64 {
+        self.signature_fees + self.rent + self.priority_fees
+    }
+}
+
+// The outcome of the latest verification, see `contract/src/result_account.rs` for the
+// layout.
+pub struct VerificationResult {
+    pub valid: bool,
+    pub y16_hash: [u8; 32],
+    pub slot: u64,
+}
+
+// How far the verification of a session has come, as its scratch accounts tell. The
+// accumulators start out as one and each stage writes its own in order: the gamma and
+// delta Miller loops, the final accumulator, then the hard part's y0, y1, y3, .., y15.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionPhase {
+    // every accumulator still holds one
+    NotStarted,
+    MillerLoops,
+    // the final accumulator is prepared, its easy part under way
+    FinalExponentiation,
+    // the hard part has written y0..yk, the latest being k
+    HardPart(u8),
+    // y14 and y15 are written, all the final check y16 = y14 * y15 needs
+    FinalCheck,
+}
+
+impl fmt::Display for SessionPhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionPhase::NotStarted => write!(f, "not started"),
+            SessionPhase::MillerLoops => write!(f, "gamma and delta Miller loops"),
+            SessionPhase::FinalExponentiation => write!(f, "final exponentiation, easy part"),
+            SessionPhase::HardPart(k) => write!(f, "final exponentiation, hard part at y{}", k),
+            SessionPhase::FinalCheck => write!(f, "final check"),
+        }
+    }
+}
+
+// The state of a session's scratch accounts, see `Client::session_status`.
+pub struct SessionStatus {
+    // every accumulator account that exists, with its accumulator or why it cannot be
+    // read
+    pub accumulators: Vec<(Role, Pubkey, Result<Fq12, ProgramError>)>,
+    pub phase: SessionPhase,
+    // whether y14 * y15 equals alpha_g1_beta_g2 of the key in use, once both are written
+    pub final_check: Option<bool>,
+}
+
+// A record of the result log, see `contract/src/result_log.rs` for the layout.
+pub struct ResultRecord {
+    pub vk_id: [u8; 32],
+    pub input_hash: [u8; 32],
+    pub valid: bool,
+    pub slot: u64,
+}
+
+
+fn connect(
+    url: &str,
+    commitment: CommitmentConfig,
+) -> (RpcClient, nonblocking::rpc_client::RpcClient) {
+    (
+        RpcClient::new_with_commitment(url.to_string(), commitment),
+        nonblocking::rpc_client::RpcClient::new_with_commitment(url.to_string(), commitment),
+    )
+}
+
+// The signer at `path`, anything the Solana CLI takes for a keypair: a keypair file, a
+// Ledger (`usb://ledger`, `usb://ledger?key=1` for another account), a seed phrase to
+// type in (`prompt://`) or a keypair read from `stdin`. A Ledger asks to approve every
+// transaction on the device.
+pub fn read_signer(path: &str) -> Result<Box<dyn Signer>, Box<dyn Error>> {
+    signer_from_path(&ArgMatches::default(), path, "keypair", &mut None)
+}
+
+// Check a proof from another prover off-chain, and refuse one that fails rather than
+// pay for a verification bound to fail, unless `force`.
+pub fn check_proof(
+    vk: &VerifyingKey<Bn254>,
+    proof: &Proof<Bn254>,
+    public_inputs: &[Fr],
+    force: bool,
+) -> ClientResult<()> {
+    if let Err(err) = circuit::check_proof(vk, proof, public_inputs) {
+        if !force {
+            return Err(ClientError::Input(format!(
+                "invalid proof: {}; force it to submit it anyway",
+                err
+            )));
+        }
+        warn!("invalid proof: {}; submitting it anyway", err);
+    }
+    Ok(())
+}
+
+// A verifying key of generators for `count` public inputs, as large as any other such
+// key, which is all an estimate needs.
+pub fn stand_in_vk(count: usize) -> VerifyingKey<Bn254> {
+    let g1 = G1Affine::prime_subgroup_generator();
+    let g2 = G2Affine::prime_subgroup_generator();
+    VerifyingKey {
+        alpha_g1: g1,
+        beta_g2: g2,
+        gamma_g2: g2,
+        delta_g2: g2,
+        gamma_abc_g1: vec![g1; count + 1],
+    }
+}
+
+// The pairs of e(a * g1, b * g2) * e(-ab * g1, g2) for random a and b, a product of
+// one as a BLS signature check is, for `check_pairing_product`.
+pub fn demo_pairs() -> Vec<(G1Affine, G2Affine)> {
+    let (a, b) = (Fr::rand(&mut OsRng), Fr::rand(&mut OsRng));
+    let g1 = G1Affine::prime_subgroup_generator();
+    let g2 = G2Affine::prime_subgroup_generator();
+    vec![
+        (g1.mul(a).into_affine(), g2.mul(b).into_affine()),
+        (-g1.mul(a * b).into_affine(), g2),
+    ]
+}
+
+// The error of a manifest write failing while a transaction is sent.
+fn manifest_error(err: ClientError) -> RpcClientError {
+    ClientErrorKind::Custom(err.to_string()).into()
+}
+
+// A manifest step without a session manifest open, see `set_manifest_path`.
+fn no_manifest() -> ClientError {
+    ClientError::Input("no session manifest is open".to_string())
+}
+
+// A VK account that does not hold a finalized key of the program's layout.
+fn invalid_vk_account(vk: Pubkey) -> ClientError {
+    ClientError::AccountMismatch {
+        account: vk,
+        reason: "does not hold a verifying key".to_string(),
+    }
+}
+
+fn deploy_path(file: &str) -> String {
+    let target = option_env!("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../target"));
+    target.join("deploy").join(file).to_string_lossy().into_owned()
+}
+
+impl Default for Client {
+    fn default() -> Client {
+        Client::new()
+    }
 }
 
 impl Client {
+    // A client for the cluster and payer of the Solana CLI config, or the CLI's defaults
+    // (a local cluster, ~/.config/solana/id.json) without one.
     pub fn new() -> Client {
-        let config = Config::load(CONFIG_FILE.as_ref().unwrap()).unwrap();
-        let json_rpc_url = String::from(&config.json_rpc_url);
-        println!("Get config file: {:?}", config);
-        println!("Connecting to {}", config.json_rpc_url);
+        let config = CONFIG_FILE
+            .as_ref()
+            .and_then(|path| Config::load(path).ok())
+            .unwrap_or_default();
+        Client::with_config(config)
+    }
+
+    // A client for the cluster and payer of the Solana CLI config file at `path`.
+    pub fn with_config_file(path: &str) -> ClientResult<Client> {
+        let config = Config::load(path).map_err(|error| ClientError::Io {
+            path: path.to_string(),
+            error,
+        })?;
+        Ok(Client::with_config(config))
+    }
+
+    // A client for the cluster at `url`, `payer` paying fees and owning the scratch
+    // accounts, without a Solana CLI config: a keypair, a Ledger (see `read_signer`) or
+    // an external signing service (see `CommandSigner`).
+    pub fn with_payer(url: &str, payer: impl Signer + 'static) -> Client {
+        let config = Config {
+            json_rpc_url: url.to_string(),
+            keypair_path: String::new(),
+            ..Config::default()
+        };
+        Client::build(config, Box::new(payer))
+    }
+
+    fn with_config(config: Config) -> Client {
+        info!("Get config file: {:?}", config);
+        let payer = match read_signer(&config.keypair_path) {
+            Ok(payer) => payer,
+            Err(_) => {
+                warn!(
+                    "Failed to create keypair from CLI config file, falling back to new random keypair"
+                );
+                Box::new(Keypair::new())
+            }
+        };
+        Client::build(config, payer)
+    }
+
+    // The cluster, and the commitment transactions are confirmed at, follow from the
+    // config's URL, see `set_url`.
+    fn build(config: Config, payer: Box<dyn Signer>) -> Client {
+        let program_keypair_path = deploy_path(CONTRACT_KEYPAIR);
+        let cluster = Cluster::from_url(&config.json_rpc_url);
+        let commitment = cluster.map_or(CommitmentConfig::confirmed(), Cluster::commitment);
+        let (connection, sender) = connect(&config.json_rpc_url, commitment);
+        info!("Connecting to {}", config.json_rpc_url);
         Client {
             config,
-            connection: RpcClient::new_with_commitment(json_rpc_url, CommitmentConfig::confirmed()),
-            payer: Keypair::new(),
-            program_id: read_keypair_file(CONTRACT_KEYPAIR_PATH)
-                .unwrap()
-                .pubkey(),
+            cluster,
+            commitment,
+            connection,
+            sender,
+            pubsub: None,
+            tpu: None,
+            runtime: runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap(),
+            payer,
+            rent_payer: None,
+            session_id: 0,
+            max_sessions: None,
+            program_id: read_keypair_file(&program_keypair_path)
+                .map_or(Pubkey::default(), |keypair| keypair.pubkey()),
+            program_path: deploy_path(CONTRACT_SO),
+            program_keypair_path,
+            buffer_keypair_path: None,
+            compute_budget: Cell::new(true),
+            compute_unit_price: None,
+            nonce_accounts: vec![],
+            free_nonce_accounts: RefCell::new(vec![]),
+            retries: RETRIES,
+            retry_backoff: RETRY_BACKOFF,
+            telemetry: false,
+            result_log: None,
+            session_guard: false,
+            session: Cell::new((0, 0)),
+            proving_key: None,
+            verifying_key: None,
+            vk_fingerprint: RefCell::new(None),
+            vk_account: None,
+            registered_vk: None,
+            onchain_inputs: false,
+            inline_vk: false,
+            stateless_vk: RefCell::new(None),
+            result_account: false,
+            alt_bn128: false,
+            compressed_points: false,
+            nullifiers: false,
+            receipts: false,
+            miller_loop_steps: 1,
+            naf_digits: 1,
+            fused_miller_loops: false,
+            onchain_g2: false,
+            step_costs: None,
+            calibration: RefCell::new(None),
+            preflight: false,
+            preflight_costs: RefCell::new(StepCosts::new()),
+            manifest_path: None,
+            manifest: RefCell::new(None),
+            cleanup: false,
+            progress: None,
+            phase: Cell::new(""),
+            report: RefCell::new(None),
         }
     }
 
-    fn get_payer(&self) -> Keypair {
-        let keypair_path = &self.config.keypair_path;
-        if self.config.keypair_path.is_empty() {
-            println!(
-                "Failed to create keypair from CLI config file, falling back to new random keypair"
-            );
-            Keypair::new()
-        } else {
-            read_keypair_file(&Path::new(keypair_path)).unwrap()
+    // Connect to `url` instead of the cluster of the CLI config, confirming transactions
+    // at the commitment of the cluster it belongs to (see `Cluster::commitment`), or at
+    // confirmed for an RPC endpoint of unknown cluster.
+    pub fn set_url(&mut self, url: &str) {
+        info!("Connecting to {}", url);
+        self.config.json_rpc_url = url.to_string();
+        // the websocket of the new URL's node
+        self.config.websocket_url = String::new();
+        self.cluster = Cluster::from_url(url);
+        self.commitment = self
+            .cluster
+            .map_or(CommitmentConfig::confirmed(), Cluster::commitment);
+        let (connection, sender) = connect(url, self.commitment);
+        self.connection = connection;
+        self.sender = sender;
+    }
+
+    // Subscribe to signatures at `url` instead of the RPC URL's websocket, see
+    // `enable_websocket`.
+    pub fn set_websocket_url(&mut self, url: &str) {
+        self.config.websocket_url = url.to_string();
+    }
+
+    // Learn that transactions landed from signature subscriptions over the node's
+    // websocket, as soon as the node notifies, rather than by polling their statuses
+    // (see `send_window`). Polling stays the fallback for a notification that never
+    // comes, less often, and the only way if the websocket cannot be reached.
+    pub fn enable_websocket(&mut self) {
+        let url = match self.config.websocket_url.as_str() {
+            "" => Config::compute_websocket_url(&self.config.json_rpc_url),
+            url => url.to_string(),
+        };
+        match self.runtime.block_on(PubsubClient::new(&url)) {
+            Ok(pubsub) => self.pubsub = Some(pubsub),
+            Err(err) => warn!(
+                "cannot subscribe to signatures at {} ({}), polling instead",
+                url, err
+            ),
+        }
+    }
+
+    // Send the transactions of `send_window` to the TPUs of the current and upcoming
+    // leaders over QUIC, as the validators' own clients do, rather than through the
+    // node's `sendTransaction`, which forwards them there in turn. The leader schedule
+    // and the leaders' addresses come from the RPC node, and its websocket tells the
+    // current slot. Transactions are still confirmed by subscriptions or polling,
+    // and go over RPC when the leaders cannot be reached, or when the TPU client
+    // cannot be set up.
+    pub fn enable_tpu(&mut self) {
+        let url = match self.config.websocket_url.as_str() {
+            "" => Config::compute_websocket_url(&self.config.json_rpc_url),
+            url => url.to_string(),
+        };
+        let rpc = Arc::new(nonblocking::rpc_client::RpcClient::new_with_commitment(
+            self.config.json_rpc_url.clone(),
+            self.commitment,
+        ));
+        let connect = TpuClient::new("groth16-sol-client", rpc, &url, TpuClientConfig::default());
+        match self.runtime.block_on(connect) {
+            Ok(tpu) => self.tpu = Some(tpu),
+            Err(err) => warn!(
+                "cannot reach the leaders' TPUs ({}), sending over RPC instead",
+                err
+            ),
+        }
+    }
+
+    // Connect to the public RPC endpoint of `cluster`, or to a local validator.
+    pub fn set_cluster(&mut self, cluster: Cluster) {
+        self.set_url(cluster.url());
+    }
+
+    // Confirm transactions at `commitment` instead of the cluster's.
+    pub fn set_commitment(&mut self, commitment: CommitmentConfig) {
+        self.commitment = commitment;
+        let (connection, sender) = connect(&self.config.json_rpc_url, commitment);
+        self.connection = connection;
+        self.sender = sender;
+    }
+
+    // Pay fees with the signer at `path` instead of the one of the CLI config, whatever
+    // the Solana CLI takes for one (see `read_signer`).
+    pub fn set_keypair_path(&mut self, path: &str) -> ClientResult<()> {
+        self.payer = read_signer(path).map_err(|err| {
+            ClientError::input(format!("cannot read the payer keypair {}: {}", path, err))
+        })?;
+        self.config.keypair_path = path.to_string();
+        Ok(())
+    }
+
+    // Pay fees with `payer`, an external signing service say (see `CommandSigner`),
+    // instead of the signer of the CLI config.
+    pub fn set_payer(&mut self, payer: impl Signer + 'static) {
+        self.payer = Box::new(payer);
+    }
+
+    // Send the steps to the program deployed at `program_id` instead of the one whose
+    // keypair `cargo build-bpf` wrote.
+    pub fn set_program_id(&mut self, program_id: Pubkey) {
+        self.program_id = program_id;
+    }
+
+    // Deploy the program built to `path` instead of the one in target/deploy.
+    pub fn set_program_path(&mut self, path: &str) {
+        self.program_path = path.to_string();
+    }
+
+    // Deploy under the program keypair at `path` instead of the one in target/deploy,
+    // and send the steps to its program unless `set_program_id` names another.
+    pub fn set_program_keypair_path(&mut self, path: &str) {
+        self.program_keypair_path = path.to_string();
+        if let Ok(keypair) = read_keypair_file(path) {
+            self.program_id = keypair.pubkey();
         }
     }
 
+    // Save the keypair of the buffer `deploy` writes the program to at `path`, instead
+    // of `buffer-<program id>.json` in the working directory.
+    pub fn set_buffer_keypair_path(&mut self, path: &str) {
+        self.buffer_keypair_path = Some(path.to_string());
+    }
+
+    pub fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    pub fn payer(&self) -> Pubkey {
+        self.payer.pubkey()
+    }
+
     pub fn get_version(&self) -> ClientResult<RpcVersionInfo> {
-        self.connection.get_version()
+        Ok(self.connection.get_version()?)
+    }
+
+    pub fn get_slot(&self) -> ClientResult<u64> {
+        Ok(self.connection.get_slot()?)
+    }
+
+    pub fn get_payer_balance(&self) -> ClientResult<u64> {
+        Ok(self.connection.get_balance(&self.payer.pubkey())?)
+    }
+
+    // Let `rent_payer` fund the verification accounts while the fee payer keeps
+    // signing and paying for the transactions.
+    pub fn set_rent_payer(&mut self, rent_payer: Keypair) {
+        info!("Using account {} to pay for rent", rent_payer.pubkey());
+        self.rent_payer = Some(rent_payer);
+    }
+
+    // Derive the scratch accounts from session `session_id` instead of 0, so several
+    // verifications by the same payer can run side by side.
+    pub fn set_session_id(&mut self, session_id: u64) {
+        self.session_id = session_id;
+    }
+
+    pub fn session_id(&self) -> u64 {
+        self.session_id
     }
 
-    pub fn establish_payer(&mut self) {
+    // Verify the proofs of a batch in no more than `sessions` sessions at once, session
+    // ids the client's to the client's plus `sessions - 1`, proofs beyond waiting for
+    // the sessions to free up (see `verify_batch`).
+    pub fn set_max_sessions(&mut self, sessions: usize) {
+        self.max_sessions = Some(sessions);
+    }
+
+    pub fn max_sessions(&self) -> Option<usize> {
+        self.max_sessions
+    }
+
+    // The fee the cluster charges per signature: the fee of a message signed by the payer
+    // alone.
+    fn lamports_per_signature(&self) -> ClientResult<u64> {
+        let message = Message::new(&[], Some(&self.payer.pubkey()));
+        Ok(self.connection.get_fee_for_message(&message)?)
+    }
+
+    fn rent_payer(&self) -> &dyn Signer {
+        match &self.rent_payer {
+            Some(rent_payer) => rent_payer,
+            None => &*self.payer,
+        }
+    }
+
+    // Fund the payer by an airdrop if it holds too little to pay for a verification,
+    // and fail with `InsufficientFunds` if it still does. Mainnet-beta, told by its
+    // genesis hash behind any URL, has no airdrops: the payer must be funded by a
+    // transfer there.
+    pub fn establish_payer(&self) -> ClientResult<()> {
         let mut fees: u64 = 0;
-        let (_, fee_calculator) = self.connection.get_recent_blockhash().unwrap();
-        // Calculate the cost to fund the greeter account
-        fees += self
-            .connection
-            .get_minimum_balance_for_rent_exemption(SIZE)
-            .unwrap();
+        let lamports_per_signature = self.lamports_per_signature()?;
+        // the rent of the session's scratch accounts, unless a rent payer funds them
+        if self.rent_payer.is_none() {
+            for role in self.session_roles() {
+                fees += self
+                    .connection
+                    .get_minimum_balance_for_rent_exemption(role.data_len())?;
+            }
+        }
         // Calculate the cost of sending transactions
-        fees += fee_calculator.lamports_per_signature * 100;
+        fees += lamports_per_signature * 100;
 
-        self.payer = self.get_payer();
-
-        let ref pub_key = self.payer.pubkey();
-        let mut lamports = self.connection.get_balance(pub_key).unwrap();
+        let pub_key = &self.payer.pubkey();
+        let mut lamports = self.connection.get_balance(pub_key)?;
 
         if lamports < fees {
-            let sig = self.connection.request_airdrop(pub_key, fees - lamports);
-            let _confirmed = self.connection.confirm_transaction(&sig.unwrap());
-            lamports = self.connection.get_balance(pub_key).unwrap();
+            if self.is_mainnet_beta() {
+                return Err(ClientError::InsufficientFunds(format!(
+                    "payer {} holds {} lamports, {} short of the fees; airdrops are not \
+                     available on mainnet-beta, fund it with a transfer",
+                    pub_key,
+                    lamports,
+                    fees - lamports
+                )));
+            }
+            let airdrop = match self.connection.request_airdrop(pub_key, fees - lamports) {
+                Ok(sig) => self.connection.confirm_transaction(&sig),
+                Err(err) => Err(err),
+            };
+            if let Err(err) = airdrop {
+                return Err(ClientError::InsufficientFunds(format!(
+                    "cannot airdrop {} lamports to payer {} ({}), fund it with a transfer",
+                    fees - lamports,
+                    pub_key,
+                    err
+                )));
+            }
+            lamports = self.connection.get_balance(pub_key)?;
         }
 
-        println!(
+        info!(
             "Using account {} containing {} SOL to pay for fees",
             pub_key,
             lamports / LAMPORTS_PER_SOL
         );
+        Ok(())
+    }
+
+    fn is_mainnet_beta(&self) -> bool {
+        if let Some(cluster) = self.cluster {
+            return cluster == Cluster::MainnetBeta;
+        }
+        matches!(
+            self.connection.get_genesis_hash(),
+            Ok(hash) if hash.to_string() == MAINNET_BETA_GENESIS_HASH
+        )
     }
 
     pub fn check_program(&self) {
-        let program_info = self.connection.get_account(&self.program_id);
-        if program_info.is_err() {
-            if !Path::new(CONTRACT_SO_PATH).exists() {
-                println!("Program needs to be deployed with `solana program deploy target/deploy/contract.so`");
-            } else {
-                println!("Program needs to be built and deployed");
-            }
-        } else if !program_info.unwrap().executable {
-            println!("Program is not executable");
-        }
-
-        println!("Using program {}", self.program_id);
-    }
-    pub fn check_account(&self, seed: &str) -> Pubkey {
-        // Generate the address (public key) of an account from the program so that it's easy to find later.
-        let pubkey =
-            Pubkey::create_with_seed(&self.payer.pubkey(), seed, &self.program_id).unwrap();
-
-        // Check if the account has already been created
-        let account = self.connection.get_account(&pubkey);
-        if account.is_err() {
-            println!("Creating a account {} with {} bytes", pubkey, SIZE);
-            let lamports = self
-                .connection
-                .get_minimum_balance_for_rent_exemption(SIZE)
-                .unwrap();
-            let intruction = solana_sdk::system_instruction::create_account_with_seed(
-                &self.payer.pubkey(),
-                &pubkey,
-                &self.payer.pubkey(),
-                seed,
+        match self.connection.get_account(&self.program_id) {
+            Err(_) if Path::new(&self.program_path).exists() => {
+                warn!("Program needs to be deployed with `client deploy`");
+            }
+            Err(_) => warn!("Program needs to be built and deployed"),
+            Ok(account) if !account.executable => warn!("Program is not executable"),
+            Ok(_) => {}
+        }
+
+        info!("Using program {}", self.program_id);
+    }
+
+    // Deploy the program (`target/deploy/contract.so` unless `set_program_path` names
+    // another build) under the program keypair (see `set_program_keypair_path`), with
+    // the BPF upgradeable loader and the payer as upgrade authority. A program already
+    // deployed there is upgraded instead. The program id becomes that keypair's.
+    //
+    // The build is written to a buffer account first, whose keypair is saved (see
+    // `set_buffer_keypair_path`) until the buffer is deployed, so an interrupted
+    // deployment goes on where it stopped: a second run writes only the chunks the
+    // buffer does not hold yet.
+    pub fn deploy(&mut self) -> ClientResult<()> {
+        let so_path = self.program_path.as_str();
+        let keypair_path = self.program_keypair_path.as_str();
+        let program = read_keypair_file(keypair_path).map_err(|err| {
+            ClientError::Input(format!("cannot read program keypair {}: {}", keypair_path, err))
+        })?;
+        let program_data = fs::read(so_path).map_err(|error| ClientError::Io {
+            path: so_path.to_string(),
+            error,
+        })?;
+        let program_id = program.pubkey();
+        info!("Deploying {} as program {}", so_path, program_id);
+        let buffer = self.write_buffer(&program_id, &program_data)?;
+
+        let payer = self.payer.pubkey();
+        match self.connection.get_account(&program_id) {
+            Ok(account) => self.upgrade_program(&program_id, &account, &buffer, &program_data)?,
+            Err(_) => {
+                let len = UpgradeableLoaderState::size_of_program();
+                let lamports = self.connection.get_minimum_balance_for_rent_exemption(len)?;
+                // room for later upgrades to a larger build
+                let max_len = 2 * program_data.len();
+                let instructions = bpf_loader_upgradeable::deploy_with_max_program_len(
+                    &payer,
+                    &program_id,
+                    &buffer,
+                    &payer,
+                    lamports,
+                    max_len,
+                )
+                .map_err(ClientError::input)?;
+                self.send_instructions_signed(&instructions, &[&program])?;
+            }
+        }
+        // the loader closed the buffer into the program data
+        let _ = fs::remove_file(self.buffer_keypair_path(&program_id));
+        self.program_id = program_id;
+        Ok(())
+    }
+
+    // Where the keypair of the buffer deploying `program_id` is saved, the path set with
+    // `set_buffer_keypair_path` or `buffer-<program id>.json` in the working directory.
+    fn buffer_keypair_path(&self, program_id: &Pubkey) -> String {
+        self.buffer_keypair_path
+            .clone()
+            .unwrap_or_else(|| format!("buffer-{}.json", program_id))
+    }
+
+    // Write `program_data` to the buffer account of the saved keypair, creating both if
+    // need be, and return its address. A saved buffer is resumed only when the payer is
+    // its authority; one of another build is closed, and one never initialized or of
+    // another authority is left alone for a buffer at a fresh keypair. Chunks the buffer
+    // already holds are skipped; the others are written MAX_IN_FLIGHT at a time, as they
+    // do not depend on each other.
+    fn write_buffer(&self, program_id: &Pubkey, program_data: &[u8]) -> ClientResult<Pubkey> {
+        let path = self.buffer_keypair_path(program_id);
+        let payer = self.payer.pubkey();
+        let len = UpgradeableLoaderState::size_of_buffer(program_data.len());
+        let offset = UpgradeableLoaderState::size_of_buffer_metadata();
+
+        let mut keypair = None;
+        let mut written = None;
+        if let Ok(resumed) = read_keypair_file(&path) {
+            let account = self.connection.get_account(&resumed.pubkey()).ok();
+            let ours = account.as_ref().is_some_and(|account| {
+                matches!(
+                    account.state(),
+                    Ok(UpgradeableLoaderState::Buffer { authority_address: Some(authority) })
+                        if authority == payer
+                )
+            });
+            match account {
+                Some(account) if ours && account.data.len() == len => {
+                    info!("Resuming with buffer keypair {}", path);
+                    keypair = Some(resumed);
+                    written = Some(account);
+                }
+                Some(_) if ours => {
+                    // our buffer, left by a deployment of another build
+                    info!("Closing buffer {} of another build", resumed.pubkey());
+                    let close = bpf_loader_upgradeable::close(&resumed.pubkey(), &payer, &payer);
+                    self.send_instructions(&[close])?;
+                }
+                // never initialized, or not ours to close: start over at a fresh address
+                _ => {}
+            }
+        }
+        let keypair = match keypair {
+            Some(keypair) => keypair,
+            None => {
+                let keypair = Keypair::new();
+                write_keypair_file(&keypair, &path).map_err(|err| {
+                    ClientError::Input(format!("cannot write buffer keypair {}: {}", path, err))
+                })?;
+                keypair
+            }
+        };
+        let buffer = keypair.pubkey();
+        if written.is_none() {
+            info!("Creating buffer {} with {} bytes", buffer, len);
+            let lamports = self.connection.get_minimum_balance_for_rent_exemption(len)?;
+            let instructions = bpf_loader_upgradeable::create_buffer(
+                &payer,
+                &buffer,
+                &payer,
                 lamports,
-                SIZE as u64,
-                &self.program_id,
-            );
-            let (recent_hash, _) = self.connection.get_recent_blockhash().unwrap();
-            let transaction = Transaction::new_signed_with_payer(
-                &[intruction],
-                Some(&self.payer.pubkey()),
-                &[&self.payer],
-                recent_hash,
-            );
-            self.connection
-                .send_and_confirm_transaction(&transaction)
-                .unwrap();
-        }
-        pubkey
-    }
-
-    pub fn gamma_miller_loop(&self, key: Pubkey, prepared_input: Vec<u8>) {
-        let keys = vec![key];
-        let mut j: u8 = 0;
-        for i in (1..ark_bn254::Parameters::ATE_LOOP_COUNT.len()).rev() {
-            let mut data = vec![0, i as u8, j];
-            data.extend(prepared_input.iter());
-            self.send_transction(&keys, data);
-            j += 1;
-            if ark_bn254::Parameters::ATE_LOOP_COUNT[i - 1] == 1
-                || ark_bn254::Parameters::ATE_LOOP_COUNT[i - 1] == -1
-            {
-                j += 1;
+                program_data.len(),
+            )
+            .map_err(ClientError::input)?;
+            self.send_instructions_signed(&instructions, &[&keypair])?;
+        }
+
+        let chunk_len = self.buffer_chunk_len(&buffer);
+        let writes = program_data
+            .chunks(chunk_len)
+            .enumerate()
+            .filter(|(k, chunk)| match &written {
+                Some(account) => {
+                    let start = offset + k * chunk_len;
+                    account.data[start..start + chunk.len()] != **chunk
+                }
+                None => true,
+            })
+            .map(|(k, chunk)| {
+                let offset = (k * chunk_len) as u32;
+                vec![bpf_loader_upgradeable::write(&buffer, &payer, offset, chunk.to_vec())]
+            })
+            .collect::<Vec<_>>();
+        info!(
+            "Writing {} of {} chunks of {} bytes",
+            writes.len(),
+            program_data.chunks(chunk_len).len(),
+            chunk_len
+        );
+        for window in writes.chunks(self.max_in_flight()) {
+            let window = window.iter().map(|write| &write[..]).collect::<Vec<_>>();
+            for result in self.runtime.block_on(self.send_window(&window, None)) {
+                result?;
             }
         }
+        Ok(buffer)
+    }
 
-        let mut data = vec![0, 0, j];
-        data.extend(prepared_input.iter());
-        self.send_transction(&keys, data);
+    // The most program bytes one write to `buffer` carries within a transaction packet.
+    fn buffer_chunk_len(&self, buffer: &Pubkey) -> usize {
+        let payer = self.payer.pubkey();
+        let write = |len| bpf_loader_upgradeable::write(buffer, &payer, 0, vec![0; len]);
+        (1..PACKET_DATA_SIZE)
+            .rev()
+            .find(|&len| self.fits_packet(&[write(len)]))
+            .unwrap()
     }
 
-    pub fn delta_miller_loop(&self, key: Pubkey, proof_c: Vec<u8>) {
-        let keys = vec![key];
-        let mut j: u8 = 0;
-        for i in (1..ark_bn254::Parameters::ATE_LOOP_COUNT.len()).rev() {
-            let mut data = vec![1, i as u8, j];
-            data.extend(proof_c.iter());
-            self.send_transction(&keys, data);
-            j += 1;
-            if ark_bn254::Parameters::ATE_LOOP_COUNT[i - 1] == 1
-                || ark_bn254::Parameters::ATE_LOOP_COUNT[i - 1] == -1
-            {
-                j += 1;
+    // Upgrade the program at `program_id`, with program account `account`, to the build
+    // written to `buffer`, first growing its program data account if the build no longer
+    // fits.
+    fn upgrade_program(
+        &self,
+        program_id: &Pubkey,
+        account: &Account,
+        buffer: &Pubkey,
+        program_data: &[u8],
+    ) -> ClientResult<()> {
+        let payer = self.payer.pubkey();
+        let programdata_address = match account.state() {
+            Ok(UpgradeableLoaderState::Program { programdata_address }) => programdata_address,
+            _ => {
+                return Err(ClientError::AccountMismatch {
+                    account: *program_id,
+                    reason: "is not a program of the upgradeable loader".to_string(),
+                })
+            }
+        };
+        let programdata = self.connection.get_account(&programdata_address)?;
+        let metadata_len = UpgradeableLoaderState::size_of_programdata_metadata();
+        let capacity = programdata.data.len() - metadata_len;
+        if program_data.len() > capacity {
+            let additional = (program_data.len() - capacity) as u32;
+            info!("Extending program data {} by {} bytes", programdata_address, additional);
+            let extend =
+                bpf_loader_upgradeable::extend_program(program_id, Some(&payer), additional);
+            self.send_instructions(&[extend])?;
+        }
+        info!("Upgrading program {}", program_id);
+        let upgrade = bpf_loader_upgradeable::upgrade(program_id, buffer, &payer, &payer);
+        self.send_instructions(&[upgrade])?;
+        Ok(())
+    }
+
+    // Create (or reuse) the `role` scratch account of session `session_id`, the
+    // program-derived address of the payer, the session id and the role.
+    pub fn scratch_account(&self, session_id: u64, role: Role) -> ClientResult<Pubkey> {
+        self.create_scratch_accounts(session_id, &[role])?;
+        Ok(self.scratch_address(session_id, role))
+    }
+
+    fn scratch_address(&self, session_id: u64, role: Role) -> Pubkey {
+        let payer = self.payer.pubkey();
+        scratch_address(&self.program_id, &payer, session_id, role).0
+    }
+
+    // The data of the `role` scratch account of session `session_id`, header included,
+    // checked to be the program's account of that role.
+    fn read_scratch_account(&self, session_id: u64, role: Role) -> ClientResult<Vec<u8>> {
+        let key = self.scratch_address(session_id, role);
+        let account = self.connection.get_account(&key)?;
+        let reason = if account.owner != self.program_id {
+            "is not owned by the program".to_string()
+        } else if account.data.len() < role.data_len()
+            || check_header(&account.data, Some(role)).is_err()
+        {
+            format!("does not hold a {:?} account of this layout version", role)
+        } else {
+            return Ok(account.data);
+        };
+        Err(ClientError::AccountMismatch {
+            account: key,
+            reason,
+        })
+    }
+
+    // Create those of the `roles` scratch accounts of session `session_id` that do not
+    // exist yet, in concurrent transactions. An existing one is only reused if it is the
+    // program's account of its role.
+    fn create_scratch_accounts(&self, session_id: u64, roles: &[Role]) -> ClientResult<()> {
+        let keys = roles
+            .iter()
+            .map(|&role| self.scratch_address(session_id, role))
+            .collect::<Vec<_>>();
+        let existing = self.connection.get_multiple_accounts(&keys)?;
+        let mut transactions = vec![];
+        for ((role, pubkey), account) in roles.iter().zip(keys).zip(existing) {
+            if let Some(account) = account {
+                let reason = if account.owner != self.program_id {
+                    "is not owned by the program"
+                } else if check_header(&account.data, Some(*role)).is_err() {
+                    "holds another role or layout version; reset the session"
+                } else {
+                    continue;
+                };
+                return Err(ClientError::AccountMismatch {
+                    account: pubkey,
+                    reason: reason.to_string(),
+                });
             }
+            info!("Creating a scratch account {} ({:?})", pubkey, role);
+            transactions.push(vec![self.create_scratch_instruction(session_id, *role)]);
+        }
+        self.send_concurrently(&transactions, None)
+    }
+
+    fn create_scratch_instruction(&self, session_id: u64, role: Role) -> Instruction {
+        let mut data = CreateScratch(StepIndex::new(role.to_u8(), 0)).pack();
+        data.extend(session_id.to_le_bytes().iter());
+        let mut accounts = vec![
+            AccountMeta::new(self.payer.pubkey(), true),
+            AccountMeta::new(self.scratch_address(session_id, role), false),
+            AccountMeta::new_readonly(solana_sdk::system_program::id(), false),
+        ];
+        // without a rent payer the program charges the payer
+        if let Some(rent_payer) = &self.rent_payer {
+            accounts.push(AccountMeta::new(rent_payer.pubkey(), true));
+        }
+        Instruction::new_with_bytes(self.program_id, &data, accounts)
+    }
+
+    // Create the scratch accounts of session `session_id`, or reset those it has, so
+    // several sessions can coexist and each verification starts from one.
+    pub fn create_session_accounts(&self, session_id: u64) -> ClientResult<SessionKeys> {
+        // a resumed verification goes on from what its accounts hold
+        if !self.resuming() {
+            self.reset_session(session_id)?;
+        }
+        self.create_scratch_accounts(session_id, &self.session_roles())?;
+        Ok(self.session_keys(session_id))
+    }
+
+    // The roles of the scratch accounts a chunked verification uses with the options
+    // set: the inputs account only for input folding, the session and result accounts
+    // only with the session guard and the result account.
+    fn session_roles(&self) -> Vec<Role> {
+        let inputs = Some(Role::Inputs).filter(|_| self.folds_inputs());
+        let session = Some(Role::Session).filter(|_| self.session_guard);
+        let result = Some(Role::Result).filter(|_| self.result_account);
+        let telemetry = Some(Role::Telemetry).filter(|_| self.telemetry);
+        [Role::Gamma, Role::Delta, Role::Final]
+            .iter()
+            .cloned()
+            .chain((0..17).map(Role::Y))
+            .chain(Some(Role::Ab))
+            .chain(inputs)
+            .chain(session)
+            .chain(result)
+            .chain(telemetry)
+            .collect()
+    }
+
+    // The addresses of those scratch accounts of session `session_id`, existing or not.
+    fn session_keys(&self, session_id: u64) -> SessionKeys {
+        let address = |role| self.scratch_address(session_id, role);
+        SessionKeys {
+            session_id,
+            gamma: address(Role::Gamma),
+            delta: address(Role::Delta),
+            final_key: address(Role::Final),
+            y: (0..17).map(|k| address(Role::Y(k))).collect(),
+            ab: address(Role::Ab),
+            inputs: Some(Role::Inputs).filter(|_| self.folds_inputs()).map(address),
+        }
+    }
+
+    // Close the scratch accounts of session `session_id` that exist, moving their rent to
+    // `refund` or back to the payer, and return how many were closed and the lamports
+    // they held. Run it once the session's verification is done; a later verification
+    // under the same id creates the accounts again. With telemetry enabled the telemetry
+    // account is left open.
+    pub fn close_session(
+        &self,
+        session_id: u64,
+        refund: Option<Pubkey>,
+    ) -> ClientResult<(usize, u64)> {
+        let payer = self.payer.pubkey();
+        let refund = refund.unwrap_or(payer);
+        let accounts = self
+            .scratch_accounts(session_id)
+            .into_iter()
+            .filter(|&(role, _, _)| !(self.telemetry && role == Role::Telemetry))
+            .collect::<Vec<_>>();
+        if accounts.is_empty() {
+            return Ok((0, 0));
         }
+        let lamports = accounts.iter().map(|(_, _, lamports)| lamports).sum();
+        let (roles, keys): (Vec<_>, Vec<_>) = accounts
+            .into_iter()
+            .map(|(role, key, _)| (role.to_u8(), key))
+            .unzip();
+        let instruction = self.close_session_instruction(session_id, roles, &keys, refund);
+        self.send_instructions(&[instruction])?;
+        Ok((keys.len(), lamports))
+    }
 
-        let mut data = vec![1, 0, j];
-        data.extend(proof_c.iter());
-        self.send_transction(&keys, data);
+    fn close_session_instruction(
+        &self,
+        session_id: u64,
+        roles: Vec<u8>,
+        keys: &[Pubkey],
+        refund: Pubkey,
+    ) -> Instruction {
+        let data = CloseSession(StepIndex::new(CLOSE_SESSION, 0), Payload(roles)).pack();
+        let payer = AccountMeta::new(self.payer.pubkey(), true);
+        let accounts = vec![payer, AccountMeta::new(refund, false)];
+        self.session_accounts_instruction(session_id, data, accounts, keys)
     }
 
-    pub fn final_exponentiation(&self, keys: &Vec<Pubkey>, qap: Vec<u8>) {
-        let gamma_key = keys[0];
-        let delta_key = keys[1];
-        let final_key = keys[2];
-        // first, create account for y0..y16
-        let mut final_keys = vec![];
-        for i in 0..17 {
-            final_keys.push(self.check_account(i.to_string().as_str()));
+    // Reset the scratch accounts of session `session_id` that exist to their initial
+    // data, so a verification that failed halfway, or one done before under the same id,
+    // does not leave its accumulators to the next. Return how many were reset.
+    pub fn reset_session(&self, session_id: u64) -> ClientResult<usize> {
+        let (roles, keys) = self.existing_scratch_accounts(session_id);
+        if roles.is_empty() {
+            return Ok(0);
         }
+        let data = CloseSession(StepIndex::new(RESET_SESSION, 0), Payload(roles)).pack();
+        let accounts = vec![AccountMeta::new(self.payer.pubkey(), true)];
+        let instruction = self.session_accounts_instruction(session_id, data, accounts, &keys);
+        self.send_instructions(&[instruction])?;
+        Ok(keys.len())
+    }
+
+    // The roles and addresses of the scratch accounts of session `session_id` that exist.
+    fn existing_scratch_accounts(&self, session_id: u64) -> (Vec<u8>, Vec<Pubkey>) {
+        self.scratch_accounts(session_id)
+            .into_iter()
+            .map(|(role, key, _)| (role.to_u8(), key))
+            .unzip()
+    }
+
+    // The scratch accounts of session `session_id` that exist and are the program's, with
+    // their lamports.
+    pub fn scratch_accounts(&self, session_id: u64) -> Vec<(Role, Pubkey, u64)> {
+        let payer = self.payer.pubkey();
+        (0..ROLE_COUNT)
+            .filter_map(|role| {
+                let role = Role::from_u8(role).unwrap();
+                let (key, _) = scratch_address(&self.program_id, &payer, session_id, role);
+                self.connection
+                    .get_account(&key)
+                    .ok()
+                    .filter(|account| account.owner == self.program_id)
+                    .map(|account| (role, key, account.lamports))
+            })
+            .collect()
+    }
+
+    // Read the accumulators of session `session_id` and tell how far its verification
+    // has come, and once the hard part is done, whether the final check holds.
+    pub fn session_status(&self, session_id: u64) -> ClientResult<SessionStatus> {
+        let payer = self.payer.pubkey();
+        let roles = [Role::Gamma, Role::Delta, Role::Final];
+        let accumulators = roles
+            .iter()
+            .cloned()
+            .chain((0..17).map(Role::Y))
+            .filter_map(|role| {
+                let (key, _) = scratch_address(&self.program_id, &payer, session_id, role);
+                let account = self.connection.get_account(&key).ok()?;
+                let f = if account.owner != self.program_id {
+                    Err(ProgramError::IncorrectProgramId)
+                } else {
+                    check_header(&account.data, Some(role))
+                        .and_then(|_| read_accumulator(&account.data))
+                        .map(|(_, f)| f)
+                };
+                Some((role, key, f))
+            })
+            .collect::<Vec<_>>();
+
+        let written = |role: Role| {
+            accumulators
+                .iter()
+                .find(|(r, _, _)| *r == role)
+                .and_then(|(_, _, f)| f.as_ref().ok().filter(|f| !f.is_one()))
+        };
+        let latest_y = (0..17).rev().find(|&k| written(Role::Y(k)).is_some());
+        let phase = match latest_y {
+            Some(k) if k >= 15 => SessionPhase::FinalCheck,
+            Some(k) => SessionPhase::HardPart(k),
+            None if written(Role::Final).is_some() => SessionPhase::FinalExponentiation,
+            None if written(Role::Gamma).or_else(|| written(Role::Delta)).is_some() => {
+                SessionPhase::MillerLoops
+            }
+            None => SessionPhase::NotStarted,
+        };
+        let final_check = match (written(Role::Y(14)), written(Role::Y(15))) {
+            (Some(y14), Some(y15)) => Some(*y14 * y15 == self.alpha_g1_beta_g2()?),
+            _ => None,
+        };
+        Ok(SessionStatus {
+            accumulators,
+            phase,
+            final_check,
+        })
+    }
+
+    fn session_accounts_instruction(
+        &self,
+        session_id: u64,
+        mut data: Vec<u8>,
+        accounts: Vec<AccountMeta>,
+        keys: &[Pubkey],
+    ) -> Instruction {
+        data.extend(session_id.to_le_bytes().iter());
+        let accounts = [
+            accounts,
+            keys.iter().map(|key| AccountMeta::new(*key, false)).collect(),
+        ]
+        .concat();
+        Instruction::new_with_bytes(self.program_id, &data, accounts)
+    }
 
-        // prepare_final_data
-        let mut data = vec![2, 0, 0];
-        data.extend(qap.iter());
-        let k = vec![gamma_key, delta_key, final_key];
-        self.send_transction(&k, data);
+    // The gamma and delta loop steps as sent: packed, and fused into one stream if
+    // enabled, or else as two streams, which `send_streams` sends side by side.
+    pub(crate) fn miller_loops(&self, gamma: Vec<Step>, delta: Vec<Step>) -> Vec<Vec<Step>> {
+        let gamma = steps::pack_miller_loop(gamma, self.miller_loop_steps);
+        let delta = steps::pack_miller_loop(delta, self.miller_loop_steps);
+        if self.fused_miller_loops {
+            vec![steps::fuse_miller_loops(gamma, delta)]
+        } else {
+            vec![gamma, delta]
+        }
+    }
 
-        // easy_part1
-        let data = vec![3, 0, 0];
-        let k = vec![final_key];
-        self.send_transction(&k, data);
+    pub(crate) fn pack_naf_digits(&self, steps: Vec<Step>) -> Vec<Step> {
+        steps::pack_naf_digits(steps, self.naf_digits)
+    }
 
-        // easy_part2
-        let data = vec![4, 0, 0];
-        let k = vec![final_key];
-        self.send_transction(&k, data);
+    // `callback`, if any, is made by the last step once `proof` of `public_inputs` is
+    // verified; a result log record is tagged with the hash of the public inputs folded
+    // on-chain, `nullifier` is the account of their nullifier (see `nullifier_account`)
+    // and with receipts enabled, the step creates the receipt of the statement
+    pub fn final_exponentiation(
+        &self,
+        keys: &SessionKeys,
+        proof: &Proof<Bn254>,
+        public_inputs: &[Fr],
+        nullifier: Option<Pubkey>,
+        callback: Option<&Callback>,
+    ) -> ClientResult<()> {
+        let steps =
+            self.final_exponentiation_steps(keys, proof, public_inputs, nullifier, callback)?;
+        self.send_steps(&steps)
+    }
 
-        // hard_part_y0
-        for i in 0..63 {
-            let data = vec![5, 0, i];
-            let k = vec![final_key, final_keys[0]];
-            self.send_transction(&k, data);
+    fn final_exponentiation_steps(
+        &self,
+        keys: &SessionKeys,
+        proof: &Proof<Bn254>,
+        public_inputs: &[Fr],
+        nullifier: Option<Pubkey>,
+        callback: Option<&Callback>,
+    ) -> ClientResult<Vec<Step>> {
+        let mut steps = self.pack_naf_digits(steps::final_exponentiation(keys));
+        let last = steps.last_mut().unwrap();
+        if self.attests_inputs() || callback.is_some() {
+            // the proof the loops ran on, and the inputs a receipt names
+            let inputs = if self.receipts { public_inputs } else { &[] };
+            let statement = complete_submission(proof, inputs, self.point_encoding());
+            steps::attest_inputs(last, keys.inputs.unwrap(), &statement);
+        }
+        if let Some(log) = self.result_log {
+            steps::record_result(last, log);
+        }
+        if self.result_account {
+            let result = self.scratch_address(keys.session_id, Role::Result);
+            steps::write_result(last, result);
+        }
+        if let Some(nullifier) = nullifier {
+            steps::spend_nullifier(last, nullifier);
         }
+        if self.receipts {
+            let address = self.receipt_address(proof, public_inputs)?;
+            info!("receipt {}", address);
+            steps::create_receipt(last, address);
+        }
+        if let Some(callback) = callback {
+            steps::add_callback(last, callback);
+        }
+        Ok(steps)
+    }
+
+    pub fn groth16_verify(&self) -> ClientResult<()> {
+        self.verify_demo(None)
+    }
+
+    // Verify `proof` of `public_inputs`, from any prover, with the chunked steps against
+    // the verifying key in use: set it with `set_verifying_key` and have the steps read
+    // it from a VK account or carry it inline.
+    pub fn groth16_verify_proof(
+        &self,
+        proof: &Proof<Bn254>,
+        public_inputs: &[Fr],
+    ) -> ClientResult<()> {
+        let pvk = prepare_verifying_key(&self.verifying_key()?);
+        info!("verifying against vk {}", self.vk_fingerprint()?);
+        self.verify_chunked(&pvk, proof, public_inputs, None)
+    }
+
+    // Verify the demo proof and, in the same transaction that completes verification,
+    // call `callback` so the target program can record or act on the result. See
+    // `Callback` for the accounts to supply. The program only calls back for inputs
+    // folded on-chain, so this folds them.
+    pub fn groth16_verify_and_call(&mut self, callback: &Callback) -> ClientResult<()> {
+        self.enable_onchain_inputs();
+        self.verify_demo(Some(callback))
+    }
+
+    // Prove with `proving_key` instead of the seeded demo setup, however the key was
+    // generated. The program must have been built with the matching verifying key.
+    pub fn set_proving_key(&mut self, proving_key: ProvingKey<Bn254>) {
+        self.proving_key = Some(proving_key);
+        self.vk_fingerprint.replace(None);
+        self.stateless_vk.replace(None);
+    }
+
+    // Verify proofs from another prover against `vk`, e.g. a snarkjs
+    // `verification_key.json`: the fingerprint, the inline key and the VK id follow it.
+    pub fn set_verifying_key(&mut self, vk: VerifyingKey<Bn254>) {
+        self.verifying_key = Some(vk);
+        self.vk_fingerprint.replace(None);
+        self.stateless_vk.replace(None);
+    }
 
-        // hard_part_y1
-        let data = vec![6, 0, 64];
-        let k = vec![final_keys[0], final_keys[1]];
-        self.send_transction(&k, data);
+    // A proof of the demo circuit under the proving key in use.
+    fn prove_demo(&self) -> ClientResult<(PreparedVerifyingKey<Bn254>, Proof<Bn254>, Vec<Fr>)> {
+        let (pvk, mut proofs) = self.prove_demo_batch(1)?;
+        let (proof, public_inputs) = proofs.remove(0);
+        Ok((pvk, proof, public_inputs))
+    }
 
-        // hard_part_y3
-        let data = vec![7, 0, 0];
-        let k = vec![final_keys[0], final_keys[3]];
-        self.send_transction(&k, data);
+    fn prove_demo_batch(
+        &self,
+        count: usize,
+    ) -> ClientResult<(PreparedVerifyingKey<Bn254>, Vec<ProofWithInputs>)> {
+        match &self.proving_key {
+            Some(proving_key) => {
+                let proofs = (0..count)
+                    .map(|_| prove(proving_key, &demo_constants(), &mut OsRng))
+                    .collect::<Result<_, _>>()
+                    .map_err(ClientError::input)?;
+                Ok((prepare_verifying_key(&proving_key.vk), proofs))
+            }
+            None => demo_batch(count).map_err(ClientError::input),
+        }
+    }
 
-        // hard_part_y4
-        for i in 0..63 {
-            let data = vec![8, 0, i];
-            let k = vec![final_keys[3], final_keys[4]];
-            self.send_transction(&k, data);
+    // Verify the demo proof with a single instruction (t = 24) carrying the proof and
+    // the raw public inputs, against the VK account if one is set. Needs a cluster
+    // whose compute budget covers a full pairing, or a program built with the
+    // `alt-bn128` feature.
+    pub fn verify_complete(&self) -> ClientResult<()> {
+        let (_, proof, public_inputs) = self.prove_demo()?;
+        info!("verifying against vk {}", self.vk_fingerprint()?);
+        self.verify_proof(&proof, &public_inputs)?;
+        if let Some(receipt) = self.read_receipt(&proof, &public_inputs)? {
+            info!(
+                "receipt: verifier version {}, slot {}",
+                receipt.version, receipt.slot
+            );
         }
+        Ok(())
+    }
 
-        // hard_part_y6
-        for i in 0..63 {
-            let data = vec![9, 0, i];
-            let k = vec![final_keys[4], final_keys[6]];
-            self.send_transction(&k, data);
+    // Verify `proof` of `public_inputs`, from any prover, with a single instruction
+    // (t = 24) against the VK account if one is set, the baked key otherwise.
+    // With receipts enabled, the instruction also creates the receipt of the statement.
+    pub fn verify_proof(&self, proof: &Proof<Bn254>, public_inputs: &[Fr]) -> ClientResult<()> {
+        let input = complete_submission(proof, public_inputs, self.point_encoding());
+        let mut index = StepIndex::default();
+        let mut accounts = vec![];
+        if self.receipts {
+            let address = self.receipt_address(proof, public_inputs)?;
+            info!("receipt {}", address);
+            index.i = RECEIPT_FLAG;
+            accounts.push(AccountMeta::new(self.payer.pubkey(), true));
+            accounts.push(AccountMeta::new(address, false));
+            accounts.push(AccountMeta::new_readonly(system_program::id(), false));
         }
+        self.send_single(VerifyComplete(index, Payload(input)), accounts)
+    }
 
-        // hard_part_y8
-        let data = vec![10, 0, 0];
-        let k = vec![final_keys[3], final_keys[4], final_keys[6], final_keys[8]];
-        self.send_transction(&k, data);
+    // Verify `proof` of `public_inputs` from another prover against `vk` in a single
+    // instruction, checked off-chain first (see `check_proof`), reading `vk` from a new
+    // VK account unless one is set.
+    pub fn verify_external(
+        &mut self,
+        vk: VerifyingKey<Bn254>,
+        proof: &Proof<Bn254>,
+        public_inputs: &[Fr],
+        force: bool,
+    ) -> ClientResult<()> {
+        check_proof(&vk, proof, public_inputs, force)?;
+        self.use_vk_account(&vk)?;
+        self.set_verifying_key(vk);
+        self.verify_proof(proof, public_inputs)
+    }
 
-        // hard_part_y9
-        let data = vec![11, 0, 0];
-        let k = vec![final_keys[1], final_keys[8], final_keys[9]];
-        self.send_transction(&k, data);
+    // Have `groth16_verify_proof` verify proofs from another prover against `vk`,
+    // reading it from a new VK account unless one is set or the steps carry it, once
+    // `proof` of `public_inputs` passes the off-chain check (see `check_proof`).
+    pub fn prepare_external(
+        &mut self,
+        vk: VerifyingKey<Bn254>,
+        proof: &Proof<Bn254>,
+        public_inputs: &[Fr],
+        force: bool,
+    ) -> ClientResult<()> {
+        check_proof(&vk, proof, public_inputs, force)?;
+        if !self.inline_vk {
+            self.use_vk_account(&vk)?;
+        }
+        self.set_verifying_key(vk);
+        Ok(())
+    }
 
-        // hard_part_y11
-        let data = vec![12, 0, 0];
-        let k = vec![final_keys[4], final_keys[8], final_key, final_keys[11]];
-        self.send_transction(&k, data);
+    // Whether verifying against a key of another prover uploads it first: no VK account
+    // is set and the steps do not carry the key.
+    pub fn uploads_vk(&self) -> bool {
+        self.vk_account.is_none() && !self.inline_vk
+    }
 
-        // hard_part_y13
-        let data = vec![13, 0, 0];
-        let k = vec![final_keys[9], final_keys[11], final_keys[13]];
-        self.send_transction(&k, data);
+    // Read the verifying key from a new VK account holding `vk`, unless one is set.
+    fn use_vk_account(&mut self, vk: &VerifyingKey<Bn254>) -> ClientResult<()> {
+        if self.vk_account.is_none() {
+            self.vk_account = Some(self.upload_vk(vk)?);
+        }
+        Ok(())
+    }
 
-        // hard_part_y14
-        let data = vec![14, 0, 0];
-        let k = vec![final_keys[8], final_keys[13], final_keys[14]];
-        self.send_transction(&k, data);
+    // Verify `count` demo proofs together with a single instruction (t = 25), which
+    // checks a random linear combination of them with one multi-pairing. The proofs
+    // must fit one transaction.
+    pub fn verify_combined(&self, count: usize) -> ClientResult<()> {
+        let (_, proofs) = self.prove_demo_batch(count)?;
+        info!("verifying against vk {}", self.vk_fingerprint()?);
+        let input = batch_submission(&proofs, self.point_encoding());
+        self.send_single(VerifyBatch(StepIndex::default(), Payload(input)), vec![])
+    }
 
-        // hard_part_y15
-        let data = vec![15, 0, 0];
-        let k = vec![final_keys[9], final_key, final_keys[15]];
-        self.send_transction(&k, data);
+    // Send a whole-verification instruction, against the VK account if one is set,
+    // with `extra_accounts` following it.
+    fn send_single(
+        &self,
+        instruction: VerifierInstruction,
+        extra_accounts: Vec<AccountMeta>,
+    ) -> ClientResult<()> {
+        let mut data = instruction.pack();
+        let mut accounts = vec![];
+        self.add_vk_account(&mut data, &mut accounts);
+        accounts.extend(extra_accounts);
+        self.send_transction(accounts, data)
+    }
 
-        // hard_part_y16
-        let data = vec![16, 0, 0];
-        let k = vec![final_keys[14], final_keys[15]];
-        self.send_transction(&k, data);
+    // Have a whole-verification or fold instruction read the verifying key from the VK
+    // account if one is set, named by its registered id if it has one. Must follow
+    // everything else appended to `data`.
+    fn add_vk_account(&self, data: &mut Vec<u8>, accounts: &mut Vec<AccountMeta>) {
+        let vk = match self.vk_account {
+            Some(vk) => vk,
+            None => return,
+        };
+        data[0] |= VK_ACCOUNT_FLAG as u8;
+        if let Some((id, entry)) = self.registered_vk {
+            data[0] |= VK_REGISTRY_FLAGS as u8;
+            data.extend(id.to_le_bytes().iter());
+            accounts.push(AccountMeta::new_readonly(entry, false));
+        }
+        accounts.push(AccountMeta::new_readonly(vk, false));
     }
 
-    pub fn groth16_verify(&self) {
+    fn verify_demo(&self, callback: Option<&Callback>) -> ClientResult<()> {
         // run a circuit demo
-        let (proof_c, prepared_input, qap) = initialize().unwrap();
-        println!("run a circuit demo, get input and proof");
+        let (pvk, proof, public_inputs) = self.prove_demo()?;
+        info!("run a circuit demo, get input and proof");
+        info!("verifying against vk {}", self.vk_fingerprint()?);
+        self.verify_chunked(&pvk, &proof, &public_inputs, callback)
+    }
 
-        // create accounts for verify
-        let mut keys = vec![];
-        keys.push(self.check_account("gamma"));
-        keys.push(self.check_account("delta"));
-        keys.push(self.check_account("final"));
+    fn verify_chunked(
+        &self,
+        pvk: &PreparedVerifyingKey<Bn254>,
+        proof: &Proof<Bn254>,
+        public_inputs: &[Fr],
+        callback: Option<&Callback>,
+    ) -> ClientResult<()> {
+        if let Some(log) = self.report.borrow_mut().as_mut() {
+            log.proof_hash = Some(proof_hash(proof));
+        }
+        let encoding = self.point_encoding();
+        let (proof_c, prepared_input, proof_ab) =
+            submission(pvk, proof, public_inputs, encoding).map_err(ClientError::input)?;
+        let folded = FoldedInput::new(pvk, public_inputs).map_err(ClientError::input)?;
+        let nullifier = self.nullifier_account(public_inputs)?;
+
+        if self.alt_bn128 && self.alt_bn128_applies(callback) {
+            info!("running single-instruction verification");
+            let input = alt_bn128_submission(proof, public_inputs);
+            let data = VerifyAltBn128(StepIndex::default(), Payload(input)).pack();
+            let instruction = Instruction::new_with_bytes(self.program_id, &data, vec![]);
+            match self.send_instructions(&[instruction]) {
+                Err(err) if !matches!(err.verifier_error(), Some(VerifierError::InvalidProof)) => {
+                    warn!(
+                        "single-instruction verification failed ({}), falling back to the chunked verification",
+                        err
+                    );
+                }
+                result => {
+                    result?;
+                    return Ok(());
+                }
+            }
+        }
+        self.start_session()?;
+        if callback.is_none() {
+            self.open_manifest(&pvk.vk, proof, public_inputs)?;
+        }
 
-        // gamma miller loop
-        println!("running gamma miller loop");
-        self.gamma_miller_loop(keys[0], prepared_input);
+        // create accounts for verify
+        self.set_phase("accounts");
+        let keys = self.create_session_accounts(self.session_id)?;
 
-        // delta miller loop
-        println!("running delta miller loop");
-        self.delta_miller_loop(keys[1], proof_c);
+        if self.folds_inputs() {
+            self.set_phase("inputs");
+            self.fold_public_inputs(&keys, &folded.public_input_bytes())?;
+        }
+        let streams = self.miller_loop_streams(&keys, &prepared_input, &proof_c, &proof_ab);
+        info!("running gamma, delta and A·B miller loops");
+        self.set_phase("miller loops");
+        self.send_streams(&streams)?;
 
         // final exponentiation
-        println!("running final exponentiation");
-        self.final_exponentiation(&keys, qap);
+        info!("running final exponentiation");
+        self.set_phase("final exponentiation");
+        self.final_exponentiation(&keys, proof, public_inputs, nullifier, callback)?;
+        self.set_phase("");
+        self.close_manifest()?;
+        if self.cleanup {
+            self.set_phase("cleanup");
+            let (closed, lamports) = self.close_session(self.session_id, None)?;
+            self.set_phase("");
+            info!("closed {} scratch accounts, recovered {} lamports", closed, lamports);
+        }
+        Ok(())
+    }
+
+    // The Miller-loop streams of a chunked verification: the gamma loop over the
+    // prepared input, or over the inputs account once the inputs are folded into it,
+    // the delta loop over C, and the A·B loop over A and B.
+    fn miller_loop_streams(
+        &self,
+        keys: &SessionKeys,
+        prepared_input: &[u8],
+        proof_c: &[u8],
+        proof_ab: &[u8],
+    ) -> Vec<Vec<Step>> {
+        let gamma_steps = match keys.inputs {
+            Some(inputs) => steps::gamma_miller_loop_folded(keys.gamma, inputs),
+            None => steps::gamma_miller_loop(keys.gamma, prepared_input),
+        };
+        let delta_steps = steps::delta_miller_loop(keys.delta, proof_c);
+        let mut streams = self.miller_loops(gamma_steps, delta_steps);
+        streams.push(steps::ab_miller_loop(keys.ab, proof_ab));
+        streams
+    }
+
+    // Estimate what a chunked verification against `vk` costs with the options set,
+    // sending nothing: the transactions it takes from a fresh session, their signature
+    // fees, the rent of the scratch accounts it creates and, if `upload_vk`, of the VK
+    // account and the transactions uploading `vk` into it, and the priority fees at the
+    // compute unit price set. The steps are built from a stand-in proof, the sizes of
+    // its points being all that counts. Rent and the fee per signature are the
+    // cluster's; preflight and the accounts the steps create themselves, a nullifier's
+    // say, are left out.
+    pub fn estimate(
+        &mut self,
+        vk: &VerifyingKey<Bn254>,
+        upload_vk: bool,
+    ) -> ClientResult<Estimate> {
+        let vk_account = self.vk_account;
+        if upload_vk {
+            // the steps read the key from the account, whichever it is
+            self.vk_account = Some(Pubkey::new_unique());
+        }
+        let session = self.session.get();
+        let default_units = |instructions: usize| {
+            let advance = self.nonce_accounts.len().min(1);
+            (instructions + advance) as u64 * DEFAULT_INSTRUCTION_UNITS
+        };
+        // (signatures, compute units) of every transaction, and the size of every
+        // account created
+        let mut transactions = vec![];
+        let mut accounts = vec![];
+
+        for role in self.session_roles() {
+            transactions.push((1, default_units(1)));
+            accounts.push(role.data_len());
+        }
+        if upload_vk {
+            let data = to_on_chain_vk(vk).map_err(ClientError::Input)?;
+            accounts.push(VK_ACCOUNT_HEADER_LEN + data.len());
+            let signers = if self.rent_payer.is_some() { 3 } else { 2 };
+            transactions.push((signers, default_units(2)));
+            let skipped = if self.onchain_g2 {
+                parse_on_chain_vk(&data).map_err(ClientError::input)?.tables()
+            } else {
+                0..0
+            };
+            for range in [0..skipped.start, skipped.end..data.len()].iter() {
+                let chunks = range.clone().step_by(VK_CHUNK_LEN);
+                transactions.extend(chunks.map(|_| (1, default_units(1))));
+            }
+            if self.onchain_g2 {
+                transactions.push((1, default_units(1)));
+                accounts.push(Role::G2.data_len());
+                let encoding = self.point_encoding();
+                let tables = [(GAMMA_TABLE, vk.gamma_g2), (DELTA_TABLE, vk.delta_g2)];
+                for (table, point) in tables.iter() {
+                    let point = [vec![encoding as u8], write_g2(point, encoding)].concat();
+                    let steps = prepare_g2_steps(*table, &point, self.miller_loop_steps).len();
+                    transactions.extend((0..steps).map(|_| (1, COMPUTE_UNITS as u64)));
+                }
+            }
+            transactions.push((1, default_units(1)));
+        }
+        if self.folds_inputs() {
+            transactions.push((1, COMPUTE_UNITS as u64));
+        }
+
+        let g1 = G1Affine::prime_subgroup_generator();
+        let proof = Proof {
+            a: g1,
+            b: G2Affine::prime_subgroup_generator(),
+            c: g1,
+        };
+        let public_inputs = vec![Fr::from(0u64); vk.gamma_abc_g1.len() - 1];
+        let pvk = prepare_verifying_key(vk);
+        let (proof_c, prepared_input, proof_ab) =
+            submission(&pvk, &proof, &public_inputs, self.point_encoding())
+                .map_err(ClientError::input)?;
+        let nullifier = self.nullifier_account(&public_inputs)?;
+        let keys = self.session_keys(self.session_id);
+        let final_steps =
+            self.final_exponentiation_steps(&keys, &proof, &public_inputs, nullifier, None)?;
+        let streams = [
+            self.miller_loop_streams(&keys, &prepared_input, &proof_c, &proof_ab),
+            vec![final_steps],
+        ];
+        for streams in streams.iter() {
+            let (steps, units) = self.stream_transactions(streams)?;
+            transactions.extend(steps.iter().map(|_| (1, units as u64)));
+        }
+        if self.cleanup {
+            transactions.push((1, default_units(1)));
+        }
+        self.vk_account = vk_account;
+        self.session.set(session);
+
+        let lamports_per_signature = self.lamports_per_signature()?;
+        let signatures = transactions.iter().map(|(signatures, _)| signatures).sum();
+        let price = self.compute_unit_price.unwrap_or(0);
+        let rent = accounts
+            .iter()
+            .map(|&len| Ok(self.connection.get_minimum_balance_for_rent_exemption(len)?))
+            .sum::<ClientResult<u64>>()?;
+        Ok(Estimate {
+            transactions: transactions.len(),
+            signatures,
+            signature_fees: signatures as u64 * lamports_per_signature,
+            accounts: accounts.len(),
+            rent,
+            compute_units: transactions.iter().map(|(_, units)| units).sum(),
+            // micro-lamports per unit, rounded up per transaction as the runtime does
+            priority_fees: transactions
+                .iter()
+                .map(|(_, units)| match units * price {
+                    0 => 0,
+                    micro_lamports => (micro_lamports - 1) / 1_000_000 + 1,
+                })
+                .sum(),
+        })
     }
 
-    pub fn send_transction(&self, keys: &Vec<Pubkey>, data: Vec<u8>) {
-        let accounts = keys
+    // The transactions of a chunked verification of `proof`, in the order they are sent,
+    // each as its instructions behind the compute budget ones, built without any RPC:
+    // those of a session whose scratch accounts do not exist yet, against a key already
+    // in its VK account if one is set. Streams sent side by side, such as the gamma and
+    // delta loops, are listed one after the other. The recent blockhash or durable nonce
+    // and the signatures are left to whoever sends them.
+    pub fn dry_run(
+        &self,
+        proof: &Proof<Bn254>,
+        public_inputs: &[Fr],
+    ) -> ClientResult<Vec<Vec<Instruction>>> {
+        let pvk = prepare_verifying_key(&self.verifying_key()?);
+        let (proof_c, prepared_input, proof_ab) =
+            submission(&pvk, proof, public_inputs, self.point_encoding())
+                .map_err(ClientError::input)?;
+        let nullifier = self.nullifier_account(public_inputs)?;
+        let input_bytes = FoldedInput::new(&pvk, public_inputs)
+            .map_err(ClientError::input)?
+            .public_input_bytes();
+        let session = self.session.get();
+        self.start_session()?;
+        let budgeted = |instructions: Vec<Instruction>, units: Option<u32>| {
+            [self.budget_instructions(units), instructions].concat()
+        };
+        let roles = self.session_roles();
+        let keys = self.session_keys(self.session_id);
+        let mut transactions = roles
             .iter()
-            .map(|key| AccountMeta::new(*key, false))
-            .collect();
-        let (recent_hash, _) = self.connection.get_recent_blockhash().unwrap();
+            .map(|&role| self.create_scratch_instruction(self.session_id, role))
+            .map(|create| budgeted(vec![create], None))
+            .collect::<Vec<_>>();
+        if self.folds_inputs() {
+            let fold = self.fold_instruction(&keys, &input_bytes);
+            transactions.push(budgeted(vec![fold], Some(COMPUTE_UNITS)));
+        }
+        let final_steps =
+            self.final_exponentiation_steps(&keys, proof, public_inputs, nullifier, None)?;
+        let streams = [
+            self.miller_loop_streams(&keys, &prepared_input, &proof_c, &proof_ab),
+            vec![final_steps],
+        ];
+        for streams in streams.iter() {
+            let (steps, units) = self.stream_transactions(streams)?;
+            transactions.extend(steps.into_iter().map(|step| budgeted(step, Some(units))));
+        }
+        if self.cleanup {
+            let roles = roles
+                .into_iter()
+                .filter(|&role| role != Role::Telemetry)
+                .collect::<Vec<_>>();
+            let addresses = roles
+                .iter()
+                .map(|&role| self.scratch_address(self.session_id, role))
+                .collect::<Vec<_>>();
+            let roles = roles.iter().map(|role| role.to_u8()).collect();
+            let payer = self.payer.pubkey();
+            let close = self.close_session_instruction(self.session_id, roles, &addresses, payer);
+            transactions.push(budgeted(vec![close], None));
+        }
+        self.session.set(session);
+        Ok(transactions)
+    }
 
-        let i1 = solana_sdk::compute_budget::request_units(1_000_000 as u32);
+    // A proof of the demo circuit under the proving key in use, and its public inputs.
+    pub fn demo_proof(&self) -> ClientResult<(Proof<Bn254>, Vec<Fr>)> {
+        let (_, proof, public_inputs) = self.prove_demo()?;
+        Ok((proof, public_inputs))
+    }
 
-        let i2 = solana_sdk::instruction::Instruction::new_with_bytes(
-            self.program_id,
-            data.as_slice(),
-            accounts,
-        );
-        let transaction = Transaction::new_signed_with_payer(
-            &[i1, i2],
-            Some(&self.payer.pubkey()),
-            &[&self.payer],
-            recent_hash,
-        );
-        self.connection
-            .send_and_confirm_transaction(&transaction)
-            .unwrap();
+    // Close the session's scratch accounts once a chunked verification succeeds, their
+    // rent back to the payer, instead of keeping them for the next verification under
+    // the same session id, which then creates them again.
+    pub fn enable_cleanup(&mut self) {
+        self.cleanup = true;
+    }
+
+    // Record the chunked verification to follow in a session manifest at the path set
+    // with `set_manifest_path`, if any. A resumed one instead goes on with the session
+    // nonce its steps were tagged with, so they are rebuilt the same.
+    fn open_manifest(
+        &self,
+        vk: &VerifyingKey<Bn254>,
+        proof: &Proof<Bn254>,
+        public_inputs: &[Fr],
+    ) -> ClientResult<()> {
+        let path = match &self.manifest_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut manifest = self.manifest.borrow_mut();
+        match manifest.as_ref() {
+            Some(resumed) => self.session.set((resumed.nonce, 0)),
+            None => {
+                *manifest = Some(Manifest {
+                    program_id: self.program_id,
+                    payer: self.payer.pubkey(),
+                    session_id: self.session_id,
+                    nonce: self.session.get().0,
+                    vk_account: self.vk_account,
+                    vk: vk.clone(),
+                    proof: proof.clone(),
+                    public_inputs: public_inputs.to_vec(),
+                    last_step: None,
+                    complete: false,
+                    transactions: vec![],
+                })
+            }
+        }
+        // later writes go to the same path, so failing here is failing early
+        if let Some(manifest) = manifest.as_ref() {
+            manifest.write(path).map_err(|error| ClientError::Io {
+                path: path.clone(),
+                error,
+            })?;
+        }
+        info!("recording the session's progress to {}", path);
+        Ok(())
+    }
+
+    // Mark the manifest's verification complete; later transactions aren't recorded.
+    fn close_manifest(&self) -> ClientResult<()> {
+        if let Some(mut manifest) = self.manifest.borrow_mut().take() {
+            manifest.complete = true;
+            self.write_manifest(&manifest)?;
+        }
+        Ok(())
+    }
+
+    fn resuming(&self) -> bool {
+        matches!(&*self.manifest.borrow(), Some(manifest) if manifest.last_step.is_some())
+    }
+
+    fn write_manifest(&self, manifest: &Manifest) -> ClientResult<()> {
+        let path = self.manifest_path.as_ref().ok_or_else(no_manifest)?;
+        manifest.write(path).map_err(|error| ClientError::Io {
+            path: path.clone(),
+            error,
+        })
+    }
+
+    // Record `transaction`, running `instructions`, as signed in the manifest if one is
+    // open, before it is sent. A transaction the manifest cannot record is not sent, so
+    // a later `resume` never misses one that landed.
+    fn record_signed(
+        &self,
+        instructions: &[Instruction],
+        transaction: &Transaction,
+    ) -> ClientResult<()> {
+        if let Some(manifest) = self.manifest.borrow_mut().as_mut() {
+            let step = instructions
+                .iter()
+                .rev()
+                .find(|instruction| instruction.program_id == self.program_id)
+                .map_or([0; 3], |instruction| {
+                    let mut step = [0; 3];
+                    let len = instruction.data.len().min(3);
+                    step[..len].copy_from_slice(&instruction.data[..len]);
+                    step
+                });
+            manifest.signed(
+                transaction_hash(&self.program_id, instructions),
+                transaction.signatures[0],
+                transaction.message.recent_blockhash,
+                step,
+            );
+            self.write_manifest(manifest)?;
+        }
+        Ok(())
+    }
+
+    fn record_confirmed(&self, signature: &Signature) -> ClientResult<()> {
+        if let Some(log) = self.report.borrow_mut().as_mut() {
+            log.transactions.push((self.phase.get(), *signature));
+        }
+        if let Some(manifest) = self.manifest.borrow_mut().as_mut() {
+            manifest.confirmed(signature);
+            self.write_manifest(manifest)?;
+        }
+        Ok(())
+    }
+
+    // Whether the manifest, if one is open, has `instructions` landed already.
+    fn is_confirmed(&self, instructions: &[Instruction]) -> bool {
+        let hash = transaction_hash(&self.program_id, instructions);
+        matches!(&*self.manifest.borrow(), Some(manifest) if manifest.is_confirmed(&hash))
+    }
+
+    // Record chunked verifications to a session manifest at `path`, see `resume`.
+    pub fn set_manifest_path(&mut self, path: &str) {
+        self.manifest_path = Some(path.to_string());
+    }
+
+    // The session manifest at `path`, refused if it records a session of another program
+    // or payer, whose accounts this client cannot advance.
+    pub fn read_manifest(&self, path: &str) -> ClientResult<Manifest> {
+        let manifest = Manifest::read(path).map_err(|error| ClientError::Io {
+            path: path.to_string(),
+            error,
+        })?;
+        if manifest.program_id != self.program_id || manifest.payer != self.payer.pubkey() {
+            return Err(ClientError::Input(format!(
+                "{} is a session of program {} paid by {}, connect with its program id and \
+                 payer",
+                path, manifest.program_id, manifest.payer
+            )));
+        }
+        Ok(manifest)
+    }
+
+    // Pick up the verification `manifest`, read from `path`, where it stopped: rebuild
+    // its steps from the key, proof and public inputs it holds and send only those that
+    // have not landed, leaving the accounts as they are. The options that shape the
+    // steps (`STATELESS_VK`, `STEP_COSTS`, ...) must be the same as when it started, or
+    // nothing matches and every step runs again on the partial accumulators.
+    pub fn resume(&mut self, path: &str, manifest: Manifest) -> ClientResult<()> {
+        self.session_id = manifest.session_id;
+        if manifest.vk_account.is_some() {
+            self.vk_account = manifest.vk_account;
+        }
+        self.set_verifying_key(manifest.vk.clone());
+        let proof = manifest.proof.clone();
+        let public_inputs = manifest.public_inputs.clone();
+        self.manifest_path = Some(path.to_string());
+        self.manifest.replace(Some(manifest));
+        self.settle_pending()?;
+        self.groth16_verify_proof(&proof, &public_inputs)
+    }
+
+    // Settle the transactions the manifest has signed but not seen land: confirmed if
+    // they did, forgotten if they failed or their blockhash expired before they landed.
+    // Until then one may still land, so resending its steps could run them twice.
+    fn settle_pending(&self) -> ClientResult<()> {
+        let pending = match self.manifest.borrow().as_ref() {
+            Some(manifest) => manifest.pending(),
+            None => return Err(no_manifest()),
+        };
+        for (signature, blockhash) in pending {
+            let landed = loop {
+                let status = self
+                    .connection
+                    .get_signature_status_with_commitment_and_history(
+                        &signature,
+                        self.commitment,
+                        true,
+                    )?;
+                match status {
+                    Some(result) => break result.is_ok(),
+                    None if self
+                        .connection
+                        .is_blockhash_valid(&blockhash, CommitmentConfig::processed())? =>
+                    {
+                        info!("waiting for {} to land or expire", signature);
+                        std::thread::sleep(Duration::from_secs(2));
+                    }
+                    None => break false,
+                }
+            };
+            let mut manifest = self.manifest.borrow_mut();
+            let manifest = manifest.as_mut().ok_or_else(no_manifest)?;
+            if landed {
+                manifest.confirmed(&signature);
+            } else {
+                manifest.dropped(&signature);
+            }
+            self.write_manifest(manifest)?;
+        }
+        Ok(())
+    }
+
+    // Check e(P_1, Q_1) * .. * e(P_k, Q_k) == 1 for `pairs` on-chain, as pairing-based
+    // protocols besides Groth16 need, in the session's pairing account and the scratch
+    // accounts of its final exponentiation; fails if the product is not one.
+    pub fn check_pairing_product(&self, pairs: &[(G1Affine, G2Affine)]) -> ClientResult<()> {
+        let encoding = self.point_encoding();
+        let mut data = vec![encoding as u8];
+        for (p, q) in pairs {
+            data.push(PAIR_POINT);
+            data.extend(write_g1(p, encoding));
+            data.extend(write_g2(q, encoding));
+        }
+        self.start_session()?;
+        let keys = self.create_session_accounts(self.session_id)?;
+        let pairing = self.scratch_account(self.session_id, Role::Pairing)?;
+        info!("running the pairing-product miller loop and final exponentiation");
+        let steps = steps::pairing_product(&keys, pairing, &data, self.miller_loop_steps);
+        self.send_steps(&self.pack_naf_digits(steps))
+    }
+
+    // First 8 bytes of the verifying key's hash, to correlate logs and results with the
+    // circuit version in use.
+    pub fn vk_fingerprint(&self) -> ClientResult<String> {
+        if let Some(fingerprint) = &*self.vk_fingerprint.borrow() {
+            return Ok(fingerprint.clone());
+        }
+        let fingerprint = vk_fingerprint(&self.verifying_key()?);
+        *self.vk_fingerprint.borrow_mut() = Some(fingerprint.clone());
+        Ok(fingerprint)
+    }
+
+    // The verifying key set with `set_verifying_key`, or else of the proving key in use,
+    // or of the demo setup.
+    pub fn verifying_key(&self) -> ClientResult<VerifyingKey<Bn254>> {
+        match (&self.verifying_key, &self.proving_key) {
+            (Some(vk), _) => Ok(vk.clone()),
+            (None, Some(proving_key)) => Ok(proving_key.vk.clone()),
+            (None, None) => Ok(demo_proof().map_err(ClientError::input)?.0.vk),
+        }
+    }
+
+    // The public inputs of the demo proof and the folded G1 point submitted to the
+    // gamma Miller loop, so an auditor can recompute the folding independently.
+    pub fn folded_input(&self) -> ClientResult<FoldedInput> {
+        folded_input().map_err(ClientError::input)
+    }
+
+    // Verify the demo proof off-chain and only record the outcome on-chain. The
+    // contract does not re-verify in this mode; the record is an attestation signed by
+    // the payer.
+    pub fn attest(&self) -> ClientResult<bool> {
+        let (valid, statement_hash) = attest().map_err(ClientError::input)?;
+        info!(
+            "off-chain verification result: {} (vk {})",
+            valid,
+            self.vk_fingerprint()?
+        );
+
+        let payer = self.payer.pubkey();
+        let (key, _) = attestation_address(&self.program_id, &payer, &statement_hash);
+        let mut input = vec![valid as u8];
+        input.extend(statement_hash.iter());
+        let data = RecordAttestation(StepIndex::default(), Payload(input)).pack();
+        let accounts = vec![
+            AccountMeta::new(key, false),
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+        let instruction = Instruction::new_with_bytes(self.program_id, data.as_slice(), accounts);
+        self.send_instructions(&[instruction])?;
+        Ok(valid)
+    }
+
+    // Have every step add its compute units to the session's telemetry account. Only
+    // a program built with the `telemetry` feature accepts such steps. Cleaning up after
+    // a verification keeps the account so its totals can still be read.
+    pub fn enable_telemetry(&mut self) -> ClientResult<()> {
+        self.scratch_account(self.session_id, Role::Telemetry)?;
+        self.telemetry = true;
+        Ok(())
+    }
+
+    // (steps, cumulative compute units) recorded in the session's telemetry account, if
+    // enabled
+    pub fn read_telemetry(&self) -> ClientResult<Option<(u64, u64)>> {
+        if !self.telemetry {
+            return Ok(None);
+        }
+        let data = self.read_scratch_account(self.session_id, Role::Telemetry)?;
+        let data = &data[SCRATCH_HEADER_LEN..];
+        let mut steps = [0u8; 8];
+        let mut compute_units = [0u8; 8];
+        steps.copy_from_slice(&data[0..8]);
+        compute_units.copy_from_slice(&data[8..16]);
+        Ok(Some((u64::from_le_bytes(steps), u64::from_le_bytes(compute_units))))
+    }
+
+    // Record verification results in the payer's append-only log account instead of
+    // failing the final step on an invalid proof. The program creates the log, derived
+    // from the payer, with room for `capacity` records; an existing log keeps the
+    // capacity it was created with.
+    pub fn enable_result_log(&mut self, capacity: usize) -> ClientResult<()> {
+        let payer = self.payer.pubkey();
+        let (log, _) = result_log_address(&self.program_id, &payer);
+        if self.connection.get_account(&log).is_err() {
+            if capacity == 0 || capacity > max_log_capacity() {
+                return Err(ClientError::Input(format!(
+                    "result log capacity must be 1 to {} records",
+                    max_log_capacity()
+                )));
+            }
+            info!("Creating the result log {} for {} records", log, capacity);
+            let index = StepIndex::new(capacity as u8, CREATE_RESULT_LOG);
+            let mut data = CreateScratch(index).pack();
+            data.extend(self.session_id.to_le_bytes().iter());
+            let accounts = vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new(log, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ];
+            let instruction = Instruction::new_with_bytes(self.program_id, &data, accounts);
+            self.send_instructions(&[instruction])?;
+        }
+        self.read_result_log(&log)?;
+        self.result_log = Some(log);
+        Ok(())
+    }
+
+    // The data of the result log `log`, checked to be a log of the program.
+    fn read_result_log(&self, log: &Pubkey) -> ClientResult<Vec<u8>> {
+        let account = self.connection.get_account(log)?;
+        if account.owner != self.program_id
+            || account.data.len() < LOG_HEADER_LEN
+            || check_account_header(&account.data, AccountKind::ResultLog).is_err()
+        {
+            return Err(ClientError::AccountMismatch {
+                account: *log,
+                reason: "is not a result log of this program".to_string(),
+            });
+        }
+        Ok(account.data)
+    }
+
+    // Number of records in the result log, if enabled
+    pub fn result_count(&self) -> ClientResult<Option<usize>> {
+        let result_log = match self.result_log {
+            Some(result_log) => result_log,
+            None => return Ok(None),
+        };
+        let data = self.read_result_log(&result_log)?;
+        let mut count = [0u8; 8];
+        count.copy_from_slice(&data[ACCOUNT_HEADER_LEN..LOG_HEADER_LEN]);
+        Ok(Some(u64::from_le_bytes(count) as usize))
+    }
+
+    // The i-th record of the result log, the oldest being 0
+    pub fn read_result(&self, i: usize) -> ClientResult<Option<ResultRecord>> {
+        match self.result_count()? {
+            Some(count) if i < count => {}
+            _ => return Ok(None),
+        }
+        let data = self.read_result_log(&self.result_log.unwrap())?;
+        let record = &data[record_offset(i)..];
+        let mut vk_id = [0u8; 32];
+        let mut input_hash = [0u8; 32];
+        let mut slot = [0u8; 8];
+        vk_id.copy_from_slice(&record[0..32]);
+        input_hash.copy_from_slice(&record[32..64]);
+        slot.copy_from_slice(&record[65..73]);
+        Ok(Some(ResultRecord {
+            vk_id,
+            input_hash,
+            valid: record[64] == 1,
+            slot: u64::from_le_bytes(slot),
+        }))
+    }
+
+    // Have the final step write the outcome, the hash of the pairing result and the slot
+    // to a result account instead of failing the transaction on an invalid proof. The
+    // result account is the scratch account of role `Result`, created with the others of
+    // each session id.
+    pub fn enable_result_account(&mut self) -> ClientResult<()> {
+        self.scratch_account(self.session_id, Role::Result)?;
+        self.result_account = true;
+        Ok(())
+    }
+
+    // The outcome of the latest verification written to the current session's result
+    // account, if enabled
+    pub fn read_verification_result(&self) -> ClientResult<Option<VerificationResult>> {
+        if !self.result_account {
+            return Ok(None);
+        }
+        let data = self.read_scratch_account(self.session_id, Role::Result)?;
+        let data = &data[SCRATCH_HEADER_LEN..];
+        let mut y16_hash = [0u8; 32];
+        let mut slot = [0u8; 8];
+        y16_hash.copy_from_slice(&data[1..33]);
+        slot.copy_from_slice(&data[33..41]);
+        Ok(Some(VerificationResult {
+            valid: data[0] == 1,
+            y16_hash,
+            slot: u64::from_le_bytes(slot),
+        }))
+    }
+
+    // Guard every verification with a session account, so that another client driving
+    // the same accounts at the same time makes the steps fail with a session conflict
+    // (custom program error 0) instead of corrupting the accumulators. The session
+    // account is the scratch account of role `Session`, created with the others of each
+    // session id.
+    pub fn enable_session_guard(&mut self) -> ClientResult<()> {
+        self.scratch_account(self.session_id, Role::Session)?;
+        self.session_guard = true;
+        Ok(())
+    }
+
+    // Upload `vk` into a new VK account and return its address. The account's keypair
+    // only starts the upload, which the fee payer then writes and finalizes with the
+    // key's hash, see `contract/src/vk.rs`; from then on the key can no longer change.
+    pub fn upload_vk(&self, vk: &VerifyingKey<Bn254>) -> ClientResult<Pubkey> {
+        let data = to_on_chain_vk(vk).map_err(ClientError::Input)?;
+        let len = VK_ACCOUNT_HEADER_LEN + data.len();
+        let vk_keypair = Keypair::new();
+        let vk_pubkey = vk_keypair.pubkey();
+        info!("Creating vk account {} with {} bytes", vk_pubkey, len);
+        let lamports = self.connection.get_minimum_balance_for_rent_exemption(len)?;
+        let create = solana_sdk::system_instruction::create_account(
+            &self.rent_payer().pubkey(),
+            &vk_pubkey,
+            lamports,
+            len as u64,
+            &self.program_id,
+        );
+        let init = self.upload_instruction(vk_pubkey, INIT_VK_ACCOUNT, vec![]);
+        self.send_instructions_signed(&[create, init], &[&vk_keypair])?;
+
+        // the program computes the tables itself if asked to
+        let skipped = if self.onchain_g2 {
+            parse_on_chain_vk(&data).map_err(ClientError::input)?.tables()
+        } else {
+            0..0
+        };
+        for range in [0..skipped.start, skipped.end..data.len()].iter() {
+            for offset in range.clone().step_by(VK_CHUNK_LEN) {
+                let end = range.end.min(offset + VK_CHUNK_LEN);
+                let mut input = (offset as u32).to_le_bytes().to_vec();
+                input.extend(data[offset..end].iter());
+                let instruction = self.upload_instruction(vk_pubkey, WRITE_VK_CHUNK, input);
+                self.send_instructions(&[instruction])?;
+            }
+        }
+        if self.onchain_g2 {
+            self.prepare_g2(vk_pubkey, vk)?;
+        }
+        let key_hash = hash(&data).to_bytes().to_vec();
+        let instruction = self.upload_instruction(vk_pubkey, FINALIZE_VK, key_hash);
+        self.send_instructions(&[instruction])?;
+        Ok(vk_pubkey)
+    }
+
+    // Compute the gamma and delta tables of the key being uploaded into `vk` from the
+    // key's own points, see `contract/src/g2_prepare.rs`. The running point is kept in
+    // the session's G2 scratch account, and each instruction runs as many steps as a
+    // Miller-loop instruction.
+    fn prepare_g2(&self, vk_account: Pubkey, vk: &VerifyingKey<Bn254>) -> ClientResult<()> {
+        let payer = self.payer.pubkey();
+        let g2 = self.scratch_account(self.session_id, Role::G2)?;
+        let encoding = self.point_encoding();
+        for (table, point) in [(GAMMA_TABLE, vk.gamma_g2), (DELTA_TABLE, vk.delta_g2)].iter() {
+            let name = if *table == GAMMA_TABLE { "gamma" } else { "delta" };
+            info!("Preparing the {} table on-chain", name);
+            let point = [vec![encoding as u8], write_g2(point, encoding)].concat();
+            for mut data in prepare_g2_steps(*table, &point, self.miller_loop_steps) {
+                data.extend(self.session_id.to_le_bytes().iter());
+                let accounts = vec![
+                    AccountMeta::new(payer, true),
+                    AccountMeta::new(g2, false),
+                    AccountMeta::new(vk_account, false),
+                    AccountMeta::new_readonly(payer, true),
+                ];
+                self.send_transction(accounts, data)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Upload operation `operation` on the VK account `vk`, with the fee payer as the
+    // upload authority; only the first one needs the VK account's signature.
+    fn upload_instruction(&self, vk: Pubkey, operation: u8, input: Vec<u8>) -> Instruction {
+        let data = WriteVk(StepIndex::new(operation, 0), Payload(input)).pack();
+        let accounts = vec![
+            AccountMeta::new(vk, operation == INIT_VK_ACCOUNT),
+            AccountMeta::new_readonly(self.payer.pubkey(), true),
+        ];
+        Instruction::new_with_bytes(self.program_id, &data, accounts)
+    }
+
+    // Compute the gamma and delta tables of keys uploaded from now on on-chain instead of
+    // uploading them, about 65 more transactions per key at one step per instruction.
+    pub fn enable_onchain_g2(&mut self) {
+        self.onchain_g2 = true;
+    }
+
+    // Verify against the verifying key in the VK account `vk` (see `upload_vk`) instead
+    // of the one baked into the program.
+    pub fn set_vk_account(&mut self, vk: Pubkey) {
+        self.vk_account = Some(vk);
+    }
+
+    // Create the program's VK registry with the fee payer as its administrator, see
+    // `contract/src/registry.rs`. The fee payer must be the program's upgrade authority,
+    // and only the first call for a deployment succeeds.
+    pub fn init_registry(&self) -> ClientResult<Pubkey> {
+        let accounts = vec![
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(program_data_address(&self.program_id), false),
+        ];
+        self.send_registry(REGISTRY_INIT, vec![], accounts)?;
+        Ok(registry_address(&self.program_id).0)
+    }
+
+    // Register the key in the VK account `vk` (see `upload_vk`) under `id`, for good if
+    // `immutable`, and return the id's registry entry. The fee payer must be the
+    // registry's administrator.
+    pub fn register_vk(&self, id: u64, vk: Pubkey, immutable: bool) -> ClientResult<Pubkey> {
+        let entry = vk_entry_address(&self.program_id, id).0;
+        let mut input = id.to_le_bytes().to_vec();
+        if immutable {
+            input.push(ENTRY_IMMUTABLE);
+        }
+        let accounts = vec![
+            AccountMeta::new(entry, false),
+            AccountMeta::new_readonly(vk, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+        self.send_registry(REGISTRY_REGISTER, input, accounts)?;
+        Ok(entry)
+    }
+
+    // Point the entry of `id` at the key in the VK account `vk`; fails once the entry
+    // is frozen.
+    pub fn update_vk(&self, id: u64, vk: Pubkey) -> ClientResult<()> {
+        let entry = vk_entry_address(&self.program_id, id).0;
+        let accounts = vec![
+            AccountMeta::new(entry, false),
+            AccountMeta::new_readonly(vk, false),
+        ];
+        self.send_registry(REGISTRY_UPDATE, id.to_le_bytes().to_vec(), accounts)
+    }
+
+    // Freeze the entry of `id`, so its key can never be swapped.
+    pub fn freeze_vk(&self, id: u64) -> ClientResult<()> {
+        let entry = vk_entry_address(&self.program_id, id).0;
+        let accounts = vec![AccountMeta::new(entry, false)];
+        self.send_registry(REGISTRY_FREEZE, id.to_le_bytes().to_vec(), accounts)
+    }
+
+    // Hand the registry over to `admin`; the fee payer can no longer administer it.
+    pub fn set_registry_admin(&self, admin: Pubkey) -> ClientResult<()> {
+        self.send_registry(REGISTRY_SET_ADMIN, admin.to_bytes().to_vec(), vec![])
+    }
+
+    // The registry entry of `id`, if it is registered.
+    pub fn vk_entry(&self, id: u64) -> Option<VkEntry> {
+        let entry = vk_entry_address(&self.program_id, id).0;
+        let data = self.connection.get_account_data(&entry).ok()?;
+        VkEntry::unpack(&data)
+    }
+
+    // Registry operation `operation`, sent by the fee payer as the administrator, with
+    // the given accounts after the registry's.
+    fn send_registry(
+        &self,
+        operation: u8,
+        input: Vec<u8>,
+        accounts: Vec<AccountMeta>,
+    ) -> ClientResult<()> {
+        let data = VkRegistry(StepIndex::new(operation, 0), Payload(input)).pack();
+        let accounts = [
+            vec![
+                AccountMeta::new(self.payer.pubkey(), true),
+                AccountMeta::new(registry_address(&self.program_id).0, false),
+            ],
+            accounts,
+        ]
+        .concat();
+        let instruction = Instruction::new_with_bytes(self.program_id, &data, accounts);
+        self.send_instructions(&[instruction])?;
+        Ok(())
+    }
+
+    // Verify against the key registered under `id`: steps name it by its id, and the
+    // program finds its VK account through the registry.
+    pub fn set_registered_vk(&mut self, id: u64) -> ClientResult<()> {
+        let entry = vk_entry_address(&self.program_id, id).0;
+        let vk_entry = self.vk_entry(id).ok_or_else(|| ClientError::AccountMismatch {
+            account: entry,
+            reason: format!("is no registry entry, vk id {} is not registered", id),
+        })?;
+        let mutability = if vk_entry.is_immutable() { "immutable" } else { "mutable" };
+        info!("vk id {} is registered to {} ({})", id, vk_entry.vk_account, mutability);
+        self.vk_account = Some(vk_entry.vk_account);
+        self.registered_vk = Some((id, entry));
+        Ok(())
+    }
+
+    // Send the raw public inputs instead of the prepared input, and have the program
+    // fold them against the VK account (see `set_vk_account`) or the baked key, so the
+    // Miller loop runs over inputs the client cannot have mis-folded.
+    pub fn enable_onchain_inputs(&mut self) {
+        self.onchain_inputs = true;
+    }
+
+    // Whether the public inputs are folded on-chain: on request, and always for a final
+    // step attesting to them, which the program only accepts on inputs it folded.
+    fn folds_inputs(&self) -> bool {
+        self.onchain_inputs || self.attests_inputs()
+    }
+
+    // Whether the final step records the outcome under the public inputs, spends a
+    // nullifier or creates a receipt, see `contract/src/final_exponentiation.rs`.
+    fn attests_inputs(&self) -> bool {
+        self.result_log.is_some() || self.nullifiers || self.receipts
+    }
+
+    // Fold `public_inputs` (32-byte little-endian field elements) on-chain into the
+    // inputs account of `keys`, against the VK account if one is set. The program
+    // rejects a count other than the key's.
+    fn fold_public_inputs(&self, keys: &SessionKeys, public_inputs: &[u8]) -> ClientResult<()> {
+        let instruction = self.fold_instruction(keys, public_inputs);
+        self.send_with_budget(&[instruction], COMPUTE_UNITS)
+    }
+
+    fn fold_instruction(&self, keys: &SessionKeys, public_inputs: &[u8]) -> Instruction {
+        let mut input = vec![SOURCE_INSTRUCTION];
+        input.extend(public_inputs.iter());
+        let mut data = FoldPublicInputs(StepIndex::default(), Payload(input)).pack();
+        data.extend(keys.session_id.to_le_bytes().iter());
+        let mut accounts = vec![];
+        self.add_vk_account(&mut data, &mut accounts);
+        accounts.push(AccountMeta::new_readonly(self.payer.pubkey(), true));
+        accounts.push(AccountMeta::new(keys.inputs.unwrap(), false));
+        self.program_instruction(accounts, data)
+    }
+
+    // Verify without a stored verifying key: every Miller-loop step carries its ell
+    // coefficients and the final step alpha_g1_beta_g2, taken from the proving key in
+    // use, so the proof checks against that key whatever the program was built with.
+    pub fn enable_inline_vk(&mut self) {
+        self.inline_vk = true;
+    }
+
+    // Verify with a single instruction over the alt_bn128 pairing syscall (program built
+    // with the `alt-bn128` feature), falling back to the chunked verification on
+    // clusters or builds without it.
+    pub fn enable_alt_bn128(&mut self) {
+        self.alt_bn128 = true;
+    }
+
+    // Send the proof's points and the prepared input compressed, about half the
+    // instruction data, at the cost of a square root per point on-chain.
+    pub fn enable_compressed_points(&mut self) {
+        self.compressed_points = true;
+    }
+
+    // Run `steps` gamma and delta Miller-loop steps per instruction instead of one. Each
+    // loop has 65 steps, so 8 cut its transactions from 65 to 9, as far as the cluster's
+    // compute budget allows.
+    pub fn set_miller_loop_steps(&mut self, steps: u8) {
+        assert!(steps > 0, "at least one Miller-loop step per instruction");
+        self.miller_loop_steps = steps;
+    }
+
+    // Advance the gamma and delta Miller loops together, one instruction per step of
+    // both (or per `set_miller_loop_steps` steps), halving their transactions.
+    pub fn enable_fused_miller_loops(&mut self) {
+        self.fused_miller_loops = true;
+    }
+
+    // Run `digits` NAF digits per step of the exponentiations by x (y0, y4, y6) instead
+    // of one. Each has 63 digits, so 10 cut its transactions from 63 to 7, as far as the
+    // cluster's compute budget allows.
+    pub fn set_naf_digits(&mut self, digits: u8) {
+        assert!(digits > 0, "at least one NAF digit per step");
+        self.naf_digits = digits;
+    }
+
+    // Plan the steps from measured `costs` (see `calibrate`): as many Miller-loop steps
+    // and NAF digits per instruction, and as many instructions per transaction, as fit
+    // `target` compute units. Must follow `enable_fused_miller_loops`; an explicit
+    // `set_miller_loop_steps` or `set_naf_digits` after it overrides the chunk sizes.
+    pub fn set_step_costs(&mut self, costs: StepCosts, target: u32) {
+        let target_units = target as u64;
+        self.miller_loop_steps = costs.miller_loop_steps(target_units, self.fused_miller_loops);
+        self.naf_digits = costs.naf_digits(target_units);
+        info!(
+            "planning {} compute units per transaction: {} Miller-loop steps, {} NAF digits per instruction",
+            target, self.miller_loop_steps, self.naf_digits
+        );
+        self.step_costs = Some((costs, target));
+    }
+
+    // Run the demo verification one transaction per step, simulating each step before
+    // sending it, and return the compute units one step of each instruction took.
+    pub fn calibrate(&self) -> ClientResult<StepCosts> {
+        self.calibration.replace(Some(StepCosts::new()));
+        let verified = self.groth16_verify();
+        let costs = self.calibration.replace(None).unwrap();
+        verified.map(|_| costs)
+    }
+
+    // Have the final step spend the nullifier of the public inputs once the proof is
+    // verified, so that no later verification under the same verifying key can use it
+    // again (custom program error 7). The payer funds the nullifier's account.
+    pub fn enable_nullifiers(&mut self) {
+        self.nullifiers = true;
+    }
+
+    // The nullifier the final step spends for `public_inputs`, if enabled: the program
+    // reads public input `NULLIFIER_INPUT` of the inputs it folded, as 32 little-endian
+    // bytes, so a key without public inputs has none.
+    fn nullifier(&self, public_inputs: &[Fr]) -> ClientResult<Option<[u8; 32]>> {
+        if !self.nullifiers {
+            return Ok(None);
+        }
+        let input = public_inputs
+            .get(NULLIFIER_INPUT)
+            .ok_or_else(|| ClientError::input("the verifying key has no nullifier input"))?;
+        let mut nullifier = [0; 32];
+        nullifier.copy_from_slice(&to_bytes!(input).unwrap());
+        Ok(Some(nullifier))
+    }
+
+    // Address of `nullifier`'s account under the verifying key in use
+    pub fn nullifier_address(&self, nullifier: &[u8; 32]) -> ClientResult<Pubkey> {
+        Ok(nullifier_address(&self.program_id, &self.vk_id()?, nullifier).0)
+    }
+
+    // The account of the nullifier of `public_inputs` the final step spends, if any
+    fn nullifier_account(&self, public_inputs: &[Fr]) -> ClientResult<Option<Pubkey>> {
+        self.nullifier(public_inputs)?
+            .map(|nullifier| self.nullifier_address(&nullifier))
+            .transpose()
+    }
+
+    // Have each verification, single-instruction or chunked, create a receipt account
+    // for the verified statement, which other programs can check later (see
+    // `contract/src/receipt.rs`). The payer funds the receipts.
+    pub fn enable_receipts(&mut self) {
+        self.receipts = true;
+    }
+
+    // Address of the receipt of `proof` of `public_inputs` under the verifying key in use
+    pub fn receipt_address(
+        &self,
+        proof: &Proof<Bn254>,
+        public_inputs: &[Fr],
+    ) -> ClientResult<Pubkey> {
+        let statement = statement_hash(&proof.a, &proof.b, &proof.c, public_inputs, &self.vk_id()?);
+        Ok(receipt_address(&self.program_id, &statement).0)
+    }
+
+    // The receipt of `proof` of `public_inputs`, if that statement has been verified
+    pub fn read_receipt(
+        &self,
+        proof: &Proof<Bn254>,
+        public_inputs: &[Fr],
+    ) -> ClientResult<Option<Receipt>> {
+        let address = self.receipt_address(proof, public_inputs)?;
+        Ok(self
+            .connection
+            .get_account(&address)
+            .ok()
+            .filter(|account| account.owner == self.program_id)
+            .and_then(|account| Receipt::unpack(&account.data)))
+    }
+
+    // Id of the verifying key in use, the VK account's if one is set, as the program
+    // derives nullifiers and receipts from it
+    fn vk_id(&self) -> ClientResult<[u8; 32]> {
+        match self.vk_account {
+            Some(vk) => {
+                let data = self.connection.get_account_data(&vk)?;
+                Ok(read_vk_account(&data).map_err(|_| invalid_vk_account(vk))?.id())
+            }
+            None => on_chain_vk_id(&self.verifying_key()?).map_err(ClientError::Input),
+        }
+    }
+
+    // What the final check compares y16 against, from the VK account if one is set
+    fn alpha_g1_beta_g2(&self) -> ClientResult<Fq12> {
+        match self.vk_account {
+            Some(vk) => {
+                let data = self.connection.get_account_data(&vk)?;
+                read_vk_account(&data)
+                    .and_then(|key| key.alpha_g1_beta_g2())
+                    .map_err(|_| invalid_vk_account(vk))
+            }
+            None => Ok(prepare_verifying_key(&self.verifying_key()?).alpha_g1_beta_g2),
+        }
+    }
+
+    // Whether `nullifier` has been spent under the verifying key in use
+    pub fn is_nullifier_spent(&self, nullifier: &[u8; 32]) -> ClientResult<bool> {
+        let address = self.nullifier_address(nullifier)?;
+        Ok(match self.connection.get_account(&address) {
+            Ok(account) => account.owner == self.program_id,
+            Err(_) => false,
+        })
+    }
+
+    pub(crate) fn point_encoding(&self) -> PointEncoding {
+        if self.compressed_points {
+            PointEncoding::Compressed
+        } else {
+            PointEncoding::Uncompressed
+        }
+    }
+
+    // The single-instruction verification checks against the baked key and only fails
+    // the transaction on an invalid proof; anything else needs the chunked path.
+    fn alt_bn128_applies(&self, callback: Option<&Callback>) -> bool {
+        let applies = callback.is_none()
+            && self.result_log.is_none()
+            && !self.result_account
+            && !self.nullifiers
+            && !self.receipts
+            && self.vk_account.is_none()
+            && !self.inline_vk;
+        if !applies {
+            warn!("single-instruction verification does not support the options in use");
+        }
+        applies
+    }
+
+    // Start a new guarded session under a fresh nonce.
+    pub fn start_session(&self) -> ClientResult<()> {
+        let nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|err| ClientError::Input(format!("system clock: {}", err)))?
+            .as_nanos() as u64;
+        self.session.set((nonce, 0));
+        Ok(())
+    }
+
+    // Send `steps`, those independent of each other concurrently (see
+    // `send_concurrently`), one after the other while calibrating.
+    pub fn send_steps(&self, steps: &[Step]) -> ClientResult<()> {
+        self.send_streams(&[steps.to_vec()])
+    }
+
+    // Send several streams of steps, such as the gamma and delta Miller loops: each one
+    // packed into transactions of its own, so no transaction makes one stream wait for
+    // another, and the streams' transactions in flight together.
+    pub fn send_streams(&self, streams: &[Vec<Step>]) -> ClientResult<()> {
+        if self.calibration.borrow().is_some() {
+            for step in streams.iter().flatten() {
+                let instruction = self.step_instruction(step)?;
+                if let Some(costs) = self.calibration.borrow_mut().as_mut() {
+                    if let Some(units) = self.simulate_units(&instruction) {
+                        costs.record(&instruction.data, units);
+                    }
+                }
+                self.send_with_budget(&[instruction], COMPUTE_UNITS)?;
+            }
+            return Ok(());
+        }
+        let (transactions, units) = self.stream_transactions(streams)?;
+        self.send_concurrently(&transactions, Some(units))
+    }
+
+    // The transactions `streams` go out in and the compute units each requests: the
+    // steps packed by their costs (see `set_step_costs`), or one per transaction.
+    fn stream_transactions(
+        &self,
+        streams: &[Vec<Step>],
+    ) -> ClientResult<(Vec<Vec<Instruction>>, u32)> {
+        let streams = streams
+            .iter()
+            .map(|steps| {
+                steps
+                    .iter()
+                    .map(|step| self.step_instruction(step))
+                    .collect::<ClientResult<Vec<_>>>()
+            })
+            .collect::<ClientResult<Vec<_>>>()?;
+        match &self.step_costs {
+            Some((costs, target)) => {
+                let mut transactions = vec![];
+                for instructions in streams.iter() {
+                    let data = instructions.iter().map(|instruction| &instruction.data[..]);
+                    for run in costs.plan(data, *target as u64) {
+                        // as many instructions as also fit a packet
+                        let mut start = run.start;
+                        for end in run.start + 2..=run.end {
+                            if !self.fits_packet(&instructions[start..end]) {
+                                transactions.push(instructions[start..end - 1].to_vec());
+                                start = end - 1;
+                            }
+                        }
+                        transactions.push(instructions[start..run.end].to_vec());
+                    }
+                }
+                Ok((transactions, *target))
+            }
+            None => {
+                let transactions = streams
+                    .into_iter()
+                    .flatten()
+                    .map(|instruction| vec![instruction])
+                    .collect();
+                Ok((transactions, COMPUTE_UNITS))
+            }
+        }
+    }
+
+    // Send `transactions`, requesting `units` compute units for each if given, and
+    // return once all have landed. A transaction is only sent once every earlier one
+    // writing an account it uses has landed, so transactions go out in waves: the
+    // gamma and delta loops side by side, say, or the streams of a batch, up to
+    // `MAX_IN_FLIGHT` at once, confirmed together (see `send_window`). The fee payer,
+    // which every transaction writes, does not order them. Fails with the first failure,
+    // as sending one at a time does.
+    fn send_concurrently(
+        &self,
+        transactions: &[Vec<Instruction>],
+        units: Option<u32>,
+    ) -> ClientResult<()> {
+        if units.is_some() {
+            transactions.iter().for_each(|instructions| self.simulate_new_tags(instructions));
+        }
+        let payer = self.payer.pubkey();
+        let mut last_wave = HashMap::new();
+        let mut waves: Vec<Vec<&[Instruction]>> = vec![];
+        let steps = transactions.len();
+        // a resumed verification's transactions that landed before
+        let transactions = transactions
+            .iter()
+            .filter(|instructions| !self.is_confirmed(instructions))
+            .collect::<Vec<_>>();
+        let mut step = steps - transactions.len();
+        for instructions in transactions {
+            let keys = instructions
+                .iter()
+                .flat_map(|instruction| instruction.accounts.iter())
+                .filter(|account| account.is_writable && account.pubkey != payer)
+                .map(|account| account.pubkey)
+                .collect::<Vec<_>>();
+            let wave = keys
+                .iter()
+                .filter_map(|key| last_wave.get(key))
+                .map(|wave| wave + 1)
+                .max()
+                .unwrap_or(0);
+            for key in keys {
+                last_wave.insert(key, wave);
+            }
+            if wave == waves.len() {
+                waves.push(vec![]);
+            }
+            waves[wave].push(instructions);
+        }
+        for wave in waves {
+            for chunk in wave.chunks(self.max_in_flight()) {
+                let results = self.runtime.block_on(async {
+                    let signatures = self.send_window(chunk, units).await;
+                    let units = signatures.iter().map(|signature| async move {
+                        match (signature, &self.progress) {
+                            (Ok(signature), Some(_)) => self.consumed_units(signature).await,
+                            _ => None,
+                        }
+                    });
+                    let units = join_all(units).await;
+                    signatures.into_iter().zip(units).collect::<Vec<_>>()
+                });
+                for (signature, units) in results {
+                    let signature = signature?;
+                    step += 1;
+                    if let Some(progress) = &self.progress {
+                        progress(&ProgressEvent {
+                            phase: self.phase.get(),
+                            step,
+                            steps,
+                            signature,
+                            units,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Send the transactions of `window`, independent of each other, and return their
+    // signatures in order once all have landed. They are signed over one blockhash,
+    // sent (to the leaders' TPUs with `enable_tpu`), and confirmed together: as the
+    // node notifies that they landed, with signature subscriptions (see
+    // `enable_websocket`), and by one `get_signature_statuses` call polling them all,
+    // for notifications missed or without them. Those the cluster has not seen yet are
+    // sent again after each poll.
+    // One that fails to send, or that never lands before the blockhash expires, goes
+    // on alone with the retries of `send_instructions`. Durable nonces and compute
+    // requests sized by simulation need the latter for every transaction.
+    async fn send_window(
+        &self,
+        window: &[&[Instruction]],
+        units: Option<u32>,
+    ) -> Vec<RpcResult<Signature>> {
+        let send_alone = |instructions| async move {
+            match units {
+                Some(units) => self.send_with_budget_async(instructions, units).await,
+                None => self.send_instructions_async(instructions, &[]).await,
+            }
+        };
+        let blockhash = match self.sender.get_latest_blockhash().await {
+            Ok(blockhash) if self.nonce_accounts.is_empty() && !self.preflight => blockhash,
+            _ => return join_all(window.iter().map(|instructions| send_alone(instructions))).await,
+        };
+        let transactions = window
+            .iter()
+            .map(|instructions| {
+                let with_budget = [&self.budget_instructions(units)[..], instructions].concat();
+                let transaction = Transaction::new_signed_with_payer(
+                    &with_budget,
+                    Some(&self.payer.pubkey()),
+                    &self.signers(instructions, &[]),
+                    blockhash,
+                );
+                self.record_signed(instructions, &transaction)
+                    .map(|()| transaction)
+            })
+            .collect::<Vec<_>>();
+        // send none of the window unless the manifest holds all of it
+        let transactions = match transactions.into_iter().collect::<ClientResult<Vec<_>>>() {
+            Ok(transactions) => transactions,
+            Err(err) => {
+                let err = err.to_string();
+                let mut failed = Vec::with_capacity(window.len());
+                for _ in window {
+                    failed.push(Err(ClientErrorKind::Custom(err.clone()).into()));
+                }
+                return failed;
+            }
+        };
+
+        // subscribed before sending, so no transaction lands ahead of its subscription
+        let mut streams = vec![];
+        let mut unsubscribes = vec![];
+        if let Some(pubsub) = &self.pubsub {
+            let config = RpcSignatureSubscribeConfig {
+                commitment: Some(self.commitment),
+                enable_received_notification: Some(false),
+            };
+            let subscribes = transactions.iter().map(|transaction| {
+                pubsub.signature_subscribe(&transaction.signatures[0], Some(config.clone()))
+            });
+            for (k, subscribed) in join_all(subscribes).await.into_iter().enumerate() {
+                // one not subscribed to is polled
+                if let Ok((notifications, unsubscribe)) = subscribed {
+                    streams.push(notifications.map(move |notification| (k, notification.value)));
+                    unsubscribes.push(unsubscribe);
+                }
+            }
+        }
+        let mut notifications = stream::select_all(streams);
+
+        let sends = transactions
+            .iter()
+            .map(|transaction| self.submit(transaction, RpcSendTransactionConfig::default()));
+        // none while pending, the result once landed, or none to send it alone
+        let mut results = join_all(sends)
+            .await
+            .into_iter()
+            .map(|sent| match sent {
+                // to be sent alone
+                Err(err) if is_rejected(&err) => Some(None),
+                // polled, as one lost on its way may have reached the node
+                _ => None,
+            })
+            .collect::<Vec<Option<Option<RpcResult<Signature>>>>>();
+        let landed = |k: usize, err: Option<TransactionError>| {
+            let signature = transactions[k].signatures[0];
+            Some(Some(match err {
+                None => self
+                    .record_confirmed(&signature)
+                    .map(|()| signature)
+                    .map_err(manifest_error),
+                Some(err) => Err(err.into()),
+            }))
+        };
+
+        let resend = RpcSendTransactionConfig {
+            skip_preflight: true,
+            ..RpcSendTransactionConfig::default()
+        };
+        let wait = match self.pubsub {
+            Some(_) => NOTIFICATION_TIMEOUT,
+            None => STATUS_POLL_INTERVAL,
+        };
+        let mut failures = 0;
+        loop {
+            let deadline = tokio::time::Instant::now() + wait;
+            while results.iter().any(Option::is_none) {
+                match tokio::time::timeout_at(deadline, notifications.next()).await {
+                    Ok(Some((k, RpcSignatureResult::ProcessedSignature(processed)))) => {
+                        if results[k].is_none() {
+                            results[k] = landed(k, processed.err);
+                        }
+                    }
+                    Ok(Some(_)) => {}
+                    // nothing left to notify
+                    Ok(None) => {
+                        tokio::time::sleep_until(deadline).await;
+                        break;
+                    }
+                    Err(_) => break,
+                }
+            }
+            let pending = (0..window.len())
+                .filter(|&k| results[k].is_none())
+                .collect::<Vec<_>>();
+            if pending.is_empty() {
+                break;
+            }
+            // checked before the statuses, so that a transaction still unseen after it
+            // expired can no longer land
+            let expired = !self
+                .sender
+                .is_blockhash_valid(&blockhash, CommitmentConfig::processed())
+                .await
+                .unwrap_or(true);
+            let signatures = pending
+                .iter()
+                .map(|&k| transactions[k].signatures[0])
+                .collect::<Vec<_>>();
+            let statuses = match self.sender.get_signature_statuses(&signatures).await {
+                Ok(statuses) => statuses.value,
+                Err(err) if failures < self.retries => {
+                    warn!("cannot poll transaction statuses ({}), retrying", err);
+                    failures += 1;
+                    continue;
+                }
+                Err(err) => {
+                    let msg = format!("cannot poll transaction statuses: {}", err);
+                    for k in pending {
+                        results[k] = Some(Some(Err(ClientErrorKind::Custom(msg.clone()).into())));
+                    }
+                    break;
+                }
+            };
+            failures = 0;
+            let mut unseen = vec![];
+            for (&k, status) in pending.iter().zip(statuses) {
+                match status {
+                    Some(status) if status.satisfies_commitment(self.commitment) => {
+                        results[k] = landed(k, status.err)
+                    }
+                    // landed, on its way to the commitment
+                    Some(_) => {}
+                    None => unseen.push(k),
+                }
+            }
+            if expired {
+                for k in unseen {
+                    results[k] = Some(None);
+                }
+                continue;
+            }
+            for k in unseen {
+                // a resend of one that landed meanwhile is dropped as a duplicate
+                let _ = self.submit(&transactions[k], resend).await;
+            }
+        }
+        // the node ends a subscription once it notified, but the client keeps its
+        // channel until unsubscribed
+        join_all(unsubscribes.into_iter().map(|unsubscribe| unsubscribe())).await;
+
+        let sends = window.iter().zip(results).map(|(instructions, result)| async move {
+            match result {
+                Some(Some(result)) => result,
+                _ => send_alone(instructions).await,
+            }
+        });
+        join_all(sends).await
+    }
+
+    // Send `transaction` to the leaders' TPUs with `enable_tpu`, or over RPC with
+    // `config` without, or when none of the leaders took it. The TPU runs no preflight,
+    // so a transaction failing there is only known once it lands, failed.
+    async fn submit(
+        &self,
+        transaction: &Transaction,
+        config: RpcSendTransactionConfig,
+    ) -> RpcResult<Signature> {
+        if let Some(tpu) = &self.tpu {
+            match tpu.try_send_transaction(transaction).await {
+                Ok(()) => return Ok(transaction.signatures[0]),
+                Err(err) => {
+                    warn!("cannot send to the leaders' TPUs ({}), sending over RPC", err)
+                }
+            }
+        }
+        self.sender
+            .send_transaction_with_config(transaction, config)
+            .await
+    }
+
+    // The compute units the landed transaction `signature` consumed, as its metadata
+    // has them.
+    async fn consumed_units(&self, signature: &Signature) -> Option<u64> {
+        let transaction = self
+            .sender
+            .get_transaction(signature, UiTransactionEncoding::Base64)
+            .await
+            .ok()?;
+        Option::from(transaction.transaction.meta?.compute_units_consumed)
+    }
+
+    // Report every transaction that lands while sending steps to `progress`, with the
+    // phase of the verification it belongs to, its place among the phase's
+    // transactions, its signature and the compute units it consumed. Fetching those
+    // takes a request per transaction.
+    pub fn set_progress(&mut self, progress: impl Fn(&ProgressEvent) + 'static) {
+        self.progress = Some(Box::new(progress));
+    }
+
+    // Keep what the verifications that follow leave for `report`: the proof, and every
+    // transaction that lands with its phase.
+    pub fn enable_report(&mut self) {
+        self.report.replace(Some(ReportLog::default()));
+    }
+
+    // The report of the verifications since `enable_report`, which ended in `outcome`:
+    // their transactions' fees and rent, read back from the cluster at confirmed
+    // commitment, a request per transaction, and the result, from the result account
+    // or the result log if either is enabled. Reporting stops until enabled again.
+    pub fn report(&self, outcome: &ClientResult<()>) -> ClientResult<Report> {
+        let log = self.report.replace(None).unwrap_or_default();
+        let (valid, error) = match outcome {
+            Ok(()) => (Some(self.latest_result()?), None),
+            Err(err) if matches!(err.verifier_error(), Some(VerifierError::InvalidProof)) => {
+                (Some(false), None)
+            }
+            Err(err) => (None, Some(err.to_string())),
+        };
+        let mut report = Report {
+            proof_hash: log.proof_hash,
+            vk_id: self.vk_id()?,
+            valid,
+            error,
+            final_slot: None,
+            fees: 0,
+            rent_paid: 0,
+            rent_reclaimed: 0,
+            phases: log.phases(),
+        };
+        for (phase, signature) in log.transactions.iter() {
+            let config = RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Base64),
+                commitment: Some(CommitmentConfig::confirmed()),
+                ..RpcTransactionConfig::default()
+            };
+            let transaction = self.connection.get_transaction_with_config(signature, config)?;
+            // the final check lands last but for the cleanup, and only if it held or
+            // recorded its result
+            if outcome.is_ok() && *phase != "cleanup" {
+                report.final_slot = Some(transaction.slot);
+            }
+            let meta = match transaction.transaction.meta {
+                Some(meta) => meta,
+                None => continue,
+            };
+            report.fees += meta.fee;
+            // accounts created hold their rent afterwards, those closed held it before
+            for (pre, post) in meta.pre_balances.iter().zip(meta.post_balances.iter()) {
+                if *pre == 0 {
+                    report.rent_paid += post;
+                } else if *post == 0 {
+                    report.rent_reclaimed += pre;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    // Whether the latest verification held, as the result account or the result log
+    // has it; one that recorded its result nowhere failed if the proof was invalid.
+    fn latest_result(&self) -> ClientResult<bool> {
+        if let Some(result) = self.read_verification_result()? {
+            return Ok(result.valid);
+        }
+        let count = self.result_count()?.unwrap_or(0);
+        match self.read_result(count.saturating_sub(1))? {
+            Some(record) => Ok(record.valid),
+            None => Ok(true),
+        }
+    }
+
+    pub(crate) fn set_phase(&self, phase: &'static str) {
+        self.phase.set(phase);
+    }
+
+    // Transactions in flight at once: with durable nonces, one per nonce account.
+    fn max_in_flight(&self) -> usize {
+        match self.nonce_accounts.len() {
+            0 => MAX_IN_FLIGHT,
+            accounts => accounts.min(MAX_IN_FLIGHT),
+        }
+    }
+
+    fn step_instruction(&self, step: &Step) -> ClientResult<Instruction> {
+        let mut step = step.clone();
+        if let Some(vk) = self.vk_account {
+            steps::use_vk_account(&mut step, vk);
+        } else if self.inline_vk {
+            let mut stateless_vk = self.stateless_vk.borrow_mut();
+            if stateless_vk.is_none() {
+                *stateless_vk = Some(to_stateless_vk(&self.verifying_key()?));
+            }
+            if let Some(vk) = stateless_vk.as_ref() {
+                steps::inline_vk(&mut step, vk);
+            }
+        }
+        let session_id = step.session_id.unwrap_or(self.session_id);
+        let telemetry = Some(Role::Telemetry)
+            .filter(|_| self.telemetry)
+            .map(|role| self.scratch_address(session_id, role));
+        steps::use_scratch(&mut step, self.payer.pubkey(), session_id, telemetry);
+        if let Some((id, entry)) = self.registered_vk {
+            steps::use_registered_vk(&mut step, entry, id);
+        }
+        if self.session_guard {
+            let session = self.scratch_address(session_id, Role::Session);
+            let (nonce, index) = self.session.get();
+            steps::guard(&mut step, session, nonce, index);
+            self.session.set((nonce, index + 1));
+        }
+        let payer = self.payer.pubkey();
+        let accounts = step
+            .keys
+            .iter()
+            .map(|key| AccountMeta::new(*key, *key == payer))
+            .chain(step.extra_accounts.iter().cloned())
+            .collect();
+        Ok(self.program_instruction(accounts, step.data))
+    }
+
+    fn program_instruction(&self, accounts: Vec<AccountMeta>, data: Vec<u8>) -> Instruction {
+        Instruction::new_with_bytes(self.program_id, &data, accounts)
+    }
+
+    pub fn send_transction(&self, accounts: Vec<AccountMeta>, data: Vec<u8>) -> ClientResult<()> {
+        let instruction = self.program_instruction(accounts, data);
+        self.send_with_budget(&[instruction], COMPUTE_UNITS)
+    }
+
+    // Pay `micro_lamports` per compute unit on every transaction, so they land ahead of
+    // others during congestion. The fee is the price times the units requested, see
+    // `send_with_budget`, or the default budget without a request.
+    pub fn set_compute_unit_price(&mut self, micro_lamports: u64) {
+        self.compute_unit_price = Some(micro_lamports);
+    }
+
+    // Have transactions use the durable nonces of `accounts` (see
+    // `create_nonce_accounts`) instead of recent blockhashes, so that they stay valid
+    // however long they take to land. A transaction advances its nonce when it lands,
+    // and only one transaction per account can be in flight at a time.
+    pub fn set_nonce_accounts(&mut self, accounts: Vec<Pubkey>) {
+        self.free_nonce_accounts.replace(accounts.clone());
+        self.nonce_accounts = accounts;
+    }
+
+    // Create `count` durable nonce accounts with the fee payer as their authority and
+    // return their addresses.
+    pub fn create_nonce_accounts(&self, count: usize) -> ClientResult<Vec<Pubkey>> {
+        let lamports = self
+            .connection
+            .get_minimum_balance_for_rent_exemption(NonceState::size())?;
+        let payer = self.payer.pubkey();
+        (0..count)
+            .map(|_| {
+                let nonce_keypair = Keypair::new();
+                let nonce = nonce_keypair.pubkey();
+                info!("Creating nonce account {}", nonce);
+                let instructions = system_instruction::create_nonce_account(
+                    &payer, &nonce, &payer, lamports,
+                );
+                self.send_instructions_signed(&instructions, &[&nonce_keypair])?;
+                Ok(nonce)
+            })
+            .collect()
+    }
+
+    // Send `instructions` in one transaction requesting `units` compute units, or with
+    // the default budget from the first cluster that rejects the request on.
+    fn send_with_budget(&self, instructions: &[Instruction], units: u32) -> ClientResult<()> {
+        self.simulate_new_tags(instructions);
+        self.runtime
+            .block_on(self.send_with_budget_async(instructions, units))?;
+        Ok(())
+    }
+
+    async fn send_with_budget_async(
+        &self,
+        instructions: &[Instruction],
+        units: u32,
+    ) -> RpcResult<Signature> {
+        if let Some(sized) = self.preflight_units(instructions).filter(|&sized| sized < units) {
+            match self.send_budgeted(instructions, Some(sized), &[]).await {
+                Err(err) if is_budget_exceeded(&err) => {
+                    warn!(
+                        "{} compute units were too few ({}), requesting {}",
+                        sized, err, units
+                    );
+                    self.raise_preflight_costs(instructions);
+                }
+                result => return result,
+            }
+        }
+        self.send_budgeted(instructions, Some(units), &[]).await
+    }
+
+    // Size compute unit requests by simulation: simulate one instruction of each tag
+    // the first time one is sent, and request what the instructions of a transaction
+    // took, plus a margin, instead of `COMPUTE_UNITS` or `CU_TARGET`. Steps of a tag
+    // can cost more than the one simulated; a transaction that runs out of units is
+    // sent again with the full request, and the costs of its tags are raised.
+    pub fn enable_preflight(&mut self) {
+        self.preflight = true;
+    }
+
+    // Simulate the first instruction of every tag in `instructions` not measured yet.
+    fn simulate_new_tags(&self, instructions: &[Instruction]) {
+        if !self.preflight {
+            return;
+        }
+        for instruction in instructions {
+            if instruction.program_id != self.program_id
+                || self.preflight_costs.borrow().cost(&instruction.data).is_some()
+            {
+                continue;
+            }
+            if let Some(units) = self.simulate_units(instruction) {
+                self.preflight_costs
+                    .borrow_mut()
+                    .record(&instruction.data, units);
+            }
+        }
+    }
+
+    // The compute units to request for `instructions`, `None` unless every one of the
+    // program's was measured.
+    fn preflight_units(&self, instructions: &[Instruction]) -> Option<u32> {
+        if !self.preflight {
+            return None;
+        }
+        let costs = self.preflight_costs.borrow();
+        let mut units = 0;
+        for instruction in instructions {
+            if instruction.program_id == self.program_id {
+                units += costs.cost(&instruction.data)?;
+            }
+        }
+        let units = units * (100 + PREFLIGHT_MARGIN_PERCENT) / 100 + PREFLIGHT_OVERHEAD_UNITS;
+        Some(units.min(u32::MAX as u64) as u32)
+    }
+
+    // Raise the cost of the tags of `instructions` by the margin, after they ran out.
+    fn raise_preflight_costs(&self, instructions: &[Instruction]) {
+        let mut costs = self.preflight_costs.borrow_mut();
+        let program_instructions = instructions
+            .iter()
+            .filter(|instruction| instruction.program_id == self.program_id);
+        for instruction in program_instructions {
+            if let Some(units) = costs.cost(&instruction.data) {
+                let raised = units * (100 + PREFLIGHT_MARGIN_PERCENT) / 100;
+                costs.record(&instruction.data, raised);
+            }
+        }
+    }
+
+    // Send `instructions` behind the compute budget instructions: a request for `units`
+    // compute units, if any, and the compute unit price, if set. Without them from the
+    // first cluster that rejects them on.
+    async fn send_budgeted(
+        &self,
+        instructions: &[Instruction],
+        units: Option<u32>,
+        signers: &[&dyn Signer],
+    ) -> RpcResult<Signature> {
+        let budget = self.budget_instructions(units);
+        if !budget.is_empty() {
+            let with_budget = [&budget[..], instructions].concat();
+            // behind the nonce advance, if any
+            let first = !self.nonce_accounts.is_empty() as usize;
+            match self.send_recorded(&with_budget, signers).await {
+                Err(err) if is_compute_budget_rejected(&err, first..first + budget.len()) => {
+                    warn!(
+                        "cluster rejected the compute budget instruction ({}), falling back to the default budget",
+                        err
+                    );
+                    self.compute_budget.set(false);
+                }
+                result => return result,
+            }
+        }
+        self.send_recorded(instructions, signers).await
+    }
+
+    // The compute budget instructions to put first in a transaction, none once the
+    // cluster rejected them.
+    fn budget_instructions(&self, units: Option<u32>) -> Vec<Instruction> {
+        if !self.compute_budget.get() {
+            return vec![];
+        }
+        let request = units.map(ComputeBudgetInstruction::set_compute_unit_limit);
+        let price = self
+            .compute_unit_price
+            .map(ComputeBudgetInstruction::set_compute_unit_price);
+        request.into_iter().chain(price).collect()
+    }
+
+    // Whether `instructions` with the compute budget instructions, and the nonce advance
+    // if any, fit one transaction packet.
+    fn fits_packet(&self, instructions: &[Instruction]) -> bool {
+        let advance = self.nonce_accounts.first().map(|nonce| {
+            system_instruction::advance_nonce_account(nonce, &self.payer.pubkey())
+        });
+        let budget = self.budget_instructions(Some(COMPUTE_UNITS));
+        let with_request = [&advance.into_iter().collect::<Vec<_>>(), &budget[..], instructions]
+            .concat();
+        let message = Message::new(&with_request, Some(&self.payer.pubkey()));
+        let signatures = message.header.num_required_signatures as usize;
+        // signature count (compact-u16) and signatures before the message
+        1 + 64 * signatures + message.serialize().len() <= PACKET_DATA_SIZE
+    }
+
+    // The compute units the program consumed in a simulation of `instruction`, read
+    // from its "Program <id> consumed <units> of <budget> compute units" log line.
+    fn simulate_units(&self, instruction: &Instruction) -> Option<u64> {
+        let mut instructions = self.budget_instructions(Some(COMPUTE_UNITS));
+        instructions.push(instruction.clone());
+        let recent_hash = self.connection.get_latest_blockhash().ok()?;
+        let transaction = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&self.payer.pubkey()),
+            &[&*self.payer],
+            recent_hash,
+        );
+        let simulation = self.connection.simulate_transaction(&transaction).ok()?.value;
+        let prefix = format!("Program {} consumed ", self.program_id);
+        simulation.logs?.iter().find_map(|log| {
+            let units = log.strip_prefix(&prefix)?;
+            units.split(' ').next()?.parse().ok()
+        })
+    }
+
+    // Resend a transaction up to `retries` times after a retryable failure (see
+    // `is_retryable`); 0 fails at the first one.
+    pub fn set_retries(&mut self, retries: u32) {
+        self.retries = retries;
+    }
+
+    // Wait `backoff` before the first resend, and twice as long before each later one.
+    pub fn set_retry_backoff(&mut self, backoff: Duration) {
+        self.retry_backoff = backoff;
+    }
+
+    // Send `instructions` in one transaction, retrying it as set by `set_retries`. While
+    // its blockhash is valid the same signed transaction is resent, so it lands at most
+    // once; only after the blockhash expired, and the transaction didn't land, is it
+    // re-signed with a fresh one. Fatal failures are returned at once.
+    fn send_instructions(&self, instructions: &[Instruction]) -> ClientResult<Signature> {
+        self.send_instructions_signed(instructions, &[])
+    }
+
+    // As `send_instructions`, with `signers` signing besides the fee payer.
+    fn send_instructions_signed(
+        &self,
+        instructions: &[Instruction],
+        signers: &[&dyn Signer],
+    ) -> ClientResult<Signature> {
+        Ok(self
+            .runtime
+            .block_on(self.send_instructions_async(instructions, signers))?)
+    }
+
+    async fn send_instructions_async(
+        &self,
+        instructions: &[Instruction],
+        signers: &[&dyn Signer],
+    ) -> RpcResult<Signature> {
+        self.send_budgeted(instructions, None, signers).await
+    }
+
+    // Send `instructions` as they are, recording them in the manifest if one is open.
+    async fn send_recorded(
+        &self,
+        instructions: &[Instruction],
+        signers: &[&dyn Signer],
+    ) -> RpcResult<Signature> {
+        // `send_concurrently` keeps no more transactions in flight than nonce accounts
+        let nonce = if self.nonce_accounts.is_empty() {
+            None
+        } else {
+            Some(self.free_nonce_accounts.borrow_mut().pop().unwrap())
+        };
+        let result = self.send_retrying(instructions, signers, nonce).await;
+        if let Some(nonce) = nonce {
+            self.free_nonce_accounts.borrow_mut().push(nonce);
+        }
+        if let Ok(signature) = &result {
+            self.record_confirmed(signature).map_err(manifest_error)?;
+        }
+        result
+    }
+
+    // The fee payer, `signers` and the rent payer if `instructions` take its signature,
+    // as `CreateScratch` and the creation of a VK account do.
+    fn signers<'s>(
+        &'s self,
+        instructions: &[Instruction],
+        signers: &[&'s dyn Signer],
+    ) -> Vec<&'s dyn Signer> {
+        let mut all = [&[&*self.payer], signers].concat();
+        if let Some(rent_payer) = &self.rent_payer {
+            let key = rent_payer.pubkey();
+            let signs = instructions
+                .iter()
+                .flat_map(|instruction| instruction.accounts.iter())
+                .any(|account| account.is_signer && account.pubkey == key);
+            if signs && !all.iter().any(|signer| signer.pubkey() == key) {
+                all.push(rent_payer);
+            }
+        }
+        all
+    }
+
+    // See `send_instructions`. With durable nonce account `nonce`, the transaction
+    // advances it first and is signed over its nonce.
+    async fn send_retrying(
+        &self,
+        instructions: &[Instruction],
+        signers: &[&dyn Signer],
+        nonce: Option<Pubkey>,
+    ) -> RpcResult<Signature> {
+        let signers = self.signers(instructions, signers);
+        let advance = nonce.map(|nonce| {
+            system_instruction::advance_nonce_account(&nonce, &self.payer.pubkey())
+        });
+        let with_advance = advance
+            .into_iter()
+            .chain(instructions.iter().cloned())
+            .collect::<Vec<_>>();
+        let mut transaction = None;
+        let mut attempt = 0;
+        loop {
+            let result = match &transaction {
+                Some(transaction) => self.sender.send_and_confirm_transaction(transaction).await,
+                None => match self.recent_blockhash(nonce).await {
+                    Ok(recent_hash) => {
+                        let signed = Transaction::new_signed_with_payer(
+                            &with_advance,
+                            Some(&self.payer.pubkey()),
+                            &signers,
+                            recent_hash,
+                        );
+                        self.record_signed(instructions, &signed)
+                            .map_err(manifest_error)?;
+                        let result = self.sender.send_and_confirm_transaction(&signed).await;
+                        transaction = Some(signed);
+                        result
+                    }
+                    Err(err) => Err(err),
+                },
+            };
+            let err = match result {
+                // an earlier send of this very transaction landed
+                Err(err) if attempt > 0 && is_already_processed(&err) => {
+                    return Ok(transaction.unwrap().signatures[0])
+                }
+                Err(err) if is_retryable(&err) && attempt < self.retries => err,
+                result => return result,
+            };
+            if is_blockhash_expired(&err) {
+                if let Some(landed) = self.landed(transaction.as_ref()).await {
+                    return landed;
+                }
+                // a durable nonce transaction stays valid until its nonce advances
+                if nonce.is_none() || is_nonce_advanced(&err) {
+                    transaction = None;
+                }
+            }
+            let delay = self.retry_backoff * 2u32.pow(attempt);
+            attempt += 1;
+            warn!(
+                "transaction failed ({}), retrying in {:?} ({}/{})",
+                err, delay, attempt, self.retries
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    // The blockhash to sign over: the latest one, or the nonce stored in `nonce`.
+    async fn recent_blockhash(&self, nonce: Option<Pubkey>) -> RpcResult<Hash> {
+        let nonce = match nonce {
+            Some(nonce) => nonce,
+            None => return self.sender.get_latest_blockhash().await,
+        };
+        let account = self.sender.get_account(&nonce).await?;
+        match nonce_utils::data_from_account(&account) {
+            Ok(data) => Ok(data.blockhash()),
+            Err(err) => {
+                let msg = format!("nonce account {}: {}", nonce, err);
+                Err(ClientErrorKind::Custom(msg).into())
+            }
+        }
+    }
+
+    // The outcome of `transaction` if it landed after all.
+    async fn landed(&self, transaction: Option<&Transaction>) -> Option<RpcResult<Signature>> {
+        let signature = transaction?.signatures[0];
+        match self.sender.get_signature_status(&signature).await {
+            Ok(Some(Ok(()))) => Some(Ok(signature)),
+            Ok(Some(Err(err))) => Some(Err(err.into())),
+            _ => None,
+        }
+    }
+}
+
+// Failures that may pass on a resend: the transaction's blockhash, the connection to
+// the node or the node's health, contention for its accounts, or a full block. Anything
+// else, such as a failing instruction or missing funds, would fail the same way again.
+fn is_retryable(err: &RpcClientError) -> bool {
+    if is_blockhash_expired(err) {
+        return true;
+    }
+    match err.kind() {
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => true,
+        ClientErrorKind::RpcError(RpcError::RpcRequestError(_)) => true,
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. })
+            if *code == JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY =>
+        {
+            true
+        }
+        _ => matches!(
+            err.get_transaction_error(),
+            Some(TransactionError::AccountInUse)
+                | Some(TransactionError::WouldExceedMaxBlockCostLimit)
+                | Some(TransactionError::WouldExceedMaxAccountCostLimit)
+                | Some(TransactionError::ClusterMaintenance)
+        ),
+    }
+}
+
+// The transaction ran out of compute units.
+fn is_budget_exceeded(err: &RpcClientError) -> bool {
+    matches!(
+        err.get_transaction_error(),
+        Some(TransactionError::InstructionError(
+            _,
+            InstructionError::ComputationalBudgetExceeded
+        )) | Some(TransactionError::InstructionError(
+            _,
+            InstructionError::ProgramFailedToComplete
+        ))
+    )
+}
+
+// A durable nonce transaction whose nonce is no longer the one stored, so it can never
+// land, unlike one merely not confirmed yet.
+fn is_nonce_advanced(err: &RpcClientError) -> bool {
+    matches!(
+        err.get_transaction_error(),
+        Some(TransactionError::BlockhashNotFound)
+    )
+}
+
+// The node turned a transaction down rather than forwarding it, failing its
+// simulation, say, so it cannot land.
+fn is_rejected(err: &RpcClientError) -> bool {
+    matches!(
+        err.kind(),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { .. })
+    )
+}
+
+fn is_already_processed(err: &RpcClientError) -> bool {
+    matches!(
+        err.get_transaction_error(),
+        Some(TransactionError::AlreadyProcessed)
+    )
+}
+
+// Failures caused only by the transaction's blockhash: rejected as unknown up front, or
+// not confirmed before the blockhash stopped being valid.
+fn is_blockhash_expired(err: &RpcClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::RpcError(RpcError::ForUser(msg)) => {
+            msg.starts_with("unable to confirm transaction")
+        }
+        _ => matches!(
+            err.get_transaction_error(),
+            Some(TransactionError::BlockhashNotFound)
+        ),
+    }
+}
+
+// The compute budget instructions, at `budget` in the transaction, come before all of
+// the program's, so a failure at one of them (or an unknown program) means the cluster
+// doesn't support them.
+fn is_compute_budget_rejected(err: &RpcClientError, budget: Range<usize>) -> bool {
+    match err.get_transaction_error() {
+        Some(TransactionError::InstructionError(index, _)) => budget.contains(&(index as usize)),
+        Some(TransactionError::ProgramAccountNotFound)
+        | Some(TransactionError::InvalidProgramForExecution) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use solana_client::client_error::reqwest;
+    use solana_client::rpc_custom_error::JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE;
+    use solana_client::rpc_request::RpcResponseErrorData;
+    use solana_client::rpc_response::RpcSimulateTransactionResult;
+    use solana_sdk::signer::SignerError;
+
+    use super::*;
+
+    // A transaction failing its simulation on the node
+    fn preflight(err: TransactionError) -> RpcClientError {
+        RpcError::RpcResponseError {
+            code: JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE,
+            message: "Transaction simulation failed".to_string(),
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(
+                RpcSimulateTransactionResult {
+                    err: Some(err),
+                    logs: None,
+                    accounts: None,
+                    units_consumed: None,
+                    return_data: None,
+                },
+            ),
+        }
+        .into()
+    }
+
+    fn instruction(err: InstructionError) -> TransactionError {
+        TransactionError::InstructionError(2, err)
+    }
+
+    // Every class of failure a send can end in, and whether a resend may pass.
+    #[test]
+    fn test_retry_classification() {
+        let reqwest = reqwest::Client::new().get("not a url").build().unwrap_err();
+        let cases: Vec<(&str, RpcClientError, bool)> = vec![
+            // the connection or the node
+            (
+                "io",
+                io::Error::new(io::ErrorKind::ConnectionReset, "reset").into(),
+                true,
+            ),
+            ("http", reqwest.into(), true),
+            (
+                "request",
+                RpcError::RpcRequestError("timed out".to_string()).into(),
+                true,
+            ),
+            (
+                "node unhealthy",
+                RpcError::RpcResponseError {
+                    code: JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+                    message: "Node is behind".to_string(),
+                    data: RpcResponseErrorData::NodeUnhealthy {
+                        num_slots_behind: Some(200),
+                    },
+                }
+                .into(),
+                true,
+            ),
+            (
+                "other response",
+                RpcError::RpcResponseError {
+                    code: -32602,
+                    message: "Invalid params".to_string(),
+                    data: RpcResponseErrorData::Empty,
+                }
+                .into(),
+                false,
+            ),
+            // the blockhash
+            (
+                "not confirmed in time",
+                RpcError::ForUser("unable to confirm transaction. This can happen ...".to_string())
+                    .into(),
+                true,
+            ),
+            (
+                "other for user",
+                RpcError::ForUser("no such account".to_string()).into(),
+                false,
+            ),
+            (
+                "blockhash not found",
+                TransactionError::BlockhashNotFound.into(),
+                true,
+            ),
+            (
+                "blockhash not found in preflight",
+                preflight(TransactionError::BlockhashNotFound),
+                true,
+            ),
+            // contention and full blocks, sent or in preflight
+            (
+                "account in use",
+                TransactionError::AccountInUse.into(),
+                true,
+            ),
+            (
+                "account in use in preflight",
+                preflight(TransactionError::AccountInUse),
+                true,
+            ),
+            (
+                "block cost",
+                TransactionError::WouldExceedMaxBlockCostLimit.into(),
+                true,
+            ),
+            (
+                "account cost",
+                TransactionError::WouldExceedMaxAccountCostLimit.into(),
+                true,
+            ),
+            (
+                "maintenance",
+                TransactionError::ClusterMaintenance.into(),
+                true,
+            ),
+            // what a resend fails the same way
+            (
+                "funds",
+                TransactionError::InsufficientFundsForFee.into(),
+                false,
+            ),
+            (
+                "funds in preflight",
+                preflight(TransactionError::InsufficientFundsForFee),
+                false,
+            ),
+            (
+                "already processed",
+                TransactionError::AlreadyProcessed.into(),
+                false,
+            ),
+            (
+                "program error",
+                instruction(InstructionError::Custom(1)).into(),
+                false,
+            ),
+            (
+                "program error in preflight",
+                preflight(instruction(InstructionError::Custom(1))),
+                false,
+            ),
+            (
+                "budget",
+                instruction(InstructionError::ComputationalBudgetExceeded).into(),
+                false,
+            ),
+            ("signer", SignerError::NoDeviceFound.into(), false),
+            (
+                "json",
+                serde_json::from_str::<u8>("x").unwrap_err().into(),
+                false,
+            ),
+            (
+                "custom",
+                ClientErrorKind::Custom("manifest".to_string()).into(),
+                false,
+            ),
+        ];
+        for (name, err, retryable) in cases.iter() {
+            assert_eq!(is_retryable(err), *retryable, "{}", name);
+        }
+    }
+
+    // The failures sending handles other than by resending or giving up.
+    #[test]
+    fn test_other_classifications() {
+        let budget = instruction(InstructionError::ComputationalBudgetExceeded);
+        assert!(is_budget_exceeded(&budget.clone().into()));
+        assert!(is_budget_exceeded(&preflight(budget)));
+        assert!(is_budget_exceeded(
+            &instruction(InstructionError::ProgramFailedToComplete).into()
+        ));
+        assert!(!is_budget_exceeded(
+            &instruction(InstructionError::Custom(1)).into()
+        ));
+
+        assert!(is_nonce_advanced(
+            &TransactionError::BlockhashNotFound.into()
+        ));
+        assert!(!is_nonce_advanced(&TransactionError::AccountInUse.into()));
+        assert!(is_already_processed(&preflight(
+            TransactionError::AlreadyProcessed
+        )));
+        assert!(!is_already_processed(
+            &TransactionError::BlockhashNotFound.into()
+        ));
+
+        assert!(is_rejected(&preflight(TransactionError::AccountInUse)));
+        assert!(!is_rejected(&TransactionError::AccountInUse.into()));
+
+        // the compute budget instructions are the first two
+        let budget = 0..2;
+        let at = |index| {
+            TransactionError::InstructionError(index, InstructionError::InvalidInstructionData)
+        };
+        assert!(is_compute_budget_rejected(&at(1).into(), budget.clone()));
+        assert!(!is_compute_budget_rejected(&at(2).into(), budget.clone()));
+        assert!(is_compute_budget_rejected(
+            &TransactionError::ProgramAccountNotFound.into(),
+            budget.clone()
+        ));
+        assert!(is_compute_budget_rejected(
+            &TransactionError::InvalidProgramForExecution.into(),
+            budget.clone()
+        ));
+        assert!(!is_compute_budget_rejected(
+            &TransactionError::AccountInUse.into(),
+            budget
+        ));
     }
 }
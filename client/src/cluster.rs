@@ -0,0 +1,67 @@
+use std::str::FromStr;
+
+use solana_sdk::commitment_config::CommitmentConfig;
+
+// The clusters `--cluster` names: a local test validator and the public clusters, and
+// what the client does differently on each.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cluster {
+    Localnet,
+    Devnet,
+    Testnet,
+    MainnetBeta,
+}
+
+// The genesis hash of mainnet-beta, which tells it apart behind any RPC URL
+pub const MAINNET_BETA_GENESIS_HASH: &str = "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d";
+
+impl Cluster {
+    pub fn url(self) -> &'static str {
+        match self {
+            Cluster::Localnet => "http://127.0.0.1:8899",
+            Cluster::Devnet => "https://api.devnet.solana.com",
+            Cluster::Testnet => "https://api.testnet.solana.com",
+            Cluster::MainnetBeta => "https://api.mainnet-beta.solana.com",
+        }
+    }
+
+    // The cluster at `url`, if it is a local validator or a public cluster's own RPC
+    // endpoint.
+    pub fn from_url(url: &str) -> Option<Cluster> {
+        let host = url.split("://").last()?.split(&[':', '/'][..]).next()?;
+        match host {
+            "localhost" | "127.0.0.1" | "0.0.0.0" => Some(Cluster::Localnet),
+            "api.devnet.solana.com" => Some(Cluster::Devnet),
+            "api.testnet.solana.com" => Some(Cluster::Testnet),
+            "api.mainnet-beta.solana.com" => Some(Cluster::MainnetBeta),
+            _ => None,
+        }
+    }
+
+    // The commitment transactions are confirmed at. Every step builds on the scratch
+    // accounts the previous ones wrote, so a step rolled back with a fork sinks the
+    // whole verification: a local validator has no forks and goes with processed,
+    // devnet and testnet with confirmed, and mainnet-beta, where a lost verification
+    // costs real fees, waits for finalized.
+    pub fn commitment(self) -> CommitmentConfig {
+        match self {
+            Cluster::Localnet => CommitmentConfig::processed(),
+            Cluster::Devnet | Cluster::Testnet => CommitmentConfig::confirmed(),
+            Cluster::MainnetBeta => CommitmentConfig::finalized(),
+        }
+    }
+}
+
+impl FromStr for Cluster {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Cluster, String> {
+        match name {
+            "localnet" => Ok(Cluster::Localnet),
+            "devnet" => Ok(Cluster::Devnet),
+            "testnet" => Ok(Cluster::Testnet),
+            "mainnet-beta" => Ok(Cluster::MainnetBeta),
+            _ => Err(format!("unknown cluster {}", name)),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::fs;
+
+use serde_json::{json, Value};
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::error::{ClientError, ClientResult};
+use crate::files::io_error;
+
+// The transactions of a dry run (see `Client::dry_run`) as JSON, for inspecting,
+// auditing or sending them from other infrastructure:
+//
+//   { "payer": ..., "transactions": [ { "instructions": [ { "program_id": ...,
+//     "accounts": [ { "pubkey": ..., "is_signer": ..., "is_writable": ... } ],
+//     "data": <hex> } ] } ] }
+//
+// Addresses are base58. `payer` pays the fees of every transaction and signs it; no
+// other account signs.
+pub fn to_json(payer: &Pubkey, transactions: &[Vec<Instruction>]) -> Value {
+    let transactions = transactions
+        .iter()
+        .map(|instructions| {
+            let instructions = instructions
+                .iter()
+                .map(instruction_json)
+                .collect::<Vec<_>>();
+            json!({ "instructions": instructions })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "payer": payer.to_string(),
+        "transactions": transactions,
+    })
+}
+
+// Write the transactions to `path` as `to_json` has them, pretty-printed.
+pub fn write(path: &str, payer: &Pubkey, transactions: &[Vec<Instruction>]) -> ClientResult<()> {
+    let json =
+        serde_json::to_string_pretty(&to_json(payer, transactions)).map_err(ClientError::input)?;
+    fs::write(path, json).map_err(io_error(path))
+}
+
+fn instruction_json(instruction: &Instruction) -> Value {
+    let accounts = instruction
+        .accounts
+        .iter()
+        .map(|account| {
+            json!({
+                "pubkey": account.pubkey.to_string(),
+                "is_signer": account.is_signer,
+                "is_writable": account.is_writable,
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "program_id": instruction.program_id.to_string(),
+        "accounts": accounts,
+        "data": instruction.data.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+    })
+}
@@ -0,0 +1,168 @@
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+use solana_sdk::signature::read_keypair_file;
+
+use crate::client::{Client, COMPUTE_UNITS};
+use crate::error::{ClientError, ClientResult};
+use crate::files::read_step_costs;
+
+// The verification options the `client` binary takes from environment variables, see
+// the README, for other front ends to take the same way. A flag is on when its
+// variable is set at all; a value that does not parse is an `Input` error.
+impl Client {
+    // Set the options of the environment that need no cluster: the rent payer and the
+    // proving key, retries, the VK account, the shape of the steps and what the final
+    // step records.
+    pub fn configure_from_env(&mut self) -> ClientResult<()> {
+        // Optionally let a separate account pay rent for the verification accounts
+        if let Ok(path) = env::var("RENT_PAYER_KEYPAIR") {
+            let rent_payer = read_keypair_file(&path).map_err(|err| {
+                ClientError::Input(format!("invalid rent payer keypair {}: {}", path, err))
+            })?;
+            self.set_rent_payer(rent_payer);
+        }
+
+        // Optionally prove with a pre-generated proving key, e.g. from `setup` (arkworks
+        // serialization)
+        if let Ok(path) = env::var("PROVING_KEY") {
+            let pk = circuit::load_proving_key(&path);
+            self.set_proving_key(pk.map_err(|error| ClientError::Io { path, error })?);
+        }
+
+        // Optionally prove the demo circuit with N public inputs, under the demo setup's
+        // key for that count; the program needs its verifying key (`upload-vk`,
+        // STATELESS_VK)
+        if let Some(count) = env_value("NUM_PUBLIC_INPUTS", "public input count")? {
+            let pk = circuit::demo_key(count)
+                .map_err(|err| ClientError::Input(format!("setup failed: {}", err)))?;
+            self.set_proving_key(pk);
+        }
+
+        // Resend a failed transaction up to RETRIES times, waiting RETRY_BACKOFF_MS
+        // before the first resend and twice as long before each later one
+        if let Some(retries) = env_value("RETRIES", "retry count")? {
+            self.set_retries(retries);
+        }
+        if let Some(ms) = env_value("RETRY_BACKOFF_MS", "retry backoff")? {
+            self.set_retry_backoff(Duration::from_millis(ms));
+        }
+
+        // Read the verifying key from a VK account uploaded with `upload-vk`
+        if let Some(vk) = env_value("VK_ACCOUNT", "vk account")? {
+            self.set_vk_account(vk);
+        }
+
+        // Fold the raw public inputs on-chain against the verifying key
+        if env::var("ONCHAIN_INPUTS").is_ok() {
+            self.enable_onchain_inputs();
+        }
+
+        // Compute the gamma and delta tables of uploaded keys on-chain
+        if env::var("ONCHAIN_G2").is_ok() {
+            self.enable_onchain_g2();
+        }
+
+        // Carry the verifying key in the instruction data instead of using the baked one
+        if env::var("STATELESS_VK").is_ok() {
+            self.enable_inline_vk();
+        }
+
+        // Verify in one instruction over the alt_bn128 syscall where the cluster
+        // supports it
+        if env::var("ALT_BN128").is_ok() {
+            self.enable_alt_bn128();
+        }
+
+        // Send the proof's points compressed
+        if env::var("COMPRESSED_POINTS").is_ok() {
+            self.enable_compressed_points();
+        }
+
+        // Advance the gamma and delta Miller loops in the same instructions
+        if env::var("FUSED_MILLER_LOOPS").is_ok() {
+            self.enable_fused_miller_loops();
+        }
+
+        // Request the compute units simulating each instruction tag once says a
+        // transaction needs, plus a margin
+        if env::var("PREFLIGHT").is_ok() {
+            self.enable_preflight();
+        }
+
+        // Pack steps into transactions of CU_TARGET compute units by the step costs
+        // `calibrate` wrote to STEP_COSTS
+        if let Ok(path) = env::var("STEP_COSTS") {
+            let target = env_value("CU_TARGET", "compute unit target")?.unwrap_or(COMPUTE_UNITS);
+            self.set_step_costs(read_step_costs(&path)?, target);
+        }
+
+        // Run MILLER_LOOP_STEPS gamma and delta Miller-loop steps per instruction
+        if let Some(steps) = env_value("MILLER_LOOP_STEPS", "Miller-loop step count")? {
+            self.set_miller_loop_steps(steps);
+        }
+
+        // Run NAF_DIGITS digits per exponentiation-by-x step of the final exponentiation
+        if let Some(digits) = env_value("NAF_DIGITS", "NAF digit count")? {
+            self.set_naf_digits(digits);
+        }
+
+        // Spend the nullifier of the public inputs on a successful verification
+        if env::var("NULLIFIERS").is_ok() {
+            self.enable_nullifiers();
+        }
+
+        // Create a receipt for every statement verified
+        if env::var("RECEIPTS").is_ok() {
+            self.enable_receipts();
+        }
+        Ok(())
+    }
+
+    // Set the options of the environment that read or create accounts on the cluster,
+    // once the payer is established: the session's telemetry, session and result
+    // accounts, the key registered under VK_ID, and the result log of RESULT_LOG
+    // records, created if the payer has none.
+    pub fn open_env_accounts(&mut self) -> ClientResult<()> {
+        // Collect on-chain compute telemetry (program built with `--features telemetry`)
+        if env::var("TELEMETRY").is_ok() {
+            self.enable_telemetry()?;
+        }
+
+        // Reject steps from another client driving the same accounts at the same time
+        if env::var("SESSION_GUARD").is_ok() {
+            self.enable_session_guard()?;
+        }
+
+        // Verify against the key registered under an id in the VK registry
+        if let Some(id) = env_value("VK_ID", "vk id")? {
+            self.set_registered_vk(id)?;
+        }
+
+        // Write the outcome of each verification to a result account
+        if env::var("RESULT_ACCOUNT").is_ok() {
+            self.enable_result_account()?;
+        }
+
+        // Record results in an append-only log account of RESULT_LOG records
+        if let Some(capacity) = env_value("RESULT_LOG", "result log capacity")? {
+            self.enable_result_log(capacity)?;
+        }
+        Ok(())
+    }
+}
+
+// The value of environment variable `name` parsed, `None` when it is not set.
+pub fn env_value<T: FromStr>(name: &str, what: &str) -> ClientResult<Option<T>> {
+    match env::var(name) {
+        Ok(value) => match value.parse() {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => Err(ClientError::Input(format!(
+                "invalid {} in {}: {}",
+                what, name, value
+            ))),
+        },
+        Err(_) => Ok(None),
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt::Display;
+use std::io;
+use std::time::Duration;
+
+use contract::VerifierError;
+use solana_client::client_error::ClientError as RpcClientError;
+use solana_sdk::decode_error::DecodeError;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::TransactionError;
+use thiserror::Error;
+
+// What failed talking to the cluster or running a verification. Transaction failures
+// are sorted by what the user can do about them, see `hint`.
+#[derive(Debug, Error)]
+pub enum ClientError {
+    // the node could not be reached, or turned the request down; boxed, as it is the
+    // largest by far
+    #[error("RPC request failed: {0}")]
+    Rpc(Box<RpcClientError>),
+    // the payer, or the rent payer, cannot pay for a transaction or an account
+    #[error("insufficient funds: {0}")]
+    InsufficientFunds(String),
+    // an account holds something else than the client expects at its address
+    #[error("account {account} {reason}")]
+    AccountMismatch { account: Pubkey, reason: String },
+    // an instruction failed on chain, with a verifier error (see `contract::VerifierError`)
+    // or a built-in one
+    #[error("instruction {index} failed: {}", describe(.error))]
+    Program { index: u8, error: InstructionError },
+    // the cluster turned the transaction down as a whole
+    #[error("transaction failed: {0}")]
+    Transaction(TransactionError),
+    // a proof, key or other input the client was given does not encode or prove
+    #[error("invalid input: {0}")]
+    Input(String),
+    #[error("cannot access {path}: {error}")]
+    Io { path: String, error: io::Error },
+    // a `VerifierClient` was asked to verify before it had a session to verify in
+    #[error("no session, create one before submitting proofs")]
+    NoSession,
+    // no result of the proof submitted appeared in the session's result account in time
+    #[error("no verification result after {0:?}")]
+    Timeout(Duration),
+}
+
+pub type ClientResult<T> = Result<T, ClientError>;
+
+impl From<RpcClientError> for ClientError {
+    fn from(err: RpcClientError) -> ClientError {
+        match err.get_transaction_error() {
+            Some(TransactionError::InstructionError(index, error)) => {
+                ClientError::Program { index, error }
+            }
+            Some(TransactionError::InsufficientFundsForFee) => ClientError::InsufficientFunds(
+                "the fee payer cannot pay the transaction fee".to_string(),
+            ),
+            Some(TransactionError::InsufficientFundsForRent { account_index }) => {
+                ClientError::InsufficientFunds(format!(
+                    "account {} of the transaction would not be rent exempt",
+                    account_index
+                ))
+            }
+            Some(error) => ClientError::Transaction(error),
+            None => ClientError::Rpc(Box::new(err)),
+        }
+    }
+}
+
+impl ClientError {
+    // An input the client cannot encode or prove, as `map_err(ClientError::input)`.
+    pub(crate) fn input(err: impl Display) -> ClientError {
+        ClientError::Input(err.to_string())
+    }
+
+    // The verifier error an instruction failed with, if it did.
+    pub fn verifier_error(&self) -> Option<VerifierError> {
+        match self {
+            ClientError::Program {
+                error: InstructionError::Custom(code),
+                ..
+            } => verifier_error(*code),
+            _ => None,
+        }
+    }
+
+    // What to do about the failure, for the client binary to print under it.
+    pub fn hint(&self) -> Option<&'static str> {
+        if let Some(error) = self.verifier_error() {
+            return match error {
+                VerifierError::SessionConflict => Some(
+                    "another client drives the same session; wait for it or pick another \
+                     --session",
+                ),
+                VerifierError::InvalidProof => {
+                    Some("the proof does not verify under the verifying key in use")
+                }
+                VerifierError::InvalidAccumulator | VerifierError::InvalidAccountHeader => {
+                    Some("reset the session's accounts with `client close --reset`, or close them")
+                }
+                VerifierError::NullifierUsed => Some("the nullifier has been spent before"),
+                _ => None,
+            };
+        }
+        match self {
+            ClientError::Rpc(_) => Some("check --url and that the node is up and healthy"),
+            ClientError::InsufficientFunds(_) => {
+                Some("fund the payer, with `solana airdrop` on a test cluster")
+            }
+            ClientError::AccountMismatch { .. } => Some(
+                "check --program-id and --session, or close the session's accounts with \
+                 `client close`",
+            ),
+            ClientError::Program {
+                error: InstructionError::IncorrectProgramId,
+                ..
+            }
+            | ClientError::Transaction(TransactionError::ProgramAccountNotFound)
+            | ClientError::Transaction(TransactionError::InvalidProgramForExecution) => {
+                Some("deploy the program with `client deploy`, or pass its --program-id")
+            }
+            ClientError::Transaction(TransactionError::BlockhashNotFound) => {
+                Some("the transaction expired before it landed; run the command again")
+            }
+            ClientError::Timeout(_) => {
+                Some("the result may still land; wait longer, or check with `client status`")
+            }
+            _ => None,
+        }
+    }
+}
+
+fn verifier_error(code: u32) -> Option<VerifierError> {
+    VerifierError::decode_custom_error_to_enum(code)
+}
+
+// An instruction error, named after the verifier error it carries if any.
+fn describe(error: &InstructionError) -> String {
+    match error {
+        InstructionError::Custom(code) => match verifier_error(*code) {
+            Some(error) => format!("{} ({:?})", error, error),
+            None => error.to_string(),
+        },
+        _ => error.to_string(),
+    }
+}
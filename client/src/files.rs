@@ -0,0 +1,156 @@
+use std::fs::{self, File};
+use std::str::FromStr;
+
+use ark_bn254::{Bn254, Fr};
+use ark_groth16::{Proof, VerifyingKey};
+use ark_serialize::CanonicalDeserialize;
+use circuit::{normalize_gamma_abc, GammaAbcLayout, VkReport};
+use contract::plan::StepCosts;
+
+use crate::error::{ClientError, ClientResult};
+use crate::{arkworks, snarkjs};
+
+// The files of other provers the client verifies from, in whichever format they come,
+// and the step costs `calibrate` writes, each read into what `Client` takes. Failures
+// are `Io` for a file that does not open and `Input` for one that does not parse.
+
+// A snarkjs `verification_key.json`, the key of a snarkjs proving key (`.zkey`), or
+// else an arkworks `VerifyingKey<Bn254>` written with `serialize_uncompressed`, its
+// points checked to be on the curve and in the prime-order subgroup.
+pub fn read_vk_file(path: &str) -> ClientResult<VerifyingKey<Bn254>> {
+    if path.ends_with(".json") {
+        let json = fs::read_to_string(path).map_err(io_error(path))?;
+        return snarkjs::read_verifying_key(&json).map_err(invalid_vk);
+    }
+    if path.ends_with(".zkey") {
+        return read_zkey_vk(path);
+    }
+    let file = File::open(path).map_err(io_error(path))?;
+    VerifyingKey::<Bn254>::deserialize_uncompressed(file).map_err(invalid_vk)
+}
+
+// An arkworks verifying key, compressed or not, or the key of a snarkjs `.zkey`, its IC
+// points in `layout`.
+pub fn read_vk(path: &str, layout: GammaAbcLayout) -> ClientResult<VerifyingKey<Bn254>> {
+    let mut vk = if path.ends_with(".zkey") {
+        read_zkey_vk(path)?
+    } else {
+        let vk = fs::read(path).map_err(io_error(path))?;
+        arkworks::read_verifying_key(&vk).map_err(invalid_vk)?
+    };
+    normalize_gamma_abc(&mut vk, layout);
+    Ok(vk)
+}
+
+pub fn read_zkey_vk(path: &str) -> ClientResult<VerifyingKey<Bn254>> {
+    let zkey = fs::read(path).map_err(io_error(path))?;
+    circuit::read_zkey_verifying_key(&zkey)
+        .map_err(|err| ClientError::Input(format!("invalid proving key: {}", err)))
+}
+
+// A verifying key file as `read_vk_file` reads an arkworks one, but without checks, so
+// that `validate_vk` can name the offending point; the key and what it says about it.
+pub fn validate_vk_file(path: &str) -> ClientResult<(VerifyingKey<Bn254>, VkReport)> {
+    let file = File::open(path).map_err(io_error(path))?;
+    let vk = VerifyingKey::<Bn254>::deserialize_unchecked(file).map_err(|err| {
+        ClientError::Input(format!("invalid vk: cannot deserialize ({})", err))
+    })?;
+    let report = circuit::validate_vk(&vk)
+        .map_err(|err| ClientError::Input(format!("invalid vk: {}", err)))?;
+    Ok((vk, report))
+}
+
+// A gnark verifying key and proof, `WriteTo` or `WriteRawTo`.
+pub fn read_gnark_files(
+    vk: &str,
+    proof: &str,
+    layout: GammaAbcLayout,
+) -> ClientResult<(VerifyingKey<Bn254>, Proof<Bn254>)> {
+    let vk_bytes = fs::read(vk).map_err(io_error(vk))?;
+    let mut vk = circuit::read_gnark_verifying_key(&vk_bytes).map_err(invalid_vk)?;
+    normalize_gamma_abc(&mut vk, layout);
+    let proof_bytes = fs::read(proof).map_err(io_error(proof))?;
+    let proof = circuit::read_gnark_proof(&proof_bytes)
+        .map_err(|err| ClientError::Input(format!("invalid proof: {}", err)))?;
+    Ok((vk, proof))
+}
+
+// The files of a proof in `format`: by default those of a snarkjs proof if the proof
+// file name ends in `.json`, and of an arkworks one otherwise. A gnark proof comes with
+// its public inputs as decimal values in a text file. The key's IC points are in
+// `layout`.
+pub fn read_proof_files(
+    format: Option<&str>,
+    vk: &str,
+    proof: &str,
+    public: &str,
+    layout: GammaAbcLayout,
+) -> ClientResult<(VerifyingKey<Bn254>, Proof<Bn254>, Vec<Fr>)> {
+    let format = format.unwrap_or(if proof.ends_with(".json") {
+        "snarkjs"
+    } else {
+        "arkworks"
+    });
+    match format {
+        "snarkjs" => snarkjs::read_files(vk, proof, public, layout)
+            .map_err(|err| ClientError::Input(format!("invalid snarkjs files: {}", err))),
+        "gnark" => {
+            let (vk, proof) = read_gnark_files(vk, proof, layout)?;
+            let public_inputs = fs::read_to_string(public)
+                .map_err(io_error(public))?
+                .split_whitespace()
+                .map(public_input)
+                .collect::<ClientResult<_>>()?;
+            Ok((vk, proof, public_inputs))
+        }
+        _ => arkworks::read_files(vk, proof, public, layout)
+            .map_err(|err| ClientError::Input(format!("invalid arkworks files: {}", err))),
+    }
+}
+
+// A public input in decimal.
+pub fn public_input(x: &str) -> ClientResult<Fr> {
+    Fr::from_str(x).map_err(|_| ClientError::Input(format!("invalid public input {}", x)))
+}
+
+// Step costs as `calibrate` writes them: one `<instruction tag> <compute units>` line
+// per measured tag.
+pub fn read_step_costs(path: &str) -> ClientResult<StepCosts> {
+    let text = fs::read_to_string(path).map_err(io_error(path))?;
+    let mut costs = StepCosts::new();
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let mut fields = line.split_whitespace().map(|field| field.parse().ok());
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(Some(tag)), Some(Some(units)), None) if tag < 32 => {
+                costs.set(tag as u8, units)
+            }
+            _ => {
+                return Err(ClientError::Input(format!(
+                    "invalid step cost line in {}: {}",
+                    path, line
+                )))
+            }
+        }
+    }
+    Ok(costs)
+}
+
+pub fn write_step_costs(path: &str, costs: &StepCosts) -> ClientResult<()> {
+    let text = costs
+        .costs()
+        .map(|(tag, units)| format!("{} {}\n", tag, units))
+        .collect::<String>();
+    fs::write(path, text).map_err(io_error(path))
+}
+
+// The error of a file at `path` that cannot be read or written.
+pub fn io_error(path: &str) -> impl FnOnce(std::io::Error) -> ClientError + '_ {
+    move |error| ClientError::Io {
+        path: path.to_string(),
+        error,
+    }
+}
+
+fn invalid_vk(err: impl std::fmt::Display) -> ClientError {
+    ClientError::Input(format!("invalid verifying key: {}", err))
+}
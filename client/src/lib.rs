@@ -0,0 +1,31 @@
+// The client of the on-chain Groth16 verifier as a library: `VerifierClient` for
+// verifying proofs from a dapp or service, and `Client`, which it drives, for
+// everything else the `client` binary does. What they do as they go, the accounts they
+// create and the retries they make say, goes to the `log` crate: progress at info
+// level, fallbacks at warn level.
+
+pub mod arkworks;
+pub mod batch;
+pub mod client;
+pub mod cluster;
+pub mod dry_run;
+pub mod env;
+pub mod error;
+pub mod files;
+pub mod manifest;
+pub mod report;
+pub mod signer;
+pub mod snarkjs;
+pub mod steps;
+pub mod verifier;
+
+pub use crate::client::{
+    check_proof, demo_pairs, read_signer, stand_in_vk, Client, Estimate, ProgressEvent,
+    ResultRecord, SessionPhase, SessionStatus, VerificationResult, COMPUTE_UNITS,
+};
+pub use crate::cluster::Cluster;
+pub use crate::error::{ClientError, ClientResult};
+pub use crate::report::Report;
+pub use crate::signer::CommandSigner;
+pub use crate::steps::{Callback, SessionKeys};
+pub use crate::verifier::VerifierClient;
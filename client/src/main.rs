@@ -1,23 +1,678 @@
-use crate::client::Client;
+#[macro_use]
+extern crate clap;
 
-mod client;
+use std::env;
+use std::fs;
+use std::process;
+use std::str::FromStr;
+
+use ark_bn254::{Bn254, Fr};
+use ark_ff::One;
+use ark_groth16::{Proof, VerifyingKey};
+use clap::ArgMatches;
+use indicatif::{ProgressBar, ProgressStyle};
+use rand::rngs::OsRng;
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::native_token::lamports_to_sol;
+use solana_sdk::pubkey::Pubkey;
+
+use groth16_sol_client::env::env_value;
+use groth16_sol_client::files::{self, io_error};
+use groth16_sol_client::{batch, dry_run};
+use groth16_sol_client::{
+    demo_pairs, snarkjs, stand_in_vk, Callback, Client, ClientError, ClientResult,
+    CommandSigner, ResultRecord,
+};
+
+use crate::cli::{app, ic_layout, manifest_path, value};
+
+mod bench;
+mod cli;
 
 fn main() {
-    // Establish a connection to the cluster
-    let mut client = Client::new();
+    cli::init_logger();
+    if let Err(err) = run() {
+        eprintln!("error: {}", err);
+        if let Some(hint) = err.hint() {
+            eprintln!("hint: {}", hint);
+        }
+        process::exit(1);
+    }
+}
+
+// Run the subcommand. Failures of the cluster or the program come back as a
+// `ClientError`, which `main` prints with what to do about it.
+fn run() -> ClientResult<()> {
+    let matches = app().get_matches();
+    // global arguments are given to the subcommand's matches too
+    let (command, args) = match matches.subcommand() {
+        (command, Some(args)) => (command, args),
+        (_, None) => ("verify", &matches),
+    };
+    if let "validate-vk" | "setup" | "gen-pvk" = command {
+        return run_offline(command, args);
+    }
+
+    let mut client = connect(command, args)?;
+
+    // Deploy the program with the upgradeable loader, before anything needs it
+    if command == "deploy" {
+        if let Some(path) = args.value_of("program") {
+            client.set_program_path(path);
+        }
+        if let Some(path) = args.value_of("buffer") {
+            client.set_buffer_keypair_path(path);
+        }
+        client.deploy()?;
+        println!("deployed program {}", client.program_id());
+        return Ok(());
+    }
+
+    configure(&mut client, command, args)?;
+    let bar = progress_bar(&mut client);
+    run_command(&mut client, command, args)?;
+    bar.finish_and_clear();
+
+    if let Some((steps, compute_units)) = client.read_telemetry()? {
+        println!(
+            "telemetry: {} steps, {} compute units consumed on-chain",
+            steps, compute_units
+        );
+    }
+    Ok(())
+}
+
+// The subcommands that need no cluster.
+fn run_offline(command: &str, args: &ArgMatches) -> ClientResult<()> {
+    match command {
+        // Check a verifying key file before building the program's constants from it
+        "validate-vk" => {
+            let (vk, report) = files::validate_vk_file(args.value_of("file").unwrap())?;
+            println!("vk is valid");
+            println!("num_public_inputs: {}", report.num_public_inputs);
+            println!("vk_hash: {}", to_hex(&report.vk_hash));
+            println!("fingerprint: {}", circuit::vk_fingerprint(&vk));
+        }
+        // Run the demo circuit's setup once, for NUM_PUBLIC_INPUTS public inputs (1 by
+        // default), and write its proving and verifying keys for PROVING_KEY and
+        // `upload-vk` to reuse
+        "setup" => {
+            let count = env_value("NUM_PUBLIC_INPUTS", "public input count")?.unwrap_or(1);
+            let pk = circuit::setup_with_inputs(&circuit::demo_constants(), count, &mut OsRng)
+                .map_err(|err| ClientError::Input(format!("setup failed: {}", err)))?;
+            let (pk_path, vk_path) = (args.value_of("pk").unwrap(), args.value_of("vk").unwrap());
+            circuit::save_keys(&pk, pk_path, vk_path).map_err(|error| ClientError::Io {
+                path: format!("{} or {}", pk_path, vk_path),
+                error,
+            })?;
+            println!("wrote the proving key to {} and the verifying key to {}", pk_path, vk_path);
+            println!("fingerprint: {}", circuit::vk_fingerprint(&pk.vk));
+        }
+        // Write the program's hardcoded verifying key, `contract/src/pvk.rs`, for another
+        // circuit
+        _ => {
+            let mut vk = files::read_vk_file(args.value_of("vk").unwrap())?;
+            circuit::normalize_gamma_abc(&mut vk, ic_layout(args));
+            circuit::validate_vk(&vk)
+                .map_err(|err| ClientError::Input(format!("invalid vk: {}", err)))?;
+            let source = circuit::pvk_source(&vk).map_err(ClientError::Input)?;
+            match args.value_of("output") {
+                Some(path) => fs::write(path, source).map_err(io_error(path))?,
+                None => print!("{}", source),
+            }
+        }
+    }
+    Ok(())
+}
+
+// Establish a connection to the cluster, the CLI config's (--config or SOLANA_CONFIG, or
+// the CLI's own) unless --url names one, paid for by its keypair unless --keypair or
+// PAYER_KEYPAIR names another.
+fn connect(command: &str, args: &ArgMatches) -> ClientResult<Client> {
+    let mut client = match args.value_of("config") {
+        Some(path) => Client::with_config_file(path)?,
+        None => Client::new(),
+    };
+    if let Some(cluster) = value(args, "cluster") {
+        client.set_cluster(cluster);
+    }
+    if let Some(url) = args.value_of("url") {
+        client.set_url(url);
+    }
+    // Confirm transactions at the cluster's commitment unless --commitment names another
+    if let Some(commitment) = value(args, "commitment") {
+        client.set_commitment(commitment);
+    }
+    // The payer may be a Ledger (usb://ledger) or anything else the Solana CLI signs
+    // with, or an external signing service reached through --signer-command or
+    // SIGNER_COMMAND
+    if let Some(path) = args.value_of("keypair") {
+        client.set_keypair_path(path)?;
+    }
+    if let Some(command) = args.value_of("signer_command") {
+        let signer = CommandSigner::new(command).map_err(|err| {
+            ClientError::Input(format!("cannot use the signer command: {}", err))
+        })?;
+        client.set_payer(signer);
+    }
+    // The program is the program keypair's (--program-keypair or PROGRAM_KEYPAIR, by
+    // default the one in target/deploy) unless --program-id or PROGRAM_ID names another
+    if let Some(path) = args.value_of("program_keypair") {
+        client.set_program_keypair_path(path);
+    }
+    if let Some(program_id) = value::<Pubkey>(args, "program_id") {
+        client.set_program_id(program_id);
+    }
+    // a dry run touches no RPC at all
+    if command != "dry-run" {
+        println!(
+            "connection established, version: {}",
+            client.get_version()?
+        );
+    }
+    Ok(client)
+}
+
+// Set the options of the command line, then those of the environment (see
+// `Client::configure_from_env`).
+fn configure(client: &mut Client, command: &str, args: &ArgMatches) -> ClientResult<()> {
+    // Derive the scratch accounts from another session id than 0 (--session or
+    // SESSION_ID)
+    client.set_session_id(value(args, "session").unwrap());
+
+    // Pay a priority fee on every transaction (--compute-unit-price or
+    // COMPUTE_UNIT_PRICE)
+    if let Some(price) = value(args, "compute_unit_price") {
+        client.set_compute_unit_price(price);
+    }
+
+    // Sign over the durable nonces of the payer's nonce accounts instead of recent
+    // blockhashes (--nonce-account or NONCE_ACCOUNTS, comma-separated)
+    if args.is_present("nonce_account") {
+        let accounts = values_t!(args, "nonce_account", Pubkey).unwrap_or_else(|err| err.exit());
+        client.set_nonce_accounts(accounts);
+    }
+
+    // Close the session's scratch accounts after a successful verification, unless
+    // --keep-accounts
+    if !args.is_present("keep_accounts") {
+        client.enable_cleanup();
+    }
+
+    client.configure_from_env()?;
+
+    // Determine who pays for fees, and check if the main program has been deployed;
+    // an estimate or a dry run sends nothing, so needs neither
+    if command != "estimate" && command != "dry-run" {
+        client.establish_payer()?;
+        client.check_program();
+        // Learn that transactions landed from signature subscriptions over the node's
+        // websocket (--ws or WEBSOCKET_URL, by default the RPC URL's), unless
+        // --no-websocket
+        if !args.is_present("no_websocket") {
+            if let Some(url) = args.value_of("ws") {
+                client.set_websocket_url(url);
+            }
+            client.enable_websocket();
+        }
+        // Send transactions straight to the leaders' TPUs over QUIC with --tpu (or TPU)
+        if args.is_present("tpu") {
+            client.enable_tpu();
+        }
+    }
+
+    if command == "dry-run" && env::var("VK_ID").is_ok() {
+        return Err(ClientError::Input(
+            "a dry run cannot look up VK_ID's key account, set VK_ACCOUNT instead".to_string(),
+        ));
+    }
+    client.open_env_accounts()
+}
+
+// Show how far the steps of a verification are on a progress bar, when stderr is a
+// terminal.
+fn progress_bar(client: &mut Client) -> ProgressBar {
+    let bar = ProgressBar::new(0);
+    if !bar.is_hidden() {
+        bar.set_style(
+            ProgressStyle::with_template("{prefix:>20} [{bar:40}] {pos}/{len} {msg}").unwrap(),
+        );
+        let bar = bar.clone();
+        client.set_progress(move |event| {
+            bar.set_prefix(event.phase);
+            bar.set_length(event.steps as u64);
+            bar.set_position(event.step as u64);
+            match event.units {
+                Some(units) => bar.set_message(format!("{} CU, {}", units, event.signature)),
+                None => bar.set_message(event.signature.to_string()),
+            }
+        });
+    }
+    bar
+}
+
+fn run_command(client: &mut Client, command: &str, args: &ArgMatches) -> ClientResult<()> {
+    match command {
+        // Benchmark N full verifications against the configured cluster
+        "bench" => {
+            let runs = value(args, "runs").unwrap();
+            println!("benchmarking {} verifications", runs);
+            bench::bench(client, runs)?.print();
+        }
+        // Verify N demo proofs as one batch, in up to --max-sessions (or MAX_SESSIONS)
+        // sessions at once
+        "batch" => {
+            let count = value(args, "count").unwrap();
+            if let Some(sessions) = value(args, "max_sessions") {
+                client.set_max_sessions(sessions);
+            }
+            let proofs = batch::demo_proofs(count)?;
+            println!("start verify {} proofs on chain", count);
+            client.verify_batch(&proofs)?;
+            println!("verify success!");
+        }
+        // Verify the demo proof in a single instruction
+        "complete" => {
+            println!("start verify a proof on chain in one instruction");
+            client.verify_complete()?;
+            println!("verify success! (vk {})", client.vk_fingerprint()?);
+        }
+        // Verify N demo proofs in a single instruction, combined into one pairing check
+        "combined" => {
+            let count = value(args, "count").unwrap();
+            println!("start verify {} proofs on chain in one instruction", count);
+            client.verify_combined(count)?;
+            println!("verify success! (vk {})", client.vk_fingerprint()?);
+        }
+        // Verify a circom + snarkjs proof in a single instruction against its verifying
+        // key
+        "snarkjs" => {
+            let (vk, proof, public_inputs) = snarkjs::read_files(
+                args.value_of("vk").unwrap(),
+                args.value_of("proof").unwrap(),
+                args.value_of("public").unwrap(),
+                ic_layout(args),
+            )
+            .map_err(|err| ClientError::Input(format!("invalid snarkjs files: {}", err)))?;
+            println!("start verify a snarkjs proof on chain in one instruction");
+            client.verify_external(vk, &proof, &public_inputs, args.is_present("force"))?;
+            println!("verify success! (vk {})", client.vk_fingerprint()?);
+        }
+        // Verify a gnark proof (`WriteTo` or `WriteRawTo`) of the given public inputs,
+        // decimal, likewise
+        "gnark" => {
+            let (vk, proof) = files::read_gnark_files(
+                args.value_of("vk").unwrap(),
+                args.value_of("proof").unwrap(),
+                ic_layout(args),
+            )?;
+            let public_inputs = args
+                .values_of("inputs")
+                .into_iter()
+                .flatten()
+                .map(files::public_input)
+                .collect::<ClientResult<Vec<_>>>()?;
+            println!("start verify a gnark proof on chain in one instruction");
+            client.verify_external(vk, &proof, &public_inputs, args.is_present("force"))?;
+            println!("verify success! (vk {})", client.vk_fingerprint()?);
+        }
+        // Print the public inputs and the folded input point for auditing
+        "audit" => {
+            let folded = client.folded_input()?;
+            println!("public inputs: {}", to_hex(&folded.public_input_bytes()));
+            println!("prepared input: {}", to_hex(&folded.prepared_input_bytes()));
+        }
+        // Verify off-chain and record only an attestation on-chain
+        "attest" => {
+            println!("verify a proof off-chain and record the attestation");
+            let valid = client.attest()?;
+            println!("attestation recorded, proof valid: {}", valid);
+        }
+        // Verify and, in the final transaction, call a target program with the
+        // demo's public inputs as instruction data and the given writable accounts
+        "callback" => {
+            let program_id = value(args, "program").unwrap();
+            let accounts = args
+                .values_of("accounts")
+                .into_iter()
+                .flatten()
+                .map(|key| match Pubkey::from_str(key) {
+                    Ok(key) => Ok(AccountMeta::new(key, false)),
+                    Err(_) => Err(ClientError::Input(format!("invalid account {}", key))),
+                })
+                .collect::<ClientResult<_>>()?;
+            let callback = Callback {
+                program_id,
+                accounts,
+                data: client.folded_input()?.public_input_bytes(),
+            };
+            println!("start verify a proof on chain, then call {}", program_id);
+            client.groth16_verify_and_call(&callback)?;
+            println!("verify success! (vk {})", client.vk_fingerprint()?);
+        }
+        // Upload a verifying key (arkworks serialization), or the one of the proving key
+        // in use, into a new VK account
+        "upload-vk" => {
+            let vk = vk_arg(client, args)?;
+            let vk_account = client.upload_vk(&vk)?;
+            println!("uploaded vk {} to {}", circuit::vk_fingerprint(&vk), vk_account);
+        }
+        // Create the program's VK registry, administered by the fee payer, who must be the
+        // program's upgrade authority
+        "init-registry" => {
+            let registry = client.init_registry()?;
+            println!("created vk registry {}", registry);
+        }
+        // Upload a verifying key (arkworks serialization), or the one of the proving key
+        // in use, and register it under an id, for good with IMMUTABLE_VK set; the fee
+        // payer must administer the registry
+        "register-vk" => {
+            let id = value(args, "id").unwrap();
+            let vk = vk_arg(client, args)?;
+            let vk_account = client.upload_vk(&vk)?;
+            let immutable = env::var("IMMUTABLE_VK").is_ok();
+            let entry = client.register_vk(id, vk_account, immutable)?;
+            println!(
+                "registered vk {} in {} as id {} ({})",
+                circuit::vk_fingerprint(&vk),
+                vk_account,
+                id,
+                entry
+            );
+        }
+        // Upload a verifying key likewise and point a registered id at it
+        "update-vk" => {
+            let id = value(args, "id").unwrap();
+            let vk = vk_arg(client, args)?;
+            let vk_account = client.upload_vk(&vk)?;
+            client.update_vk(id, vk_account)?;
+            println!(
+                "vk id {} now points at vk {} in {}",
+                id,
+                circuit::vk_fingerprint(&vk),
+                vk_account
+            );
+        }
+        // Freeze a registered id, so its key can never be swapped
+        "freeze-vk" => {
+            let id = value(args, "id").unwrap();
+            client.freeze_vk(id)?;
+            println!("vk id {} is immutable", id);
+        }
+        // Hand the VK registry over to another administrator
+        "set-registry-admin" => {
+            let admin = value(args, "admin").unwrap();
+            client.set_registry_admin(admin)?;
+            println!("vk registry administered by {}", admin);
+        }
+        // Create the scratch accounts of the current session, or reset those it has
+        "create-session" => {
+            let keys = client.create_session_accounts(client.session_id())?;
+            println!(
+                "session {}: gamma {}, delta {}, final {}, {} y accounts",
+                keys.session_id,
+                keys.gamma,
+                keys.delta,
+                keys.final_key,
+                keys.y.len()
+            );
+        }
+        // Create durable nonce accounts for --nonce-account, authorized to the payer
+        "create-nonce-accounts" => {
+            for nonce in client.create_nonce_accounts(value(args, "count").unwrap())? {
+                println!("created nonce account {}", nonce);
+            }
+        }
+        // List the scratch accounts of the current session that exist, and tell from
+        // their accumulators how far its verification has come
+        "status" => print_status(client)?,
+        // Close the scratch accounts of the current session and move their rent to the
+        // given address, or back to the payer; or with --reset, reset them to restart a
+        // verification that failed halfway
+        "close" => {
+            if args.is_present("reset") {
+                let reset = client.reset_session(client.session_id())?;
+                println!("reset {} scratch accounts", reset);
+            } else {
+                let refund = value(args, "refund");
+                let (closed, lamports) = client.close_session(client.session_id(), refund)?;
+                println!("closed {} scratch accounts, recovered {} lamports", closed, lamports);
+            }
+        }
+        // Measure the compute units of every step of a demo verification, for STEP_COSTS
+        "calibrate" => {
+            let path = args.value_of("file").unwrap();
+            let costs = client.calibrate()?;
+            files::write_step_costs(path, &costs)?;
+            println!("wrote {} step costs to {}", costs.costs().count(), path);
+        }
+        // Check a demo pairing product on chain, e(a * g1, b * g2) * e(-ab * g1, g2) == 1
+        // for random a and b, as a BLS signature check is
+        "pairing" => {
+            client.check_pairing_product(&demo_pairs())?;
+            println!("pairing product is one");
+        }
+        // Tell whether a nullifier (32 bytes in hex, the little-endian public input) has
+        // been spent under the key in use
+        "nullifier" => {
+            let nullifier = args.value_of("nullifier").unwrap();
+            let nullifier = from_hex(nullifier)
+                .ok_or_else(|| ClientError::Input(format!("invalid nullifier {}", nullifier)))?;
+            println!(
+                "nullifier {} spent: {}",
+                to_hex(&nullifier),
+                client.is_nullifier_spent(&nullifier)?
+            );
+        }
+        // Count what verifying a proof costs, against the given key (arkworks
+        // serialization), a key of the given public input count, or the one in use; a
+        // given key is uploaded first, as `verify` does with proof files
+        "estimate" => {
+            let vk = match value(args, "inputs") {
+                Some(count) => stand_in_vk(count),
+                None => vk_arg(client, args)?,
+            };
+            let upload = (args.is_present("vk") || args.is_present("inputs")) && client.uploads_vk();
+            let estimate = client.estimate(&vk, upload)?;
+            println!(
+                "{} transactions, {} signatures: {} lamports in signature fees",
+                estimate.transactions, estimate.signatures, estimate.signature_fees
+            );
+            println!(
+                "{} accounts: {} lamports in rent, refunded when they are closed",
+                estimate.accounts, estimate.rent
+            );
+            println!(
+                "{} compute units requested: {} lamports in priority fees",
+                estimate.compute_units, estimate.priority_fees
+            );
+            println!("total: {} SOL", lamports_to_sol(estimate.total()));
+        }
+        // Write the transactions verifying the demo proof, or the given proof files, to a
+        // file instead of sending them; without a VK account, the steps carry the key of
+        // the files
+        "dry-run" => {
+            let (proof, public_inputs) = if args.is_present("proof") {
+                let (vk, proof, public_inputs) = proof_files(args)?;
+                if client.uploads_vk() {
+                    client.enable_inline_vk();
+                }
+                client.set_verifying_key(vk);
+                (proof, public_inputs)
+            } else {
+                client.demo_proof()?
+            };
+            let transactions = client.dry_run(&proof, &public_inputs)?;
+            let path = match args.value_of("output") {
+                Some(path) => path.to_string(),
+                None => format!("instructions-{}.json", client.session_id()),
+            };
+            dry_run::write(&path, &client.payer(), &transactions)?;
+            println!(
+                "wrote the {} transactions of the verification to {}",
+                transactions.len(),
+                path
+            );
+        }
+        // Pick up an interrupted verification after the last step its manifest saw land
+        "resume" => {
+            let path = manifest_path(args);
+            let manifest = client.read_manifest(&path)?;
+            if manifest.complete {
+                println!("session {} is verified already", manifest.session_id);
+                return Ok(());
+            }
+            match manifest.last_step {
+                Some([t, i, j]) => println!(
+                    "resuming session {} after step ({}, {}, {})",
+                    manifest.session_id, t, i, j
+                ),
+                None => println!("resuming session {} from its start", manifest.session_id),
+            }
+            verify_reported(client, args, |client| client.resume(&path, manifest))?;
+            println!("verify success! (vk {})", client.vk_fingerprint()?);
+        }
+        // Print the i-th record of the result log
+        "result" => {
+            let i = value(args, "index").unwrap();
+            match client.read_result(i)? {
+                Some(record) => print_result(i, &record),
+                None => println!("no record {} in the result log", i),
+            }
+        }
+        // Verify the given proof files, snarkjs, gnark or arkworks, with the chunked
+        // steps against their verifying key
+        _ if args.is_present("proof") => {
+            let (vk, proof, public_inputs) = proof_files(args)?;
+            client.prepare_external(vk, &proof, &public_inputs, args.is_present("force"))?;
+            println!("start verify a proof on chain");
+            client.set_manifest_path(&manifest_path(args));
+            verify_reported(client, args, |client| {
+                client.groth16_verify_proof(&proof, &public_inputs)
+            })?;
+            println!("verify success! (vk {})", client.vk_fingerprint()?);
+        }
+        _ => {
+            // Run a circuit demo and verify on chain
+            println!("start verify a proof on chain");
+            client.set_manifest_path(&manifest_path(args));
+            verify_reported(client, args, |client| client.groth16_verify())?;
+            if let Some(result) = client.read_verification_result()? {
+                println!(
+                    "proof valid: {}, y16 hash {}, slot {}",
+                    result.valid,
+                    to_hex(&result.y16_hash),
+                    result.slot
+                );
+            }
+            match client.result_count()? {
+                Some(count) => match client.read_result(count.saturating_sub(1))? {
+                    Some(record) => print_result(count - 1, &record),
+                    None => println!("the result log is empty"),
+                },
+                None => println!("verify success! (vk {})", client.vk_fingerprint()?),
+            }
+        }
+    }
+    Ok(())
+}
+
+// The verifying key file argument (arkworks serialization, compressed or not, or the key
+// of a snarkjs `.zkey`), or else the key of the proving key in use.
+fn vk_arg(client: &Client, args: &ArgMatches) -> ClientResult<VerifyingKey<Bn254>> {
+    match args.value_of("vk") {
+        Some(path) => files::read_vk(path, ic_layout(args)),
+        None => client.verifying_key(),
+    }
+}
+
+// The proof files arguments, see `files::read_proof_files`.
+fn proof_files(
+    args: &ArgMatches,
+) -> ClientResult<(VerifyingKey<Bn254>, Proof<Bn254>, Vec<Fr>)> {
+    files::read_proof_files(
+        args.value_of("format"),
+        args.value_of("vk").unwrap(),
+        args.value_of("proof").unwrap(),
+        args.value_of("public").unwrap(),
+        ic_layout(args),
+    )
+}
+
+// Run `verify` and, if --report asks for one, write the report of the verification
+// where it says: pretty-printed to a file, or on one line to stdout for `-`.
+fn verify_reported(
+    client: &mut Client,
+    args: &ArgMatches,
+    verify: impl FnOnce(&mut Client) -> ClientResult<()>,
+) -> ClientResult<()> {
+    let path = match args.value_of("report") {
+        Some(path) => path,
+        None => return verify(client),
+    };
+    client.enable_report();
+    let outcome = verify(client);
+    let report = client.report(&outcome)?;
+    if path == "-" {
+        println!("{}", report.to_json());
+    } else {
+        report.write(path)?;
+        println!("wrote the verification report to {}", path);
+    }
+    outcome
+}
+
+fn print_status(client: &Client) -> ClientResult<()> {
+    println!("program {}, payer {}", client.program_id(), client.payer());
+    let accounts = client.scratch_accounts(client.session_id());
     println!(
-        "connection established, version: {}",
-        client.get_version().unwrap()
+        "session {}: {} scratch accounts",
+        client.session_id(),
+        accounts.len()
     );
+    for (role, key, lamports) in accounts {
+        println!("  {:?} {} ({} lamports)", role, key, lamports);
+    }
+    let status = client.session_status(client.session_id())?;
+    for (role, _, f) in &status.accumulators {
+        match f {
+            Ok(f) if f.is_one() => println!("  {:?} accumulator: one", role),
+            Ok(_) => println!("  {:?} accumulator: written", role),
+            Err(err) => println!("  {:?} accumulator: unreadable ({})", role, err),
+        }
+    }
+    println!("phase: {}", status.phase);
+    if let Some(valid) = status.final_check {
+        println!("final check y16 == alpha_g1_beta_g2: {}", valid);
+    }
+    if let Some(result) = client.read_verification_result()? {
+        println!("result account: valid {} (slot {})", result.valid, result.slot);
+    }
+    Ok(())
+}
 
-    // Determine who pays for fees
-    client.establish_payer();
+fn print_result(i: usize, record: &ResultRecord) {
+    println!(
+        "record {}: valid {}, slot {}, vk id {}, input hash {}",
+        i,
+        record.valid,
+        record.slot,
+        to_hex(&record.vk_id),
+        to_hex(&record.input_hash)
+    );
+}
 
-    // Check if the main program has been deployed
-    client.check_program();
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
 
-    // Run a circuit demo and verify on chain
-    println!("start verify a proof on chain");
-    client.groth16_verify();
-    println!("verify success!");
+fn from_hex(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0u8; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(bytes)
 }
@@ -0,0 +1,336 @@
+use std::convert::TryInto;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::str::FromStr;
+
+use ark_bn254::{Bn254, Fr};
+use ark_groth16::{Proof, VerifyingKey};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use serde_json::{json, Value};
+use solana_sdk::hash::{hashv, Hash};
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+// The progress of a chunked verification, written to disk as its transactions are
+// signed and confirmed so that `resume` can pick an interrupted one up where it stopped:
+// what the steps are rebuilt from (program, payer, session, VK account, the key, the
+// proof and its public inputs), and every transaction sent so far.
+//
+// A transaction is named by the hash of its program instructions, which rebuilding the
+// same steps reproduces. It is recorded with its signature and blockhash when signed,
+// before it is sent, and marked confirmed once it landed, so that one that landed
+// without being marked can still be told apart from one that never will.
+#[derive(Debug, PartialEq)]
+pub struct Manifest {
+    pub program_id: Pubkey,
+    pub payer: Pubkey,
+    pub session_id: u64,
+    // The session guard nonce the steps were tagged with
+    pub nonce: u64,
+    pub vk_account: Option<Pubkey>,
+    pub vk: VerifyingKey<Bn254>,
+    pub proof: Proof<Bn254>,
+    pub public_inputs: Vec<Fr>,
+    // The (t, i, j) of the last step confirmed
+    pub last_step: Option<[u8; 3]>,
+    pub complete: bool,
+    pub transactions: Vec<SentTransaction>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SentTransaction {
+    pub hash: Hash,
+    pub signature: Signature,
+    pub blockhash: Hash,
+    pub step: [u8; 3],
+    pub confirmed: bool,
+}
+
+// The name of a transaction running `instructions`: the hash of those of `program_id`,
+// their accounts and data. Compute budget requests are left out, as the budget may
+// change between runs.
+pub fn transaction_hash(program_id: &Pubkey, instructions: &[Instruction]) -> Hash {
+    let mut parts: Vec<&[u8]> = vec![];
+    for instruction in instructions {
+        if instruction.program_id != *program_id {
+            continue;
+        }
+        for account in instruction.accounts.iter() {
+            parts.push(account.pubkey.as_ref());
+        }
+        parts.push(&instruction.data);
+    }
+    hashv(&parts)
+}
+
+impl Manifest {
+    pub fn is_confirmed(&self, hash: &Hash) -> bool {
+        self.transactions
+            .iter()
+            .any(|transaction| transaction.hash == *hash && transaction.confirmed)
+    }
+
+    // Record a transaction as signed, replacing what an earlier signature of it left.
+    pub fn signed(&mut self, hash: Hash, signature: Signature, blockhash: Hash, step: [u8; 3]) {
+        self.transactions
+            .retain(|transaction| transaction.hash != hash);
+        self.transactions.push(SentTransaction {
+            hash,
+            signature,
+            blockhash,
+            step,
+            confirmed: false,
+        });
+    }
+
+    pub fn confirmed(&mut self, signature: &Signature) {
+        if let Some(transaction) = self
+            .transactions
+            .iter_mut()
+            .find(|transaction| transaction.signature == *signature)
+        {
+            transaction.confirmed = true;
+            self.last_step = Some(transaction.step);
+        }
+    }
+
+    // Transactions signed but not known to have landed.
+    pub fn pending(&self) -> Vec<(Signature, Hash)> {
+        self.transactions
+            .iter()
+            .filter(|transaction| !transaction.confirmed)
+            .map(|transaction| (transaction.signature, transaction.blockhash))
+            .collect()
+    }
+
+    // Forget a pending transaction that can no longer land.
+    pub fn dropped(&mut self, signature: &Signature) {
+        self.transactions
+            .retain(|transaction| transaction.signature != *signature);
+    }
+
+    pub fn read(path: &str) -> Result<Manifest> {
+        let value: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let transactions = value["transactions"]
+            .as_array()
+            .ok_or_else(|| invalid("transactions are not an array"))?
+            .iter()
+            .map(|transaction| {
+                Ok(SentTransaction {
+                    hash: parse(&transaction["hash"])?,
+                    signature: parse(&transaction["signature"])?,
+                    blockhash: parse(&transaction["blockhash"])?,
+                    step: step(&transaction["step"]).ok_or_else(|| invalid("invalid step"))?,
+                    confirmed: transaction["confirmed"].as_bool().unwrap_or(false),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Manifest {
+            program_id: parse(&value["program_id"])?,
+            payer: parse(&value["payer"])?,
+            session_id: number(&value["session_id"])?,
+            nonce: number(&value["nonce"])?,
+            vk_account: match &value["vk_account"] {
+                Value::Null => None,
+                vk_account => Some(parse(vk_account)?),
+            },
+            vk: deserialize(&value["vk"])?,
+            proof: deserialize(&value["proof"])?,
+            public_inputs: deserialize(&value["public_inputs"])?,
+            last_step: step(&value["last_step"]),
+            complete: value["complete"].as_bool().unwrap_or(false),
+            transactions,
+        })
+    }
+
+    // Write the manifest to `path`, through a file next to it renamed over it, so an
+    // interrupted write leaves the previous manifest.
+    pub fn write(&self, path: &str) -> Result<()> {
+        let transactions = self
+            .transactions
+            .iter()
+            .map(|transaction| {
+                json!({
+                    "hash": transaction.hash.to_string(),
+                    "signature": transaction.signature.to_string(),
+                    "blockhash": transaction.blockhash.to_string(),
+                    "step": transaction.step,
+                    "confirmed": transaction.confirmed,
+                })
+            })
+            .collect::<Vec<_>>();
+        let value = json!({
+            "program_id": self.program_id.to_string(),
+            "payer": self.payer.to_string(),
+            "session_id": self.session_id,
+            "nonce": self.nonce,
+            "vk_account": self.vk_account.map(|vk_account| vk_account.to_string()),
+            "vk": serialize(&self.vk)?,
+            "proof": serialize(&self.proof)?,
+            "public_inputs": serialize(&self.public_inputs)?,
+            "last_step": self.last_step,
+            "complete": self.complete,
+            "transactions": transactions,
+        });
+        let temporary = format!("{}.tmp", path);
+        fs::write(&temporary, serde_json::to_string_pretty(&value)?)?;
+        fs::rename(&temporary, path)
+    }
+}
+
+fn parse<T: FromStr>(value: &Value) -> Result<T> {
+    value
+        .as_str()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| invalid("invalid address, hash or signature"))
+}
+
+fn number(value: &Value) -> Result<u64> {
+    value.as_u64().ok_or_else(|| invalid("invalid number"))
+}
+
+fn step(value: &Value) -> Option<[u8; 3]> {
+    let step = value.as_array()?;
+    let byte = |k: usize| -> Option<u8> { step.get(k)?.as_u64()?.try_into().ok() };
+    Some([byte(0)?, byte(1)?, byte(2)?])
+}
+
+// Keys, proofs and public inputs as hex of their compressed arkworks serialization
+fn serialize<T: CanonicalSerialize>(value: &T) -> Result<String> {
+    let mut bytes = vec![];
+    value
+        .serialize(&mut bytes)
+        .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn deserialize<T: CanonicalDeserialize>(value: &Value) -> Result<T> {
+    let hex = value.as_str().ok_or_else(|| invalid("expected hex"))?;
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err(invalid("invalid hex"));
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|k| u8::from_str_radix(&hex[k..k + 2], 16))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|_| invalid("invalid hex"))?;
+    T::deserialize(&bytes[..]).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn manifest() -> Manifest {
+        let (pvk, proof, public_inputs) = circuit::demo_proof().unwrap();
+        let mut manifest = Manifest {
+            program_id: Pubkey::new_unique(),
+            payer: Pubkey::new_unique(),
+            session_id: 3,
+            nonce: 5,
+            vk_account: Some(Pubkey::new_unique()),
+            vk: pvk.vk,
+            proof,
+            public_inputs,
+            last_step: None,
+            complete: false,
+            transactions: vec![],
+        };
+        let blockhash = Hash::new_unique();
+        for (k, step) in [[0, 64, 0], [0, 63, 0]].iter().enumerate() {
+            let signature = Signature::from([k as u8 + 1; 64]);
+            manifest.signed(Hash::new_unique(), signature, blockhash, *step);
+        }
+        manifest
+    }
+
+    // A directory of its own for each test, removed by the test
+    fn dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("manifest-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = dir("round-trip");
+        let path = dir.join("session.json");
+        let path = path.to_str().unwrap();
+
+        let mut manifest = manifest();
+        manifest.write(path).unwrap();
+        assert_eq!(Manifest::read(path).unwrap(), manifest);
+
+        // progress written over the earlier manifest, through no leftover file
+        let first = manifest.transactions[0].signature;
+        manifest.confirmed(&first);
+        manifest.dropped(&manifest.transactions[1].signature.clone());
+        manifest.vk_account = None;
+        manifest.complete = true;
+        manifest.write(path).unwrap();
+        let read = Manifest::read(path).unwrap();
+        assert_eq!(read, manifest);
+        assert_eq!(read.last_step, Some([0, 64, 0]));
+        assert!(read.is_confirmed(&manifest.transactions[0].hash));
+        assert!(read.pending().is_empty());
+        assert!(!PathBuf::from(format!("{}.tmp", path)).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_file() {
+        let dir = dir("corrupt");
+        let path = dir.join("session.json");
+        let path = path.to_str().unwrap();
+        manifest().write(path).unwrap();
+        let written: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+
+        let read = |edit: fn(&mut Value)| {
+            let mut value = written.clone();
+            edit(&mut value);
+            fs::write(path, value.to_string()).unwrap();
+            Manifest::read(path).unwrap_err().kind()
+        };
+        let invalid = ErrorKind::InvalidData;
+        assert_eq!(read(|m| m["payer"] = json!("not an address")), invalid);
+        assert_eq!(read(|m| m["session_id"] = json!("3")), invalid);
+        assert_eq!(read(|m| m["vk"] = json!("abc")), invalid);
+        assert_eq!(read(|m| m["proof"] = json!("zz")), invalid);
+        assert_eq!(read(|m| m["public_inputs"] = json!("00")), invalid);
+        assert_eq!(read(|m| m["transactions"] = json!({})), invalid);
+        assert_eq!(
+            read(|m| m["transactions"][0]["step"] = json!([0, 256, 0])),
+            invalid
+        );
+        assert_eq!(
+            read(|m| m["transactions"][1]["signature"] = json!(1)),
+            invalid
+        );
+
+        // truncated by an interrupted copy, or gone
+        let text = written.to_string();
+        fs::write(path, &text[..text.len() / 2]).unwrap();
+        assert_eq!(
+            Manifest::read(path).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+        fs::remove_file(path).unwrap();
+        assert_eq!(
+            Manifest::read(path).unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+
+        // a path that cannot be written fails the write
+        let missing = dir.join("missing").join("session.json");
+        assert!(manifest().write(missing.to_str().unwrap()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
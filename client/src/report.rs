@@ -0,0 +1,113 @@
+use std::fs;
+
+use ark_bn254::Bn254;
+use ark_groth16::Proof;
+use ark_serialize::CanonicalSerialize;
+use serde_json::{json, Value};
+use solana_sdk::hash::{hash, Hash};
+use solana_sdk::signature::Signature;
+
+use crate::error::{ClientError, ClientResult};
+use crate::files::io_error;
+
+// The outcome of a chunked verification for CI systems and backends to consume (see
+// `Client::enable_report`), as JSON:
+//
+//   { "proof_hash": <hex>, "vk_id": <hex>, "result": "valid" | "invalid" | "failed",
+//     "error": ..., "final_slot": ..., "fees": ..., "rent_paid": ...,
+//     "rent_reclaimed": ..., "phases": [ { "phase": ..., "signatures": [ ... ] } ] }
+//
+// Fees and rent are in lamports, read from the metadata of the transactions that
+// landed: the rent of the accounts they created, and of those they closed. `error` is
+// null unless the verification failed short of deciding, and `final_slot` unless the
+// final check landed, holding or recording its result.
+pub struct Report {
+    // Hash of the proof's compressed arkworks serialization, see `proof_hash`
+    pub proof_hash: Option<Hash>,
+    pub vk_id: [u8; 32],
+    // Whether the proof verified, none if the verification failed before deciding
+    pub valid: Option<bool>,
+    pub error: Option<String>,
+    // Slot of the transaction whose final check landed
+    pub final_slot: Option<u64>,
+    pub fees: u64,
+    pub rent_paid: u64,
+    pub rent_reclaimed: u64,
+    // Signatures of the transactions that landed, by phase in the order the phases ran
+    pub phases: Vec<(&'static str, Vec<Signature>)>,
+}
+
+// What a verification leaves for its report as it goes: the proof, and every
+// transaction that landed with the phase it belongs to.
+#[derive(Default)]
+pub(crate) struct ReportLog {
+    pub proof_hash: Option<Hash>,
+    pub transactions: Vec<(&'static str, Signature)>,
+}
+
+impl ReportLog {
+    // The signatures grouped by phase, a phase that runs again, in a later batch round
+    // say, starting a group of its own. Transactions outside a verification's phases,
+    // a VK upload say, are grouped as "other".
+    pub fn phases(&self) -> Vec<(&'static str, Vec<Signature>)> {
+        let mut phases: Vec<(&'static str, Vec<Signature>)> = vec![];
+        for (phase, signature) in self.transactions.iter() {
+            let phase = if phase.is_empty() { "other" } else { phase };
+            match phases.last_mut() {
+                Some((last, signatures)) if *last == phase => signatures.push(*signature),
+                _ => phases.push((phase, vec![*signature])),
+            }
+        }
+        phases
+    }
+}
+
+pub fn proof_hash(proof: &Proof<Bn254>) -> Hash {
+    let mut bytes = vec![];
+    proof
+        .serialize(&mut bytes)
+        .expect("serializing to a vector cannot fail");
+    hash(&bytes)
+}
+
+impl Report {
+    pub fn to_json(&self) -> Value {
+        let result = match self.valid {
+            Some(true) => "valid",
+            Some(false) => "invalid",
+            None => "failed",
+        };
+        let phases = self
+            .phases
+            .iter()
+            .map(|(phase, signatures)| {
+                let signatures = signatures
+                    .iter()
+                    .map(Signature::to_string)
+                    .collect::<Vec<_>>();
+                json!({ "phase": phase, "signatures": signatures })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "proof_hash": self.proof_hash.map(|hash| to_hex(hash.as_ref())),
+            "vk_id": to_hex(&self.vk_id),
+            "result": result,
+            "error": self.error,
+            "final_slot": self.final_slot,
+            "fees": self.fees,
+            "rent_paid": self.rent_paid,
+            "rent_reclaimed": self.rent_reclaimed,
+            "phases": phases,
+        })
+    }
+
+    // Write the report to `path` as `to_json` has it, pretty-printed.
+    pub fn write(&self, path: &str) -> ClientResult<()> {
+        let json = serde_json::to_string_pretty(&self.to_json()).map_err(ClientError::input)?;
+        fs::write(path, json).map_err(io_error(path))
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
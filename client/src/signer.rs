@@ -0,0 +1,97 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::signer::{Signer, SignerError};
+
+// A payer whose key stays with an external signing service, a KMS or an HSM say,
+// reached through a command that talks to it:
+//
+//   <command> pubkey   prints the signer's public key in base58
+//   <command> sign     reads a message in hex on stdin and prints its signature in
+//                      base58
+//
+// The command is split on whitespace, so it may carry arguments of its own. Every
+// signature is checked against the public key before it is used.
+pub struct CommandSigner {
+    command: Vec<String>,
+    pubkey: Pubkey,
+}
+
+impl CommandSigner {
+    // A signer signing with `command`, asked for its public key once here.
+    pub fn new(command: &str) -> Result<CommandSigner, SignerError> {
+        let command = command
+            .split_whitespace()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        if command.is_empty() {
+            return Err(SignerError::InvalidInput(
+                "empty signer command".to_string(),
+            ));
+        }
+        let mut signer = CommandSigner {
+            command,
+            pubkey: Pubkey::default(),
+        };
+        let pubkey = signer.run("pubkey", None)?;
+        signer.pubkey = Pubkey::from_str(&pubkey)
+            .map_err(|_| SignerError::Protocol(format!("invalid public key {}", pubkey)))?;
+        Ok(signer)
+    }
+
+    // The trimmed output of `command <operation>`, given `input` on stdin.
+    fn run(&self, operation: &str, input: Option<&str>) -> Result<String, SignerError> {
+        let mut child = Command::new(&self.command[0])
+            .args(&self.command[1..])
+            .arg(operation)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| SignerError::Connection(format!("{}: {}", self.command[0], err)))?;
+        if let Some(input) = input {
+            // a command that exits without reading its input fails below
+            let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|err| SignerError::Connection(err.to_string()))?;
+        if !output.status.success() {
+            let msg = format!(
+                "{} {} failed ({})",
+                self.command[0], operation, output.status
+            );
+            return Err(SignerError::Custom(msg));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+impl Signer for CommandSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.pubkey)
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let hex = message
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        let signature = self.run("sign", Some(&hex))?;
+        let signature = Signature::from_str(&signature)
+            .map_err(|_| SignerError::Protocol(format!("invalid signature {}", signature)))?;
+        if !signature.verify(self.pubkey.as_ref(), message) {
+            return Err(SignerError::Protocol(format!(
+                "signature {} is not {}'s",
+                signature, self.pubkey
+            )));
+        }
+        Ok(signature)
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
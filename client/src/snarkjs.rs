@@ -0,0 +1,347 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+use ark_bn254::{Bn254, Fq, Fq2, Fr, G1Affine, G2Affine};
+use ark_ff::{to_bytes, BigInteger256, One, PrimeField, Zero};
+use ark_groth16::{Proof, VerifyingKey};
+use circuit::{normalize_gamma_abc, read_g1, read_g2, FieldForm, GammaAbcLayout};
+use num_bigint::BigUint;
+use serde_json::Value;
+
+// The JSON files of circom + snarkjs (`snarkjs groth16 prove`, `snarkjs zkey
+// export verificationkey`), read into the arkworks types the rest of the client
+// builds instructions from (`circuit::complete_submission`, `circuit::submission`,
+// `circuit::to_on_chain_vk`).
+//
+// Field elements are canonical decimal strings. Points are projective with z = 1, or
+// z = 0 for the point at infinity: G1 as [x, y, z] and G2 as [[x.c0, x.c1], [y.c0,
+// y.c1], [z.c0, z.c1]]. The Solidity calldata snarkjs exports (`zkey export
+// soliditycalldata`) lists G2 coordinates c1 first and is not read here. Every point is
+// checked to be on the curve and in the prime-order subgroup.
+
+// proof.json: pi_a, pi_b, pi_c
+pub fn read_proof(json: &str) -> Result<Proof<Bn254>> {
+    let proof = parse(json)?;
+    Ok(Proof {
+        a: g1(member(&proof, "pi_a")?)?,
+        b: g2(member(&proof, "pi_b")?)?,
+        c: g1(member(&proof, "pi_c")?)?,
+    })
+}
+
+// public.json: the public inputs as an array of decimal strings
+pub fn read_public_inputs(json: &str) -> Result<Vec<Fr>> {
+    parse(json)?
+        .as_array()
+        .ok_or_else(|| invalid("public inputs are not an array"))?
+        .iter()
+        .map(element)
+        .collect()
+}
+
+// verification_key.json: vk_alpha_1, vk_beta_2, vk_gamma_2, vk_delta_2 and the IC
+// points, one more than nPublic
+pub fn read_verifying_key(json: &str) -> Result<VerifyingKey<Bn254>> {
+    let vk = parse(json)?;
+    let ic = member(&vk, "IC")?
+        .as_array()
+        .ok_or_else(|| invalid("IC is not an array"))?
+        .iter()
+        .map(g1)
+        .collect::<Result<Vec<_>>>()?;
+    if ic.is_empty() {
+        return Err(invalid("IC is empty"));
+    }
+    if let Some(num_public) = vk.get("nPublic") {
+        if num_public.as_u64() != Some(ic.len() as u64 - 1) {
+            return Err(invalid("nPublic does not match the IC points"));
+        }
+    }
+    Ok(VerifyingKey {
+        alpha_g1: g1(member(&vk, "vk_alpha_1")?)?,
+        beta_g2: g2(member(&vk, "vk_beta_2")?)?,
+        gamma_g2: g2(member(&vk, "vk_gamma_2")?)?,
+        delta_g2: g2(member(&vk, "vk_delta_2")?)?,
+        gamma_abc_g1: ic,
+    })
+}
+
+// Read the three files `snarkjs groth16 prove` and `zkey export verificationkey` write,
+// the key's IC points in `layout`, reordered into arkworks' (snarkjs itself writes
+// `ConstantFirst`).
+pub fn read_files(
+    vk_path: &str,
+    proof_path: &str,
+    public_path: &str,
+    layout: GammaAbcLayout,
+) -> Result<(VerifyingKey<Bn254>, Proof<Bn254>, Vec<Fr>)> {
+    let mut vk = read_verifying_key(&fs::read_to_string(vk_path)?)?;
+    normalize_gamma_abc(&mut vk, layout);
+    let proof = read_proof(&fs::read_to_string(proof_path)?)?;
+    let public_inputs = read_public_inputs(&fs::read_to_string(public_path)?)?;
+    if public_inputs.len() + 1 != vk.gamma_abc_g1.len() {
+        return Err(invalid(
+            "public input count does not match the verifying key",
+        ));
+    }
+    Ok((vk, proof, public_inputs))
+}
+
+// A proof or key object, for Groth16 over BN254 if it says which
+fn parse(json: &str) -> Result<Value> {
+    let value: Value = serde_json::from_str(json)?;
+    if let Some(protocol) = value.get("protocol") {
+        if protocol != "groth16" {
+            return Err(invalid("not a groth16 proof or key"));
+        }
+    }
+    if let Some(curve) = value.get("curve") {
+        if curve != "bn128" && curve != "bn254" {
+            return Err(invalid("not a BN254 proof or key"));
+        }
+    }
+    Ok(value)
+}
+
+fn member<'a>(value: &'a Value, name: &str) -> Result<&'a Value> {
+    value
+        .get(name)
+        .ok_or_else(|| invalid(&format!("missing {}", name)))
+}
+
+fn g1(value: &Value) -> Result<G1Affine> {
+    let p = array(value, 3)?;
+    let z: Fq = element(&p[2])?;
+    if z.is_zero() {
+        return Ok(G1Affine::zero());
+    }
+    if !z.is_one() {
+        return Err(invalid("point is not normalized"));
+    }
+    let (x, y): (Fq, Fq) = (element(&p[0])?, element(&p[1])?);
+    read_g1(&to_bytes!(x, y).unwrap(), FieldForm::Canonical)
+}
+
+fn g2(value: &Value) -> Result<G2Affine> {
+    let p = array(value, 3)?;
+    let z = fq2(&p[2])?;
+    if z.is_zero() {
+        return Ok(G2Affine::zero());
+    }
+    if !z.is_one() {
+        return Err(invalid("point is not normalized"));
+    }
+    let (x, y) = (fq2(&p[0])?, fq2(&p[1])?);
+    read_g2(&to_bytes!(x, y).unwrap(), FieldForm::Canonical)
+}
+
+fn fq2(value: &Value) -> Result<Fq2> {
+    let c = array(value, 2)?;
+    Ok(Fq2::new(element(&c[0])?, element(&c[1])?))
+}
+
+// A canonical decimal string below the field's modulus.
+fn element<F: PrimeField<BigInt = BigInteger256>>(value: &Value) -> Result<F> {
+    let digits = value
+        .as_str()
+        .ok_or_else(|| invalid("field element is not a string"))?;
+    let n = BigUint::parse_bytes(digits.as_bytes(), 10)
+        .ok_or_else(|| invalid("field element is not a decimal number"))?;
+    BigInteger256::try_from(n)
+        .ok()
+        .and_then(F::from_repr)
+        .ok_or_else(|| invalid("field element is not reduced"))
+}
+
+// An array of exactly `len` entries, snarkjs writes no more
+fn array(value: &Value, len: usize) -> Result<&[Value]> {
+    match value.as_array() {
+        Some(entries) if entries.len() == len => Ok(entries),
+        _ => Err(invalid("unexpected point encoding")),
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use ark_groth16::{prepare_verifying_key, verify_proof};
+    use circuit::GammaAbcLayout;
+    use serde_json::json;
+
+    use super::*;
+
+    // The demo circuit's key, proof and public input (`circuit::demo_proof`) as snarkjs
+    // writes them, `vk_alphabeta_12` included.
+    const VK: &str = include_str!("../tests/fixtures/snarkjs/verification_key.json");
+    const PROOF: &str = include_str!("../tests/fixtures/snarkjs/proof.json");
+    const PUBLIC: &str = include_str!("../tests/fixtures/snarkjs/public.json");
+
+    // The base field modulus, one past the largest coordinate
+    const Q: &str = "21888242871839275222246405745257275088696311157297823662689037894645226208583";
+
+    fn fixture(name: &str) -> String {
+        format!(
+            "{}/tests/fixtures/snarkjs/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        )
+    }
+
+    // `json` with `edit` applied to its parsed value
+    fn edited(json: &str, edit: impl FnOnce(&mut Value)) -> String {
+        let mut value: Value = serde_json::from_str(json).unwrap();
+        edit(&mut value);
+        value.to_string()
+    }
+
+    fn message(err: Error) -> String {
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        err.to_string()
+    }
+
+    #[test]
+    fn test_read_fixture() {
+        let (pvk, proof, public_inputs) = circuit::demo_proof().unwrap();
+        let (vk, file_proof, file_inputs) = read_files(
+            &fixture("verification_key.json"),
+            &fixture("proof.json"),
+            &fixture("public.json"),
+            GammaAbcLayout::ConstantFirst,
+        )
+        .unwrap();
+        assert_eq!(vk, pvk.vk);
+        assert_eq!((&file_proof, &file_inputs), (&proof, &public_inputs));
+        assert!(verify_proof(&prepare_verifying_key(&vk), &file_proof, &file_inputs).unwrap());
+
+        // the same key with its constant term last
+        let rotated = edited(VK, |vk| vk["IC"].as_array_mut().unwrap().rotate_left(1));
+        let mut vk = read_verifying_key(&rotated).unwrap();
+        assert_ne!(vk, pvk.vk);
+        circuit::normalize_gamma_abc(&mut vk, GammaAbcLayout::ConstantLast);
+        assert_eq!(vk, pvk.vk);
+
+        // without the optional members
+        let bare = edited(PROOF, |proof| {
+            let proof = proof.as_object_mut().unwrap();
+            proof.remove("protocol");
+            proof.remove("curve");
+        });
+        assert_eq!(read_proof(&bare).unwrap(), proof);
+        let bare = edited(VK, |vk| drop(vk.as_object_mut().unwrap().remove("nPublic")));
+        assert_eq!(read_verifying_key(&bare).unwrap(), pvk.vk);
+    }
+
+    #[test]
+    fn test_malformed_proof() {
+        let err = |edit: fn(&mut Value)| message(read_proof(&edited(PROOF, edit)).unwrap_err());
+
+        assert!(read_proof("{\"pi_a\": [").is_err());
+        assert_eq!(
+            err(|p| p["protocol"] = json!("plonk")),
+            "not a groth16 proof or key"
+        );
+        assert_eq!(
+            err(|p| p["curve"] = json!("bls12381")),
+            "not a BN254 proof or key"
+        );
+        assert_eq!(
+            err(|p| drop(p.as_object_mut().unwrap().remove("pi_b"))),
+            "missing pi_b"
+        );
+        assert_eq!(
+            err(|p| p["pi_a"][2] = json!("2")),
+            "point is not normalized"
+        );
+        assert_eq!(
+            err(|p| p["pi_b"][2] = json!(["1", "1"])),
+            "point is not normalized"
+        );
+        assert_eq!(
+            err(|p| p["pi_a"][0] = json!(Q)),
+            "field element is not reduced"
+        );
+        assert_eq!(
+            err(|p| p["pi_c"][1] = json!("0x1")),
+            "field element is not a decimal number"
+        );
+        assert_eq!(
+            err(|p| p["pi_c"][1] = json!(1)),
+            "field element is not a string"
+        );
+        assert_eq!(
+            err(|p| p["pi_a"] = json!(["1", "2"])),
+            "unexpected point encoding"
+        );
+        assert_eq!(
+            err(|p| p["pi_b"][0] = json!(["1"])),
+            "unexpected point encoding"
+        );
+        // a point off the curve
+        assert!(read_proof(&edited(PROOF, |p| p["pi_a"][0] = json!("1"))).is_err());
+        // the point at infinity is read as such
+        let infinity = edited(PROOF, |p| p["pi_c"][2] = json!("0"));
+        assert!(read_proof(&infinity).unwrap().c.infinity);
+    }
+
+    #[test]
+    fn test_malformed_key_and_inputs() {
+        let err =
+            |edit: fn(&mut Value)| message(read_verifying_key(&edited(VK, edit)).unwrap_err());
+
+        assert_eq!(err(|vk| vk["IC"] = json!([])), "IC is empty");
+        assert_eq!(err(|vk| vk["IC"] = json!({})), "IC is not an array");
+        assert_eq!(
+            err(|vk| vk["nPublic"] = json!(2)),
+            "nPublic does not match the IC points"
+        );
+        assert_eq!(
+            err(|vk| drop(vk.as_object_mut().unwrap().remove("vk_delta_2"))),
+            "missing vk_delta_2"
+        );
+        assert_eq!(
+            err(|vk| vk["vk_gamma_2"][1][0] = json!(Q)),
+            "field element is not reduced"
+        );
+
+        assert_eq!(read_public_inputs(PUBLIC).unwrap().len(), 1);
+        assert_eq!(
+            message(read_public_inputs("{}").unwrap_err()),
+            "public inputs are not an array"
+        );
+        // the scalar field's modulus is below the base field's
+        assert_eq!(
+            message(read_public_inputs(&json!([Q]).to_string()).unwrap_err()),
+            "field element is not reduced"
+        );
+
+        let dir = env::temp_dir().join(format!("snarkjs-files-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let public = dir.join("public.json");
+        fs::write(
+            &public,
+            edited(PUBLIC, |inputs| {
+                let inputs = inputs.as_array_mut().unwrap();
+                inputs.push(inputs[0].clone());
+            }),
+        )
+        .unwrap();
+        let err = read_files(
+            &fixture("verification_key.json"),
+            &fixture("proof.json"),
+            public.to_str().unwrap(),
+            GammaAbcLayout::ConstantFirst,
+        )
+        .unwrap_err();
+        assert_eq!(
+            message(err),
+            "public input count does not match the verifying key"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,366 @@
+use circuit::StatelessVk;
+use contract::instruction::VerifierInstruction::{self, *};
+use contract::instruction::{Payload, StepIndex};
+use contract::schedule::{self, coeff_range};
+use contract::scratch::TELEMETRY_FLAG;
+use contract::session::SESSION_FLAG;
+use contract::{
+    FLAG_CALLBACK, FLAG_INLINE_VK, FLAG_NULLIFIER, FLAG_RECEIPT, FLAG_RECORD_RESULT,
+    FLAG_TARGET_ONE, FLAG_WRITE_RESULT, INLINE_COEFFS_FLAG, VK_ACCOUNT_FLAG,
+};
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_program;
+
+// One verifier instruction: the accounts it touches (all writable) and its data.
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub keys: Vec<Pubkey>,
+    // Accounts following `keys` that keep their own signer and writable flags
+    pub extra_accounts: Vec<AccountMeta>,
+    pub data: Vec<u8>,
+    // Session id the step's scratch accounts derive from; the client's when unset
+    pub session_id: Option<u64>,
+}
+
+impl Step {
+    fn new(keys: Vec<Pubkey>, data: Vec<u8>) -> Step {
+        Step {
+            keys,
+            extra_accounts: vec![],
+            data,
+            session_id: None,
+        }
+    }
+}
+
+// Run `steps` on the scratch accounts of session `session_id`.
+pub fn in_session(steps: &mut [Step], session_id: u64) {
+    steps
+        .iter_mut()
+        .for_each(|step| step.session_id = Some(session_id));
+}
+
+// Have `step`, if it runs on scratch accounts, take `payer` and the session id its
+// accounts derive from, see `contract/src/scratch.rs`, and with `telemetry`, the
+// session's telemetry account, flagged on the session id. Must follow `use_vk_account`
+// and `inline_vk` and precede `use_registered_vk` and `guard`.
+pub fn use_scratch(step: &mut Step, payer: Pubkey, session_id: u64, telemetry: Option<Pubkey>) {
+    let tag = step.data[0] & !(VK_ACCOUNT_FLAG | INLINE_COEFFS_FLAG) as u8;
+    if VerifierInstruction::runs_on_scratch(tag) {
+        let vk_account = step.data[0] & VK_ACCOUNT_FLAG as u8 != 0;
+        step.keys.insert(vk_account as usize, payer);
+        let session_id = match telemetry {
+            Some(telemetry) => {
+                step.keys.insert(vk_account as usize + 1, telemetry);
+                session_id | TELEMETRY_FLAG
+            }
+            None => session_id,
+        };
+        step.data.extend(session_id.to_le_bytes().iter());
+    }
+}
+
+// A cross-program call the verifier makes once the proof is verified, in the
+// transaction that completes verification.
+//
+// The final step passes the target program and then `accounts`, in order, with their
+// signer and writable flags, and as instruction data the verified key's id and the hash
+// of the public inputs folded on-chain, then `data`. The target program must
+// be deployed and executable; `accounts` are whatever the target's instruction expects.
+// Only the fee payer signs the final step, so it is the only account that may be a
+// signer of the callback.
+#[derive(Clone, Debug)]
+pub struct Callback {
+    pub program_id: Pubkey,
+    pub accounts: Vec<AccountMeta>,
+    pub data: Vec<u8>,
+}
+
+// Make `step` the `index`-th step of the guarded session `nonce`, checked against the
+// `session` account.
+pub fn guard(step: &mut Step, session: Pubkey, nonce: u64, index: u32) {
+    step.keys.insert(0, session);
+    step.data[0] |= SESSION_FLAG as u8;
+    step.data.extend(nonce.to_le_bytes().iter());
+    step.data.extend(index.to_le_bytes().iter());
+}
+
+// Have the final step take the inputs account `inputs` the result log, the nullifier
+// and the receipt attest to, and `statement`, the proof checked and for a receipt its
+// public inputs, as `circuit::complete_submission` encodes them; the program reads the
+// key id and input hash from the account. Must precede `record_result`,
+// `spend_nullifier` and `create_receipt`.
+pub fn attest_inputs(step: &mut Step, inputs: Pubkey, statement: &[u8]) {
+    step.keys.push(inputs);
+    step.data.splice(3..3, statement.iter().cloned());
+}
+
+// Have the final step append its result to `log`, tagged with the hash of the inputs
+// folded on-chain, instead of failing on an invalid proof. Must follow `attest_inputs`.
+pub fn record_result(step: &mut Step, log: Pubkey) {
+    step.data[1] |= FLAG_RECORD_RESULT;
+    step.keys.push(log);
+}
+
+// Have the final step write its outcome to the result account `result` instead of
+// failing on an invalid proof. Must follow `record_result`.
+pub fn write_result(step: &mut Step, result: Pubkey) {
+    step.data[1] |= FLAG_WRITE_RESULT;
+    step.keys.push(result);
+}
+
+// Have the final step spend the nullifier of the inputs it attests to, whose account is
+// `address`, on success and fail if it was spent before. Must follow `write_result` and
+// precede `create_receipt` and `add_callback`.
+pub fn spend_nullifier(step: &mut Step, address: Pubkey) {
+    step.data[1] |= FLAG_NULLIFIER;
+    step.keys.push(address);
+    step.extra_accounts
+        .push(AccountMeta::new_readonly(system_program::id(), false));
+}
+
+// Have the final step create the receipt of the statement it attests to, whose account
+// is `address`, on success. Must follow `spend_nullifier` and precede `add_callback`.
+pub fn create_receipt(step: &mut Step, address: Pubkey) {
+    step.data[1] |= FLAG_RECEIPT;
+    step.extra_accounts.push(AccountMeta::new(address, false));
+    step.extra_accounts
+        .push(AccountMeta::new_readonly(system_program::id(), false));
+}
+
+// Have the final step make `callback` on success. Must follow `attest_inputs`,
+// `record_result`, `write_result`, `spend_nullifier` and `create_receipt`.
+pub fn add_callback(step: &mut Step, callback: &Callback) {
+    step.data[1] |= FLAG_CALLBACK;
+    step.extra_accounts
+        .push(AccountMeta::new_readonly(callback.program_id, false));
+    step.extra_accounts
+        .extend(callback.accounts.iter().cloned());
+    step.data.push(callback.accounts.len() as u8);
+    step.data.extend(callback.data.iter());
+}
+
+// Have `step` read its verifying key from the VK account `vk` if it needs one, i.e. if
+// it is a Miller-loop or final step.
+pub fn use_vk_account(step: &mut Step, vk: Pubkey) {
+    if matches!(step.data[0], 0 | 1 | 16 | 27 | 28 | 29) && !targets_one(step) {
+        step.data[0] |= VK_ACCOUNT_FLAG as u8;
+        step.keys.insert(0, vk);
+    }
+}
+
+// The final step of a pairing-product check against one reads no verifying key.
+fn targets_one(step: &Step) -> bool {
+    step.data[0] == 16 && step.data[1] & FLAG_TARGET_ONE != 0
+}
+
+// Have `step`, if it reads a VK account, name the key by the id it is registered under,
+// with `entry` its registry entry, see `contract/src/registry.rs`. Must follow
+// `use_scratch` and precede `guard`.
+pub fn use_registered_vk(step: &mut Step, entry: Pubkey, id: u64) {
+    if step.data[0] & VK_ACCOUNT_FLAG as u8 != 0 {
+        step.data[0] |= INLINE_COEFFS_FLAG as u8;
+        step.keys.insert(0, entry);
+        step.data.extend(id.to_le_bytes().iter());
+    }
+}
+
+// Have `step` carry the part of `vk` it needs instead of using the program's baked key:
+// the ell coefficients of a Miller-loop step, alpha_g1_beta_g2 for the final step.
+// Other steps are left as they are.
+pub fn inline_vk(step: &mut Step, vk: &StatelessVk) {
+    let (i, j) = (step.data[1] as usize, step.data[2] as usize);
+    // a step running several loop steps carries the coefficients of all of them, the
+    // fused step the gamma and then the delta ones
+    let (coeffs, offset) = match step.data[0] {
+        0 => (vk.gamma_coeffs[coeff_range(i, j, 1)].to_vec(), 3),
+        1 => (vk.delta_coeffs[coeff_range(i, j, 1)].to_vec(), 3),
+        27 => (vk.gamma_coeffs[coeff_range(i, j, step.data[3])].to_vec(), 4),
+        28 => (vk.delta_coeffs[coeff_range(i, j, step.data[3])].to_vec(), 4),
+        29 => {
+            let range = coeff_range(i, j, step.data[3]);
+            let gamma = &vk.gamma_coeffs[range.clone()];
+            ([gamma, &vk.delta_coeffs[range]].concat(), 4)
+        }
+        16 if !targets_one(step) => {
+            step.data[1] |= FLAG_INLINE_VK;
+            step.data.splice(3..3, vk.alpha_g1_beta_g2.iter().cloned());
+            return;
+        }
+        _ => return,
+    };
+    step.data[0] |= INLINE_COEFFS_FLAG as u8;
+    step.data.splice(offset..offset, coeffs);
+}
+
+// Scratch accounts of one verification, derived from the payer and `session_id`:
+// gamma, delta and final accumulators, the y0..y16 hard-part accounts, the AB account
+// of the A·B Miller loop and, when the public inputs are folded on-chain, the inputs
+// account.
+#[derive(Clone, Debug)]
+pub struct SessionKeys {
+    pub session_id: u64,
+    pub gamma: Pubkey,
+    pub delta: Pubkey,
+    pub final_key: Pubkey,
+    pub y: Vec<Pubkey>,
+    pub ab: Pubkey,
+    pub inputs: Option<Pubkey>,
+}
+
+pub fn gamma_miller_loop(key: Pubkey, prepared_input: &[u8]) -> Vec<Step> {
+    miller_loop(GammaMillerLoop, key, prepared_input)
+}
+
+// Gamma Miller loop over the public inputs folded on-chain into `inputs` (see
+// `contract/src/public_inputs.rs`) rather than a prepared input sent by the client.
+pub fn gamma_miller_loop_folded(key: Pubkey, inputs: Pubkey) -> Vec<Step> {
+    let mut steps = miller_loop(GammaMillerLoop, key, &[]);
+    steps.iter_mut().for_each(|step| step.keys.push(inputs));
+    steps
+}
+
+pub fn delta_miller_loop(key: Pubkey, proof_c: &[u8]) -> Vec<Step> {
+    miller_loop(DeltaMillerLoop, key, proof_c)
+}
+
+fn miller_loop(
+    stage: fn(StepIndex, Payload) -> VerifierInstruction,
+    key: Pubkey,
+    input: &[u8],
+) -> Vec<Step> {
+    schedule::miller_loop_steps(stage, input)
+        .into_iter()
+        .map(|data| Step::new(vec![key], data))
+        .collect()
+}
+
+// Merge runs of consecutive gamma or delta Miller-loop steps into instructions running
+// up to `per_instruction` steps each (t = 27, 28), cutting the transactions per loop
+// several-fold; see `contract::schedule::with_packed_miller_loops`.
+pub fn pack_miller_loop(steps: Vec<Step>, per_instruction: u8) -> Vec<Step> {
+    let mut packed: Vec<Step> = vec![];
+    for mut step in steps {
+        let continues = match packed.last() {
+            Some(last) => {
+                step.data[0] <= 1
+                    && last.data[0] == step.data[0] + 27
+                    && last.data[3] < per_instruction
+                    && last.keys == step.keys
+                    && last.data[4..] == step.data[3..]
+            }
+            None => false,
+        };
+        if continues {
+            packed.last_mut().unwrap().data[3] += 1;
+            continue;
+        }
+        if step.data[0] <= 1 && per_instruction > 1 {
+            step.data[0] += 27;
+            step.data.insert(3, 1);
+        }
+        packed.push(step);
+    }
+    packed
+}
+
+// Fuse the gamma and delta loops, split alike, into steps advancing both (t = 29) with
+// the gamma step's accounts and the delta account after the gamma account, halving
+// the transactions of the two loops; see `contract::schedule::with_fused_miller_loops`.
+pub fn fuse_miller_loops(gamma: Vec<Step>, delta: Vec<Step>) -> Vec<Step> {
+    gamma
+        .into_iter()
+        .zip(delta)
+        .map(|(mut step, mut delta)| {
+            // single steps get a step count of one
+            if step.data[0] == 0 {
+                step.data.insert(3, 1);
+            }
+            if delta.data[0] == 1 {
+                delta.data.insert(3, 1);
+            }
+            assert_eq!(step.data[1..4], delta.data[1..4]);
+            step.data[0] = 29;
+            step.data.splice(4..4, delta.data.drain(4..));
+            step.keys.insert(1, delta.keys[0]);
+            step
+        })
+        .collect()
+}
+
+// A·B Miller loop (t = 21) over `proof_ab`, proof.a || proof.b, in the AB account `key`
+pub fn ab_miller_loop(key: Pubkey, proof_ab: &[u8]) -> Vec<Step> {
+    miller_loop(AbMillerLoop, key, proof_ab)
+}
+
+// Merge the one-digit steps of the exponentiations by x (t = 5, 8, 9) into steps of up
+// to `per_instruction` consecutive NAF digits each (digit count in `i`); see
+// `contract::schedule::with_packed_naf_digits`.
+pub fn pack_naf_digits(steps: Vec<Step>, per_instruction: u8) -> Vec<Step> {
+    let mut packed: Vec<Step> = vec![];
+    for mut step in steps {
+        let naf_step = matches!(step.data[0], 5 | 8 | 9);
+        if let Some(last) = packed.last_mut() {
+            if naf_step
+                && last.data[0] == step.data[0]
+                && last.data[1] < per_instruction
+                && last.data[2] + last.data[1] == step.data[2]
+                && last.keys == step.keys
+            {
+                last.data[1] += 1;
+                continue;
+            }
+        }
+        if naf_step {
+            step.data[1] = 1;
+        }
+        packed.push(step);
+    }
+    packed
+}
+
+pub fn final_exponentiation(keys: &SessionKeys) -> Vec<Step> {
+    schedule::final_exponentiation_steps()
+        .into_iter()
+        .map(|(accounts, data)| {
+            let accounts = accounts
+                .into_iter()
+                .map(|account| session_key(keys, account));
+            Step::new(accounts.collect(), data)
+        })
+        .collect()
+}
+
+// A pairing-product check of `pairs` against one, see `contract/src/pairing.rs`: the
+// Miller loop in the pairing account `pairing`, then the final exponentiation in the
+// session's accounts as for a proof.
+pub fn pairing_product(
+    keys: &SessionKeys,
+    pairing: Pubkey,
+    pairs: &[u8],
+    per_instruction: u8,
+) -> Vec<Step> {
+    let index = schedule::NUM_ACCOUNTS;
+    schedule::pairing_product_steps(index, pairs, per_instruction)
+        .into_iter()
+        .map(|(accounts, data)| {
+            let accounts = accounts.into_iter().map(|account| match account {
+                account if account == index => pairing,
+                account => session_key(keys, account),
+            });
+            Step::new(accounts.collect(), data)
+        })
+        .collect()
+}
+
+// The schedule numbers the scratch accounts, see `contract::schedule::y`
+fn session_key(keys: &SessionKeys, account: usize) -> Pubkey {
+    match account {
+        schedule::GAMMA => keys.gamma,
+        schedule::DELTA => keys.delta,
+        schedule::FINAL => keys.final_key,
+        schedule::AB => keys.ab,
+        y => keys.y[y - schedule::y(0)],
+    }
+}
@@ -0,0 +1,141 @@
+use std::cell::Cell;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ark_bn254::{Bn254, Fr};
+use ark_groth16::{Proof, VerifyingKey};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signer::Signer;
+
+use crate::client::{Client, VerificationResult};
+use crate::error::{ClientError, ClientResult};
+use crate::steps::SessionKeys;
+
+// Wait between reads of the result account while awaiting a result
+const RESULT_POLL_INTERVAL: Duration = Duration::from_millis(400);
+
+// On-chain verification for programs embedding it, a dapp backend or a proving service
+// say, over the chunked steps `Client` sends:
+//
+//   let mut verifier = VerifierClient::new(url, payer, program_id);
+//   verifier.set_verifying_key(vk, None);
+//   verifier.create_session(1)?;
+//   verifier.submit_proof(&proof, &public_inputs)?;
+//   let result = verifier.await_result(Duration::from_secs(30))?;
+//
+// The payer must hold enough for the fees and the rent of the session's accounts; no
+// airdrop is asked for. The final step writes the outcome to the session's result
+// account instead of failing on an invalid proof, so an invalid proof is a result,
+// not an error. The options of `Client`, from priority fees to durable nonces, are set
+// on `client_mut`. RPC and transaction failures are returned as `ClientError`s.
+pub struct VerifierClient {
+    client: Client,
+    session: Option<SessionKeys>,
+    // Slot the latest proof was submitted at; its result is written at this slot or a
+    // later one
+    submitted: Cell<u64>,
+}
+
+impl VerifierClient {
+    // A verifier sending to the program `program_id` on the cluster at `url`, `payer`
+    // paying the fees and owning the scratch accounts: a keypair, a Ledger (see
+    // `read_signer`) or an external signing service (see `CommandSigner`).
+    pub fn new(url: &str, payer: impl Signer + 'static, program_id: Pubkey) -> VerifierClient {
+        let mut client = Client::with_payer(url, payer);
+        client.set_program_id(program_id);
+        VerifierClient {
+            client,
+            session: None,
+            submitted: Cell::new(0),
+        }
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn client_mut(&mut self) -> &mut Client {
+        &mut self.client
+    }
+
+    // Verify proofs against `vk`, read from `vk_account` if the key was uploaded there
+    // (see `Client::upload_vk`), or else carried in the steps themselves (see
+    // `Client::enable_inline_vk`). Without a key, proofs are verified against the one
+    // the program was built with.
+    pub fn set_verifying_key(&mut self, vk: VerifyingKey<Bn254>, vk_account: Option<Pubkey>) {
+        match vk_account {
+            Some(vk_account) => self.client.set_vk_account(vk_account),
+            None => self.client.enable_inline_vk(),
+        }
+        self.client.set_verifying_key(vk);
+    }
+
+    // Create the scratch accounts and the result account of session `session_id`, or
+    // reset those it has, and submit the proofs that follow in it. Verifications in
+    // sessions of their own do not disturb each other.
+    pub fn create_session(&mut self, session_id: u64) -> ClientResult<&SessionKeys> {
+        self.client.set_session_id(session_id);
+        self.client.enable_result_account()?;
+        let keys = self.client.create_session_accounts(session_id)?;
+        self.submitted.set(0);
+        Ok(self.session.insert(keys))
+    }
+
+    // Verify `proof` of `public_inputs` in the session, returning once every step has
+    // landed. Fails with `ClientError::NoSession` before `create_session`.
+    pub fn submit_proof(&self, proof: &Proof<Bn254>, public_inputs: &[Fr]) -> ClientResult<()> {
+        if self.session.is_none() {
+            return Err(ClientError::NoSession);
+        }
+        self.submitted.set(self.client.get_slot()?);
+        self.client.groth16_verify_proof(proof, public_inputs)
+    }
+
+    // The outcome of the latest proof submitted in the session, once the result account
+    // shows it: polled until it holds a result written since the proof was submitted,
+    // for up to `timeout`, after which this fails with `ClientError::Timeout`.
+    pub fn await_result(&self, timeout: Duration) -> ClientResult<VerificationResult> {
+        if self.session.is_none() {
+            return Err(ClientError::NoSession);
+        }
+        let start = Instant::now();
+        loop {
+            match self.client.read_verification_result()? {
+                Some(result) if result.slot >= self.submitted.get() && result.slot > 0 => {
+                    return Ok(result)
+                }
+                _ if start.elapsed() >= timeout => return Err(ClientError::Timeout(timeout)),
+                _ => thread::sleep(RESULT_POLL_INTERVAL),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::{G1Affine, G2Affine};
+    use ark_ec::AffineCurve;
+    use solana_sdk::signature::Keypair;
+
+    use super::*;
+
+    // Without a session, neither call reaches the cluster, an unreachable one here.
+    #[test]
+    fn test_no_session() {
+        let verifier =
+            VerifierClient::new("http://127.0.0.1:1", Keypair::new(), Pubkey::new_unique());
+        let proof = Proof {
+            a: G1Affine::prime_subgroup_generator(),
+            b: G2Affine::prime_subgroup_generator(),
+            c: G1Affine::prime_subgroup_generator(),
+        };
+        assert!(matches!(
+            verifier.submit_proof(&proof, &[]),
+            Err(ClientError::NoSession)
+        ));
+        assert!(matches!(
+            verifier.await_result(Duration::from_secs(1)),
+            Err(ClientError::NoSession)
+        ));
+    }
+}
@@ -0,0 +1,293 @@
+use ark_bn254::{Fq, Fr, G1Affine, G2Affine};
+use ark_ff::{to_bytes, BigInteger256, FromBytes, PrimeField, Zero};
+use arrayref::{array_ref, array_refs};
+use solana_program::alt_bn128::prelude::{
+    alt_bn128_addition, alt_bn128_multiplication, alt_bn128_pairing, ALT_BN128_PAIRING_ELEMENT_LEN,
+    ALT_BN128_PAIRING_OUTPUT_LEN,
+};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+
+use crate::error::VerifierError;
+use crate::public_inputs::instruction_inputs;
+use crate::pvk::{get_ic, ALPHA_G1, BETA_G2, DELTA_G2, GAMMA_G2, NUM_PUBLIC_INPUTS};
+use crate::verify_batch::Batch;
+
+// Whole Groth16 check in one instruction (t = 23), on runtimes exposing the
+// `alt_bn128_pairing` syscall. The program folds the raw public inputs into the
+// prepared input with the multiplication and addition syscalls and checks
+//
+//   e(-A, B) * e(alpha, beta) * e(prepared input, gamma) * e(C, delta) == 1
+//
+// against the baked verifying key with a single multi-pairing instead of the chunked
+// Miller loops and final exponentiation, which remain the path for older clusters.
+//
+// input layout, every point in the syscall's encoding (big-endian, G2 as EIP-197):
+//   0..64     proof.a (G1)
+//   64..192   proof.b (G2)
+//   192..256  proof.c (G1)
+//   256..     public inputs, exactly `NUM_PUBLIC_INPUTS` 32-byte little-endian values,
+//             each below r (see `public_inputs::instruction_inputs`)
+pub const G1_DATA_LEN: usize = 64;
+pub const G2_DATA_LEN: usize = 128;
+pub const ALT_BN128_PROOF_LEN: usize = 2 * G1_DATA_LEN + G2_DATA_LEN;
+
+pub fn verify_alt_bn128(input: &[u8]) -> ProgramResult {
+    if input.len() < ALT_BN128_PROOF_LEN {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (proof, public_inputs) = input.split_at(ALT_BN128_PROOF_LEN);
+    let proof = array_ref![proof, 0, ALT_BN128_PROOF_LEN];
+    let (a, b, c) = array_refs![proof, 64, 128, 64];
+    let public_inputs = instruction_inputs(public_inputs, NUM_PUBLIC_INPUTS)?;
+    pairing_check(a, b, c, &prepare_inputs(&public_inputs)?)
+}
+
+// `verify_complete` against the baked key, checked as by `verify_alt_bn128`.
+pub fn verify_complete(
+    a: &G1Affine,
+    b: &G2Affine,
+    c: &G1Affine,
+    public_inputs: &[Fr],
+) -> ProgramResult {
+    let prepared_input = prepare_inputs(public_inputs)?;
+    pairing_check(&g1_data(a), &g2_data(b), &g1_data(c), &prepared_input)
+}
+
+// `IC_0 + sum(x_i * IC_i)` over the baked key's IC points, with the multiplication and
+// addition syscalls.
+fn prepare_inputs(public_inputs: &[Fr]) -> Result<[u8; G1_DATA_LEN], ProgramError> {
+    let mut prepared_input = g1_data(&get_ic(0));
+    for (i, x) in public_inputs.iter().enumerate() {
+        let product = g1_mul(&g1_data(&get_ic(i + 1)), x)?;
+        prepared_input = g1_add(&prepared_input, &product)?;
+    }
+    Ok(prepared_input)
+}
+
+// `verify_batch` against the baked key, with the proofs combined through the
+// multiplication and addition syscalls and a single pairing syscall over k + 3 pairs.
+pub fn verify_batch(batch: &Batch) -> ProgramResult {
+    let scalar_sum = batch.scalar_sum();
+    let mut prepared_input = g1_mul(&g1_data(&get_ic(0)), &scalar_sum)?;
+    for (i, x) in batch.public_inputs.iter().enumerate() {
+        let product = g1_mul(&g1_data(&get_ic(i + 1)), x)?;
+        prepared_input = g1_add(&prepared_input, &product)?;
+    }
+    let mut c = [0u8; G1_DATA_LEN];
+    let mut pairs = Vec::with_capacity(batch.proofs.len() + 3);
+    for ((proof_a, proof_b, proof_c), r) in batch.proofs.iter().zip(batch.scalars.iter()) {
+        c = g1_add(&c, &g1_mul(&g1_data(proof_c), r)?)?;
+        let a = g1_mul(&g1_data(proof_a), r)?;
+        pairs.push((negate_g1(&a)?, g2_data(proof_b)));
+    }
+    pairs.push((g1_mul(&ALPHA_G1, &scalar_sum)?, BETA_G2));
+    pairs.push((prepared_input, GAMMA_G2));
+    pairs.push((c, DELTA_G2));
+    multi_pairing_check(&pairs)
+}
+
+fn pairing_check(
+    a: &[u8; G1_DATA_LEN],
+    b: &[u8; G2_DATA_LEN],
+    c: &[u8; G1_DATA_LEN],
+    prepared_input: &[u8; G1_DATA_LEN],
+) -> ProgramResult {
+    multi_pairing_check(&[
+        (negate_g1(a)?, *b),
+        (ALPHA_G1, BETA_G2),
+        (*prepared_input, GAMMA_G2),
+        (*c, DELTA_G2),
+    ])
+}
+
+// Whether the product of the pairings of `pairs` is one.
+fn multi_pairing_check(pairs: &[([u8; G1_DATA_LEN], [u8; G2_DATA_LEN])]) -> ProgramResult {
+    let mut pairing_input = Vec::with_capacity(pairs.len() * ALT_BN128_PAIRING_ELEMENT_LEN);
+    for (g1, g2) in pairs.iter() {
+        pairing_input.extend_from_slice(&g1[..]);
+        pairing_input.extend_from_slice(&g2[..]);
+    }
+    let result = alt_bn128_pairing(&pairing_input).map_err(|_| VerifierError::InvalidEncoding)?;
+    let mut one = [0u8; ALT_BN128_PAIRING_OUTPUT_LEN];
+    one[ALT_BN128_PAIRING_OUTPUT_LEN - 1] = 1;
+    if result[..] != one[..] {
+        return Err(VerifierError::InvalidProof.into());
+    }
+    Ok(())
+}
+
+// x * p and p + q for G1 points in the syscall's encoding.
+fn g1_mul(p: &[u8; G1_DATA_LEN], x: &Fr) -> Result<[u8; G1_DATA_LEN], ProgramError> {
+    let mut scalar = to_bytes!(x).map_err(|_| ProgramError::InvalidInstructionData)?;
+    scalar.reverse();
+    let product = alt_bn128_multiplication(&[&p[..], &scalar].concat())
+        .map_err(|_| VerifierError::InvalidEncoding)?;
+    Ok(*array_ref![product, 0, G1_DATA_LEN])
+}
+
+fn g1_add(p: &[u8; G1_DATA_LEN], q: &[u8; G1_DATA_LEN]) -> Result<[u8; G1_DATA_LEN], ProgramError> {
+    let sum = alt_bn128_addition(&[&p[..], &q[..]].concat())
+        .map_err(|_| VerifierError::InvalidEncoding)?;
+    Ok(*array_ref![sum, 0, G1_DATA_LEN])
+}
+
+// -p for a G1 point p in the syscall's encoding; the point at infinity is its own
+// negation.
+fn negate_g1(p: &[u8; G1_DATA_LEN]) -> Result<[u8; G1_DATA_LEN], ProgramError> {
+    let (x, y) = array_refs![p, 32, 32];
+    let y = read_fq_be(y)?;
+    if y.is_zero() {
+        return Ok(*p);
+    }
+    let mut neg_y = to_bytes!(-y).map_err(|_| ProgramError::InvalidInstructionData)?;
+    neg_y.reverse();
+    let mut negated = [0u8; G1_DATA_LEN];
+    negated[..32].copy_from_slice(x);
+    negated[32..].copy_from_slice(&neg_y);
+    Ok(negated)
+}
+
+// An arkworks point in the syscall's encoding, see `circuit::write_alt_bn128_g1`.
+fn g1_data(p: &G1Affine) -> [u8; G1_DATA_LEN] {
+    let mut data = [0u8; G1_DATA_LEN];
+    if !p.infinity {
+        data[..32].copy_from_slice(&fq_be(&p.x));
+        data[32..].copy_from_slice(&fq_be(&p.y));
+    }
+    data
+}
+
+fn g2_data(p: &G2Affine) -> [u8; G2_DATA_LEN] {
+    let mut data = [0u8; G2_DATA_LEN];
+    if !p.infinity {
+        for (dst, x) in data
+            .chunks_mut(32)
+            .zip([p.x.c1, p.x.c0, p.y.c1, p.y.c0].iter())
+        {
+            dst.copy_from_slice(&fq_be(x));
+        }
+    }
+    data
+}
+
+fn fq_be(x: &Fq) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(&to_bytes!(x).unwrap());
+    bytes.reverse();
+    bytes
+}
+
+fn read_fq_be(bytes: &[u8; 32]) -> Result<Fq, ProgramError> {
+    let mut le = *bytes;
+    le.reverse();
+    let repr = BigInteger256::read(&le[..]).map_err(|_| VerifierError::InvalidEncoding)?;
+    Fq::from_repr(repr).ok_or_else(|| VerifierError::InvalidEncoding.into())
+}
+
+#[cfg(test)]
+mod test {
+    use circuit::PointEncoding::{Compressed, Uncompressed};
+    use circuit::{alt_bn128_submission, demo_proof};
+
+    use super::*;
+
+    // The points `gen-pvk` bakes for the syscalls are the key the rest of `pvk.rs`
+    // holds: alpha and beta pair to its alpha_g1_beta_g2, and gamma and delta prepare
+    // to its coefficient tables.
+    #[test]
+    fn test_baked_key_matches_pvk() {
+        use ark_bn254::{Bn254, Fq2};
+        use ark_ec::bn::G2Prepared;
+        use ark_ec::PairingEngine;
+
+        use crate::pvk::{get_alpha_g1_beta_g2, get_delta_qef, get_gamma_qef};
+
+        // a baked G2 point and the getter of its baked coefficient table
+        type Table = ([u8; G2_DATA_LEN], fn(usize, usize) -> Fq2);
+
+        let fq = |bytes: &[u8]| read_fq_be(array_ref![bytes, 0, 32]).unwrap();
+        let g1 = |data: &[u8; G1_DATA_LEN]| G1Affine::new(fq(&data[..32]), fq(&data[32..]), false);
+        let g2 = |data: &[u8; G2_DATA_LEN]| {
+            let fq2 = |bytes: &[u8]| Fq2::new(fq(&bytes[32..]), fq(&bytes[..32]));
+            G2Affine::new(fq2(&data[..64]), fq2(&data[64..]), false)
+        };
+        let (alpha, beta) = (g1(&ALPHA_G1), g2(&BETA_G2));
+        assert!(alpha.is_on_curve() && beta.is_on_curve());
+        assert_eq!(Bn254::pairing(alpha, beta), get_alpha_g1_beta_g2());
+
+        let tables: [Table; 2] = [(GAMMA_G2, get_gamma_qef), (DELTA_G2, get_delta_qef)];
+        for (point, baked) in tables.iter() {
+            let coeffs = G2Prepared::<ark_bn254::Parameters>::from(-g2(point)).ell_coeffs;
+            for (j, coeff) in coeffs.iter().enumerate() {
+                assert_eq!((baked(j, 0), baked(j, 1), baked(j, 2)), *coeff);
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_alt_bn128() {
+        let (_, proof, public_inputs) = demo_proof().unwrap();
+        let input = alt_bn128_submission(&proof, &public_inputs);
+        verify_alt_bn128(&input).unwrap();
+
+        let mut data = vec![23, 0, 0];
+        data.extend(input.iter());
+        crate::process_instruction(&Default::default(), &[], &data).unwrap();
+
+        let wrong = alt_bn128_submission(&proof, &[Fr::from(1u64)]);
+        assert_eq!(
+            verify_alt_bn128(&wrong),
+            Err(VerifierError::InvalidProof.into())
+        );
+        // the syscall-backed single-transaction verification
+        let input = circuit::complete_submission(&proof, &public_inputs, Compressed);
+        let mut data = vec![24, 0, 0];
+        data.extend(input.iter());
+        crate::process_instruction(&Default::default(), &[], &data).unwrap();
+        let wrong = circuit::complete_submission(&proof, &[Fr::from(1u64)], Uncompressed);
+        let mut data = vec![24, 0, 0];
+        data.extend(wrong.iter());
+        assert_eq!(
+            crate::process_instruction(&Default::default(), &[], &data),
+            Err(VerifierError::InvalidProof.into())
+        );
+        // and the syscall-backed batch verification
+        let (_, proofs) = circuit::demo_batch(2).unwrap();
+        let input = circuit::batch_submission(&proofs, Compressed);
+        crate::verify_batch::verify_batch(None, &input).unwrap();
+        let mut swapped = proofs.clone();
+        swapped[0].0.c = proofs[1].0.c;
+        swapped[1].0.c = proofs[0].0.c;
+        assert_eq!(
+            crate::verify_batch::verify_batch(
+                None,
+                &circuit::batch_submission(&swapped, Uncompressed)
+            ),
+            Err(VerifierError::InvalidProof.into())
+        );
+
+        let input = alt_bn128_submission(&proof, &public_inputs);
+        let mut off_curve = input.clone();
+        off_curve[G1_DATA_LEN - 1] ^= 1;
+        assert_eq!(
+            verify_alt_bn128(&off_curve),
+            Err(VerifierError::InvalidEncoding.into())
+        );
+        assert_eq!(
+            verify_alt_bn128(&input[1..]),
+            Err(VerifierError::InvalidDataLength.into())
+        );
+        assert_eq!(
+            verify_alt_bn128(&input[..ALT_BN128_PROOF_LEN - 1]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        // an input must be below r, not reduced by the program
+        let mut unreduced = input[..ALT_BN128_PROOF_LEN].to_vec();
+        unreduced.extend([0xff; 32].iter());
+        assert_eq!(
+            verify_alt_bn128(&unreduced),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use std::slice::Iter;
+
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::account_info::{next_account_info, AccountInfo};
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::sysvar::Sysvar;
+
+use crate::header::{check_program_account, AccountKind, ACCOUNT_HEADER_LEN};
+use crate::utils::create_program_account;
+
+// Attestation mode: the submitter verified the proof off-chain and only records the
+// outcome. The contract does NOT re-verify anything, the record is exactly as
+// trustworthy as the signer that submitted it.
+//
+// Each submitter has one attestation account per statement, derived from
+// `ATTESTATION_SEED || submitter || statement hash` and created by the first attestation
+// at the submitter's expense. A later attestation of the same statement by the same
+// submitter overwrites it; the program refuses any account not derived for the
+// submitter and statement, not its own or not holding an attestation of that submitter.
+//
+// instruction data: result (1 byte, 1 = valid) || statement hash (32 bytes)
+// accounts: [attestation account (writable), submitter (writable signer), system program]
+//
+// attestation account layout:
+//   0..8    account header of kind `Attestation` (see `header.rs`)
+//   8..9    result
+//   9..41   submitter pubkey
+//   41..73  statement hash
+//   73..81  slot (u64, little-endian)
+pub const ATTESTATION_SEED: &[u8] = b"attestation";
+pub const ATTESTATION_DATA_LEN: usize = ACCOUNT_HEADER_LEN + 73;
+
+pub fn attestation_address(
+    program_id: &Pubkey,
+    submitter: &Pubkey,
+    statement_hash: &[u8; 32],
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ATTESTATION_SEED, submitter.as_ref(), statement_hash],
+        program_id,
+    )
+}
+
+pub fn record_attestation<'a>(
+    program_id: &Pubkey,
+    accounts_iter: &mut Iter<AccountInfo<'a>>,
+    input: &[u8],
+) -> ProgramResult {
+    let attestation_account = next_account_info(accounts_iter)?;
+    let submitter = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    if !submitter.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if input.len() != 33 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let input = array_ref![input, 0, 33];
+    let (result, statement_hash) = array_refs![input, 1, 32];
+
+    let (address, bump) = attestation_address(program_id, submitter.key, statement_hash);
+    if attestation_account.owner == program_id {
+        check_program_account(
+            program_id,
+            attestation_account,
+            &address,
+            AccountKind::Attestation,
+        )?;
+        let data = attestation_account.try_borrow_data()?;
+        if data.len() < ATTESTATION_DATA_LEN
+            || data[ACCOUNT_HEADER_LEN + 1..ACCOUNT_HEADER_LEN + 33] != submitter.key.as_ref()[..]
+        {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+    } else {
+        if *attestation_account.key != address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let seeds: &[&[u8]] = &[
+            ATTESTATION_SEED,
+            submitter.key.as_ref(),
+            statement_hash,
+            &[bump],
+        ];
+        create_program_account(
+            program_id,
+            submitter,
+            attestation_account,
+            system_program,
+            seeds,
+            ATTESTATION_DATA_LEN,
+        )?;
+    }
+
+    let slot = Clock::get()?.slot;
+    let mut data = attestation_account.try_borrow_mut_data()?;
+    if data.len() < ATTESTATION_DATA_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    let dst = array_mut_ref![data, 0, ATTESTATION_DATA_LEN];
+    let (dst_header, dst_result, dst_submitter, dst_hash, dst_slot) =
+        mut_array_refs![dst, ACCOUNT_HEADER_LEN, 1, 32, 32, 8];
+    *dst_header = AccountKind::Attestation.header();
+    dst_result[0] = (result[0] == 1) as u8;
+    dst_submitter.copy_from_slice(submitter.key.as_ref());
+    dst_hash.copy_from_slice(statement_hash);
+    *dst_slot = slot.to_le_bytes();
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use solana_program::rent::Rent;
+    use solana_program::system_instruction;
+    use solana_program::system_program;
+
+    use super::*;
+    use crate::error::VerifierError;
+    use crate::test_utils::{install_stubs, invoked, TestAccount, SLOT};
+
+    fn attest(
+        program_id: &Pubkey,
+        account: &mut TestAccount,
+        submitter: &mut TestAccount,
+        result: u8,
+    ) -> ProgramResult {
+        let mut system = TestAccount::new(&Pubkey::default(), 0);
+        system.key = system_program::id();
+        let infos = [account.info(), submitter.info(), system.info()];
+        let data = [&[17, 0, 0, result][..], &[7; 32]].concat();
+        crate::process_instruction(program_id, &infos, &data)
+    }
+
+    #[test]
+    fn test_record_attestation() {
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let mut submitter = TestAccount::payer();
+        let (address, _) = attestation_address(&program_id, &submitter.key, &[7; 32]);
+        let mut account = TestAccount::new(&system_program::id(), ATTESTATION_DATA_LEN);
+        account.key = address;
+
+        // the first attestation creates the account at the submitter's expense
+        attest(&program_id, &mut account, &mut submitter, 1).unwrap();
+        let lamports = Rent::default().minimum_balance(ATTESTATION_DATA_LEN);
+        let len = ATTESTATION_DATA_LEN as u64;
+        let create = system_instruction::create_account(
+            &submitter.key,
+            &address,
+            lamports,
+            len,
+            &program_id,
+        );
+        assert_eq!(invoked().last(), Some(&create));
+        let expected = [
+            &AccountKind::Attestation.header()[..],
+            &[1],
+            submitter.key.as_ref(),
+            &[7; 32],
+            &SLOT.to_le_bytes(),
+        ]
+        .concat();
+        assert_eq!(account.data, expected);
+
+        // the same submitter attests again in place
+        account.owner = program_id;
+        attest(&program_id, &mut account, &mut submitter, 0).unwrap();
+        assert_eq!(account.data[ACCOUNT_HEADER_LEN], 0);
+
+        // another submitter has an account of its own
+        let mut other = TestAccount::new(&Pubkey::default(), 0);
+        other.is_signer = true;
+        assert_eq!(
+            attest(&program_id, &mut account, &mut other, 1),
+            Err(ProgramError::InvalidSeeds)
+        );
+        // and an account at the submitter's address holding someone else's attestation
+        // or another kind is not overwritten
+        account.data[ACCOUNT_HEADER_LEN + 1..ACCOUNT_HEADER_LEN + 33].copy_from_slice(&[9; 32]);
+        assert_eq!(
+            attest(&program_id, &mut account, &mut submitter, 1),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+        account.data[..ACCOUNT_HEADER_LEN].copy_from_slice(&AccountKind::Receipt.header());
+        assert_eq!(
+            attest(&program_id, &mut account, &mut submitter, 1),
+            Err(VerifierError::InvalidAccountHeader.into())
+        );
+        submitter.is_signer = false;
+        assert_eq!(
+            attest(&program_id, &mut account, &mut submitter, 1),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::slice::Iter;
+
+use solana_program::account_info::{next_account_info, AccountInfo};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::program::invoke;
+use solana_program::program_error::ProgramError;
+
+use crate::public_inputs::Statement;
+
+// Cross-program callback made by the final step once the proof is verified, so that
+// "verify, then act on it" completes in the transaction that finishes verification.
+// Like the result log, the callback attests to a statement: the final step only makes it
+// for a proof checked against the key and inputs of the session's inputs account (see
+// `public_inputs::bound_statement`), and the target receives both ahead of its data, so
+// it learns what was proven from the verifier rather than from the caller.
+//
+// instruction data, what follows the proof in the final step's input: number of
+//                   callback accounts n (1 byte) || callback instruction data
+// accounts: [target program, n callback accounts]
+// callback data: vk id (32 bytes) || public-input hash (32 bytes) || callback
+//                instruction data
+//
+// The callback accounts are passed to the target with the signer and writable flags
+// they have in the final step's transaction, so only signers of that transaction can be
+// signers of the callback.
+pub fn invoke_callback(
+    accounts_iter: &mut Iter<AccountInfo>,
+    statement: &Statement,
+    input: &[u8],
+) -> ProgramResult {
+    let (&count, data) = input
+        .split_first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    let target = next_account_info(accounts_iter)?;
+
+    let mut account_infos = Vec::with_capacity(count as usize + 1);
+    let mut account_metas = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let account = next_account_info(accounts_iter)?;
+        account_metas.push(AccountMeta {
+            pubkey: *account.key,
+            is_signer: account.is_signer,
+            is_writable: account.is_writable,
+        });
+        account_infos.push(account.clone());
+    }
+    account_infos.push(target.clone());
+
+    let instruction = Instruction {
+        program_id: *target.key,
+        accounts: account_metas,
+        data: [&statement.vk_id[..], &statement.inputs_hash, data].concat(),
+    };
+    invoke(&instruction, &account_infos)
+}
+
+#[cfg(test)]
+mod test {
+    use ark_bn254::Fq12;
+    use ark_ff::One;
+    use solana_program::pubkey::Pubkey;
+
+    use crate::error::VerifierError;
+    use crate::test_utils::{
+        attesting_step, bound_inputs_account, final_step_accounts, install_stubs, invalid_y15,
+        invoked, run, scratch_data, TestAccount, SESSION_ID,
+    };
+    use crate::utils::vk_id;
+    use crate::{FLAG_CALLBACK, FLAG_RECORD_RESULT};
+
+    #[test]
+    fn test_callback_on_success_only() {
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let target_program = Pubkey::new_unique();
+        let mut target = TestAccount::new(&Pubkey::default(), 0);
+        target.key = target_program;
+        let mut record = TestAccount::new(&target_program, 8);
+        let mut log = TestAccount::result_log(&program_id, 1);
+
+        // valid proof: the target is called with the record account, and the key id and
+        // input hash ahead of the data
+        let (mut y14, mut y15) = final_step_accounts(&program_id, SESSION_ID, Fq12::one());
+        let mut inputs = bound_inputs_account(&program_id, SESSION_ID, [1; 32], &mut y14, &mut y15);
+        run(
+            &program_id,
+            &mut [&mut y14, &mut y15, &mut inputs, &mut target, &mut record],
+            &attesting_step(FLAG_CALLBACK, &[1, 7, 7]),
+        );
+        let calls = invoked()
+            .into_iter()
+            .filter(|ix| ix.program_id == target_program)
+            .collect::<Vec<_>>();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(
+            calls[0].data,
+            [&vk_id(None)[..], &[1; 32], &[7, 7]].concat()
+        );
+        assert_eq!(calls[0].accounts.len(), 1);
+        assert_eq!(calls[0].accounts[0].pubkey, record.key);
+        assert!(calls[0].accounts[0].is_writable);
+
+        // invalid proof with a result log: recorded, but no callback
+        let (mut y14, mut y15) = final_step_accounts(&program_id, SESSION_ID, invalid_y15());
+        let mut inputs = bound_inputs_account(&program_id, SESSION_ID, [1; 32], &mut y14, &mut y15);
+        run(
+            &program_id,
+            &mut [
+                &mut y14,
+                &mut y15,
+                &mut inputs,
+                &mut log,
+                &mut target,
+                &mut record,
+            ],
+            &attesting_step(FLAG_RECORD_RESULT | FLAG_CALLBACK, &[1, 7, 7]),
+        );
+        let calls = invoked()
+            .into_iter()
+            .filter(|ix| ix.program_id == target_program)
+            .count();
+        assert_eq!(calls, 1);
+
+        // a run whose inputs are not bound on-chain makes no callback
+        let (mut y14, mut y15) = final_step_accounts(&program_id, SESSION_ID, Fq12::one());
+        let mut inputs = bound_inputs_account(&program_id, SESSION_ID, [1; 32], &mut y14, &mut y15);
+        let mut unbound = final_step_accounts(&program_id, SESSION_ID, Fq12::one()).0;
+        let mut payer = TestAccount::payer();
+        let infos = [
+            payer.info(),
+            unbound.info(),
+            y15.info(),
+            inputs.info(),
+            target.info(),
+            record.info(),
+        ];
+        let data = scratch_data(&attesting_step(FLAG_CALLBACK, &[1, 7, 7]), SESSION_ID);
+        assert_eq!(
+            crate::process_instruction(&program_id, &infos, &data),
+            Err(VerifierError::UnboundInputs.into())
+        );
+        let calls = invoked()
+            .into_iter()
+            .filter(|ix| ix.program_id == target_program)
+            .count();
+        assert_eq!(calls, 1);
+    }
+}
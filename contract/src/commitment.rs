@@ -0,0 +1,44 @@
+use ark_bn254::Fr;
+use ark_ff::PrimeField;
+use solana_program::hash::hashv;
+
+use crate::utils::FR_DATA_LEN;
+
+// Hash-chain commitment of public inputs, matching `circuit::hash_chain`:
+//   acc_0 = [0; 32]
+//   acc_k = sha256(acc_{k-1} || inputs[k-1])
+// where each input is the 32-byte little-endian encoding of a field element, and the
+// single public input seen by the circuit is acc_n reduced modulo r.
+pub fn hash_chain(inputs: &[[u8; FR_DATA_LEN]]) -> Fr {
+    Fr::from_le_bytes_mod_order(&hash_chain_accumulator(inputs))
+}
+
+pub fn hash_chain_accumulator(inputs: &[[u8; FR_DATA_LEN]]) -> [u8; 32] {
+    inputs
+        .iter()
+        .fold([0u8; 32], |acc, input| hashv(&[&acc, input]).to_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ark_ff::to_bytes;
+    use ark_std::{test_rng, UniformRand};
+
+    #[test]
+    fn test_hash_chain_matches_circuit() {
+        let rng = &mut test_rng();
+        let inputs = (0..5).map(|_| Fr::rand(rng)).collect::<Vec<_>>();
+        let encoded = inputs
+            .iter()
+            .map(|x| {
+                let mut bytes = [0u8; FR_DATA_LEN];
+                bytes.copy_from_slice(&to_bytes!(x).unwrap());
+                bytes
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(hash_chain(&encoded), circuit::hash_chain(&inputs));
+        assert_eq!(hash_chain(&[]), Fr::from(0u64));
+    }
+}
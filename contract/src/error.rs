@@ -0,0 +1,139 @@
+use num_derive::FromPrimitive;
+use solana_program::decode_error::DecodeError;
+use solana_program::program_error::ProgramError;
+use thiserror::Error;
+
+// Failures specific to the verifier, returned as `ProgramError::Custom(error as u32)`.
+// Malformed instructions and accounts in general use the matching built-in
+// `ProgramError` variants; the codes below are stable and clients may match on them.
+#[derive(Clone, Copy, Debug, Eq, Error, FromPrimitive, PartialEq)]
+pub enum VerifierError {
+    // 0: the step does not belong to the session's current nonce and progress
+    #[error("step does not belong to the current session")]
+    SessionConflict,
+    // 1: the pairing check of the final step failed
+    #[error("proof is invalid")]
+    InvalidProof,
+    // 2: a scratch account does not hold a field element, e.g. a step was skipped
+    #[error("scratch account does not hold a valid accumulator")]
+    InvalidAccumulator,
+    // 3: a point or field element in the instruction data does not decode
+    #[error("instruction data holds an invalid point or field element")]
+    InvalidEncoding,
+    // 4: the Miller-loop result is zero and has no inverse
+    #[error("miller loop result is not invertible")]
+    NotInvertible,
+    // 5: a scratch account was passed read-only
+    #[error("scratch account is not writable")]
+    ReadOnlyAccount,
+    // 6: an account's header is not this layout version's or not of the kind expected
+    #[error("account header does not match its layout version or kind")]
+    InvalidAccountHeader,
+    // 7: the nullifier of a verified proof has been spent before
+    #[error("nullifier has already been used")]
+    NullifierUsed,
+    // 8: the signer of a VK registry operation is not the registry's administrator
+    #[error("signer is not the VK registry's administrator")]
+    NotRegistryAdmin,
+    // 9: a finalized verifying key was written to
+    #[error("verifying key is finalized and can no longer be written")]
+    VkLocked,
+    // 10: an uploaded verifying key does not match the hash it was finalized with
+    #[error("verifying key does not match its hash")]
+    VkHashMismatch,
+    // 11: a frozen VK registry entry was to point at another key
+    #[error("registered verifying key is immutable")]
+    VkImmutable,
+    // 12: a step's data is shorter or longer than its instruction's layout
+    #[error("instruction data does not have the length of its layout")]
+    InvalidDataLength,
+    // 13: a pairing-product step carries other pairs than its session's first step
+    #[error("pairs differ from those the pairing product started with")]
+    PairsMismatch,
+    // 14: a final step recording its outcome under the folded public inputs was not
+    // computed from them, see `public_inputs::bound_statement`
+    #[error("proof was not checked against the public inputs folded on-chain")]
+    UnboundInputs,
+    // 15: the result account of a final step is not its session's, set up by the program
+    #[error("result account is not the session's result account")]
+    InvalidResultAccount,
+}
+
+impl From<VerifierError> for ProgramError {
+    fn from(e: VerifierError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for VerifierError {
+    fn type_of() -> &'static str {
+        "VerifierError"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use solana_program::entrypoint::ProgramResult;
+    use solana_program::pubkey::Pubkey;
+
+    use super::*;
+    use crate::process_instruction;
+    use crate::scratch::{Role, SCRATCH_HEADER_LEN};
+    use crate::test_utils::{
+        final_step_accounts, invalid_y15, scratch_data, TestAccount, SESSION_ID,
+    };
+    use crate::utils::BN254_DATA_LEN;
+
+    fn step(program_id: &Pubkey, accounts: &mut [&mut TestAccount], data: &[u8]) -> ProgramResult {
+        let mut payer = TestAccount::payer();
+        let mut infos = vec![payer.info()];
+        infos.extend(accounts.iter_mut().map(|account| account.info()));
+        process_instruction(program_id, &infos, &scratch_data(data, SESSION_ID))
+    }
+
+    // Malformed steps fail with an error instead of aborting the program.
+    #[test]
+    fn test_errors_instead_of_panics() {
+        let program_id = Pubkey::new_unique();
+
+        let (mut y14, mut y15) = final_step_accounts(&program_id, SESSION_ID, invalid_y15());
+        assert_eq!(
+            step(&program_id, &mut [&mut y14, &mut y15], &[16, 0, 0]),
+            Err(VerifierError::InvalidProof.into())
+        );
+
+        let mut final_account = TestAccount::scratch(&program_id, SESSION_ID, Role::Final);
+        final_account.data[SCRATCH_HEADER_LEN..].copy_from_slice(&[0; BN254_DATA_LEN]);
+        assert_eq!(
+            step(&program_id, &mut [&mut final_account], &[3, 0, 0]),
+            Err(VerifierError::NotInvertible.into())
+        );
+        final_account.data[SCRATCH_HEADER_LEN..].copy_from_slice(&[0xff; BN254_DATA_LEN]);
+        assert_eq!(
+            step(&program_id, &mut [&mut final_account], &[3, 0, 0]),
+            Err(VerifierError::InvalidAccumulator.into())
+        );
+        final_account.data.truncate(1);
+        assert_eq!(
+            step(&program_id, &mut [&mut final_account], &[3, 0, 0]),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+
+        assert_eq!(
+            step(&program_id, &mut [], &[3, 0, 0]),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+
+        // an exponentiation-by-x step must stop at the last NAF digit
+        let mut y3 = TestAccount::scratch(&program_id, SESSION_ID, Role::Y(3));
+        let mut y4 = TestAccount::scratch(&program_id, SESSION_ID, Role::Y(4));
+        assert_eq!(
+            step(&program_id, &mut [&mut y3, &mut y4], &[8, 2, 62]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(
+            process_instruction(&program_id, &[TestAccount::payer().info()], &[3]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use solana_program::msg;
+
+use crate::curve::MILLER_LOOP_LAST_STEP;
+use crate::instruction::StepIndex;
+use crate::instruction::VerifierInstruction::{self, *};
+use crate::pairing::PAIRING_MILLER_LOOP;
+use crate::scratch::Scratch;
+
+// Program log lines marking the phase boundaries of a chunked verification, so
+// explorers and indexers can follow its progress from transaction logs alone. A step
+// that crosses a boundary logs one line once it succeeded:
+//
+//   groth16: <phase> session=<session id> i=<i> j=<j>
+//
+// with phases
+//   gamma-loop-start / gamma-loop-end, likewise delta- and ab-: the first and last
+//     step of each Miller loop; an instruction running several gamma or delta steps
+//     logs the boundary its first step is at, or else the end if it runs the last;
+//     gamma-delta-loop-start / gamma-delta-loop-end for both loops run together, and
+//     pairing-loop-start / pairing-loop-end for the loop of a pairing product
+//   fold-inputs, prepare-final, easy-part-1, easy-part-2
+//   hard-part-y<k>: every hard-part step, for y0, y4 and y6 the first NAF digit in j
+//     and the digit count in i
+//   final-check: the comparison, with `valid=<0|1>` appended and the `FLAG_*` bits in
+//     i, logged before an invalid proof fails the transaction
+//
+// The payer the session belongs to is the step's first signer and not repeated here.
+pub const LOG_PREFIX: &str = "groth16:";
+
+pub fn log_step(scratch: &Scratch, instruction: &VerifierInstruction) {
+    if let Some((phase, index)) = phase(instruction) {
+        msg!(
+            "{} {} session={} i={} j={}",
+            LOG_PREFIX,
+            phase,
+            scratch.session_id(),
+            index.i,
+            index.j
+        );
+    }
+}
+
+pub fn log_final_check(scratch: &Scratch, flags: u8, valid: bool) {
+    msg!(
+        "{} final-check session={} i={} j=0 valid={}",
+        LOG_PREFIX,
+        scratch.session_id(),
+        flags,
+        valid as u8
+    );
+}
+
+fn phase(instruction: &VerifierInstruction) -> Option<(&'static str, StepIndex)> {
+    let miller_loop = |index: &StepIndex, start, end| match index.j as usize {
+        0 => Some((start, *index)),
+        MILLER_LOOP_LAST_STEP => Some((end, *index)),
+        _ => None,
+    };
+    // several steps from (i, j) on run the last one when they reach i = 0
+    let miller_loop_steps = |index: &StepIndex, steps: u8, start, end| match index.j {
+        0 => Some((start, *index)),
+        _ if steps as usize > index.i as usize => Some((end, *index)),
+        _ => None,
+    };
+    match instruction {
+        GammaMillerLoop(index, _) => miller_loop(index, "gamma-loop-start", "gamma-loop-end"),
+        DeltaMillerLoop(index, _) => miller_loop(index, "delta-loop-start", "delta-loop-end"),
+        GammaMillerLoopSteps(index, steps, _) => {
+            miller_loop_steps(index, *steps, "gamma-loop-start", "gamma-loop-end")
+        }
+        DeltaMillerLoopSteps(index, steps, _) => {
+            miller_loop_steps(index, *steps, "delta-loop-start", "delta-loop-end")
+        }
+        GammaDeltaMillerLoop(index, steps, _) => miller_loop_steps(
+            index,
+            *steps,
+            "gamma-delta-loop-start",
+            "gamma-delta-loop-end",
+        ),
+        AbMillerLoop(index, _) => miller_loop(index, "ab-loop-start", "ab-loop-end"),
+        PrepareG2(index, steps, input) if input.0.first() == Some(&PAIRING_MILLER_LOOP) => {
+            miller_loop_steps(index, *steps, "pairing-loop-start", "pairing-loop-end")
+        }
+        FoldPublicInputs(index, _) => Some(("fold-inputs", *index)),
+        PrepareFinal(index, _) => Some(("prepare-final", *index)),
+        EasyPart1(index) => Some(("easy-part-1", *index)),
+        EasyPart2(index) => Some(("easy-part-2", *index)),
+        HardPartY0(index) => Some(("hard-part-y0", *index)),
+        HardPartY1(index) => Some(("hard-part-y1", *index)),
+        HardPartY3(index) => Some(("hard-part-y3", *index)),
+        HardPartY4(index) => Some(("hard-part-y4", *index)),
+        HardPartY6(index) => Some(("hard-part-y6", *index)),
+        HardPartY8(index) => Some(("hard-part-y8", *index)),
+        HardPartY9(index) => Some(("hard-part-y9", *index)),
+        HardPartY11(index) => Some(("hard-part-y11", *index)),
+        HardPartY13(index) => Some(("hard-part-y13", *index)),
+        HardPartY14(index) => Some(("hard-part-y14", *index)),
+        HardPartY15(index) => Some(("hard-part-y15", *index)),
+        _ => None,
+    }
+}
@@ -1,317 +1,418 @@
+use std::ops::Range;
 use std::slice::Iter;
 
 use ark_bn254::Fq12Parameters;
-use ark_ff::{Field, Fp12, Fp12ParamsWrapper, FromBytes, QuadExtField};
-use arrayref::array_ref;
-use solana_program::account_info::AccountInfo;
+use ark_ff::{to_bytes, Fp12, FromBytes, One};
+use groth16_sol_core::final_exponentiation as fe;
+use groth16_sol_core::miller_loop::finish_loop;
+use solana_program::account_info::{next_account_info, AccountInfo};
 use solana_program::entrypoint::ProgramResult;
-
-use crate::pvk::get_alpha_g1_beta_g2;
-use crate::utils::{get_account_data, put_account_data, BN254_DATA_LEN};
-
-const NAF: [i64; 63] = [
-    1, 0, 0, 0, 1, 0, 1, 0, 0, -1, 0, 1, 0, 1, 0, -1, 0, 0, 1, 0, 1, 0, -1, 0, -1, 0, -1, 0, 1, 0,
-    0, 0, 1, 0, 0, 1, 0, 1, 0, 1, 0, -1, 0, 1, 0, 0, 1, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, -1, 0, 0,
-    0, 1,
-];
-
-pub fn final_exponentiation(
-    accounts_iter: &mut Iter<AccountInfo>,
-    t: usize,
-    j: usize,
-    input: &[u8],
+use solana_program::hash::{hash, hashv};
+use solana_program::program_error::ProgramError;
+
+use crate::callback::invoke_callback;
+use crate::curve::{Fq12, X_NAF};
+use crate::error::VerifierError;
+use crate::events::log_final_check;
+use crate::instruction::StepIndex;
+use crate::instruction::VerifierInstruction::{self, *};
+use crate::nullifier::{spend_nullifier, NULLIFIER_INPUT};
+use crate::pairing::prepare_pairing;
+use crate::point::PointReader;
+use crate::public_inputs::{bound_statement, instruction_inputs, Statement};
+use crate::pvk::{get_alpha_g1_beta_g2, NUM_PUBLIC_INPUTS};
+use crate::receipt::{create_receipt, statement_hash};
+use crate::result_account::write_result;
+use crate::result_log::append_result;
+use crate::return_data::{set_outcome, Outcome};
+use crate::scratch::{
+    accumulate_tag, derive_tag, is_bound, join_tags, read_tag, write_tag, Role, Scratch,
+    UNBOUND_TAG,
+};
+use crate::utils::{get_account_data, put_account_data, vk_id, BN254_DATA_LEN, FR_DATA_LEN};
+use crate::vk::OnChainVk;
+
+pub fn final_exponentiation<'a>(
+    scratch: &Scratch<'_, 'a>,
+    accounts_iter: &mut Iter<AccountInfo<'a>>,
+    stage: &VerifierInstruction,
+    vk: Option<&OnChainVk>,
 ) -> ProgramResult {
-    match t {
-        2 => prepare_final_data(accounts_iter, input),
-        // Easy part: result = elt^((q^6-1)*(q^2+1)).
-        // Follows, e.g., Beuchat et al page 9, by computing result as follows:
-        //   elt^((q^6-1)*(q^2+1)) = (conj(elt) * elt^(-1))^(q^2+1)
-        3 => easy_part1(accounts_iter),
-        4 => easy_part2(accounts_iter),
-        // Hard part follows Laura Fuentes-Castaneda et al. "Faster hashing to G2"
-        // by computing:
-        //
-        // result = elt^(q^3 * (12*z^3 + 6z^2 + 4z - 1) +
-        //               q^2 * (12*z^3 + 6z^2 + 6z) +
-        //               q   * (12*z^3 + 6z^2 + 4z) +
-        //               1   * (12*z^3 + 12z^2 + 6z + 1))
-        // which equals
-        //
-        // result = elt^( 2z * ( 6z^2 + 3z + 1 ) * (q^4 - q^2 + 1)/r ).
-        5 => hard_part_y0(accounts_iter, j),
-        6 => hard_part_y1(accounts_iter),
-        7 => hard_part_y3(accounts_iter),
-        8 => hard_part_y4(accounts_iter, j),
-        9 => hard_part_y6(accounts_iter, j),
-        10 => hard_part_y8(accounts_iter),
-        11 => hard_part_y9(accounts_iter),
-        12 => hard_part_y11(accounts_iter),
-        13 => hard_part_y13(accounts_iter),
-        14 => hard_part_y14(accounts_iter),
-        15 => hard_part_y15(accounts_iter),
-        16 => hard_part_y16(accounts_iter),
-        _ => {}
+    match stage {
+        PrepareFinal(index, input) => match index.i {
+            _ if !input.0.is_empty() => Err(ProgramError::InvalidInstructionData),
+            PREPARE_PROOF => prepare_final_data(scratch, accounts_iter),
+            PREPARE_PAIRING => prepare_pairing(scratch, accounts_iter),
+            _ => Err(ProgramError::InvalidInstructionData),
+        },
+        // Easy and hard part, see `groth16_sol_core::final_exponentiation`
+        EasyPart1(_) => easy_part1(scratch, accounts_iter),
+        EasyPart2(_) => easy_part2(scratch, accounts_iter),
+        HardPartY0(index) => hard_part_y0(scratch, accounts_iter, naf_digits(index)?),
+        HardPartY1(_) => hard_part_y1(scratch, accounts_iter),
+        HardPartY3(_) => hard_part_y3(scratch, accounts_iter),
+        HardPartY4(index) => hard_part_y4(scratch, accounts_iter, naf_digits(index)?),
+        HardPartY6(index) => hard_part_y6(scratch, accounts_iter, naf_digits(index)?),
+        HardPartY8(_) => hard_part_y8(scratch, accounts_iter),
+        HardPartY9(_) => hard_part_y9(scratch, accounts_iter),
+        HardPartY11(_) => hard_part_y11(scratch, accounts_iter),
+        HardPartY13(_) => hard_part_y13(scratch, accounts_iter),
+        HardPartY14(_) => hard_part_y14(scratch, accounts_iter),
+        HardPartY15(_) => hard_part_y15(scratch, accounts_iter),
+        HardPartY16(index, input) => hard_part_y16(scratch, accounts_iter, index.i, &input.0, vk),
+        _ => Err(ProgramError::InvalidInstructionData),
     }
-
-    Ok(())
 }
 
-fn prepare_final_data(accounts_iter: &mut Iter<AccountInfo>, input: &[u8]) {
-    let gamma_account = accounts_iter.next().unwrap();
-    let delta_account = accounts_iter.next().unwrap();
-    let final_account = accounts_iter.next().unwrap();
-
-    let qap = array_ref![input, 0, BN254_DATA_LEN];
-    let mut qap = Fp12::<Fq12Parameters>::read(&mut qap.as_ref()).unwrap();
-    qap *= get_account_data(gamma_account, 1);
-    qap *= get_account_data(delta_account, 1);
-
-    put_account_data(final_account, &qap);
-}
-
-fn easy_part1(accounts_iter: &mut Iter<AccountInfo>) {
-    let final_account = accounts_iter.next().unwrap();
-    let f = get_account_data(final_account, 1);
-
-    // f1 = r.conjugate() = f^(p^6)
-    let mut f1 = f;
-    f1.conjugate();
-    let f2 = f.inverse().unwrap();
-    let f = f1 * &f2;
-    put_account_data(final_account, &f);
+// The NAF digits an exponentiation-by-x step (y0, y4, y6) handles: `i` digits from
+// digit `j` on, one when `i` is 0 as older clients send. Several digits per step cut
+// the 63 transactions of each exponentiation, as far as the compute budget allows.
+pub fn naf_digits(index: &StepIndex) -> Result<Range<usize>, ProgramError> {
+    let start = index.j as usize;
+    let end = start + index.i.max(1) as usize;
+    if end > X_NAF.len() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    Ok(start..end)
 }
 
-fn easy_part2(accounts_iter: &mut Iter<AccountInfo>) {
-    let final_account = accounts_iter.next().unwrap();
-    let mut r = get_account_data(final_account, 1);
-
-    // f2 = f^(p^6 - 1)
-    // r = f^((p^6 - 1)(p^2))
-    // r = f^((p^6 - 1)(p^2) + (p^6 - 1))
-    // r = f^((p^6 - 1)(p^2 + 1))
-    let f2 = r;
-    r.frobenius_map(2);
-    r *= &f2;
-    put_account_data(final_account, &r);
+// What the final exponentiation starts from, in `i` of `PrepareFinal`: the product of a
+// proof's Miller loops, or the Miller loop of a pairing product (see `pairing.rs`).
+pub const PREPARE_PROOF: u8 = 0;
+pub const PREPARE_PAIRING: u8 = 1;
+
+// e(A, B) always comes from the AB account of the on-chain A·B loop, never from the
+// client, which could otherwise send whatever value makes the final check hold.
+fn prepare_final_data(scratch: &Scratch, accounts_iter: &mut Iter<AccountInfo>) -> ProgramResult {
+    let gamma_account = scratch.next_account(accounts_iter, Role::Gamma)?;
+    let delta_account = scratch.next_account(accounts_iter, Role::Delta)?;
+    let final_account = scratch.next_account(accounts_iter, Role::Final)?;
+    let ab_account = scratch.next_account(accounts_iter, Role::Ab)?;
+
+    let mut qap = get_account_data(ab_account)?;
+    qap *= get_account_data(gamma_account)?;
+    qap *= get_account_data(delta_account)?;
+
+    put_account_data(final_account, &qap)?;
+    let tags = [
+        read_tag(gamma_account)?,
+        read_tag(delta_account)?,
+        read_tag(ab_account)?,
+    ];
+    let tag = if tags.iter().all(is_bound) {
+        hashv(&[&tags[0], &tags[1], &tags[2]]).to_bytes()
+    } else {
+        UNBOUND_TAG
+    };
+    write_tag(final_account, &tag)
 }
 
-fn cal_y0(
-    f: &Fp12<Fq12Parameters>,
-    res: QuadExtField<Fp12ParamsWrapper<Fq12Parameters>>,
-    j: usize,
-) -> Fp12<Fq12Parameters> {
-    // y0
-    let mut inverse = f.clone();
-    inverse.conjugate();
-    let y0 = exp_by_neg_x(&f, &inverse, res, j);
-    y0
+fn easy_part1(scratch: &Scratch, accounts_iter: &mut Iter<AccountInfo>) -> ProgramResult {
+    let final_account = scratch.next_account(accounts_iter, Role::Final)?;
+    let mut f = get_account_data(final_account)?;
+    finish_loop(&mut f);
+    let f = fe::easy_part1(&f).ok_or(VerifierError::NotInvertible)?;
+    put_account_data(final_account, &f)
 }
 
-fn hard_part_y0(accounts_iter: &mut Iter<AccountInfo>, j: usize) {
-    let final_account = accounts_iter.next().unwrap();
-    let y0_account = accounts_iter.next().unwrap();
-    let r = get_account_data(final_account, 1);
-    let y0 = get_account_data(y0_account, j);
-    let mut y0 = cal_y0(&r, y0, j);
-    if j == 62 {
-        y0.conjugate();
-    }
-    put_account_data(y0_account, &y0);
+fn easy_part2(scratch: &Scratch, accounts_iter: &mut Iter<AccountInfo>) -> ProgramResult {
+    let final_account = scratch.next_account(accounts_iter, Role::Final)?;
+    let r = get_account_data(final_account)?;
+    put_account_data(final_account, &fe::easy_part2(&r))
 }
 
-fn hard_part_y1(accounts_iter: &mut Iter<AccountInfo>) {
-    let y0_account = accounts_iter.next().unwrap();
-    let y1_account = accounts_iter.next().unwrap();
-
-    let y0 = get_account_data(y0_account, 1);
-    let y1 = y0.cyclotomic_square();
-    put_account_data(y1_account, &y1);
+fn hard_part_y0(
+    scratch: &Scratch,
+    accounts_iter: &mut Iter<AccountInfo>,
+    digits: Range<usize>,
+) -> ProgramResult {
+    let final_account = scratch.next_account(accounts_iter, Role::Final)?;
+    let y0_account = scratch.next_account(accounts_iter, Role::Y(0))?;
+    let r = get_account_data(final_account)?;
+    let y0 = get_account_data(y0_account)?;
+    put_account_data(y0_account, &fe::exp_by_neg_x(&r, y0, digits))?;
+    accumulate_tag(y0_account, &read_tag(final_account)?)
 }
 
-fn cal_y3(f: &Fp12<Fq12Parameters>) -> Fp12<Fq12Parameters> {
-    // y1 y2 y3
-    let y1 = f.cyclotomic_square();
-    let y2 = y1.cyclotomic_square();
-    let y3 = y2 * &y1;
-    y3
+fn hard_part_y1(scratch: &Scratch, accounts_iter: &mut Iter<AccountInfo>) -> ProgramResult {
+    let y0_account = scratch.next_account(accounts_iter, Role::Y(0))?;
+    let y1_account = scratch.next_account(accounts_iter, Role::Y(1))?;
+    let y0 = get_account_data(y0_account)?;
+    put_account_data(y1_account, &fe::y1(&y0))?;
+    derive_tag(y1_account, &[y0_account])
 }
 
-fn hard_part_y3(accounts_iter: &mut Iter<AccountInfo>) {
-    let y0_account = accounts_iter.next().unwrap();
-    let y3_account = accounts_iter.next().unwrap();
-    let y0 = get_account_data(y0_account, 1);
-    let y3 = cal_y3(&y0);
-    put_account_data(y3_account, &y3);
+fn hard_part_y3(scratch: &Scratch, accounts_iter: &mut Iter<AccountInfo>) -> ProgramResult {
+    let y0_account = scratch.next_account(accounts_iter, Role::Y(0))?;
+    let y3_account = scratch.next_account(accounts_iter, Role::Y(3))?;
+    let y0 = get_account_data(y0_account)?;
+    put_account_data(y3_account, &fe::y3(&y0))?;
+    derive_tag(y3_account, &[y0_account])
 }
 
-fn cal_y4(
-    f: &Fp12<Fq12Parameters>,
-    res: QuadExtField<Fp12ParamsWrapper<Fq12Parameters>>,
-    j: usize,
-) -> Fp12<Fq12Parameters> {
-    let mut inverse = f.clone();
-    inverse.conjugate();
-    let y4 = exp_by_neg_x(&f, &inverse, res, j);
-    y4
+fn hard_part_y4(
+    scratch: &Scratch,
+    accounts_iter: &mut Iter<AccountInfo>,
+    digits: Range<usize>,
+) -> ProgramResult {
+    let y3_account = scratch.next_account(accounts_iter, Role::Y(3))?;
+    let y4_account = scratch.next_account(accounts_iter, Role::Y(4))?;
+    let y3 = get_account_data(y3_account)?;
+    let y4 = get_account_data(y4_account)?;
+    put_account_data(y4_account, &fe::exp_by_neg_x(&y3, y4, digits))?;
+    accumulate_tag(y4_account, &read_tag(y3_account)?)
 }
 
-fn hard_part_y4(accounts_iter: &mut Iter<AccountInfo>, j: usize) {
-    let y3_account = accounts_iter.next().unwrap();
-    let y4_account = accounts_iter.next().unwrap();
-    let y3 = get_account_data(y3_account, 1);
-    let y4 = get_account_data(y4_account, j);
-    let mut y4 = cal_y4(&y3, y4, j);
-    if j == 62 {
-        y4.conjugate();
-    }
-    put_account_data(y4_account, &y4);
+fn hard_part_y6(
+    scratch: &Scratch,
+    accounts_iter: &mut Iter<AccountInfo>,
+    digits: Range<usize>,
+) -> ProgramResult {
+    let y4_account = scratch.next_account(accounts_iter, Role::Y(4))?;
+    let y6_account = scratch.next_account(accounts_iter, Role::Y(6))?;
+    let y5 = fe::y5(&get_account_data(y4_account)?);
+    let y6 = get_account_data(y6_account)?;
+    put_account_data(y6_account, &fe::exp_by_neg_x(&y5, y6, digits))?;
+    accumulate_tag(y6_account, &read_tag(y4_account)?)
 }
 
-fn cal_y6(
-    f: &Fp12<Fq12Parameters>,
-    res: QuadExtField<Fp12ParamsWrapper<Fq12Parameters>>,
-    j: usize,
-) -> Fp12<Fq12Parameters> {
-    // y6
-    let mut inverse = f.clone();
-    inverse.conjugate();
-    let y6 = exp_by_neg_x(&f, &inverse, res, j);
-    y6
+fn hard_part_y8(scratch: &Scratch, accounts_iter: &mut Iter<AccountInfo>) -> ProgramResult {
+    let y3_account = scratch.next_account(accounts_iter, Role::Y(3))?;
+    let y4_account = scratch.next_account(accounts_iter, Role::Y(4))?;
+    let y6_account = scratch.next_account(accounts_iter, Role::Y(6))?;
+    let y8_account = scratch.next_account(accounts_iter, Role::Y(8))?;
+
+    let y3 = get_account_data(y3_account)?;
+    let y4 = get_account_data(y4_account)?;
+    let y6 = get_account_data(y6_account)?;
+    put_account_data(y8_account, &fe::y8(&y3, &y4, &y6))?;
+    derive_tag(y8_account, &[y3_account, y4_account, y6_account])
 }
 
-fn hard_part_y6(accounts_iter: &mut Iter<AccountInfo>, j: usize) {
-    let y4_account = accounts_iter.next().unwrap();
-    let y6_account = accounts_iter.next().unwrap();
+fn hard_part_y9(scratch: &Scratch, accounts_iter: &mut Iter<AccountInfo>) -> ProgramResult {
+    let y1_account = scratch.next_account(accounts_iter, Role::Y(1))?;
+    let y8_account = scratch.next_account(accounts_iter, Role::Y(8))?;
+    let y9_account = scratch.next_account(accounts_iter, Role::Y(9))?;
 
-    let y4 = get_account_data(y4_account, 1);
-    let y5 = y4.cyclotomic_square();
-    let y6 = get_account_data(y6_account, j);
-    let mut y6 = cal_y6(&y5, y6, j);
-    if j == 62 {
-        y6.conjugate();
-    }
-    put_account_data(y6_account, &y6);
+    let y1 = get_account_data(y1_account)?;
+    let y8 = get_account_data(y8_account)?;
+    put_account_data(y9_account, &fe::y9(&y1, &y8))?;
+    derive_tag(y9_account, &[y1_account, y8_account])
 }
 
-fn hard_part_y8(accounts_iter: &mut Iter<AccountInfo>) {
-    let y3_account = accounts_iter.next().unwrap();
-    let y4_account = accounts_iter.next().unwrap();
-    let y6_account = accounts_iter.next().unwrap();
-    let y8_account = accounts_iter.next().unwrap();
-
-    let mut y3 = get_account_data(y3_account, 1);
-    let y4 = get_account_data(y4_account, 1);
-    let mut y6 = get_account_data(y6_account, 1);
-
-    y3.conjugate();
-    y6.conjugate();
-    let y7 = y6 * y4;
-    let y8 = y7 * y3;
-
-    put_account_data(y8_account, &y8);
+fn hard_part_y11(scratch: &Scratch, accounts_iter: &mut Iter<AccountInfo>) -> ProgramResult {
+    let y4_account = scratch.next_account(accounts_iter, Role::Y(4))?;
+    let y8_account = scratch.next_account(accounts_iter, Role::Y(8))?;
+    let final_account = scratch.next_account(accounts_iter, Role::Final)?;
+    let y11_account = scratch.next_account(accounts_iter, Role::Y(11))?;
+
+    let y4 = get_account_data(y4_account)?;
+    let y8 = get_account_data(y8_account)?;
+    let r = get_account_data(final_account)?;
+    put_account_data(y11_account, &fe::y11(&r, &y4, &y8))?;
+    derive_tag(y11_account, &[y4_account, y8_account, final_account])
 }
 
-fn hard_part_y9(accounts_iter: &mut Iter<AccountInfo>) {
-    let y1_account = accounts_iter.next().unwrap();
-    let y8_account = accounts_iter.next().unwrap();
-    let y9_account = accounts_iter.next().unwrap();
-
-    let y1 = get_account_data(y1_account, 1);
-    let y8 = get_account_data(y8_account, 1);
-
-    let y9 = y8 * y1;
+fn hard_part_y13(scratch: &Scratch, accounts_iter: &mut Iter<AccountInfo>) -> ProgramResult {
+    let y9_account = scratch.next_account(accounts_iter, Role::Y(9))?;
+    let y11_account = scratch.next_account(accounts_iter, Role::Y(11))?;
+    let y13_account = scratch.next_account(accounts_iter, Role::Y(13))?;
 
-    put_account_data(y9_account, &y9);
+    let y9 = get_account_data(y9_account)?;
+    let y11 = get_account_data(y11_account)?;
+    put_account_data(y13_account, &fe::y13(&y9, &y11))?;
+    derive_tag(y13_account, &[y9_account, y11_account])
 }
 
-fn hard_part_y11(accounts_iter: &mut Iter<AccountInfo>) {
-    let y4_account = accounts_iter.next().unwrap();
-    let y8_account = accounts_iter.next().unwrap();
-    let final_account = accounts_iter.next().unwrap();
-    let y11_account = accounts_iter.next().unwrap();
+fn hard_part_y14(scratch: &Scratch, accounts_iter: &mut Iter<AccountInfo>) -> ProgramResult {
+    let y8_account = scratch.next_account(accounts_iter, Role::Y(8))?;
+    let y13_account = scratch.next_account(accounts_iter, Role::Y(13))?;
+    let y14_account = scratch.next_account(accounts_iter, Role::Y(14))?;
 
-    let y4 = get_account_data(y4_account, 1);
-    let y8 = get_account_data(y8_account, 1);
-    let r = get_account_data(final_account, 1);
-
-    let y11 = y8 * y4 * r;
-
-    put_account_data(y11_account, &y11);
-}
-
-fn hard_part_y13(accounts_iter: &mut Iter<AccountInfo>) {
-    let y9_account = accounts_iter.next().unwrap();
-    let y11_account = accounts_iter.next().unwrap();
-    let y13_account = accounts_iter.next().unwrap();
-
-    let y9 = get_account_data(y9_account, 1);
-    let y11 = get_account_data(y11_account, 1);
-
-    let mut y12 = y9;
-    y12.frobenius_map(1);
-    let y13 = y12 * y11;
-
-    put_account_data(y13_account, &y13);
+    let y8 = get_account_data(y8_account)?;
+    let y13 = get_account_data(y13_account)?;
+    put_account_data(y14_account, &fe::y14(&y8, &y13))?;
+    derive_tag(y14_account, &[y8_account, y13_account])
 }
 
-fn hard_part_y14(accounts_iter: &mut Iter<AccountInfo>) {
-    let y8_account = accounts_iter.next().unwrap();
-    let y13_account = accounts_iter.next().unwrap();
-    let y14_account = accounts_iter.next().unwrap();
+fn hard_part_y15(scratch: &Scratch, accounts_iter: &mut Iter<AccountInfo>) -> ProgramResult {
+    let y9_account = scratch.next_account(accounts_iter, Role::Y(9))?;
+    let final_account = scratch.next_account(accounts_iter, Role::Final)?;
+    let y15_account = scratch.next_account(accounts_iter, Role::Y(15))?;
 
-    let mut y8 = get_account_data(y8_account, 1);
-    let y13 = get_account_data(y13_account, 1);
-
-    y8.frobenius_map(2);
-    let y14 = y8 * y13;
-
-    put_account_data(y14_account, &y14);
+    let r = get_account_data(final_account)?;
+    let y9 = get_account_data(y9_account)?;
+    put_account_data(y15_account, &fe::y15(&r, &y9))?;
+    derive_tag(y15_account, &[y9_account, final_account])
 }
 
-fn hard_part_y15(accounts_iter: &mut Iter<AccountInfo>) {
-    let y9_account = accounts_iter.next().unwrap();
-    let final_account = accounts_iter.next().unwrap();
-    let y15_account = accounts_iter.next().unwrap();
-
-    let mut r = get_account_data(final_account, 1);
-    let y9 = get_account_data(y9_account, 1);
-
-    r.conjugate();
-    let mut y15 = r * y9;
-    y15.frobenius_map(3);
-
-    put_account_data(y15_account, &y15);
-}
+// `flags` selects what follows the check, in this order:
+//   FLAG_INLINE_VK: compare against the 384-byte alpha_g1_beta_g2 at the start of the
+//     input instead of the baked one, for stateless-VK verification, or against any
+//     target of a pairing product
+//   FLAG_TARGET_ONE: compare against one, as pairing products (see `pairing.rs`)
+//     usually are, taking nothing from the input
+//   FLAG_RECORD_RESULT: append the outcome to the result log account that follows,
+//     under the id of the key (see `utils::vk_id`) and the hash of the public inputs,
+//     instead of failing the transaction on a mismatch
+//   FLAG_WRITE_RESULT: write the outcome, the hash of y16 and the slot to the session's
+//     result account that follows (see `write_result`), also instead of failing on a
+//     mismatch
+//   FLAG_RETURN_DATA: set the outcome as the instruction's return data (see
+//     `return_data.rs`) for a calling program, with the same key id and public-input
+//     hash and the inputs' source, also instead of failing on a mismatch
+//   FLAG_NULLIFIER: on success, spend the nullifier of the public inputs, creating its
+//     account from the two accounts that follow, the nullifier account and the system
+//     program (see `nullifier.rs`); fails on a nullifier spent before
+//   FLAG_RECEIPT: on success, create the receipt of the statement, as the
+//     single-instruction verification does with `RECEIPT_FLAG`, from the two accounts
+//     that follow, the receipt account and the system program, at the payer's expense
+//     (see `receipt.rs`)
+//   FLAG_CALLBACK: on success, make the cross-program callback described by the rest of
+//     the input and the remaining accounts, passing the target the key id and public-input
+//     hash (see `invoke_callback`)
+// FLAG_RECORD_RESULT, FLAG_RETURN_DATA, FLAG_NULLIFIER, FLAG_RECEIPT and FLAG_CALLBACK
+// attest to a statement, so they take the session's inputs account right after y14 and
+// y15 and read the key id, source and public-input hash from it, never from the client.
+// Their input starts with the proof checked, encoding (see `point.rs`) || A || B || C,
+// and with FLAG_RECEIPT goes on with its public inputs, 32-byte little-endian values,
+// as `VerifyComplete` takes them. The step fails with `UnboundInputs` unless the Miller
+// loops ran on exactly this proof and these inputs (see
+// `public_inputs::bound_statement`), which rules out a client's prepared input and
+// inline coefficients, and unless the public inputs are those folded. A target given
+// inline or one names no key, so neither takes these flags.
+pub const FLAG_RECORD_RESULT: u8 = 1;
+pub const FLAG_CALLBACK: u8 = 2;
+pub const FLAG_INLINE_VK: u8 = 4;
+pub const FLAG_WRITE_RESULT: u8 = 8;
+pub const FLAG_RETURN_DATA: u8 = 16;
+pub const FLAG_NULLIFIER: u8 = 32;
+pub const FLAG_TARGET_ONE: u8 = 64;
+pub const FLAG_RECEIPT: u8 = 128;
+const STATEMENT_FLAGS: u8 =
+    FLAG_RECORD_RESULT | FLAG_RETURN_DATA | FLAG_NULLIFIER | FLAG_RECEIPT | FLAG_CALLBACK;
+
+fn hard_part_y16<'a>(
+    scratch: &Scratch<'_, 'a>,
+    accounts_iter: &mut Iter<AccountInfo<'a>>,
+    flags: u8,
+    input: &[u8],
+    vk: Option<&OnChainVk>,
+) -> ProgramResult {
+    let y14_account = scratch.next_account(accounts_iter, Role::Y(14))?;
+    let y15_account = scratch.next_account(accounts_iter, Role::Y(15))?;
 
-fn hard_part_y16(accounts_iter: &mut Iter<AccountInfo>) {
-    let y14_account = accounts_iter.next().unwrap();
-    let y15_account = accounts_iter.next().unwrap();
+    let y14 = get_account_data(y14_account)?;
+    let y15 = get_account_data(y15_account)?;
 
-    let y14 = get_account_data(y14_account, 1);
-    let y15 = get_account_data(y15_account, 1);
+    // what y16 must equal, alpha_g1_beta_g2 for a proof
+    let mut input = input;
+    let target = if flags & FLAG_TARGET_ONE != 0 {
+        if vk.is_some() || flags & FLAG_INLINE_VK != 0 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        Fq12::one()
+    } else if flags & FLAG_INLINE_VK != 0 {
+        if vk.is_some() || input.len() < BN254_DATA_LEN {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (target, rest) = input.split_at(BN254_DATA_LEN);
+        input = rest;
+        Fp12::<Fq12Parameters>::read(target).map_err(|_| VerifierError::InvalidEncoding)?
+    } else if let Some(vk) = vk {
+        vk.alpha_g1_beta_g2()?
+    } else {
+        get_alpha_g1_beta_g2()
+    };
+    let mut receipt = None;
+    let statement = if flags & STATEMENT_FLAGS == 0 {
+        Statement::default()
+    } else if flags & (FLAG_TARGET_ONE | FLAG_INLINE_VK) != 0 {
+        return Err(ProgramError::InvalidInstructionData);
+    } else {
+        let num_public_inputs = vk.map_or(NUM_PUBLIC_INPUTS, |vk| vk.num_public_inputs);
+        if flags & FLAG_NULLIFIER != 0 && !(0..num_public_inputs).contains(&NULLIFIER_INPUT) {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let mut points = PointReader::new(input)?;
+        let (a, b, c) = (points.g1()?, points.g2()?, points.g1()?);
+        input = points.rest();
+        let inputs_account = scratch.next_account(accounts_iter, Role::Inputs)?;
+        let tag = join_tags(&[read_tag(y14_account)?, read_tag(y15_account)?]);
+        let statement = bound_statement(inputs_account, &tag, &vk_id(vk), &a, &b, &c)?;
+        if flags & FLAG_RECEIPT != 0 {
+            let len = num_public_inputs * FR_DATA_LEN;
+            if input.len() < len {
+                return Err(VerifierError::InvalidDataLength.into());
+            }
+            let (public_inputs, rest) = input.split_at(len);
+            input = rest;
+            if hash(public_inputs).to_bytes() != statement.inputs_hash {
+                return Err(VerifierError::UnboundInputs.into());
+            }
+            let public_inputs = instruction_inputs(public_inputs, num_public_inputs)?;
+            receipt = Some(statement_hash(&a, &b, &c, &public_inputs, &statement.vk_id));
+        }
+        statement
+    };
+    let y16 = fe::y16(&y14, &y15);
+    let valid = y16 == target;
+    log_final_check(scratch, flags, valid);
+
+    if flags & FLAG_RECORD_RESULT != 0 {
+        let log_account = next_account_info(accounts_iter)?;
+        append_result(
+            scratch,
+            log_account,
+            &statement.vk_id,
+            &statement.inputs_hash,
+            valid,
+        )?;
+    }
+    if flags & FLAG_WRITE_RESULT != 0 {
+        let result_account = next_account_info(accounts_iter)?;
+        write_result(
+            scratch,
+            result_account,
+            &hash(&to_bytes!(y16).unwrap()).to_bytes(),
+            valid,
+        )?;
+    }
+    if flags & FLAG_RETURN_DATA != 0 {
+        set_outcome(&Outcome {
+            valid,
+            vk_id: statement.vk_id,
+            input_hash: statement.inputs_hash,
+            source_id: statement.source_id,
+        });
+    }
+    if flags & (FLAG_RECORD_RESULT | FLAG_WRITE_RESULT | FLAG_RETURN_DATA) == 0 && !valid {
+        return Err(VerifierError::InvalidProof.into());
+    }
 
-    let y16 = y15 * &y14;
-    let alpha_g1_beta_g2 = get_alpha_g1_beta_g2();
-    assert!(y16 == alpha_g1_beta_g2);
-}
+    if flags & FLAG_NULLIFIER != 0 && valid {
+        spend_nullifier(
+            scratch,
+            accounts_iter,
+            &statement.vk_id,
+            &statement.nullifier,
+        )?;
+    }
 
-fn exp_by_neg_x(
-    fe: &QuadExtField<Fp12ParamsWrapper<Fq12Parameters>>,
-    self_inverse: &QuadExtField<Fp12ParamsWrapper<Fq12Parameters>>,
-    mut res: QuadExtField<Fp12ParamsWrapper<Fq12Parameters>>,
-    j: usize,
-) -> Fp12<Fq12Parameters> {
-    let value = NAF[j];
-    if j > 0 {
-        res.square_in_place();
+    if let (Some(statement_hash), true) = (receipt, valid) {
+        let receipt_account = next_account_info(accounts_iter)?;
+        let system_program = next_account_info(accounts_iter)?;
+        create_receipt(
+            scratch.program_id(),
+            scratch.payer(),
+            receipt_account,
+            system_program,
+            &statement_hash,
+        )?;
     }
 
-    if value != 0 {
-        if value > 0 {
-            res *= fe;
-        } else {
-            res *= self_inverse;
-        }
+    if flags & FLAG_CALLBACK != 0 && valid {
+        invoke_callback(accounts_iter, &statement, input)?;
     }
-    res
+    Ok(())
 }
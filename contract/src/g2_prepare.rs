@@ -0,0 +1,188 @@
+use std::slice::Iter;
+
+use ark_ff::{to_bytes, One, Zero};
+use solana_program::account_info::{next_account_info, AccountInfo};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+
+use groth16_sol_core::miller_loop::{g2_step_coeffs, step_range};
+
+use crate::curve::{Fq2, LAST_STEP_LINES, MILLER_LOOP_LAST_STEP};
+use crate::error::VerifierError;
+use crate::miller_loop::{read_projective, write_projective};
+use crate::point::PointReader;
+use crate::scratch::{Role, Scratch, SCRATCH_HEADER_LEN};
+use crate::vk::{
+    check_writing, parse_on_chain_vk, ELL_COEFF_DATA_LEN, FP2_DATA_LEN, VK_ACCOUNT_HEADER_LEN,
+};
+
+// The gamma and delta coefficient tables of a locked VK upload (see `vk.rs`) computed
+// by the program from the key's own G2 points, so the tables need not be trusted to be
+// their precomputation. proof.B needs no such step: `ab_miller_loop` derives its lines
+// as it goes.
+//
+// A point is prepared over the (i, j) schedule of the chunked Miller loops, `steps`
+// steps per instruction as for `GammaMillerLoopSteps`: step (i, j) writes the
+// coefficients from j on that `G2Prepared::from` computes for digit i - 1, or for the
+// Frobenius images of the point on the last step. The running point R lives in the
+// session's G2 scratch account in between. The tables hold the lines of the negated
+// points, so the instruction takes gamma_g2 or delta_g2 as the verifying key has them.
+//
+// instruction data: t = 31, i, j, steps || table (`GAMMA_TABLE` or `DELTA_TABLE`) ||
+//                   encoding (see `point.rs`) || point (G2Affine) || session id
+// accounts: [payer (signer), G2 account (writable), vk account (writable),
+//            upload authority (signer)]
+//
+// The key must still be writing and its header already written, with
+// `MILLER_LOOP_LAST_STEP + LAST_STEP_LINES` coefficients per table. The authority could
+// still overwrite the tables with chunks, but the hash it finalizes the key with covers
+// them.
+//
+// G2 account layout, after the scratch header (see `scratch.rs`):
+//   0..192  R in homogeneous projective coordinates: x, y, z (Fp2 each)
+pub const GAMMA_TABLE: u8 = 0;
+pub const DELTA_TABLE: u8 = 1;
+pub const G2_DATA_LEN: usize = 3 * FP2_DATA_LEN;
+
+pub fn prepare_g2(
+    scratch: &Scratch,
+    accounts_iter: &mut Iter<AccountInfo>,
+    i: usize,
+    j: usize,
+    steps: usize,
+    input: &[u8],
+) -> ProgramResult {
+    let g2_account = scratch.next_account(accounts_iter, Role::G2)?;
+    let vk_account = next_account_info(accounts_iter)?;
+    let authority = next_account_info(accounts_iter)?;
+    let (&table, input) = input
+        .split_first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    let mut points = PointReader::new(input)?;
+    let q = -points.g2()?;
+    points.finish()?;
+    if q.is_zero() || !q.is_on_curve() {
+        return Err(VerifierError::InvalidEncoding.into());
+    }
+    let range = step_range(i, j, steps).ok_or(ProgramError::InvalidInstructionData)?;
+
+    let mut data = vk_account.try_borrow_mut_data()?;
+    check_writing(scratch.program_id(), vk_account, &data, authority)?;
+    let vk = parse_on_chain_vk(&data[VK_ACCOUNT_HEADER_LEN..])?;
+    let (num_coeffs, tables) = (vk.num_coeffs, vk.tables());
+    if num_coeffs != MILLER_LOOP_LAST_STEP + LAST_STEP_LINES {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let offset = VK_ACCOUNT_HEADER_LEN
+        + tables.start
+        + match table {
+            GAMMA_TABLE => 0,
+            DELTA_TABLE => num_coeffs * ELL_COEFF_DATA_LEN,
+            _ => return Err(ProgramError::InvalidInstructionData),
+        };
+
+    let mut r = if j == 0 {
+        [q.x, q.y, Fq2::one()]
+    } else {
+        read_projective(g2_account, SCRATCH_HEADER_LEN)?
+    };
+    for (i, j) in range {
+        let coeffs = g2_step_coeffs(&mut r, q, i);
+        if j + coeffs.len() > num_coeffs {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        for (k, (c0, c1, c2)) in coeffs.iter().enumerate() {
+            let start = offset + (j + k) * ELL_COEFF_DATA_LEN;
+            let bytes = to_bytes!(c0, c1, c2).unwrap();
+            data[start..start + ELL_COEFF_DATA_LEN].copy_from_slice(&bytes);
+        }
+    }
+    write_projective(g2_account, SCRATCH_HEADER_LEN, &r)
+}
+
+#[cfg(test)]
+mod test {
+    use circuit::PointEncoding::{Compressed, Uncompressed};
+    use circuit::{to_on_chain_vk, write_g2};
+    use solana_program::hash::hashv;
+    use solana_program::pubkey::Pubkey;
+
+    use super::*;
+    use crate::process_instruction;
+    use crate::schedule::prepare_g2_steps;
+    use crate::test_utils::{scratch_data, TestAccount, SESSION_ID};
+    use crate::vk::{FINALIZE_VK, INIT_VK_ACCOUNT, WRITE_VK_CHUNK};
+
+    #[test]
+    fn test_prepare_vk_tables() {
+        let (pvk, _, _) = circuit::demo_proof().unwrap();
+        let key = to_on_chain_vk(&pvk.vk).unwrap();
+        let program_id = Pubkey::new_unique();
+        let mut payer = TestAccount::payer();
+        let mut authority = TestAccount::payer();
+        let mut g2_account = TestAccount::scratch(&program_id, SESSION_ID, Role::G2);
+        let mut vk_account = TestAccount::new(&program_id, VK_ACCOUNT_HEADER_LEN + key.len());
+
+        let mut upload = |vk_account: &mut TestAccount, operation: u8, input: &[u8]| {
+            let data = [&[20, operation, 0][..], input].concat();
+            let infos = [vk_account.info(), authority.info()];
+            process_instruction(&program_id, &infos, &data)
+        };
+        vk_account.is_signer = true;
+        upload(&mut vk_account, INIT_VK_ACCOUNT, &[]).unwrap();
+        vk_account.is_signer = false;
+
+        // the key but its tables, which stay zero
+        let tables = parse_on_chain_vk(&key).unwrap().tables();
+        let head = [&[0; 4][..], &key[..tables.start]].concat();
+        upload(&mut vk_account, WRITE_VK_CHUNK, &head).unwrap();
+        let tail = [&(tables.end as u32).to_le_bytes()[..], &key[tables.end..]].concat();
+        upload(&mut vk_account, WRITE_VK_CHUNK, &tail).unwrap();
+
+        let mut prepare = |vk_account: &mut TestAccount, data: &[u8]| {
+            let mut authority = TestAccount::payer();
+            let infos = [
+                payer.info(),
+                g2_account.info(),
+                vk_account.info(),
+                authority.info(),
+            ];
+            process_instruction(&program_id, &infos, &scratch_data(data, SESSION_ID))
+        };
+        let point = |q, encoding| [&[encoding as u8][..], &write_g2(q, encoding)].concat();
+        let gamma = point(&pvk.vk.gamma_g2, Compressed);
+        let delta = point(&pvk.vk.delta_g2, Uncompressed);
+        for data in prepare_g2_steps(GAMMA_TABLE, &gamma, 3) {
+            prepare(&mut vk_account, &data).unwrap();
+        }
+        for data in prepare_g2_steps(DELTA_TABLE, &delta, 64) {
+            prepare(&mut vk_account, &data).unwrap();
+        }
+        assert_eq!(&vk_account.data[VK_ACCOUNT_HEADER_LEN..], &key[..]);
+
+        // there are only two tables (2 selects the pairing-product loop), and a point
+        // off the curve has none
+        let mut data = prepare_g2_steps(GAMMA_TABLE, &gamma, 1).remove(0);
+        data[4] = 3;
+        assert_eq!(
+            prepare(&mut vk_account, &data),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        let mut off_curve = pvk.vk.gamma_g2;
+        off_curve.y.c0 += Fq2::one().c0;
+        let data = prepare_g2_steps(GAMMA_TABLE, &point(&off_curve, Uncompressed), 1).remove(0);
+        assert_eq!(
+            prepare(&mut vk_account, &data),
+            Err(VerifierError::InvalidEncoding.into())
+        );
+
+        // the key the tables make up finalizes, after which they are locked
+        let hash = hashv(&[&key]).to_bytes();
+        upload(&mut vk_account, FINALIZE_VK, &hash).unwrap();
+        let data = prepare_g2_steps(GAMMA_TABLE, &gamma, 1).remove(0);
+        assert_eq!(
+            prepare(&mut vk_account, &data),
+            Err(VerifierError::VkLocked.into())
+        );
+    }
+}
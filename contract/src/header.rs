@@ -0,0 +1,148 @@
+use solana_program::account_info::AccountInfo;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use crate::error::VerifierError;
+use crate::scratch::{Role, SCRATCH_VERSION};
+
+// Every account the program writes starts with a header naming what it holds, written
+// when the program creates the account and checked before each read and write. An
+// account of one kind is thereby never read or written as another: a step handed a
+// registry entry where it expects a result account, or an account the program never set
+// up, fails with `InvalidAccountHeader` instead of overwriting it.
+//
+// account header layout:
+//   0..4  magic, b"G16S"
+//   4     layout version of the account's kind
+//   5     kind, see `AccountKind::to_u8`
+//   6..8  reserved, zero
+//
+// Scratch accounts continue with their binding tag (see `scratch.rs`), every other kind
+// with its data. VK accounts are the exception: a locked upload has a header of its own
+// (see `vk.rs`), a bare one is written under its own keypair only.
+pub const ACCOUNT_MAGIC: [u8; 4] = *b"G16S";
+pub const ACCOUNT_HEADER_LEN: usize = 8;
+// Layout version of every kind but scratch accounts, which count their own
+pub const ACCOUNT_VERSION: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountKind {
+    // a session's scratch account in its role, see `scratch.rs`
+    Scratch(Role),
+    // see `receipt.rs`
+    Receipt,
+    // the registry account and a registry entry, see `registry.rs`
+    Registry,
+    VkEntry,
+    // see `nullifier.rs`
+    Nullifier,
+    // see `attestation.rs`
+    Attestation,
+    // see `result_log.rs`
+    ResultLog,
+}
+
+impl AccountKind {
+    // The kind byte: the role byte of a scratch account, below 0x80, and 0x80 on for the
+    // others.
+    pub fn to_u8(self) -> u8 {
+        match self {
+            AccountKind::Scratch(role) => role.to_u8(),
+            AccountKind::Receipt => 0x80,
+            AccountKind::Registry => 0x81,
+            AccountKind::VkEntry => 0x82,
+            AccountKind::Nullifier => 0x83,
+            AccountKind::Attestation => 0x84,
+            AccountKind::ResultLog => 0x85,
+        }
+    }
+
+    pub fn version(self) -> u8 {
+        match self {
+            AccountKind::Scratch(_) => SCRATCH_VERSION,
+            _ => ACCOUNT_VERSION,
+        }
+    }
+
+    pub fn header(self) -> [u8; ACCOUNT_HEADER_LEN] {
+        let mut header = [0; ACCOUNT_HEADER_LEN];
+        header[..4].copy_from_slice(&ACCOUNT_MAGIC);
+        header[4] = self.version();
+        header[5] = self.to_u8();
+        header
+    }
+}
+
+// Check that account data starts with the header of `kind`.
+pub fn check_account_header(data: &[u8], kind: AccountKind) -> ProgramResult {
+    if data.len() < ACCOUNT_HEADER_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    if data[..ACCOUNT_HEADER_LEN] != kind.header() {
+        return Err(VerifierError::InvalidAccountHeader.into());
+    }
+    Ok(())
+}
+
+// Check that `account` is the program's account at `address`, holding `kind`.
+pub fn check_program_account(
+    program_id: &Pubkey,
+    account: &AccountInfo,
+    address: &Pubkey,
+    kind: AccountKind,
+) -> ProgramResult {
+    if account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if account.key != address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    check_account_header(&account.try_borrow_data()?, kind)
+}
+
+// Write the header of `kind` into the data of an account the program just created.
+pub fn write_account_header(account: &AccountInfo, kind: AccountKind) -> ProgramResult {
+    let mut data = account.try_borrow_mut_data()?;
+    if data.len() < ACCOUNT_HEADER_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    data[..ACCOUNT_HEADER_LEN].copy_from_slice(&kind.header());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::scratch::ROLE_COUNT;
+
+    // No two kinds share a header, whatever their role.
+    #[test]
+    fn test_kinds_are_distinct() {
+        let kinds = (0..ROLE_COUNT)
+            .map(|role| AccountKind::Scratch(Role::from_u8(role).unwrap()))
+            .chain(vec![
+                AccountKind::Receipt,
+                AccountKind::Registry,
+                AccountKind::VkEntry,
+                AccountKind::Nullifier,
+                AccountKind::Attestation,
+                AccountKind::ResultLog,
+            ])
+            .collect::<Vec<_>>();
+        for (i, kind) in kinds.iter().enumerate() {
+            for other in kinds[i + 1..].iter() {
+                assert_ne!(kind.header(), other.header());
+                assert_eq!(
+                    check_account_header(&other.header(), *kind),
+                    Err(VerifierError::InvalidAccountHeader.into())
+                );
+            }
+            assert_eq!(check_account_header(&kind.header(), *kind), Ok(()));
+        }
+        assert_eq!(
+            check_account_header(&[0; 4], AccountKind::Receipt),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use std::io::{self, Write};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::program_error::ProgramError;
+
+use crate::utils::{pack_index, unpack_index};
+
+// Every instruction the program accepts, Borsh-encoded: the variant index (1 byte) is
+// the instruction tag `t`, followed by the step index `i, j` and the instruction's
+// payload. The encoding is the `t, i, j, input` layout the program has always read, so
+// instructions built by hand or by older clients decode unchanged.
+//
+// Bits of the first byte that select how a step is run rather than what it does are not
+// part of the instruction: `SESSION_FLAG`, `VK_ACCOUNT_FLAG` and `INLINE_COEFFS_FLAG` are
+// set on the packed tag and stripped by `process_instruction` before decoding. The
+// payloads the session flag and the inline flag add travel in the `Payload`.
+//
+// A payload is exactly as long as its layout; data that ends early or runs on fails
+// with `InvalidDataLength` instead of being read short or ignored. Points follow an
+// encoding byte (see `point.rs`), sizes given uncompressed / compressed:
+//
+//   GammaMillerLoop(Steps)  [coefficients] || encoding || prepared input (96 / 32),
+//                           or [coefficients] alone to read the folded inputs
+//   DeltaMillerLoop(Steps)  [coefficients] || encoding || proof.c (65 / 32)
+//   GammaDeltaMillerLoop    [gamma coefficients] || [delta coefficients] || encoding ||
+//                           proof.c || the gamma loop's payload after its coefficients
+//   AbMillerLoop            encoding || A (65 / 32) || B (129 / 64)
+//   VerifyComplete          encoding || A || B || C || 32 bytes per public input
+//   VerifyBatch             encoding || (A || B || C || public inputs) per proof
+//   PrepareG2               table (1) || encoding || point (129 / 64)
+//
+// where [coefficients] are the `step_coeff_count` ell coefficients of each step, 192
+// bytes apiece, with `INLINE_COEFFS_FLAG` only. The other payloads are laid out where
+// they are parsed.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub enum VerifierInstruction {
+    // 0: gamma Miller loop over the prepared input (G1Projective behind its encoding
+    // byte, see `point.rs`), or over the folded inputs account when the payload is empty
+    GammaMillerLoop(StepIndex, Payload),
+    // 1: delta Miller loop over proof.c (G1Affine behind its encoding byte)
+    DeltaMillerLoop(StepIndex, Payload),
+    // 2: multiply the Miller-loop results together with e(A, B) from the AB account, the
+    // payload empty; with i = `PREPARE_PAIRING`, take the Miller loop of a pairing product
+    // instead, see `pairing.rs`
+    PrepareFinal(StepIndex, Payload),
+    // 3, 4: easy part of the final exponentiation
+    EasyPart1(StepIndex),
+    EasyPart2(StepIndex),
+    // 5..=15: hard part of the final exponentiation, one instruction per y_k; the
+    // exponentiations by x (y0, y4, y6) take steps of `i` NAF digits from digit `j` on,
+    // one digit when `i` is 0
+    HardPartY0(StepIndex),
+    HardPartY1(StepIndex),
+    HardPartY3(StepIndex),
+    HardPartY4(StepIndex),
+    HardPartY6(StepIndex),
+    HardPartY8(StepIndex),
+    HardPartY9(StepIndex),
+    HardPartY11(StepIndex),
+    HardPartY13(StepIndex),
+    HardPartY14(StepIndex),
+    HardPartY15(StepIndex),
+    // 16: final check; `i` holds the `FLAG_*` bits selecting the payload
+    HardPartY16(StepIndex, Payload),
+    // 17: record an off-chain verification, see `attestation.rs`
+    RecordAttestation(StepIndex, Payload),
+    // 18: fold public inputs on-chain, see `public_inputs.rs`
+    FoldPublicInputs(StepIndex, Payload),
+    // 19: independent instructions run as one, see `packed.rs`
+    Packed(StepIndex, Payload),
+    // 20: upload part of a verifying key, the operation in `i`, see `vk.rs`
+    WriteVk(StepIndex, Payload),
+    // 21: Miller loop of the proof's A and B, see `ab_miller_loop`
+    AbMillerLoop(StepIndex, Payload),
+    // 22: allocate (j = `CREATE_SCRATCH`) or re-initialize (j = `INIT_SCRATCH`) the
+    // scratch account with role `i`, see `create_scratch` and `init_scratch`, or create
+    // the payer's result log with room for `i` records (j = `CREATE_RESULT_LOG`), see
+    // `result_log.rs`
+    CreateScratch(StepIndex),
+    // 23: the whole check over the alt_bn128 pairing syscall, see `alt_bn128.rs`; only
+    // built with the `alt-bn128` feature
+    VerifyAltBn128(StepIndex, Payload),
+    // 24: the whole verification from the proof and raw public inputs, see
+    // `verify_complete.rs`
+    VerifyComplete(StepIndex, Payload),
+    // 25: several proofs against one key checked together, see `verify_batch.rs`
+    VerifyBatch(StepIndex, Payload),
+    // 26: close (i = `CLOSE_SESSION`) or reset (i = `RESET_SESSION`) the session's
+    // scratch accounts, the role of each in the payload, see `close_session` and
+    // `reset_session`
+    CloseSession(StepIndex, Payload),
+    // 27, 28: that many consecutive gamma or delta Miller-loop steps from (i, j) on in
+    // one instruction, with the payload of a single step
+    GammaMillerLoopSteps(StepIndex, u8, Payload),
+    DeltaMillerLoopSteps(StepIndex, u8, Payload),
+    // 29: that many steps of the gamma and the delta loop together, see
+    // `gamma_delta_miller_loop`
+    GammaDeltaMillerLoop(StepIndex, u8, Payload),
+    // 30: set up the VK registry or register a key in it, the operation in `i`, see
+    // `registry.rs`
+    VkRegistry(StepIndex, Payload),
+    // 31: that many steps of the ell coefficients of a G2 point written into a VK
+    // account being uploaded, see `g2_prepare.rs`, or, behind `PAIRING_MILLER_LOOP`,
+    // of the Miller loop of a pairing product, see `pairing.rs`
+    PrepareG2(StepIndex, u8, Payload),
+}
+
+// Position of a step within its stage: the ATE loop index `i` and ell coefficient index
+// `j` of a Miller-loop step, the NAF digit `j` of a hard-part exponentiation. Other
+// steps send zeros unless documented otherwise. Encoded as one index word through
+// `pack_index` (see `utils.rs`), `i` in its low byte and `j` in its high byte, which is
+// the `i, j` byte layout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StepIndex {
+    pub i: u8,
+    pub j: u8,
+}
+
+impl StepIndex {
+    pub fn new(i: u8, j: u8) -> StepIndex {
+        StepIndex { i, j }
+    }
+
+    // The index word, see above.
+    pub fn index(&self) -> u16 {
+        u16::from_le_bytes([self.i, self.j])
+    }
+
+    pub fn from_index(index: u16) -> StepIndex {
+        let [i, j] = index.to_le_bytes();
+        StepIndex { i, j }
+    }
+}
+
+impl BorshSerialize for StepIndex {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&pack_index(self.index()))
+    }
+}
+
+impl BorshDeserialize for StepIndex {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        let (index, rest) = unpack_index(buf)
+            .map_err(|_| io::Error::new(io::ErrorKind::UnexpectedEof, "missing step index"))?;
+        *buf = rest;
+        Ok(StepIndex::from_index(index))
+    }
+}
+
+// The rest of the instruction data, unprefixed. Only valid as the last field of a
+// variant.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Payload(pub Vec<u8>);
+
+impl BorshSerialize for Payload {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.0)
+    }
+}
+
+impl BorshDeserialize for Payload {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        let payload = buf.to_vec();
+        *buf = &[];
+        Ok(Payload(payload))
+    }
+}
+
+impl VerifierInstruction {
+    // The instruction data of this instruction.
+    pub fn pack(&self) -> Vec<u8> {
+        self.try_to_vec().unwrap()
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::try_from_slice(data).map_err(|_| ProgramError::InvalidInstructionData)
+    }
+
+    // Whether instructions tagged `tag` run on scratch accounts, and so take the payer
+    // and session id the accounts are derived from (see `scratch.rs`). Everything but
+    // attestations, packed instructions, VK uploads and registrations and
+    // single-instruction and batch verifications does.
+    pub fn runs_on_scratch(tag: u8) -> bool {
+        !matches!(tag, 17 | 19 | 20 | 23 | 24 | 25 | 30)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::VerifierInstruction::*;
+    use super::*;
+
+    #[test]
+    fn test_encoding_is_the_tag_layout() {
+        let gamma = GammaMillerLoop(StepIndex::new(64, 0), Payload(vec![7; 96]));
+        let data = gamma.pack();
+        assert_eq!(&data[..3], &[0, 64, 0]);
+        assert_eq!(&data[3..], &[7; 96][..]);
+        assert_eq!(VerifierInstruction::unpack(&data).unwrap(), gamma);
+
+        assert_eq!(
+            VerifierInstruction::unpack(&[16, 1, 0, 9]).unwrap(),
+            HardPartY16(StepIndex::new(1, 0), Payload(vec![9]))
+        );
+        assert_eq!(
+            VerifierInstruction::unpack(&[5, 0, 62]).unwrap(),
+            HardPartY0(StepIndex::new(0, 62))
+        );
+        assert_eq!(
+            AbMillerLoop(StepIndex::default(), Payload::default()).pack(),
+            [21, 0, 0]
+        );
+
+        // the index word, little-endian as `pack_index` writes it and the program reads it
+        let index = StepIndex::from_index(0x0102);
+        assert_eq!(index, StepIndex::new(2, 1));
+        assert_eq!(CreateScratch(index).pack(), [22, 0x02, 0x01]);
+        assert_eq!(
+            VerifierInstruction::unpack(&[22, 0x02, 0x01]).unwrap(),
+            CreateScratch(index)
+        );
+        assert_eq!(index.index(), 0x0102);
+
+        // unknown tags, missing indices and trailing data on steps without a payload
+        assert_eq!(
+            DeltaMillerLoopSteps(StepIndex::new(64, 0), 4, Payload(vec![7])).pack(),
+            [28, 64, 0, 4, 7]
+        );
+        assert!(VerifierInstruction::unpack(&[28, 64, 0]).is_err());
+
+        assert!(VerifierInstruction::unpack(&[32, 0, 0]).is_err());
+        assert!(VerifierInstruction::unpack(&[0, 0]).is_err());
+        assert!(VerifierInstruction::unpack(&[3, 0, 0, 1]).is_err());
+    }
+}
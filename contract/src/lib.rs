@@ -1,27 +1,770 @@
+use crate::attestation::record_attestation;
 use crate::final_exponentiation::final_exponentiation;
-use crate::miller_loop::{delta_miller_loop, gamma_miller_loop};
-use crate::utils::unpack_instruction_data;
-use solana_program::account_info::AccountInfo;
-use solana_program::entrypoint;
+use crate::g2_prepare::prepare_g2;
+use crate::instruction::VerifierInstruction::{self, *};
+use crate::miller_loop::{
+    ab_miller_loop, delta_miller_loop, gamma_delta_miller_loop, gamma_miller_loop, CoeffSource,
+};
+use crate::packed::process_packed;
+use crate::pairing::{pairing_product_loop, PAIRING_MILLER_LOOP};
+use crate::public_inputs::fold_public_inputs;
+use crate::registry::{process_registry, registered_vk, VK_REGISTRY_FLAGS};
+use crate::result_log::{create_result_log, CREATE_RESULT_LOG};
+use crate::scratch::{
+    close_session, create_scratch, init_scratch, reset_session, Scratch, CLOSE_SESSION,
+    CREATE_SCRATCH, INIT_SCRATCH, RESET_SESSION,
+};
+use crate::session::SESSION_FLAG;
+use crate::verify_batch::verify_batch;
+use crate::verify_complete::verify_complete;
+use crate::vk::process_write_vk;
+use solana_program::account_info::{next_account_info, AccountInfo};
 use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
 use solana_program::pubkey::Pubkey;
 
+pub use crate::error::VerifierError;
+pub use crate::final_exponentiation::{
+    FLAG_CALLBACK, FLAG_INLINE_VK, FLAG_NULLIFIER, FLAG_RECEIPT, FLAG_RECORD_RESULT,
+    FLAG_RETURN_DATA, FLAG_TARGET_ONE, FLAG_WRITE_RESULT,
+};
+pub use crate::miller_loop::{AB_DATA_LEN, INLINE_COEFFS_FLAG};
+pub use crate::utils::{pack_index, read_accumulator, unpack_index, vk_id, INDEX_LEN};
+pub use crate::vk::{parse_on_chain_vk, read_vk_account, OnChainVk, VK_ACCOUNT_FLAG};
+pub use groth16_sol_core::curve;
+pub use groth16_sol_core::miller_loop::{step_coeff_count, step_range};
+
+#[cfg(feature = "alt-bn128")]
+mod alt_bn128;
+pub mod attestation;
+mod callback;
+pub mod commitment;
+mod error;
+pub mod events;
 mod final_exponentiation;
+pub mod g2_prepare;
+pub mod header;
+pub mod instruction;
 mod miller_loop;
+pub mod nullifier;
+pub mod packed;
+pub mod pairing;
+pub mod plan;
+pub mod point;
+pub mod public_inputs;
 mod pvk;
+pub mod receipt;
+pub mod registry;
+pub mod result_account;
+pub mod result_log;
+pub mod return_data;
+pub mod schedule;
+pub mod scratch;
+pub mod session;
+pub mod telemetry;
+#[cfg(test)]
+mod test_utils;
 mod utils;
+pub mod verify_batch;
+pub mod verify_complete;
+pub mod vk;
 
-entrypoint!(process_instruction);
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
 pub fn process_instruction(
-    _program_id: &Pubkey,
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
-    let (t, i, j, input) = unpack_instruction_data(instruction_data).unwrap();
-    match t {
-        0 => gamma_miller_loop(accounts_iter, i, j, input),
-        1 => delta_miller_loop(accounts_iter, i, j, input),
-        _ => final_exponentiation(accounts_iter, i, j, input),
+    let (&t, rest) = instruction_data
+        .split_first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    let t = t as usize;
+
+    // steps of a guarded session take the session account first, checked once the
+    // scratch accounts it belongs with are known
+    let (session, rest) = if t & SESSION_FLAG != 0 {
+        let session_account = next_account_info(accounts_iter)?;
+        let (rest, suffix) = session::split_suffix(rest)?;
+        (Some((session_account, suffix)), rest)
+    } else {
+        (None, rest)
+    };
+
+    // Miller-loop and final steps may read their verifying key from a VK account that
+    // comes next, possibly registered under the id the data ends with, or carry it in
+    // the instruction data, instead of using the baked key
+    let registered = t & VK_REGISTRY_FLAGS == VK_REGISTRY_FLAGS;
+    let (vk_data, rest) = if registered {
+        let (vk_account, rest) = registered_vk(program_id, accounts_iter, rest)?;
+        (Some(vk_account.try_borrow_data()?), rest)
+    } else if t & VK_ACCOUNT_FLAG != 0 {
+        let vk_account = next_account_info(accounts_iter)?;
+        if vk_account.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        (Some(vk_account.try_borrow_data()?), rest)
+    } else {
+        (None, rest)
+    };
+    let vk = vk_data
+        .as_ref()
+        .map(|data| read_vk_account(data))
+        .transpose()?;
+    let inline_coeffs = t & INLINE_COEFFS_FLAG != 0 && !registered;
+
+    // steps on scratch accounts take the payer the accounts are derived from next
+    let tag = (t & !(SESSION_FLAG | VK_ACCOUNT_FLAG | INLINE_COEFFS_FLAG)) as u8;
+    let (scratch, rest) = if VerifierInstruction::runs_on_scratch(tag) {
+        let payer = next_account_info(accounts_iter)?;
+        let (scratch, rest) = Scratch::new(program_id, payer, rest)?;
+        (Some(scratch), rest)
+    } else {
+        (None, rest)
+    };
+    // and, with the telemetry flag on the session id, the session's telemetry account
+    let telemetry_account = match &scratch {
+        Some(scratch) if scratch.telemetry() => {
+            Some(scratch.next_account(accounts_iter, scratch::Role::Telemetry)?)
+        }
+        _ => None,
+    };
+    match (session, &scratch) {
+        (Some((session_account, suffix)), Some(scratch)) => {
+            session::advance(scratch, session_account, suffix)?
+        }
+        (Some(_), None) => return Err(ProgramError::InvalidInstructionData),
+        (None, _) => {}
+    }
+
+    let mut data = Vec::with_capacity(instruction_data.len());
+    data.push(tag);
+    data.extend_from_slice(rest);
+    let instruction = VerifierInstruction::unpack(&data)?;
+    let miller_loop = matches!(
+        instruction,
+        GammaMillerLoop(..)
+            | DeltaMillerLoop(..)
+            | GammaMillerLoopSteps(..)
+            | DeltaMillerLoopSteps(..)
+            | GammaDeltaMillerLoop(..)
+    );
+    let takes_vk = miller_loop
+        || matches!(
+            instruction,
+            HardPartY16(..)
+                | FoldPublicInputs(..)
+                | VerifyComplete(..)
+                | VerifyBatch(..)
+                | PrepareG2(..)
+        );
+    if (inline_coeffs && !miller_loop) || (vk.is_some() && (inline_coeffs || !takes_vk)) {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let source = match &vk {
+        Some(vk) => CoeffSource::Account(vk),
+        None if inline_coeffs => CoeffSource::Inline,
+        None => CoeffSource::Baked,
+    };
+
+    let start = telemetry::remaining_compute_units();
+
+    let result = match (&instruction, &scratch) {
+        (RecordAttestation(_, input), _) => record_attestation(program_id, accounts_iter, &input.0),
+        (Packed(_, input), _) => process_packed(program_id, accounts_iter, &input.0),
+        (WriteVk(index, input), _) => {
+            process_write_vk(program_id, accounts_iter, index.i, &input.0)
+        }
+        (VkRegistry(index, input), _) => {
+            process_registry(program_id, accounts_iter, index.i, &input.0)
+        }
+        #[cfg(feature = "alt-bn128")]
+        (VerifyAltBn128(_, input), _) => alt_bn128::verify_alt_bn128(&input.0),
+        #[cfg(not(feature = "alt-bn128"))]
+        (VerifyAltBn128(..), _) => Err(ProgramError::InvalidInstructionData),
+        (VerifyComplete(index, input), _) => {
+            verify_complete(program_id, accounts_iter, vk.as_ref(), index.i, &input.0)
+        }
+        (VerifyBatch(_, input), _) => verify_batch(vk.as_ref(), &input.0),
+        (GammaMillerLoop(index, input), Some(scratch)) => gamma_miller_loop(
+            scratch,
+            accounts_iter,
+            index.i as usize,
+            index.j as usize,
+            1,
+            &input.0,
+            source,
+        ),
+        (GammaMillerLoopSteps(index, steps, input), Some(scratch)) => gamma_miller_loop(
+            scratch,
+            accounts_iter,
+            index.i as usize,
+            index.j as usize,
+            *steps as usize,
+            &input.0,
+            source,
+        ),
+        (DeltaMillerLoop(index, input), Some(scratch)) => delta_miller_loop(
+            scratch,
+            accounts_iter,
+            index.i as usize,
+            index.j as usize,
+            1,
+            &input.0,
+            source,
+        ),
+        (DeltaMillerLoopSteps(index, steps, input), Some(scratch)) => delta_miller_loop(
+            scratch,
+            accounts_iter,
+            index.i as usize,
+            index.j as usize,
+            *steps as usize,
+            &input.0,
+            source,
+        ),
+        (GammaDeltaMillerLoop(index, steps, input), Some(scratch)) => gamma_delta_miller_loop(
+            scratch,
+            accounts_iter,
+            index.i as usize,
+            index.j as usize,
+            *steps as usize,
+            &input.0,
+            source,
+        ),
+        (FoldPublicInputs(_, input), Some(scratch)) => {
+            fold_public_inputs(scratch, accounts_iter, &input.0, vk.as_ref())
+        }
+        (AbMillerLoop(index, input), Some(scratch)) => {
+            let (i, j) = (index.i as usize, index.j as usize);
+            ab_miller_loop(scratch, accounts_iter, i, j, &input.0)
+        }
+        (PrepareG2(index, steps, input), Some(scratch)) => {
+            let (i, j, steps) = (index.i as usize, index.j as usize, *steps as usize);
+            match input.0.split_first() {
+                Some((&PAIRING_MILLER_LOOP, pairs)) => {
+                    pairing_product_loop(scratch, accounts_iter, i, j, steps, pairs, source)
+                }
+                _ if vk.is_some() => Err(ProgramError::InvalidInstructionData),
+                _ => prepare_g2(scratch, accounts_iter, i, j, steps, &input.0),
+            }
+        }
+        (CreateScratch(index), Some(scratch)) => match index.j {
+            CREATE_SCRATCH => create_scratch(scratch, accounts_iter, index.i),
+            INIT_SCRATCH => init_scratch(scratch, accounts_iter, index.i),
+            CREATE_RESULT_LOG => create_result_log(scratch, accounts_iter, index.i),
+            _ => Err(ProgramError::InvalidInstructionData),
+        },
+        (CloseSession(index, input), Some(scratch)) => match index.i {
+            CLOSE_SESSION => close_session(scratch, accounts_iter, &input.0),
+            RESET_SESSION => reset_session(scratch, accounts_iter, &input.0),
+            _ => Err(ProgramError::InvalidInstructionData),
+        },
+        (stage, Some(scratch)) => final_exponentiation(scratch, accounts_iter, stage, vk.as_ref()),
+        (_, None) => Err(ProgramError::InvalidInstructionData),
+    };
+
+    if let (Ok(()), Some(scratch)) = (&result, &scratch) {
+        events::log_step(scratch, &instruction);
+    }
+
+    if let (Ok(()), Some(telemetry_account)) = (&result, telemetry_account) {
+        telemetry::record(telemetry_account, start)?;
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use circuit::PointEncoding::{Compressed, Uncompressed};
+    use solana_program::pubkey::Pubkey;
+
+    use crate::registry::{vk_entry_address, VkEntry, VK_ENTRY_DATA_LEN};
+    use crate::schedule::{
+        verification_steps, with_folded_inputs, with_fused_miller_loops, with_inline_vk,
+        with_packed_miller_loops, with_packed_naf_digits, with_registered_vk, with_scratch,
+        with_vk_account, NUM_ACCOUNTS, PAYER,
+    };
+    use crate::test_utils::{
+        install_stubs, logs, run_step, session_accounts, TestAccount, SESSION_ID,
+    };
+
+    #[test]
+    fn test_full_verification() {
+        install_stubs();
+        let (proof_c, prepared_input, proof_ab) = circuit::initialize().unwrap();
+        let program_id = Pubkey::new_unique();
+        let mut accounts = session_accounts(&program_id);
+        let mut steps = verification_steps(&proof_c, &prepared_input, &proof_ab);
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        for step in steps.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+
+        // the phases can be followed from the program logs
+        let logs: Vec<_> = logs()
+            .into_iter()
+            .filter(|line| line.starts_with(crate::events::LOG_PREFIX))
+            .collect();
+        let phases: Vec<_> = logs
+            .iter()
+            .map(|line| line.split(' ').nth(1).unwrap())
+            .filter(|phase| !phase.starts_with("hard-part"))
+            .collect();
+        assert_eq!(
+            phases,
+            [
+                "gamma-loop-start",
+                "gamma-loop-end",
+                "delta-loop-start",
+                "delta-loop-end",
+                "ab-loop-start",
+                "ab-loop-end",
+                "prepare-final",
+                "easy-part-1",
+                "easy-part-2",
+                "final-check",
+            ]
+        );
+        let hard_part = steps.len() - 3 * crate::curve::ATE_LOOP_COUNT.len() - 4;
+        assert_eq!(logs.len(), 10 + hard_part);
+        assert!(logs.iter().all(|line| line.contains("session=1 ")));
+        assert!(logs.contains(&"groth16: hard-part-y4 session=1 i=0 j=62".to_string()));
+        assert!(logs.last().unwrap().ends_with("valid=1"));
+    }
+
+    // A proof under a key other than the baked one verifies with the key carried in the
+    // instruction data, and fails with the baked one.
+    #[test]
+    fn test_stateless_vk() {
+        use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(3);
+        let params = circuit::setup(&circuit::demo_constants(), &mut rng).unwrap();
+        let (proof_c, prepared_input, proof_ab) =
+            circuit::initialize_with_key(&params, &mut rng).unwrap();
+        let vk = circuit::to_stateless_vk(&params.vk);
+        let program_id = Pubkey::new_unique();
+
+        let mut steps = verification_steps(&proof_c, &prepared_input, &proof_ab);
+        let mut baked = steps.clone();
+        with_scratch(&mut baked, PAYER, SESSION_ID);
+        let (last, rest) = baked.split_last().unwrap();
+        let mut accounts = session_accounts(&program_id);
+        for step in rest.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+        assert!(run_step(&program_id, &mut accounts, last).is_err());
+
+        with_inline_vk(
+            &mut steps,
+            &vk.gamma_coeffs,
+            &vk.delta_coeffs,
+            &vk.alpha_g1_beta_g2,
+        );
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        let mut accounts = session_accounts(&program_id);
+        for step in steps.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+    }
+
+    // One deployment verifies proofs under any key uploaded to a VK account, and the
+    // baked key no longer applies to them.
+    #[test]
+    fn test_vk_account() {
+        use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(4);
+        let params = circuit::setup(&circuit::demo_constants(), &mut rng).unwrap();
+        let (proof_c, prepared_input, proof_ab) =
+            circuit::initialize_with_key(&params, &mut rng).unwrap();
+        let vk_data = circuit::to_on_chain_vk(&params.vk).unwrap();
+        let program_id = Pubkey::new_unique();
+
+        let mut accounts = session_accounts(&program_id);
+        let mut vk_account = TestAccount::new(&program_id, vk_data.len());
+        vk_account.data.copy_from_slice(&vk_data);
+        accounts.push(vk_account);
+
+        let mut steps = verification_steps(&proof_c, &prepared_input, &proof_ab);
+        with_vk_account(&mut steps, NUM_ACCOUNTS);
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        for step in steps.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+
+        // a VK account owned by another program is rejected
+        accounts[NUM_ACCOUNTS].owner = Pubkey::new_unique();
+        assert!(run_step(&program_id, &mut accounts, &steps[0]).is_err());
+    }
+
+    // A key registered under an id verifies like its VK account, named by the id.
+    #[test]
+    fn test_registered_vk() {
+        use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(4);
+        let params = circuit::setup(&circuit::demo_constants(), &mut rng).unwrap();
+        let (proof_c, prepared_input, proof_ab) =
+            circuit::initialize_with_key(&params, &mut rng).unwrap();
+        let vk_data = circuit::to_on_chain_vk(&params.vk).unwrap();
+        let program_id = Pubkey::new_unique();
+
+        let mut accounts = session_accounts(&program_id);
+        let mut vk_account = TestAccount::new(&program_id, vk_data.len());
+        vk_account.data.copy_from_slice(&vk_data);
+        let (address, bump) = vk_entry_address(&program_id, 3);
+        let mut entry = TestAccount::new(&program_id, VK_ENTRY_DATA_LEN);
+        entry.key = address;
+        let vk_entry = VkEntry {
+            id: 3,
+            vk_account: vk_account.key,
+            bump,
+            flags: 0,
+        };
+        entry.data.copy_from_slice(&vk_entry.pack());
+        accounts.push(vk_account);
+        accounts.push(entry);
+
+        let mut steps = verification_steps(&proof_c, &prepared_input, &proof_ab);
+        with_vk_account(&mut steps, NUM_ACCOUNTS);
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        with_registered_vk(&mut steps, NUM_ACCOUNTS + 1, 3);
+        for step in steps.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+
+        // the id must be the entry's, and the VK account the one registered
+        let (keys, mut data) = steps[0].clone();
+        *data.last_mut().unwrap() = 1;
+        assert!(run_step(&program_id, &mut accounts, &(keys, data)).is_err());
+        accounts[NUM_ACCOUNTS].key = Pubkey::new_unique();
+        assert!(run_step(&program_id, &mut accounts, &steps[0]).is_err());
+    }
+
+    // Several Miller-loop steps per instruction verify like single steps, with the baked
+    // key, coefficients carried inline and a VK account alike, and log the same phases.
+    #[test]
+    fn test_packed_miller_loop_steps() {
+        use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+        install_stubs();
+        let mut rng = StdRng::seed_from_u64(6);
+        let params = circuit::setup(&circuit::demo_constants(), &mut rng).unwrap();
+        let (proof_c, prepared_input, proof_ab) =
+            circuit::initialize_with_key(&params, &mut rng).unwrap();
+        let vk = circuit::to_stateless_vk(&params.vk);
+        let program_id = Pubkey::new_unique();
+        let single = verification_steps(&proof_c, &prepared_input, &proof_ab);
+
+        let mut steps = single.clone();
+        with_packed_miller_loops(&mut steps, 8);
+        // each loop's 65 steps fit in 9 instructions
+        assert_eq!(crate::curve::ATE_LOOP_COUNT.len(), 65);
+        assert_eq!(steps.len(), single.len() - 2 * (65 - 9));
+        with_inline_vk(
+            &mut steps,
+            &vk.gamma_coeffs,
+            &vk.delta_coeffs,
+            &vk.alpha_g1_beta_g2,
+        );
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        let mut accounts = session_accounts(&program_id);
+        for step in steps.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+        let phases = logs()
+            .into_iter()
+            .filter(|line| line.contains("-loop-"))
+            .map(|line| line.split(' ').nth(1).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            phases,
+            [
+                "gamma-loop-start",
+                "gamma-loop-end",
+                "delta-loop-start",
+                "delta-loop-end",
+                "ab-loop-start",
+                "ab-loop-end",
+            ]
+        );
+
+        let mut accounts = session_accounts(&program_id);
+        let mut vk_account = TestAccount::new(&program_id, 0);
+        vk_account.data = circuit::to_on_chain_vk(&params.vk).unwrap();
+        accounts.push(vk_account);
+        let mut steps = single;
+        with_packed_miller_loops(&mut steps, 5);
+        with_vk_account(&mut steps, NUM_ACCOUNTS);
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        for step in steps.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+    }
+
+    // The gamma and delta loops run together verify like separate loops, single or
+    // several steps at a time, with the baked key and coefficients carried inline.
+    #[test]
+    fn test_fused_miller_loops() {
+        install_stubs();
+        let (proof_c, prepared_input, proof_ab) = circuit::initialize().unwrap();
+        let program_id = Pubkey::new_unique();
+        let single = verification_steps(&proof_c, &prepared_input, &proof_ab);
+
+        let mut steps = single.clone();
+        with_fused_miller_loops(&mut steps);
+        let loop_steps = crate::curve::ATE_LOOP_COUNT.len();
+        assert_eq!(steps.len(), single.len() - loop_steps);
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        let mut accounts = session_accounts(&program_id);
+        for step in steps.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+        let phases = logs()
+            .into_iter()
+            .filter(|line| line.contains("-loop-"))
+            .map(|line| line.split(' ').nth(1).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            phases,
+            [
+                "gamma-delta-loop-start",
+                "gamma-delta-loop-end",
+                "ab-loop-start",
+                "ab-loop-end",
+            ]
+        );
+
+        let vk = circuit::to_stateless_vk(&circuit::demo_proof().unwrap().0.vk);
+        let mut steps = single;
+        with_packed_miller_loops(&mut steps, 4);
+        with_fused_miller_loops(&mut steps);
+        with_inline_vk(
+            &mut steps,
+            &vk.gamma_coeffs,
+            &vk.delta_coeffs,
+            &vk.alpha_g1_beta_g2,
+        );
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        let mut accounts = session_accounts(&program_id);
+        for step in steps.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+    }
+
+    // Exponentiation-by-x steps over several NAF digits verify like one-digit steps, the
+    // run that reaches the last digit conjugating the result.
+    #[test]
+    fn test_packed_naf_digits() {
+        let (proof_c, prepared_input, proof_ab) = circuit::initialize().unwrap();
+        let program_id = Pubkey::new_unique();
+        let single = verification_steps(&proof_c, &prepared_input, &proof_ab);
+
+        let mut steps = single.clone();
+        with_packed_naf_digits(&mut steps, 10);
+        // 63 digits in 7 steps, for each of y0, y4 and y6
+        assert_eq!(steps.len(), single.len() - 3 * (63 - 7));
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        let mut accounts = session_accounts(&program_id);
+        for step in steps.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+
+        // a wrong digit count fails the final check
+        let mut steps = single;
+        with_packed_naf_digits(&mut steps, 10);
+        let y4 = steps.iter().position(|(_, data)| data[0] == 8).unwrap();
+        steps[y4].1[1] -= 1;
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        let (last, rest) = steps.split_last().unwrap();
+        let mut accounts = session_accounts(&program_id);
+        for step in rest.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+        assert!(run_step(&program_id, &mut accounts, last).is_err());
+    }
+
+    // Keys with several public inputs verify through the chunked steps and the single
+    // instruction alike, and the instruction's inputs must match the key's count.
+    #[test]
+    fn test_several_public_inputs() {
+        use crate::VerifierError;
+        use ark_groth16::prepare_verifying_key;
+        use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(9);
+        let constants = circuit::demo_constants();
+        let params = circuit::setup_with_inputs(&constants, 3, &mut rng).unwrap();
+        let (proof, public_inputs) = circuit::prove(&params, &constants, &mut rng).unwrap();
+        let pvk = prepare_verifying_key(&params.vk);
+        let (proof_c, prepared_input, proof_ab) =
+            circuit::submission(&pvk, &proof, &public_inputs, Compressed).unwrap();
+        let program_id = Pubkey::new_unique();
+
+        let mut accounts = session_accounts(&program_id);
+        let mut vk_account = TestAccount::new(&program_id, 0);
+        vk_account.data = circuit::to_on_chain_vk(&params.vk).unwrap();
+        accounts.push(vk_account);
+        let mut steps = verification_steps(&proof_c, &prepared_input, &proof_ab);
+        with_vk_account(&mut steps, NUM_ACCOUNTS);
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        for step in steps.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+
+        let mut data = vec![24 | 0x20, 0, 0];
+        data.extend(circuit::complete_submission(
+            &proof,
+            &public_inputs,
+            Uncompressed,
+        ));
+        let vk_account = &mut accounts[NUM_ACCOUNTS];
+        crate::process_instruction(&program_id, &[vk_account.info()], &data).unwrap();
+        let mut data = vec![24 | 0x20, 0, 0];
+        data.extend(circuit::complete_submission(
+            &proof,
+            &public_inputs[..2],
+            Compressed,
+        ));
+        assert_eq!(
+            crate::process_instruction(&program_id, &[vk_account.info()], &data),
+            Err(VerifierError::InvalidDataLength.into())
+        );
+    }
+
+    // The A·B Miller loop runs on-chain over the A and B sent with its steps, so those
+    // of another proof fail the final check, and the prepare step takes no qap from the
+    // client.
+    #[test]
+    fn test_onchain_ab() {
+        use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+        let (proof_c, prepared_input, _) = circuit::initialize().unwrap();
+        let program_id = Pubkey::new_unique();
+        let mut rng = StdRng::seed_from_u64(5);
+        let params = circuit::setup(&circuit::demo_constants(), &mut rng).unwrap();
+        let (other, _) = circuit::prove(&params, &circuit::demo_constants(), &mut rng).unwrap();
+        let other_ab = circuit::proof_ab(&other, Compressed);
+        let mut steps = verification_steps(&proof_c, &prepared_input, &other_ab);
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        let (last, rest) = steps.split_last().unwrap();
+        let mut accounts = session_accounts(&program_id);
+        for step in rest.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+        assert!(run_step(&program_id, &mut accounts, last).is_err());
+
+        let prepare = steps.iter().position(|(_, data)| data[0] == 2).unwrap();
+        let (keys, mut data) = steps[prepare].clone();
+        data.splice(3..3, vec![0; 384]);
+        assert_eq!(
+            run_step(&program_id, &mut accounts, &(keys, data)),
+            Err(solana_program::program_error::ProgramError::InvalidInstructionData)
+        );
+    }
+
+    // A final step attesting to public inputs runs on inputs folded on-chain: the
+    // outcome carries their hash and source, read from the inputs account. A proof
+    // checked against the client's prepared input is refused, even with other inputs
+    // folded in the same session, whose hash it would otherwise be recorded under.
+    #[test]
+    fn test_bound_inputs() {
+        use ark_ff::{to_bytes, One};
+        use solana_program::hash::hash;
+
+        use crate::return_data::{outcome, Outcome};
+        use crate::scratch::Role;
+        use crate::test_utils::RETURNING_PROGRAM;
+        use crate::{VerifierError, FLAG_RETURN_DATA};
+
+        install_stubs();
+        let (pvk, proof, public_inputs) = circuit::demo_proof().unwrap();
+        let (proof_c, prepared_input, proof_ab) =
+            circuit::submission(&pvk, &proof, &public_inputs, Compressed).unwrap();
+        let raw_inputs = to_bytes!(public_inputs).unwrap();
+        let statement = circuit::complete_submission(&proof, &[], Compressed);
+        let program_id = RETURNING_PROGRAM;
+        let run = |prepared_input: &[u8], raw_inputs: &[u8]| {
+            let mut accounts = session_accounts(&program_id);
+            accounts.push(TestAccount::scratch(&program_id, SESSION_ID, Role::Inputs));
+            let mut steps = verification_steps(&proof_c, prepared_input, &proof_ab);
+            with_folded_inputs(
+                &mut steps,
+                NUM_ACCOUNTS,
+                raw_inputs,
+                FLAG_RETURN_DATA,
+                &statement,
+            );
+            with_scratch(&mut steps, PAYER, SESSION_ID);
+            let (last, rest) = steps.split_last().unwrap();
+            for step in rest.iter() {
+                run_step(&program_id, &mut accounts, step).unwrap();
+            }
+            run_step(&program_id, &mut accounts, last)
+        };
+
+        run(&[], &raw_inputs).unwrap();
+        let inputs_hash = hash(&raw_inputs).to_bytes();
+        assert_eq!(
+            outcome(&program_id),
+            Some(Outcome {
+                valid: true,
+                vk_id: crate::pvk::VK_ID,
+                input_hash: inputs_hash,
+                source_id: inputs_hash,
+            })
+        );
+
+        let other_inputs = to_bytes!(public_inputs[0] + ark_bn254::Fr::one()).unwrap();
+        assert_eq!(
+            run(&prepared_input, &other_inputs),
+            Err(VerifierError::UnboundInputs.into())
+        );
+    }
+
+    // Interrupt the flow after every step, restart from the persisted account data
+    // alone and finish the remaining steps. The final step fails unless the result
+    // matches alpha_g1_beta_g2, and every resumed run must end in the same state.
+    #[test]
+    fn test_resume_after_every_step() {
+        let (proof_c, prepared_input, proof_ab) = circuit::initialize().unwrap();
+        let mut steps = verification_steps(&proof_c, &prepared_input, &proof_ab);
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        let program_id = Pubkey::new_unique();
+
+        let mut accounts = session_accounts(&program_id);
+        let mut checkpoints = vec![];
+        for step in steps.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+            checkpoints.push(snapshot(&accounts));
+        }
+        let expected = snapshot(&accounts);
+
+        for (interrupted_after, checkpoint) in checkpoints.iter().enumerate() {
+            let mut resumed = restore(&program_id, checkpoint);
+            for step in steps[interrupted_after + 1..].iter() {
+                run_step(&program_id, &mut resumed, step).unwrap_or_else(|err| {
+                    panic!("resume after step {} failed: {}", interrupted_after, err)
+                });
+            }
+            assert_eq!(snapshot(&resumed), expected);
+        }
+    }
+
+    fn snapshot(accounts: &[TestAccount]) -> Vec<Vec<u8>> {
+        accounts
+            .iter()
+            .map(|account| account.data.clone())
+            .collect()
+    }
+
+    fn restore(program_id: &Pubkey, snapshot: &[Vec<u8>]) -> Vec<TestAccount> {
+        let mut accounts = session_accounts(program_id);
+        for (account, data) in accounts.iter_mut().zip(snapshot.iter()) {
+            account.data.copy_from_slice(data);
+        }
+        accounts
     }
 }
@@ -1,144 +1,571 @@
 use std::slice::Iter;
 
-use ark_bn254::{Fq12Parameters, G1Affine, G1Projective, Parameters};
-use ark_ec::bn::{BnParameters, G1Prepared};
+use ark_bn254::{G1Affine, G2Affine, Parameters};
+use ark_ec::bn::G1Prepared;
 use ark_ec::ProjectiveCurve;
-use ark_ff::{Field, Fp12, Fp12ParamsWrapper, FromBytes, QuadExtField};
-use solana_program::account_info::{next_account_info, AccountInfo};
+use ark_ff::{to_bytes, Field, FromBytes, One, Zero};
+use groth16_sol_core::miller_loop::{point_lines, prepared_step, step_coeff_count, step_range};
+use solana_program::account_info::AccountInfo;
 use solana_program::entrypoint::ProgramResult;
+use solana_program::hash::{hash, hashv};
+use solana_program::program_error::ProgramError;
 
+use crate::curve::{ell, EllCoeff, Fq12, Fq2, ATE_LOOP_COUNT, MILLER_LOOP_LAST_STEP};
+use crate::error::VerifierError;
+use crate::point::PointReader;
+use crate::public_inputs::{folded_input_tag, read_folded_input};
 use crate::pvk::{get_delta_qef, get_gamma_qef};
-use crate::utils::{get_account_data, put_account_data};
+use crate::scratch::{accumulate_tag, Role, Scratch, SCRATCH_HEADER_LEN, UNBOUND_TAG};
+use crate::utils::{get_account_data, put_account_data, vk_id, BN254_DATA_LEN};
+use crate::vk::{OnChainVk, ELL_COEFF_DATA_LEN, FP2_DATA_LEN};
 
+// Set in `t` when a Miller-loop step carries the ell coefficients it needs at the start
+// of its input, each as c0 || c1 || c2 (192 bytes, the on-chain VK encoding), instead
+// of reading the baked VK. Together with `FLAG_INLINE_VK` on the final step this
+// verifies against any VK without storing it: every step carries its
+// `step_coeff_count` coefficients, at most 384 extra bytes per transaction.
+pub const INLINE_COEFFS_FLAG: usize = 0x40;
+
+// Where a Miller-loop step reads its ell coefficients from.
+#[derive(Clone, Copy)]
+pub enum CoeffSource<'a> {
+    // The constants baked into `pvk.rs`
+    Baked,
+    // The front of the step's input, see `INLINE_COEFFS_FLAG`
+    Inline,
+    // A VK account, see `VK_ACCOUNT_FLAG`
+    Account(&'a OnChainVk<'a>),
+}
+
+// The gamma or delta coefficient table of a verifying key, the lines of its prepared
+// -gamma_g2 or -delta_g2.
+#[derive(Clone, Copy)]
+pub enum Table {
+    Gamma,
+    Delta,
+}
+
+// The coefficients of step (i, j) from `table`, and the rest of the input.
+fn step_coeffs<'a>(
+    source: CoeffSource,
+    table: Table,
+    i: usize,
+    j: usize,
+    input: &'a [u8],
+) -> Result<(Vec<EllCoeff>, &'a [u8]), ProgramError> {
+    let count = step_coeff_count(i, j);
+    match source {
+        CoeffSource::Baked => {
+            let baked = match table {
+                Table::Gamma => get_gamma_qef,
+                Table::Delta => get_delta_qef,
+            };
+            let coeffs = (j..j + count)
+                .map(|k| (baked(k, 0), baked(k, 1), baked(k, 2)))
+                .collect();
+            Ok((coeffs, input))
+        }
+        CoeffSource::Account(vk) => {
+            let account = |k, quad_index| match table {
+                Table::Gamma => vk.gamma_qef(k, quad_index),
+                Table::Delta => vk.delta_qef(k, quad_index),
+            };
+            let mut coeffs = vec![];
+            for k in j..j + count {
+                coeffs.push((account(k, 0)?, account(k, 1)?, account(k, 2)?));
+            }
+            Ok((coeffs, input))
+        }
+        CoeffSource::Inline => {
+            if input.len() < count * ELL_COEFF_DATA_LEN {
+                return Err(VerifierError::InvalidDataLength.into());
+            }
+            let (mut src, rest) = input.split_at(count * ELL_COEFF_DATA_LEN);
+            let mut coeffs = vec![];
+            for _ in 0..count {
+                let mut read = || Fq2::read(&mut src).map_err(|_| VerifierError::InvalidEncoding);
+                coeffs.push((read()?, read()?, read()?));
+            }
+            Ok((coeffs, rest))
+        }
+    }
+}
+
+// A step's indices (i, j) and its coefficients.
+type StepCoeffs = (usize, usize, Vec<EllCoeff>);
+
+// The coefficients of each of `steps` steps from (i, j) on and the rest of the input.
+// Inline coefficients come step after step.
+fn steps_coeffs<'a>(
+    source: CoeffSource,
+    table: Table,
+    i: usize,
+    j: usize,
+    steps: usize,
+    input: &'a [u8],
+) -> Result<(Vec<StepCoeffs>, &'a [u8]), ProgramError> {
+    let range = step_range(i, j, steps).ok_or(ProgramError::InvalidInstructionData)?;
+    let mut input = input;
+    let mut coeffs = vec![];
+    for (i, j) in range {
+        let (step, rest) = step_coeffs(source, table, i, j, input)?;
+        coeffs.push((i, j, step));
+        input = rest;
+    }
+    Ok((coeffs, input))
+}
+
+// Steps (i, j) .. of the gamma loop, `steps` of them: one instruction normally, several
+// for `GammaMillerLoopSteps`, which fits more of the loop in a transaction's compute
+// budget. Likewise for the delta loop.
 pub fn gamma_miller_loop(
+    scratch: &Scratch,
     accounts_iter: &mut Iter<AccountInfo>,
     i: usize,
     j: usize,
+    steps: usize,
     input: &[u8],
+    source: CoeffSource,
 ) -> ProgramResult {
-    let gamma_account = next_account_info(accounts_iter)?;
-
-    let prepared_input = G1Projective::read(&mut input.as_ref())
-        .unwrap()
-        .into_affine()
-        .into();
-    let account_data = get_account_data(gamma_account, j);
-    let account_data = match j {
-        89 => final_gamma_miller_loop(&prepared_input, account_data, j),
-        _ => sub_gamma_miller_loop(&prepared_input, account_data, i, j),
+    let gamma_account = scratch.next_account(accounts_iter, Role::Gamma)?;
+    let (coeffs, input) = steps_coeffs(source, Table::Gamma, i, j, steps, input)?;
+
+    let (prepared_input, tag) = read_prepared_input(scratch, accounts_iter, input, source)?;
+    let account_data = get_account_data(gamma_account)?;
+    let account_data = run_steps(&prepared_input, account_data, &coeffs);
+    put_account_data(gamma_account, &account_data)?;
+    accumulate_tag(gamma_account, &tag)
+}
+
+// The id of the key whose coefficients `source` holds, none for inline ones.
+fn key_id(source: CoeffSource) -> Option<[u8; 32]> {
+    match source {
+        CoeffSource::Baked => Some(vk_id(None)),
+        CoeffSource::Account(vk) => Some(vk.id()),
+        CoeffSource::Inline => None,
+    }
+}
+
+// Without input data, the prepared input comes from a folded inputs account. Returns
+// the tag the step contributes to the gamma account (see `scratch.rs`), unbound for a
+// prepared input the client sent.
+fn read_prepared_input(
+    scratch: &Scratch,
+    accounts_iter: &mut Iter<AccountInfo>,
+    input: &[u8],
+    source: CoeffSource,
+) -> Result<(G1Prepared<Parameters>, [u8; 32]), ProgramError> {
+    let (prepared_input, tag) = if input.is_empty() {
+        let inputs_account = scratch.next_account(accounts_iter, Role::Inputs)?;
+        let tag = folded_input_tag(inputs_account, key_id(source))?;
+        (read_folded_input(inputs_account)?, tag)
+    } else {
+        let mut reader = PointReader::new(input)?;
+        let prepared_input = reader.g1_projective()?;
+        reader.finish()?;
+        (prepared_input, UNBOUND_TAG)
     };
-    put_account_data(gamma_account, &account_data);
-    Ok(())
+    Ok((prepared_input.into_affine().into(), tag))
 }
 
-pub fn gamma_onchain_ell(f: &mut Fp12<Fq12Parameters>, j: usize, p: &G1Affine) {
-    let mut c0 = get_gamma_qef(j, 0);
-    let mut c1 = get_gamma_qef(j, 1);
-    let c2 = get_gamma_qef(j, 2);
+// The tag a delta step over proof.c = `c` contributes to the delta account (see
+// `scratch.rs`), unbound for inline coefficients.
+fn source_delta_tag(source: CoeffSource, c: &G1Affine) -> [u8; 32] {
+    key_id(source).map_or(UNBOUND_TAG, |vk_id| delta_tag(&vk_id, c))
+}
+
+// The tag of a delta loop over proof.c = `c` with the coefficients of the key `vk_id`.
+pub fn delta_tag(vk_id: &[u8; 32], c: &G1Affine) -> [u8; 32] {
+    hashv(&[vk_id, &to_bytes!(c).unwrap()]).to_bytes()
+}
 
-    c0.mul_assign_by_fp(&p.y);
-    c1.mul_assign_by_fp(&p.x);
-    f.mul_by_034(&c0, &c1, &c2);
+// The tag of an A·B loop over proof.a = `a` and proof.b = `b`.
+pub fn ab_tag(a: &G1Affine, b: &G2Affine) -> [u8; 32] {
+    hash(&to_bytes!(a, b).unwrap()).to_bytes()
 }
 
-fn sub_gamma_miller_loop(
-    p: &G1Prepared<ark_bn254::Parameters>,
-    mut f: QuadExtField<Fp12ParamsWrapper<Fq12Parameters>>,
+// Steps (i, j) .. of the gamma and the delta loop together, `steps` of each. The loops
+// are independent and a step of either is far under the compute budget, so advancing
+// both per instruction halves the Miller-loop transactions. Takes the gamma and delta
+// accounts (and the inputs account when folded); the input holds the gamma and then the
+// delta coefficients when inline, proof.c behind its encoding byte and the prepared
+// input behind its own, or nothing after proof.c to read the folded inputs account.
+pub fn gamma_delta_miller_loop(
+    scratch: &Scratch,
+    accounts_iter: &mut Iter<AccountInfo>,
     i: usize,
     j: usize,
-) -> QuadExtField<Fp12ParamsWrapper<Fq12Parameters>> {
-    if !p.is_zero() {
-        if i != ark_bn254::Parameters::ATE_LOOP_COUNT.len() - 1 {
-            f.square_in_place();
-        }
-        gamma_onchain_ell(&mut f, j, &p.0);
-        match ark_bn254::Parameters::ATE_LOOP_COUNT[i - 1] {
-            1 => {
-                gamma_onchain_ell(&mut f, j + 1, &p.0);
-            }
-            -1 => {
-                gamma_onchain_ell(&mut f, j + 1, &p.0);
-            }
-            _ => {}
-        }
-    }
-    f
+    steps: usize,
+    input: &[u8],
+    source: CoeffSource,
+) -> ProgramResult {
+    let gamma_account = scratch.next_account(accounts_iter, Role::Gamma)?;
+    let delta_account = scratch.next_account(accounts_iter, Role::Delta)?;
+    let (gamma_coeffs, input) = steps_coeffs(source, Table::Gamma, i, j, steps, input)?;
+    let (delta_coeffs, input) = steps_coeffs(source, Table::Delta, i, j, steps, input)?;
+
+    let mut reader = PointReader::new(input)?;
+    let c = reader.g1()?;
+    let (prepared_input, tag) = read_prepared_input(scratch, accounts_iter, reader.rest(), source)?;
+
+    let gamma = get_account_data(gamma_account)?;
+    put_account_data(
+        gamma_account,
+        &run_steps(&prepared_input, gamma, &gamma_coeffs),
+    )?;
+    accumulate_tag(gamma_account, &tag)?;
+    let delta = get_account_data(delta_account)?;
+    put_account_data(delta_account, &run_steps(&c.into(), delta, &delta_coeffs))?;
+    accumulate_tag(delta_account, &source_delta_tag(source, &c))
 }
 
-fn final_gamma_miller_loop(
-    p: &G1Prepared<ark_bn254::Parameters>,
-    mut f: QuadExtField<Fp12ParamsWrapper<Fq12Parameters>>,
-    j: usize,
-) -> QuadExtField<Fp12ParamsWrapper<Fq12Parameters>> {
-    if !p.is_zero() {
-        gamma_onchain_ell(&mut f, j, &p.0);
-        gamma_onchain_ell(&mut f, j + 1, &p.0);
-    }
-    f
+fn run_steps(p: &G1Prepared<Parameters>, f: Fq12, coeffs: &[StepCoeffs]) -> Fq12 {
+    coeffs.iter().fold(f, |f, (i, j, coeffs)| {
+        prepared_step(&p.0, f, *i, *j, coeffs)
+    })
 }
 
 pub fn delta_miller_loop(
+    scratch: &Scratch,
     accounts_iter: &mut Iter<AccountInfo>,
     i: usize,
     j: usize,
+    steps: usize,
     input: &[u8],
+    source: CoeffSource,
 ) -> ProgramResult {
-    let delta_account = next_account_info(accounts_iter)?;
-
-    let proof_c = G1Affine::read(&mut input.as_ref())
-        .map(|p| G1Prepared::<Parameters>::from(p))
-        .unwrap();
-    let account_data = get_account_data(delta_account, j);
-    let account_data = match j {
-        89 => final_delta_miller_loop(&proof_c, account_data, j),
-        _ => sub_delta_miller_loop(&proof_c, account_data, i, j),
+    let delta_account = scratch.next_account(accounts_iter, Role::Delta)?;
+    let (coeffs, input) = steps_coeffs(source, Table::Delta, i, j, steps, input)?;
+
+    let mut reader = PointReader::new(input)?;
+    let c = reader.g1()?;
+    reader.finish()?;
+    let account_data = get_account_data(delta_account)?;
+    let account_data = run_steps(&c.into(), account_data, &coeffs);
+    put_account_data(delta_account, &account_data)?;
+    accumulate_tag(delta_account, &source_delta_tag(source, &c))
+}
+
+// The proof's own Miller loop, e(A, B), so the client never submits the `qap` value it
+// could otherwise forge. Unlike the gamma and delta loops, B is not a key constant: its
+// line coefficients are computed step by step from the running point R, exactly as
+// `G2Prepared::from` would, and applied to A right away.
+//
+// instruction data: t = 21, i, j as for the other loops || encoding (see `point.rs`) ||
+//                   A (G1Affine) || B (G2Affine)
+// accounts: [ab account (writable)]
+//
+// ab account layout, after the scratch header (see `scratch.rs`):
+//   0..384    accumulator f (Fp12)
+//   384..576  R in homogeneous projective coordinates: x, y, z (Fp2 each)
+//
+// The result is consumed by `prepare_final_data`.
+pub const AB_DATA_LEN: usize = BN254_DATA_LEN + 3 * FP2_DATA_LEN;
+const AB_PROJECTIVE_OFFSET: usize = SCRATCH_HEADER_LEN + BN254_DATA_LEN;
+
+pub fn ab_miller_loop(
+    scratch: &Scratch,
+    accounts_iter: &mut Iter<AccountInfo>,
+    i: usize,
+    j: usize,
+    input: &[u8],
+) -> ProgramResult {
+    let ab_account = scratch.next_account(accounts_iter, Role::Ab)?;
+    let mut points = PointReader::new(input)?;
+    let a = points.g1()?;
+    let b = points.g2()?;
+    points.finish()?;
+
+    let mut f = get_account_data(ab_account)?;
+    let mut r = if j == 0 {
+        [b.x, b.y, Fq2::one()]
+    } else {
+        read_projective(ab_account, AB_PROJECTIVE_OFFSET)?
     };
-    put_account_data(delta_account, &account_data);
-    Ok(())
+    if !a.is_zero() && !b.is_zero() {
+        if j == MILLER_LOOP_LAST_STEP {
+            point_lines(&mut f, &mut r, &a, b, 0);
+        } else {
+            sub_ab_miller_loop(&mut f, &mut r, &a, b, i);
+        }
+    }
+    put_account_data(ab_account, &f)?;
+    write_projective(ab_account, AB_PROJECTIVE_OFFSET, &r)?;
+    accumulate_tag(ab_account, &ab_tag(&a, &b))
 }
 
-fn delta_onchain_ell(f: &mut Fp12<Fq12Parameters>, j: usize, p: &G1Affine) {
-    let mut c0 = get_delta_qef(j, 0);
-    let mut c1 = get_delta_qef(j, 1);
-    let c2 = get_delta_qef(j, 2);
+fn sub_ab_miller_loop(f: &mut Fq12, r: &mut [Fq2; 3], a: &G1Affine, b: G2Affine, i: usize) {
+    if i != ATE_LOOP_COUNT.len() - 1 {
+        f.square_in_place();
+    }
+    point_lines(f, r, a, b, i);
+}
+
+// The G2 side of a pair of a pairing product: a point, its lines computed step by step
+// from the running point R that follows it, as in the A·B loop, or a verifying key's
+// prepared -gamma_g2 or -delta_g2, its lines read from the step's coefficient source.
+// A handful of pairs live on the stack for one instruction, so boxing points saves
+// nothing.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Copy)]
+pub enum PairG2 {
+    Point(G2Affine, [Fq2; 3]),
+    Prepared(Table),
+}
 
-    c0.mul_assign_by_fp(&p.y);
-    c1.mul_assign_by_fp(&p.x);
-    f.mul_by_034(&c0, &c1, &c2);
+impl PairG2 {
+    // `q` before the first step, R = Q with z = 1.
+    pub fn point(q: G2Affine) -> PairG2 {
+        PairG2::Point(q, [q.x, q.y, Fq2::one()])
+    }
 }
 
-fn sub_delta_miller_loop(
-    p: &G1Prepared<ark_bn254::Parameters>,
-    mut f: QuadExtField<Fp12ParamsWrapper<Fq12Parameters>>,
+// Step (i, j) of the Miller loop of a product of pairings, e(P_1, Q_1) * .. *
+// e(P_k, Q_k), on the accumulator `f`: square it, except on the first and the last
+// step (i = 0), and apply the lines of every pair, advancing the running point of each
+// `Point` pair. A pair with a zero side contributes nothing. Coefficients of `Prepared`
+// pairs come from `source`, which must not be `Inline`.
+pub fn pairing_step(
+    f: &mut Fq12,
+    pairs: &mut [(G1Affine, PairG2)],
     i: usize,
     j: usize,
-) -> QuadExtField<Fp12ParamsWrapper<Fq12Parameters>> {
-    if !p.is_zero() {
-        if i != ark_bn254::Parameters::ATE_LOOP_COUNT.len() - 1 {
-            f.square_in_place();
+    source: CoeffSource,
+) -> ProgramResult {
+    if i != 0 && i != ATE_LOOP_COUNT.len() - 1 {
+        f.square_in_place();
+    }
+    for (p, q) in pairs.iter_mut() {
+        if p.is_zero() {
+            continue;
         }
-        delta_onchain_ell(&mut f, j, &p.0);
-        match ark_bn254::Parameters::ATE_LOOP_COUNT[i - 1] {
-            1 => {
-                delta_onchain_ell(&mut f, j + 1, &p.0);
+        match q {
+            PairG2::Point(q, r) if !q.is_zero() => point_lines(f, r, p, *q, i),
+            PairG2::Point(..) => {}
+            PairG2::Prepared(table) => {
+                let (coeffs, _) = step_coeffs(source, *table, i, j, &[])?;
+                coeffs.iter().for_each(|coeff| ell(f, coeff, p));
             }
-            -1 => {
-                delta_onchain_ell(&mut f, j + 1, &p.0);
-            }
-            _ => {}
         }
     }
-    f
+    Ok(())
 }
 
-fn final_delta_miller_loop(
-    p: &G1Prepared<ark_bn254::Parameters>,
-    mut f: QuadExtField<Fp12ParamsWrapper<Fq12Parameters>>,
-    j: usize,
-) -> QuadExtField<Fp12ParamsWrapper<Fq12Parameters>> {
-    if !p.is_zero() {
-        delta_onchain_ell(&mut f, j, &p.0);
-        delta_onchain_ell(&mut f, j + 1, &p.0);
+// The whole Miller loop of a product of pairings, see `pairing_step`.
+pub fn pairing_miller_loop(
+    pairs: &mut [(G1Affine, PairG2)],
+    source: CoeffSource,
+) -> Result<Fq12, ProgramError> {
+    let len = ATE_LOOP_COUNT.len();
+    let mut f = Fq12::one();
+    for (i, j) in step_range(len - 1, 0, len).unwrap() {
+        pairing_step(&mut f, pairs, i, j, source)?;
+    }
+    Ok(f)
+}
+
+// The product of the proofs' Miller loops, e(A_1, B_1) * .. * e(A_k, B_k) *
+// e(prepared input, -gamma) * e(C, -delta) before the final exponentiation, as one
+// pairing product sharing the squarings of the accumulator. A single proof passes one
+// (A, B) pair; a batch (see `verify_batch.rs`) passes one per proof and the combined
+// prepared input and C. The gamma and delta coefficients come from `source`, which
+// must not be `Inline`.
+pub fn multi_miller_loop(
+    ab: &[(G1Affine, G2Affine)],
+    prepared_input: &G1Affine,
+    c: &G1Affine,
+    source: CoeffSource,
+) -> Result<Fq12, ProgramError> {
+    let mut pairs = vec![
+        (*prepared_input, PairG2::Prepared(Table::Gamma)),
+        (*c, PairG2::Prepared(Table::Delta)),
+    ];
+    pairs.extend(ab.iter().map(|(a, b)| (*a, PairG2::point(*b))));
+    pairing_miller_loop(&mut pairs, source)
+}
+
+// R at `offset` of a scratch account.
+pub fn read_projective(account: &AccountInfo, offset: usize) -> Result<[Fq2; 3], ProgramError> {
+    let data = account.try_borrow_data()?;
+    let mut src = &data[offset..offset + 3 * FP2_DATA_LEN];
+    let mut read = || Fq2::read(&mut src).map_err(|_| VerifierError::InvalidAccumulator);
+    Ok([read()?, read()?, read()?])
+}
+
+pub fn write_projective(account: &AccountInfo, offset: usize, r: &[Fq2; 3]) -> ProgramResult {
+    let mut data = account.try_borrow_mut_data()?;
+    let dst = &mut data[offset..offset + 3 * FP2_DATA_LEN];
+    dst.copy_from_slice(&to_bytes!(r[0], r[1], r[2]).unwrap());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ec::bn::G2Prepared;
+    use ark_ec::ProjectiveCurve;
+    use ark_ff::{to_bytes, One};
+    use ark_groth16::prepare_inputs;
+    use circuit::PointEncoding::{self, Compressed, Uncompressed};
+    use circuit::{write_g1, write_g1_projective};
+    use solana_program::pubkey::Pubkey;
+
+    use super::*;
+    use crate::instruction::VerifierInstruction::{self, *};
+    use crate::instruction::{Payload, StepIndex};
+    use crate::schedule::miller_loop_steps;
+    use crate::test_utils::{run, scratch_data, TestAccount, SESSION_ID};
+
+    fn point_data(point: Vec<u8>, encoding: PointEncoding) -> Vec<u8> {
+        let mut data = vec![encoding as u8];
+        data.extend(point);
+        data
+    }
+
+    #[test]
+    fn test_gamma_loop_matches_prepare_inputs() {
+        let (pvk, _, public_inputs) = circuit::demo_proof().unwrap();
+        let prepared_input = prepare_inputs(&pvk, &public_inputs).unwrap();
+
+        let program_id = Pubkey::new_unique();
+        let mut gamma_account = TestAccount::scratch(&program_id, SESSION_ID, Role::Gamma);
+        let input = point_data(write_g1_projective(&prepared_input, Compressed), Compressed);
+        for data in miller_loop_steps(GammaMillerLoop, &input) {
+            run(&program_id, &mut [&mut gamma_account], &data);
+        }
+
+        let expected = circuit::offline_miller_loop(
+            &G1Prepared::from(prepared_input.into_affine()),
+            &pvk.gamma_g2_neg_pc,
+            Fq12::one(),
+        );
+        assert_eq!(get_account_data(&gamma_account.info()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_step_data_has_exact_length() {
+        let (pvk, proof, public_inputs) = circuit::demo_proof().unwrap();
+        let prepared_input = prepare_inputs(&pvk, &public_inputs).unwrap();
+        let program_id = Pubkey::new_unique();
+        let mut payer = TestAccount::payer();
+        let mut gamma_account = TestAccount::scratch(&program_id, SESSION_ID, Role::Gamma);
+        let mut delta_account = TestAccount::scratch(&program_id, SESSION_ID, Role::Delta);
+        let mut step = |account: &mut TestAccount, data: &[u8]| {
+            let infos = [payer.info(), account.info()];
+            crate::process_instruction(&program_id, &infos, &scratch_data(data, SESSION_ID))
+        };
+
+        // the prepared input is 96 bytes uncompressed, not a byte more or less
+        let input = point_data(
+            write_g1_projective(&prepared_input, Uncompressed),
+            Uncompressed,
+        );
+        let gamma = miller_loop_steps(GammaMillerLoop, &input).remove(0);
+        step(&mut gamma_account, &gamma).unwrap();
+        for data in [&gamma[..gamma.len() - 1], &[&gamma[..], &[0]].concat()].iter() {
+            assert_eq!(
+                step(&mut gamma_account, data),
+                Err(VerifierError::InvalidDataLength.into())
+            );
+        }
+
+        let input = point_data(write_g1(&proof.c, Compressed), Compressed);
+        let delta = miller_loop_steps(DeltaMillerLoop, &input).remove(0);
+        step(&mut delta_account, &delta).unwrap();
+        assert_eq!(
+            step(&mut delta_account, &[&delta[..], &[0]].concat()),
+            Err(VerifierError::InvalidDataLength.into())
+        );
+    }
+
+    // The last step of each loop (j == 89) applies the two trailing ell coefficients
+    // outside the ATE_LOOP_COUNT loop. Check it against the offline loop on its own: the
+    // accumulator before it plus exactly coefficients 89 and 90 must give the full loop.
+    fn check_final_step(
+        stage: fn(StepIndex, Payload) -> VerifierInstruction,
+        role: Role,
+        input: &[u8],
+        p: G1Affine,
+        q: &G2Prepared<Parameters>,
+    ) {
+        let program_id = Pubkey::new_unique();
+        let mut account = TestAccount::scratch(&program_id, SESSION_ID, role);
+        let steps = miller_loop_steps(stage, input);
+        let (last, rest) = steps.split_last().unwrap();
+        assert_eq!(&last[1..3], &[0, 89]);
+        assert_eq!(q.ell_coeffs.len(), 91);
+
+        for data in rest.iter() {
+            run(&program_id, &mut [&mut account], data);
+        }
+        let before = get_account_data(&account.info()).unwrap();
+        run(&program_id, &mut [&mut account], last);
+        let after = get_account_data(&account.info()).unwrap();
+
+        let p = G1Prepared::from(p);
+        let expected = circuit::offline_miller_loop(&p, q, Fq12::one());
+        assert_eq!(after, expected);
+        assert_ne!(before, expected);
+
+        let mut trailing = before;
+        circuit::ell(&mut trailing, &q.ell_coeffs[89], &p.0);
+        circuit::ell(&mut trailing, &q.ell_coeffs[90], &p.0);
+        assert_eq!(trailing, after);
+    }
+
+    #[test]
+    fn test_final_step_trailing_coeffs() {
+        let (pvk, proof, public_inputs) = circuit::demo_proof().unwrap();
+        let prepared_input = prepare_inputs(&pvk, &public_inputs).unwrap();
+        check_final_step(
+            GammaMillerLoop,
+            Role::Gamma,
+            &point_data(to_bytes!(prepared_input).unwrap(), Uncompressed),
+            prepared_input.into_affine(),
+            &pvk.gamma_g2_neg_pc,
+        );
+        check_final_step(
+            DeltaMillerLoop,
+            Role::Delta,
+            &point_data(write_g1(&proof.c, Compressed), Compressed),
+            proof.c,
+            &pvk.delta_g2_neg_pc,
+        );
+    }
+
+    // Packed steps must be consecutive and stop at the last step.
+    #[test]
+    fn test_packed_step_count() {
+        let (_, proof, _) = circuit::demo_proof().unwrap();
+        let program_id = Pubkey::new_unique();
+        let mut delta_account = TestAccount::scratch(&program_id, SESSION_ID, Role::Delta);
+        let mut payer = TestAccount::payer();
+        let input = point_data(write_g1(&proof.c, Compressed), Compressed);
+        let mut step = |i: u8, j: u8, steps: u8| {
+            let data = DeltaMillerLoopSteps(StepIndex::new(i, j), steps, Payload(input.clone()));
+            let infos = [payer.info(), delta_account.info()];
+            let data = crate::test_utils::scratch_data(&data.pack(), SESSION_ID);
+            crate::process_instruction(&program_id, &infos, &data)
+        };
+        assert_eq!(step(64, 0, 0), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(step(1, 88, 3), Err(ProgramError::InvalidInstructionData));
+        step(1, 88, 2).unwrap();
+        assert_eq!(step_range(1, 88, 2), Some(vec![(1, 88), (0, 89)]));
+    }
+
+    #[test]
+    fn test_ab_loop_matches_offline_loop() {
+        let (_, proof, _) = circuit::demo_proof().unwrap();
+        let program_id = Pubkey::new_unique();
+        let mut ab_account = TestAccount::scratch(&program_id, SESSION_ID, Role::Ab);
+        for data in miller_loop_steps(AbMillerLoop, &circuit::proof_ab(&proof, Compressed)) {
+            run(&program_id, &mut [&mut ab_account], &data);
+        }
+
+        let expected = circuit::offline_miller_loop(
+            &G1Prepared::from(proof.a),
+            &G2Prepared::from(proof.b),
+            Fq12::one(),
+        );
+        assert_eq!(get_account_data(&ab_account.info()).unwrap(), expected);
     }
-    f
 }
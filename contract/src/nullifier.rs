@@ -0,0 +1,189 @@
+use std::slice::Iter;
+
+use solana_program::account_info::{next_account_info, AccountInfo};
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::sysvar::Sysvar;
+
+use crate::error::VerifierError;
+use crate::header::{AccountKind, ACCOUNT_HEADER_LEN};
+use crate::scratch::Scratch;
+use crate::utils::create_program_account;
+
+// Nullifiers spent by successful verifications, one program-derived account each, so
+// that a proof of a privacy-style application cannot be replayed. The final step, sent
+// with `FLAG_NULLIFIER`, takes the nullifier from the session's inputs account: public
+// input `NULLIFIER_INPUT` of the inputs folded on-chain, as a 32-byte little-endian
+// value (see `public_inputs.rs`). Once the proof checked out against exactly these
+// inputs, it creates the nullifier's account at the payer's expense. A nullifier whose
+// account exists already fails the step with `NullifierUsed`; an invalid proof spends
+// nothing, and a key without public inputs has no nullifier to spend.
+//
+// Nullifiers are scoped to the verifying key, the address being derived from
+// `NULLIFIER_SEED || vk id || nullifier` with the vk id of the result log. The circuit
+// is what ties the nullifier to the proof's secrets; the program never takes one from
+// the client.
+//
+// nullifier account layout:
+//   0..8   account header of kind `Nullifier` (see `header.rs`)
+//   8..16  slot the nullifier was spent in (u64, little-endian)
+pub const NULLIFIER_SEED: &[u8] = b"nullifier";
+pub const NULLIFIER_LEN: usize = 32;
+pub const NULLIFIER_INPUT: usize = 0;
+pub const NULLIFIER_DATA_LEN: usize = ACCOUNT_HEADER_LEN + 8;
+
+pub fn nullifier_address(
+    program_id: &Pubkey,
+    vk_id: &[u8; 32],
+    nullifier: &[u8; NULLIFIER_LEN],
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[NULLIFIER_SEED, vk_id, nullifier], program_id)
+}
+
+// Spend `nullifier`: the next accounts are its account and the system program.
+pub fn spend_nullifier<'a>(
+    scratch: &Scratch<'_, 'a>,
+    accounts_iter: &mut Iter<AccountInfo<'a>>,
+    vk_id: &[u8; 32],
+    nullifier: &[u8; NULLIFIER_LEN],
+) -> ProgramResult {
+    let account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let program_id = scratch.program_id();
+    let (address, bump) = nullifier_address(program_id, vk_id, nullifier);
+    if *account.key != address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if account.owner == program_id {
+        return Err(VerifierError::NullifierUsed.into());
+    }
+
+    let seeds: &[&[u8]] = &[NULLIFIER_SEED, vk_id, nullifier, &[bump]];
+    create_program_account(
+        program_id,
+        scratch.payer(),
+        account,
+        system_program,
+        seeds,
+        NULLIFIER_DATA_LEN,
+    )?;
+
+    let slot = Clock::get()?.slot;
+    let mut data = account.try_borrow_mut_data()?;
+    if data.len() < NULLIFIER_DATA_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    data[..ACCOUNT_HEADER_LEN].copy_from_slice(&AccountKind::Nullifier.header());
+    data[ACCOUNT_HEADER_LEN..NULLIFIER_DATA_LEN].copy_from_slice(&slot.to_le_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use ark_bn254::Fq12;
+    use ark_ff::One;
+    use solana_program::rent::Rent;
+    use solana_program::system_instruction;
+    use solana_program::system_program;
+
+    use super::*;
+    use crate::scratch::Role;
+    use crate::test_utils::{
+        attesting_step, bound_inputs_account, final_step_accounts, install_stubs, invalid_y15,
+        invoked, scratch_data, TestAccount, NULLIFIER, SESSION_ID, SLOT,
+    };
+    use crate::utils::vk_id;
+    use crate::{FLAG_NULLIFIER, FLAG_WRITE_RESULT};
+
+    fn final_step(
+        program_id: &Pubkey,
+        flags: u8,
+        y15: Fq12,
+        nullifier: &mut TestAccount,
+    ) -> ProgramResult {
+        let mut payer = TestAccount::payer();
+        let (mut y14_account, mut y15_account) = final_step_accounts(program_id, SESSION_ID, y15);
+        let mut inputs_account = bound_inputs_account(
+            program_id,
+            SESSION_ID,
+            [1; 32],
+            &mut y14_account,
+            &mut y15_account,
+        );
+        let mut result = TestAccount::scratch(program_id, SESSION_ID, Role::Result);
+        let mut system = TestAccount::new(&Pubkey::default(), 0);
+        system.key = system_program::id();
+        let mut infos = vec![
+            payer.info(),
+            y14_account.info(),
+            y15_account.info(),
+            inputs_account.info(),
+        ];
+        if flags & FLAG_WRITE_RESULT != 0 {
+            infos.push(result.info());
+        }
+        infos.push(nullifier.info());
+        infos.push(system.info());
+        let data = scratch_data(&attesting_step(flags, &[]), SESSION_ID);
+        crate::process_instruction(program_id, &infos, &data)
+    }
+
+    #[test]
+    fn test_spend_nullifier() {
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let vk_id = vk_id(None);
+        let (address, _) = nullifier_address(&program_id, &vk_id, &NULLIFIER);
+        let mut nullifier = TestAccount::new(&system_program::id(), NULLIFIER_DATA_LEN);
+        nullifier.key = address;
+
+        // an invalid proof spends nothing
+        let invalid = invalid_y15();
+        let flags = FLAG_NULLIFIER | FLAG_WRITE_RESULT;
+        final_step(&program_id, flags, invalid, &mut nullifier).unwrap();
+        assert!(!invoked().iter().any(|ix| ix.accounts[1].pubkey == address));
+
+        final_step(&program_id, FLAG_NULLIFIER, Fq12::one(), &mut nullifier).unwrap();
+        let payer = TestAccount::payer().key;
+        let lamports = Rent::default().minimum_balance(NULLIFIER_DATA_LEN);
+        let len = NULLIFIER_DATA_LEN as u64;
+        let create =
+            system_instruction::create_account(&payer, &address, lamports, len, &program_id);
+        assert_eq!(invoked().last(), Some(&create));
+        assert_eq!(
+            nullifier.data[..ACCOUNT_HEADER_LEN],
+            AccountKind::Nullifier.header()
+        );
+        assert_eq!(nullifier.data[ACCOUNT_HEADER_LEN..], SLOT.to_le_bytes());
+
+        // once created, the nullifier cannot be spent again
+        nullifier.owner = program_id;
+        assert_eq!(
+            final_step(&program_id, FLAG_NULLIFIER, Fq12::one(), &mut nullifier),
+            Err(VerifierError::NullifierUsed.into())
+        );
+
+        // a pre-funded address is allocated and assigned rather than created
+        nullifier.owner = system_program::id();
+        nullifier.lamports = 1;
+        final_step(&program_id, FLAG_NULLIFIER, Fq12::one(), &mut nullifier).unwrap();
+        let calls = invoked();
+        assert_eq!(
+            &calls[calls.len() - 3..],
+            &[
+                system_instruction::transfer(&payer, &address, lamports - 1),
+                system_instruction::allocate(&address, len),
+                system_instruction::assign(&address, &program_id),
+            ]
+        );
+
+        // the account must be the nullifier's under this key
+        nullifier.key = nullifier_address(&program_id, &[0; 32], &NULLIFIER).0;
+        assert_eq!(
+            final_step(&program_id, FLAG_NULLIFIER, Fq12::one(), &mut nullifier),
+            Err(ProgramError::InvalidSeeds)
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use std::slice::Iter;
+
+use arrayref::array_ref;
+use solana_program::account_info::{next_account_info, AccountInfo};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use crate::process_instruction;
+
+// Several independent instructions ("tasks") packed into one, for workloads small enough
+// that the per-transaction overhead dominates. Each task runs as if it were sent on its
+// own; a failing task does not abort the others, its bit in the result bitmap is just
+// left clear. Nothing is aggregated, every task is checked independently.
+//
+// instruction data: task count (1 byte) || task*
+//   task: account count (1 byte) || data length (u16, little-endian) || data
+// accounts: [bitmap account (writable), accounts of task 0, accounts of task 1, ...]
+//
+// Task data is a complete instruction (`t, i, j, input`), session-guarded steps
+// included. Packed instructions cannot be nested.
+//
+// bitmap account layout:
+//   0..8    bit k set iff task k succeeded (u64, little-endian)
+//
+// Tasks share the compute budget of the one instruction, so the sum of their costs has
+// to fit it; `MAX_PACKED_TASKS` only bounds the bitmap. A task that fails after writing
+// to its accounts leaves those writes in place, so only pack tasks that write nothing
+// but their result, such as final steps.
+pub const PACKED_TAG: usize = 19;
+pub const MAX_PACKED_TASKS: usize = 64;
+pub const BITMAP_DATA_LEN: usize = 8;
+
+pub fn process_packed(
+    program_id: &Pubkey,
+    accounts_iter: &mut Iter<AccountInfo>,
+    input: &[u8],
+) -> ProgramResult {
+    let bitmap_account = next_account_info(accounts_iter)?;
+    let (&count, mut input) = input
+        .split_first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+    if count as usize > MAX_PACKED_TASKS {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let mut bitmap = 0u64;
+    for k in 0..count as usize {
+        if input.len() < 3 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let num_accounts = input[0] as usize;
+        let len = u16::from_le_bytes(*array_ref![input, 1, 2]) as usize;
+        if input.len() < 3 + len {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let data = &input[3..3 + len];
+        input = &input[3 + len..];
+
+        let accounts = accounts_iter.as_slice();
+        if accounts.len() < num_accounts {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        let (task_accounts, _) = accounts.split_at(num_accounts);
+        for _ in 0..num_accounts {
+            accounts_iter.next();
+        }
+
+        if data.first().map(|&t| t as usize) == Some(PACKED_TAG) {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        if process_instruction(program_id, task_accounts, data).is_ok() {
+            bitmap |= 1 << k;
+        }
+    }
+    if !input.is_empty() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let mut dst = bitmap_account.try_borrow_mut_data()?;
+    if dst.len() < BITMAP_DATA_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    dst[0..BITMAP_DATA_LEN].copy_from_slice(&bitmap.to_le_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use ark_bn254::Fq12;
+    use ark_ff::One;
+
+    use super::*;
+    use crate::test_utils::{final_step_accounts, invalid_y15, scratch_data, TestAccount};
+
+    fn pack_task(data: &mut Vec<u8>, num_accounts: u8, task: &[u8]) {
+        data.push(num_accounts);
+        data.extend((task.len() as u16).to_le_bytes().iter());
+        data.extend(task.iter());
+    }
+
+    #[test]
+    fn test_packed_final_steps() {
+        let program_id = Pubkey::new_unique();
+        let mut bitmap_account = TestAccount::new(&program_id, BITMAP_DATA_LEN);
+        let mut payer = TestAccount::payer();
+        let (mut valid_y14, mut valid_y15) = final_step_accounts(&program_id, 1, Fq12::one());
+        let (mut invalid_y14, mut invalid_y15) = final_step_accounts(&program_id, 2, invalid_y15());
+
+        let mut data = vec![PACKED_TAG as u8, 0, 0, 2];
+        pack_task(&mut data, 3, &scratch_data(&[16, 0, 0], 2));
+        pack_task(&mut data, 3, &scratch_data(&[16, 0, 0], 1));
+        let payer = payer.info();
+        let infos = [
+            bitmap_account.info(),
+            payer.clone(),
+            invalid_y14.info(),
+            invalid_y15.info(),
+            payer,
+            valid_y14.info(),
+            valid_y15.info(),
+        ];
+        process_instruction(&program_id, &infos, &data).unwrap();
+        drop(infos);
+        assert_eq!(bitmap_account.data, 0b10u64.to_le_bytes());
+
+        // nested packing is rejected
+        let mut nested = vec![PACKED_TAG as u8, 0, 0, 1];
+        pack_task(&mut nested, 0, &[PACKED_TAG as u8, 0, 0, 0]);
+        assert!(process_instruction(&program_id, &[bitmap_account.info()], &nested).is_err());
+    }
+}
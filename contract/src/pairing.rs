@@ -0,0 +1,291 @@
+use std::slice::Iter;
+
+use ark_bn254::G1Affine;
+use ark_ff::One;
+use solana_program::account_info::AccountInfo;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::hash::{hash, HASH_BYTES};
+use solana_program::program_error::ProgramError;
+
+use groth16_sol_core::miller_loop::step_range;
+
+use crate::curve::{Fq12, Fq2};
+use crate::error::VerifierError;
+use crate::miller_loop::{
+    pairing_step, read_projective, write_projective, CoeffSource, PairG2, Table,
+};
+use crate::point::PointReader;
+use crate::scratch::{write_tag, Role, Scratch, SCRATCH_HEADER_LEN, UNBOUND_TAG};
+use crate::utils::{get_account_data, put_account_data, BN254_DATA_LEN};
+use crate::vk::FP2_DATA_LEN;
+
+// A product of pairings checked over a chunked session, for pairing-based protocols
+// besides Groth16 such as aggregated BLS signatures or KZG openings:
+//
+//   e(P_1, Q_1) * .. * e(P_k, Q_k) == target
+//
+// The Miller loops of all pairs run together over the (i, j) schedule of the gamma and
+// delta loops, sharing the squarings of one accumulator, `steps` steps per instruction
+// as for `GammaMillerLoopSteps`; the product then goes through the final
+// exponentiation of a proof. A Groth16 proof is the product of (A, B), (prepared input,
+// -gamma) and (C, -delta) against alpha_g1_beta_g2.
+//
+// instruction data: t = 31, i, j, steps || PAIRING_MILLER_LOOP || encoding (see
+//                   `point.rs`) || pair* || session id
+//   pair: kind || P (G1Affine) || Q (G2Affine, PAIR_POINT only)
+// accounts: [payer (signer), pairing account (writable)], after the VK account when
+//           sent with `VK_ACCOUNT_FLAG`
+//
+// A PAIR_POINT pair carries Q, whose lines are computed from a running point kept in the
+// pairing account, as in the A·B loop; at most `MAX_PAIRING_POINTS` pairs can. The Q of
+// PAIR_GAMMA and PAIR_DELTA pairs is the verifying key's -gamma_g2 or -delta_g2, its
+// lines read from the baked key or the VK account. The first step (j = 0) starts the
+// accumulator at one, checks that every point is on its curve and records the hash of
+// the pairs; every later step must carry the same pairs, or fails with `PairsMismatch`.
+// Like all points of this program, Q is not checked to be in the G2 subgroup, which a
+// protocol relying on it has to ensure itself.
+//
+// `PrepareFinal` with i = `PREPARE_PAIRING` and no payload then takes [pairing account,
+// final account] and moves the accumulator into the final account, and the final check
+// compares against one with `FLAG_TARGET_ONE`, or against the target at the start of
+// its input with `FLAG_INLINE_VK`.
+//
+// pairing account layout, after the scratch header (see `scratch.rs`):
+//   0..384    accumulator f (Fp12)
+//   384..416  sha256 of the pairs as sent, encoding byte included
+//   416..     R of each PAIR_POINT pair in order, in homogeneous projective
+//             coordinates x, y, z (Fp2 each), 192 bytes apiece
+pub const PAIRING_MILLER_LOOP: u8 = 2;
+pub const PAIR_POINT: u8 = 0;
+pub const PAIR_GAMMA: u8 = 1;
+pub const PAIR_DELTA: u8 = 2;
+pub const MAX_PAIRING_POINTS: usize = 8;
+
+const PAIRS_HASH_OFFSET: usize = SCRATCH_HEADER_LEN + BN254_DATA_LEN;
+const POINTS_OFFSET: usize = PAIRS_HASH_OFFSET + HASH_BYTES;
+const PROJECTIVE_LEN: usize = 3 * FP2_DATA_LEN;
+pub const PAIRING_DATA_LEN: usize =
+    BN254_DATA_LEN + HASH_BYTES + MAX_PAIRING_POINTS * PROJECTIVE_LEN;
+
+pub fn pairing_product_loop(
+    scratch: &Scratch,
+    accounts_iter: &mut Iter<AccountInfo>,
+    i: usize,
+    j: usize,
+    steps: usize,
+    pairs_data: &[u8],
+    source: CoeffSource,
+) -> ProgramResult {
+    let pairing_account = scratch.next_account(accounts_iter, Role::Pairing)?;
+    let range = step_range(i, j, steps).ok_or(ProgramError::InvalidInstructionData)?;
+    let mut pairs = read_pairs(pairs_data)?;
+    let points = pairs
+        .iter()
+        .filter(|(_, q)| matches!(q, PairG2::Point(..)))
+        .count();
+    if pairs.is_empty() || points > MAX_PAIRING_POINTS {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let pairs_hash = hash(pairs_data).to_bytes();
+    let mut f = if j == 0 {
+        let on_curve = pairs.iter().all(|(p, q)| match q {
+            PairG2::Point(q, _) => p.is_on_curve() && q.is_on_curve(),
+            PairG2::Prepared(_) => p.is_on_curve(),
+        });
+        if !on_curve {
+            return Err(VerifierError::InvalidEncoding.into());
+        }
+        let mut data = pairing_account.try_borrow_mut_data()?;
+        data[PAIRS_HASH_OFFSET..POINTS_OFFSET].copy_from_slice(&pairs_hash);
+        Fq12::one()
+    } else {
+        let data = pairing_account.try_borrow_data()?;
+        if data[PAIRS_HASH_OFFSET..POINTS_OFFSET] != pairs_hash {
+            return Err(VerifierError::PairsMismatch.into());
+        }
+        drop(data);
+        for (k, r) in running_points(&mut pairs).enumerate() {
+            *r = read_projective(pairing_account, POINTS_OFFSET + k * PROJECTIVE_LEN)?;
+        }
+        get_account_data(pairing_account)?
+    };
+
+    for (i, j) in range {
+        pairing_step(&mut f, &mut pairs, i, j, source)?;
+    }
+    put_account_data(pairing_account, &f)?;
+    for (k, r) in running_points(&mut pairs).enumerate() {
+        write_projective(pairing_account, POINTS_OFFSET + k * PROJECTIVE_LEN, r)?;
+    }
+    Ok(())
+}
+
+fn read_pairs(data: &[u8]) -> Result<Vec<(G1Affine, PairG2)>, ProgramError> {
+    let reader = PointReader::new(data)?;
+    let encoding = reader.encoding();
+    let mut rest = reader.rest();
+    let mut pairs = vec![];
+    while let Some((&kind, pair)) = rest.split_first() {
+        let mut reader = PointReader::with_encoding(encoding, pair);
+        let p = reader.g1()?;
+        let q = match kind {
+            PAIR_POINT => PairG2::point(reader.g2()?),
+            PAIR_GAMMA => PairG2::Prepared(Table::Gamma),
+            PAIR_DELTA => PairG2::Prepared(Table::Delta),
+            _ => return Err(ProgramError::InvalidInstructionData),
+        };
+        pairs.push((p, q));
+        rest = reader.rest();
+    }
+    Ok(pairs)
+}
+
+fn running_points(pairs: &mut [(G1Affine, PairG2)]) -> impl Iterator<Item = &mut [Fq2; 3]> {
+    pairs.iter_mut().filter_map(|(_, q)| match q {
+        PairG2::Point(_, r) => Some(r),
+        PairG2::Prepared(_) => None,
+    })
+}
+
+// The accumulator of a finished pairing-product Miller loop as the final
+// exponentiation's input.
+pub fn prepare_pairing(scratch: &Scratch, accounts_iter: &mut Iter<AccountInfo>) -> ProgramResult {
+    let pairing_account = scratch.next_account(accounts_iter, Role::Pairing)?;
+    let final_account = scratch.next_account(accounts_iter, Role::Final)?;
+    put_account_data(final_account, &get_account_data(pairing_account)?)?;
+    // a pairing product attests to no public inputs
+    write_tag(final_account, &UNBOUND_TAG)
+}
+
+#[cfg(test)]
+mod test {
+    use ark_bn254::{Fq, Fr, G2Affine};
+    use ark_ec::{AffineCurve, ProjectiveCurve};
+    use ark_ff::{to_bytes, Zero};
+    use ark_std::{test_rng, UniformRand};
+    use circuit::PointEncoding::{self, Compressed, Uncompressed};
+    use circuit::{write_g1, write_g2};
+    use solana_program::pubkey::Pubkey;
+
+    use super::*;
+    use crate::final_exponentiation::FLAG_INLINE_VK;
+    use crate::schedule::{pairing_product_steps, with_scratch, PAYER};
+    use crate::test_utils::{run_step, session_accounts, TestAccount, SESSION_ID};
+
+    fn pairs_data(encoding: PointEncoding, pairs: &[(u8, G1Affine, Option<G2Affine>)]) -> Vec<u8> {
+        let mut data = vec![encoding as u8];
+        for (kind, p, q) in pairs {
+            data.push(*kind);
+            data.extend(write_g1(p, encoding));
+            data.extend(q.iter().flat_map(|q| write_g2(q, encoding)));
+        }
+        data
+    }
+
+    // Check the product of `pairs` against `target`, or one, step by step.
+    fn check(pairs: &[u8], per_instruction: u8, target: Option<Fq12>) -> ProgramResult {
+        let program_id = Pubkey::new_unique();
+        let mut accounts = session_accounts(&program_id);
+        accounts.push(TestAccount::scratch(&program_id, SESSION_ID, Role::Pairing));
+        let mut steps = pairing_product_steps(accounts.len() - 1, pairs, per_instruction);
+        if let Some(target) = target {
+            let check = &mut steps.last_mut().unwrap().1;
+            check[1] = FLAG_INLINE_VK;
+            check.extend(to_bytes!(target).unwrap());
+        }
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        for step in steps.iter() {
+            run_step(&program_id, &mut accounts, step)?;
+        }
+        Ok(())
+    }
+
+    // e(a * g1, b * g2) * e(-ab * g1, g2) == 1, as a BLS signature check is
+    #[test]
+    fn test_pairing_product() {
+        let rng = &mut test_rng();
+        let (a, b) = (Fr::rand(rng), Fr::rand(rng));
+        let g1 = G1Affine::prime_subgroup_generator();
+        let g2 = G2Affine::prime_subgroup_generator();
+        let pairs = |ab: Fr| {
+            [
+                (
+                    PAIR_POINT,
+                    g1.mul(a).into_affine(),
+                    Some(g2.mul(b).into_affine()),
+                ),
+                (PAIR_POINT, -g1.mul(ab).into_affine(), Some(g2)),
+                // contributes nothing
+                (PAIR_POINT, G1Affine::zero(), Some(g2)),
+            ]
+        };
+        check(&pairs_data(Uncompressed, &pairs(a * b)), 8, None).unwrap();
+        check(&pairs_data(Compressed, &pairs(a * b)[..2]), 65, None).unwrap();
+        assert_eq!(
+            check(&pairs_data(Compressed, &pairs(a * b + Fr::one())), 8, None),
+            Err(VerifierError::InvalidProof.into())
+        );
+    }
+
+    // A Groth16 proof as a pairing product with the baked key's prepared points.
+    #[test]
+    fn test_prepared_pairs() {
+        let (pvk, proof, _) = circuit::demo_proof().unwrap();
+        let prepared_input = circuit::folded_input().unwrap().prepared_input;
+        let pairs = pairs_data(
+            Compressed,
+            &[
+                (PAIR_POINT, proof.a, Some(proof.b)),
+                (PAIR_GAMMA, prepared_input.into_affine(), None),
+                (PAIR_DELTA, proof.c, None),
+            ],
+        );
+        check(&pairs, 4, Some(pvk.alpha_g1_beta_g2)).unwrap();
+        assert_eq!(
+            check(&pairs, 4, None),
+            Err(VerifierError::InvalidProof.into())
+        );
+    }
+
+    #[test]
+    fn test_pairs_mismatch() {
+        let g1 = G1Affine::prime_subgroup_generator();
+        let g2 = G2Affine::prime_subgroup_generator();
+        let pairs = pairs_data(Uncompressed, &[(PAIR_POINT, g1, Some(g2))]);
+        let other = pairs_data(Uncompressed, &[(PAIR_POINT, -g1, Some(g2))]);
+        let program_id = Pubkey::new_unique();
+        let mut accounts = session_accounts(&program_id);
+        accounts.push(TestAccount::scratch(&program_id, SESSION_ID, Role::Pairing));
+        let pairing = accounts.len() - 1;
+        let mut steps = pairing_product_steps(pairing, &pairs, 1);
+        steps.truncate(1);
+        steps.extend(pairing_product_steps(pairing, &other, 1).drain(1..2));
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        run_step(&program_id, &mut accounts, &steps[0]).unwrap();
+        assert_eq!(
+            run_step(&program_id, &mut accounts, &steps[1]),
+            Err(VerifierError::PairsMismatch.into())
+        );
+
+        // points off their curve are rejected up front, as are unknown kinds
+        let mut off_curve = g1;
+        off_curve.y += Fq::one();
+        let data = [
+            pairs_data(Uncompressed, &[(PAIR_POINT, off_curve, Some(g2))]),
+            [&[0][..], &[3], &write_g1(&g1, Uncompressed)].concat(),
+        ];
+        let errors = [
+            VerifierError::InvalidEncoding.into(),
+            ProgramError::InvalidInstructionData,
+        ];
+        for (data, error) in data.iter().zip(errors.iter()) {
+            let mut steps = pairing_product_steps(pairing, data, 1);
+            with_scratch(&mut steps, PAYER, SESSION_ID);
+            assert_eq!(
+                run_step(&program_id, &mut accounts, &steps[0]),
+                Err(error.clone())
+            );
+        }
+    }
+}
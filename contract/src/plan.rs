@@ -0,0 +1,234 @@
+use std::ops::Range;
+
+use crate::curve::{ATE_LOOP_COUNT, X_NAF};
+use crate::miller_loop::INLINE_COEFFS_FLAG;
+use crate::session::SESSION_FLAG;
+use crate::vk::VK_ACCOUNT_FLAG;
+
+// Compute-unit-aware packing of a verification's steps. Steps cost wildly different
+// amounts, from the Fp12 inversion of easy part 1 to the single multiplication of y9, so
+// one step count per instruction either wastes most transactions or overruns the budget
+// of some. `StepCosts` holds what one step of each instruction tag costs, as measured on
+// a cluster (the client's `calibrate` command simulates every step of a verification);
+// from it, `miller_loop_steps` and `naf_digits` give the chunk sizes of the packed
+// Miller-loop and exponentiation-by-x instructions that fit a compute-unit target, and
+// `plan` groups consecutive instructions into transactions under the same target.
+//
+// A packed instruction is estimated as its step count times the cost of one step, which
+// overestimates it by the per-instruction overhead it saves.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StepCosts {
+    // compute units of one step, by instruction tag; zero where not measured
+    costs: [u64; 32],
+}
+
+impl StepCosts {
+    pub fn new() -> StepCosts {
+        StepCosts::default()
+    }
+
+    // Record that the instruction with `data` consumed `units`, keeping the most
+    // expensive step seen per tag. Packed instructions count per step.
+    pub fn record(&mut self, data: &[u8], units: u64) {
+        let (tag, steps) = match steps_of(data) {
+            Some(steps) => steps,
+            None => return,
+        };
+        // a fused step runs one gamma and one delta step, split evenly between the two
+        let (tags, steps) = if tag == FUSED_TAG {
+            ([0, 1], 2 * steps)
+        } else {
+            ([tag, tag], steps)
+        };
+        let per_step = units / steps;
+        for &tag in tags.iter() {
+            let cost = &mut self.costs[tag as usize];
+            *cost = (*cost).max(per_step);
+        }
+    }
+
+    pub fn set(&mut self, tag: u8, units: u64) {
+        self.costs[tag as usize] = units;
+    }
+
+    // The measured tags and their cost per step.
+    pub fn costs(&self) -> impl Iterator<Item = (u8, u64)> + '_ {
+        (0..32u8)
+            .zip(self.costs.iter().cloned())
+            .filter(|&(_, units)| units > 0)
+    }
+
+    // The estimated cost of the instruction with `data`, `None` if one of its steps was
+    // never measured.
+    pub fn cost(&self, data: &[u8]) -> Option<u64> {
+        let (tag, steps) = steps_of(data)?;
+        let per_step = if tag == FUSED_TAG {
+            self.measured(0)? + self.measured(1)?
+        } else {
+            self.measured(tag)?
+        };
+        Some(per_step * steps)
+    }
+
+    fn measured(&self, tag: u8) -> Option<u64> {
+        Some(self.costs[tag as usize]).filter(|&units| units > 0)
+    }
+
+    // Gamma and delta Miller-loop steps per instruction within `target`, both loops
+    // advanced together when `fused`; one when the loops were not measured.
+    pub fn miller_loop_steps(&self, target: u64, fused: bool) -> u8 {
+        let (gamma, delta) = (self.measured(0), self.measured(1));
+        let per_step = match (gamma, delta) {
+            (Some(gamma), Some(delta)) if fused => gamma + delta,
+            (Some(gamma), Some(delta)) => gamma.max(delta),
+            _ => return 1,
+        };
+        chunk(target, per_step, ATE_LOOP_COUNT.len())
+    }
+
+    // NAF digits per exponentiation-by-x step (y0, y4, y6) within `target`; one when
+    // none of them was measured.
+    pub fn naf_digits(&self, target: u64) -> u8 {
+        match NAF_TAGS.iter().filter_map(|&tag| self.measured(tag)).max() {
+            Some(per_digit) => chunk(target, per_digit, X_NAF.len()),
+            None => 1,
+        }
+    }
+
+    // Consecutive runs of the instructions `steps` (their data), each run to be sent in
+    // one transaction: as many instructions as fit `target` together. An instruction
+    // over the target on its own, or not measured, gets a transaction to itself.
+    pub fn plan<'a, I>(&self, steps: I, target: u64) -> Vec<Range<usize>>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let mut runs: Vec<Range<usize>> = vec![];
+        let mut total = 0;
+        for (index, data) in steps.into_iter().enumerate() {
+            let cost = self.cost(data).unwrap_or(target);
+            match runs.last_mut() {
+                Some(run) if total + cost <= target => {
+                    run.end = index + 1;
+                    total += cost;
+                }
+                _ => {
+                    runs.push(index..index + 1);
+                    total = cost;
+                }
+            }
+        }
+        runs
+    }
+}
+
+const FUSED_TAG: u8 = 29;
+// exponentiation-by-x steps, `i` digits each
+const NAF_TAGS: [u8; 3] = [5, 8, 9];
+
+// The tag of one step of the instruction with `data` and how many steps it runs,
+// whatever flags it is sent with: packed gamma and delta steps count as single ones.
+fn steps_of(data: &[u8]) -> Option<(u8, u64)> {
+    let flags = (SESSION_FLAG | INLINE_COEFFS_FLAG | VK_ACCOUNT_FLAG) as u8;
+    let tag = data.first()? & !flags;
+    let steps = match tag {
+        27 | 28 | FUSED_TAG | 31 => *data.get(3)?,
+        5 | 8 | 9 => *data.get(1)?,
+        _ => 1,
+    };
+    let tag = match tag {
+        27 | 28 => tag - 27,
+        _ => tag,
+    };
+    Some((tag, steps.max(1) as u64))
+}
+
+fn chunk(target: u64, per_step: u64, max: usize) -> u8 {
+    (target / per_step).max(1).min(max as u64) as u8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::instruction::VerifierInstruction::*;
+    use crate::instruction::{Payload, StepIndex};
+    use crate::schedule::{
+        final_exponentiation_steps, verification_steps, with_packed_miller_loops,
+        with_packed_naf_digits, with_scratch, PAYER,
+    };
+    use crate::test_utils::{run_step, session_accounts, SESSION_ID};
+    use solana_program::pubkey::Pubkey;
+
+    #[test]
+    fn test_step_costs() {
+        let mut costs = StepCosts::new();
+        let step = |i, j| StepIndex::new(i, j);
+        costs.record(
+            &GammaMillerLoop(step(64, 0), Payload(vec![0; 97])).pack(),
+            90_000,
+        );
+        costs.record(
+            &GammaMillerLoop(step(63, 2), Payload(vec![0; 97])).pack(),
+            60_000,
+        );
+        // four delta steps in one instruction, with the VK account flag
+        let mut delta = DeltaMillerLoopSteps(step(64, 0), 4, Payload(vec![0; 33])).pack();
+        delta[0] |= VK_ACCOUNT_FLAG as u8;
+        costs.record(&delta, 320_000);
+        costs.record(&HardPartY0(step(3, 0)).pack(), 30_000);
+        costs.record(&HardPartY9(step(0, 0)).pack(), 5_000);
+        assert_eq!(
+            costs.costs().collect::<Vec<_>>(),
+            [(0, 90_000), (1, 80_000), (5, 10_000), (11, 5_000)]
+        );
+
+        assert_eq!(costs.cost(&HardPartY0(step(8, 0)).pack()), Some(80_000));
+        let fused = GammaDeltaMillerLoop(step(64, 0), 2, Payload::default()).pack();
+        assert_eq!(costs.cost(&fused), Some(340_000));
+        assert_eq!(costs.cost(&EasyPart1(step(0, 0)).pack()), None);
+
+        assert_eq!(costs.miller_loop_steps(400_000, false), 4);
+        assert_eq!(costs.miller_loop_steps(400_000, true), 2);
+        assert_eq!(costs.miller_loop_steps(10_000_000, false), 65);
+        assert_eq!(costs.naf_digits(200_000), 20);
+        assert_eq!(costs.naf_digits(1_000), 1);
+        assert_eq!(StepCosts::new().naf_digits(200_000), 1);
+    }
+
+    // A plan keeps every transaction within the target, and the schedule packed by the
+    // chunk sizes it derives still verifies.
+    #[test]
+    fn test_plan() {
+        let (proof_c, prepared_input, proof_ab) = circuit::initialize().unwrap();
+        let mut costs = StepCosts::new();
+        for tag in (0..=16).chain(Some(21)) {
+            costs.set(tag, 10_000 + 1_000 * tag as u64);
+        }
+        costs.set(3, 150_000);
+        let target = 200_000;
+
+        let mut steps = verification_steps(&proof_c, &prepared_input, &proof_ab);
+        with_packed_miller_loops(&mut steps, costs.miller_loop_steps(target, false));
+        with_packed_naf_digits(&mut steps, costs.naf_digits(target));
+        let plan = costs.plan(steps.iter().map(|(_, data)| &data[..]), target);
+        assert_eq!(plan.first().unwrap().start, 0);
+        assert_eq!(plan.last().unwrap().end, steps.len());
+        for (run, next) in plan.iter().zip(plan.iter().skip(1)) {
+            assert_eq!(run.end, next.start);
+            let total: u64 = steps[run.clone()]
+                .iter()
+                .map(|(_, data)| costs.cost(data).unwrap())
+                .sum();
+            assert!(total <= target);
+            // nothing more would have fit
+            assert!(total + costs.cost(&steps[next.start].1).unwrap() > target);
+        }
+        assert!(plan.len() < final_exponentiation_steps().len());
+
+        let program_id = Pubkey::new_unique();
+        let mut accounts = session_accounts(&program_id);
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        for step in steps.iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use ark_bn254::{G1Affine, G1Projective, G2Affine};
+use ark_ec::models::short_weierstrass_jacobian::GroupAffine;
+use ark_ec::models::SWModelParameters;
+use ark_ec::AffineCurve;
+use ark_ff::{FromBytes, Zero};
+use ark_serialize::{CanonicalDeserializeWithFlags, SWFlags};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+
+use crate::error::VerifierError;
+
+// The curve points of an instruction come after an encoding byte selecting how all of
+// them are written:
+//
+//   0 uncompressed: arkworks `ToBytes`; G1Affine x || y || infinity flag (65 bytes),
+//     G1Projective x || y || z (96 bytes), G2Affine (129 bytes)
+//   1 compressed: arkworks `CanonicalSerialize`; x with the sign of y and the infinity
+//     flag in its top bits, 32 bytes for G1 (projective points are sent affine) and 64
+//     for G2
+//
+// Compressed points halve the instruction data at the cost of a square root per point
+// to recover y. The recovered point is on the curve; like uncompressed points it is not
+// checked to be in the G2 subgroup (G1 has cofactor 1). The `alt_bn128` instruction
+// (t = 23) keeps its own syscall encoding.
+pub const ENCODING_UNCOMPRESSED: u8 = 0;
+pub const ENCODING_COMPRESSED: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointEncoding {
+    Uncompressed,
+    Compressed,
+}
+
+impl PointEncoding {
+    pub fn from_u8(encoding: u8) -> Option<PointEncoding> {
+        match encoding {
+            ENCODING_UNCOMPRESSED => Some(PointEncoding::Uncompressed),
+            ENCODING_COMPRESSED => Some(PointEncoding::Compressed),
+            _ => None,
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            PointEncoding::Uncompressed => ENCODING_UNCOMPRESSED,
+            PointEncoding::Compressed => ENCODING_COMPRESSED,
+        }
+    }
+
+    pub fn g1_len(self) -> usize {
+        match self {
+            PointEncoding::Uncompressed => 65,
+            PointEncoding::Compressed => 32,
+        }
+    }
+
+    pub fn g1_projective_len(self) -> usize {
+        match self {
+            PointEncoding::Uncompressed => 96,
+            PointEncoding::Compressed => 32,
+        }
+    }
+
+    pub fn g2_len(self) -> usize {
+        match self {
+            PointEncoding::Uncompressed => 129,
+            PointEncoding::Compressed => 64,
+        }
+    }
+
+    // proof.a || proof.b || proof.c
+    pub fn proof_len(self) -> usize {
+        2 * self.g1_len() + self.g2_len()
+    }
+}
+
+// Reads the points following an encoding byte.
+pub struct PointReader<'a> {
+    encoding: PointEncoding,
+    src: &'a [u8],
+}
+
+impl<'a> PointReader<'a> {
+    // Strip the encoding byte off `input`.
+    pub fn new(input: &'a [u8]) -> Result<Self, ProgramError> {
+        let (&encoding, src) = input
+            .split_first()
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        let encoding =
+            PointEncoding::from_u8(encoding).ok_or(ProgramError::InvalidInstructionData)?;
+        Ok(PointReader::with_encoding(encoding, src))
+    }
+
+    // Points in `encoding` without a byte of their own, such as the proofs of a batch.
+    pub fn with_encoding(encoding: PointEncoding, src: &'a [u8]) -> Self {
+        PointReader { encoding, src }
+    }
+
+    pub fn encoding(&self) -> PointEncoding {
+        self.encoding
+    }
+
+    pub fn g1(&mut self) -> Result<G1Affine, ProgramError> {
+        let mut src = self.take(self.encoding.g1_len())?;
+        match self.encoding {
+            PointEncoding::Uncompressed => read(&mut src),
+            PointEncoding::Compressed => decompress(&mut src),
+        }
+    }
+
+    pub fn g1_projective(&mut self) -> Result<G1Projective, ProgramError> {
+        let mut src = self.take(self.encoding.g1_projective_len())?;
+        match self.encoding {
+            PointEncoding::Uncompressed => read(&mut src),
+            PointEncoding::Compressed => {
+                let p: G1Affine = decompress(&mut src)?;
+                Ok(p.into_projective())
+            }
+        }
+    }
+
+    pub fn g2(&mut self) -> Result<G2Affine, ProgramError> {
+        let mut src = self.take(self.encoding.g2_len())?;
+        match self.encoding {
+            PointEncoding::Uncompressed => read(&mut src),
+            PointEncoding::Compressed => decompress(&mut src),
+        }
+    }
+
+    // What follows the points.
+    pub fn rest(self) -> &'a [u8] {
+        self.src
+    }
+
+    // Check that the points were all of the data, so a step whose data runs on fails
+    // with `InvalidDataLength` rather than ignoring the excess.
+    pub fn finish(self) -> ProgramResult {
+        if !self.src.is_empty() {
+            return Err(VerifierError::InvalidDataLength.into());
+        }
+        Ok(())
+    }
+
+    // The next `len` bytes, or `InvalidDataLength` when the data ends first.
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ProgramError> {
+        if self.src.len() < len {
+            return Err(VerifierError::InvalidDataLength.into());
+        }
+        let (point, rest) = self.src.split_at(len);
+        self.src = rest;
+        Ok(point)
+    }
+}
+
+fn read<T: FromBytes>(src: &mut &[u8]) -> Result<T, ProgramError> {
+    T::read(src).map_err(|_| VerifierError::InvalidEncoding.into())
+}
+
+fn decompress<P: SWModelParameters>(src: &mut &[u8]) -> Result<GroupAffine<P>, ProgramError> {
+    let (x, flags): (P::BaseField, SWFlags) =
+        CanonicalDeserializeWithFlags::deserialize_with_flags(&mut *src)
+            .map_err(|_| VerifierError::InvalidEncoding)?;
+    match flags.is_positive() {
+        None => Ok(GroupAffine::zero()),
+        Some(greatest) => GroupAffine::get_point_from_x(x, greatest)
+            .ok_or_else(|| VerifierError::InvalidEncoding.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ff::to_bytes;
+    use ark_serialize::CanonicalSerialize;
+    use circuit::{write_g1, write_g1_projective, write_g2};
+
+    use super::*;
+
+    #[test]
+    fn test_encodings_round_trip() {
+        let (_, proof, _) = circuit::demo_proof().unwrap();
+        let c = proof.c.into_projective();
+        for &encoding in [
+            circuit::PointEncoding::Uncompressed,
+            circuit::PointEncoding::Compressed,
+        ]
+        .iter()
+        {
+            let mut data = vec![encoding as u8];
+            data.extend(write_g1(&proof.a, encoding));
+            data.extend(write_g2(&proof.b, encoding));
+            data.extend(write_g1(&G1Affine::zero(), encoding));
+            data.extend(write_g1_projective(&c, encoding));
+            data.push(7);
+
+            let mut reader = PointReader::new(&data).unwrap();
+            let read = reader.encoding();
+            assert_eq!(read.to_u8(), encoding as u8);
+            let len = 1 + read.proof_len() + read.g1_projective_len();
+            assert_eq!(data.len(), len + 1);
+            assert_eq!(reader.g1().unwrap(), proof.a);
+            assert_eq!(reader.g2().unwrap(), proof.b);
+            assert!(reader.g1().unwrap().is_zero());
+            assert_eq!(reader.g1_projective().unwrap(), c);
+            assert_eq!(reader.rest(), &[7]);
+        }
+
+        // arkworks' own encodings
+        let compressed = circuit::PointEncoding::Compressed;
+        let mut serialized = vec![];
+        proof.b.serialize(&mut serialized).unwrap();
+        assert_eq!(serialized, write_g2(&proof.b, compressed));
+        let uncompressed = circuit::PointEncoding::Uncompressed;
+        assert_eq!(
+            to_bytes!(proof.a).unwrap(),
+            write_g1(&proof.a, uncompressed)
+        );
+
+        // short data and unknown encodings
+        let mut data = vec![ENCODING_COMPRESSED];
+        data.extend(write_g1(&proof.a, compressed));
+        assert_eq!(
+            PointReader::new(&data[..32]).unwrap().g1(),
+            Err(VerifierError::InvalidDataLength.into())
+        );
+        let reader = PointReader::new(&data).unwrap();
+        assert_eq!(
+            reader.finish(),
+            Err(VerifierError::InvalidDataLength.into())
+        );
+        let mut reader = PointReader::new(&data).unwrap();
+        assert_eq!(reader.g1().unwrap(), proof.a);
+        assert_eq!(reader.finish(), Ok(()));
+        assert!(PointReader::new(&[2]).is_err());
+        assert!(PointReader::new(&[]).is_err());
+    }
+}
@@ -0,0 +1,535 @@
+use std::slice::Iter;
+
+use ark_bn254::{Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::AffineCurve;
+use ark_ff::{to_bytes, BigInteger256, FromBytes, PrimeField};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::account_info::{next_account_info, AccountInfo};
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::hash::{hash, hashv};
+use solana_program::program_error::ProgramError;
+use solana_program::sysvar::{self, Sysvar};
+
+use crate::commitment::hash_chain;
+use crate::error::VerifierError;
+use crate::miller_loop::{ab_tag, delta_tag};
+use crate::nullifier::NULLIFIER_INPUT;
+use crate::pvk::{get_ic, NUM_PUBLIC_INPUTS};
+use crate::scratch::{read_tag, write_tag, Role, Scratch, SCRATCH_HEADER_LEN, UNBOUND_TAG};
+use crate::utils::{vk_id, FR_DATA_LEN};
+use crate::vk::OnChainVk;
+
+// Fold public inputs read from on-chain data, so the statement is bound to data the
+// client cannot forge. The folded point `IC_0 + sum(x_i * IC_i)` is stored in an
+// inputs account and consumed by gamma Miller-loop steps sent with empty input data.
+//
+// instruction data: source (1 byte) || source parameters
+// accounts: [source account, inputs account (writable)], without the source account
+//           for sources 3 and 4; the inputs account is the session's `Role::Inputs` scratch
+//           account
+//
+// The inputs are folded against the IC points of the VK account when sent with
+// `VK_ACCOUNT_FLAG`, or of the baked key otherwise.
+//
+// Supported sources, and the public inputs they yield (in order):
+//   0 Clock sysvar:       [slot, epoch, unix_timestamp as u64]
+//   1 SlotHashes sysvar:  [slot, hash] of the most recent entry; the 32-byte hash is
+//                         read little-endian and reduced modulo r
+//   2 oracle account:     parameters are the oracle account's address and its expected
+//                         owner program (32 bytes each) and a byte offset (u32,
+//                         little-endian); reads `num_public_inputs` consecutive 32-byte
+//                         little-endian values from the account data at that offset,
+//                         each reduced modulo r. The account must be owned by that
+//                         program, and the source id commits to all three parameters,
+//                         so a statement names the oracle program it trusted
+//   3 instruction data:   parameters are the raw public inputs, exactly
+//                         `num_public_inputs` 32-byte little-endian values, each
+//                         below r; the client is trusted with nothing but the values
+//   4 hash chain:         parameters are any number of raw inputs, 32-byte
+//                         little-endian values below r; yields the single input of a
+//                         circuit committing to them, their hash chain (see
+//                         `commitment::hash_chain`)
+// The key's `num_public_inputs` selects how many of these are used; a source yielding
+// fewer values is rejected, as are instruction data holding another count.
+//
+// inputs account layout, after the scratch header (see `scratch.rs`):
+//   0..96     folded G1 point (G1Projective, arkworks encoding)
+//   96..128   source id: the source account's address for sources 0 and 1,
+//             sha256(address || owner program || offset) for source 2, or for
+//             sources 3 and 4 the sha256 of the raw inputs
+//   128..160  id of the key folded against (see `utils::vk_id`)
+//   160..192  inputs hash, sha256 of the used inputs as 32-byte little-endian values
+//   192..224  nullifier, input `NULLIFIER_INPUT` as a 32-byte little-endian value, or
+//             zeros for a key without public inputs (see `nullifier.rs`)
+// The account's binding tag is sha256(vk id || source id || inputs hash): a final step
+// recording the outcome under these inputs checks that the proof's Miller loops ran on
+// this very fold (see `bound_statement`).
+pub const SOURCE_CLOCK: u8 = 0;
+pub const SOURCE_SLOT_HASHES: u8 = 1;
+pub const SOURCE_ORACLE: u8 = 2;
+pub const SOURCE_INSTRUCTION: u8 = 3;
+pub const SOURCE_HASH_CHAIN: u8 = 4;
+
+pub const G1_PROJECTIVE_DATA_LEN: usize = 96;
+pub const INPUTS_DATA_LEN: usize = G1_PROJECTIVE_DATA_LEN + 4 * 32;
+
+pub fn fold_public_inputs(
+    scratch: &Scratch,
+    accounts_iter: &mut Iter<AccountInfo>,
+    input: &[u8],
+    vk: Option<&OnChainVk>,
+) -> ProgramResult {
+    let num_public_inputs = vk.map_or(NUM_PUBLIC_INPUTS, |vk| vk.num_public_inputs);
+    let (&source, params) = input
+        .split_first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+
+    let (public_inputs, source_id) = match source {
+        SOURCE_INSTRUCTION => {
+            let inputs = instruction_inputs(params, num_public_inputs)?;
+            (inputs, hash(params).to_bytes())
+        }
+        SOURCE_HASH_CHAIN => (hash_chain_inputs(params)?, hash(params).to_bytes()),
+        SOURCE_ORACLE => {
+            let source_account = next_account_info(accounts_iter)?;
+            let inputs = oracle_inputs(source_account, params, num_public_inputs)?;
+            (inputs, hash(params).to_bytes())
+        }
+        _ => {
+            let source_account = next_account_info(accounts_iter)?;
+            let inputs = match source {
+                SOURCE_CLOCK => clock_inputs(source_account)?,
+                SOURCE_SLOT_HASHES => slot_hashes_inputs(source_account)?,
+                _ => return Err(ProgramError::InvalidInstructionData),
+            };
+            (inputs, source_account.key.to_bytes())
+        }
+    };
+    let inputs_account = scratch.next_account(accounts_iter, Role::Inputs)?;
+    if public_inputs.len() < num_public_inputs {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let public_inputs = &public_inputs[..num_public_inputs];
+    let folded = match vk {
+        Some(vk) => fold(|i| vk.ic(i), public_inputs)?,
+        None => fold(|i| Ok(get_ic(i)), public_inputs)?,
+    };
+
+    let vk_id = vk_id(vk);
+    let inputs_hash = hash(&to_bytes!(public_inputs).unwrap()).to_bytes();
+    let nullifier = public_inputs
+        .get(NULLIFIER_INPUT)
+        .map_or(vec![0; 32], |x| to_bytes!(x).unwrap());
+    {
+        let mut data = inputs_account.try_borrow_mut_data()?;
+        let dst = array_mut_ref![data, SCRATCH_HEADER_LEN, INPUTS_DATA_LEN];
+        let (dst_point, dst_source, dst_vk_id, dst_hash, dst_nullifier) =
+            mut_array_refs![dst, G1_PROJECTIVE_DATA_LEN, 32, 32, 32, 32];
+        dst_point.copy_from_slice(&to_bytes!(folded).unwrap());
+        dst_source.copy_from_slice(&source_id);
+        dst_vk_id.copy_from_slice(&vk_id);
+        dst_hash.copy_from_slice(&inputs_hash);
+        dst_nullifier.copy_from_slice(&nullifier);
+    }
+    write_tag(
+        inputs_account,
+        &hashv(&[&vk_id, &source_id, &inputs_hash]).to_bytes(),
+    )
+}
+
+// What an inputs account holds besides the folded point, see `fold_public_inputs`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Statement {
+    pub source_id: [u8; 32],
+    pub vk_id: [u8; 32],
+    pub inputs_hash: [u8; 32],
+    pub nullifier: [u8; 32],
+}
+
+fn read_statement(inputs_account: &AccountInfo) -> Result<Statement, ProgramError> {
+    let data = inputs_account.try_borrow_data()?;
+    let src = array_ref![data, SCRATCH_HEADER_LEN + G1_PROJECTIVE_DATA_LEN, 128];
+    let (source_id, vk_id, inputs_hash, nullifier) = array_refs![src, 32, 32, 32, 32];
+    Ok(Statement {
+        source_id: *source_id,
+        vk_id: *vk_id,
+        inputs_hash: *inputs_hash,
+        nullifier: *nullifier,
+    })
+}
+
+// The tag a gamma step reading the inputs account contributes (see `scratch.rs`): the
+// account's when the step's coefficients are those of the key `vk_id` the inputs were
+// folded against, which a step with inline coefficients, from no key, never has.
+pub fn folded_input_tag(
+    inputs_account: &AccountInfo,
+    vk_id: Option<[u8; 32]>,
+) -> Result<[u8; 32], ProgramError> {
+    match vk_id {
+        Some(vk_id) if read_statement(inputs_account)?.vk_id == vk_id => read_tag(inputs_account),
+        _ => Ok(UNBOUND_TAG),
+    }
+}
+
+// The statement of the inputs account, once `tag`, the tag of the hard part's last
+// accumulators, shows the proof (a, b, c) was checked against exactly these inputs under
+// the key `vk_id`: its gamma loop read them from this account, its delta loop used the
+// same key's coefficients on c, its A·B loop ran on a and b, and nothing since mixed in
+// another value.
+pub fn bound_statement(
+    inputs_account: &AccountInfo,
+    tag: &[u8; 32],
+    vk_id: &[u8; 32],
+    a: &G1Affine,
+    b: &G2Affine,
+    c: &G1Affine,
+) -> Result<Statement, ProgramError> {
+    let statement = read_statement(inputs_account)?;
+    let inputs_tag = read_tag(inputs_account)?;
+    let expected = hashv(&[&inputs_tag, &delta_tag(vk_id, c), &ab_tag(a, b)]);
+    if statement.vk_id != *vk_id || *tag != expected.to_bytes() {
+        return Err(VerifierError::UnboundInputs.into());
+    }
+    Ok(statement)
+}
+
+// The folded point stored by `fold_public_inputs`, in an inputs account the caller has
+// checked (see `Scratch::next_account`).
+pub fn read_folded_input(inputs_account: &AccountInfo) -> Result<G1Projective, ProgramError> {
+    let data = inputs_account.try_borrow_data()?;
+    let src = array_ref![data, SCRATCH_HEADER_LEN, G1_PROJECTIVE_DATA_LEN];
+    G1Projective::read(&mut src.as_ref()).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+// `IC_0 + sum(x_i * IC_i)` over the IC points `ic(0..=n)` of a verifying key.
+pub fn fold<F>(ic: F, public_inputs: &[Fr]) -> Result<G1Projective, ProgramError>
+where
+    F: Fn(usize) -> Result<G1Affine, ProgramError>,
+{
+    let mut folded = ic(0)?.into_projective();
+    for (i, x) in public_inputs.iter().enumerate() {
+        folded += ic(i + 1)?.mul(x.into_repr());
+    }
+    Ok(folded)
+}
+
+// Exactly `count` raw public inputs, 32-byte little-endian values below r.
+pub fn instruction_inputs(params: &[u8], count: usize) -> Result<Vec<Fr>, ProgramError> {
+    if params.len() != count * FR_DATA_LEN {
+        return Err(VerifierError::InvalidDataLength.into());
+    }
+    params
+        .chunks(FR_DATA_LEN)
+        .map(|bytes| {
+            let repr =
+                BigInteger256::read(bytes).map_err(|_| ProgramError::InvalidInstructionData)?;
+            Fr::from_repr(repr).ok_or(ProgramError::InvalidInstructionData)
+        })
+        .collect()
+}
+
+// The hash chain of raw inputs, 32-byte little-endian values below r.
+fn hash_chain_inputs(params: &[u8]) -> Result<Vec<Fr>, ProgramError> {
+    instruction_inputs(params, params.len() / FR_DATA_LEN)?;
+    let inputs = params
+        .chunks(FR_DATA_LEN)
+        .map(|bytes| *array_ref![bytes, 0, FR_DATA_LEN])
+        .collect::<Vec<_>>();
+    Ok(vec![hash_chain(&inputs)])
+}
+
+fn clock_inputs(account: &AccountInfo) -> Result<Vec<Fr>, ProgramError> {
+    let clock = Clock::from_account_info(account)?;
+    Ok(vec![
+        Fr::from(clock.slot),
+        Fr::from(clock.epoch),
+        Fr::from(clock.unix_timestamp as u64),
+    ])
+}
+
+fn slot_hashes_inputs(account: &AccountInfo) -> Result<Vec<Fr>, ProgramError> {
+    if *account.key != sysvar::slot_hashes::id() {
+        return Err(ProgramError::InvalidArgument);
+    }
+    // bincode layout: entry count (u64), then (slot u64, hash [u8; 32]) newest first
+    let data = account.try_borrow_data()?;
+    if data.len() < 48 || u64::from_le_bytes(*array_ref![data, 0, 8]) == 0 {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let slot = u64::from_le_bytes(*array_ref![data, 8, 8]);
+    let hash = array_ref![data, 16, 32];
+    Ok(vec![Fr::from(slot), Fr::from_le_bytes_mod_order(hash)])
+}
+
+fn oracle_inputs(
+    account: &AccountInfo,
+    params: &[u8],
+    count: usize,
+) -> Result<Vec<Fr>, ProgramError> {
+    if params.len() != 68 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let params = array_ref![params, 0, 68];
+    let (address, owner, offset) = array_refs![params, 32, 32, 4];
+    if account.key.as_ref() != address {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if account.owner.as_ref() != owner {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let offset = u32::from_le_bytes(*offset) as usize;
+    let data = account.try_borrow_data()?;
+    let end = offset + count * 32;
+    if data.len() < end {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(data[offset..end]
+        .chunks(32)
+        .map(Fr::from_le_bytes_mod_order)
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use ark_bn254::Bn254;
+    use ark_groth16::{prepare_inputs, prepare_verifying_key, VerifyingKey};
+    use ark_std::rand::{rngs::StdRng, SeedableRng};
+    use ark_std::{test_rng, UniformRand};
+    use solana_program::pubkey::Pubkey;
+
+    use super::*;
+    use crate::process_instruction;
+    use crate::test_utils::{run, scratch_data, TestAccount, SESSION_ID};
+    use crate::VK_ACCOUNT_FLAG;
+
+    // Fold against the VK account: [vk, payer, accounts..].
+    fn fold_with_vk(
+        program_id: &Pubkey,
+        vk_account: &mut TestAccount,
+        accounts: &mut [&mut TestAccount],
+        data: &[u8],
+    ) -> ProgramResult {
+        let mut data = scratch_data(data, SESSION_ID);
+        data[0] |= VK_ACCOUNT_FLAG as u8;
+        let mut payer = TestAccount::payer();
+        let mut infos = vec![vk_account.info(), payer.info()];
+        infos.extend(accounts.iter_mut().map(|account| account.info()));
+        process_instruction(program_id, &infos, &data)
+    }
+
+    fn vk_account(program_id: &Pubkey, vk: &VerifyingKey<Bn254>) -> TestAccount {
+        let mut vk_account = TestAccount::new(program_id, 0);
+        vk_account.data = circuit::to_on_chain_vk(vk).unwrap();
+        vk_account
+    }
+
+    #[test]
+    fn test_fold_oracle_inputs() {
+        let (pvk, _, _) = circuit::demo_proof().unwrap();
+        let program_id = Pubkey::new_unique();
+        let oracle_program = Pubkey::new_unique();
+        let mut vk_account = vk_account(&program_id, &pvk.vk);
+
+        let price = Fr::rand(&mut test_rng());
+        let mut oracle = TestAccount::new(&oracle_program, 40);
+        oracle.data[8..40].copy_from_slice(&to_bytes!(price).unwrap());
+
+        let mut inputs_account = TestAccount::scratch(&program_id, SESSION_ID, Role::Inputs);
+        let mut data = vec![18, 0, 0, SOURCE_ORACLE];
+        data.extend(oracle.key.as_ref());
+        data.extend(oracle_program.as_ref());
+        data.extend(8u32.to_le_bytes().iter());
+        fold_with_vk(
+            &program_id,
+            &mut vk_account,
+            &mut [&mut oracle, &mut inputs_account],
+            &data,
+        )
+        .unwrap();
+
+        let expected = prepare_inputs(&pvk, &[price]).unwrap();
+        assert_eq!(read_folded_input(&inputs_account.info()).unwrap(), expected);
+        let statement = read_statement(&inputs_account.info()).unwrap();
+        let oracle_tag = read_tag(&inputs_account.info()).unwrap();
+        assert_eq!(statement.source_id, hash(&data[4..]).to_bytes());
+        assert_eq!(statement.vk_id, circuit::on_chain_vk_id(&pvk.vk).unwrap());
+        assert_eq!(
+            statement.inputs_hash,
+            hash(&to_bytes!(price).unwrap()).to_bytes()
+        );
+        assert_eq!(
+            statement.nullifier.as_ref(),
+            to_bytes!(price).unwrap().as_slice()
+        );
+
+        // another account with the same owner and layout is not the oracle
+        let mut other = TestAccount::new(&oracle_program, 40);
+        assert_eq!(
+            fold_with_vk(
+                &program_id,
+                &mut vk_account,
+                &mut [&mut other, &mut inputs_account],
+                &data,
+            ),
+            Err(ProgramError::InvalidArgument)
+        );
+        // nor is the oracle's account under another owner than the one named
+        let mut forged = TestAccount::new(&Pubkey::new_unique(), 40);
+        forged.key = oracle.key;
+        forged.data = oracle.data.clone();
+        assert_eq!(
+            fold_with_vk(
+                &program_id,
+                &mut vk_account,
+                &mut [&mut forged, &mut inputs_account],
+                &data,
+            ),
+            Err(ProgramError::IncorrectProgramId)
+        );
+        // and naming the forger's program instead yields a statement of another source
+        let mut forged_data = data.clone();
+        forged_data[36..68].copy_from_slice(forged.owner.as_ref());
+        fold_with_vk(
+            &program_id,
+            &mut vk_account,
+            &mut [&mut forged, &mut inputs_account],
+            &forged_data,
+        )
+        .unwrap();
+        let forged_statement = read_statement(&inputs_account.info()).unwrap();
+        assert_eq!(forged_statement.inputs_hash, statement.inputs_hash);
+        assert_ne!(forged_statement.source_id, statement.source_id);
+        assert_ne!(read_tag(&inputs_account.info()).unwrap(), oracle_tag);
+    }
+
+    // Against the baked key, which has one public input.
+    #[test]
+    fn test_fold_instruction_inputs() {
+        let (pvk, _, public_inputs) = circuit::demo_proof().unwrap();
+        let program_id = Pubkey::new_unique();
+        let mut inputs_account = TestAccount::scratch(&program_id, SESSION_ID, Role::Inputs);
+
+        let raw_inputs = to_bytes!(public_inputs).unwrap();
+        let mut data = vec![18, 0, 0, SOURCE_INSTRUCTION];
+        data.extend(raw_inputs.iter());
+        run(&program_id, &mut [&mut inputs_account], &data);
+
+        let expected = prepare_inputs(&pvk, &public_inputs).unwrap();
+        assert_eq!(read_folded_input(&inputs_account.info()).unwrap(), expected);
+        let inputs_hash = hash(&raw_inputs).to_bytes();
+        let statement = Statement {
+            source_id: inputs_hash,
+            vk_id: vk_id(None),
+            inputs_hash,
+            nullifier: *array_ref![raw_inputs, 0, 32],
+        };
+        assert_eq!(read_statement(&inputs_account.info()).unwrap(), statement);
+        assert_eq!(
+            read_tag(&inputs_account.info()).unwrap(),
+            hashv(&[&statement.vk_id, &inputs_hash, &inputs_hash]).to_bytes()
+        );
+
+        // values must be below r and there must be exactly one per public input
+        let mut payer = TestAccount::payer();
+        let infos = [payer.info(), inputs_account.info()];
+        let mut unreduced = vec![18, 0, 0, SOURCE_INSTRUCTION];
+        unreduced.extend([0xff; 32].iter());
+        let unreduced = scratch_data(&unreduced, SESSION_ID);
+        assert!(process_instruction(&program_id, &infos, &unreduced).is_err());
+        let short = scratch_data(&data[..data.len() - 1], SESSION_ID);
+        assert!(process_instruction(&program_id, &infos, &short).is_err());
+    }
+
+    // The baked key's one input commits to any number of raw inputs.
+    #[test]
+    fn test_fold_hash_chain() {
+        let (pvk, _, _) = circuit::demo_proof().unwrap();
+        let program_id = Pubkey::new_unique();
+        let mut inputs_account = TestAccount::scratch(&program_id, SESSION_ID, Role::Inputs);
+
+        let rng = &mut test_rng();
+        let values = (0..3).map(|_| Fr::rand(rng)).collect::<Vec<_>>();
+        let raw_inputs = to_bytes!(values).unwrap();
+        let mut data = vec![18, 0, 0, SOURCE_HASH_CHAIN];
+        data.extend(raw_inputs.iter());
+        run(&program_id, &mut [&mut inputs_account], &data);
+
+        let commitment = circuit::hash_chain(&values);
+        let expected = prepare_inputs(&pvk, &[commitment]).unwrap();
+        assert_eq!(read_folded_input(&inputs_account.info()).unwrap(), expected);
+        let statement = read_statement(&inputs_account.info()).unwrap();
+        assert_eq!(statement.source_id, hash(&raw_inputs).to_bytes());
+        assert_eq!(
+            statement.inputs_hash,
+            hash(&to_bytes!(commitment).unwrap()).to_bytes()
+        );
+
+        // the raw inputs are whole values below r
+        let mut payer = TestAccount::payer();
+        let infos = [payer.info(), inputs_account.info()];
+        let short = scratch_data(&data[..data.len() - 1], SESSION_ID);
+        assert_eq!(
+            process_instruction(&program_id, &infos, &short),
+            Err(VerifierError::InvalidDataLength.into())
+        );
+        let mut unreduced = vec![18, 0, 0, SOURCE_HASH_CHAIN];
+        unreduced.extend([0xff; 32].iter());
+        let unreduced = scratch_data(&unreduced, SESSION_ID);
+        assert_eq!(
+            process_instruction(&program_id, &infos, &unreduced),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+
+    // The count of public inputs is the VK account's.
+    #[test]
+    fn test_fold_several_inputs() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let constants = circuit::demo_constants();
+        let params = circuit::setup_with_inputs(&constants, 3, &mut rng).unwrap();
+        let (_, public_inputs) = circuit::prove(&params, &constants, &mut rng).unwrap();
+        let program_id = Pubkey::new_unique();
+        let mut vk_account = vk_account(&program_id, &params.vk);
+        let mut inputs_account = TestAccount::scratch(&program_id, SESSION_ID, Role::Inputs);
+
+        let mut data = vec![18, 0, 0, SOURCE_INSTRUCTION];
+        data.extend(to_bytes!(public_inputs).unwrap());
+        fold_with_vk(
+            &program_id,
+            &mut vk_account,
+            &mut [&mut inputs_account],
+            &data,
+        )
+        .unwrap();
+        let pvk = prepare_verifying_key(&params.vk);
+        let expected = prepare_inputs(&pvk, &public_inputs).unwrap();
+        assert_eq!(read_folded_input(&inputs_account.info()).unwrap(), expected);
+        let nullifier = to_bytes!(public_inputs[NULLIFIER_INPUT]).unwrap();
+        assert_eq!(
+            read_statement(&inputs_account.info())
+                .unwrap()
+                .nullifier
+                .as_ref(),
+            nullifier.as_slice()
+        );
+
+        // one input short, or the baked key's count
+        assert_eq!(
+            fold_with_vk(
+                &program_id,
+                &mut vk_account,
+                &mut [&mut inputs_account],
+                &data[..data.len() - FR_DATA_LEN],
+            ),
+            Err(VerifierError::InvalidDataLength.into())
+        );
+        let mut payer = TestAccount::payer();
+        let infos = [payer.info(), inputs_account.info()];
+        assert_eq!(
+            process_instruction(&program_id, &infos, &scratch_data(&data, SESSION_ID)),
+            Err(VerifierError::InvalidDataLength.into())
+        );
+    }
+}
@@ -1,9 +1,12 @@
-use ark_bn254::{Fq12Parameters, Fq2Parameters};
+// Generated by `client gen-pvk` from the verifying key of the demo circuit; regenerate it
+// for another circuit rather than editing the constants by hand.
+use ark_bn254::{Fq12Parameters, Fq2Parameters, G1Affine};
 use ark_ff::{
     BigInteger256, CubicExtField, Fp12ParamsWrapper, Fp256, Fp2ParamsWrapper, QuadExtField,
 };
+use arrayref::array_ref;
 
-const GAMMA: [u64; 2184] = [
+static GAMMA: [u64; 2184] = [
     16776782478048074777,
     5153905037500849513,
     17984879393431728588,
@@ -2190,7 +2193,7 @@ const GAMMA: [u64; 2184] = [
     1174119134671340192,
 ];
 
-const DELTA: [u64; 2184] = [
+static DELTA: [u64; 2184] = [
     9618203633639557139,
     3730882676121572274,
     12069486696372507092,
@@ -4379,9 +4382,7 @@ const DELTA: [u64; 2184] = [
 
 fn get_gamma_data<const LEN: usize>(offset: usize) -> [u64; LEN] {
     let mut arr: [u64; LEN] = [0; LEN];
-    for i in 0..LEN {
-        arr[i] = GAMMA[offset + i];
-    }
+    arr.copy_from_slice(&GAMMA[offset..offset + LEN]);
     arr
 }
 
@@ -4399,9 +4400,7 @@ pub fn get_gamma_qef(
 
 fn get_delta_data<const LEN: usize>(offset: usize) -> [u64; LEN] {
     let mut arr: [u64; LEN] = [0; LEN];
-    for i in 0..LEN {
-        arr[i] = DELTA[offset + i];
-    }
+    arr.copy_from_slice(&DELTA[offset..offset + LEN]);
     arr
 }
 
@@ -4510,6 +4509,88 @@ pub fn get_alpha_g1_beta_g2() -> QuadExtField<Fp12ParamsWrapper<Fq12Parameters>>
     )
 }
 
+// alpha_g1, beta_g2, gamma_g2 and delta_g2 as the alt_bn128 syscalls read them, see
+// `alt_bn128.rs`
+#[cfg_attr(not(feature = "alt-bn128"), allow(dead_code))]
+pub const ALPHA_G1: [u8; 64] = [
+    0x23, 0xc1, 0x50, 0xf1, 0x9d, 0x70, 0xba, 0x4d, 0x18, 0x46, 0x2d, 0x31, 0x12, 0x56, 0xf3, 0x4c,
+    0x6a, 0xf7, 0x7e, 0x34, 0x9d, 0xb5, 0x23, 0x87, 0x8d, 0xa5, 0x66, 0x72, 0x2d, 0xd2, 0xfd, 0x52,
+    0x1f, 0x4e, 0x14, 0xc1, 0x26, 0xbc, 0x51, 0xf4, 0x81, 0x97, 0x5d, 0xa8, 0x8c, 0xb0, 0xc7, 0x5b,
+    0x72, 0x98, 0xb1, 0x32, 0xbf, 0x24, 0xbe, 0x2c, 0x76, 0x42, 0x6d, 0xee, 0xa6, 0x83, 0x76, 0x3a,
+];
+#[cfg_attr(not(feature = "alt-bn128"), allow(dead_code))]
+pub const BETA_G2: [u8; 128] = [
+    0x00, 0xea, 0xac, 0x5c, 0xc3, 0xb5, 0xac, 0xcf, 0x54, 0x51, 0xb6, 0xbc, 0xa6, 0x21, 0xec, 0x57,
+    0x6a, 0xa1, 0x1c, 0xfc, 0x97, 0xac, 0xd8, 0xb8, 0x3a, 0x68, 0xf2, 0x58, 0x96, 0xc0, 0xb3, 0x7f,
+    0x0f, 0x71, 0x14, 0x63, 0x79, 0x61, 0x27, 0x01, 0xf8, 0xeb, 0x5d, 0xd6, 0x86, 0x5b, 0x9c, 0xea,
+    0xb9, 0x2c, 0x09, 0xfa, 0x9d, 0xcc, 0x3b, 0x27, 0xbe, 0x6e, 0x28, 0x3b, 0xe9, 0xc0, 0x2c, 0x40,
+    0x2d, 0x16, 0x1c, 0x1e, 0xe5, 0x91, 0xa7, 0x20, 0x25, 0x14, 0x46, 0x61, 0x94, 0x93, 0x56, 0xc1,
+    0x2d, 0xaa, 0x5c, 0x73, 0x43, 0x05, 0x13, 0xaf, 0x6f, 0x8c, 0xc1, 0x22, 0xc8, 0xa2, 0xe4, 0x50,
+    0x27, 0xef, 0x87, 0xc1, 0xf2, 0xf0, 0x9c, 0xde, 0xe0, 0x4a, 0xb9, 0xae, 0xba, 0xca, 0xd2, 0xf0,
+    0x2d, 0x59, 0x25, 0xd0, 0x57, 0xe1, 0x30, 0x9c, 0xe8, 0xe6, 0x3e, 0x56, 0xa1, 0x3a, 0xb2, 0xc2,
+];
+#[cfg_attr(not(feature = "alt-bn128"), allow(dead_code))]
+pub const GAMMA_G2: [u8; 128] = [
+    0x0c, 0xef, 0xff, 0x8a, 0xbb, 0xbe, 0x9a, 0xf6, 0x9f, 0x8f, 0xfc, 0x53, 0x0b, 0xf0, 0xb4, 0xc4,
+    0xb0, 0x56, 0x86, 0x19, 0x81, 0x35, 0x03, 0x4b, 0x06, 0xd4, 0x06, 0x24, 0xdd, 0xff, 0x05, 0x13,
+    0x18, 0xcd, 0x09, 0x7b, 0x2c, 0x07, 0xd9, 0xca, 0xe4, 0xf8, 0xf7, 0x7a, 0xdc, 0x45, 0xa6, 0xff,
+    0x44, 0x0e, 0x31, 0x8a, 0x78, 0xa9, 0xe0, 0x08, 0x13, 0xfb, 0x78, 0x7a, 0xe1, 0xc5, 0x0d, 0x99,
+    0x06, 0x96, 0xcf, 0x28, 0x16, 0xfe, 0x04, 0xe5, 0xf8, 0xdc, 0x99, 0x1f, 0x6d, 0x21, 0xb5, 0xef,
+    0xbf, 0x25, 0x2b, 0xad, 0x00, 0xf8, 0x14, 0x91, 0x61, 0xf0, 0x1d, 0x74, 0x32, 0xb6, 0xf1, 0xf8,
+    0x03, 0x0e, 0x9c, 0x7f, 0x81, 0x5c, 0xc8, 0xdd, 0x75, 0x03, 0xfa, 0x7a, 0xa3, 0xaa, 0xd6, 0x62,
+    0x1b, 0x80, 0xcc, 0x0e, 0x1c, 0x48, 0x2f, 0xc6, 0xed, 0x37, 0xe8, 0xbe, 0x3c, 0xab, 0x29, 0x66,
+];
+#[cfg_attr(not(feature = "alt-bn128"), allow(dead_code))]
+pub const DELTA_G2: [u8; 128] = [
+    0x29, 0x2d, 0xe3, 0xa2, 0x02, 0xdf, 0x6f, 0x73, 0x88, 0x77, 0x2d, 0xbd, 0x58, 0x10, 0x4a, 0x3b,
+    0x00, 0x9e, 0xf8, 0x07, 0x0e, 0x86, 0xf6, 0x0f, 0x9b, 0xa9, 0xa3, 0x86, 0x51, 0x5e, 0x85, 0x41,
+    0x14, 0x16, 0xaf, 0x96, 0x92, 0xf7, 0xb9, 0xca, 0xd0, 0xde, 0x2f, 0xd2, 0x23, 0x52, 0x9d, 0xdb,
+    0xb3, 0xc2, 0x99, 0x39, 0x2a, 0xa5, 0x6d, 0xed, 0x06, 0x86, 0x9b, 0xc8, 0x4b, 0x78, 0x5d, 0x3d,
+    0x2c, 0xa4, 0x2c, 0xc6, 0x48, 0x48, 0x6e, 0xce, 0x89, 0x4f, 0x16, 0x1e, 0x5d, 0x9d, 0x2b, 0x46,
+    0xfa, 0x36, 0x69, 0xb3, 0xa0, 0x74, 0x18, 0x90, 0x38, 0x9d, 0xc6, 0xe6, 0x06, 0x82, 0x1c, 0x68,
+    0x14, 0xd3, 0x79, 0xba, 0x30, 0x6e, 0x3c, 0x05, 0x7d, 0xc8, 0xec, 0xdb, 0x0f, 0x89, 0x9a, 0x75,
+    0xfd, 0xad, 0x0e, 0x83, 0x31, 0x49, 0xa5, 0xbc, 0xa7, 0x32, 0xdb, 0x9b, 0x00, 0xaf, 0xee, 0xc3,
+];
+
+// sha256 of the key in the on-chain VK layout, the id results, nullifiers and
+// receipts name it by, see `utils::vk_id`
+pub const VK_ID: [u8; 32] = [
+    0x0b, 0x44, 0x97, 0x46, 0xb4, 0x9e, 0x29, 0x22, 0x88, 0xcd, 0x59, 0x37, 0xf0, 0xec, 0x06, 0x22,
+    0xa3, 0x46, 0x91, 0xf3, 0xd5, 0x91, 0xc1, 0x5d, 0xcd, 0x65, 0x69, 0x51, 0xeb, 0x35, 0xe6, 0xf2,
+];
+
+// gamma_abc_g1 (IC) points, constant term first: x then y of each, four limbs per
+// coordinate
+pub const NUM_PUBLIC_INPUTS: usize = 1;
+
+static IC: [u64; 16] = [
+    4167760644736523912,
+    3114870143877803802,
+    9842120507202713092,
+    1846841240114748140,
+    15969776001957939534,
+    11292160962820876286,
+    16446421663119224266,
+    1458872708411191081,
+    15010071743712804485,
+    13712813272531845193,
+    3984604048363802188,
+    850673671452308909,
+    7664714725043757339,
+    1171712995496591633,
+    445780378298551578,
+    565848270368208982,
+];
+
+pub fn get_ic(i: usize) -> G1Affine {
+    let offset = i * 8;
+    G1Affine::new(
+        Fp256::new(BigInteger256::new(*array_ref![IC, offset, 4])),
+        Fp256::new(BigInteger256::new(*array_ref![IC, offset + 4, 4])),
+        false,
+    )
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -4523,4 +4604,43 @@ mod test {
         let c = get_gamma_qef(2, 1);
         println!("{:#?}", c);
     }
+
+    // The baked constants must come from the circuit's setup: regenerating the demo VK
+    // has to reproduce them, or every proof fails the final check.
+    #[test]
+    fn test_baked_vk_matches_circuit() {
+        let (pvk, _, _) = circuit::demo_proof().unwrap();
+        assert_eq!(
+            get_alpha_g1_beta_g2(),
+            pvk.alpha_g1_beta_g2,
+            "alpha_g1_beta_g2 in pvk.rs does not match the circuit's setup"
+        );
+        for (j, coeffs) in pvk.gamma_g2_neg_pc.ell_coeffs.iter().enumerate() {
+            assert_eq!(get_gamma_qef(j, 0), coeffs.0, "gamma coeff {}", j);
+            assert_eq!(get_gamma_qef(j, 1), coeffs.1, "gamma coeff {}", j);
+            assert_eq!(get_gamma_qef(j, 2), coeffs.2, "gamma coeff {}", j);
+        }
+        for (j, coeffs) in pvk.delta_g2_neg_pc.ell_coeffs.iter().enumerate() {
+            assert_eq!(get_delta_qef(j, 0), coeffs.0, "delta coeff {}", j);
+            assert_eq!(get_delta_qef(j, 1), coeffs.1, "delta coeff {}", j);
+            assert_eq!(get_delta_qef(j, 2), coeffs.2, "delta coeff {}", j);
+        }
+        assert_eq!(pvk.vk.gamma_abc_g1.len(), NUM_PUBLIC_INPUTS + 1);
+        for (i, point) in pvk.vk.gamma_abc_g1.iter().enumerate() {
+            assert_eq!(get_ic(i), *point, "IC point {}", i);
+        }
+    }
+
+    // Everything above the tests is `gen-pvk` output for the demo key.
+    #[test]
+    fn test_generated_from_demo_vk() {
+        let (pvk, _, _) = circuit::demo_proof().unwrap();
+        let generated = circuit::pvk_source(&pvk.vk).unwrap();
+        let source = include_str!("pvk.rs");
+        assert!(
+            source.starts_with(&generated),
+            "pvk.rs differs from gen-pvk output"
+        );
+        assert!(source[generated.len()..].starts_with("\n#[cfg(test)]"));
+    }
 }
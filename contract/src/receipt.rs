@@ -0,0 +1,416 @@
+use std::slice::Iter;
+
+use ark_bn254::{Fr, G1Affine, G2Affine};
+use ark_ff::to_bytes;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::account_info::{next_account_info, AccountInfo};
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::hash::hashv;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::sysvar::Sysvar;
+
+use crate::header::{check_account_header, AccountKind, ACCOUNT_HEADER_LEN};
+use crate::utils::create_program_account;
+
+// Receipts of verified statements, so downstream programs can later check that a proof
+// was verified without re-running anything. The single-instruction verification, sent
+// with `RECEIPT_FLAG` in the `i` byte, takes the payer (a signer), the receipt account
+// and the system program, and once the proof checked out creates the receipt of its
+// statement at the payer's expense. The chunked verification's final step does the same
+// with `FLAG_RECEIPT` (see `hard_part_y16`), so both name a statement by the same
+// receipt. A statement verified before keeps its receipt.
+//
+// The statement is identified by its hash, sha256 of the proof's uncompressed arkworks
+// encoding (a || b || c, so compressed and uncompressed submissions agree), the public
+// inputs (32 bytes each, little-endian) and the id of the verifying key, the hash of the
+// whole key (see `utils::vk_id`). The receipt's address is derived from
+// `RECEIPT_SEED || statement hash`.
+//
+// receipt account layout:
+//   0..8    account header of kind `Receipt` (see `header.rs`)
+//   8..9    result (1 = proof valid; only valid proofs get a receipt)
+//   9..10   verifier version, `RECEIPT_VERSION`, bumped when what a receipt attests
+//           changes; version 2 names keys by the hash of the whole key instead of
+//           alpha_g1_beta_g2 alone
+//   10..18  slot of the verification (u64, little-endian)
+//   18..50  statement hash
+pub const RECEIPT_FLAG: u8 = 1;
+pub const RECEIPT_SEED: &[u8] = b"receipt";
+pub const RECEIPT_VERSION: u8 = 2;
+pub const RECEIPT_DATA_LEN: usize = ACCOUNT_HEADER_LEN + 42;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Receipt {
+    pub valid: bool,
+    pub version: u8,
+    pub slot: u64,
+    pub statement_hash: [u8; 32],
+}
+
+impl Receipt {
+    pub fn pack(&self) -> [u8; RECEIPT_DATA_LEN] {
+        let mut data = [0; RECEIPT_DATA_LEN];
+        let dst = array_mut_ref![data, 0, RECEIPT_DATA_LEN];
+        let (header, result, version, slot, statement_hash) =
+            mut_array_refs![dst, ACCOUNT_HEADER_LEN, 1, 1, 8, 32];
+        *header = AccountKind::Receipt.header();
+        result[0] = self.valid as u8;
+        version[0] = self.version;
+        *slot = self.slot.to_le_bytes();
+        *statement_hash = self.statement_hash;
+        data
+    }
+
+    // The receipt in account data, `None` if it holds none.
+    pub fn unpack(data: &[u8]) -> Option<Receipt> {
+        if data.len() < RECEIPT_DATA_LEN
+            || check_account_header(data, AccountKind::Receipt).is_err()
+        {
+            return None;
+        }
+        let data = array_ref![data, 0, RECEIPT_DATA_LEN];
+        let (_, result, version, slot, statement_hash) =
+            array_refs![data, ACCOUNT_HEADER_LEN, 1, 1, 8, 32];
+        Some(Receipt {
+            valid: result[0] == 1,
+            version: version[0],
+            slot: u64::from_le_bytes(*slot),
+            statement_hash: *statement_hash,
+        })
+    }
+}
+
+pub fn statement_hash(
+    a: &G1Affine,
+    b: &G2Affine,
+    c: &G1Affine,
+    public_inputs: &[Fr],
+    vk_id: &[u8; 32],
+) -> [u8; 32] {
+    let proof = to_bytes!(a, b, c).unwrap();
+    let inputs = public_inputs
+        .iter()
+        .flat_map(|x| to_bytes!(x).unwrap())
+        .collect::<Vec<_>>();
+    hashv(&[&proof, &inputs, vk_id]).to_bytes()
+}
+
+pub fn receipt_address(program_id: &Pubkey, statement_hash: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RECEIPT_SEED, statement_hash], program_id)
+}
+
+// The receipt in `account` if it is the verifier `program_id`'s receipt of the
+// statement `statement_hash`, for downstream programs checking a statement was verified.
+pub fn read_receipt(
+    program_id: &Pubkey,
+    account: &AccountInfo,
+    statement_hash: &[u8; 32],
+) -> Option<Receipt> {
+    if account.owner != program_id || *account.key != receipt_address(program_id, statement_hash).0
+    {
+        return None;
+    }
+    let receipt = Receipt::unpack(&account.try_borrow_data().ok()?)?;
+    Some(receipt).filter(|receipt| receipt.statement_hash == *statement_hash)
+}
+
+// Create the receipt of the verified statement `statement_hash` from the next accounts:
+// the payer, the receipt account and the system program.
+pub fn write_receipt<'a>(
+    program_id: &Pubkey,
+    accounts_iter: &mut Iter<AccountInfo<'a>>,
+    statement_hash: &[u8; 32],
+) -> ProgramResult {
+    let payer = next_account_info(accounts_iter)?;
+    let account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    create_receipt(program_id, payer, account, system_program, statement_hash)
+}
+
+// Create the receipt of the verified statement `statement_hash` in `account`, funded by
+// `payer`, which the caller has checked signs.
+pub fn create_receipt<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    statement_hash: &[u8; 32],
+) -> ProgramResult {
+    let (address, bump) = receipt_address(program_id, statement_hash);
+    if *account.key != address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if account.owner == program_id {
+        return Ok(());
+    }
+
+    let seeds: &[&[u8]] = &[RECEIPT_SEED, statement_hash, &[bump]];
+    create_program_account(
+        program_id,
+        payer,
+        account,
+        system_program,
+        seeds,
+        RECEIPT_DATA_LEN,
+    )?;
+    let receipt = Receipt {
+        valid: true,
+        version: RECEIPT_VERSION,
+        slot: Clock::get()?.slot,
+        statement_hash: *statement_hash,
+    };
+    let mut data = account.try_borrow_mut_data()?;
+    if data.len() < RECEIPT_DATA_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    data[..RECEIPT_DATA_LEN].copy_from_slice(&receipt.pack());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use ark_bn254::Fr;
+    use circuit::PointEncoding;
+    use solana_program::rent::Rent;
+    use solana_program::system_instruction;
+    use solana_program::system_program;
+
+    use super::*;
+    use crate::error::VerifierError;
+    use crate::test_utils::{install_stubs, invoked, TestAccount, SLOT};
+    use crate::utils::vk_id;
+    use crate::vk::parse_on_chain_vk;
+    use crate::VK_ACCOUNT_FLAG;
+
+    fn verify(
+        program_id: &Pubkey,
+        flags: u8,
+        input: &[u8],
+        receipt: &mut TestAccount,
+    ) -> ProgramResult {
+        let mut payer = TestAccount::payer();
+        let mut system = TestAccount::new(&Pubkey::default(), 0);
+        system.key = system_program::id();
+        let infos = [payer.info(), receipt.info(), system.info()];
+        let mut data = vec![24, flags, 0];
+        data.extend(input.iter());
+        crate::process_instruction(program_id, &infos, &data)
+    }
+
+    #[test]
+    fn test_write_receipt() {
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let (_, proof, public_inputs) = circuit::demo_proof().unwrap();
+        let input =
+            circuit::complete_submission(&proof, &public_inputs, PointEncoding::Uncompressed);
+        let statement = statement_hash(&proof.a, &proof.b, &proof.c, &public_inputs, &vk_id(None));
+        let (address, _) = receipt_address(&program_id, &statement);
+        let mut receipt = TestAccount::new(&system_program::id(), RECEIPT_DATA_LEN);
+        receipt.key = address;
+
+        verify(&program_id, RECEIPT_FLAG, &input, &mut receipt).unwrap();
+        let payer = TestAccount::payer().key;
+        let lamports = Rent::default().minimum_balance(RECEIPT_DATA_LEN);
+        let len = RECEIPT_DATA_LEN as u64;
+        let create =
+            system_instruction::create_account(&payer, &address, lamports, len, &program_id);
+        assert_eq!(invoked().last(), Some(&create));
+        let expected = Receipt {
+            valid: true,
+            version: RECEIPT_VERSION,
+            slot: SLOT,
+            statement_hash: statement,
+        };
+        assert_eq!(Receipt::unpack(&receipt.data), Some(expected));
+
+        // downstream programs find it under the verifier's program id
+        receipt.owner = program_id;
+        assert_eq!(
+            read_receipt(&program_id, &receipt.info(), &statement),
+            Some(expected)
+        );
+        assert_eq!(read_receipt(&program_id, &receipt.info(), &[0; 32]), None);
+        assert_eq!(
+            read_receipt(&Pubkey::new_unique(), &receipt.info(), &statement),
+            None
+        );
+
+        // the compressed submission is the same statement, verified before
+        let compressed =
+            circuit::complete_submission(&proof, &public_inputs, PointEncoding::Compressed);
+        let calls = invoked().len();
+        verify(&program_id, RECEIPT_FLAG, &compressed, &mut receipt).unwrap();
+        assert_eq!(invoked().len(), calls);
+
+        // an invalid proof gets no receipt, and the receipt must be the statement's
+        let wrong =
+            circuit::complete_submission(&proof, &[Fr::from(1u64)], PointEncoding::Uncompressed);
+        assert_eq!(
+            verify(&program_id, RECEIPT_FLAG, &wrong, &mut receipt),
+            Err(VerifierError::InvalidProof.into())
+        );
+        receipt.key = Pubkey::new_unique();
+        assert_eq!(
+            verify(&program_id, RECEIPT_FLAG, &input, &mut receipt),
+            Err(ProgramError::InvalidSeeds)
+        );
+        assert_eq!(
+            verify(&program_id, 2, &input, &mut receipt),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+
+    // A key made up from the baked key's alpha and beta with gamma = delta accepts
+    // (alpha, beta, -prepared input) for any inputs. The forged proof's receipt is under
+    // the made-up key's id, never the baked key's.
+    #[test]
+    fn test_spoofed_vk_gets_no_receipt_under_victim_id() {
+        use ark_ec::ProjectiveCurve;
+        use ark_groth16::{prepare_inputs, prepare_verifying_key, Proof};
+
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let (pvk, _, public_inputs) = circuit::demo_proof().unwrap();
+        let mut spoofed = pvk.vk;
+        spoofed.gamma_g2 = spoofed.delta_g2;
+        let prepared = prepare_inputs(&prepare_verifying_key(&spoofed), &public_inputs).unwrap();
+        let forged = Proof {
+            a: spoofed.alpha_g1,
+            b: spoofed.beta_g2,
+            c: (-prepared).into_affine(),
+        };
+        let input =
+            circuit::complete_submission(&forged, &public_inputs, PointEncoding::Uncompressed);
+        let statement = |vk_id: &[u8; 32]| {
+            statement_hash(&forged.a, &forged.b, &forged.c, &public_inputs, vk_id)
+        };
+
+        let mut vk_account = TestAccount::new(&program_id, 0);
+        vk_account.data = circuit::to_on_chain_vk(&spoofed).unwrap();
+        let spoofed_id = vk_id(Some(&parse_on_chain_vk(&vk_account.data).unwrap()));
+        assert_ne!(spoofed_id, vk_id(None));
+        let mut verify_spoofed = |receipt: &mut TestAccount| {
+            let mut payer = TestAccount::payer();
+            let mut system = TestAccount::new(&Pubkey::default(), 0);
+            system.key = system_program::id();
+            let infos = [
+                vk_account.info(),
+                payer.info(),
+                receipt.info(),
+                system.info(),
+            ];
+            let mut data = vec![24 | VK_ACCOUNT_FLAG as u8, RECEIPT_FLAG, 0];
+            data.extend(input.iter());
+            crate::process_instruction(&program_id, &infos, &data)
+        };
+
+        // the forged proof checks out against the made-up key, under its own id only
+        let mut receipt = TestAccount::new(&system_program::id(), RECEIPT_DATA_LEN);
+        receipt.key = receipt_address(&program_id, &statement(&vk_id(None))).0;
+        assert_eq!(
+            verify_spoofed(&mut receipt),
+            Err(ProgramError::InvalidSeeds)
+        );
+        receipt.key = receipt_address(&program_id, &statement(&spoofed_id)).0;
+        verify_spoofed(&mut receipt).unwrap();
+        assert_eq!(
+            Receipt::unpack(&receipt.data).unwrap().statement_hash,
+            statement(&spoofed_id)
+        );
+
+        // and fails against the baked key
+        receipt.key = receipt_address(&program_id, &statement(&vk_id(None))).0;
+        assert_eq!(
+            verify(&program_id, RECEIPT_FLAG, &input, &mut receipt),
+            Err(VerifierError::InvalidProof.into())
+        );
+    }
+
+    // The chunked verification's final step, with `FLAG_RECEIPT`, creates the receipt
+    // the single-instruction verification would, for exactly the proof and inputs its
+    // Miller loops ran on: a step naming another C or other inputs gets none.
+    #[test]
+    fn test_chunked_receipt() {
+        use ark_ff::to_bytes;
+        use circuit::PointEncoding::Compressed;
+
+        use crate::point::ENCODING_UNCOMPRESSED;
+        use crate::schedule::{
+            verification_steps, with_folded_inputs, with_scratch, NUM_ACCOUNTS, PAYER,
+        };
+        use crate::scratch::Role;
+        use crate::test_utils::{run_step, session_accounts, SESSION_ID};
+        use crate::FLAG_RECEIPT;
+
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let (pvk, proof, public_inputs) = circuit::demo_proof().unwrap();
+        let (proof_c, _, proof_ab) =
+            circuit::submission(&pvk, &proof, &public_inputs, Compressed).unwrap();
+        let raw_inputs = to_bytes!(public_inputs).unwrap();
+        let statement = statement_hash(&proof.a, &proof.b, &proof.c, &public_inputs, &vk_id(None));
+        let (address, _) = receipt_address(&program_id, &statement);
+        let submission = |c: &G1Affine, public_inputs: &[u8]| {
+            let points = to_bytes!(proof.a, proof.b, c).unwrap();
+            [&[ENCODING_UNCOMPRESSED][..], &points, public_inputs].concat()
+        };
+        let verify = |submission: &[u8]| {
+            let mut accounts = session_accounts(&program_id);
+            accounts.push(TestAccount::scratch(&program_id, SESSION_ID, Role::Inputs));
+            let mut receipt = TestAccount::new(&system_program::id(), RECEIPT_DATA_LEN);
+            receipt.key = address;
+            let mut system = TestAccount::new(&Pubkey::default(), 0);
+            system.key = system_program::id();
+            accounts.extend(vec![receipt, system]);
+
+            let mut steps = verification_steps(&proof_c, &[], &proof_ab);
+            with_folded_inputs(
+                &mut steps,
+                NUM_ACCOUNTS,
+                &raw_inputs,
+                FLAG_RECEIPT,
+                submission,
+            );
+            let last = steps.len() - 1;
+            steps[last]
+                .0
+                .extend([NUM_ACCOUNTS + 1, NUM_ACCOUNTS + 2].iter());
+            with_scratch(&mut steps, PAYER, SESSION_ID);
+            for step in steps[..last].iter() {
+                run_step(&program_id, &mut accounts, step).unwrap();
+            }
+            let result = run_step(&program_id, &mut accounts, &steps[last]);
+            (result, accounts.swap_remove(NUM_ACCOUNTS + 1))
+        };
+
+        let (result, receipt) = verify(&submission(&proof.c, &raw_inputs));
+        result.unwrap();
+        let payer = TestAccount::payer().key;
+        let lamports = Rent::default().minimum_balance(RECEIPT_DATA_LEN);
+        let len = RECEIPT_DATA_LEN as u64;
+        let create =
+            system_instruction::create_account(&payer, &address, lamports, len, &program_id);
+        assert_eq!(invoked().last(), Some(&create));
+        let expected = Receipt {
+            valid: true,
+            version: RECEIPT_VERSION,
+            slot: SLOT,
+            statement_hash: statement,
+        };
+        assert_eq!(Receipt::unpack(&receipt.data), Some(expected));
+
+        let other_c = submission(&proof.a, &raw_inputs);
+        let other_inputs = submission(&proof.c, &to_bytes!(Fr::from(1u64)).unwrap());
+        for forged in [other_c, other_inputs].iter() {
+            let (result, receipt) = verify(forged);
+            assert_eq!(result, Err(VerifierError::UnboundInputs.into()));
+            assert_eq!(Receipt::unpack(&receipt.data), None);
+        }
+    }
+}
@@ -0,0 +1,589 @@
+use std::slice::Iter;
+
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use solana_program::account_info::{next_account_info, AccountInfo};
+use solana_program::bpf_loader_upgradeable;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use crate::error::VerifierError;
+use crate::header::{check_account_header, AccountKind, ACCOUNT_HEADER_LEN};
+use crate::miller_loop::INLINE_COEFFS_FLAG;
+use crate::utils::create_program_account;
+use crate::vk::{read_vk_account, VK_ACCOUNT_FLAG};
+
+// Registry of verifying keys, so one deployment serves several circuits of an
+// application (e.g. its deposit, withdraw and transfer circuits) under ids of their own.
+// An administrator registers each key, uploaded to a VK account beforehand (see
+// `vk.rs`), under a numeric id; steps then name the key by its id.
+//
+// The registry account, derived from `REGISTRY_SEED`, holds the administrator, the
+// authority every other operation is gated by. `REGISTRY_INIT` creates it with the key
+// in its data as administrator, or else its signer, who must be the program's upgrade
+// authority as recorded in its ProgramData account, so nobody else can claim the
+// registry of a fresh deployment; a program deployed immutable has no registry.
+// `REGISTRY_SET_ADMIN` hands it over to another key, e.g. a multisig. Every `REGISTRY_REGISTER` creates the entry of a new id, derived from
+// `VK_ENTRY_SEED || id` (u64, little-endian), pointing at the key's VK account. An id
+// cannot be registered twice. `REGISTRY_UPDATE` points an entry at another key, e.g.
+// after a circuit upgrade, until the entry is frozen: one registered with
+// `ENTRY_IMMUTABLE` or frozen by `REGISTRY_FREEZE` keeps its key for good, whoever
+// administers the registry later, so an integrator checking the flag knows its key
+// cannot be swapped under it.
+//
+// instruction data: t = 30, i = operation, j = 0, then
+//   REGISTRY_INIT       (empty) or administrator (32 bytes)
+//     accounts: [upgrade authority (signer, pays), registry account, system program,
+//                program data account]
+//   REGISTRY_REGISTER   id (u64, little-endian) || flags (1 byte, optional)
+//     accounts: [administrator (signer, pays), registry account, entry account,
+//                vk account, system program]
+//   REGISTRY_UPDATE     id
+//     accounts: [administrator (signer), registry account, entry account, vk account]
+//   REGISTRY_FREEZE     id
+//     accounts: [administrator (signer), registry account, entry account]
+//   REGISTRY_SET_ADMIN  new administrator (32 bytes)
+//     accounts: [administrator (signer), registry account]
+// Updating a frozen entry fails with `VkImmutable`; freezing one again does nothing.
+//
+// Steps that accept a VK account take a registered key with both `VK_ACCOUNT_FLAG` and
+// `INLINE_COEFFS_FLAG` set in `t`, a combination no step accepts otherwise: the entry
+// and the VK account come where the VK account would, and the id (u64, little-endian)
+// follows the session id, if any, at the end of the data, ahead of the session suffix.
+// Checking the entry's address costs a `create_program_address` per step.
+//
+// Both start with an account header (see `header.rs`), of kind `Registry` and `VkEntry`.
+//
+// registry account layout:
+//   0..8    account header
+//   8..40   administrator
+//
+// entry account layout:
+//   0..8    account header
+//   8..16   id (u64, little-endian)
+//   16..48  VK account
+//   48..49  bump seed of the entry's address
+//   49..50  flags, `ENTRY_IMMUTABLE`
+pub const REGISTRY_SEED: &[u8] = b"vk-registry";
+pub const VK_ENTRY_SEED: &[u8] = b"vk";
+pub const REGISTRY_DATA_LEN: usize = ACCOUNT_HEADER_LEN + 32;
+pub const VK_ENTRY_DATA_LEN: usize = ACCOUNT_HEADER_LEN + 42;
+pub const VK_ID_LEN: usize = 8;
+const PROGRAM_DATA_METADATA_LEN: usize = 45;
+pub const VK_REGISTRY_FLAGS: usize = VK_ACCOUNT_FLAG | INLINE_COEFFS_FLAG;
+
+pub const REGISTRY_INIT: u8 = 0;
+pub const REGISTRY_REGISTER: u8 = 1;
+pub const REGISTRY_UPDATE: u8 = 2;
+pub const REGISTRY_FREEZE: u8 = 3;
+pub const REGISTRY_SET_ADMIN: u8 = 4;
+
+pub const ENTRY_IMMUTABLE: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VkEntry {
+    pub id: u64,
+    pub vk_account: Pubkey,
+    pub bump: u8,
+    pub flags: u8,
+}
+
+impl VkEntry {
+    pub fn pack(&self) -> [u8; VK_ENTRY_DATA_LEN] {
+        let mut data = [0; VK_ENTRY_DATA_LEN];
+        let dst = array_mut_ref![data, 0, VK_ENTRY_DATA_LEN];
+        let (header, id, vk_account, bump, flags) =
+            mut_array_refs![dst, ACCOUNT_HEADER_LEN, 8, 32, 1, 1];
+        *header = AccountKind::VkEntry.header();
+        *id = self.id.to_le_bytes();
+        vk_account.copy_from_slice(self.vk_account.as_ref());
+        bump[0] = self.bump;
+        flags[0] = self.flags;
+        data
+    }
+
+    // The entry in account data, `None` if it holds none.
+    pub fn unpack(data: &[u8]) -> Option<VkEntry> {
+        if data.len() < VK_ENTRY_DATA_LEN
+            || check_account_header(data, AccountKind::VkEntry).is_err()
+        {
+            return None;
+        }
+        let data = array_ref![data, 0, VK_ENTRY_DATA_LEN];
+        let (_, id, vk_account, bump, flags) = array_refs![data, ACCOUNT_HEADER_LEN, 8, 32, 1, 1];
+        Some(VkEntry {
+            id: u64::from_le_bytes(*id),
+            vk_account: Pubkey::new_from_array(*vk_account),
+            bump: bump[0],
+            flags: flags[0],
+        })
+    }
+
+    // Whether the entry keeps its key for good.
+    pub fn is_immutable(&self) -> bool {
+        self.flags & ENTRY_IMMUTABLE != 0
+    }
+}
+
+pub fn registry_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REGISTRY_SEED], program_id)
+}
+
+pub fn vk_entry_address(program_id: &Pubkey, id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[VK_ENTRY_SEED, &id.to_le_bytes()], program_id)
+}
+
+pub fn process_registry<'a>(
+    program_id: &Pubkey,
+    accounts_iter: &mut Iter<AccountInfo<'a>>,
+    operation: u8,
+    input: &[u8],
+) -> ProgramResult {
+    let admin = next_account_info(accounts_iter)?;
+    let registry = next_account_info(accounts_iter)?;
+    if !admin.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let (address, bump) = registry_address(program_id);
+    if *registry.key != address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if operation != REGISTRY_INIT {
+        check_admin(program_id, registry, admin)?;
+    }
+
+    match (operation, input.len()) {
+        (REGISTRY_INIT, 0) | (REGISTRY_INIT, 32) => {
+            let system_program = next_account_info(accounts_iter)?;
+            let program_data = next_account_info(accounts_iter)?;
+            check_upgrade_authority(program_id, program_data, admin)?;
+            if registry.owner == program_id {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+            let seeds: &[&[u8]] = &[REGISTRY_SEED, &[bump]];
+            create_program_account(
+                program_id,
+                admin,
+                registry,
+                system_program,
+                seeds,
+                REGISTRY_DATA_LEN,
+            )?;
+            let admin = if input.is_empty() {
+                admin.key.as_ref()
+            } else {
+                input
+            };
+            write_registry(registry, admin)
+        }
+        (REGISTRY_REGISTER, VK_ID_LEN) | (REGISTRY_REGISTER, 9) => {
+            let entry = next_account_info(accounts_iter)?;
+            let vk_account = next_account_info(accounts_iter)?;
+            let system_program = next_account_info(accounts_iter)?;
+            let flags = input.get(VK_ID_LEN).copied().unwrap_or(0);
+            if flags & !ENTRY_IMMUTABLE != 0 {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            check_vk_account(program_id, vk_account)?;
+
+            let id = u64::from_le_bytes(*array_ref![input, 0, VK_ID_LEN]);
+            let (address, bump) = vk_entry_address(program_id, id);
+            if *entry.key != address {
+                return Err(ProgramError::InvalidSeeds);
+            }
+            if entry.owner == program_id {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+            let seeds: &[&[u8]] = &[VK_ENTRY_SEED, &id.to_le_bytes(), &[bump]];
+            create_program_account(
+                program_id,
+                admin,
+                entry,
+                system_program,
+                seeds,
+                VK_ENTRY_DATA_LEN,
+            )?;
+            let vk_entry = VkEntry {
+                id,
+                vk_account: *vk_account.key,
+                bump,
+                flags,
+            };
+            write_entry(entry, &vk_entry)
+        }
+        (REGISTRY_UPDATE, VK_ID_LEN) => {
+            let entry = next_account_info(accounts_iter)?;
+            let vk_account = next_account_info(accounts_iter)?;
+            let id = u64::from_le_bytes(*array_ref![input, 0, VK_ID_LEN]);
+            let mut vk_entry = read_entry(program_id, entry, id)?;
+            if vk_entry.is_immutable() {
+                return Err(VerifierError::VkImmutable.into());
+            }
+            check_vk_account(program_id, vk_account)?;
+            vk_entry.vk_account = *vk_account.key;
+            write_entry(entry, &vk_entry)
+        }
+        (REGISTRY_FREEZE, VK_ID_LEN) => {
+            let entry = next_account_info(accounts_iter)?;
+            let id = u64::from_le_bytes(*array_ref![input, 0, VK_ID_LEN]);
+            let mut vk_entry = read_entry(program_id, entry, id)?;
+            vk_entry.flags |= ENTRY_IMMUTABLE;
+            write_entry(entry, &vk_entry)
+        }
+        (REGISTRY_SET_ADMIN, 32) => write_registry(registry, input),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+fn check_admin(program_id: &Pubkey, registry: &AccountInfo, admin: &AccountInfo) -> ProgramResult {
+    if registry.owner != program_id {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    let data = registry.try_borrow_data()?;
+    check_account_header(&data, AccountKind::Registry)?;
+    if data.len() < REGISTRY_DATA_LEN
+        || data[ACCOUNT_HEADER_LEN..REGISTRY_DATA_LEN] != admin.key.as_ref()[..]
+    {
+        return Err(VerifierError::NotRegistryAdmin.into());
+    }
+    Ok(())
+}
+
+// Whether `authority` is the program's upgrade authority, read from the ProgramData
+// account of the upgradeable loader, which `program_data` must be.
+fn check_upgrade_authority(
+    program_id: &Pubkey,
+    program_data: &AccountInfo,
+    authority: &AccountInfo,
+) -> ProgramResult {
+    if *program_data.key != program_data_address(program_id)
+        || *program_data.owner != bpf_loader_upgradeable::id()
+    {
+        return Err(ProgramError::InvalidArgument);
+    }
+    // bincode layout: variant (u32, 3 for ProgramData), slot (u64), then the upgrade
+    // authority as an Option<Pubkey>, a tag byte and the key if present
+    let data = program_data.try_borrow_data()?;
+    if data.len() < PROGRAM_DATA_METADATA_LEN || data[..4] != 3u32.to_le_bytes() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if data[12] != 1 || data[13..PROGRAM_DATA_METADATA_LEN] != authority.key.as_ref()[..] {
+        return Err(VerifierError::NotRegistryAdmin.into());
+    }
+    Ok(())
+}
+
+pub fn program_data_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+fn check_vk_account(program_id: &Pubkey, vk_account: &AccountInfo) -> ProgramResult {
+    if vk_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    read_vk_account(&vk_account.try_borrow_data()?)?;
+    Ok(())
+}
+
+fn write_registry(registry: &AccountInfo, admin: &[u8]) -> ProgramResult {
+    let mut data = registry.try_borrow_mut_data()?;
+    if data.len() < REGISTRY_DATA_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    data[..ACCOUNT_HEADER_LEN].copy_from_slice(&AccountKind::Registry.header());
+    data[ACCOUNT_HEADER_LEN..REGISTRY_DATA_LEN].copy_from_slice(admin);
+    Ok(())
+}
+
+// The entry of `id` in `entry`, checked to be the program's entry of that id.
+fn read_entry(program_id: &Pubkey, entry: &AccountInfo, id: u64) -> Result<VkEntry, ProgramError> {
+    if entry.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let data = entry.try_borrow_data()?;
+    check_account_header(&data, AccountKind::VkEntry)?;
+    let vk_entry = VkEntry::unpack(&data).ok_or(ProgramError::InvalidAccountData)?;
+    let seeds: &[&[u8]] = &[VK_ENTRY_SEED, &id.to_le_bytes(), &[vk_entry.bump]];
+    let address = Pubkey::create_program_address(seeds, program_id)
+        .map_err(|_| ProgramError::InvalidSeeds)?;
+    if *entry.key != address || vk_entry.id != id {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(vk_entry)
+}
+
+fn write_entry(entry: &AccountInfo, vk_entry: &VkEntry) -> ProgramResult {
+    let mut data = entry.try_borrow_mut_data()?;
+    if data.len() < VK_ENTRY_DATA_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    data[..VK_ENTRY_DATA_LEN].copy_from_slice(&vk_entry.pack());
+    Ok(())
+}
+
+// The VK account registered under the id at the end of `input`, taken from the next
+// accounts, the entry and the VK account, and `input` without the id.
+pub fn registered_vk<'a, 'b, 'c>(
+    program_id: &Pubkey,
+    accounts_iter: &mut Iter<'a, AccountInfo<'b>>,
+    input: &'c [u8],
+) -> Result<(&'a AccountInfo<'b>, &'c [u8]), ProgramError> {
+    if input.len() < VK_ID_LEN {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (input, id) = input.split_at(input.len() - VK_ID_LEN);
+    let id = u64::from_le_bytes(*array_ref![id, 0, VK_ID_LEN]);
+
+    let entry = next_account_info(accounts_iter)?;
+    let vk_account = next_account_info(accounts_iter)?;
+    let vk_entry = read_entry(program_id, entry, id)?;
+    if *vk_account.key != vk_entry.vk_account || vk_account.owner != program_id {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok((vk_account, input))
+}
+
+#[cfg(test)]
+mod test {
+    use solana_program::rent::Rent;
+    use solana_program::system_instruction;
+    use solana_program::system_program;
+
+    use super::*;
+    use crate::test_utils::{install_stubs, invoked, TestAccount};
+
+    fn registry_step(
+        program_id: &Pubkey,
+        accounts: &mut [&mut TestAccount],
+        operation: u8,
+        input: &[u8],
+    ) -> ProgramResult {
+        let mut system = TestAccount::new(&Pubkey::default(), 0);
+        system.key = system_program::id();
+        let mut infos: Vec<_> = accounts.iter_mut().map(|account| account.info()).collect();
+        infos.push(system.info());
+        let mut data = vec![30, operation, 0];
+        data.extend(input.iter());
+        crate::process_instruction(program_id, &infos, &data)
+    }
+
+    // The ProgramData account of `program_id`, upgradeable by `authority` if any.
+    fn program_data(program_id: &Pubkey, authority: Option<&Pubkey>) -> TestAccount {
+        let mut program_data =
+            TestAccount::new(&bpf_loader_upgradeable::id(), PROGRAM_DATA_METADATA_LEN);
+        program_data.key = program_data_address(program_id);
+        program_data.data[..4].copy_from_slice(&3u32.to_le_bytes());
+        if let Some(authority) = authority {
+            program_data.data[12] = 1;
+            program_data.data[13..].copy_from_slice(authority.as_ref());
+        }
+        program_data
+    }
+
+    #[test]
+    fn test_register_vk() {
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let mut admin = TestAccount::payer();
+        let mut registry = TestAccount::new(&system_program::id(), REGISTRY_DATA_LEN);
+        registry.key = registry_address(&program_id).0;
+        let init = |admin: &mut TestAccount,
+                    registry: &mut TestAccount,
+                    program_data: &mut TestAccount| {
+            let mut system = TestAccount::new(&Pubkey::default(), 0);
+            system.key = system_program::id();
+            let infos = [
+                admin.info(),
+                registry.info(),
+                system.info(),
+                program_data.info(),
+            ];
+            crate::process_instruction(&program_id, &infos, &[30, REGISTRY_INIT, 0])
+        };
+
+        // only the upgrade authority of an upgradeable program creates the registry
+        let mut other = TestAccount::new(&Pubkey::default(), 0);
+        other.is_signer = true;
+        let mut upgradeable = program_data(&program_id, Some(&admin.key));
+        assert_eq!(
+            init(&mut other, &mut registry, &mut upgradeable),
+            Err(VerifierError::NotRegistryAdmin.into())
+        );
+        let mut immutable = program_data(&program_id, None);
+        assert_eq!(
+            init(&mut admin, &mut registry, &mut immutable),
+            Err(VerifierError::NotRegistryAdmin.into())
+        );
+        let mut elsewhere = program_data(&Pubkey::new_unique(), Some(&admin.key));
+        assert_eq!(
+            init(&mut admin, &mut registry, &mut elsewhere),
+            Err(ProgramError::InvalidArgument)
+        );
+        init(&mut admin, &mut registry, &mut upgradeable).unwrap();
+        let lamports = Rent::default().minimum_balance(REGISTRY_DATA_LEN);
+        let len = REGISTRY_DATA_LEN as u64;
+        let (payer, address) = (admin.key, registry.key);
+        let create =
+            system_instruction::create_account(&payer, &address, lamports, len, &program_id);
+        assert_eq!(invoked().last(), Some(&create));
+        assert_eq!(
+            registry.data[..ACCOUNT_HEADER_LEN],
+            AccountKind::Registry.header()
+        );
+        assert_eq!(registry.data[ACCOUNT_HEADER_LEN..], payer.as_ref()[..]);
+        registry.owner = program_id;
+        assert_eq!(
+            init(&mut admin, &mut registry, &mut upgradeable),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+
+        let (pvk, _, _) = circuit::demo_proof().unwrap();
+        let vk_data = circuit::to_on_chain_vk(&pvk.vk).unwrap();
+        let mut vk_account = TestAccount::new(&program_id, vk_data.len());
+        vk_account.data.copy_from_slice(&vk_data);
+        let mut entry = TestAccount::new(&system_program::id(), VK_ENTRY_DATA_LEN);
+        entry.key = vk_entry_address(&program_id, 7).0;
+        let id = 7u64.to_le_bytes();
+        let mut register =
+            |admin: &mut TestAccount, entry: &mut TestAccount, vk: &mut TestAccount| {
+                let accounts = &mut [admin, &mut registry, entry, vk];
+                registry_step(&program_id, accounts, REGISTRY_REGISTER, &id)
+            };
+        register(&mut admin, &mut entry, &mut vk_account).unwrap();
+        let expected = VkEntry {
+            id: 7,
+            vk_account: vk_account.key,
+            bump: vk_entry_address(&program_id, 7).1,
+            flags: 0,
+        };
+        assert_eq!(VkEntry::unpack(&entry.data), Some(expected));
+
+        // an id is registered once, and only by the administrator
+        entry.owner = program_id;
+        assert_eq!(
+            register(&mut admin, &mut entry, &mut vk_account),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+        entry.owner = system_program::id();
+        let mut other = TestAccount::new(&Pubkey::default(), 0);
+        other.is_signer = true;
+        assert_eq!(
+            register(&mut other, &mut entry, &mut vk_account),
+            Err(VerifierError::NotRegistryAdmin.into())
+        );
+        admin.is_signer = false;
+        assert_eq!(
+            register(&mut admin, &mut entry, &mut vk_account),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        admin.is_signer = true;
+        vk_account.data.truncate(8);
+        assert_eq!(
+            register(&mut admin, &mut entry, &mut vk_account),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // steps look the key up by its id
+        entry.owner = program_id;
+        let mut input = vec![5];
+        input.extend(7u64.to_le_bytes().iter());
+        let infos = [entry.info(), vk_account.info()];
+        let (vk, rest) = registered_vk(&program_id, &mut infos.iter(), &input).unwrap();
+        assert_eq!((*vk.key, rest), (expected.vk_account, &[5][..]));
+        input[1] = 8;
+        assert_eq!(
+            registered_vk(&program_id, &mut infos.iter(), &input).err(),
+            Some(ProgramError::InvalidSeeds)
+        );
+    }
+
+    #[test]
+    fn test_update_and_freeze() {
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let mut admin = TestAccount::payer();
+        let mut registry = TestAccount::new(&program_id, REGISTRY_DATA_LEN);
+        registry.key = registry_address(&program_id).0;
+        registry.data[..ACCOUNT_HEADER_LEN].copy_from_slice(&AccountKind::Registry.header());
+        registry.data[ACCOUNT_HEADER_LEN..].copy_from_slice(admin.key.as_ref());
+        let (pvk, _, _) = circuit::demo_proof().unwrap();
+        let vk_data = circuit::to_on_chain_vk(&pvk.vk).unwrap();
+        let vk_account = || {
+            let mut vk_account = TestAccount::new(&program_id, vk_data.len());
+            vk_account.data.copy_from_slice(&vk_data);
+            vk_account
+        };
+        let (mut old_vk, mut new_vk) = (vk_account(), vk_account());
+        let (address, bump) = vk_entry_address(&program_id, 7);
+        let mut entry = TestAccount::new(&program_id, VK_ENTRY_DATA_LEN);
+        entry.key = address;
+        let vk_entry = VkEntry {
+            id: 7,
+            vk_account: old_vk.key,
+            bump,
+            flags: 0,
+        };
+        entry.data.copy_from_slice(&vk_entry.pack());
+        let id = 7u64.to_le_bytes();
+
+        // the administrator points a mutable entry at another key
+        let accounts = &mut [&mut admin, &mut registry, &mut entry, &mut new_vk];
+        registry_step(&program_id, accounts, REGISTRY_UPDATE, &id).unwrap();
+        assert_eq!(VkEntry::unpack(&entry.data).unwrap().vk_account, new_vk.key);
+
+        // until it is frozen
+        let accounts = &mut [&mut admin, &mut registry, &mut entry];
+        registry_step(&program_id, accounts, REGISTRY_FREEZE, &id).unwrap();
+        assert!(VkEntry::unpack(&entry.data).unwrap().is_immutable());
+        let accounts = &mut [&mut admin, &mut registry, &mut entry, &mut old_vk];
+        assert_eq!(
+            registry_step(&program_id, accounts, REGISTRY_UPDATE, &id),
+            Err(VerifierError::VkImmutable.into())
+        );
+
+        // handing the registry over leaves the old administrator out
+        let mut other = TestAccount::new(&Pubkey::default(), 0);
+        other.key = Pubkey::new_unique();
+        other.is_signer = true;
+        let new_admin = other.key.to_bytes();
+        let accounts = &mut [&mut admin, &mut registry];
+        registry_step(&program_id, accounts, REGISTRY_SET_ADMIN, &new_admin).unwrap();
+        assert_eq!(registry.data[ACCOUNT_HEADER_LEN..], new_admin);
+        let accounts = &mut [&mut admin, &mut registry, &mut entry];
+        assert_eq!(
+            registry_step(&program_id, accounts, REGISTRY_FREEZE, &id),
+            Err(VerifierError::NotRegistryAdmin.into())
+        );
+        let accounts = &mut [&mut other, &mut registry, &mut entry];
+        registry_step(&program_id, accounts, REGISTRY_FREEZE, &id).unwrap();
+
+        // an entry can be immutable from the start, and takes no other flags
+        let mut fresh = TestAccount::new(&system_program::id(), VK_ENTRY_DATA_LEN);
+        fresh.key = vk_entry_address(&program_id, 8).0;
+        let mut register = |flags: u8, fresh: &mut TestAccount, other: &mut TestAccount| {
+            let input = [&8u64.to_le_bytes()[..], &[flags]].concat();
+            let accounts = &mut [other, &mut registry, fresh, &mut old_vk];
+            registry_step(&program_id, accounts, REGISTRY_REGISTER, &input)
+        };
+        assert_eq!(
+            register(2, &mut fresh, &mut other),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        register(ENTRY_IMMUTABLE, &mut fresh, &mut other).unwrap();
+        assert!(VkEntry::unpack(&fresh.data).unwrap().is_immutable());
+
+        // neither the registry nor an entry is read without its header
+        entry.data[..ACCOUNT_HEADER_LEN].copy_from_slice(&AccountKind::Receipt.header());
+        let accounts = &mut [&mut other, &mut registry, &mut entry];
+        assert_eq!(
+            registry_step(&program_id, accounts, REGISTRY_FREEZE, &id),
+            Err(VerifierError::InvalidAccountHeader.into())
+        );
+        registry.data[5] = AccountKind::VkEntry.to_u8();
+        let accounts = &mut [&mut other, &mut registry];
+        assert_eq!(
+            registry_step(&program_id, accounts, REGISTRY_SET_ADMIN, &new_admin),
+            Err(VerifierError::InvalidAccountHeader.into())
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use arrayref::{array_mut_ref, mut_array_refs};
+use solana_program::account_info::AccountInfo;
+use solana_program::clock::Clock;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::sysvar::Sysvar;
+
+use crate::error::VerifierError;
+use crate::scratch::{Role, Scratch, SCRATCH_HEADER_LEN};
+
+// Outcome of the latest verification driven through a set of scratch accounts, written
+// by the final step when sent with `FLAG_WRITE_RESULT` (see `hard_part_y16`). Unlike
+// the result log it holds a single record, overwritten by every verification, and
+// records the pairing result itself so a failed check can be inspected afterwards.
+//
+// The result account is the session's scratch account of role `Result`, derived from
+// the payer and session id like the others and created with them. The step checks its
+// address, owner and header before writing, and fails with `InvalidResultAccount` on
+// any other account.
+//
+// result account layout:
+//   0..40   scratch header of role `Result`
+//   40..41  result (1 = proof valid)
+//   41..73  sha256 of y16, the final exponentiation result (arkworks encoding)
+//   73..81  slot of the final step (u64, little-endian)
+pub const RESULT_DATA_LEN: usize = 41;
+
+pub fn write_result(
+    scratch: &Scratch,
+    result_account: &AccountInfo,
+    y16_hash: &[u8; 32],
+    valid: bool,
+) -> ProgramResult {
+    scratch
+        .check_account(result_account, Role::Result)
+        .map_err(|_| VerifierError::InvalidResultAccount)?;
+    let slot = Clock::get()?.slot;
+    let mut data = result_account.try_borrow_mut_data()?;
+    let dst = array_mut_ref![data, SCRATCH_HEADER_LEN, RESULT_DATA_LEN];
+    let (dst_result, dst_hash, dst_slot) = mut_array_refs![dst, 1, 32, 8];
+    dst_result[0] = valid as u8;
+    dst_hash.copy_from_slice(y16_hash);
+    *dst_slot = slot.to_le_bytes();
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use ark_bn254::Fq12;
+    use ark_ff::{to_bytes, One};
+    use solana_program::hash::hash;
+    use solana_program::pubkey::Pubkey;
+
+    use super::*;
+    use crate::pvk::get_alpha_g1_beta_g2;
+    use crate::scratch::Role;
+    use crate::test_utils::{
+        final_step_accounts, install_stubs, invalid_y15, scratch_data, TestAccount, SESSION_ID,
+        SLOT,
+    };
+    use crate::FLAG_WRITE_RESULT;
+
+    fn final_step(program_id: &Pubkey, result: &mut TestAccount, y15: Fq12) -> ProgramResult {
+        let mut payer = TestAccount::payer();
+        let (mut y14_account, mut y15_account) = final_step_accounts(program_id, SESSION_ID, y15);
+        let infos = vec![
+            payer.info(),
+            y14_account.info(),
+            y15_account.info(),
+            result.info(),
+        ];
+        let data = scratch_data(&[16, FLAG_WRITE_RESULT, 0], SESSION_ID);
+        crate::process_instruction(program_id, &infos, &data)
+    }
+
+    #[test]
+    fn test_write_result() {
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let mut result = TestAccount::scratch(&program_id, SESSION_ID, Role::Result);
+
+        final_step(&program_id, &mut result, Fq12::one()).unwrap();
+        let y16 = get_alpha_g1_beta_g2();
+        let data = &result.data[SCRATCH_HEADER_LEN..];
+        assert_eq!(data[0], 1);
+        assert_eq!(&data[1..33], hash(&to_bytes!(y16).unwrap()).as_ref());
+        assert_eq!(data[33..41], SLOT.to_le_bytes());
+
+        // an invalid proof is recorded rather than failing the transaction
+        final_step(&program_id, &mut result, invalid_y15()).unwrap();
+        let y16 = y16 * y16;
+        let data = &result.data[SCRATCH_HEADER_LEN..];
+        assert_eq!(data[0], 0);
+        assert_eq!(&data[1..33], hash(&to_bytes!(y16).unwrap()).as_ref());
+    }
+
+    // The step writes only to its session's result account.
+    #[test]
+    fn test_result_account_checked() {
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let mut accounts = vec![
+            // another session's
+            TestAccount::scratch(&program_id, SESSION_ID + 1, Role::Result),
+            // a short one
+            TestAccount::scratch(&program_id, SESSION_ID, Role::Result),
+            // one the program does not own
+            TestAccount::scratch(&program_id, SESSION_ID, Role::Result),
+            // another role's
+            TestAccount::scratch(&program_id, SESSION_ID, Role::Result),
+        ];
+        accounts[1]
+            .data
+            .truncate(SCRATCH_HEADER_LEN + RESULT_DATA_LEN - 1);
+        accounts[2].owner = Pubkey::new_unique();
+        accounts[3].data[..SCRATCH_HEADER_LEN].copy_from_slice(&Role::Session.header());
+        for mut account in accounts {
+            let before = account.data.clone();
+            assert_eq!(
+                final_step(&program_id, &mut account, Fq12::one()),
+                Err(VerifierError::InvalidResultAccount.into())
+            );
+            assert_eq!(account.data, before);
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use std::slice::Iter;
+
+use arrayref::{array_mut_ref, array_ref, mut_array_refs};
+use solana_program::account_info::{next_account_info, AccountInfo};
+use solana_program::clock::Clock;
+use solana_program::entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::sysvar::Sysvar;
+
+use crate::header::{check_program_account, write_account_header, AccountKind, ACCOUNT_HEADER_LEN};
+use crate::scratch::Scratch;
+use crate::utils::create_program_account;
+
+// Append-only log of verification outcomes, written by the final step when it is sent
+// with `FLAG_RECORD_RESULT` (see `hard_part_y16`). A record names the key and inputs of
+// the session's inputs account, which the step only takes for a proof checked against
+// exactly those inputs (see `public_inputs::bound_statement`).
+//
+// Each payer has one log, derived from `RESULT_LOG_SEED || payer` and created with
+// `CreateScratch` (t = 22, j = `CREATE_RESULT_LOG`, the capacity in records in `i`) at
+// the payer's expense. The final step appends to the log of the payer it runs for, and
+// checks the log's address, owner and header first, so it never appends to an account
+// of another layout.
+//
+// log account layout:
+//   0..8    account header of kind `ResultLog` (see `header.rs`)
+//   8..16   number of records (u64, little-endian)
+//   16..    records, RESULT_RECORD_LEN bytes each; record i starts at 16 + i * 73
+//
+// record layout:
+//   0..32   vk id, sha256 of the whole verifying key (see `utils::vk_id`)
+//   32..64  public-input hash, sha256 of the inputs folded on-chain (see
+//           `fold_public_inputs`)
+//   64..65  result (1 = proof valid)
+//   65..73  slot of the final step (u64, little-endian)
+pub const RESULT_LOG_SEED: &[u8] = b"result-log";
+pub const LOG_HEADER_LEN: usize = ACCOUNT_HEADER_LEN + 8;
+pub const RESULT_RECORD_LEN: usize = 73;
+// Operation of `CreateScratch` (t = 22) creating the payer's log, in `j`
+pub const CREATE_RESULT_LOG: u8 = 2;
+
+pub fn record_offset(i: usize) -> usize {
+    LOG_HEADER_LEN + i * RESULT_RECORD_LEN
+}
+
+pub fn result_log_address(program_id: &Pubkey, payer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RESULT_LOG_SEED, payer.as_ref()], program_id)
+}
+
+// The most records a log can hold, as the program can allocate at most
+// `MAX_PERMITTED_DATA_INCREASE` bytes at once.
+pub fn max_capacity() -> usize {
+    (MAX_PERMITTED_DATA_INCREASE - LOG_HEADER_LEN) / RESULT_RECORD_LEN
+}
+
+// Create the payer's empty log with room for `capacity` records.
+//
+// accounts: [log account (writable), system program]
+pub fn create_result_log<'a>(
+    scratch: &Scratch<'_, 'a>,
+    accounts_iter: &mut Iter<AccountInfo<'a>>,
+    capacity: u8,
+) -> ProgramResult {
+    let account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let program_id = scratch.program_id();
+    let payer = scratch.payer();
+    if capacity == 0 || capacity as usize > max_capacity() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (address, bump) = result_log_address(program_id, payer.key);
+    if *account.key != address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if account.owner == program_id {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    let seeds: &[&[u8]] = &[RESULT_LOG_SEED, payer.key.as_ref(), &[bump]];
+    let len = record_offset(capacity as usize);
+    create_program_account(program_id, payer, account, system_program, seeds, len)?;
+    write_account_header(account, AccountKind::ResultLog)
+}
+
+pub fn append_result(
+    scratch: &Scratch,
+    log_account: &AccountInfo,
+    vk_id: &[u8; 32],
+    input_hash: &[u8; 32],
+    result: bool,
+) -> ProgramResult {
+    let program_id = scratch.program_id();
+    let (address, _) = result_log_address(program_id, scratch.payer().key);
+    check_program_account(program_id, log_account, &address, AccountKind::ResultLog)?;
+    let slot = Clock::get()?.slot;
+    let mut data = log_account.try_borrow_mut_data()?;
+    if data.len() < LOG_HEADER_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    let count = u64::from_le_bytes(*array_ref![data, ACCOUNT_HEADER_LEN, 8]) as usize;
+    let offset = record_offset(count);
+    if data.len() < offset + RESULT_RECORD_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+
+    let record = array_mut_ref![data, offset, RESULT_RECORD_LEN];
+    let (dst_vk_id, dst_input_hash, dst_result, dst_slot) = mut_array_refs![record, 32, 32, 1, 8];
+    dst_vk_id.copy_from_slice(vk_id);
+    dst_input_hash.copy_from_slice(input_hash);
+    dst_result[0] = result as u8;
+    *dst_slot = slot.to_le_bytes();
+
+    let dst_count = array_mut_ref![data, ACCOUNT_HEADER_LEN, 8];
+    *dst_count = (count as u64 + 1).to_le_bytes();
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use ark_bn254::Fq12;
+    use ark_ff::One;
+    use solana_program::rent::Rent;
+    use solana_program::system_instruction;
+    use solana_program::system_program;
+
+    use super::*;
+    use crate::error::VerifierError;
+    use crate::scratch::{write_tag, UNBOUND_TAG};
+    use crate::test_utils::{
+        attesting_step, bound_inputs_account, final_step_accounts, install_stubs, invalid_y15,
+        invoked, scratch_data, TestAccount, SESSION_ID, SLOT,
+    };
+    use crate::utils::vk_id;
+    use crate::FLAG_RECORD_RESULT;
+
+    fn count(log: &TestAccount) -> u64 {
+        u64::from_le_bytes(*array_ref![log.data, ACCOUNT_HEADER_LEN, 8])
+    }
+
+    fn final_step(
+        program_id: &Pubkey,
+        log: &mut TestAccount,
+        y15: Fq12,
+        input_hash: u8,
+    ) -> ProgramResult {
+        let mut payer = TestAccount::payer();
+        let (mut y14_account, mut y15_account) = final_step_accounts(program_id, SESSION_ID, y15);
+        let mut inputs_account = bound_inputs_account(
+            program_id,
+            SESSION_ID,
+            [input_hash; 32],
+            &mut y14_account,
+            &mut y15_account,
+        );
+        let infos = vec![
+            payer.info(),
+            y14_account.info(),
+            y15_account.info(),
+            inputs_account.info(),
+            log.info(),
+        ];
+        let data = attesting_step(FLAG_RECORD_RESULT, &[]);
+        crate::process_instruction(program_id, &infos, &scratch_data(&data, SESSION_ID))
+    }
+
+    #[test]
+    fn test_append_results() {
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let mut log = TestAccount::result_log(&program_id, 2);
+
+        final_step(&program_id, &mut log, Fq12::one(), 1).unwrap();
+        final_step(&program_id, &mut log, invalid_y15(), 2).unwrap();
+        assert_eq!(count(&log), 2);
+
+        let vk_id = vk_id(None);
+        for (i, valid) in [true, false].iter().enumerate() {
+            let record = array_ref![log.data, record_offset(i), RESULT_RECORD_LEN];
+            assert_eq!(&record[0..32], &vk_id);
+            assert_eq!(&record[32..64], &[i as u8 + 1; 32]);
+            assert_eq!(record[64], *valid as u8);
+            assert_eq!(u64::from_le_bytes(*array_ref![record, 65, 8]), SLOT);
+        }
+
+        // the log is full
+        assert_eq!(
+            final_step(&program_id, &mut log, Fq12::one(), 3),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+    }
+
+    // A proof checked against other inputs than the inputs account holds, or against the
+    // client's prepared input, records nothing.
+    #[test]
+    fn test_record_needs_bound_inputs() {
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let mut log = TestAccount::result_log(&program_id, 1);
+        let (mut y14, mut y15) = final_step_accounts(&program_id, SESSION_ID, Fq12::one());
+        let mut other = bound_inputs_account(&program_id, SESSION_ID, [2; 32], &mut y14, &mut y15);
+        let mut inputs = bound_inputs_account(&program_id, SESSION_ID, [1; 32], &mut y14, &mut y15);
+        let mut step = |y14: &mut TestAccount, inputs: &mut TestAccount| {
+            let mut payer = TestAccount::payer();
+            let infos = [
+                payer.info(),
+                y14.info(),
+                y15.info(),
+                inputs.info(),
+                log.info(),
+            ];
+            let data = scratch_data(&attesting_step(FLAG_RECORD_RESULT, &[]), SESSION_ID);
+            crate::process_instruction(&program_id, &infos, &data)
+        };
+
+        assert_eq!(
+            step(&mut y14, &mut other),
+            Err(VerifierError::UnboundInputs.into())
+        );
+        write_tag(&y14.info(), &UNBOUND_TAG).unwrap();
+        assert_eq!(
+            step(&mut y14, &mut inputs),
+            Err(VerifierError::UnboundInputs.into())
+        );
+        assert_eq!(count(&log), 0);
+    }
+
+    #[test]
+    fn test_create_result_log() {
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let mut log = TestAccount::result_log(&program_id, 3);
+        log.owner = system_program::id();
+        log.data = vec![0; log.data.len()];
+        let create = |log: &mut TestAccount, capacity: u8| {
+            let mut payer = TestAccount::payer();
+            let mut system = TestAccount::new(&Pubkey::default(), 0);
+            system.key = system_program::id();
+            let infos = [payer.info(), log.info(), system.info()];
+            let data = scratch_data(&[22, capacity, CREATE_RESULT_LOG], SESSION_ID);
+            crate::process_instruction(&program_id, &infos, &data)
+        };
+
+        for capacity in [0, max_capacity() as u8 + 1].iter() {
+            assert_eq!(
+                create(&mut log, *capacity),
+                Err(ProgramError::InvalidInstructionData)
+            );
+        }
+        create(&mut log, 3).unwrap();
+        let len = record_offset(3);
+        let lamports = Rent::default().minimum_balance(len);
+        let (payer, address) = (TestAccount::payer().key, log.key);
+        let expected =
+            system_instruction::create_account(&payer, &address, lamports, len as u64, &program_id);
+        assert_eq!(invoked().last(), Some(&expected));
+        assert_eq!(
+            log.data[..ACCOUNT_HEADER_LEN],
+            AccountKind::ResultLog.header()
+        );
+        assert_eq!(count(&log), 0);
+
+        log.owner = program_id;
+        assert_eq!(
+            create(&mut log, 3),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+        let mut other = TestAccount::result_log(&program_id, 3);
+        other.key = Pubkey::new_unique();
+        other.owner = system_program::id();
+        assert_eq!(create(&mut other, 3), Err(ProgramError::InvalidSeeds));
+    }
+
+    // The step appends to nothing but the payer's log.
+    #[test]
+    fn test_foreign_log_rejected() {
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let mut elsewhere = TestAccount::result_log(&program_id, 1);
+        elsewhere.key = Pubkey::new_unique();
+        let mut foreign = TestAccount::result_log(&program_id, 1);
+        foreign.owner = Pubkey::new_unique();
+        // the old layout, a bare count and records
+        let mut unheaded = TestAccount::result_log(&program_id, 1);
+        unheaded.data[..ACCOUNT_HEADER_LEN].copy_from_slice(&[0; ACCOUNT_HEADER_LEN]);
+        let mut other_kind = TestAccount::result_log(&program_id, 1);
+        other_kind.data[..ACCOUNT_HEADER_LEN].copy_from_slice(&AccountKind::Receipt.header());
+
+        let cases = vec![
+            (elsewhere, ProgramError::InvalidSeeds),
+            (foreign, ProgramError::IncorrectProgramId),
+            (unheaded, VerifierError::InvalidAccountHeader.into()),
+            (other_kind, VerifierError::InvalidAccountHeader.into()),
+        ];
+        for (mut log, error) in cases {
+            assert_eq!(
+                final_step(&program_id, &mut log, Fq12::one(), 1),
+                Err(error)
+            );
+            assert_eq!(count(&log), 0);
+        }
+    }
+}
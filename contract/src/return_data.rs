@@ -0,0 +1,182 @@
+use arrayref::{array_ref, array_refs};
+use solana_program::program::{get_return_data, set_return_data};
+use solana_program::pubkey::Pubkey;
+
+// Outcome of a verification handed back to the caller, set by the final step when sent
+// with `FLAG_RETURN_DATA` (see `hard_part_y16`). A program driving the final step
+// through a cross-program invocation reads it with `outcome` right after the invoke and
+// branches on it in the same transaction; like the result log, the step then succeeds
+// on an invalid proof, so the caller must check `valid`. The key id, hash and source
+// come from the session's inputs account, and the step fails unless the proof was
+// checked against exactly those inputs (see `public_inputs::bound_statement`), so the
+// caller can trust them as far as `valid`.
+//
+// return data layout:
+//   0..1    result (1 = proof valid)
+//   1..33   vk id, sha256 of the whole verifying key (see `utils::vk_id`)
+//   33..65  public-input hash, sha256 of the inputs folded on-chain (see
+//           `fold_public_inputs`)
+//   65..97  their source id: the sysvar's address, the sha256 of an oracle's address,
+//           owner program and offset, or the sha256 of inputs carried in the fold
+//           instruction (see `fold_public_inputs`)
+pub const RETURN_DATA_LEN: usize = 97;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub valid: bool,
+    pub vk_id: [u8; 32],
+    pub input_hash: [u8; 32],
+    pub source_id: [u8; 32],
+}
+
+impl Outcome {
+    pub fn pack(&self) -> [u8; RETURN_DATA_LEN] {
+        let mut data = [0; RETURN_DATA_LEN];
+        data[0] = self.valid as u8;
+        data[1..33].copy_from_slice(&self.vk_id);
+        data[33..65].copy_from_slice(&self.input_hash);
+        data[65..].copy_from_slice(&self.source_id);
+        data
+    }
+
+    pub fn unpack(data: &[u8]) -> Option<Outcome> {
+        if data.len() != RETURN_DATA_LEN {
+            return None;
+        }
+        let data = array_ref![data, 0, RETURN_DATA_LEN];
+        let (result, vk_id, input_hash, source_id) = array_refs![data, 1, 32, 32, 32];
+        let valid = match result[0] {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        Some(Outcome {
+            valid,
+            vk_id: *vk_id,
+            input_hash: *input_hash,
+            source_id: *source_id,
+        })
+    }
+}
+
+pub fn set_outcome(outcome: &Outcome) {
+    set_return_data(&outcome.pack());
+}
+
+// The outcome the verifier `program_id` returned from the last invocation, `None` if
+// the last program to set return data was another one or the final step was not sent
+// with `FLAG_RETURN_DATA`.
+pub fn outcome(program_id: &Pubkey) -> Option<Outcome> {
+    match get_return_data() {
+        Some((returned_by, data)) if returned_by == *program_id => Outcome::unpack(&data),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ark_bn254::Fq12;
+    use ark_ff::One;
+    use solana_program::entrypoint::ProgramResult;
+
+    use super::*;
+    use crate::error::VerifierError;
+    use crate::scratch::{write_tag, UNBOUND_TAG};
+    use crate::test_utils::{
+        attesting_step, bound_inputs_account, final_step_accounts, install_stubs, invalid_y15,
+        scratch_data, TestAccount, RETURNING_PROGRAM, SESSION_ID,
+    };
+    use crate::utils::vk_id;
+    use crate::{FLAG_RECORD_RESULT, FLAG_RETURN_DATA};
+
+    fn final_step(flags: u8, y15: Fq12, input_hash: u8, log: &mut TestAccount) -> ProgramResult {
+        let program_id = RETURNING_PROGRAM;
+        let mut payer = TestAccount::payer();
+        let (mut y14_account, mut y15_account) = final_step_accounts(&program_id, SESSION_ID, y15);
+        let mut inputs_account = bound_inputs_account(
+            &program_id,
+            SESSION_ID,
+            [input_hash; 32],
+            &mut y14_account,
+            &mut y15_account,
+        );
+        let infos = vec![
+            payer.info(),
+            y14_account.info(),
+            y15_account.info(),
+            inputs_account.info(),
+            log.info(),
+        ];
+        let data = attesting_step(flags, &[]);
+        crate::process_instruction(&program_id, &infos, &scratch_data(&data, SESSION_ID))
+    }
+
+    #[test]
+    fn test_return_outcome() {
+        install_stubs();
+        let mut log = TestAccount::result_log(&RETURNING_PROGRAM, 1);
+        let vk_id = vk_id(None);
+
+        final_step(FLAG_RETURN_DATA, Fq12::one(), 1, &mut log).unwrap();
+        let returned = outcome(&RETURNING_PROGRAM).unwrap();
+        assert_eq!(
+            returned,
+            Outcome {
+                valid: true,
+                vk_id,
+                input_hash: [1; 32],
+                source_id: [1; 32],
+            }
+        );
+        assert_eq!(Outcome::unpack(&returned.pack()), Some(returned));
+        assert_eq!(outcome(&Pubkey::new_unique()), None);
+
+        // an invalid proof is returned rather than failing the transaction, with the
+        // hash shared with the result log
+        let flags = FLAG_RETURN_DATA | FLAG_RECORD_RESULT;
+        final_step(flags, invalid_y15(), 2, &mut log).unwrap();
+        let returned = outcome(&RETURNING_PROGRAM).unwrap();
+        assert!(!returned.valid);
+        assert_eq!(returned.input_hash, [2; 32]);
+        let record = crate::result_log::record_offset(0);
+        assert_eq!(&log.data[record + 32..record + 64], &[2; 32]);
+
+        // without the flag, the step fails as before
+        assert_eq!(
+            final_step(0, invalid_y15(), 3, &mut log),
+            Err(VerifierError::InvalidProof.into())
+        );
+        let mut data = returned.pack();
+        data[0] = 2;
+        assert_eq!(Outcome::unpack(&data), None);
+        assert_eq!(Outcome::unpack(&data[1..]), None);
+    }
+
+    // The outcome cannot name inputs the proof was not checked against: other inputs
+    // folded afterwards, or any when the gamma loop ran on the client's prepared input.
+    #[test]
+    fn test_outcome_of_other_inputs() {
+        install_stubs();
+        let program_id = RETURNING_PROGRAM;
+        let (mut y14, mut y15) = final_step_accounts(&program_id, SESSION_ID, Fq12::one());
+        let mut other = bound_inputs_account(&program_id, SESSION_ID, [2; 32], &mut y14, &mut y15);
+        let mut inputs = bound_inputs_account(&program_id, SESSION_ID, [1; 32], &mut y14, &mut y15);
+        let mut step = |inputs: &mut TestAccount, y15: &mut TestAccount| {
+            let mut payer = TestAccount::payer();
+            let infos = [payer.info(), y14.info(), y15.info(), inputs.info()];
+            let data = scratch_data(&attesting_step(FLAG_RETURN_DATA, &[]), SESSION_ID);
+            crate::process_instruction(&program_id, &infos, &data)
+        };
+
+        step(&mut inputs, &mut y15).unwrap();
+        assert_eq!(
+            step(&mut other, &mut y15),
+            Err(VerifierError::UnboundInputs.into())
+        );
+        write_tag(&y15.info(), &UNBOUND_TAG).unwrap();
+        assert_eq!(
+            step(&mut inputs, &mut y15),
+            Err(VerifierError::UnboundInputs.into())
+        );
+    }
+}
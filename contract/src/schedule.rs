@@ -0,0 +1,345 @@
+use std::ops::Range;
+
+use groth16_sol_core::miller_loop::{step_coeff_count, step_range};
+
+use crate::curve::{ATE_LOOP_COUNT, X_NAF};
+use crate::final_exponentiation::{FLAG_INLINE_VK, FLAG_TARGET_ONE, PREPARE_PAIRING};
+use crate::instruction::VerifierInstruction::{self, *};
+use crate::instruction::{Payload, StepIndex};
+use crate::miller_loop::INLINE_COEFFS_FLAG;
+use crate::pairing::PAIRING_MILLER_LOOP;
+use crate::public_inputs::SOURCE_INSTRUCTION;
+use crate::vk::{ELL_COEFF_DATA_LEN, VK_ACCOUNT_FLAG};
+
+// The instruction sequence of a full verification, shared by the tests and the off-chain
+// verifier. Each step is the indices of the scratch accounts it takes, in order, and its
+// instruction data; the client sends the same sequence (see `client/src/steps.rs`).
+
+// Instruction data for a full Miller loop of `stage` (`GammaMillerLoop`,
+// `DeltaMillerLoop` or `AbMillerLoop`), each step carrying `input`, in the order the
+// client sends them.
+pub fn miller_loop_steps(
+    stage: fn(StepIndex, Payload) -> VerifierInstruction,
+    input: &[u8],
+) -> Vec<Vec<u8>> {
+    let mut steps = vec![];
+    let mut j: u8 = 0;
+    for i in (1..ATE_LOOP_COUNT.len()).rev() {
+        steps.push(stage(StepIndex::new(i as u8, j), Payload(input.to_vec())).pack());
+        j += step_coeff_count(i, j as usize) as u8;
+    }
+    steps.push(stage(StepIndex::new(0, j), Payload(input.to_vec())).pack());
+    steps
+}
+
+// Instruction data preparing `point` (behind its encoding byte) into `table` of a VK
+// upload on-chain, `per_instruction` steps per instruction, see `g2_prepare.rs`; with
+// `PAIRING_MILLER_LOOP` for the table and pairs for the point, the Miller loop of a
+// pairing product. The session id still has to be appended.
+pub fn prepare_g2_steps(table: u8, point: &[u8], per_instruction: u8) -> Vec<Vec<u8>> {
+    let len = ATE_LOOP_COUNT.len();
+    let payload = [&[table][..], point].concat();
+    step_range(len - 1, 0, len)
+        .unwrap()
+        .chunks(per_instruction.max(1) as usize)
+        .map(|chunk| {
+            let (i, j) = chunk[0];
+            let index = StepIndex::new(i as u8, j as u8);
+            PrepareG2(index, chunk.len() as u8, Payload(payload.clone())).pack()
+        })
+        .collect()
+}
+
+// Merge runs of consecutive gamma or delta Miller-loop steps in `steps` into
+// `GammaMillerLoopSteps` / `DeltaMillerLoopSteps` instructions of up to `per_instruction`
+// steps each, keeping the accounts and input of the run's first step. Must come before
+// the other rewrites.
+pub fn with_packed_miller_loops(steps: &mut Vec<(Vec<usize>, Vec<u8>)>, per_instruction: u8) {
+    let mut packed: Vec<(Vec<usize>, Vec<u8>)> = vec![];
+    for (accounts, data) in steps.drain(..) {
+        let continues = match packed.last() {
+            Some((last_accounts, last)) => {
+                data[0] <= 1
+                    && last[0] == data[0] + 27
+                    && last[3] < per_instruction
+                    && *last_accounts == accounts
+                    && last[4..] == data[3..]
+            }
+            None => false,
+        };
+        if continues {
+            packed.last_mut().unwrap().1[3] += 1;
+        } else if data[0] <= 1 && per_instruction > 1 {
+            let mut first = data;
+            first[0] += 27;
+            first.insert(3, 1);
+            packed.push((accounts, first));
+        } else {
+            packed.push((accounts, data));
+        }
+    }
+    *steps = packed;
+}
+
+// Fuse the gamma and delta Miller-loop steps of `steps` pairwise into
+// `GammaDeltaMillerLoop` instructions, in place of the gamma steps, each taking the
+// gamma step's accounts with the delta account after the gamma account. Both loops must
+// be split alike; must follow `with_packed_miller_loops` and precede the other rewrites.
+pub fn with_fused_miller_loops(steps: &mut Vec<(Vec<usize>, Vec<u8>)>) {
+    let is_delta = |data: &[u8]| data[0] == 1 || data[0] == 28;
+    let mut delta_steps = steps
+        .iter()
+        .filter(|(_, data)| is_delta(data))
+        .cloned()
+        .collect::<Vec<_>>()
+        .into_iter();
+    steps.retain(|(_, data)| !is_delta(data));
+    for (accounts, data) in steps.iter_mut() {
+        if data[0] != 0 && data[0] != 27 {
+            continue;
+        }
+        let (delta_accounts, mut delta) = delta_steps.next().unwrap();
+        // single steps get a step count of one
+        if data[0] == 0 {
+            data.insert(3, 1);
+        }
+        if delta[0] == 1 {
+            delta.insert(3, 1);
+        }
+        assert_eq!(data[1..4], delta[1..4]);
+        data[0] = 29;
+        data.splice(4..4, delta.drain(4..));
+        accounts.insert(1, delta_accounts[0]);
+    }
+}
+
+// Scratch accounts of a full verification, indexed as in `verification_steps`.
+pub const GAMMA: usize = 0;
+pub const DELTA: usize = 1;
+pub const FINAL: usize = 2;
+pub const AB: usize = 20;
+// The payer the scratch accounts are derived from, see `with_scratch`
+pub const PAYER: usize = 21;
+pub const NUM_ACCOUNTS: usize = 22;
+
+pub fn y(i: usize) -> usize {
+    3 + i
+}
+
+// Every instruction of a full verification as (account indices, data), in the order
+// the client sends them. The proof's A·B Miller loop runs on-chain over `proof_ab`
+// (encoding || A || B), so nothing the final exponentiation starts from is taken on
+// trust.
+pub fn verification_steps(
+    proof_c: &[u8],
+    prepared_input: &[u8],
+    proof_ab: &[u8],
+) -> Vec<(Vec<usize>, Vec<u8>)> {
+    let mut steps = vec![];
+    for data in miller_loop_steps(GammaMillerLoop, prepared_input) {
+        steps.push((vec![GAMMA], data));
+    }
+    for data in miller_loop_steps(DeltaMillerLoop, proof_c) {
+        steps.push((vec![DELTA], data));
+    }
+    for data in miller_loop_steps(AbMillerLoop, proof_ab) {
+        steps.push((vec![AB], data));
+    }
+
+    steps.extend(final_exponentiation_steps());
+    steps
+}
+
+// The final exponentiation part of `verification_steps`, from the prepare step on.
+pub fn final_exponentiation_steps() -> Vec<(Vec<usize>, Vec<u8>)> {
+    let index = StepIndex::default;
+    let digit = |j: u8| StepIndex::new(0, j);
+
+    let mut steps = vec![];
+    let prepare = PrepareFinal(index(), Payload::default());
+    steps.push((vec![GAMMA, DELTA, FINAL, AB], prepare.pack()));
+    steps.push((vec![FINAL], EasyPart1(index()).pack()));
+    steps.push((vec![FINAL], EasyPart2(index()).pack()));
+    let digits = 0..X_NAF.len() as u8;
+    for j in digits.clone() {
+        steps.push((vec![FINAL, y(0)], HardPartY0(digit(j)).pack()));
+    }
+    steps.push((vec![y(0), y(1)], HardPartY1(digit(64)).pack()));
+    steps.push((vec![y(0), y(3)], HardPartY3(index()).pack()));
+    for j in digits.clone() {
+        steps.push((vec![y(3), y(4)], HardPartY4(digit(j)).pack()));
+    }
+    for j in digits {
+        steps.push((vec![y(4), y(6)], HardPartY6(digit(j)).pack()));
+    }
+    steps.push((vec![y(3), y(4), y(6), y(8)], HardPartY8(index()).pack()));
+    steps.push((vec![y(1), y(8), y(9)], HardPartY9(index()).pack()));
+    steps.push((vec![y(4), y(8), FINAL, y(11)], HardPartY11(index()).pack()));
+    steps.push((vec![y(9), y(11), y(13)], HardPartY13(index()).pack()));
+    steps.push((vec![y(8), y(13), y(14)], HardPartY14(index()).pack()));
+    steps.push((vec![y(9), FINAL, y(15)], HardPartY15(index()).pack()));
+    let check = HardPartY16(index(), Payload::default());
+    steps.push((vec![y(14), y(15)], check.pack()));
+    steps
+}
+
+// Every instruction of a pairing-product check of `pairs` (encoding byte, then each
+// pair behind its kind, see `pairing.rs`) against one, `per_instruction` Miller-loop
+// steps per instruction, with the pairing account at index `pairing` and the others as
+// in `verification_steps`. For another target, set `FLAG_INLINE_VK` in place of
+// `FLAG_TARGET_ONE` on the last step and put the target in its payload.
+pub fn pairing_product_steps(
+    pairing: usize,
+    pairs: &[u8],
+    per_instruction: u8,
+) -> Vec<(Vec<usize>, Vec<u8>)> {
+    let mut steps: Vec<_> = prepare_g2_steps(PAIRING_MILLER_LOOP, pairs, per_instruction)
+        .into_iter()
+        .map(|data| (vec![pairing], data))
+        .collect();
+    let mut final_steps = final_exponentiation_steps();
+    let prepare = PrepareFinal(StepIndex::new(PREPARE_PAIRING, 0), Payload::default());
+    final_steps[0] = (vec![pairing, FINAL], prepare.pack());
+    let check = HardPartY16(StepIndex::new(FLAG_TARGET_ONE, 0), Payload::default());
+    final_steps.last_mut().unwrap().1 = check.pack();
+    steps.extend(final_steps);
+    steps
+}
+
+// Merge the one-digit steps of the exponentiations by x (y0, y4, y6) in `steps` into
+// steps of up to `per_instruction` consecutive NAF digits each, see
+// `final_exponentiation::naf_digits`. Must come before `with_scratch`.
+pub fn with_packed_naf_digits(steps: &mut Vec<(Vec<usize>, Vec<u8>)>, per_instruction: u8) {
+    let mut packed: Vec<(Vec<usize>, Vec<u8>)> = vec![];
+    for (accounts, mut data) in steps.drain(..) {
+        let naf_step = matches!(data[0], 5 | 8 | 9);
+        if let Some((last_accounts, last)) = packed.last_mut() {
+            if naf_step
+                && last[0] == data[0]
+                && last[1] < per_instruction
+                && last[2] + last[1] == data[2]
+                && *last_accounts == accounts
+            {
+                last[1] += 1;
+                continue;
+            }
+        }
+        if naf_step {
+            data[1] = 1;
+        }
+        packed.push((accounts, data));
+    }
+    *steps = packed;
+}
+
+// Rewrite `steps` to verify against a VK carried in the instruction data instead of the
+// baked one: each Miller-loop step gets its coefficients from the concatenated
+// `gamma_coeffs` / `delta_coeffs` tables, the final step `alpha_g1_beta_g2`.
+pub fn with_inline_vk(
+    steps: &mut [(Vec<usize>, Vec<u8>)],
+    gamma_coeffs: &[u8],
+    delta_coeffs: &[u8],
+    alpha_g1_beta_g2: &[u8],
+) {
+    for (_, data) in steps.iter_mut() {
+        let (i, j) = (data[1] as usize, data[2] as usize);
+        let (coeffs, offset) = match data[0] {
+            0 => (gamma_coeffs[coeff_range(i, j, 1)].to_vec(), 3),
+            1 => (delta_coeffs[coeff_range(i, j, 1)].to_vec(), 3),
+            27 => (gamma_coeffs[coeff_range(i, j, data[3])].to_vec(), 4),
+            28 => (delta_coeffs[coeff_range(i, j, data[3])].to_vec(), 4),
+            29 => {
+                let range = coeff_range(i, j, data[3]);
+                (
+                    [&gamma_coeffs[range.clone()], &delta_coeffs[range]].concat(),
+                    4,
+                )
+            }
+            16 => {
+                data[1] |= FLAG_INLINE_VK;
+                data.splice(3..3, alpha_g1_beta_g2.iter().cloned());
+                continue;
+            }
+            _ => continue,
+        };
+        data[0] |= INLINE_COEFFS_FLAG as u8;
+        data.splice(offset..offset, coeffs);
+    }
+}
+
+// The bytes of the concatenated coefficient table that `steps` Miller-loop steps from
+// (i, j) on consume.
+pub fn coeff_range(i: usize, j: usize, steps: u8) -> Range<usize> {
+    let range = step_range(i, j, steps as usize).unwrap();
+    let (last_i, last_j) = *range.last().unwrap();
+    let end = last_j + step_coeff_count(last_i, last_j);
+    j * ELL_COEFF_DATA_LEN..end * ELL_COEFF_DATA_LEN
+}
+
+// Rewrite `steps`, built with an empty prepared input, to fold `raw_inputs` (32-byte
+// little-endian values) on-chain into the inputs account at index `inputs` first, run
+// the gamma loop on the folded point and send the final step with `flags`, which
+// attest to those inputs, and `statement`, the proof and with `FLAG_RECEIPT` the
+// inputs as the step takes them (see `FLAG_RECORD_RESULT`). Must come before
+// `with_vk_account`.
+pub fn with_folded_inputs(
+    steps: &mut Vec<(Vec<usize>, Vec<u8>)>,
+    inputs: usize,
+    raw_inputs: &[u8],
+    flags: u8,
+    statement: &[u8],
+) {
+    for (accounts, data) in steps.iter_mut() {
+        match data[0] {
+            0 | 27 | 29 => accounts.push(inputs),
+            16 => {
+                data[1] |= flags;
+                data.splice(3..3, statement.iter().cloned());
+                accounts.push(inputs);
+            }
+            _ => {}
+        }
+    }
+    let source = [&[SOURCE_INSTRUCTION][..], raw_inputs].concat();
+    let fold = FoldPublicInputs(StepIndex::default(), Payload(source));
+    steps.insert(0, (vec![inputs], fold.pack()));
+}
+
+// Rewrite `steps` to read the verifying key from the VK account at index `vk`: the
+// Miller-loop, fold and final steps take it as their first account.
+pub fn with_vk_account(steps: &mut [(Vec<usize>, Vec<u8>)], vk: usize) {
+    for (accounts, data) in steps.iter_mut() {
+        if matches!(data[0], 0 | 1 | 16 | 18 | 27 | 28 | 29) {
+            data[0] |= VK_ACCOUNT_FLAG as u8;
+            accounts.insert(0, vk);
+        }
+    }
+}
+
+// Rewrite `steps` to run under the session `session_id` of the payer at index `payer`:
+// every step on scratch accounts takes the payer before its own accounts and ends with
+// the session id, see `scratch.rs`. Must be the last rewrite before `with_registered_vk`
+// and `session::guard_steps`.
+pub fn with_scratch(steps: &mut [(Vec<usize>, Vec<u8>)], payer: usize, session_id: u64) {
+    for (accounts, data) in steps.iter_mut() {
+        let tag = data[0] & !((VK_ACCOUNT_FLAG | INLINE_COEFFS_FLAG) as u8);
+        if VerifierInstruction::runs_on_scratch(tag) {
+            let vk_account = data[0] & VK_ACCOUNT_FLAG as u8 != 0;
+            accounts.insert(vk_account as usize, payer);
+            data.extend(session_id.to_le_bytes().iter());
+        }
+    }
+}
+
+// Rewrite the VK-account steps of `steps` (see `with_vk_account`) to read the key
+// registered under `id` instead, with its registry entry at index `entry`, see
+// `registry.rs`. Must follow `with_scratch`.
+pub fn with_registered_vk(steps: &mut [(Vec<usize>, Vec<u8>)], entry: usize, id: u64) {
+    for (accounts, data) in steps.iter_mut() {
+        if data[0] & VK_ACCOUNT_FLAG as u8 != 0 {
+            data[0] |= INLINE_COEFFS_FLAG as u8;
+            accounts.insert(0, entry);
+            data.extend(id.to_le_bytes().iter());
+        }
+    }
+}
@@ -0,0 +1,718 @@
+use std::slice::Iter;
+
+use ark_ff::One;
+use arrayref::{array_mut_ref, array_ref};
+use solana_program::account_info::{next_account_info, AccountInfo};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use crate::curve::Fq12;
+use crate::error::VerifierError;
+use crate::g2_prepare::G2_DATA_LEN;
+use crate::header::{check_account_header, AccountKind, ACCOUNT_HEADER_LEN};
+use crate::miller_loop::AB_DATA_LEN;
+use crate::pairing::PAIRING_DATA_LEN;
+use crate::public_inputs::INPUTS_DATA_LEN;
+use crate::result_account::RESULT_DATA_LEN;
+use crate::session::SESSION_DATA_LEN;
+use crate::telemetry::TELEMETRY_DATA_LEN;
+use crate::utils::{
+    check_scratch_account, create_program_account, Fp12Data, BN254_DATA_LEN, CYCLOTOMIC_DATA_LEN,
+};
+
+// Scratch accounts are program-derived addresses seeded by the payer driving the
+// verification, a session id the payer chooses and the account's role:
+//
+//   seeds: payer || session id (u64, little-endian) || role (1 byte)
+//
+// so verifications by different payers, or by one payer under different session ids,
+// never share an account, and every step re-derives the address of each account it
+// touches instead of trusting the client's choice.
+//
+// Steps running on scratch accounts take the payer, which must sign, right before their
+// own accounts, after the session and VK accounts if any, and their data ends with the
+// session id, before the session suffix if any. `create_scratch` allocates the accounts.
+pub const SCRATCH_SUFFIX_LEN: usize = 8;
+// Top bit of the session id suffix: the step records its compute units in the session's
+// telemetry account, which comes right after the payer (see `telemetry.rs`). Session ids
+// are below this bit.
+pub const TELEMETRY_FLAG: u64 = 1 << 63;
+
+// Every scratch account starts with a header, written by `create_scratch`, so that an
+// account of another layout version or role, or one the program never set up, is
+// rejected instead of its bytes being read as field elements. The Fp12 accumulator of
+// every role holding one starts out as one, so the first step of a stage multiplies
+// into whatever the account holds rather than treating j = 0 as a fresh start;
+// `init_scratch` and `reset_session` restore that state to restart a session:
+//
+// scratch account layout:
+//   0..8  account header (see `header.rs`): magic, layout version SCRATCH_VERSION and
+//         the role, the verification stage the account holds (see `Role::to_u8`)
+//   8..40 binding tag, see below
+//   40..  the role's data (Fp12 accumulator, see `Fp12Data`, AB, inputs, G2 or
+//         pairing layout)
+//
+// Version 2 stores accumulators as raw Montgomery limbs instead of their `ToBytes`
+// encoding, version 3 the hard-part accumulators y0..y16 compressed (see
+// `CyclotomicData`), in accounts of half the size, version 4 adds the binding tag.
+//
+// `Scratch::next_account` checks the whole header, `get_account_data` and
+// `put_account_data` the magic and version again before touching an accumulator.
+pub const SCRATCH_VERSION: u8 = 4;
+pub const SCRATCH_HEADER_LEN: usize = 40;
+const TAG_OFFSET: usize = 8;
+
+// The binding tag records what statement and proof an account's value was computed
+// for, so the final step can tell a proof checked against public inputs folded on-chain
+// from one checked against a prepared input the client chose, and name the proof it
+// checked. A step sets the tag of the account it writes from the tags of what it read:
+//   inputs   sha256(vk id || source id || inputs hash), see `fold_public_inputs`
+//   gamma    the inputs account's tag, as long as every step read the prepared input
+//            from it with the coefficients of the key it was folded for
+//   delta    sha256(vk id || proof.c), as long as every step ran on the same proof.c
+//            with the coefficients of the same key
+//   ab       sha256(proof.a || proof.b), as long as every step ran on the same points
+//   final    sha256(gamma tag || delta tag || ab tag) after `PrepareFinal`; the easy
+//            part keeps it
+//   y0..y16  the tag of the accounts each is computed from
+// Points are hashed in their uncompressed arkworks encoding, whichever encoding the
+// steps were sent in (see `miller_loop::delta_tag` and `ab_tag`).
+// A value mixing tags, or built from a client's prepared input, inline coefficients, an
+// account never written or a pairing product, is `UNBOUND_TAG`. Fresh accounts hold
+// `UNWRITTEN_TAG`, which an accumulator's first step replaces with its own.
+pub const UNWRITTEN_TAG: [u8; 32] = [0; 32];
+pub const UNBOUND_TAG: [u8; 32] = [0xff; 32];
+
+pub fn read_tag(account: &AccountInfo) -> Result<[u8; 32], ProgramError> {
+    let data = account.try_borrow_data()?;
+    check_header(&data, None)?;
+    Ok(*array_ref![data, TAG_OFFSET, 32])
+}
+
+pub fn write_tag(account: &AccountInfo, tag: &[u8; 32]) -> ProgramResult {
+    let mut data = account.try_borrow_mut_data()?;
+    check_header(&data, None)?;
+    array_mut_ref![data, TAG_OFFSET, 32].copy_from_slice(tag);
+    Ok(())
+}
+
+pub fn is_bound(tag: &[u8; 32]) -> bool {
+    *tag != UNWRITTEN_TAG && *tag != UNBOUND_TAG
+}
+
+// The tag of a value computed from values tagged `tags`: theirs when they all agree,
+// `UNBOUND_TAG` when they differ or one was never written.
+pub fn join_tags(tags: &[[u8; 32]]) -> [u8; 32] {
+    match tags.split_first() {
+        Some((first, rest)) if is_bound(first) && rest.iter().all(|tag| tag == first) => *first,
+        _ => UNBOUND_TAG,
+    }
+}
+
+// Tag `account` as overwritten from `inputs`.
+pub fn derive_tag(account: &AccountInfo, inputs: &[&AccountInfo]) -> ProgramResult {
+    let tags = inputs
+        .iter()
+        .map(|input| read_tag(input))
+        .collect::<Result<Vec<_>, _>>()?;
+    write_tag(account, &join_tags(&tags))
+}
+
+// Fold the tag of a step's contribution into the accumulator `account` it multiplied
+// into, which takes the contribution's on its stage's first step.
+pub fn accumulate_tag(account: &AccountInfo, tag: &[u8; 32]) -> ProgramResult {
+    let prior = read_tag(account)?;
+    let tag = if prior == UNWRITTEN_TAG {
+        join_tags(&[*tag])
+    } else {
+        join_tags(&[prior, *tag])
+    };
+    write_tag(account, &tag)
+}
+
+// Check the header of scratch account data, and that it holds `role` when given, or
+// else any role.
+pub fn check_header(data: &[u8], role: Option<Role>) -> ProgramResult {
+    if data.len() < SCRATCH_HEADER_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    let role = match role {
+        Some(role) => role,
+        None => Role::from_u8(data[5]).ok_or(VerifierError::InvalidAccountHeader)?,
+    };
+    check_account_header(data, AccountKind::Scratch(role))
+}
+
+// What a scratch account holds. The role byte of gamma, delta, final, y0..y16 and ab is
+// their index in `schedule::verification_steps`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Gamma,
+    Delta,
+    Final,
+    // hard-part accumulator y0..y16
+    Y(u8),
+    // A·B Miller loop, see `ab_miller_loop`
+    Ab,
+    // folded public inputs, see `fold_public_inputs`
+    Inputs,
+    // running point of an on-chain G2 preparation, see `g2_prepare.rs`
+    G2,
+    // Miller loop of a pairing product, see `pairing.rs`
+    Pairing,
+    // progress of a guarded session, see `session.rs`
+    Session,
+    // outcome of the session's latest verification, see `result_account.rs`
+    Result,
+    // compute units consumed by the session's steps, see `telemetry.rs`
+    Telemetry,
+}
+
+// Number of roles, whose bytes are 0..ROLE_COUNT.
+pub const ROLE_COUNT: u8 = 27;
+
+impl Role {
+    pub fn from_u8(role: u8) -> Option<Role> {
+        match role {
+            0 => Some(Role::Gamma),
+            1 => Some(Role::Delta),
+            2 => Some(Role::Final),
+            3..=19 => Some(Role::Y(role - 3)),
+            20 => Some(Role::Ab),
+            21 => Some(Role::Inputs),
+            22 => Some(Role::G2),
+            23 => Some(Role::Pairing),
+            24 => Some(Role::Session),
+            25 => Some(Role::Result),
+            26 => Some(Role::Telemetry),
+            _ => None,
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Role::Gamma => 0,
+            Role::Delta => 1,
+            Role::Final => 2,
+            Role::Y(k) => 3 + k,
+            Role::Ab => 20,
+            Role::Inputs => 21,
+            Role::G2 => 22,
+            Role::Pairing => 23,
+            Role::Session => 24,
+            Role::Result => 25,
+            Role::Telemetry => 26,
+        }
+    }
+
+    // Size of the account, header included.
+    pub fn data_len(self) -> usize {
+        SCRATCH_HEADER_LEN
+            + match self {
+                Role::Ab => AB_DATA_LEN,
+                Role::Inputs => INPUTS_DATA_LEN,
+                Role::G2 => G2_DATA_LEN,
+                Role::Pairing => PAIRING_DATA_LEN,
+                Role::Session => SESSION_DATA_LEN,
+                Role::Result => RESULT_DATA_LEN,
+                Role::Telemetry => TELEMETRY_DATA_LEN,
+                _ => self.accumulator_len(),
+            }
+    }
+
+    // Whether the role holds its Fp12 accumulator compressed, as the hard-part
+    // accumulators, all in the cyclotomic subgroup, are.
+    pub fn is_compressed(self) -> bool {
+        matches!(self, Role::Y(_))
+    }
+
+    // Size of the Fp12 accumulator at the start of the role's data.
+    pub fn accumulator_len(self) -> usize {
+        if self.is_compressed() {
+            CYCLOTOMIC_DATA_LEN
+        } else {
+            BN254_DATA_LEN
+        }
+    }
+
+    pub fn header(self) -> [u8; SCRATCH_HEADER_LEN] {
+        let mut header = [0; SCRATCH_HEADER_LEN];
+        header[..ACCOUNT_HEADER_LEN].copy_from_slice(&AccountKind::Scratch(self).header());
+        header
+    }
+
+    // The data of an account in this role before its stage's first step, as
+    // `create_scratch` and `init_scratch` write it: the header, then a one accumulator
+    // for all roles but inputs and G2, whose first steps overwrite them, and the session,
+    // result and telemetry, which hold none, and zeros. A compressed one is zeros.
+    pub fn initial_data(self) -> Vec<u8> {
+        let mut data = vec![0; self.data_len()];
+        data[..SCRATCH_HEADER_LEN].copy_from_slice(&self.header());
+        if !matches!(
+            self,
+            Role::Inputs | Role::G2 | Role::Session | Role::Result | Role::Telemetry | Role::Y(_)
+        ) {
+            let one = Fp12Data::pack(&Fq12::one());
+            data[SCRATCH_HEADER_LEN..SCRATCH_HEADER_LEN + BN254_DATA_LEN]
+                .copy_from_slice(one.as_bytes());
+        }
+        data
+    }
+}
+
+pub fn scratch_address(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    session_id: u64,
+    role: Role,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[payer.as_ref(), &session_id.to_le_bytes(), &[role.to_u8()]],
+        program_id,
+    )
+}
+
+// The payer and session id a step's scratch accounts are derived from.
+pub struct Scratch<'a, 'b> {
+    program_id: &'a Pubkey,
+    payer: &'a AccountInfo<'b>,
+    session_id: u64,
+    telemetry: bool,
+}
+
+impl<'a, 'b> Scratch<'a, 'b> {
+    // Take the payer and strip the session id, and the telemetry flag on it, off `input`.
+    // The flag is refused by a program built without the `telemetry` feature.
+    pub fn new<'c>(
+        program_id: &'a Pubkey,
+        payer: &'a AccountInfo<'b>,
+        input: &'c [u8],
+    ) -> Result<(Self, &'c [u8]), ProgramError> {
+        if !payer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if input.len() < SCRATCH_SUFFIX_LEN {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (input, suffix) = input.split_at(input.len() - SCRATCH_SUFFIX_LEN);
+        let session_id = u64::from_le_bytes(*array_ref![suffix, 0, SCRATCH_SUFFIX_LEN]);
+        let telemetry = session_id & TELEMETRY_FLAG != 0;
+        if telemetry && !cfg!(feature = "telemetry") {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let scratch = Scratch {
+            program_id,
+            payer,
+            session_id: session_id & !TELEMETRY_FLAG,
+            telemetry,
+        };
+        Ok((scratch, input))
+    }
+
+    pub fn program_id(&self) -> &Pubkey {
+        self.program_id
+    }
+
+    pub fn payer(&self) -> &'a AccountInfo<'b> {
+        self.payer
+    }
+
+    pub fn telemetry(&self) -> bool {
+        self.telemetry
+    }
+
+    pub fn session_id(&self) -> u64 {
+        self.session_id
+    }
+
+    // The next account, checked to be this session's `role` account.
+    pub fn next_account<'c, 'd>(
+        &self,
+        accounts_iter: &mut Iter<'c, AccountInfo<'d>>,
+        role: Role,
+    ) -> Result<&'c AccountInfo<'d>, ProgramError> {
+        let account = next_account_info(accounts_iter)?;
+        self.check_account(account, role)?;
+        Ok(account)
+    }
+
+    pub fn check_account(&self, account: &AccountInfo, role: Role) -> ProgramResult {
+        self.check_address(account, role)?;
+        check_scratch_account(self.program_id, account, role.data_len())?;
+        check_header(&account.try_borrow_data()?, Some(role))
+    }
+
+    fn check_address(&self, account: &AccountInfo, role: Role) -> ProgramResult {
+        let (address, _) = scratch_address(self.program_id, self.payer.key, self.session_id, role);
+        if *account.key != address {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        Ok(())
+    }
+}
+
+// Operations of `CreateScratch` (t = 22), in `j`; the role is in `i`.
+pub const CREATE_SCRATCH: u8 = 0;
+pub const INIT_SCRATCH: u8 = 1;
+
+// Operations of `CloseSession` (t = 26), in `i`.
+pub const CLOSE_SESSION: u8 = 0;
+pub const RESET_SESSION: u8 = 1;
+
+// Allocate this session's `role` account, funded by the rent payer (the payer without
+// one) and owned by the program, and write its initial data. The address is known in
+// advance, so lamports sent to it first are topped up rather than refused, see
+// `create_program_account`.
+//
+// accounts: [scratch account (writable), system program,
+//            rent payer (signer, writable; optional)]
+pub fn create_scratch<'a>(
+    scratch: &Scratch<'_, 'a>,
+    accounts_iter: &mut Iter<AccountInfo<'a>>,
+    role: u8,
+) -> ProgramResult {
+    let role = Role::from_u8(role).ok_or(ProgramError::InvalidInstructionData)?;
+    let account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let rent_payer = accounts_iter.next().unwrap_or(scratch.payer);
+    if !rent_payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let (address, bump) = scratch_address(
+        scratch.program_id,
+        scratch.payer.key,
+        scratch.session_id,
+        role,
+    );
+    if *account.key != address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let len = role.data_len();
+    create_program_account(
+        scratch.program_id,
+        rent_payer,
+        account,
+        system_program,
+        &[
+            scratch.payer.key.as_ref(),
+            &scratch.session_id.to_le_bytes(),
+            &[role.to_u8()],
+            &[bump],
+        ],
+        len,
+    )?;
+    account.try_borrow_mut_data()?[..len].copy_from_slice(&role.initial_data());
+    Ok(())
+}
+
+// Write the initial data of this session's existing `role` account over whatever it
+// holds, so a stage that failed or finished restarts from scratch without reallocating
+// the account. The header is not checked, which also upgrades an account of an older
+// layout version.
+//
+// accounts: [scratch account (writable)]
+pub fn init_scratch(
+    scratch: &Scratch,
+    accounts_iter: &mut Iter<AccountInfo>,
+    role: u8,
+) -> ProgramResult {
+    let role = Role::from_u8(role).ok_or(ProgramError::InvalidInstructionData)?;
+    let account = next_account_info(accounts_iter)?;
+    init_account(scratch, account, role)
+}
+
+// `init_scratch` over several of this session's accounts in one instruction, to restart
+// a failed verification under the same session id.
+//
+// instruction data: t = 26, i = RESET_SESSION, j = 0 || role of each account (1 byte
+//                   each) || session id
+// accounts: [one scratch account (writable) per role]
+pub fn reset_session(
+    scratch: &Scratch,
+    accounts_iter: &mut Iter<AccountInfo>,
+    roles: &[u8],
+) -> ProgramResult {
+    if roles.is_empty() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    for &role in roles {
+        let role = Role::from_u8(role).ok_or(ProgramError::InvalidInstructionData)?;
+        let account = next_account_info(accounts_iter)?;
+        init_account(scratch, account, role)?;
+    }
+    Ok(())
+}
+
+fn init_account(scratch: &Scratch, account: &AccountInfo, role: Role) -> ProgramResult {
+    scratch.check_address(account, role)?;
+    check_scratch_account(scratch.program_id, account, role.data_len())?;
+    account.try_borrow_mut_data()?[..role.data_len()].copy_from_slice(&role.initial_data());
+    Ok(())
+}
+
+// Close this session's scratch accounts once its verification is done: zero each
+// account's data and move its lamports to the refund account, after which the runtime
+// reclaims the account. Only the payer the accounts are derived from can close them,
+// and the roles given must be the accounts' own, so other sessions are left alone.
+//
+// instruction data: t = 26, i = CLOSE_SESSION, j = 0 || role of each account (1 byte
+//                   each) || session id
+// accounts: [refund account (writable), one scratch account (writable) per role]
+pub fn close_session(
+    scratch: &Scratch,
+    accounts_iter: &mut Iter<AccountInfo>,
+    roles: &[u8],
+) -> ProgramResult {
+    let refund = next_account_info(accounts_iter)?;
+    if roles.is_empty() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    for &role in roles {
+        let role = Role::from_u8(role).ok_or(ProgramError::InvalidInstructionData)?;
+        // the header is not checked, so accounts of an older layout version can be closed
+        let account = next_account_info(accounts_iter)?;
+        scratch.check_address(account, role)?;
+        check_scratch_account(scratch.program_id, account, 0)?;
+        if account.key == refund.key {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let lamports = refund
+            .lamports()
+            .checked_add(account.lamports())
+            .ok_or(ProgramError::InvalidArgument)?;
+        **refund.try_borrow_mut_lamports()? = lamports;
+        **account.try_borrow_mut_lamports()? = 0;
+        account
+            .try_borrow_mut_data()?
+            .iter_mut()
+            .for_each(|b| *b = 0);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use solana_program::rent::Rent;
+    use solana_program::{system_instruction, system_program};
+
+    use super::*;
+    use crate::process_instruction;
+    use crate::test_utils::{install_stubs, invoked, scratch_data, TestAccount, SESSION_ID};
+    use crate::utils::get_account_data;
+
+    #[test]
+    fn test_create_scratch() {
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let mut payer = TestAccount::payer();
+        let mut system = TestAccount::new(&Pubkey::default(), 0);
+        system.key = system_program::id();
+        let mut ab = TestAccount::scratch(&program_id, SESSION_ID, Role::Ab);
+        ab.owner = system_program::id();
+        ab.data = vec![0; ab.data.len()];
+        let (payer_key, ab_key) = (payer.key, ab.key);
+
+        let data = scratch_data(&[22, Role::Ab.to_u8(), 0], SESSION_ID);
+        let infos = [payer.info(), ab.info(), system.info()];
+        process_instruction(&program_id, &infos, &data).unwrap();
+        let create = invoked()
+            .into_iter()
+            .find(|ix| ix.accounts.get(1).map(|meta| meta.pubkey) == Some(ab_key))
+            .unwrap();
+        let len = Role::Ab.data_len() as u64;
+        let lamports = Rent::default().minimum_balance(Role::Ab.data_len());
+        let expected =
+            system_instruction::create_account(&payer_key, &ab_key, lamports, len, &program_id);
+        assert_eq!(create, expected);
+
+        // the address must be the role's under the payer and session id
+        let data = scratch_data(&[22, Role::Gamma.to_u8(), 0], SESSION_ID);
+        assert_eq!(
+            process_instruction(&program_id, &infos, &data),
+            Err(ProgramError::InvalidSeeds)
+        );
+        let data = scratch_data(&[22, Role::Ab.to_u8(), 0], SESSION_ID + 1);
+        assert_eq!(
+            process_instruction(&program_id, &infos, &data),
+            Err(ProgramError::InvalidSeeds)
+        );
+        assert_eq!(ab.data, Role::Ab.initial_data());
+
+        // a rent payer funds the account in the payer's place, even a pre-funded one
+        let mut rent_payer = TestAccount::new(&system_program::id(), 0);
+        rent_payer.is_signer = true;
+        let rent_payer_key = rent_payer.key;
+        ab.owner = system_program::id();
+        ab.lamports = 1;
+        let data = scratch_data(&[22, Role::Ab.to_u8(), 0], SESSION_ID);
+        let infos = [payer.info(), ab.info(), system.info(), rent_payer.info()];
+        process_instruction(&program_id, &infos, &data).unwrap();
+        let calls = invoked();
+        assert_eq!(
+            &calls[calls.len() - 3..],
+            &[
+                system_instruction::transfer(&rent_payer_key, &ab_key, lamports - 1),
+                system_instruction::allocate(&ab_key, len),
+                system_instruction::assign(&ab_key, &program_id),
+            ]
+        );
+        rent_payer.is_signer = false;
+        let infos = [payer.info(), ab.info(), system.info(), rent_payer.info()];
+        assert_eq!(
+            process_instruction(&program_id, &infos, &data),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+    }
+
+    // A session restarts from the initial data of its accounts, one at a time or all at
+    // once, whatever a failed verification left in them.
+    #[test]
+    fn test_reset_session() {
+        let program_id = Pubkey::new_unique();
+        let mut payer = TestAccount::payer();
+        let mut gamma = TestAccount::scratch(&program_id, SESSION_ID, Role::Gamma);
+        let mut y0 = TestAccount::scratch(&program_id, SESSION_ID, Role::Y(0));
+        gamma.data[SCRATCH_HEADER_LEN..].copy_from_slice(&[7; BN254_DATA_LEN]);
+        y0.data = vec![0; y0.data.len()];
+
+        let data = scratch_data(&[22, Role::Gamma.to_u8(), INIT_SCRATCH], SESSION_ID);
+        process_instruction(&program_id, &[payer.info(), gamma.info()], &data).unwrap();
+        assert_eq!(gamma.data, Role::Gamma.initial_data());
+        let data = scratch_data(&[22, Role::Gamma.to_u8(), 3], SESSION_ID);
+        assert_eq!(
+            process_instruction(&program_id, &[payer.info(), gamma.info()], &data),
+            Err(ProgramError::InvalidInstructionData)
+        );
+
+        gamma.data[SCRATCH_HEADER_LEN..].copy_from_slice(&[7; BN254_DATA_LEN]);
+        let roles = [Role::Gamma.to_u8(), Role::Y(0).to_u8()];
+        let data = scratch_data(&[&[26, RESET_SESSION, 0][..], &roles].concat(), SESSION_ID);
+        let infos = [payer.info(), y0.info(), gamma.info()];
+        assert_eq!(
+            process_instruction(&program_id, &infos, &data),
+            Err(ProgramError::InvalidSeeds)
+        );
+        let infos = [payer.info(), gamma.info(), y0.info()];
+        process_instruction(&program_id, &infos, &data).unwrap();
+        assert_eq!(gamma.data, Role::Gamma.initial_data());
+        assert_eq!(y0.data, Role::Y(0).initial_data());
+        assert_eq!(get_account_data(&gamma.info()).unwrap(), Fq12::one());
+    }
+
+    // Steps reject accounts whose header is not the layout version's or their role's.
+    #[test]
+    fn test_check_header() {
+        let program_id = Pubkey::new_unique();
+        let easy_part1 = |account: &mut TestAccount| {
+            let mut payer = TestAccount::payer();
+            let data = scratch_data(&[3, 0, 0], SESSION_ID);
+            process_instruction(&program_id, &[payer.info(), account.info()], &data)
+        };
+        let mut final_account = TestAccount::scratch(&program_id, SESSION_ID, Role::Final);
+        final_account.data[SCRATCH_HEADER_LEN..]
+            .copy_from_slice(Fp12Data::pack(&Fq12::one()).as_bytes());
+        easy_part1(&mut final_account).unwrap();
+
+        let header = Role::Final.header();
+        for &(at, value) in [(0, b'X'), (4, SCRATCH_VERSION + 1), (5, Role::Ab.to_u8())].iter() {
+            final_account.data[at] = value;
+            assert_eq!(
+                easy_part1(&mut final_account),
+                Err(VerifierError::InvalidAccountHeader.into())
+            );
+            final_account.data[at] = header[at];
+        }
+        easy_part1(&mut final_account).unwrap();
+
+        assert_eq!(
+            check_header(&header[..4], None),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+        assert_eq!(check_header(&Role::Ab.header(), None), Ok(()));
+    }
+
+    // Steps only run on the session's own accounts, in their roles, for a signing payer.
+    #[test]
+    fn test_steps_check_addresses() {
+        let program_id = Pubkey::new_unique();
+        let easy_part1 = |payer: &mut TestAccount, account: &mut TestAccount, session_id| {
+            let data = scratch_data(&[3, 0, 0], session_id);
+            process_instruction(&program_id, &[payer.info(), account.info()], &data)
+        };
+        let mut payer = TestAccount::payer();
+        let mut final_account = TestAccount::scratch(&program_id, SESSION_ID, Role::Final);
+        final_account.data[SCRATCH_HEADER_LEN..]
+            .copy_from_slice(Fp12Data::pack(&Fq12::one()).as_bytes());
+        easy_part1(&mut payer, &mut final_account, SESSION_ID).unwrap();
+
+        // another session's, another role's and another payer's accounts are rejected
+        assert_eq!(
+            easy_part1(&mut payer, &mut final_account, SESSION_ID + 1),
+            Err(ProgramError::InvalidSeeds)
+        );
+        let mut y0 = TestAccount::scratch(&program_id, SESSION_ID, Role::Y(0));
+        assert_eq!(
+            easy_part1(&mut payer, &mut y0, SESSION_ID),
+            Err(ProgramError::InvalidSeeds)
+        );
+        let mut other_payer = TestAccount::payer();
+        other_payer.key = Pubkey::new_unique();
+        assert_eq!(
+            easy_part1(&mut other_payer, &mut final_account, SESSION_ID),
+            Err(ProgramError::InvalidSeeds)
+        );
+
+        payer.is_signer = false;
+        assert_eq!(
+            easy_part1(&mut payer, &mut final_account, SESSION_ID),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+    }
+
+    #[test]
+    fn test_close_session() {
+        let program_id = Pubkey::new_unique();
+        let mut payer = TestAccount::payer();
+        let mut refund = TestAccount::new(&Pubkey::default(), 0);
+        refund.lamports = 1;
+        let mut gamma = TestAccount::scratch(&program_id, SESSION_ID, Role::Gamma);
+        let mut y16 = TestAccount::scratch(&program_id, SESSION_ID, Role::Y(16));
+        gamma.lamports = 10;
+        gamma.data = vec![7; gamma.data.len()];
+        y16.lamports = 100;
+        let roles = [Role::Gamma.to_u8(), Role::Y(16).to_u8()];
+        let data = scratch_data(&[&[26, 0, 0][..], &roles].concat(), SESSION_ID);
+
+        // every account must be in its role
+        let swapped = scratch_data(&[26, 0, 0, roles[1], roles[0]], SESSION_ID);
+        let infos = [payer.info(), refund.info(), gamma.info(), y16.info()];
+        assert_eq!(
+            process_instruction(&program_id, &infos, &swapped),
+            Err(ProgramError::InvalidSeeds)
+        );
+        process_instruction(&program_id, &infos, &data).unwrap();
+        assert_eq!((refund.lamports, gamma.lamports, y16.lamports), (111, 0, 0));
+        assert!(gamma.data.iter().all(|b| *b == 0));
+
+        // nor can a scratch account be its own refund account
+        let mut final_account = TestAccount::scratch(&program_id, SESSION_ID, Role::Final);
+        final_account.lamports = 5;
+        let mut same = TestAccount::scratch(&program_id, SESSION_ID, Role::Final);
+        let data = scratch_data(&[26, 0, 0, Role::Final.to_u8()], SESSION_ID);
+        let infos = [payer.info(), same.info(), final_account.info()];
+        assert_eq!(
+            process_instruction(&program_id, &infos, &data),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_roles_round_trip() {
+        for role in 0..ROLE_COUNT {
+            assert_eq!(Role::from_u8(role).unwrap().to_u8(), role);
+        }
+        assert_eq!(Role::from_u8(ROLE_COUNT), None);
+    }
+}
@@ -0,0 +1,158 @@
+use arrayref::{array_mut_ref, array_ref, mut_array_refs};
+use solana_program::account_info::AccountInfo;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+
+use crate::error::VerifierError;
+use crate::scratch::{Role, Scratch, SCRATCH_HEADER_LEN};
+
+// Guard against two clients driving the same scratch accounts at once, which would
+// otherwise interleave their writes into the accumulators undetected.
+//
+// With SESSION_FLAG set in `t`, an instruction takes a session account before its
+// regular accounts, and its data ends with the session nonce (u64, little-endian) and
+// the index of the step within the session (u32, little-endian). Step 0 starts a
+// session under a new nonce; every later step must carry the current nonce and the
+// next expected index. A concurrent writer that (re)starts the session therefore makes
+// the other writer's next step fail, instead of both silently continuing.
+//
+// The session account is the scratch account of role `Session` (see `scratch.rs`),
+// derived from the payer and session id of the step and created with the others. Only
+// steps running on scratch accounts can be guarded.
+//
+// session account layout:
+//   0..40   scratch header of role `Session`
+//   40..48  nonce of the current session (u64, little-endian)
+//   48..52  index of the next expected step (u32, little-endian)
+pub const SESSION_FLAG: usize = 0x80;
+pub const SESSION_DATA_LEN: usize = 12;
+pub const SESSION_SUFFIX_LEN: usize = 12;
+
+// Split the session suffix off the instruction input.
+pub fn split_suffix(input: &[u8]) -> Result<(&[u8], &[u8; SESSION_SUFFIX_LEN]), ProgramError> {
+    if input.len() < SESSION_SUFFIX_LEN {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (input, suffix) = input.split_at(input.len() - SESSION_SUFFIX_LEN);
+    Ok((input, array_ref![suffix, 0, SESSION_SUFFIX_LEN]))
+}
+
+// Check the step's session suffix against the session account of `scratch`, and advance
+// its progress.
+pub fn advance(
+    scratch: &Scratch,
+    session_account: &AccountInfo,
+    suffix: &[u8; SESSION_SUFFIX_LEN],
+) -> ProgramResult {
+    scratch.check_account(session_account, Role::Session)?;
+    let nonce = *array_ref![suffix, 0, 8];
+    let step = u32::from_le_bytes(*array_ref![suffix, 8, 4]);
+
+    let mut data = session_account.try_borrow_mut_data()?;
+    let dst = array_mut_ref![data, SCRATCH_HEADER_LEN, SESSION_DATA_LEN];
+    let (session_nonce, progress) = mut_array_refs![dst, 8, 4];
+    if step == 0 {
+        *session_nonce = nonce;
+    } else if *session_nonce != nonce || u32::from_le_bytes(*progress) != step {
+        return Err(VerifierError::SessionConflict.into());
+    }
+    *progress = (step + 1).to_le_bytes();
+    Ok(())
+}
+
+// Turn steps from `schedule::verification_steps` into the steps of a guarded session
+// under `nonce`, with the session account at index `session`.
+pub fn guard_steps(steps: &mut [(Vec<usize>, Vec<u8>)], session: usize, nonce: u64) {
+    for (index, (accounts, data)) in steps.iter_mut().enumerate() {
+        accounts.insert(0, session);
+        data[0] |= SESSION_FLAG as u8;
+        data.extend(nonce.to_le_bytes().iter());
+        data.extend((index as u32).to_le_bytes().iter());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use solana_program::pubkey::Pubkey;
+
+    use super::*;
+    use crate::schedule::{verification_steps, with_scratch, NUM_ACCOUNTS, PAYER};
+    use crate::scratch::RESET_SESSION;
+    use crate::test_utils::{run_step, scratch_data, session_accounts, TestAccount, SESSION_ID};
+
+    #[test]
+    fn test_interleaved_writers() {
+        let (proof_c, prepared_input, proof_ab) = circuit::initialize().unwrap();
+        let program_id = Pubkey::new_unique();
+        let mut accounts = session_accounts(&program_id);
+        accounts.push(TestAccount::scratch(&program_id, SESSION_ID, Role::Session));
+
+        let mut first = verification_steps(&proof_c, &prepared_input, &proof_ab);
+        with_scratch(&mut first, PAYER, SESSION_ID);
+        let mut second = first.clone();
+        guard_steps(&mut first, NUM_ACCOUNTS, 1);
+        guard_steps(&mut second, NUM_ACCOUNTS, 2);
+
+        // both writers start on the same accounts; the second one resets them and takes
+        // over
+        run_step(&program_id, &mut accounts, &first[0]).unwrap();
+        run_step(&program_id, &mut accounts, &first[1]).unwrap();
+        let roles: Vec<u8> = (0..PAYER as u8).collect();
+        let data = [&[26, RESET_SESSION, 0][..], &roles].concat();
+        let reset = (
+            std::iter::once(PAYER).chain(0..PAYER).collect(),
+            scratch_data(&data, SESSION_ID),
+        );
+        run_step(&program_id, &mut accounts, &reset).unwrap();
+        run_step(&program_id, &mut accounts, &second[0]).unwrap();
+        assert_eq!(
+            run_step(&program_id, &mut accounts, &first[2]),
+            Err(VerifierError::SessionConflict.into())
+        );
+        // skipping a step is rejected too
+        assert_eq!(
+            run_step(&program_id, &mut accounts, &second[2]),
+            Err(VerifierError::SessionConflict.into())
+        );
+
+        // the remaining writer completes, and the final step checks the result
+        for step in second[1..].iter() {
+            run_step(&program_id, &mut accounts, step).unwrap();
+        }
+    }
+
+    // The session account must be the session's own, set up by the program.
+    #[test]
+    fn test_session_account() {
+        let (proof_c, prepared_input, proof_ab) = circuit::initialize().unwrap();
+        let program_id = Pubkey::new_unique();
+        let mut accounts = session_accounts(&program_id);
+        let mut steps = verification_steps(&proof_c, &prepared_input, &proof_ab);
+        with_scratch(&mut steps, PAYER, SESSION_ID);
+        guard_steps(&mut steps, NUM_ACCOUNTS, 1);
+        let mut run = |session: TestAccount| {
+            accounts.truncate(NUM_ACCOUNTS);
+            accounts.push(session);
+            run_step(&program_id, &mut accounts, &steps[0])
+        };
+
+        // another session's
+        let other = TestAccount::scratch(&program_id, SESSION_ID + 1, Role::Session);
+        assert_eq!(run(other), Err(ProgramError::InvalidSeeds));
+        // an account at the session's address the program does not own
+        let mut foreign = TestAccount::scratch(&program_id, SESSION_ID, Role::Session);
+        foreign.owner = Pubkey::new_unique();
+        assert_eq!(run(foreign), Err(ProgramError::IncorrectProgramId));
+        // another role's header
+        let mut gamma = TestAccount::scratch(&program_id, SESSION_ID, Role::Session);
+        gamma.data[..SCRATCH_HEADER_LEN].copy_from_slice(&Role::Gamma.header());
+        assert_eq!(run(gamma), Err(VerifierError::InvalidAccountHeader.into()));
+        let session = TestAccount::scratch(&program_id, SESSION_ID, Role::Session);
+        run(session).unwrap();
+        let session = &accounts[NUM_ACCOUNTS].data[SCRATCH_HEADER_LEN..];
+        assert_eq!(
+            session,
+            [&1u64.to_le_bytes()[..], &1u32.to_le_bytes()].concat()
+        );
+    }
+}
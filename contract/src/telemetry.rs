@@ -0,0 +1,127 @@
+// On-chain compute telemetry, built with the `telemetry` feature. A scratch step whose
+// session id carries `TELEMETRY_FLAG` takes the session's telemetry account (role
+// `Telemetry`, see `scratch.rs`) right after the payer, checked like any scratch
+// account, and adds the compute units its handler consumed to it:
+//
+// telemetry account layout:
+//   0..40   scratch header
+//   40..48  number of steps recorded (u64, little-endian)
+//   48..56  cumulative compute units consumed by the handlers (u64, little-endian)
+//
+// The measurement brackets the handler only, so entrypoint deserialization and the
+// telemetry write itself are not counted. Without the feature the flag is refused, so
+// no step takes a telemetry account and recording does nothing.
+pub const TELEMETRY_DATA_LEN: usize = 16;
+
+pub use imp::{record, remaining_compute_units};
+
+#[cfg(feature = "telemetry")]
+mod imp {
+    use arrayref::{array_mut_ref, mut_array_refs};
+    use solana_program::account_info::AccountInfo;
+    use solana_program::compute_units::sol_remaining_compute_units;
+    use solana_program::entrypoint::ProgramResult;
+
+    use super::TELEMETRY_DATA_LEN;
+    use crate::scratch::{check_header, Role, SCRATCH_HEADER_LEN};
+
+    pub fn remaining_compute_units() -> u64 {
+        sol_remaining_compute_units()
+    }
+
+    pub fn record(account: &AccountInfo, start: u64) -> ProgramResult {
+        let consumed = start.saturating_sub(remaining_compute_units());
+        let mut data = account.try_borrow_mut_data()?;
+        check_header(&data, Some(Role::Telemetry))?;
+        let dst = array_mut_ref![data, SCRATCH_HEADER_LEN, TELEMETRY_DATA_LEN];
+        let (steps, compute_units) = mut_array_refs![dst, 8, 8];
+        *steps = (u64::from_le_bytes(*steps) + 1).to_le_bytes();
+        *compute_units = (u64::from_le_bytes(*compute_units) + consumed).to_le_bytes();
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "telemetry"))]
+mod imp {
+    use solana_program::account_info::AccountInfo;
+    use solana_program::entrypoint::ProgramResult;
+
+    pub fn remaining_compute_units() -> u64 {
+        0
+    }
+
+    pub fn record(_account: &AccountInfo, _start: u64) -> ProgramResult {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use solana_program::program_error::ProgramError;
+    use solana_program::pubkey::Pubkey;
+
+    use crate::process_instruction;
+    use crate::scratch::{Role, INIT_SCRATCH, TELEMETRY_FLAG};
+    use crate::test_utils::{scratch_data, TestAccount, SESSION_ID};
+    #[cfg(feature = "telemetry")]
+    use crate::{scratch::SCRATCH_HEADER_LEN, test_utils::install_stubs};
+
+    fn init_gamma(session_id: u64) -> Vec<u8> {
+        scratch_data(&[22, Role::Gamma.to_u8(), INIT_SCRATCH], session_id)
+    }
+
+    // A flagged step counts itself in the session's telemetry account, and only in that.
+    #[cfg(feature = "telemetry")]
+    #[test]
+    fn test_record_telemetry() {
+        install_stubs();
+        let program_id = Pubkey::new_unique();
+        let mut payer = TestAccount::payer();
+        let mut telemetry = TestAccount::scratch(&program_id, SESSION_ID, Role::Telemetry);
+        let mut gamma = TestAccount::scratch(&program_id, SESSION_ID, Role::Gamma);
+        let steps = |telemetry: &TestAccount| telemetry.data[SCRATCH_HEADER_LEN];
+
+        let data = init_gamma(SESSION_ID | TELEMETRY_FLAG);
+        let infos = [payer.info(), telemetry.info(), gamma.info()];
+        process_instruction(&program_id, &infos, &data).unwrap();
+        assert_eq!(steps(&telemetry), 1);
+        assert_eq!(gamma.data, Role::Gamma.initial_data());
+
+        // without the flag a trailing account is not written
+        let infos = [payer.info(), gamma.info(), telemetry.info()];
+        process_instruction(&program_id, &infos, &init_gamma(SESSION_ID)).unwrap();
+        assert_eq!(steps(&telemetry), 1);
+
+        // another account in the telemetry account's place is refused
+        let mut other = TestAccount::scratch(&program_id, SESSION_ID + 1, Role::Telemetry);
+        let infos = [payer.info(), other.info(), gamma.info()];
+        assert_eq!(
+            process_instruction(&program_id, &infos, &data),
+            Err(ProgramError::InvalidSeeds)
+        );
+        let infos = [payer.info(), gamma.info(), telemetry.info()];
+        assert_eq!(
+            process_instruction(&program_id, &infos, &data),
+            Err(ProgramError::InvalidSeeds)
+        );
+        assert_eq!(steps(&telemetry), 1);
+    }
+
+    #[cfg(not(feature = "telemetry"))]
+    #[test]
+    fn test_flag_refused() {
+        let program_id = Pubkey::new_unique();
+        let mut payer = TestAccount::payer();
+        let mut telemetry = TestAccount::scratch(&program_id, SESSION_ID, Role::Telemetry);
+        let mut gamma = TestAccount::scratch(&program_id, SESSION_ID, Role::Gamma);
+        let infos = [payer.info(), telemetry.info(), gamma.info()];
+        assert_eq!(
+            process_instruction(
+                &program_id,
+                &infos,
+                &init_gamma(SESSION_ID | TELEMETRY_FLAG)
+            ),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use std::cell::RefCell;
+use std::sync::Once;
+
+use ark_bn254::{Fq12, G1Affine, G2Affine};
+use ark_ec::AffineCurve;
+use ark_ff::to_bytes;
+use solana_program::account_info::AccountInfo;
+use solana_program::clock::Clock;
+use solana_program::entrypoint::{ProgramResult, SUCCESS};
+use solana_program::hash::hashv;
+use solana_program::instruction::Instruction;
+use solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
+
+use crate::header::{AccountKind, ACCOUNT_HEADER_LEN};
+use crate::miller_loop::{ab_tag, delta_tag};
+use crate::point::ENCODING_UNCOMPRESSED;
+use crate::process_instruction;
+use crate::public_inputs::G1_PROJECTIVE_DATA_LEN;
+use crate::pvk::{get_alpha_g1_beta_g2, VK_ID};
+use crate::result_log::{record_offset, result_log_address};
+use crate::schedule::{NUM_ACCOUNTS, PAYER};
+use crate::scratch::{scratch_address, write_tag, Role, SCRATCH_HEADER_LEN};
+use crate::utils::CyclotomicData;
+
+// In-memory account store used to drive instruction handlers without a validator.
+pub struct TestAccount {
+    pub key: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+impl TestAccount {
+    pub fn new(owner: &Pubkey, len: usize) -> TestAccount {
+        TestAccount {
+            key: Pubkey::new_unique(),
+            owner: *owner,
+            lamports: 0,
+            data: vec![0; len],
+            is_signer: false,
+            is_writable: true,
+        }
+    }
+
+    // The payer scratch accounts are derived from, always the same key.
+    pub fn payer() -> TestAccount {
+        let mut payer = TestAccount::new(&Pubkey::default(), 0);
+        payer.key = Pubkey::new_from_array([1; 32]);
+        payer.is_signer = true;
+        payer
+    }
+
+    // The test payer's result log with room for `capacity` records.
+    pub fn result_log(program_id: &Pubkey, capacity: usize) -> TestAccount {
+        let mut log = TestAccount::new(program_id, record_offset(capacity));
+        log.key = result_log_address(program_id, &TestAccount::payer().key).0;
+        log.data[..ACCOUNT_HEADER_LEN].copy_from_slice(&AccountKind::ResultLog.header());
+        log
+    }
+
+    // The `role` scratch account of the test payer's session `session_id`.
+    pub fn scratch(program_id: &Pubkey, session_id: u64, role: Role) -> TestAccount {
+        let mut account = TestAccount::new(program_id, role.data_len());
+        account.data = role.initial_data();
+        let payer = TestAccount::payer().key;
+        account.key = scratch_address(program_id, &payer, session_id, role).0;
+        account
+    }
+
+    pub fn info(&mut self) -> AccountInfo<'_> {
+        AccountInfo::new(
+            &self.key,
+            self.is_signer,
+            self.is_writable,
+            &mut self.lamports,
+            &mut self.data,
+            &self.owner,
+            false,
+            0,
+        )
+    }
+}
+
+// Session id of the scratch accounts the tests use unless they need several sessions.
+pub const SESSION_ID: u64 = 1;
+
+// Run a step on scratch accounts of session `SESSION_ID`: the test payer goes before
+// `accounts` and the session id after `data`.
+pub fn run(program_id: &Pubkey, accounts: &mut [&mut TestAccount], data: &[u8]) {
+    let mut payer = TestAccount::payer();
+    let mut infos = vec![payer.info()];
+    infos.extend(accounts.iter_mut().map(|account| account.info()));
+    process_instruction(program_id, &infos, &scratch_data(data, SESSION_ID)).unwrap();
+}
+
+pub fn scratch_data(data: &[u8], session_id: u64) -> Vec<u8> {
+    let mut data = data.to_vec();
+    data.extend(session_id.to_le_bytes().iter());
+    data
+}
+
+// A full set of verification accounts owned by `program_id`, indexed as in
+// `schedule::verification_steps`, for session `SESSION_ID`.
+pub fn session_accounts(program_id: &Pubkey) -> Vec<TestAccount> {
+    (0..NUM_ACCOUNTS)
+        .map(|index| match index {
+            PAYER => TestAccount::payer(),
+            role => {
+                TestAccount::scratch(program_id, SESSION_ID, Role::from_u8(role as u8).unwrap())
+            }
+        })
+        .collect()
+}
+
+pub fn run_step(
+    program_id: &Pubkey,
+    accounts: &mut [TestAccount],
+    step: &(Vec<usize>, Vec<u8>),
+) -> ProgramResult {
+    let mut infos = vec![];
+    // account indices within a step are distinct, so split borrows one by one
+    let mut remaining: Vec<Option<&mut TestAccount>> = accounts.iter_mut().map(Some).collect();
+    for &index in step.0.iter() {
+        infos.push(remaining[index].take().unwrap().info());
+    }
+    process_instruction(program_id, &infos, &step.1)
+}
+
+// Slot reported by the Clock sysvar once `install_stubs` has run.
+pub const SLOT: u64 = 42;
+
+thread_local! {
+    // `const` thread-local initializers need Rust 1.59
+    #[allow(clippy::missing_const_for_thread_local)]
+    static INVOKED: RefCell<Vec<Instruction>> = RefCell::new(Vec::new());
+    #[allow(clippy::missing_const_for_thread_local)]
+    static LOGS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    #[allow(clippy::missing_const_for_thread_local)]
+    static RETURN_DATA: RefCell<Option<Vec<u8>>> = RefCell::new(None);
+}
+
+// The program every return data set under the stubs is reported as coming from.
+pub const RETURNING_PROGRAM: Pubkey = Pubkey::new_from_array([2; 32]);
+
+// Syscall stubs are process-wide, so every test needing one shares this set.
+struct TestStubs;
+
+impl SyscallStubs for TestStubs {
+    fn sol_log(&self, message: &str) {
+        LOGS.with(|logs| logs.borrow_mut().push(message.to_string()));
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = Clock {
+            slot: SLOT,
+            ..Clock::default()
+        };
+        unsafe { *(var_addr as *mut Clock) = clock };
+        SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        SUCCESS
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        _account_infos: &[AccountInfo],
+        _signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        INVOKED.with(|invoked| invoked.borrow_mut().push(instruction.clone()));
+        Ok(())
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        RETURN_DATA.with(|returned| *returned.borrow_mut() = Some(data.to_vec()));
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        RETURN_DATA.with(|returned| {
+            returned
+                .borrow()
+                .clone()
+                .map(|data| (RETURNING_PROGRAM, data))
+        })
+    }
+}
+
+pub fn install_stubs() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        set_syscall_stubs(Box::new(TestStubs));
+    });
+}
+
+// Program log lines written so far on the current thread.
+pub fn logs() -> Vec<String> {
+    LOGS.with(|logs| logs.borrow().clone())
+}
+
+// Cross-program invocations made so far on the current thread.
+pub fn invoked() -> Vec<Instruction> {
+    INVOKED.with(|invoked| invoked.borrow().clone())
+}
+
+// y14 and y15 accounts of session `session_id` for the final step, with
+// y14 = alpha_g1_beta_g2 so the proof is valid exactly when y15 is one. Both are stored
+// compressed, so y15 must lie in the cyclotomic subgroup.
+pub fn final_step_accounts(
+    program_id: &Pubkey,
+    session_id: u64,
+    y15: Fq12,
+) -> (TestAccount, TestAccount) {
+    let mut y14_account = TestAccount::scratch(program_id, session_id, Role::Y(14));
+    let y14 = CyclotomicData::pack(&get_alpha_g1_beta_g2()).unwrap();
+    y14_account.data[SCRATCH_HEADER_LEN..].copy_from_slice(y14.as_bytes());
+    let mut y15_account = TestAccount::scratch(program_id, session_id, Role::Y(15));
+    let y15 = CyclotomicData::pack(&y15).unwrap();
+    y15_account.data[SCRATCH_HEADER_LEN..].copy_from_slice(y15.as_bytes());
+    (y14_account, y15_account)
+}
+
+// Nullifier of the inputs account `bound_inputs_account` makes.
+pub const NULLIFIER: [u8; 32] = [7; 32];
+
+// The data of a final step with `flags` attesting to inputs, its proof the generators
+// as A, B and C, uncompressed (see `hard_part_y16`), then `rest`.
+pub fn attesting_step(flags: u8, rest: &[u8]) -> Vec<u8> {
+    let (a, b) = (
+        G1Affine::prime_subgroup_generator(),
+        G2Affine::prime_subgroup_generator(),
+    );
+    let proof = to_bytes!(a, b, a).unwrap();
+    [&[16, flags, 0, ENCODING_UNCOMPRESSED][..], &proof, rest].concat()
+}
+
+// The inputs account of session `session_id`, as if folded against the baked key from
+// instruction data hashing to `inputs_hash` and with nullifier `NULLIFIER`, with y14 and
+// y15 tagged as the hard part of the proof of `attesting_step` checked against it (see
+// `public_inputs::bound_statement`).
+pub fn bound_inputs_account(
+    program_id: &Pubkey,
+    session_id: u64,
+    inputs_hash: [u8; 32],
+    y14_account: &mut TestAccount,
+    y15_account: &mut TestAccount,
+) -> TestAccount {
+    let mut inputs_account = TestAccount::scratch(program_id, session_id, Role::Inputs);
+    let statement = SCRATCH_HEADER_LEN + G1_PROJECTIVE_DATA_LEN;
+    inputs_account.data[statement..statement + 32].copy_from_slice(&inputs_hash);
+    inputs_account.data[statement + 32..statement + 64].copy_from_slice(&VK_ID);
+    inputs_account.data[statement + 64..statement + 96].copy_from_slice(&inputs_hash);
+    inputs_account.data[statement + 96..statement + 128].copy_from_slice(&NULLIFIER);
+    let inputs_tag = hashv(&[&VK_ID, &inputs_hash, &inputs_hash]).to_bytes();
+    write_tag(&inputs_account.info(), &inputs_tag).unwrap();
+    let (a, b) = (
+        G1Affine::prime_subgroup_generator(),
+        G2Affine::prime_subgroup_generator(),
+    );
+    let tag = hashv(&[&inputs_tag, &delta_tag(&VK_ID, &a), &ab_tag(&a, &b)]).to_bytes();
+    write_tag(&y14_account.info(), &tag).unwrap();
+    write_tag(&y15_account.info(), &tag).unwrap();
+    inputs_account
+}
+
+// A y15 failing the final check: an element of the cyclotomic subgroup other than one,
+// here alpha_g1_beta_g2 itself.
+pub fn invalid_y15() -> Fq12 {
+    get_alpha_g1_beta_g2()
+}
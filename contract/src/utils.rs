@@ -1,45 +1,376 @@
-use ark_bn254::Fq12Parameters;
-use ark_ff::{to_bytes, Fp12, Fp12ParamsWrapper, FromBytes, QuadExtField};
-use arrayref::{array_mut_ref, array_ref};
-use num_traits::One;
+use ark_bn254::{Fq, Fq12Parameters, Fq6, FqParameters};
+use ark_ff::{
+    BigInteger256, Field, Fp12ParamsWrapper, FpParameters, One, QuadExtField, QuadExtParameters,
+};
+use arrayref::array_ref;
+use bytemuck::{Pod, Zeroable};
 use solana_program::account_info::AccountInfo;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program::{invoke, invoke_signed};
 use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
+use solana_program::system_instruction;
+use solana_program::sysvar::Sysvar;
+
+use crate::curve::{Fq12, Fq2};
+use crate::error::VerifierError;
+use crate::pvk::VK_ID;
+use crate::scratch::{check_header, Role, SCRATCH_HEADER_LEN};
+use crate::vk::OnChainVk;
 
 pub const BN254_DATA_LEN: usize = 384;
+pub const CYCLOTOMIC_DATA_LEN: usize = 192;
+pub const FR_DATA_LEN: usize = 32;
+
+// Instruction indices are encoded as little-endian 16-bit words. Both the contract and
+// the client go through these helpers (see `StepIndex`), so the byte order is defined in
+// one place, also for indices wider than a byte.
+pub const INDEX_LEN: usize = 2;
 
-pub fn unpack_instruction_data(input: &[u8]) -> Result<(usize, usize, usize, &[u8]), ProgramError> {
-    let (&t, rest) = input
-        .split_first()
-        .ok_or(solana_program::program_error::INVALID_INSTRUCTION_DATA)?;
-    let (&i, rest) = rest
-        .split_first()
-        .ok_or(solana_program::program_error::INVALID_INSTRUCTION_DATA)?;
-    let (&j, rest) = rest
-        .split_first()
-        .ok_or(solana_program::program_error::INVALID_INSTRUCTION_DATA)?;
-    Ok((t as usize, i as usize, j as usize, rest))
+pub fn pack_index(index: u16) -> [u8; INDEX_LEN] {
+    index.to_le_bytes()
 }
 
-pub fn get_account_data(
+pub fn unpack_index(input: &[u8]) -> Result<(u16, &[u8]), ProgramError> {
+    if input.len() < INDEX_LEN {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (index, rest) = input.split_at(INDEX_LEN);
+    Ok((u16::from_le_bytes(*array_ref![index, 0, INDEX_LEN]), rest))
+}
+
+// Scratch accounts hold a verification's intermediate results between steps. Each must
+// be owned by this program, so a step can neither be pointed at someone else's account
+// nor read data another program planted, be writable, and hold at least `len` bytes.
+// Checked, along with the account's address (see `scratch.rs`), before the account is
+// read or written.
+pub fn check_scratch_account(
+    program_id: &Pubkey,
     account: &AccountInfo,
-    j: usize,
-) -> QuadExtField<Fp12ParamsWrapper<Fq12Parameters>> {
-    let f = match j {
-        0 => Fp12::<Fq12Parameters>::one(),
-        _ => {
-            let src = account.try_borrow_data().unwrap();
-            let src = array_ref![src, 0, 384];
-            Fp12::<Fq12Parameters>::read(&mut src.as_ref()).unwrap()
+    len: usize,
+) -> ProgramResult {
+    if account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !account.is_writable {
+        return Err(VerifierError::ReadOnlyAccount.into());
+    }
+    if account.data_len() < len {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    Ok(())
+}
+
+// An Fp12 accumulator as scratch accounts hold it: the Montgomery limbs of its twelve
+// base-field elements, in arkworks' order (c0.c0.c0, c0.c0.c1, c0.c1.c0, ..), each as
+// four little-endian u64. Unlike the `ToBytes` encoding, reading and writing it is a
+// plain copy of account memory, with no conversion of each element out of or into
+// Montgomery form and no heap allocation; arkworks' field types have no fixed layout,
+// so the arithmetic itself still runs on a copy.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct Fp12Data {
+    limbs: [[u64; 4]; 12],
+}
+
+impl Fp12Data {
+    pub fn pack(f: &Fq12) -> Fp12Data {
+        let fq6 = |c: &Fq6| [c.c0.c0, c.c0.c1, c.c1.c0, c.c1.c1, c.c2.c0, c.c2.c1];
+        let elements = [fq6(&f.c0), fq6(&f.c1)];
+        let mut limbs = [[0; 4]; 12];
+        for (limbs, element) in limbs.iter_mut().zip(elements.iter().flatten()) {
+            *limbs = (element.0).0;
+        }
+        Fp12Data { limbs }
+    }
+
+    // The accumulator, `None` if a limb set is not a reduced field element.
+    pub fn unpack(&self) -> Option<Fq12> {
+        let mut elements = [Fq::default(); 12];
+        for (element, limbs) in elements.iter_mut().zip(self.limbs.iter()) {
+            let repr = BigInteger256(*limbs);
+            if repr >= FqParameters::MODULUS {
+                return None;
+            }
+            *element = Fq::new(repr);
+        }
+        let fq2 = |k: usize| Fq2::new(elements[k], elements[k + 1]);
+        let fq6 = |k: usize| Fq6::new(fq2(k), fq2(k + 2), fq2(k + 4));
+        Some(Fq12::new(fq6(0), fq6(6)))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::bytes_of(self)
+    }
+}
+
+// An Fp12 accumulator of the cyclotomic subgroup, which every value of the hard part
+// lies in, stored in half the space of `Fp12Data` by its torus (T2) compression: for
+// f = g + h·w with g² - v·h² = 1, the Fp6 element c = h / (1 + g), limbs in the same
+// order. Decompressing gives g = (1 + c²v) / (1 - c²v) and h = 2c / (1 - c²v), where
+// 1 - c²v is never zero as v is no square in Fp6. 1 + g is zero only for f = -1, which
+// the subgroup, of odd order, does not contain, and one compresses to zero, so zeroed
+// data is a one accumulator. Every reduced c decompresses to an element of norm one;
+// each read and write costs an Fp6 inversion.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct CyclotomicData {
+    limbs: [[u64; 4]; 6],
+}
+
+impl CyclotomicData {
+    // The compression of `f`, `None` for f = -1 and other elements outside the
+    // subgroup's torus representation.
+    pub fn pack(f: &Fq12) -> Option<CyclotomicData> {
+        let c = f.c1 * (Fq6::one() + f.c0).inverse()?;
+        let elements = [c.c0.c0, c.c0.c1, c.c1.c0, c.c1.c1, c.c2.c0, c.c2.c1];
+        let mut limbs = [[0; 4]; 6];
+        for (limbs, element) in limbs.iter_mut().zip(elements.iter()) {
+            *limbs = (element.0).0;
+        }
+        Some(CyclotomicData { limbs })
+    }
+
+    // The accumulator, `None` if a limb set is not a reduced field element.
+    pub fn unpack(&self) -> Option<Fq12> {
+        let mut elements = [Fq::default(); 6];
+        for (element, limbs) in elements.iter_mut().zip(self.limbs.iter()) {
+            let repr = BigInteger256(*limbs);
+            if repr >= FqParameters::MODULUS {
+                return None;
+            }
+            *element = Fq::new(repr);
         }
+        let fq2 = |k: usize| Fq2::new(elements[k], elements[k + 1]);
+        let c = Fq6::new(fq2(0), fq2(2), fq2(4));
+        let c2v = Fp12ParamsWrapper::<Fq12Parameters>::mul_base_field_by_nonresidue(&c.square());
+        let d = (Fq6::one() - c2v).inverse()?;
+        Some(Fq12::new((Fq6::one() + c2v) * d, c.double() * d))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::bytes_of(self)
+    }
+}
+
+// The role of the scratch account holding `data`, once its header is checked and its
+// accumulator found to fit.
+fn accumulator_role(data: &[u8]) -> Result<Role, ProgramError> {
+    check_header(data, None)?;
+    let role = Role::from_u8(data[5]).ok_or(VerifierError::InvalidAccountHeader)?;
+    if data.len() < SCRATCH_HEADER_LEN + role.accumulator_len() {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    Ok(role)
+}
+
+// Read the accumulator of a scratch account, compressed or not as its role stores it.
+pub fn get_account_data(
+    account: &AccountInfo,
+) -> Result<QuadExtField<Fp12ParamsWrapper<Fq12Parameters>>, ProgramError> {
+    read_accumulator(&account.try_borrow_data()?).map(|(_, f)| f)
+}
+
+// The role and accumulator of scratch account data, as `get_account_data` reads them;
+// clients inspecting a session off-chain read the accounts' data with it.
+pub fn read_accumulator(data: &[u8]) -> Result<(Role, Fq12), ProgramError> {
+    let role = accumulator_role(data)?;
+    let src = &data[SCRATCH_HEADER_LEN..SCRATCH_HEADER_LEN + role.accumulator_len()];
+    let f = if role.is_compressed() {
+        bytemuck::pod_read_unaligned::<CyclotomicData>(src).unpack()
+    } else {
+        bytemuck::pod_read_unaligned::<Fp12Data>(src).unpack()
     };
-    f
+    let f = f.ok_or(VerifierError::InvalidAccumulator)?;
+    Ok((role, f))
 }
 
 pub fn put_account_data(
     account: &AccountInfo,
     f: &QuadExtField<Fp12ParamsWrapper<Fq12Parameters>>,
-) {
-    let mut dst = account.try_borrow_mut_data().unwrap();
-    let dst = array_mut_ref![dst, 0, BN254_DATA_LEN];
-    dst.copy_from_slice(to_bytes!(f).unwrap().as_slice());
+) -> ProgramResult {
+    let mut dst = account.try_borrow_mut_data()?;
+    let role = accumulator_role(&dst)?;
+    let dst = &mut dst[SCRATCH_HEADER_LEN..SCRATCH_HEADER_LEN + role.accumulator_len()];
+    if role.is_compressed() {
+        let data = CyclotomicData::pack(f).ok_or(VerifierError::InvalidAccumulator)?;
+        dst.copy_from_slice(data.as_bytes());
+    } else {
+        dst.copy_from_slice(Fp12Data::pack(f).as_bytes());
+    }
+    Ok(())
+}
+
+// Id of a verifying key in results, nullifiers and receipts: the sha256 of the whole
+// key in the on-chain layout (see `vk.rs`), the VK account's or, without one, the baked
+// key's. Keys sharing alpha_g1_beta_g2 but not gamma, delta or the IC points, which a
+// key made up to accept forged proofs would, get ids of their own.
+pub fn vk_id(vk: Option<&OnChainVk>) -> [u8; 32] {
+    vk.map_or(VK_ID, |vk| vk.id())
+}
+
+// Create the program-derived `account` with `seeds` (bump last), `len` bytes and owned
+// by the program, at the payer's expense. Anyone can send lamports to the address
+// beforehand, which `create_account` would refuse, so a funded address is topped up,
+// allocated and assigned instead.
+pub fn create_program_account<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    seeds: &[&[u8]],
+    len: usize,
+) -> ProgramResult {
+    let accounts = [payer.clone(), account.clone(), system_program.clone()];
+    let lamports = Rent::get()?.minimum_balance(len);
+    if account.lamports() == 0 {
+        let instruction = system_instruction::create_account(
+            payer.key,
+            account.key,
+            lamports,
+            len as u64,
+            program_id,
+        );
+        return invoke_signed(&instruction, &accounts, &[seeds]);
+    }
+    let missing = lamports.saturating_sub(account.lamports());
+    if missing > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, missing),
+            &accounts,
+        )?;
+    }
+    let allocate = system_instruction::allocate(account.key, len as u64);
+    invoke_signed(&allocate, &accounts[1..], &[seeds])?;
+    let assign = system_instruction::assign(account.key, program_id);
+    invoke_signed(&assign, &accounts[1..], &[seeds])
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ff::One;
+
+    use super::*;
+    use crate::process_instruction;
+    use crate::scratch::{Role, SCRATCH_HEADER_LEN};
+    use crate::test_utils::{scratch_data, TestAccount, SESSION_ID};
+
+    // Accumulators round-trip through their limbs, which are the Montgomery form, and
+    // limbs outside the field are rejected.
+    #[test]
+    fn test_fp12_data() {
+        use ark_ff::UniformRand;
+        use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+        let f = Fq12::rand(&mut StdRng::seed_from_u64(7));
+        let data = Fp12Data::pack(&f);
+        assert_eq!(data.unpack(), Some(f));
+        assert_eq!(data.as_bytes().len(), BN254_DATA_LEN);
+        let one = Fp12Data::pack(&Fq12::one());
+        assert_eq!(one.limbs[0], FqParameters::R.0);
+        assert_eq!(one.as_bytes()[..8], FqParameters::R.0[0].to_le_bytes());
+
+        let mut bytes = data.as_bytes().to_vec();
+        bytes[352..].copy_from_slice(&[0xff; 32]);
+        assert_eq!(
+            bytemuck::pod_read_unaligned::<Fp12Data>(&bytes).unpack(),
+            None
+        );
+    }
+
+    // Elements of the cyclotomic subgroup round-trip through their compression, one
+    // compresses to zeros, and -1, outside the subgroup, has no compression.
+    #[test]
+    fn test_cyclotomic_data() {
+        use ark_ff::UniformRand;
+        use ark_std::rand::{rngs::StdRng, SeedableRng};
+
+        // the easy part of the final exponentiation maps into the subgroup
+        let f = Fq12::rand(&mut StdRng::seed_from_u64(8));
+        let mut f1 = f;
+        f1.conjugate();
+        let mut r = f1 * f.inverse().unwrap();
+        let f2 = r;
+        r.frobenius_map(2);
+        r *= &f2;
+
+        let data = CyclotomicData::pack(&r).unwrap();
+        assert_eq!(data.as_bytes().len(), CYCLOTOMIC_DATA_LEN);
+        assert_eq!(data.unpack(), Some(r));
+        let one = CyclotomicData::pack(&Fq12::one()).unwrap();
+        assert!(one.as_bytes().iter().all(|b| *b == 0));
+        assert_eq!(one.unpack(), Some(Fq12::one()));
+        assert!(CyclotomicData::pack(&-Fq12::one()).is_none());
+
+        let mut bytes = data.as_bytes().to_vec();
+        bytes[160..].copy_from_slice(&[0xff; 32]);
+        assert_eq!(
+            bytemuck::pod_read_unaligned::<CyclotomicData>(&bytes).unpack(),
+            None
+        );
+    }
+
+    // Initial data reads as a one accumulator in every accumulator role, and data
+    // without a valid header does not read at all.
+    #[test]
+    fn test_read_accumulator() {
+        for role in [Role::Gamma, Role::Final, Role::Y(0), Role::Y(16)].iter() {
+            let data = role.initial_data();
+            assert_eq!(read_accumulator(&data), Ok((*role, Fq12::one())));
+        }
+        let mut data = Role::Delta.initial_data();
+        data[4] -= 1;
+        assert_eq!(
+            read_accumulator(&data).unwrap_err(),
+            VerifierError::InvalidAccountHeader.into()
+        );
+    }
+
+    #[test]
+    fn test_index_is_little_endian() {
+        let packed = pack_index(0x0102);
+        assert_eq!(packed, [0x02, 0x01]);
+
+        let mut data = packed.to_vec();
+        data.push(7);
+        let (index, rest) = unpack_index(&data).unwrap();
+        assert_eq!(index, 0x0102);
+        assert_eq!(rest, &[7]);
+        assert!(unpack_index(&[1]).is_err());
+    }
+
+    // Steps only touch accounts of this program, writable and large enough.
+    #[test]
+    fn test_scratch_account_checks() {
+        let program_id = Pubkey::new_unique();
+        let mut account = TestAccount::scratch(&program_id, SESSION_ID, Role::Final);
+        account.data[SCRATCH_HEADER_LEN..].copy_from_slice(Fp12Data::pack(&Fq12::one()).as_bytes());
+        let easy_part1 = |account: &mut TestAccount| {
+            let mut payer = TestAccount::payer();
+            let data = scratch_data(&[3, 0, 0], SESSION_ID);
+            process_instruction(&program_id, &[payer.info(), account.info()], &data)
+        };
+        easy_part1(&mut account).unwrap();
+
+        account.owner = Pubkey::new_unique();
+        assert_eq!(
+            easy_part1(&mut account),
+            Err(ProgramError::IncorrectProgramId)
+        );
+        account.owner = program_id;
+        account.is_writable = false;
+        assert_eq!(
+            easy_part1(&mut account),
+            Err(VerifierError::ReadOnlyAccount.into())
+        );
+        account.is_writable = true;
+        account
+            .data
+            .truncate(SCRATCH_HEADER_LEN + BN254_DATA_LEN - 1);
+        assert_eq!(
+            easy_part1(&mut account),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+    }
 }
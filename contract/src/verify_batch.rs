@@ -0,0 +1,198 @@
+use ark_bn254::{Bn254, Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{Field, One, PrimeField, Zero};
+use arrayref::array_ref;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::hash::hashv;
+use solana_program::program_error::ProgramError;
+
+use crate::error::VerifierError;
+use crate::miller_loop::{multi_miller_loop, CoeffSource};
+use crate::point::PointReader;
+use crate::public_inputs::{fold, instruction_inputs};
+use crate::pvk::{get_alpha_g1_beta_g2, get_ic, NUM_PUBLIC_INPUTS};
+use crate::utils::FR_DATA_LEN;
+use crate::vk::OnChainVk;
+
+// Several proofs against the same verifying key in one instruction (t = 25), checked
+// with a single multi-pairing. Each proof i gets a 128-bit scalar r_i and the program
+// checks the random linear combination of the proofs' equations
+//
+//   prod e(r_i * A_i, B_i) == e(alpha, beta)^sum(r_i) * e(sum(r_i * P_i), gamma) *
+//                             e(sum(r_i * C_i), delta)
+//
+// where P_i is proof i's prepared input, so k proofs cost k + 2 Miller loops and one
+// final exponentiation instead of 3k and k. The scalars are the leading 16 bytes
+// (little-endian) of sha256(BATCH_DOMAIN || instruction data || i as u32 little-endian),
+// fixed once the proofs are, so a batch holding an invalid proof passes only with
+// probability about 2^-128.
+//
+// instruction data: encoding (see `point.rs`) || k >= 1 proofs, each as in
+//                   `verify_complete.rs` without the encoding byte: proof.a ||
+//                   proof.b || proof.c || public inputs
+// accounts: none, or the VK account when sent with `VK_ACCOUNT_FLAG`
+//
+// A failed check fails with `VerifierError::InvalidProof` without telling which proof
+// is invalid; clients verify the proofs one by one to find out.
+pub const BATCH_DOMAIN: &[u8] = b"groth16-sol-verifier batch";
+
+// The batch with its scalars, and the proofs' public inputs combined under them:
+// sum(r_i * x_ij) for every input j.
+pub struct Batch {
+    pub proofs: Vec<(G1Affine, G2Affine, G1Affine)>,
+    pub scalars: Vec<Fr>,
+    pub public_inputs: Vec<Fr>,
+}
+
+impl Batch {
+    pub fn scalar_sum(&self) -> Fr {
+        self.scalars.iter().sum()
+    }
+
+    // sum(r_i * P_i) = sum(r_i) * IC_0 + sum(sum(r_i * x_ij) * IC_j+1), folded once.
+    fn prepared_input<F>(&self, ic: F) -> Result<G1Projective, ProgramError>
+    where
+        F: Fn(usize) -> Result<G1Affine, ProgramError>,
+    {
+        let ic0 = ic(0)?;
+        Ok(fold(ic, &self.public_inputs)? + ic0.mul((self.scalar_sum() - Fr::one()).into_repr()))
+    }
+}
+
+pub fn read_batch(input: &[u8], num_public_inputs: usize) -> Result<Batch, ProgramError> {
+    let points = PointReader::new(input)?;
+    let encoding = points.encoding();
+    let proofs = points.rest();
+    let entry_len = encoding.proof_len() + num_public_inputs * FR_DATA_LEN;
+    let entries = proofs.chunks_exact(entry_len);
+    if proofs.is_empty() || !entries.remainder().is_empty() {
+        return Err(VerifierError::InvalidDataLength.into());
+    }
+    let mut batch = Batch {
+        proofs: vec![],
+        scalars: vec![],
+        public_inputs: vec![Fr::zero(); num_public_inputs],
+    };
+    for (i, entry) in entries.enumerate() {
+        let mut points = PointReader::with_encoding(encoding, entry);
+        let a = points.g1()?;
+        let b = points.g2()?;
+        let c = points.g1()?;
+        let r = batch_scalar(input, i);
+        for (sum, x) in batch
+            .public_inputs
+            .iter_mut()
+            .zip(instruction_inputs(points.rest(), num_public_inputs)?)
+        {
+            *sum += r * x;
+        }
+        batch.proofs.push((a, b, c));
+        batch.scalars.push(r);
+    }
+    Ok(batch)
+}
+
+pub fn batch_scalar(input: &[u8], i: usize) -> Fr {
+    let digest = hashv(&[BATCH_DOMAIN, input, &(i as u32).to_le_bytes()]);
+    Fr::from(u128::from_le_bytes(*array_ref![digest.as_ref(), 0, 16]))
+}
+
+pub fn verify_batch(vk: Option<&OnChainVk>, input: &[u8]) -> ProgramResult {
+    let num_public_inputs = vk.map_or(NUM_PUBLIC_INPUTS, |vk| vk.num_public_inputs);
+    let batch = read_batch(input, num_public_inputs)?;
+
+    #[cfg(feature = "alt-bn128")]
+    {
+        if vk.is_none() {
+            return crate::alt_bn128::verify_batch(&batch);
+        }
+    }
+
+    let (prepared_input, source, alpha_g1_beta_g2) = match vk {
+        Some(vk) => (
+            batch.prepared_input(|i| vk.ic(i))?,
+            CoeffSource::Account(vk),
+            vk.alpha_g1_beta_g2()?,
+        ),
+        None => (
+            batch.prepared_input(|i| Ok(get_ic(i)))?,
+            CoeffSource::Baked,
+            get_alpha_g1_beta_g2(),
+        ),
+    };
+    let mut c = G1Projective::zero();
+    let mut ab = Vec::with_capacity(batch.proofs.len());
+    for ((a, b, proof_c), r) in batch.proofs.iter().zip(batch.scalars.iter()) {
+        let r = r.into_repr();
+        c += proof_c.mul(r);
+        ab.push((a.mul(r).into_affine(), *b));
+    }
+    let f = multi_miller_loop(&ab, &prepared_input.into_affine(), &c.into_affine(), source)?;
+    let expected = alpha_g1_beta_g2.pow(batch.scalar_sum().into_repr());
+    if Bn254::final_exponentiation(&f) != Some(expected) {
+        return Err(VerifierError::InvalidProof.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use circuit::PointEncoding::{Compressed, Uncompressed};
+    use solana_program::pubkey::Pubkey;
+
+    use super::*;
+    use crate::test_utils::TestAccount;
+    use crate::vk::parse_on_chain_vk;
+
+    #[test]
+    fn test_verify_batch() {
+        let (pvk, proofs) = circuit::demo_batch(3).unwrap();
+        let input = circuit::batch_submission(&proofs, Uncompressed);
+        verify_batch(None, &input).unwrap();
+        verify_batch(None, &circuit::batch_submission(&proofs[..1], Uncompressed)).unwrap();
+        verify_batch(None, &circuit::batch_submission(&proofs, Compressed)).unwrap();
+
+        let mut data = vec![25, 0, 0];
+        data.extend(input.iter());
+        crate::process_instruction(&Pubkey::default(), &[], &data).unwrap();
+
+        // one wrong public input fails the whole batch
+        let mut wrong = proofs.clone();
+        wrong[1].1[0] += Fr::one();
+        assert_eq!(
+            verify_batch(None, &circuit::batch_submission(&wrong, Compressed)),
+            Err(VerifierError::InvalidProof.into())
+        );
+        // as does swapping the Cs of two proofs, which a sum without scalars accepts
+        let mut swapped = proofs.clone();
+        let c = swapped[0].0.c;
+        swapped[0].0.c = swapped[1].0.c;
+        swapped[1].0.c = c;
+        assert_eq!(
+            verify_batch(None, &circuit::batch_submission(&swapped, Uncompressed)),
+            Err(VerifierError::InvalidProof.into())
+        );
+        assert_eq!(
+            verify_batch(None, &input[..input.len() - 1]),
+            Err(VerifierError::InvalidDataLength.into())
+        );
+        assert_eq!(
+            verify_batch(None, &[]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(
+            verify_batch(None, &[0]),
+            Err(VerifierError::InvalidDataLength.into())
+        );
+
+        // against a VK account
+        let program_id = Pubkey::new_unique();
+        let mut vk_account = TestAccount::new(&program_id, 0);
+        vk_account.data = circuit::to_on_chain_vk(&pvk.vk).unwrap();
+        let vk = parse_on_chain_vk(&vk_account.data).unwrap();
+        verify_batch(Some(&vk), &input).unwrap();
+        let mut data = vec![25 | 0x20, 0, 0];
+        data.extend(input.iter());
+        crate::process_instruction(&program_id, &[vk_account.info()], &data).unwrap();
+    }
+}
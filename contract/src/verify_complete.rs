@@ -0,0 +1,145 @@
+use std::slice::Iter;
+
+use ark_bn254::{Bn254, Fr, G1Affine, G2Affine};
+use ark_ec::{PairingEngine, ProjectiveCurve};
+use solana_program::account_info::AccountInfo;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use crate::error::VerifierError;
+use crate::miller_loop::{multi_miller_loop, CoeffSource};
+use crate::point::PointReader;
+use crate::public_inputs::{fold, instruction_inputs};
+use crate::pvk::{get_alpha_g1_beta_g2, get_ic, NUM_PUBLIC_INPUTS};
+use crate::receipt::{statement_hash, write_receipt, RECEIPT_FLAG};
+use crate::utils::vk_id;
+use crate::vk::OnChainVk;
+
+// The whole verification in one instruction (t = 24), for clusters whose compute budget
+// covers a full pairing or, built with the `alt-bn128` feature, that expose the pairing
+// syscalls. Integrators with simple needs send this instead of the chunked steps.
+//
+// instruction data: encoding (see `point.rs`) || proof.a (G1Affine) || proof.b
+//                   (G2Affine) || proof.c (G1Affine) || public inputs (32 bytes each,
+//                   little-endian, below r)
+// accounts: none, or the VK account when sent with `VK_ACCOUNT_FLAG`; with
+//           `RECEIPT_FLAG` in `i`, then the payer, the receipt account and the system
+//           program (see `receipt.rs`)
+//
+// The public inputs are folded against the IC points of the baked key or the VK
+// account, exactly `num_public_inputs` of them. Against the baked key, builds with the
+// `alt-bn128` feature fold and pair through the syscalls (see `alt_bn128.rs`); otherwise
+// the program runs the three Miller loops in one pass and the final exponentiation
+// itself. An invalid proof fails with `VerifierError::InvalidProof`.
+pub fn verify_complete<'a>(
+    program_id: &Pubkey,
+    accounts_iter: &mut Iter<AccountInfo<'a>>,
+    vk: Option<&OnChainVk>,
+    flags: u8,
+    input: &[u8],
+) -> ProgramResult {
+    if flags & !RECEIPT_FLAG != 0 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let mut points = PointReader::new(input)?;
+    let a = points.g1()?;
+    let b = points.g2()?;
+    let c = points.g1()?;
+    let num_public_inputs = vk.map_or(NUM_PUBLIC_INPUTS, |vk| vk.num_public_inputs);
+    let public_inputs = instruction_inputs(points.rest(), num_public_inputs)?;
+    check_proof(vk, &a, &b, &c, &public_inputs)?;
+
+    if flags & RECEIPT_FLAG != 0 {
+        let statement = statement_hash(&a, &b, &c, &public_inputs, &vk_id(vk));
+        write_receipt(program_id, accounts_iter, &statement)?;
+    }
+    Ok(())
+}
+
+fn check_proof(
+    vk: Option<&OnChainVk>,
+    a: &G1Affine,
+    b: &G2Affine,
+    c: &G1Affine,
+    public_inputs: &[Fr],
+) -> ProgramResult {
+    #[cfg(feature = "alt-bn128")]
+    {
+        if vk.is_none() {
+            return crate::alt_bn128::verify_complete(a, b, c, public_inputs);
+        }
+    }
+
+    let (prepared_input, source, alpha_g1_beta_g2) = match vk {
+        Some(vk) => (
+            fold(|i| vk.ic(i), public_inputs)?,
+            CoeffSource::Account(vk),
+            vk.alpha_g1_beta_g2()?,
+        ),
+        None => (
+            fold(|i| Ok(get_ic(i)), public_inputs)?,
+            CoeffSource::Baked,
+            get_alpha_g1_beta_g2(),
+        ),
+    };
+    let f = multi_miller_loop(&[(*a, *b)], &prepared_input.into_affine(), c, source)?;
+    if Bn254::final_exponentiation(&f) != Some(alpha_g1_beta_g2) {
+        return Err(VerifierError::InvalidProof.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use ark_bn254::Fr;
+    use circuit::PointEncoding;
+
+    use super::*;
+    use crate::test_utils::TestAccount;
+    use crate::vk::parse_on_chain_vk;
+
+    fn verify(vk: Option<&OnChainVk>, input: &[u8]) -> ProgramResult {
+        verify_complete(&Pubkey::default(), &mut [].iter(), vk, 0, input)
+    }
+
+    #[test]
+    fn test_verify_complete() {
+        let (pvk, proof, public_inputs) = circuit::demo_proof().unwrap();
+        let input =
+            circuit::complete_submission(&proof, &public_inputs, PointEncoding::Uncompressed);
+        verify(None, &input).unwrap();
+        let compressed =
+            circuit::complete_submission(&proof, &public_inputs, PointEncoding::Compressed);
+        assert_eq!(
+            compressed.len(),
+            1 + 32 + 64 + 32 + 32 * public_inputs.len()
+        );
+        verify(None, &compressed).unwrap();
+
+        let mut data = vec![24, 0, 0];
+        data.extend(input.iter());
+        crate::process_instruction(&Pubkey::default(), &[], &data).unwrap();
+
+        let wrong =
+            circuit::complete_submission(&proof, &[Fr::from(1u64)], PointEncoding::Compressed);
+        assert_eq!(
+            verify(None, &wrong),
+            Err(VerifierError::InvalidProof.into())
+        );
+        assert_eq!(
+            verify(None, &input[..input.len() - 1]),
+            Err(VerifierError::InvalidDataLength.into())
+        );
+
+        // against a VK account
+        let program_id = Pubkey::new_unique();
+        let mut vk_account = TestAccount::new(&program_id, 0);
+        vk_account.data = circuit::to_on_chain_vk(&pvk.vk).unwrap();
+        let vk = parse_on_chain_vk(&vk_account.data).unwrap();
+        verify(Some(&vk), &input).unwrap();
+        let mut data = vec![24 | 0x20, 0, 0];
+        data.extend(input.iter());
+        crate::process_instruction(&program_id, &[vk_account.info()], &data).unwrap();
+    }
+}
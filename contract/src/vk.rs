@@ -0,0 +1,496 @@
+use std::ops::Range;
+use std::slice::Iter;
+
+use ark_bn254::{Fq12Parameters, Fq2Parameters, G1Affine};
+use ark_ff::{Fp12, Fp2, FromBytes};
+use arrayref::array_ref;
+use solana_program::account_info::{next_account_info, AccountInfo};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::hash::hashv;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+use crate::error::VerifierError;
+use crate::utils::BN254_DATA_LEN;
+
+// On-chain verifying key layout. All integers are little-endian, all field and group
+// elements use the arkworks `ToBytes` encoding (canonical form, little-endian), the same
+// encoding the scratch accounts use.
+//
+//   offset              size            field
+//   0                   4               num_public_inputs (u32)
+//   4                   4               num_coeffs, ell coefficients per G2 table (u32)
+//   8                   384             alpha_g1_beta_g2 (Fp12)
+//   392                 num_coeffs*192  -gamma_g2 prepared ell coefficients
+//   392+num_coeffs*192  num_coeffs*192  -delta_g2 prepared ell coefficients
+//   392+num_coeffs*384  (n+1)*65        gamma_abc_g1 (IC) points, constant term first
+//
+// Each ell coefficient is three Fp2 elements (c0, c1, c2) of 64 bytes each, each IC
+// point is an affine G1 point: x (32), y (32), infinity flag (1).
+// `circuit::to_on_chain_vk` produces this layout from an arkworks `VerifyingKey`.
+pub const VK_HEADER_LEN: usize = 8;
+pub const FP2_DATA_LEN: usize = 64;
+pub const ELL_COEFF_DATA_LEN: usize = 3 * FP2_DATA_LEN;
+pub const G1_AFFINE_DATA_LEN: usize = 65;
+
+pub const fn on_chain_vk_len(num_public_inputs: usize, num_coeffs: usize) -> usize {
+    VK_HEADER_LEN
+        + BN254_DATA_LEN
+        + 2 * num_coeffs * ELL_COEFF_DATA_LEN
+        + (num_public_inputs + 1) * G1_AFFINE_DATA_LEN
+}
+
+// A validated view over an on-chain verifying key. Elements are decoded on access, so
+// the key never has to be copied onto the program heap as a whole.
+#[derive(Clone, Copy, Debug)]
+pub struct OnChainVk<'a> {
+    data: &'a [u8],
+    // sha256 of `data`, when a finalized locked upload stores it
+    hash: Option<[u8; 32]>,
+    pub num_public_inputs: usize,
+    pub num_coeffs: usize,
+}
+
+pub fn parse_on_chain_vk(data: &[u8]) -> Result<OnChainVk<'_>, ProgramError> {
+    if data.len() < VK_HEADER_LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let num_public_inputs = read_u32(&data[0..4]) as usize;
+    let num_coeffs = read_u32(&data[4..8]) as usize;
+    if data.len() != on_chain_vk_len(num_public_inputs, num_coeffs) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(OnChainVk {
+        data,
+        hash: None,
+        num_public_inputs,
+        num_coeffs,
+    })
+}
+
+// Set in `t` when a step reads its verifying key from a VK account instead of the
+// constants baked into `pvk.rs`, so one deployment verifies proofs for any circuit. The
+// VK account comes right after the session account, if any, and before the step's own
+// accounts. Miller-loop steps take their ell coefficients from it, the final step
+// alpha_g1_beta_g2, folds of public inputs the IC points and whole verifications all of
+// them; no other step accepts the flag.
+pub const VK_ACCOUNT_FLAG: usize = 0x20;
+
+// A key is larger than a transaction, so it is uploaded into its VK account in chunks,
+// with instruction 20 and the operation in `i`. The VK account is created by the client
+// and owned by this program.
+//
+// `WRITE_VK` writes the bare layout above, `on_chain_vk_len` bytes, under the VK
+// account's own keypair; the key can no longer change once the keypair is discarded.
+//
+// instruction data: offset (u32, little-endian) || bytes
+// accounts: [vk account (writable, signer)]
+//
+// The locked upload instead prefixes the key with a header naming an upload authority
+// and ends with a hash check, after which the key is read-only for good:
+//   INIT_VK_ACCOUNT  (empty), writes the header
+//     accounts: [vk account (writable, signer), authority (signer)]
+//   WRITE_VK_CHUNK   offset into the key (u32, little-endian) || bytes
+//     accounts: [vk account (writable), authority (signer)]
+//   FINALIZE_VK      sha256 of the key (32 bytes); the key must match it and be well
+//                    formed, fails with `VkHashMismatch` otherwise
+//     accounts: [vk account (writable), authority (signer)]
+// Writes to a finalized key fail with `VkLocked`, and steps reject a key that is not
+// finalized yet. The bare upload refuses accounts with a header.
+//
+// locked VK account layout:
+//   0..4    `VK_MAGIC`
+//   4..5    state, `VK_WRITING` or `VK_FINALIZED`
+//   5..37   upload authority
+//   37..69  sha256 of the key, once finalized
+//   69..    the key in the layout above
+pub const WRITE_VK: u8 = 0;
+pub const INIT_VK_ACCOUNT: u8 = 1;
+pub const WRITE_VK_CHUNK: u8 = 2;
+pub const FINALIZE_VK: u8 = 3;
+
+pub const VK_MAGIC: &[u8; 4] = b"G16V";
+pub const VK_WRITING: u8 = 0;
+pub const VK_FINALIZED: u8 = 1;
+pub const VK_ACCOUNT_HEADER_LEN: usize = 69;
+
+pub fn process_write_vk(
+    program_id: &Pubkey,
+    accounts_iter: &mut Iter<AccountInfo>,
+    operation: u8,
+    input: &[u8],
+) -> ProgramResult {
+    match operation {
+        WRITE_VK => write_vk(program_id, accounts_iter, input),
+        INIT_VK_ACCOUNT => init_vk_account(program_id, accounts_iter, input),
+        WRITE_VK_CHUNK => write_vk_chunk(program_id, accounts_iter, input),
+        FINALIZE_VK => finalize_vk(program_id, accounts_iter, input),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+// The key in a VK account's data, behind the header of a locked upload if it has one.
+pub fn read_vk_account(data: &[u8]) -> Result<OnChainVk<'_>, ProgramError> {
+    if !data.starts_with(VK_MAGIC) {
+        return parse_on_chain_vk(data);
+    }
+    if data.len() < VK_ACCOUNT_HEADER_LEN || data[4] != VK_FINALIZED {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    let mut vk = parse_on_chain_vk(&data[VK_ACCOUNT_HEADER_LEN..])?;
+    vk.hash = Some(*array_ref![data, 37, 32]);
+    Ok(vk)
+}
+
+fn write_vk(
+    program_id: &Pubkey,
+    accounts_iter: &mut Iter<AccountInfo>,
+    input: &[u8],
+) -> ProgramResult {
+    let vk_account = next_account_info(accounts_iter)?;
+    if vk_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !vk_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if input.len() < 4 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (offset, bytes) = input.split_at(4);
+    let offset = read_u32(offset) as usize;
+
+    let mut data = vk_account.try_borrow_mut_data()?;
+    if data.starts_with(VK_MAGIC) {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+    if data.len() < offset + bytes.len() {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    Ok(())
+}
+
+fn init_vk_account(
+    program_id: &Pubkey,
+    accounts_iter: &mut Iter<AccountInfo>,
+    input: &[u8],
+) -> ProgramResult {
+    let vk_account = next_account_info(accounts_iter)?;
+    let authority = next_account_info(accounts_iter)?;
+    if vk_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !vk_account.is_signer || !authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !input.is_empty() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let mut data = vk_account.try_borrow_mut_data()?;
+    if data.starts_with(VK_MAGIC) {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+    if data.len() < VK_ACCOUNT_HEADER_LEN {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    data[..4].copy_from_slice(VK_MAGIC);
+    data[4] = VK_WRITING;
+    data[5..37].copy_from_slice(authority.key.as_ref());
+    data[37..VK_ACCOUNT_HEADER_LEN].copy_from_slice(&[0; 32]);
+    Ok(())
+}
+
+fn write_vk_chunk(
+    program_id: &Pubkey,
+    accounts_iter: &mut Iter<AccountInfo>,
+    input: &[u8],
+) -> ProgramResult {
+    let vk_account = next_account_info(accounts_iter)?;
+    let authority = next_account_info(accounts_iter)?;
+    if input.len() < 4 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (offset, bytes) = input.split_at(4);
+    let offset = VK_ACCOUNT_HEADER_LEN + read_u32(offset) as usize;
+
+    let mut data = vk_account.try_borrow_mut_data()?;
+    check_writing(program_id, vk_account, &data, authority)?;
+    if data.len() < offset + bytes.len() {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    data[offset..offset + bytes.len()].copy_from_slice(bytes);
+    Ok(())
+}
+
+fn finalize_vk(
+    program_id: &Pubkey,
+    accounts_iter: &mut Iter<AccountInfo>,
+    input: &[u8],
+) -> ProgramResult {
+    let vk_account = next_account_info(accounts_iter)?;
+    let authority = next_account_info(accounts_iter)?;
+    if input.len() != 32 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let mut data = vk_account.try_borrow_mut_data()?;
+    check_writing(program_id, vk_account, &data, authority)?;
+    let key = &data[VK_ACCOUNT_HEADER_LEN..];
+    if hashv(&[key]).to_bytes()[..] != *input {
+        return Err(VerifierError::VkHashMismatch.into());
+    }
+    parse_on_chain_vk(key)?;
+    data[4] = VK_FINALIZED;
+    data[37..VK_ACCOUNT_HEADER_LEN].copy_from_slice(input);
+    Ok(())
+}
+
+// Whether the locked upload in `vk_account` may still be written by `authority`.
+pub fn check_writing(
+    program_id: &Pubkey,
+    vk_account: &AccountInfo,
+    data: &[u8],
+    authority: &AccountInfo,
+) -> ProgramResult {
+    if vk_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !data.starts_with(VK_MAGIC) || data.len() < VK_ACCOUNT_HEADER_LEN {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if !authority.is_signer || data[5..37] != authority.key.as_ref()[..] {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if data[4] != VK_WRITING {
+        return Err(VerifierError::VkLocked.into());
+    }
+    Ok(())
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(bytes);
+    u32::from_le_bytes(buf)
+}
+
+impl<'a> OnChainVk<'a> {
+    fn gamma_offset(&self) -> usize {
+        VK_HEADER_LEN + BN254_DATA_LEN
+    }
+
+    fn delta_offset(&self) -> usize {
+        self.gamma_offset() + self.num_coeffs * ELL_COEFF_DATA_LEN
+    }
+
+    fn ic_offset(&self) -> usize {
+        self.delta_offset() + self.num_coeffs * ELL_COEFF_DATA_LEN
+    }
+
+    // The key's id, sha256 of the whole key in the layout above (see `utils::vk_id`).
+    // A locked upload stored it when it was finalized; a bare one is hashed on every
+    // call, by every Miller-loop step on the key too (see `scratch.rs`).
+    pub fn id(&self) -> [u8; 32] {
+        self.hash.unwrap_or_else(|| hashv(&[self.data]).to_bytes())
+    }
+
+    // Where the gamma and delta tables lie in the key, see `g2_prepare.rs`.
+    pub fn tables(&self) -> Range<usize> {
+        self.gamma_offset()..self.ic_offset()
+    }
+
+    pub fn alpha_g1_beta_g2(&self) -> Result<Fp12<Fq12Parameters>, ProgramError> {
+        read(&self.data[VK_HEADER_LEN..VK_HEADER_LEN + BN254_DATA_LEN])
+    }
+
+    // `quad_index` selects c0, c1 or c2 of the `ell_offset`-th coefficient, mirroring
+    // `pvk::get_gamma_qef`.
+    pub fn gamma_qef(
+        &self,
+        ell_offset: usize,
+        quad_index: usize,
+    ) -> Result<Fp2<Fq2Parameters>, ProgramError> {
+        self.qef(self.gamma_offset(), ell_offset, quad_index)
+    }
+
+    pub fn delta_qef(
+        &self,
+        ell_offset: usize,
+        quad_index: usize,
+    ) -> Result<Fp2<Fq2Parameters>, ProgramError> {
+        self.qef(self.delta_offset(), ell_offset, quad_index)
+    }
+
+    fn qef(
+        &self,
+        table: usize,
+        ell_offset: usize,
+        quad_index: usize,
+    ) -> Result<Fp2<Fq2Parameters>, ProgramError> {
+        if ell_offset >= self.num_coeffs || quad_index >= 3 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let offset = table + ell_offset * ELL_COEFF_DATA_LEN + quad_index * FP2_DATA_LEN;
+        read(&self.data[offset..offset + FP2_DATA_LEN])
+    }
+
+    // The i-th IC point, `gamma_abc_g1[i]`.
+    pub fn ic(&self, i: usize) -> Result<G1Affine, ProgramError> {
+        if i > self.num_public_inputs {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let offset = self.ic_offset() + i * G1_AFFINE_DATA_LEN;
+        read(&self.data[offset..offset + G1_AFFINE_DATA_LEN])
+    }
+}
+
+fn read<T: FromBytes>(mut bytes: &[u8]) -> Result<T, ProgramError> {
+    T::read(&mut bytes).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::process_instruction;
+    use crate::pvk::{get_alpha_g1_beta_g2, get_gamma_qef};
+    use crate::test_utils::TestAccount;
+
+    #[test]
+    fn test_on_chain_vk_round_trip() {
+        let (pvk, _, _) = circuit::demo_proof().unwrap();
+        let data = circuit::to_on_chain_vk(&pvk.vk).unwrap();
+        let vk = parse_on_chain_vk(&data).unwrap();
+
+        assert_eq!(vk.num_public_inputs, 1);
+        assert_eq!(vk.num_coeffs, pvk.gamma_g2_neg_pc.ell_coeffs.len());
+        assert_eq!(vk.alpha_g1_beta_g2().unwrap(), pvk.alpha_g1_beta_g2);
+        for (j, coeffs) in pvk.gamma_g2_neg_pc.ell_coeffs.iter().enumerate() {
+            assert_eq!(vk.gamma_qef(j, 0).unwrap(), coeffs.0);
+            assert_eq!(vk.gamma_qef(j, 1).unwrap(), coeffs.1);
+            assert_eq!(vk.gamma_qef(j, 2).unwrap(), coeffs.2);
+        }
+        for (j, coeffs) in pvk.delta_g2_neg_pc.ell_coeffs.iter().enumerate() {
+            assert_eq!(vk.delta_qef(j, 0).unwrap(), coeffs.0);
+            assert_eq!(vk.delta_qef(j, 1).unwrap(), coeffs.1);
+            assert_eq!(vk.delta_qef(j, 2).unwrap(), coeffs.2);
+        }
+        for (i, point) in pvk.vk.gamma_abc_g1.iter().enumerate() {
+            assert_eq!(vk.ic(i).unwrap(), *point);
+        }
+        assert!(vk.ic(2).is_err());
+
+        // the demo key is the one baked into pvk.rs
+        assert_eq!(vk.gamma_qef(3, 1).unwrap(), get_gamma_qef(3, 1));
+        assert_eq!(vk.alpha_g1_beta_g2().unwrap(), get_alpha_g1_beta_g2());
+
+        assert!(parse_on_chain_vk(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_write_vk_in_chunks() {
+        let (pvk, _, _) = circuit::demo_proof().unwrap();
+        let data = circuit::to_on_chain_vk(&pvk.vk).unwrap();
+        let program_id = Pubkey::new_unique();
+        let mut vk_account = TestAccount::new(&program_id, data.len());
+
+        let write = |vk_account: &mut TestAccount, offset: usize, chunk: &[u8]| {
+            let mut input = vec![20, 0, 0];
+            input.extend((offset as u32).to_le_bytes().iter());
+            input.extend(chunk.iter());
+            process_instruction(&program_id, &[vk_account.info()], &input)
+        };
+
+        // only the VK account's keypair may write it
+        assert_eq!(
+            write(&mut vk_account, 0, &data[..900]),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        vk_account.is_signer = true;
+        for (k, chunk) in data.chunks(900).enumerate() {
+            write(&mut vk_account, k * 900, chunk).unwrap();
+        }
+        assert_eq!(vk_account.data, data);
+        assert!(write(&mut vk_account, data.len() - 1, &[0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_locked_vk_upload() {
+        let (pvk, _, _) = circuit::demo_proof().unwrap();
+        let data = circuit::to_on_chain_vk(&pvk.vk).unwrap();
+        let hash = hashv(&[&data]).to_bytes();
+        let program_id = Pubkey::new_unique();
+        let mut vk_account = TestAccount::new(&program_id, VK_ACCOUNT_HEADER_LEN + data.len());
+        let mut authority = TestAccount::payer();
+
+        let upload = |vk_account: &mut TestAccount,
+                      authority: &mut TestAccount,
+                      operation: u8,
+                      input: &[u8]| {
+            let mut data = vec![20, operation, 0];
+            data.extend(input.iter());
+            let infos = [vk_account.info(), authority.info()];
+            process_instruction(&program_id, &infos, &data)
+        };
+        let chunk = |offset: usize, bytes: &[u8]| {
+            let mut input = (offset as u32).to_le_bytes().to_vec();
+            input.extend(bytes.iter());
+            input
+        };
+
+        // the VK account's keypair starts the upload, the authority writes it
+        assert_eq!(
+            upload(&mut vk_account, &mut authority, INIT_VK_ACCOUNT, &[]),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        vk_account.is_signer = true;
+        upload(&mut vk_account, &mut authority, INIT_VK_ACCOUNT, &[]).unwrap();
+        assert_eq!(&vk_account.data[5..37], authority.key.as_ref());
+        assert_eq!(
+            upload(&mut vk_account, &mut authority, INIT_VK_ACCOUNT, &[]),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+        vk_account.is_signer = false;
+        for (k, bytes) in data.chunks(900).enumerate() {
+            let input = chunk(k * 900, bytes);
+            upload(&mut vk_account, &mut authority, WRITE_VK_CHUNK, &input).unwrap();
+        }
+        let mut other = TestAccount::new(&Pubkey::default(), 0);
+        other.is_signer = true;
+        assert_eq!(
+            upload(&mut vk_account, &mut other, WRITE_VK_CHUNK, &chunk(0, &[1])),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        assert_eq!(
+            read_vk_account(&vk_account.data).err(),
+            Some(ProgramError::UninitializedAccount)
+        );
+
+        // the key must hash to what the authority finalizes it with
+        assert_eq!(
+            upload(&mut vk_account, &mut authority, FINALIZE_VK, &[0; 32]),
+            Err(VerifierError::VkHashMismatch.into())
+        );
+        upload(&mut vk_account, &mut authority, FINALIZE_VK, &hash).unwrap();
+        let vk = read_vk_account(&vk_account.data).unwrap();
+        assert_eq!(vk.alpha_g1_beta_g2().unwrap(), pvk.alpha_g1_beta_g2);
+        assert_eq!(&vk_account.data[37..VK_ACCOUNT_HEADER_LEN], &hash);
+
+        // and then it is locked, the bare upload included
+        assert_eq!(
+            upload(
+                &mut vk_account,
+                &mut authority,
+                WRITE_VK_CHUNK,
+                &chunk(0, &[1])
+            ),
+            Err(VerifierError::VkLocked.into())
+        );
+        vk_account.is_signer = true;
+        let input = [&[20, WRITE_VK, 0][..], &chunk(0, &[0; 4])[..]].concat();
+        assert_eq!(
+            process_instruction(&program_id, &[vk_account.info()], &input),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+        assert!(read_vk_account(&vk_account.data).is_ok());
+    }
+}
@@ -0,0 +1,33 @@
+// What the chunked Miller-loop and final-exponentiation pipelines know about the curve.
+// The steps themselves only walk these digits and apply `ell`, so the step count, the
+// (i, j) schedule and the coefficient indices all follow from the constants of the
+// curve module the build selects: BN254 by default, BLS12-381 with the `bls12-381`
+// feature. The hard-part chain differs too, see `final_exponentiation`.
+#[cfg(feature = "bls12-381")]
+mod bls12_381;
+#[cfg(not(feature = "bls12-381"))]
+mod bn254;
+
+#[cfg(feature = "bls12-381")]
+pub use self::bls12_381::*;
+#[cfg(not(feature = "bls12-381"))]
+pub use self::bn254::*;
+
+pub type EllCoeff = (Fq2, Fq2, Fq2);
+
+// Index j of the last Miller-loop step, the number of ell coefficients the steps before
+// it consume: one per digit and one more per non-zero digit. The last step takes
+// `LAST_STEP_LINES` more.
+pub const MILLER_LOOP_LAST_STEP: usize = last_step(ATE_LOOP_COUNT);
+
+const fn last_step(digits: &[i8]) -> usize {
+    let mut j = 0;
+    let mut i = 1;
+    while i < digits.len() {
+        j += if digits[i - 1] == 0 { 1 } else { 2 };
+        i += 1;
+    }
+    j
+}
+
+pub const X_NAF_LAST_STEP: usize = X_NAF.len() - 1;
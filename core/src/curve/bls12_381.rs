@@ -0,0 +1,75 @@
+pub use ark_bls12_381::g2::Parameters as G2Parameters;
+pub use ark_bls12_381::{Bls12_381 as Engine, Fq, G1Affine, G2Affine};
+use ark_bls12_381::{Fq12Parameters, Fq2Parameters, Parameters};
+use ark_ec::bls12::Bls12Parameters;
+use ark_ff::{Fp12, Fp2};
+
+use super::EllCoeff;
+
+pub type Fq12 = Fp12<Fq12Parameters>;
+pub type Fq2 = Fp2<Fq2Parameters>;
+
+const X: u64 = Parameters::X[0];
+
+// The bits of |x|, least significant first. Miller-loop step i (from `len - 1` down to
+// 1) handles bit i - 1; step 0 applies no lines, the loop ending in a conjugation
+// instead, x being negative.
+pub const ATE_LOOP_COUNT: &[i8] = &bits(true);
+pub const X_IS_NEGATIVE: bool = Parameters::X_IS_NEGATIVE;
+pub const LAST_STEP_LINES: usize = 0;
+
+// The bits of |x|, most significant first, one hard-part exponentiation step each.
+pub const X_NAF: [i8; 64] = bits(false);
+
+const fn bits(lsb_first: bool) -> [i8; 64] {
+    let mut bits = [0; 64];
+    let mut i = 0;
+    while i < 64 {
+        let bit = if lsb_first { i } else { 63 - i };
+        bits[i] = ((X >> bit) & 1) as i8;
+        i += 1;
+    }
+    bits
+}
+
+// Multiply the accumulator by the line with coefficients `coeffs` evaluated at `p`.
+// BLS12-381 has an M-type twist: p.y scales c2, p.x scales c1.
+pub fn ell(f: &mut Fq12, coeffs: &EllCoeff, p: &G1Affine) {
+    let c0 = coeffs.0;
+    let mut c1 = coeffs.1;
+    let mut c2 = coeffs.2;
+
+    c2.mul_assign_by_fp(&p.y);
+    c1.mul_assign_by_fp(&p.x);
+    f.mul_by_014(&c0, &c1, &c2);
+}
+
+// The coefficients of a line, given in D-type order, in the order `ell` reads them.
+pub fn line(c0: Fq2, c1: Fq2, c2: Fq2) -> EllCoeff {
+    (c2, c1, c0)
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ec::bls12::G2Prepared;
+    use ark_ec::AffineCurve;
+
+    use super::super::{MILLER_LOOP_LAST_STEP, X_NAF_LAST_STEP};
+    use super::*;
+
+    #[test]
+    fn test_loop_constants() {
+        let q = G2Prepared::<Parameters>::from(G2Affine::prime_subgroup_generator());
+        assert_eq!(MILLER_LOOP_LAST_STEP + LAST_STEP_LINES, q.ell_coeffs.len());
+        assert_eq!(X_NAF_LAST_STEP, 63);
+
+        // both digit lists spell |x|
+        let x = X_NAF.iter().fold(0u64, |x, &bit| 2 * x + bit as u64);
+        let y = ATE_LOOP_COUNT
+            .iter()
+            .rev()
+            .fold(0u64, |x, &bit| 2 * x + bit as u64);
+        assert_eq!((x, y), (X, X));
+        assert_eq!(ATE_LOOP_COUNT.last(), Some(&1));
+    }
+}
@@ -0,0 +1,71 @@
+pub use ark_bn254::g2::Parameters as G2Parameters;
+pub use ark_bn254::{Bn254 as Engine, Fq, G1Affine, G2Affine};
+use ark_bn254::{Fq12Parameters, Fq2Parameters, Parameters};
+use ark_ec::bn::BnParameters;
+use ark_ff::{Field, Fp12, Fp2};
+
+use super::EllCoeff;
+
+pub type Fq12 = Fp12<Fq12Parameters>;
+pub type Fq2 = Fp2<Fq2Parameters>;
+
+// Signed digits of the ATE loop count, least significant first. Miller-loop step i
+// (from `len - 1` down to 1) handles digit i - 1; step 0 applies the two lines of the
+// Frobenius images of Q.
+pub const ATE_LOOP_COUNT: &[i8] = Parameters::ATE_LOOP_COUNT;
+pub const X_IS_NEGATIVE: bool = Parameters::X_IS_NEGATIVE;
+pub const LAST_STEP_LINES: usize = 2;
+
+// Signed digits of |x|, most significant first, one hard-part exponentiation step each.
+pub const X_NAF: [i8; 63] = [
+    1, 0, 0, 0, 1, 0, 1, 0, 0, -1, 0, 1, 0, 1, 0, -1, 0, 0, 1, 0, 1, 0, -1, 0, -1, 0, -1, 0, 1, 0,
+    0, 0, 1, 0, 0, 1, 0, 1, 0, 1, 0, -1, 0, 1, 0, 0, 1, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, -1, 0, 0,
+    0, 1,
+];
+
+// Multiply the accumulator by the line with coefficients `coeffs` evaluated at `p`.
+// BN254 has a D-type twist: p.y scales c0, p.x scales c1.
+pub fn ell(f: &mut Fq12, coeffs: &EllCoeff, p: &G1Affine) {
+    let mut c0 = coeffs.0;
+    let mut c1 = coeffs.1;
+    let c2 = coeffs.2;
+
+    c0.mul_assign_by_fp(&p.y);
+    c1.mul_assign_by_fp(&p.x);
+    f.mul_by_034(&c0, &c1, &c2);
+}
+
+// The coefficients of a line, given in D-type order, in the order `ell` reads them.
+pub fn line(c0: Fq2, c1: Fq2, c2: Fq2) -> EllCoeff {
+    (c0, c1, c2)
+}
+
+// The Frobenius image of a point on the twist.
+pub fn mul_by_char(q: G2Affine) -> G2Affine {
+    let mut s = q;
+    s.x.frobenius_map(1);
+    s.x *= &Parameters::TWIST_MUL_BY_Q_X;
+    s.y.frobenius_map(1);
+    s.y *= &Parameters::TWIST_MUL_BY_Q_Y;
+    s
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ec::bn::G2Prepared;
+    use ark_ec::AffineCurve;
+
+    use super::super::{MILLER_LOOP_LAST_STEP, X_NAF_LAST_STEP};
+    use super::*;
+
+    #[test]
+    fn test_loop_constants() {
+        let q = G2Prepared::<Parameters>::from(G2Affine::prime_subgroup_generator());
+        assert_eq!(MILLER_LOOP_LAST_STEP + LAST_STEP_LINES, q.ell_coeffs.len());
+        assert_eq!(X_NAF_LAST_STEP, 62);
+
+        // the NAF digits add up to |x|
+        let x = X_NAF.iter().fold(0i64, |x, &digit| 2 * x + digit as i64);
+        assert_eq!(&[x as u64], Parameters::X);
+    }
+}
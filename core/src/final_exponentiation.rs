@@ -0,0 +1,108 @@
+use core::ops::Range;
+
+use ark_ff::Field;
+
+use crate::curve::{Fq12, X_NAF};
+
+// The stages of the final exponentiation f^((p^12 - 1) / r), each what one chunked step
+// computes from the values it reads.
+//
+// Easy part: f^((p^6 - 1)(p^2 + 1)), following e.g. Beuchat et al. page 9 as
+// (conj(f) * f^-1)^(p^2 + 1), in `easy_part1` and `easy_part2`.
+//
+// Hard part: a chain of products, Frobenius maps and exponentiations by x that differs
+// per curve, see `bn254.rs` and `bls12_381.rs`. The exponentiations by x take one step
+// per digit of `X_NAF`, or a few, see `exp_by_neg_x`.
+#[cfg(feature = "bls12-381")]
+mod bls12_381;
+#[cfg(not(feature = "bls12-381"))]
+mod bn254;
+
+#[cfg(feature = "bls12-381")]
+pub use self::bls12_381::*;
+#[cfg(not(feature = "bls12-381"))]
+pub use self::bn254::*;
+
+// conj(f) * f^-1 = f^(p^6 - 1), `None` for f = 0.
+pub fn easy_part1(f: &Fq12) -> Option<Fq12> {
+    let mut f1 = *f;
+    f1.conjugate();
+    Some(f1 * f.inverse()?)
+}
+
+// f^(p^2 + 1)
+pub fn easy_part2(f: &Fq12) -> Fq12 {
+    let mut r = *f;
+    r.frobenius_map(2);
+    r * f
+}
+
+// The digits `digits` of the exponentiation of `f` by -|x|, continuing from the partial
+// result `res` (one before the first digit): the step that reaches the last digit
+// conjugates the power of |x|. That is f^-x on BN254 and f^x on BLS12-381.
+pub fn exp_by_neg_x(f: &Fq12, mut res: Fq12, digits: Range<usize>) -> Fq12 {
+    let last = digits.end == X_NAF.len();
+    let mut inverse = *f;
+    inverse.conjugate();
+    for j in digits {
+        if j > 0 {
+            res.square_in_place();
+        }
+        match X_NAF[j] {
+            0 => {}
+            digit if digit > 0 => res *= f,
+            _ => res *= &inverse,
+        }
+    }
+    if last {
+        res.conjugate();
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+    use ark_ff::{One, Zero};
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+    use crate::curve::{Engine, G1Affine, G2Affine};
+    use crate::miller_loop::miller_loop;
+
+    #[test]
+    fn test_final_exponentiation_matches_arkworks() {
+        let f = Fq12::rand(&mut test_rng());
+        assert_eq!(final_exponentiation(&f), Engine::final_exponentiation(&f));
+        assert_eq!(final_exponentiation(&Fq12::zero()), None);
+    }
+
+    // The steps compute pairings: e(aP, Q) * e(-P, aQ) = 1
+    #[test]
+    fn test_pairing_check() {
+        let rng = &mut test_rng();
+        let a = <Engine as PairingEngine>::Fr::rand(rng);
+        let p = G1Affine::prime_subgroup_generator();
+        let q = G2Affine::prime_subgroup_generator();
+        let f =
+            miller_loop(&p.mul(a).into_affine(), &q) * miller_loop(&-p, &q.mul(a).into_affine());
+        assert_eq!(final_exponentiation(&f), Some(Fq12::one()));
+        let f = miller_loop(&p, &q);
+        assert_ne!(final_exponentiation(&f), Some(Fq12::one()));
+    }
+
+    // An exponentiation split into steps of a few digits ends where the one-step one does.
+    #[test]
+    fn test_exp_by_neg_x_in_steps() {
+        let f = Fq12::rand(&mut test_rng());
+        let whole = exp_by_neg_x(&f, Fq12::one(), 0..X_NAF.len());
+        let mut res = Fq12::one();
+        let mut start = 0;
+        while start < X_NAF.len() {
+            let end = (start + 10).min(X_NAF.len());
+            res = exp_by_neg_x(&f, res, start..end);
+            start = end;
+        }
+        assert_eq!(res, whole);
+    }
+}
@@ -0,0 +1,57 @@
+// The BLS12-381 hard part, following Hayashida et al. "Efficient final exponentiation
+// via cyclotomic structure for pairings over families of elliptic curves" as arkworks
+// and gnark run it: the result of the easy part r raised to 3 * (p^4 - p^2 + 1) / r, as
+//
+//   a = r^(x - 1), b = a^(x - 1), c = b^(x + p), e = c^(x^2 + p^2 - 1), result = r^3 * e
+//
+// with five exponentiations by x: r^x, a^x, b^x, c^x and (c^x)^x.
+use ark_ff::{Field, One};
+
+use super::{easy_part1, easy_part2, exp_by_neg_x};
+use crate::curve::{Fq12, X_NAF};
+
+// a = r^x * conj(r), from rx = r^x
+pub fn a(r: &Fq12, rx: &Fq12) -> Fq12 {
+    let mut r = *r;
+    r.conjugate();
+    *rx * r
+}
+
+// b = a^x * conj(a), from ax = a^x
+pub fn b(a: &Fq12, ax: &Fq12) -> Fq12 {
+    let mut a = *a;
+    a.conjugate();
+    *ax * a
+}
+
+// c = b^p * b^x, from bx = b^x
+pub fn c(b: &Fq12, bx: &Fq12) -> Fq12 {
+    let mut b = *b;
+    b.frobenius_map(1);
+    b * bx
+}
+
+// e = conj(c) * c^(x^2) * c^(p^2), from cxx = (c^x)^x
+pub fn e(c: &Fq12, cxx: &Fq12) -> Fq12 {
+    let (mut c1, mut c2) = (*c, *c);
+    c1.conjugate();
+    c2.frobenius_map(2);
+    c1 * cxx * c2
+}
+
+// r^3 * e, the result
+pub fn result(r: &Fq12, e: &Fq12) -> Fq12 {
+    r.cyclotomic_square() * r * e
+}
+
+// The whole final exponentiation of `f`, stage by stage as chunked steps would run it;
+// `None` for f = 0.
+pub fn final_exponentiation(f: &Fq12) -> Option<Fq12> {
+    let exp_by_x = |f: &Fq12| exp_by_neg_x(f, Fq12::one(), 0..X_NAF.len());
+    let r = easy_part2(&easy_part1(f)?);
+    let a = a(&r, &exp_by_x(&r));
+    let b = b(&a, &exp_by_x(&a));
+    let c = c(&b, &exp_by_x(&b));
+    let e = e(&c, &exp_by_x(&exp_by_x(&c)));
+    Some(result(&r, &e))
+}
@@ -0,0 +1,93 @@
+// The BN254 hard part, following Laura Fuentes-Castaneda et al. "Faster hashing to G2":
+// the result of the easy part r raised to
+//
+//   q^3 * (12z^3 + 6z^2 + 4z - 1) + q^2 * (12z^3 + 6z^2 + 6z) +
+//   q   * (12z^3 + 6z^2 + 4z)     + 1   * (12z^3 + 12z^2 + 6z + 1)
+//
+// which equals r^(2z * (6z^2 + 3z + 1) * (q^4 - q^2 + 1) / r), as the chain y0 .. y16
+// below, y0, y4 and y6 being exponentiations by x.
+use ark_ff::{Field, One};
+
+use super::{easy_part1, easy_part2, exp_by_neg_x};
+use crate::curve::{Fq12, X_NAF};
+
+// y1 = y0^2
+pub fn y1(y0: &Fq12) -> Fq12 {
+    y0.cyclotomic_square()
+}
+
+// y3 = y0^6
+pub fn y3(y0: &Fq12) -> Fq12 {
+    let y1 = y0.cyclotomic_square();
+    y1.cyclotomic_square() * y1
+}
+
+// y5 = y4^2, the base of y6 = y5^x
+pub fn y5(y4: &Fq12) -> Fq12 {
+    y4.cyclotomic_square()
+}
+
+// y8 = conj(y3) * y4 * conj(y6)
+pub fn y8(y3: &Fq12, y4: &Fq12, y6: &Fq12) -> Fq12 {
+    let (mut y3, mut y6) = (*y3, *y6);
+    y3.conjugate();
+    y6.conjugate();
+    y6 * y4 * y3
+}
+
+// y9 = y8 * y1
+pub fn y9(y1: &Fq12, y8: &Fq12) -> Fq12 {
+    *y8 * y1
+}
+
+// y11 = y8 * y4 * r
+pub fn y11(r: &Fq12, y4: &Fq12, y8: &Fq12) -> Fq12 {
+    *y8 * y4 * r
+}
+
+// y13 = y9^p * y11
+pub fn y13(y9: &Fq12, y11: &Fq12) -> Fq12 {
+    let mut y12 = *y9;
+    y12.frobenius_map(1);
+    y12 * y11
+}
+
+// y14 = y8^(p^2) * y13
+pub fn y14(y8: &Fq12, y13: &Fq12) -> Fq12 {
+    let mut y8 = *y8;
+    y8.frobenius_map(2);
+    y8 * y13
+}
+
+// y15 = (conj(r) * y9)^(p^3)
+pub fn y15(r: &Fq12, y9: &Fq12) -> Fq12 {
+    let mut r = *r;
+    r.conjugate();
+    let mut y15 = r * y9;
+    y15.frobenius_map(3);
+    y15
+}
+
+// y16 = y15 * y14, the result
+pub fn y16(y14: &Fq12, y15: &Fq12) -> Fq12 {
+    *y15 * y14
+}
+
+// The whole final exponentiation of `f`, stage by stage as the chunked steps run it;
+// `None` for f = 0.
+pub fn final_exponentiation(f: &Fq12) -> Option<Fq12> {
+    let digits = 0..X_NAF.len();
+    let r = easy_part2(&easy_part1(f)?);
+    let y0 = exp_by_neg_x(&r, Fq12::one(), digits.clone());
+    let y1 = y1(&y0);
+    let y3 = y3(&y0);
+    let y4 = exp_by_neg_x(&y3, Fq12::one(), digits.clone());
+    let y6 = exp_by_neg_x(&y5(&y4), Fq12::one(), digits);
+    let y8 = y8(&y3, &y4, &y6);
+    let y9 = y9(&y1, &y8);
+    let y11 = y11(&r, &y4, &y8);
+    let y13 = y13(&y9, &y11);
+    let y14 = y14(&y8, &y13);
+    let y15 = y15(&r, &y9);
+    Some(y16(&y14, &y15))
+}
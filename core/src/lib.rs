@@ -0,0 +1,14 @@
+// The math of the chunked Groth16 verification, without accounts or instruction data:
+// the curve constants and NAF tables, the steps of the Miller loops and the stages of
+// the final exponentiation, each a pure function of the Fp12 and G2 values a step reads
+// and writes. The contract runs them between its scratch accounts; anything else, from
+// native tests to other runtimes, can run the same steps in memory.
+//
+// `no_std`, with `alloc` for the coefficient lists a step returns.
+#![no_std]
+
+extern crate alloc;
+
+pub mod curve;
+pub mod final_exponentiation;
+pub mod miller_loop;
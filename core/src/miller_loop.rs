@@ -0,0 +1,217 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use ark_ec::SWModelParameters;
+use ark_ff::{Field, One, Zero};
+
+use crate::curve::{
+    ell, line, EllCoeff, Fq, Fq12, Fq2, G1Affine, G2Affine, G2Parameters, ATE_LOOP_COUNT,
+    LAST_STEP_LINES, MILLER_LOOP_LAST_STEP, X_IS_NEGATIVE,
+};
+
+// The Miller loop split into steps. Step i runs from `ATE_LOOP_COUNT.len() - 1` down to
+// 0: it squares the accumulator (but on the first and the last step) and applies the
+// lines of digit i - 1, or on the last step (i = 0) the curve's `LAST_STEP_LINES`
+// lines: those of the two Frobenius images of Q on BN254, none on BLS12-381. j counts
+// the ell coefficients the steps before it consumed, which is where a precomputed
+// table's coefficients for step i start. For a negative x the product of the loops of
+// a check ends in `finish_loop`.
+
+// Number of ell coefficients step (i, j) consumes: coefficients j and, on the step
+// after a non-zero `ATE_LOOP_COUNT` digit, j + 1; the last step takes `LAST_STEP_LINES`.
+pub fn step_coeff_count(i: usize, j: usize) -> usize {
+    if j == MILLER_LOOP_LAST_STEP {
+        LAST_STEP_LINES
+    } else if ATE_LOOP_COUNT[i - 1] != 0 {
+        2
+    } else {
+        1
+    }
+}
+
+// The (i, j) indices of `steps` consecutive steps from (i, j) on, or `None` when there
+// are no steps or they would run past the last step (i = 0).
+pub fn step_range(i: usize, j: usize, steps: usize) -> Option<Vec<(usize, usize)>> {
+    if steps == 0 || steps > i + 1 || i >= ATE_LOOP_COUNT.len() {
+        return None;
+    }
+    let mut range = vec![];
+    let mut j = j;
+    for i in (i + 1 - steps..=i).rev() {
+        range.push((i, j));
+        j += if i == 0 { 0 } else { step_coeff_count(i, j) };
+    }
+    Some(range)
+}
+
+// Step (i, j) of a loop over precomputed lines, such as the gamma and delta tables of
+// a verifying key, at `p`: `coeffs` are the step's `step_coeff_count(i, j)`
+// coefficients. A zero `p` contributes nothing.
+pub fn prepared_step(p: &G1Affine, mut f: Fq12, i: usize, j: usize, coeffs: &[EllCoeff]) -> Fq12 {
+    if !p.is_zero() {
+        if j != MILLER_LOOP_LAST_STEP && i != ATE_LOOP_COUNT.len() - 1 {
+            f.square_in_place();
+        }
+        coeffs.iter().for_each(|coeff| ell(&mut f, coeff, p));
+    }
+    f
+}
+
+// The lines of step i from Q applied to `p`, without squaring the accumulator first,
+// advancing the running point R (see `g2_step_coeffs`).
+pub fn point_lines(f: &mut Fq12, r: &mut [Fq2; 3], p: &G1Affine, q: G2Affine, i: usize) {
+    for coeffs in g2_step_coeffs(r, q, i) {
+        ell(f, &coeffs, p);
+    }
+}
+
+// The ell coefficients step i of a Miller loop takes from Q, advancing R exactly as
+// `G2Prepared::from` does: the doubling line and, after a non-zero digit i - 1, the
+// addition line, or on the last step (i = 0) the curve's last lines.
+// R starts out as Q with z = 1.
+pub fn g2_step_coeffs(r: &mut [Fq2; 3], q: G2Affine, i: usize) -> Vec<EllCoeff> {
+    if i == 0 {
+        return last_step_coeffs(r, q);
+    }
+    let mut coeffs = vec![doubling_step(r)];
+    match ATE_LOOP_COUNT[i - 1] {
+        1 => coeffs.push(addition_step(r, &q)),
+        -1 => coeffs.push(addition_step(r, &-q)),
+        _ => {}
+    }
+    coeffs
+}
+
+// The Miller loop of e(p, q), step by step as the chunked loops run it.
+pub fn miller_loop(p: &G1Affine, q: &G2Affine) -> Fq12 {
+    let len = ATE_LOOP_COUNT.len();
+    let mut f = Fq12::one();
+    if p.is_zero() || q.is_zero() {
+        return f;
+    }
+    let mut r = [q.x, q.y, Fq2::one()];
+    for (i, _) in step_range(len - 1, 0, len).unwrap() {
+        if i != 0 && i != len - 1 {
+            f.square_in_place();
+        }
+        point_lines(&mut f, &mut r, p, *q, i);
+    }
+    finish_loop(&mut f);
+    f
+}
+
+// The conjugation that ends the Miller loops of a negative x. It applies once, to the
+// product of all the loops of a check, after their last steps.
+pub fn finish_loop(f: &mut Fq12) {
+    if X_IS_NEGATIVE {
+        f.conjugate();
+    }
+}
+
+// The lines of the two Frobenius images of Q.
+#[cfg(not(feature = "bls12-381"))]
+fn last_step_coeffs(r: &mut [Fq2; 3], q: G2Affine) -> Vec<EllCoeff> {
+    use crate::curve::mul_by_char;
+
+    let q1 = mul_by_char(q);
+    let mut q2 = mul_by_char(q1);
+    if X_IS_NEGATIVE {
+        r[1] = -r[1];
+    }
+    q2.y = -q2.y;
+    vec![addition_step(r, &q1), addition_step(r, &q2)]
+}
+
+#[cfg(feature = "bls12-381")]
+fn last_step_coeffs(_r: &mut [Fq2; 3], _q: G2Affine) -> Vec<EllCoeff> {
+    vec![]
+}
+
+// The private G2Prepared helpers of ark-ec's `bn::g2` and `bls12::g2`, on R = [x, y, z].
+// `line` puts the coefficients in the order of the curve's twist.
+
+fn doubling_step(r: &mut [Fq2; 3]) -> EllCoeff {
+    let two_inv = Fq::one().double().inverse().unwrap();
+    let [x, y, z] = *r;
+    let mut a = x * y;
+    a.mul_assign_by_fp(&two_inv);
+    let b = y.square();
+    let c = z.square();
+    let e = G2Parameters::COEFF_B * (c.double() + c);
+    let f = e.double() + e;
+    let mut g = b + f;
+    g.mul_assign_by_fp(&two_inv);
+    let h = (y + z).square() - (b + c);
+    let i = e - b;
+    let j = x.square();
+    let e_square = e.square();
+
+    r[0] = a * (b - f);
+    r[1] = g.square() - (e_square.double() + e_square);
+    r[2] = b * h;
+    line(-h, j.double() + j, i)
+}
+
+fn addition_step(r: &mut [Fq2; 3], q: &G2Affine) -> EllCoeff {
+    let [x, y, z] = *r;
+    let theta = y - (q.y * z);
+    let lambda = x - (q.x * z);
+    let c = theta.square();
+    let d = lambda.square();
+    let e = lambda * d;
+    let f = z * c;
+    let g = x * d;
+    let h = e + f - g.double();
+    r[0] = lambda * h;
+    r[1] = theta * (g - h) - (e * y);
+    r[2] = z * e;
+    let j = theta * q.x - (lambda * q.y);
+    line(lambda, -theta, j)
+}
+
+#[cfg(test)]
+mod test {
+    use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+    use crate::curve::Engine;
+
+    type Fr = <Engine as PairingEngine>::Fr;
+
+    #[test]
+    fn test_miller_loop_matches_arkworks() {
+        let rng = &mut test_rng();
+        let p = G1Affine::prime_subgroup_generator()
+            .mul(Fr::rand(rng))
+            .into_affine();
+        let q = G2Affine::prime_subgroup_generator()
+            .mul(Fr::rand(rng))
+            .into_affine();
+        let expected = Engine::miller_loop(&[(p.into(), q.into())]);
+        assert_eq!(miller_loop(&p, &q), expected);
+
+        // the same loop over q's precomputed lines
+        let prepared: <Engine as PairingEngine>::G2Prepared = q.into();
+        let len = ATE_LOOP_COUNT.len();
+        let mut f = Fq12::one();
+        for (i, j) in step_range(len - 1, 0, len).unwrap() {
+            let coeffs = &prepared.ell_coeffs[j..j + step_coeff_count(i, j)];
+            f = prepared_step(&p, f, i, j, coeffs);
+        }
+        finish_loop(&mut f);
+        assert_eq!(f, expected);
+        assert_eq!(miller_loop(&G1Affine::zero(), &q), Fq12::one());
+    }
+
+    #[test]
+    fn test_step_range() {
+        let len = ATE_LOOP_COUNT.len();
+        let steps = step_range(len - 1, 0, len).unwrap();
+        assert_eq!(steps.last(), Some(&(0, MILLER_LOOP_LAST_STEP)));
+        assert_eq!(step_coeff_count(0, MILLER_LOOP_LAST_STEP), LAST_STEP_LINES);
+        assert_eq!(step_range(1, 88, 3), None);
+        assert_eq!(step_range(len, 0, 1), None);
+        assert_eq!(step_range(3, 0, 0), None);
+    }
+}
@@ -0,0 +1,157 @@
+use std::sync::Once;
+
+use contract::point::PointEncoding;
+use contract::schedule::{verification_steps, with_scratch, NUM_ACCOUNTS, PAYER};
+use contract::scratch::{scratch_address, Role, SCRATCH_HEADER_LEN};
+use contract::{process_instruction, FLAG_WRITE_RESULT};
+use solana_program::account_info::AccountInfo;
+use solana_program::clock::Clock;
+use solana_program::entrypoint::SUCCESS;
+use solana_program::program_error::ProgramError;
+use solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use solana_program::pubkey::Pubkey;
+use wasm_bindgen::prelude::*;
+
+/// Verify a proof off-chain with the contract's own instruction handler.
+///
+/// Takes the three byte strings the client submits (`circuit::initialize` produces
+/// them: proof.c, the prepared input and A || B) and replays the client's exact instruction sequence through
+/// `contract::process_instruction`, with the scratch accounts held in memory. The
+/// Miller loops, A·B's included, and the final exponentiation therefore run the same code, in the same
+/// steps, as on-chain, and the answer is the result the final step writes.
+///
+/// Inputs of the wrong length or with malformed field encodings are rejected, as they
+/// would fail the transaction on-chain.
+#[wasm_bindgen]
+pub fn verify(proof_c: &[u8], prepared_input: &[u8], proof_ab: &[u8]) -> bool {
+    if !is_point(proof_c, PointEncoding::g1_len)
+        || !is_point(prepared_input, PointEncoding::g1_projective_len)
+        || !is_point(proof_ab, |encoding| encoding.g1_len() + encoding.g2_len())
+    {
+        return false;
+    }
+    run(proof_c, prepared_input, proof_ab).unwrap_or(false)
+}
+
+// Whether `data` is an encoding byte and `len(encoding)` bytes of points.
+fn is_point(data: &[u8], len: fn(PointEncoding) -> usize) -> bool {
+    match data
+        .first()
+        .and_then(|&encoding| PointEncoding::from_u8(encoding))
+    {
+        Some(encoding) => data.len() == 1 + len(encoding),
+        None => false,
+    }
+}
+
+struct Account {
+    key: Pubkey,
+    lamports: u64,
+    data: Vec<u8>,
+}
+
+fn run(proof_c: &[u8], prepared_input: &[u8], proof_ab: &[u8]) -> Result<bool, ProgramError> {
+    install_clock();
+    let program_id = Pubkey::default();
+
+    // the scratch accounts of session 0, the payer they derive from, then the session's
+    // result account for the final step
+    let payer = Pubkey::new_from_array([1; 32]);
+    let result = NUM_ACCOUNTS;
+    let mut accounts = (0..=NUM_ACCOUNTS)
+        .map(|i| match i {
+            PAYER => Account {
+                key: payer,
+                lamports: 0,
+                data: vec![],
+            },
+            _ => {
+                let role = if i == result {
+                    Role::Result
+                } else {
+                    Role::from_u8(i as u8).unwrap()
+                };
+                Account {
+                    key: scratch_address(&program_id, &payer, 0, role).0,
+                    lamports: 0,
+                    data: role.initial_data(),
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // write the result instead of failing the final step on an invalid proof
+    let mut steps = verification_steps(proof_c, prepared_input, proof_ab);
+    let last = steps.last_mut().unwrap();
+    last.0.push(result);
+    last.1[1] |= FLAG_WRITE_RESULT;
+    with_scratch(&mut steps, PAYER, 0);
+
+    for (indices, data) in steps.iter() {
+        // account indices within a step are distinct, so split borrows one by one
+        let mut remaining: Vec<Option<&mut Account>> = accounts.iter_mut().map(Some).collect();
+        let infos = indices
+            .iter()
+            .map(|&index| {
+                let account = remaining[index].take().unwrap();
+                AccountInfo::new(
+                    &account.key,
+                    index == PAYER,
+                    true,
+                    &mut account.lamports,
+                    &mut account.data,
+                    &program_id,
+                    false,
+                    0,
+                )
+            })
+            .collect::<Vec<_>>();
+        process_instruction(&program_id, &infos, data)?;
+    }
+    Ok(accounts[result].data[SCRATCH_HEADER_LEN] == 1)
+}
+
+// The result account is stamped with the current slot; off-chain there is no Clock
+// sysvar, so report slot 0.
+struct ClockStub;
+
+impl SyscallStubs for ClockStub {
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Clock) = Clock::default() };
+        SUCCESS
+    }
+}
+
+fn install_clock() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        set_syscall_stubs(Box::new(ClockStub));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_on_chain_result() {
+        let (proof_c, prepared_input, proof_ab) = circuit::initialize().unwrap();
+        assert!(verify(&proof_c, &prepared_input, &proof_ab));
+
+        // A and B of another proof
+        let (_, proofs) = circuit::demo_batch(2).unwrap();
+        let other_ab = circuit::proof_ab(&proofs[1].0, circuit::PointEncoding::Uncompressed);
+        assert!(!verify(&proof_c, &prepared_input, &other_ab));
+
+        assert!(!verify(&proof_c, &prepared_input, &proof_ab[1..]));
+
+        // compressed points
+        let (pvk, proof, public_inputs) = circuit::demo_proof().unwrap();
+        let encoding = circuit::PointEncoding::Compressed;
+        let (proof_c, prepared_input, proof_ab) =
+            circuit::submission(&pvk, &proof, &public_inputs, encoding).unwrap();
+        assert_eq!((proof_c.len(), prepared_input.len()), (33, 33));
+        assert!(verify(&proof_c, &prepared_input, &proof_ab));
+        assert!(!verify(&proof_c[..32], &prepared_input, &proof_ab));
+    }
+}